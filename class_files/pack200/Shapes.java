@@ -0,0 +1,71 @@
+package p;
+
+import java.util.ArrayList;
+import java.util.List;
+
+public class Shapes {
+    public static final int SIDES = 4;
+    public static final String NAME = "shapes";
+    private static final long BIG = 12345678901L;
+    private final List<Shape> shapes = new ArrayList<>();
+
+    interface Shape {
+        double area();
+    }
+
+    static final class Square implements Shape {
+        private final double side;
+
+        Square(double side) {
+            this.side = side;
+        }
+
+        public double area() {
+            return side * side;
+        }
+    }
+
+    class Counter {
+        int count() {
+            return shapes.size();
+        }
+    }
+
+    public void add(double side) {
+        shapes.add(new Square(side));
+    }
+
+    public double total() {
+        double total = 0;
+        for (Shape shape : shapes) {
+            total += shape.area();
+        }
+        return total;
+    }
+
+    public static String describe(int sides) {
+        switch (sides) {
+            case 3: return "triangle";
+            case 4: return "square";
+            case 5: return "pentagon";
+            default: return "polygon";
+        }
+    }
+
+    public static int rank(int key) {
+        switch (key) {
+            case 1: return 10;
+            case 100: return 20;
+            case 10000: return 30;
+            default: return 0;
+        }
+    }
+
+    public static long parse(String s) throws NumberFormatException {
+        try {
+            return Long.parseLong(s) + BIG;
+        } catch (NumberFormatException e) {
+            return -1;
+        }
+    }
+}
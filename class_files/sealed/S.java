@@ -0,0 +1,5 @@
+public sealed interface S permits S.One, S.Two {
+    final class One implements S {}
+
+    non-sealed class Two implements S {}
+}
@@ -0,0 +1 @@
+public abstract sealed class Shape permits Circle, Square {}
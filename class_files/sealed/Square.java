@@ -0,0 +1 @@
+public non-sealed class Square extends Shape {}
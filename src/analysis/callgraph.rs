@@ -0,0 +1,372 @@
+// Call graphs across the classes of an archive or class path, built from the
+// invoke instructions of their methods. Virtual and interface calls are
+// resolved by class hierarchy analysis: to every implementation a receiver
+// of the referenced type or one of its subtypes could dispatch to.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+use serde::Serialize;
+
+use analysis::hierarchy::TypeHierarchy;
+use bytecode::cfg::dot_string;
+use bytecode::hierarchy::ClassHierarchy;
+use bytecode::{decode, Operand};
+use class_file::access_flags::{ACC_ABSTRACT, ACC_INTERFACE, ACC_STATIC};
+use class_file::attributes::{malformed, read_bootstrap_methods, read_code, BootstrapMethod};
+use class_file::constant_pool::{InvokeDynamicInfo, MethodHandleInfo};
+use class_file::ClassFile;
+
+const LAMBDA_METAFACTORY: &str = "java/lang/invoke/LambdaMetafactory";
+
+/// A method by class, name and descriptor, written java/lang/String.length()I.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MethodRef {
+    pub class: String,
+    pub name: String,
+    pub descriptor: String,
+}
+
+impl fmt::Display for MethodRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}{}", self.class, self.name, self.descriptor)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CallKind {
+    Static,
+    Special,
+    Virtual,
+    Interface,
+    // An invokedynamic other than a lambda, to its bootstrap method.
+    Dynamic,
+    // A lambda or method reference, to the method implementing it.
+    Lambda,
+}
+
+impl CallKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            CallKind::Static => "static",
+            CallKind::Special => "special",
+            CallKind::Virtual => "virtual",
+            CallKind::Interface => "interface",
+            CallKind::Dynamic => "dynamic",
+            CallKind::Lambda => "lambda",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CallEdge {
+    pub caller: String,
+    pub callee: String,
+    pub kind: CallKind,
+    // Of the invoke instruction in the caller's code.
+    pub offset: u32,
+
+    /// Whether the callee isn't a method of the classes analyzed, such as
+    /// a library method.
+    #[serde(skip_serializing_if = "is_false")]
+    pub external: bool,
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct CallGraph {
+    /// Every method of the classes analyzed.
+    pub methods: Vec<String>,
+    pub edges: Vec<CallEdge>,
+
+    /// Methods whose code couldn't be decoded, and why.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+}
+
+struct CallSite {
+    caller: String,
+    offset: u32,
+    kind: CallKind,
+    target: MethodRef,
+}
+
+/// Collects the methods and call sites of classes one at a time, so that
+/// the classes needn't all be in memory, and resolves them once all are in.
+#[derive(Default)]
+pub struct CallGraphBuilder {
+    hierarchy: TypeHierarchy,
+    class_flags: HashMap<String, u16>,
+    // Access flags of the methods each class declares, by name and
+    // descriptor.
+    methods: HashMap<String, HashMap<(String, String), u16>>,
+    sites: Vec<CallSite>,
+    errors: Vec<String>,
+}
+
+impl CallGraphBuilder {
+    pub fn new() -> CallGraphBuilder {
+        CallGraphBuilder::default()
+    }
+
+    /// Adds the methods and call sites of a class. As on a class path, only
+    /// the first class of a name is used.
+    pub fn add_class(&mut self, cf: &ClassFile) {
+        if !self.hierarchy.add_class(cf) {
+            return;
+        }
+
+        let cp = &cf.constant_pool;
+        let class = cf.name().unwrap_or_default();
+        let bootstrap_methods = match cf.attributes.find(cp, "BootstrapMethods").map(|attribute| read_bootstrap_methods(&attribute.info)) {
+            Some(Ok(bootstrap_methods)) => bootstrap_methods,
+            Some(Err(e)) => {
+                self.errors.push(format!("{}: {}", class, malformed("BootstrapMethods", e)));
+                Vec::new()
+            }
+            None => Vec::new(),
+        };
+
+        self.class_flags.insert(class.clone(), cf.access_flags);
+        let declared = self.methods.entry(class.clone()).or_default();
+
+        for method in &cf.methods.array {
+            let name = cp.utf8(method.name_index).unwrap_or_default();
+            let descriptor = cp.utf8(method.descriptor_index).unwrap_or_default();
+            let caller = format!("{}.{}{}", class, name, descriptor);
+            declared.insert((name, descriptor), method.access_flags);
+
+            let code = match method.attributes.find(cp, "Code").map(|attribute| read_code(&attribute.info)) {
+                Some(Ok(code)) => code,
+                Some(Err(e)) => {
+                    self.errors.push(format!("{}: {}", caller, malformed("Code", e)));
+                    continue;
+                }
+                None => continue,
+            };
+            let instructions = match decode(&code.code) {
+                Ok(instructions) => instructions,
+                Err(e) => {
+                    self.errors.push(format!("{}: {}", caller, e));
+                    continue;
+                }
+            };
+
+            for insn in instructions {
+                let kind = match insn.opcode {
+                    0xb6 => CallKind::Virtual,
+                    0xb7 => CallKind::Special,
+                    0xb8 => CallKind::Static,
+                    0xb9 => CallKind::Interface,
+                    0xba => CallKind::Dynamic,
+                    _ => continue,
+                };
+
+                let index = match insn.operand {
+                    Operand::Constant(index) | Operand::InvokeInterface { index, .. } | Operand::InvokeDynamic(index) => index,
+                    _ => continue,
+                };
+
+                let site = if kind == CallKind::Dynamic {
+                    dynamic_target(cf, &bootstrap_methods, index)
+                } else {
+                    method_ref(cf, index).map(|target| (kind, target))
+                };
+
+                if let Some((kind, target)) = site {
+                    self.sites.push(CallSite { caller: caller.clone(), offset: insn.offset, kind, target });
+                }
+            }
+        }
+    }
+
+    pub fn build(self) -> CallGraph {
+        let mut methods: Vec<String> = self
+            .methods
+            .iter()
+            .flat_map(|(class, declared)| declared.keys().map(move |(name, descriptor)| format!("{}.{}{}", class, name, descriptor)))
+            .collect();
+        methods.sort();
+
+        let mut edges = Vec::new();
+
+        for site in &self.sites {
+            let targets = match site.kind {
+                CallKind::Virtual | CallKind::Interface => self.dispatch_targets(&site.target),
+                _ => self.resolve(&site.target, site.kind != CallKind::Static).into_iter().collect(),
+            };
+
+            // When the method referenced isn't one of the classes analyzed,
+            // as for toString() inherited from java/lang/Object, the call
+            // may reach it besides any overrides.
+            let mut callees: Vec<(MethodRef, bool)> = targets.into_iter().map(|target| (target, false)).collect();
+            if self.resolve(&site.target, site.kind != CallKind::Static).is_none() {
+                callees.push((site.target.clone(), true));
+            }
+
+            for (callee, external) in callees {
+                edges.push(CallEdge {
+                    caller: site.caller.clone(),
+                    callee: callee.to_string(),
+                    kind: site.kind,
+                    offset: site.offset,
+                    external,
+                });
+            }
+        }
+
+        CallGraph { methods, edges, errors: self.errors }
+    }
+
+    fn declares(&self, class: &str, name: &str, descriptor: &str) -> Option<u16> {
+        self.methods.get(class)?.get(&(name.to_string(), descriptor.to_string())).cloned()
+    }
+
+    // The method a reference resolves to: declared by the class or its
+    // superclasses, or failing those by an interface. Static methods of
+    // interfaces aren't inherited.
+    fn resolve(&self, target: &MethodRef, interfaces: bool) -> Option<MethodRef> {
+        let found = |class: &String| {
+            self.declares(class, &target.name, &target.descriptor).map(|flags| (class.clone(), flags))
+        };
+
+        let (class, _) = self.hierarchy.superclasses(&target.class).iter().find_map(found).or_else(|| {
+            if !interfaces {
+                return None;
+            }
+
+            self.hierarchy
+                .all_interfaces(&target.class)
+                .iter()
+                .filter_map(found)
+                .find(|&(_, flags)| flags & (ACC_ABSTRACT | ACC_STATIC) == 0)
+        })?;
+
+        Some(MethodRef { class, ..target.clone() })
+    }
+
+    // Every implementation a virtual or interface call may reach, for a
+    // receiver of the referenced class or any subclass that can have
+    // instances.
+    fn dispatch_targets(&self, target: &MethodRef) -> Vec<MethodRef> {
+        let mut targets = BTreeSet::new();
+
+        // The method a receiver of exactly the referenced type would run.
+        if let Some(resolved) = self.resolve(target, true) {
+            let flags = self.declares(&resolved.class, &resolved.name, &resolved.descriptor).unwrap_or_default();
+            if flags & ACC_ABSTRACT == 0 {
+                targets.insert(resolved);
+            }
+        }
+
+        for subtype in self.hierarchy.subtypes(&target.class) {
+            let flags = self.class_flags.get(&subtype).cloned().unwrap_or_default();
+            if flags & (ACC_ABSTRACT | ACC_INTERFACE) != 0 {
+                continue;
+            }
+
+            let receiver = MethodRef { class: subtype, ..target.clone() };
+            if let Some(resolved) = self.resolve(&receiver, true) {
+                let flags = self.declares(&resolved.class, &resolved.name, &resolved.descriptor).unwrap_or_default();
+                if flags & (ACC_ABSTRACT | ACC_STATIC) == 0 {
+                    targets.insert(resolved);
+                }
+            }
+        }
+
+        targets.into_iter().collect()
+    }
+}
+
+impl CallGraph {
+    /// One line per call edge, with a header line.
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("caller,callee,kind,offset,external\n");
+
+        for edge in &self.edges {
+            out.push_str(&format!(
+                "{},{},{},{},{}\n",
+                csv_field(&edge.caller),
+                csv_field(&edge.callee),
+                edge.kind.name(),
+                edge.offset,
+                edge.external
+            ));
+        }
+
+        out
+    }
+
+    /// One edge per caller, callee and kind: solid for static and special
+    /// calls, dashed for virtual and interface ones and dotted for lambdas.
+    /// External methods are gray.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph calls {\n");
+        out.push_str("    rankdir=LR;\n");
+        out.push_str("    node [shape=box, fontname=monospace];\n");
+
+        let external: BTreeSet<&str> = self.edges.iter().filter(|e| e.external).map(|e| e.callee.as_str()).collect();
+        for method in external {
+            out.push_str(&format!("    {} [color=gray];\n", dot_string(method)));
+        }
+
+        let mut seen = BTreeSet::new();
+        for edge in &self.edges {
+            if !seen.insert((&edge.caller, &edge.callee, edge.kind)) {
+                continue;
+            }
+
+            let style = match edge.kind {
+                CallKind::Virtual | CallKind::Interface => " [style=dashed]",
+                CallKind::Lambda | CallKind::Dynamic => " [style=dotted]",
+                _ => "",
+            };
+            out.push_str(&format!("    {} -> {}{};\n", dot_string(&edge.caller), dot_string(&edge.callee), style));
+        }
+
+        out.push_str("}\n");
+        out
+    }
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn method_ref(cf: &ClassFile, index: u16) -> Option<MethodRef> {
+    let (class, name, descriptor) = cf.constant_pool.member_ref(index)?;
+
+    Some(MethodRef { class, name, descriptor })
+}
+
+// Lambdas and method references made by LambdaMetafactory call the method
+// handle that is the second bootstrap argument. Other call sites are linked
+// by their bootstrap method.
+fn dynamic_target(
+    cf: &ClassFile,
+    bootstrap_methods: &[BootstrapMethod],
+    index: u16,
+) -> Option<(CallKind, MethodRef)> {
+    let cp = &cf.constant_pool;
+    let info = cp.get_as::<InvokeDynamicInfo>(index)?;
+    let bootstrap = bootstrap_methods.get(info.bootstrap_method_attr_index as usize)?;
+
+    let handle = |index: u16| method_ref(cf, cp.get_as::<MethodHandleInfo>(index)?.reference_index);
+    let bootstrap_method = handle(bootstrap.bootstrap_method_ref)?;
+
+    if bootstrap_method.class == LAMBDA_METAFACTORY {
+        if let Some(implementation) = bootstrap.bootstrap_arguments.get(1).and_then(|&index| handle(index)) {
+            return Some((CallKind::Lambda, implementation));
+        }
+    }
+
+    Some((CallKind::Dynamic, bootstrap_method))
+}
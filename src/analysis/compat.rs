@@ -0,0 +1,423 @@
+// Binary and source compatibility between two versions of a library, after
+// chapter 13 of the Java Language Specification. Only the API is compared:
+// public and protected classes and their public and protected members.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use analysis::hierarchy::TypeHierarchy;
+use bytecode::hierarchy::ClassHierarchy;
+use class_file::access_flags::*;
+use class_file::attributes::{malformed, read_index, read_inner_classes};
+use class_file::ClassFile;
+
+/// The API of one version of a library.
+#[derive(Debug, Default)]
+pub struct ApiSnapshot {
+    classes: BTreeMap<String, ClassApi>,
+    hierarchy: TypeHierarchy,
+}
+
+#[derive(Debug)]
+struct ClassApi {
+    // As declared, with the flags of a member class taken from its
+    // InnerClasses entry, as those of its class file lose private and
+    // protected.
+    access_flags: u16,
+    fields: BTreeMap<String, FieldApi>,
+    // Access flags by name and descriptor.
+    methods: BTreeMap<(String, String), u16>,
+}
+
+#[derive(Debug)]
+struct FieldApi {
+    access_flags: u16,
+    descriptor: String,
+    constant_value: Option<String>,
+}
+
+/// Which consumers a change breaks: those already compiled against the old
+/// version, or only those compiled against the new one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Incompatibility {
+    Binary,
+    Source,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiChange {
+    pub kind: &'static str,
+    pub breaks: Incompatibility,
+    pub class: String,
+
+    /// The field or method, as name and descriptor.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub member: Option<String>,
+
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CompatReport {
+    pub binary_compatible: bool,
+    pub source_compatible: bool,
+    pub changes: Vec<ApiChange>,
+}
+
+fn is_accessible(access_flags: u16) -> bool {
+    access_flags & (ACC_PUBLIC | ACC_PROTECTED) != 0
+}
+
+fn access_level(access_flags: u16) -> u8 {
+    if access_flags & ACC_PUBLIC != 0 {
+        3
+    } else if access_flags & ACC_PROTECTED != 0 {
+        2
+    } else if access_flags & ACC_PRIVATE != 0 {
+        0
+    } else {
+        1
+    }
+}
+
+fn kind_name(access_flags: u16) -> &'static str {
+    if access_flags & ACC_ANNOTATION != 0 {
+        "annotation type"
+    } else if access_flags & ACC_INTERFACE != 0 {
+        "interface"
+    } else if access_flags & ACC_ENUM != 0 {
+        "enum"
+    } else {
+        "class"
+    }
+}
+
+impl ApiSnapshot {
+    pub fn new() -> ApiSnapshot {
+        ApiSnapshot::default()
+    }
+
+    /// Adds a class. As on a class path, the first class of a name wins.
+    /// Classes whose attributes can't be decoded aren't added.
+    pub fn add_class(&mut self, cf: &ClassFile) -> Result<(), String> {
+        let name = match cf.name() {
+            Some(name) => name,
+            None => return Ok(()),
+        };
+        if cf.access_flags & ACC_MODULE != 0 || self.classes.contains_key(&name) {
+            return Ok(());
+        }
+
+        let cp = &cf.constant_pool;
+        let mut access_flags = cf.access_flags;
+
+        if let Some(attribute) = cf.attributes.find(cp, "InnerClasses") {
+            for inner in read_inner_classes(&attribute.info).map_err(|e| malformed("InnerClasses", e))? {
+                if cp.class_name(inner.inner_class_info_index).as_ref() == Some(&name) {
+                    access_flags = inner.inner_class_access_flags;
+                }
+            }
+        }
+
+        let mut api = ClassApi {
+            access_flags,
+            fields: BTreeMap::new(),
+            methods: BTreeMap::new(),
+        };
+
+        for field in &cf.fields.array {
+            let constant_value = match field.attributes.find(cp, "ConstantValue") {
+                Some(attribute) => cp.describe(read_index(&attribute.info).map_err(|e| malformed("ConstantValue", e))?),
+                None => None,
+            };
+
+            api.fields.insert(
+                cp.utf8(field.name_index).unwrap_or_default(),
+                FieldApi {
+                    access_flags: field.access_flags,
+                    descriptor: cp.utf8(field.descriptor_index).unwrap_or_default(),
+                    constant_value,
+                },
+            );
+        }
+
+        for method in &cf.methods.array {
+            let key = (
+                cp.utf8(method.name_index).unwrap_or_default(),
+                cp.utf8(method.descriptor_index).unwrap_or_default(),
+            );
+            api.methods.insert(key, method.access_flags);
+        }
+
+        self.hierarchy.add_class(cf);
+        self.classes.insert(name, api);
+        Ok(())
+    }
+
+    // Whether a class and the classes enclosing it are accessible from
+    // other packages.
+    fn is_api(&self, name: &str) -> bool {
+        let mut class = name;
+
+        loop {
+            match self.classes.get(class) {
+                Some(api) if is_accessible(api.access_flags) => {}
+                _ => return false,
+            }
+
+            match class.rfind('$') {
+                Some(i) if self.classes.contains_key(&class[..i]) => class = &class[..i],
+                _ => return true,
+            }
+        }
+    }
+
+    // A field of a class or, as fields are inherited, of a supertype.
+    fn find_field(&self, class: &str, name: &str) -> Option<&FieldApi> {
+        let mut types = self.hierarchy.superclasses(class);
+        types.extend(self.hierarchy.all_interfaces(class));
+
+        types.iter().find_map(|class| self.classes.get(class)?.fields.get(name))
+    }
+
+    // A method of a class or one it inherits.
+    fn find_method(&self, class: &str, key: &(String, String)) -> Option<u16> {
+        let mut types = self.hierarchy.superclasses(class);
+        types.extend(self.hierarchy.all_interfaces(class));
+
+        types.iter().find_map(|class| self.classes.get(class)?.methods.get(key).cloned())
+    }
+}
+
+/// The changes from `old` to `new` that break consumers of `old`.
+pub fn compare(old: &ApiSnapshot, new: &ApiSnapshot) -> CompatReport {
+    let mut changes = Vec::new();
+
+    for (name, old_class) in &old.classes {
+        if !old.is_api(name) {
+            continue;
+        }
+
+        let mut change = |kind: &'static str, breaks: Incompatibility, member: Option<String>, message: String| {
+            changes.push(ApiChange { kind, breaks, class: name.clone(), member, message });
+        };
+
+        let new_class = match new.classes.get(name) {
+            Some(new_class) => new_class,
+            None => {
+                change("class_removed", Incompatibility::Binary, None, format!("{} was removed", name));
+                continue;
+            }
+        };
+
+        if !new.is_api(name) {
+            change("class_less_accessible", Incompatibility::Binary, None, format!("{} is no longer accessible", name));
+            continue;
+        }
+
+        compare_class(name, old_class, new_class, &mut change);
+        compare_supertypes(name, old, new, &mut change);
+        compare_fields(name, old_class, new_class, new, &mut change);
+        compare_methods(name, old_class, new_class, new, &mut change);
+    }
+
+    CompatReport {
+        binary_compatible: !changes.iter().any(|c| c.breaks == Incompatibility::Binary),
+        source_compatible: changes.is_empty(),
+        changes,
+    }
+}
+
+type Change<'a> = dyn FnMut(&'static str, Incompatibility, Option<String>, String) + 'a;
+
+fn compare_class(name: &str, old: &ClassApi, new: &ClassApi, change: &mut Change) {
+    let (old_flags, new_flags) = (old.access_flags, new.access_flags);
+    let kinds = ACC_INTERFACE | ACC_ANNOTATION | ACC_ENUM;
+
+    if old_flags & kinds != new_flags & kinds {
+        change(
+            "kind_changed",
+            Incompatibility::Binary,
+            None,
+            format!("{} changed from {} to {}", name, kind_name(old_flags), kind_name(new_flags)),
+        );
+    }
+    if access_level(new_flags) < access_level(old_flags) {
+        change("class_less_accessible", Incompatibility::Binary, None, format!("{} was made less accessible", name));
+    }
+    if old_flags & ACC_INTERFACE == 0 && old_flags & ACC_ABSTRACT == 0 && new_flags & ACC_ABSTRACT != 0 {
+        change("class_now_abstract", Incompatibility::Binary, None, format!("{} was made abstract", name));
+    }
+    if old_flags & ACC_FINAL == 0 && new_flags & ACC_FINAL != 0 {
+        change("class_now_final", Incompatibility::Binary, None, format!("{} was made final", name));
+    }
+    if old_flags & ACC_STATIC != 0 && new_flags & ACC_STATIC == 0 {
+        change("class_no_longer_static", Incompatibility::Binary, None, format!("{} is no longer a static member class", name));
+    }
+}
+
+fn compare_supertypes(name: &str, old: &ApiSnapshot, new: &ApiSnapshot, change: &mut Change) {
+    let new_superclasses = new.hierarchy.superclasses(name);
+    for superclass in old.hierarchy.superclasses(name).iter().skip(1) {
+        if !new_superclasses.contains(superclass) {
+            change(
+                "superclass_removed",
+                Incompatibility::Binary,
+                None,
+                format!("{} no longer extends {}", name, superclass),
+            );
+        }
+    }
+
+    let new_interfaces = new.hierarchy.all_interfaces(name);
+    for interface in old.hierarchy.all_interfaces(name) {
+        if !new_interfaces.contains(&interface) {
+            change(
+                "interface_removed",
+                Incompatibility::Binary,
+                None,
+                format!("{} no longer implements {}", name, interface),
+            );
+        }
+    }
+}
+
+fn compare_fields(name: &str, old: &ClassApi, new: &ClassApi, new_api: &ApiSnapshot, change: &mut Change) {
+    for (field_name, old_field) in &old.fields {
+        if !is_accessible(old_field.access_flags) {
+            continue;
+        }
+
+        let member = Some(format!("{}:{}", field_name, old_field.descriptor));
+        let new_field = match new.fields.get(field_name).or_else(|| new_api.find_field(name, field_name)) {
+            Some(new_field) => new_field,
+            None => {
+                change("field_removed", Incompatibility::Binary, member, format!("{}.{} was removed", name, field_name));
+                continue;
+            }
+        };
+
+        let (old_flags, new_flags) = (old_field.access_flags, new_field.access_flags);
+        let mut changed = |kind, breaks, message: &str| {
+            change(kind, breaks, member.clone(), format!("{}.{} {}", name, field_name, message));
+        };
+
+        if access_level(new_flags) < access_level(old_flags) {
+            changed("field_less_accessible", Incompatibility::Binary, "was made less accessible");
+        }
+        if new_field.descriptor != old_field.descriptor {
+            changed(
+                "field_type_changed",
+                Incompatibility::Binary,
+                &format!("changed type from {} to {}", old_field.descriptor, new_field.descriptor),
+            );
+        }
+        if old_flags & ACC_STATIC != new_flags & ACC_STATIC {
+            let message = if new_flags & ACC_STATIC != 0 { "was made static" } else { "is no longer static" };
+            changed("field_static_changed", Incompatibility::Binary, message);
+        }
+        if old_flags & ACC_FINAL == 0 && new_flags & ACC_FINAL != 0 {
+            changed("field_now_final", Incompatibility::Binary, "was made final");
+        }
+
+        // Constants are inlined by the compiler, so consumers keep the old
+        // value until they are recompiled, which breaks those already
+        // compiled (JLS 13.4.9).
+        if let Some(ref old_value) = old_field.constant_value {
+            let message = match new_field.constant_value {
+                Some(ref value) if value == old_value => None,
+                Some(ref value) => Some(format!("changed constant value from {} to {}", old_value, value)),
+                None => Some("is no longer a constant".to_string()),
+            };
+            if let Some(message) = message {
+                changed("constant_value_changed", Incompatibility::Binary, &message);
+            }
+        }
+    }
+}
+
+fn compare_methods(name: &str, old: &ClassApi, new: &ClassApi, new_api: &ApiSnapshot, change: &mut Change) {
+    let interface = new.access_flags & ACC_INTERFACE != 0;
+    // Subclasses of final classes, or of those without an accessible
+    // constructor, can't be written.
+    let extensible = new.access_flags & ACC_FINAL == 0
+        && (interface
+            || old.methods.iter().any(|((name, _), &flags)| name == "<init>" && is_accessible(flags)));
+
+    for (key, &old_flags) in &old.methods {
+        let (ref method_name, ref descriptor) = *key;
+        if !is_accessible(old_flags) || method_name == "<clinit>" {
+            continue;
+        }
+
+        let member = Some(format!("{}{}", method_name, descriptor));
+        let new_flags = match new.methods.get(key).cloned().or_else(|| new_api.find_method(name, key)) {
+            Some(new_flags) => new_flags,
+            None => {
+                // With a single method of the name before and after, it is
+                // clearer to report the change of descriptor.
+                let old_overloads: Vec<_> = old.methods.keys().filter(|(n, _)| n == method_name).collect();
+                let new_overloads: Vec<_> = new.methods.keys().filter(|(n, _)| n == method_name).collect();
+
+                if old_overloads.len() == 1 && new_overloads.len() == 1 {
+                    change(
+                        "method_descriptor_changed",
+                        Incompatibility::Binary,
+                        member,
+                        format!("{}.{} changed from {} to {}", name, method_name, descriptor, new_overloads[0].1),
+                    );
+                } else {
+                    change(
+                        "method_removed",
+                        Incompatibility::Binary,
+                        member,
+                        format!("{}.{}{} was removed", name, method_name, descriptor),
+                    );
+                }
+                continue;
+            }
+        };
+
+        let mut changed = |kind, message: &str| {
+            change(kind, Incompatibility::Binary, member.clone(), format!("{}.{}{} {}", name, method_name, descriptor, message));
+        };
+
+        if access_level(new_flags) < access_level(old_flags) {
+            changed("method_less_accessible", "was made less accessible");
+        }
+        if old_flags & ACC_STATIC != new_flags & ACC_STATIC {
+            changed(
+                "method_static_changed",
+                if new_flags & ACC_STATIC != 0 { "was made static" } else { "is no longer static" },
+            );
+        }
+        if extensible && old_flags & (ACC_FINAL | ACC_STATIC) == 0 && new_flags & ACC_FINAL != 0 {
+            changed("method_now_final", "was made final");
+        }
+        if old_flags & ACC_ABSTRACT == 0 && new_flags & ACC_ABSTRACT != 0 {
+            changed("method_now_abstract", "was made abstract");
+        }
+    }
+
+    // Consumers that implement or extend the type have to implement new
+    // abstract methods before they compile again.
+    if !extensible {
+        return;
+    }
+
+    for (key, &new_flags) in &new.methods {
+        if new_flags & ACC_ABSTRACT == 0 || !is_accessible(new_flags) || old.methods.contains_key(key) {
+            continue;
+        }
+
+        let (ref method_name, ref descriptor) = *key;
+        change(
+            "abstract_method_added",
+            Incompatibility::Source,
+            Some(format!("{}{}", method_name, descriptor)),
+            format!("{}.{}{} was added without an implementation", name, method_name, descriptor),
+        );
+    }
+}
@@ -0,0 +1,364 @@
+// Dependencies between classes, packages and modules, in the manner of the
+// JDK's jdeps: every class a class refers to through its constant pool or
+// the descriptors of its members and references.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use serde::Serialize;
+
+use class_file::access_flags::ACC_MODULE;
+use class_file::constant_pool::{ClassInfo, CpTag, MethodTypeInfo, ModuleInfo, NameAndTypeInfo};
+use class_file::ClassFile;
+
+const JAVA_BASE: &str = "java.base";
+
+/// The classes a class refers to, by internal name, leaving out itself and
+/// primitive types.
+pub fn class_dependencies(cf: &ClassFile) -> BTreeSet<String> {
+    let cp = &cf.constant_pool;
+    let mut dependencies = BTreeSet::new();
+
+    for (index, info) in cp.entries() {
+        let info = info.as_any();
+
+        match cp.tag(index) {
+            Some(CpTag::Class) => {
+                let name = info.downcast_ref::<ClassInfo>().and_then(|info| cp.utf8(info.name_index));
+                match name {
+                    // Array classes are named by their descriptor.
+                    Some(ref name) if name.starts_with('[') => dependencies.extend(descriptor_classes(name)),
+                    Some(name) => {
+                        dependencies.insert(name);
+                    }
+                    None => {}
+                }
+            }
+            Some(CpTag::NameAndType) => {
+                if let Some(descriptor) = info.downcast_ref::<NameAndTypeInfo>().and_then(|info| cp.utf8(info.descriptor_index)) {
+                    dependencies.extend(descriptor_classes(&descriptor));
+                }
+            }
+            Some(CpTag::MethodType) => {
+                if let Some(descriptor) = info.downcast_ref::<MethodTypeInfo>().and_then(|info| cp.utf8(info.descriptor_index)) {
+                    dependencies.extend(descriptor_classes(&descriptor));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let descriptors = cf.fields.array.iter().map(|f| f.descriptor_index);
+    for index in descriptors.chain(cf.methods.array.iter().map(|m| m.descriptor_index)) {
+        if let Some(descriptor) = cp.utf8(index) {
+            dependencies.extend(descriptor_classes(&descriptor));
+        }
+    }
+
+    if let Some(name) = cf.name() {
+        dependencies.remove(&name);
+    }
+
+    dependencies
+}
+
+/// The classes named in a field or method descriptor.
+pub fn descriptor_classes(descriptor: &str) -> Vec<String> {
+    let mut classes = Vec::new();
+    let mut rest = descriptor;
+
+    // Outside of class names, descriptors only have ( ) [ and the letters
+    // of the primitive types, none of them L.
+    while let Some(start) = rest.find('L') {
+        match rest[start..].find(';') {
+            Some(end) => {
+                classes.push(rest[start + 1..start + end].to_string());
+                rest = &rest[start + end + 1..];
+            }
+            None => break,
+        }
+    }
+
+    classes
+}
+
+/// The package of a class, in dotted form. The unnamed package is empty.
+pub fn package_name(class: &str) -> String {
+    class.rsplit_once('/').map_or("", |(package, _)| package).replace('/', ".")
+}
+
+/// Whether a package is an internal one of the JDK, which isn't exported
+/// to other modules.
+pub fn is_jdk_internal(package: &str) -> bool {
+    let in_package = |prefix: &str| package == prefix || package.starts_with(&format!("{}.", prefix));
+
+    in_package("sun")
+        || in_package("jdk.internal")
+        || ((in_package("com.sun") || in_package("jdk")) && package.split('.').any(|part| part == "internal"))
+}
+
+/// Collects the dependencies of classes one at a time.
+#[derive(Debug, Default)]
+pub struct DependencyAnalysis {
+    // The container, such as a jar, and dependencies of each class.
+    classes: BTreeMap<String, (String, BTreeSet<String>)>,
+    // Module names from the module-info classes of containers.
+    modules: HashMap<String, String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DependencyReport {
+    pub classes: Vec<Dependent>,
+    pub packages: Vec<Dependent>,
+    pub modules: Vec<Dependent>,
+    pub split_packages: Vec<SplitPackage>,
+    /// Packages that depend on each other, directly or not.
+    pub package_cycles: Vec<Vec<String>>,
+    pub internal_dependencies: Vec<InternalDependency>,
+    /// Classes depended on that aren't in the input or any known module.
+    pub not_found: Vec<String>,
+    /// The modules the input needs besides java.base, as `requires` of its
+    /// module-info.
+    pub requires: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Dependent {
+    pub name: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub module: Option<String>,
+
+    pub dependencies: Vec<String>,
+}
+
+/// A package with classes in more than one module.
+#[derive(Debug, Serialize)]
+pub struct SplitPackage {
+    pub package: String,
+    pub modules: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InternalDependency {
+    pub class: String,
+    pub dependency: String,
+    pub package: String,
+}
+
+impl DependencyAnalysis {
+    pub fn new() -> DependencyAnalysis {
+        DependencyAnalysis::default()
+    }
+
+    /// Adds a class found in `container`, such as the path of a jar or
+    /// directory. Classes of a container with a module-info are in that
+    /// module; those of others in an unnamed module named after the
+    /// container. The first class of a name wins.
+    pub fn add_class(&mut self, cf: &ClassFile, container: &str) {
+        if cf.access_flags & ACC_MODULE != 0 {
+            if let Some(module) = module_name(cf) {
+                self.modules.insert(container.to_string(), module);
+            }
+            return;
+        }
+
+        if let Some(name) = cf.name() {
+            self.classes
+                .entry(name)
+                .or_insert_with(|| (container.to_string(), class_dependencies(cf)));
+        }
+    }
+
+    fn module_of_container(&self, container: &str) -> String {
+        self.modules.get(container).cloned().unwrap_or_else(|| container.to_string())
+    }
+
+    /// The report, given the modules of other classes, such as those of the
+    /// JDK, by internal class name.
+    pub fn report(&self, platform: &HashMap<String, String>) -> DependencyReport {
+        let module_of = |class: &str| -> Option<String> {
+            match self.classes.get(class) {
+                Some((container, _)) => Some(self.module_of_container(container)),
+                None => platform.get(class).cloned(),
+            }
+        };
+
+        let mut classes = Vec::new();
+        let mut packages: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        let mut modules: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        let mut package_modules: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        let mut internal_dependencies = Vec::new();
+        let mut not_found = BTreeSet::new();
+
+        for (name, (container, dependencies)) in &self.classes {
+            let module = self.module_of_container(container);
+            let package = package_name(name);
+
+            package_modules.entry(package.clone()).or_default().insert(module.clone());
+            let package_dependencies = packages.entry(package.clone()).or_default();
+            let module_dependencies = modules.entry(module.clone()).or_default();
+
+            for dependency in dependencies {
+                let dependency_package = package_name(dependency);
+
+                if dependency_package != package {
+                    package_dependencies.insert(dependency_package.clone());
+                }
+
+                match module_of(dependency) {
+                    Some(ref dependency_module) if *dependency_module != module => {
+                        module_dependencies.insert(dependency_module.clone());
+                    }
+                    Some(_) => {}
+                    None => {
+                        not_found.insert(dependency.clone());
+                    }
+                }
+
+                if is_jdk_internal(&dependency_package) && !self.classes.contains_key(dependency) {
+                    internal_dependencies.push(InternalDependency {
+                        class: name.clone(),
+                        dependency: dependency.clone(),
+                        package: dependency_package,
+                    });
+                }
+            }
+
+            classes.push(Dependent {
+                name: name.clone(),
+                module: Some(module),
+                dependencies: dependencies.iter().cloned().collect(),
+            });
+        }
+
+        // Packages of the input may be split with platform modules too.
+        for (class, module) in platform {
+            if let Some(modules) = package_modules.get_mut(&package_name(class)) {
+                modules.insert(module.clone());
+            }
+        }
+
+        let split_packages = package_modules
+            .iter()
+            .filter(|&(_, modules)| modules.len() > 1)
+            .map(|(package, modules)| SplitPackage {
+                package: package.clone(),
+                modules: modules.iter().cloned().collect(),
+            })
+            .collect();
+
+        let package_cycles = cycles(&packages);
+
+        let input_modules: BTreeSet<&String> = modules.keys().collect();
+        let requires = modules
+            .values()
+            .flatten()
+            .filter(|module| *module != JAVA_BASE && !input_modules.contains(module))
+            .cloned()
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+
+        let dependents = |map: BTreeMap<String, BTreeSet<String>>| -> Vec<Dependent> {
+            map.into_iter()
+                .map(|(name, dependencies)| Dependent {
+                    name,
+                    module: None,
+                    dependencies: dependencies.into_iter().collect(),
+                })
+                .collect()
+        };
+
+        DependencyReport {
+            classes,
+            packages: dependents(packages),
+            modules: dependents(modules),
+            split_packages,
+            package_cycles,
+            internal_dependencies,
+            not_found: not_found.into_iter().collect(),
+            requires,
+        }
+    }
+}
+
+// The name of the module a module-info class declares.
+fn module_name(cf: &ClassFile) -> Option<String> {
+    let cp = &cf.constant_pool;
+    let info = &cf.attributes.find(cp, "Module")?.info;
+    let index = (*info.first()? as u16) << 8 | *info.get(1)? as u16;
+
+    cp.utf8(cp.get_as::<ModuleInfo>(index)?.name_index)
+}
+
+// The strongly connected components of more than one node, found with
+// Tarjan's algorithm, each sorted, and in order of their first node.
+fn cycles(graph: &BTreeMap<String, BTreeSet<String>>) -> Vec<Vec<String>> {
+    struct State<'a> {
+        graph: &'a BTreeMap<String, BTreeSet<String>>,
+        index: HashMap<&'a str, usize>,
+        low: HashMap<&'a str, usize>,
+        stack: Vec<&'a str>,
+        on_stack: BTreeSet<&'a str>,
+        components: Vec<Vec<String>>,
+    }
+
+    fn visit<'a>(state: &mut State<'a>, node: &'a str) {
+        let index = state.index.len();
+        state.index.insert(node, index);
+        state.low.insert(node, index);
+        state.stack.push(node);
+        state.on_stack.insert(node);
+
+        for next in state.graph.get(node).into_iter().flatten() {
+            // Only nodes of the graph itself, not the packages they depend on.
+            if !state.graph.contains_key(next) {
+                continue;
+            }
+
+            if !state.index.contains_key(next.as_str()) {
+                visit(state, next);
+                let low = state.low[node].min(state.low[next.as_str()]);
+                state.low.insert(node, low);
+            } else if state.on_stack.contains(next.as_str()) {
+                let low = state.low[node].min(state.index[next.as_str()]);
+                state.low.insert(node, low);
+            }
+        }
+
+        if state.low[node] == state.index[node] {
+            let mut component = Vec::new();
+
+            while let Some(member) = state.stack.pop() {
+                state.on_stack.remove(member);
+                component.push(member.to_string());
+                if member == node {
+                    break;
+                }
+            }
+
+            if component.len() > 1 {
+                component.sort();
+                state.components.push(component);
+            }
+        }
+    }
+
+    let mut state = State {
+        graph,
+        index: HashMap::new(),
+        low: HashMap::new(),
+        stack: Vec::new(),
+        on_stack: BTreeSet::new(),
+        components: Vec::new(),
+    };
+
+    for node in graph.keys() {
+        if !state.index.contains_key(node.as_str()) {
+            visit(&mut state, node);
+        }
+    }
+
+    state.components.sort();
+    state.components
+}
@@ -0,0 +1,234 @@
+// The Java release each class is compiled for, the features of the class file
+// format it uses that need a particular release, and the release a JVM must
+// be to load a whole archive or class path.
+
+use serde::Serialize;
+
+use bytecode::decode;
+use class_file::access_flags::ACC_MODULE;
+use class_file::attributes::{malformed, read_code};
+use class_file::constant_pool::CpTag;
+use class_file::version::JavaRelease;
+use class_file::ClassFile;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    Invokedynamic,
+    ConstantDynamic,
+    Module,
+    Record,
+    SealedType,
+    // jsr and ret.
+    Subroutines,
+}
+
+impl Feature {
+    pub fn name(self) -> &'static str {
+        match self {
+            Feature::Invokedynamic => "invokedynamic",
+            Feature::ConstantDynamic => "CONSTANT_Dynamic",
+            Feature::Module => "modules",
+            Feature::Record => "records",
+            Feature::SealedType => "sealed types",
+            Feature::Subroutines => "jsr and ret",
+        }
+    }
+
+    // The first major version with the feature, and the one before that
+    // which has it as a preview feature.
+    fn since(self) -> (u16, Option<u16>) {
+        match self {
+            Feature::Invokedynamic => (51, None),
+            Feature::ConstantDynamic => (55, None),
+            Feature::Module => (53, None),
+            Feature::Record => (60, Some(58)),
+            Feature::SealedType => (61, Some(59)),
+            Feature::Subroutines => (45, None),
+        }
+    }
+
+    // Whether class files of a release may use the feature.
+    fn allowed_in(self, release: JavaRelease) -> bool {
+        let (since, preview_since) = self.since();
+
+        match self {
+            // Type-checking verification has no subroutines.
+            Feature::Subroutines => release.major_version < 51,
+            _ => release.major_version >= since || (release.preview && preview_since.is_some_and(|v| release.major_version >= v)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClassFeatures {
+    pub class: String,
+
+    /// The entry of the class in its directory or archive.
+    pub path: String,
+
+    pub major_version: u16,
+    pub minor_version: u16,
+
+    /// None if the version isn't one of any release.
+    pub release: Option<JavaRelease>,
+
+    #[serde(skip_serializing_if = "is_false")]
+    pub preview: bool,
+
+    /// For classes under META-INF/versions/N/ of a multi-release jar, N.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub versioned_release: Option<u32>,
+
+    pub features: Vec<Feature>,
+
+    /// Features the class's version doesn't allow, and other reasons a JVM
+    /// would reject it.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub problems: Vec<String>,
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+/// The features of the class file format a class uses, in order, with any
+/// methods whose code couldn't be decoded.
+pub fn class_features(cf: &ClassFile) -> (Vec<Feature>, Vec<String>) {
+    let cp = &cf.constant_pool;
+    let mut features = Vec::new();
+    let mut errors = Vec::new();
+
+    let has_attribute = |name: &str| cf.attributes.find(cp, name).is_some();
+
+    if cf.access_flags & ACC_MODULE != 0 || has_attribute("Module") {
+        features.push(Feature::Module);
+    }
+    if has_attribute("Record") {
+        features.push(Feature::Record);
+    }
+    if has_attribute("PermittedSubclasses") {
+        features.push(Feature::SealedType);
+    }
+    if cp.entries().iter().any(|&(index, _)| cp.tag(index) == Some(CpTag::Dynamic)) {
+        features.push(Feature::ConstantDynamic);
+    }
+
+    for method in &cf.methods.array {
+        let instructions = match method.attributes.find(cp, "Code") {
+            Some(attribute) => read_code(&attribute.info)
+                .map_err(|e| malformed("Code", e))
+                .and_then(|code| decode(&code.code).map_err(|e| e.to_string())),
+            None => continue,
+        };
+
+        match instructions {
+            Ok(instructions) => {
+                for insn in instructions {
+                    let feature = match insn.opcode {
+                        0xba => Feature::Invokedynamic,
+                        0xa8 | 0xa9 | 0xc9 => Feature::Subroutines,
+                        _ => continue,
+                    };
+                    if !features.contains(&feature) {
+                        features.push(feature);
+                    }
+                }
+            }
+            Err(e) => {
+                let name = cp.utf8(method.name_index).unwrap_or_default();
+                let descriptor = cp.utf8(method.descriptor_index).unwrap_or_default();
+                errors.push(format!("{}{}: {}", name, descriptor, e));
+            }
+        }
+    }
+
+    features.sort();
+    (features, errors)
+}
+
+/// Collects the releases and features of classes one at a time.
+#[derive(Debug, Default)]
+pub struct FeatureAnalysis {
+    classes: Vec<ClassFeatures>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FeatureReport {
+    pub classes: Vec<ClassFeatures>,
+
+    /// The oldest release whose JVM loads every class outside the versioned
+    /// directories of multi-release jars, which are only loaded by JVMs of
+    /// their release and later.
+    pub minimum_release: Option<JavaRelease>,
+
+    /// Whether the minimum release must also be the exact one, run with
+    /// --enable-preview.
+    #[serde(skip_serializing_if = "is_false")]
+    pub preview: bool,
+
+    /// The classes that need the minimum release.
+    pub minimum_release_classes: Vec<String>,
+}
+
+impl FeatureAnalysis {
+    pub fn new() -> FeatureAnalysis {
+        FeatureAnalysis::default()
+    }
+
+    /// Adds a class found at `path` of its directory or archive, under the
+    /// versioned directory of `versioned_release` in a multi-release jar.
+    pub fn add_class(&mut self, cf: &ClassFile, path: &str, versioned_release: Option<u32>) {
+        let release = cf.java_release();
+        let (features, errors) = class_features(cf);
+        let mut problems = Vec::new();
+
+        match release {
+            Some(release) => {
+                for &feature in &features {
+                    if !feature.allowed_in(release) {
+                        problems.push(format!("uses {}, which class files of Java {} can't", feature.name(), release));
+                    }
+                }
+
+                if let Some(versioned_release) = versioned_release {
+                    if release.major_version as u32 > versioned_release + 44 {
+                        problems.push(format!("needs Java {} but is versioned for Java {}", release, versioned_release));
+                    }
+                }
+            }
+            None => problems.push(format!("version {}.{} is not one of any Java release", cf.major_version, cf.minor_version)),
+        }
+
+        problems.extend(errors);
+
+        self.classes.push(ClassFeatures {
+            class: cf.name().unwrap_or_default(),
+            path: path.to_string(),
+            major_version: cf.major_version,
+            minor_version: cf.minor_version,
+            release,
+            preview: release.is_some_and(|release| release.preview),
+            versioned_release,
+            features,
+            problems,
+        });
+    }
+
+    pub fn report(self) -> FeatureReport {
+        let unversioned = || self.classes.iter().filter(|class| class.versioned_release.is_none());
+
+        let minimum_release = unversioned().filter_map(|class| class.release).map(|release| release.major_version).max();
+        let needing = |class: &&ClassFeatures| class.release.map(|release| release.major_version) == minimum_release;
+
+        let minimum_release_classes = unversioned().filter(needing).map(|class| class.class.clone()).collect();
+        let preview = unversioned().filter(needing).any(|class| class.preview);
+
+        FeatureReport {
+            minimum_release: minimum_release.map(|major_version| JavaRelease { major_version, preview }),
+            preview,
+            minimum_release_classes,
+            classes: self.classes,
+        }
+    }
+}
@@ -0,0 +1,319 @@
+// The type hierarchy of a set of classes, such as those of a jar or class
+// path, built from their this_class, super_class and interfaces.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
+
+use serde::Serialize;
+
+use bytecode::cfg::dot_string;
+use bytecode::hierarchy::ClassHierarchy;
+use class_file::access_flags::{ACC_ANNOTATION, ACC_ENUM, ACC_INTERFACE, ACC_MODULE};
+use class_file::ClassFile;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TypeKind {
+    Class,
+    Interface,
+    Enum,
+    Annotation,
+}
+
+impl TypeKind {
+    pub fn of(access_flags: u16) -> TypeKind {
+        if access_flags & ACC_ANNOTATION != 0 {
+            TypeKind::Annotation
+        } else if access_flags & ACC_INTERFACE != 0 {
+            TypeKind::Interface
+        } else if access_flags & ACC_ENUM != 0 {
+            TypeKind::Enum
+        } else {
+            TypeKind::Class
+        }
+    }
+
+    /// Annotation types are interfaces too.
+    pub fn is_interface(self) -> bool {
+        self == TypeKind::Interface || self == TypeKind::Annotation
+    }
+}
+
+/// A type and its direct supertypes, by internal name.
+#[derive(Debug, Clone, Serialize)]
+pub struct TypeInfo {
+    pub name: String,
+    pub kind: TypeKind,
+    pub superclass: Option<String>,
+    pub interfaces: Vec<String>,
+
+    /// Whether the type was only added to complete the hierarchy of others,
+    /// such as a platform class.
+    #[serde(skip_serializing_if = "is_false")]
+    pub external: bool,
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+impl TypeInfo {
+    /// None for module-info, which isn't a type.
+    pub fn of(cf: &ClassFile) -> Option<TypeInfo> {
+        let cp = &cf.constant_pool;
+
+        if cf.access_flags & ACC_MODULE != 0 {
+            return None;
+        }
+
+        Some(TypeInfo {
+            name: cf.name()?,
+            kind: TypeKind::of(cf.access_flags),
+            superclass: cp.class_name(cf.super_class),
+            interfaces: cf.interfaces.iter().filter_map(|&index| cp.class_name(index)).collect(),
+            external: false,
+        })
+    }
+
+    /// The superclass followed by the interfaces.
+    pub fn supertypes(&self) -> impl Iterator<Item = &String> {
+        self.superclass.iter().chain(&self.interfaces)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct TypeHierarchy {
+    types: BTreeMap<String, TypeInfo>,
+    // Direct subtypes by the internal name of their supertype, which may not
+    // be known itself.
+    subtypes: BTreeMap<String, BTreeSet<String>>,
+}
+
+/// The hierarchy of every type that isn't external, and the supertypes it
+/// refers to that aren't known.
+#[derive(Debug, Serialize)]
+pub struct HierarchyReport {
+    pub types: Vec<TypeReport>,
+    pub missing: Vec<MissingType>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TypeReport {
+    #[serde(flatten)]
+    pub info: TypeInfo,
+
+    /// Nearest first, as far as they are known.
+    pub superclasses: Vec<String>,
+    pub all_interfaces: Vec<String>,
+    pub direct_subtypes: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MissingType {
+    pub name: String,
+    pub referenced_by: Vec<String>,
+}
+
+impl TypeHierarchy {
+    pub fn new() -> TypeHierarchy {
+        TypeHierarchy::default()
+    }
+
+    /// Adds a class. As on a class path, the first class of a name wins;
+    /// returns whether this one was added.
+    pub fn add_class(&mut self, cf: &ClassFile) -> bool {
+        match TypeInfo::of(cf) {
+            Some(info) => self.insert(info),
+            None => false,
+        }
+    }
+
+    pub fn insert(&mut self, info: TypeInfo) -> bool {
+        if self.types.contains_key(&info.name) {
+            return false;
+        }
+
+        for supertype in info.supertypes() {
+            self.subtypes.entry(supertype.clone()).or_default().insert(info.name.clone());
+        }
+
+        self.types.insert(info.name.clone(), info);
+        true
+    }
+
+    /// Adds the missing supertypes that `lookup` finds as external types,
+    /// and theirs in turn.
+    pub fn complete<F>(&mut self, mut lookup: F)
+        where F: FnMut(&str) -> Option<ClassFile> {
+        let mut tried = HashSet::new();
+
+        loop {
+            let missing: Vec<String> = self
+                .missing()
+                .into_keys()
+                .filter(|name| !tried.contains(name))
+                .collect();
+            if missing.is_empty() {
+                break;
+            }
+
+            for name in missing {
+                if let Some(mut info) = lookup(&name).as_ref().and_then(TypeInfo::of) {
+                    info.external = true;
+                    self.insert(info);
+                }
+                tried.insert(name);
+            }
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&TypeInfo> {
+        self.types.get(name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.types.contains_key(name)
+    }
+
+    /// Every known type, external ones included, sorted by name.
+    pub fn types(&self) -> impl Iterator<Item = &TypeInfo> {
+        self.types.values()
+    }
+
+    /// Every interface a type implements, directly, through its superclasses
+    /// or through other interfaces, nearest first. For an interface, those
+    /// it extends.
+    pub fn all_interfaces(&self, name: &str) -> Vec<String> {
+        let mut interfaces = Vec::new();
+        let mut queue: VecDeque<&str> = VecDeque::new();
+
+        for class in self.superclasses(name) {
+            if let Some(info) = self.types.get(&class) {
+                queue.extend(info.interfaces.iter().map(String::as_str));
+            }
+        }
+
+        while let Some(interface) = queue.pop_front() {
+            if interfaces.iter().any(|i| i == interface) {
+                continue;
+            }
+            interfaces.push(interface.to_string());
+
+            if let Some(info) = self.types.get(interface) {
+                queue.extend(info.interfaces.iter().map(String::as_str));
+            }
+        }
+
+        interfaces
+    }
+
+    /// The types that extend or implement a type directly.
+    pub fn direct_subtypes(&self, name: &str) -> Vec<String> {
+        self.subtypes.get(name).map(|set| set.iter().cloned().collect()).unwrap_or_default()
+    }
+
+    /// The types that extend or implement a type, directly or not, sorted
+    /// by name.
+    pub fn subtypes(&self, name: &str) -> Vec<String> {
+        let mut found = BTreeSet::new();
+        let mut pending = vec![name];
+
+        while let Some(name) = pending.pop() {
+            for subtype in self.subtypes.get(name).into_iter().flatten() {
+                if found.insert(subtype.clone()) {
+                    pending.push(subtype);
+                }
+            }
+        }
+
+        found.remove(name);
+        found.into_iter().collect()
+    }
+
+    /// Supertypes that are referred to but not known, with the types that
+    /// refer to them.
+    pub fn missing(&self) -> BTreeMap<String, Vec<String>> {
+        self.subtypes
+            .iter()
+            .filter(|&(name, _)| !self.types.contains_key(name))
+            .map(|(name, subtypes)| (name.clone(), subtypes.iter().cloned().collect()))
+            .collect()
+    }
+
+    pub fn report(&self) -> HierarchyReport {
+        let types = self
+            .types
+            .values()
+            .filter(|info| !info.external)
+            .map(|info| TypeReport {
+                info: info.clone(),
+                superclasses: self.superclasses(&info.name).split_off(1),
+                all_interfaces: self.all_interfaces(&info.name),
+                direct_subtypes: self.direct_subtypes(&info.name),
+            })
+            .collect();
+
+        let missing = self
+            .missing()
+            .into_iter()
+            .map(|(name, referenced_by)| MissingType { name, referenced_by })
+            .collect();
+
+        HierarchyReport { types, missing }
+    }
+
+    /// The hierarchy as a graph with edges from subtypes to supertypes:
+    /// solid for extends, dashed for implements. Interfaces have rounded
+    /// corners, external types are gray and missing ones red.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph hierarchy {\n");
+        out.push_str("    rankdir=BT;\n");
+        out.push_str("    node [shape=box, fontname=monospace];\n");
+
+        for info in self.types.values() {
+            let mut style = Vec::new();
+            if info.kind.is_interface() {
+                style.push("style=rounded");
+            }
+            if info.external {
+                style.push("color=gray");
+            }
+
+            let style = if style.is_empty() { String::new() } else { format!(" [{}]", style.join(", ")) };
+            out.push_str(&format!("    {}{};\n", dot_string(&info.name), style));
+        }
+
+        for name in self.missing().keys() {
+            out.push_str(&format!("    {} [style=dashed, color=red];\n", dot_string(name)));
+        }
+
+        for info in self.types.values() {
+            if let Some(ref superclass) = info.superclass {
+                out.push_str(&format!("    {} -> {};\n", dot_string(&info.name), dot_string(superclass)));
+            }
+            for interface in &info.interfaces {
+                out.push_str(&format!(
+                    "    {} -> {} [style=dashed];\n",
+                    dot_string(&info.name),
+                    dot_string(interface)
+                ));
+            }
+        }
+
+        out.push_str("}\n");
+        out
+    }
+}
+
+impl ClassHierarchy for TypeHierarchy {
+    fn is_known(&self, name: &str) -> bool {
+        self.types.contains_key(name)
+    }
+
+    fn superclass(&self, name: &str) -> Option<String> {
+        self.types.get(name).and_then(|info| info.superclass.clone())
+    }
+
+    fn is_interface(&self, name: &str) -> bool {
+        self.types.get(name).is_some_and(|info| info.kind.is_interface())
+    }
+}
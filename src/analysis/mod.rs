@@ -0,0 +1,9 @@
+// Views across the classes of an archive or class path, as opposed to the
+// per-class and per-method ones in class_file and bytecode.
+
+pub mod callgraph;
+pub mod compat;
+pub mod deps;
+pub mod features;
+pub mod hierarchy;
+pub mod unused;
@@ -0,0 +1,259 @@
+// Bloat left behind by compilers and code generators: constant pool entries
+// nothing in the class refers to, entries repeating an earlier one, bootstrap
+// methods no call site uses, and private fields and methods never referred to
+// from the class or its nestmates.
+
+use std::collections::{BTreeMap, HashSet};
+
+use serde::Serialize;
+
+use analysis::deps::package_name;
+use assembly::syntax::constant_spec;
+use class_file::access_flags::ACC_PRIVATE;
+use class_file::attributes::{read_index, read_index_table};
+use class_file::canonical::references;
+use class_file::constant_pool::CpTag;
+use class_file::ClassFile;
+
+// Private members the JVM or serialization use by name, with no reference
+// in any class.
+const SERIALIZATION_FIELDS: &[&str] = &["serialVersionUID", "serialPersistentFields"];
+const SERIALIZATION_METHODS: &[(&str, &str)] = &[
+    ("writeObject", "(Ljava/io/ObjectOutputStream;)V"),
+    ("readObject", "(Ljava/io/ObjectInputStream;)V"),
+    ("readObjectNoData", "()V"),
+    ("writeReplace", "()Ljava/lang/Object;"),
+    ("readResolve", "()Ljava/lang/Object;"),
+    ("$deserializeLambda$", "(Ljava/lang/invoke/SerializedLambda;)Ljava/lang/Object;"),
+];
+
+/// Collects the constant pool findings and private members of classes one at
+/// a time, and which members they refer to, and reports once all are in.
+#[derive(Debug, Default)]
+pub struct UnusedAnalysis {
+    classes: BTreeMap<String, ClassState>,
+    // Fields and methods referred to, by class, name and descriptor, from
+    // classes of the same package, where any nestmates are.
+    used_members: HashSet<(String, String, String)>,
+    errors: Vec<String>,
+}
+
+#[derive(Debug)]
+struct ClassState {
+    report: ClassReport,
+    nest_host: String,
+    nest_members: Vec<String>,
+    private_fields: Vec<(String, String)>,
+    private_methods: Vec<(String, String)>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UnusedReport {
+    /// The classes with anything unused, by name.
+    pub classes: Vec<ClassReport>,
+
+    /// Classes whose references couldn't be followed, and why.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ClassReport {
+    pub class: String,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unused_entries: Vec<UnusedEntry>,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub duplicate_entries: Vec<DuplicateEntry>,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unused_bootstrap_methods: Vec<u16>,
+
+    /// Written as name and descriptor, e.g. "count I".
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unused_private_fields: Vec<String>,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unused_private_methods: Vec<String>,
+
+    /// Members of the class's nest that aren't in the input, so that its
+    /// private members aren't reported.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub missing_nestmates: Vec<String>,
+
+    /// Attributes of unknown layout, which may refer to entries reported as
+    /// unused.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unknown_attributes: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UnusedEntry {
+    pub index: u16,
+    pub entry: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DuplicateEntry {
+    pub index: u16,
+    pub same_as: u16,
+}
+
+impl ClassReport {
+    fn is_empty(&self) -> bool {
+        self.unused_entries.is_empty()
+            && self.duplicate_entries.is_empty()
+            && self.unused_bootstrap_methods.is_empty()
+            && self.unused_private_fields.is_empty()
+            && self.unused_private_methods.is_empty()
+    }
+}
+
+impl UnusedAnalysis {
+    pub fn new() -> UnusedAnalysis {
+        UnusedAnalysis::default()
+    }
+
+    /// Adds a class. As on a class path, only the first class of a name is
+    /// used.
+    pub fn add_class(&mut self, cf: &ClassFile) {
+        let class = match cf.name() {
+            Some(class) => class,
+            None => return,
+        };
+        if self.classes.contains_key(&class) {
+            return;
+        }
+
+        let cp = &cf.constant_pool;
+        let references = match references(cf) {
+            Ok(references) => references,
+            Err(e) => {
+                self.errors.push(format!("{}: {}", class, e));
+                return;
+            }
+        };
+
+        let unused_entries = cp
+            .entries()
+            .into_iter()
+            .filter(|&(index, _)| !references.used.contains(&index))
+            .map(|(index, _)| UnusedEntry { index, entry: constant_spec(cp, index).unwrap_or_default() })
+            .collect();
+
+        // The walk has checked the layouts of these attributes.
+        let nest_host = cf
+            .attributes
+            .find(cp, "NestHost")
+            .and_then(|attribute| cp.class_name(read_index(&attribute.info).ok()?))
+            .unwrap_or_else(|| class.clone());
+        let nest_members = cf
+            .attributes
+            .find(cp, "NestMembers")
+            .and_then(|attribute| read_index_table(&attribute.info).ok())
+            .map(|indices| indices.into_iter().filter_map(|index| cp.class_name(index)).collect())
+            .unwrap_or_default();
+
+        let package = package_name(&class);
+        for &index in &references.used {
+            match cp.tag(index) {
+                Some(CpTag::Fieldref) | Some(CpTag::Methodref) | Some(CpTag::InterfaceMethodref) => {}
+                _ => continue,
+            }
+
+            if let Some(member) = cp.member_ref(index) {
+                if package_name(&member.0) == package {
+                    self.used_members.insert(member);
+                }
+            }
+        }
+
+        let private = |flags: u16| flags & ACC_PRIVATE != 0;
+        let private_fields = cf
+            .fields
+            .array
+            .iter()
+            .filter(|field| private(field.access_flags))
+            // Reads of constants are compiled to their value.
+            .filter(|field| field.attributes.find(cp, "ConstantValue").is_none())
+            .filter_map(|field| Some((cp.utf8(field.name_index)?, cp.utf8(field.descriptor_index)?)))
+            .filter(|(name, _)| !SERIALIZATION_FIELDS.contains(&name.as_str()))
+            .collect();
+        let private_methods = cf
+            .methods
+            .array
+            .iter()
+            .filter(|method| private(method.access_flags))
+            .filter_map(|method| Some((cp.utf8(method.name_index)?, cp.utf8(method.descriptor_index)?)))
+            // A private constructor without parameters is how classes that
+            // shouldn't be instantiated are written.
+            .filter(|(name, descriptor)| !(name == "<init>" && descriptor == "()V"))
+            .filter(|(name, descriptor)| !SERIALIZATION_METHODS.contains(&(name.as_str(), descriptor.as_str())))
+            .collect();
+
+        let report = ClassReport {
+            class: class.clone(),
+            unused_entries,
+            duplicate_entries: references
+                .duplicates
+                .into_iter()
+                .map(|(index, same_as)| DuplicateEntry { index, same_as })
+                .collect(),
+            unused_bootstrap_methods: references.unused_bootstrap_methods,
+            unknown_attributes: references.unknown_attributes,
+            ..ClassReport::default()
+        };
+
+        self.classes.insert(
+            class,
+            ClassState { report, nest_host, nest_members, private_fields, private_methods },
+        );
+    }
+
+    pub fn report(self) -> UnusedReport {
+        let mut findings = Vec::new();
+
+        for (class, state) in &self.classes {
+            // Nest members are listed by the host; a class without a
+            // NestHost attribute is its own.
+            let mut nest = vec![&state.nest_host];
+            if let Some(host) = self.classes.get(&state.nest_host) {
+                nest.extend(&host.nest_members);
+            }
+
+            let missing_nestmates: Vec<String> =
+                nest.into_iter().filter(|member| !self.classes.contains_key(*member)).cloned().collect();
+            if !missing_nestmates.is_empty() {
+                findings.push((missing_nestmates, Vec::new(), Vec::new()));
+                continue;
+            }
+
+            let unused = |members: &[(String, String)]| -> Vec<String> {
+                members
+                    .iter()
+                    .filter(|&(name, descriptor)| {
+                        !self.used_members.contains(&(class.clone(), name.clone(), descriptor.clone()))
+                    })
+                    .map(|(name, descriptor)| format!("{} {}", name, descriptor))
+                    .collect()
+            };
+            findings.push((Vec::new(), unused(&state.private_fields), unused(&state.private_methods)));
+        }
+
+        let classes = self
+            .classes
+            .into_iter()
+            .zip(findings)
+            .map(|((_, state), (missing_nestmates, fields, methods))| ClassReport {
+                unused_private_fields: fields,
+                unused_private_methods: methods,
+                missing_nestmates,
+                ..state.report
+            })
+            .filter(|report| !report.is_empty())
+            .collect();
+
+        UnusedReport { classes, errors: self.errors }
+    }
+}
@@ -0,0 +1,466 @@
+// Reading resources out of jimage files, the format of a JDK's lib/modules
+// runtime image.
+//
+// An image starts with a header and an index: a perfect hash table over
+// resource names, the location attributes each slot points to, and a table
+// of the strings those attributes refer to. Resource contents follow the
+// index, optionally compressed by one or more stacked decompressors.
+
+use std::io::{Read, Seek, SeekFrom};
+
+use flate2::read::ZlibDecoder;
+
+use class_file::ClassFile;
+use class_file::reader::read_classfile;
+
+pub const IMAGE_MAGIC: u32 = 0xcafe_dada;
+pub const COMPRESSED_MAGIC: u32 = 0xcafe_fafa;
+
+const HEADER_SIZE: usize = 7 * 4;
+const COMPRESSED_HEADER_SIZE: usize = 29;
+
+// Seed and multiplier of the hash used for the lookup table.
+const HASH_MULTIPLIER: i32 = 0x0100_0193;
+
+// Location attribute kinds.
+const ATTRIBUTE_END: u8 = 0;
+const ATTRIBUTE_MODULE: usize = 1;
+const ATTRIBUTE_PARENT: usize = 2;
+const ATTRIBUTE_BASE: usize = 3;
+const ATTRIBUTE_EXTENSION: usize = 4;
+const ATTRIBUTE_OFFSET: usize = 5;
+const ATTRIBUTE_COMPRESSED: usize = 6;
+const ATTRIBUTE_UNCOMPRESSED: usize = 7;
+const ATTRIBUTE_COUNT: usize = 8;
+
+// Constant pool tags used by the compact-cp decompressor for Utf8 entries
+// moved to the image's string table.
+const EXTERNALIZED_STRING: u8 = 23;
+const EXTERNALIZED_STRING_DESCRIPTOR: u8 = 25;
+
+/// Where a resource is and how it's stored. The name parts are empty when
+/// absent.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Location {
+    pub module: String,
+    pub parent: String,
+    pub base: String,
+    pub extension: String,
+    // Relative to the end of the index.
+    pub offset: u64,
+    // Zero for uncompressed resources.
+    pub compressed_size: u64,
+    pub uncompressed_size: u64,
+}
+
+impl Location {
+    /// The name the resource is looked up by, e.g.
+    /// /java.base/java/lang/String.class.
+    pub fn full_name(&self) -> String {
+        let mut name = String::new();
+
+        if !self.module.is_empty() {
+            name.push('/');
+            name.push_str(&self.module);
+            name.push('/');
+        }
+        if !self.parent.is_empty() {
+            name.push_str(&self.parent);
+            name.push('/');
+        }
+        name.push_str(&self.base);
+        if !self.extension.is_empty() {
+            name.push('.');
+            name.push_str(&self.extension);
+        }
+
+        name
+    }
+}
+
+pub struct Image<R: Read + Seek> {
+    reader: R,
+    big_endian: bool,
+    pub major_version: u16,
+    pub minor_version: u16,
+    resource_count: usize,
+    redirect: Vec<i32>,
+    offsets: Vec<u32>,
+    locations: Vec<u8>,
+    strings: Vec<u8>,
+    index_size: u64,
+}
+
+impl<R: Read + Seek> Image<R> {
+    /// Reads the index. The image is in the byte order of the platform that
+    /// built it, which is detected from the magic number.
+    pub fn new(mut reader: R) -> Result<Image<R>, String> {
+        let header = read_exact(&mut reader, HEADER_SIZE)?;
+
+        let big_endian = if header[..4] == IMAGE_MAGIC.to_le_bytes() {
+            false
+        } else if header[..4] == IMAGE_MAGIC.to_be_bytes() {
+            true
+        } else {
+            return Err(String::from("not a jimage file"));
+        };
+
+        let field = |index: usize| u32_at(&header, index * 4, big_endian);
+        let version = field(1);
+        let resource_count = field(3) as usize;
+        let table_length = field(4) as usize;
+        let locations_size = field(5) as usize;
+        let strings_size = field(6) as usize;
+
+        let redirect = read_exact(&mut reader, table_length * 4)?;
+        let offsets = read_exact(&mut reader, table_length * 4)?;
+
+        Ok(Image {
+            big_endian,
+            major_version: (version >> 16) as u16,
+            minor_version: version as u16,
+            resource_count,
+            redirect: (0..table_length).map(|i| u32_at(&redirect, i * 4, big_endian) as i32).collect(),
+            offsets: (0..table_length).map(|i| u32_at(&offsets, i * 4, big_endian)).collect(),
+            locations: read_exact(&mut reader, locations_size)?,
+            strings: read_exact(&mut reader, strings_size)?,
+            index_size: (HEADER_SIZE + table_length * 8 + locations_size + strings_size) as u64,
+            reader,
+        })
+    }
+
+    /// Number of resources.
+    pub fn len(&self) -> usize {
+        self.resource_count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.resource_count == 0
+    }
+
+    /// Every resource in the image, in table order.
+    pub fn locations(&self) -> Vec<Location> {
+        self.offsets
+            .iter()
+            .filter(|&&offset| offset != 0)
+            .filter_map(|&offset| self.location_at(offset as usize))
+            .collect()
+    }
+
+    pub fn find(&self, name: &str) -> Option<Location> {
+        let count = self.redirect.len() as i32;
+        if count == 0 {
+            return None;
+        }
+
+        let index = match self.redirect[(hash_code(name.as_bytes(), HASH_MULTIPLIER) % count) as usize] {
+            // A slot that no name hashes to.
+            0 => return None,
+            // Negative entries store the index directly.
+            redirect if redirect < 0 => -1 - redirect,
+            // Positive ones are the seed of a second hash.
+            seed => hash_code(name.as_bytes(), seed) % count,
+        };
+
+        // Offset 0 marks a slot of the table that isn't used.
+        let location = match *self.offsets.get(index as usize)? {
+            0 => return None,
+            offset => self.location_at(offset as usize)?,
+        };
+
+        // Names that aren't in the image can still hash to a used slot.
+        if location.full_name() == name {
+            Some(location)
+        } else {
+            None
+        }
+    }
+
+    /// The contents of a resource, decompressed.
+    pub fn read(&mut self, location: &Location) -> Result<Vec<u8>, String> {
+        let size = if location.compressed_size != 0 {
+            location.compressed_size
+        } else {
+            location.uncompressed_size
+        };
+
+        self.reader
+            .seek(SeekFrom::Start(self.index_size + location.offset))
+            .map_err(|e| e.to_string())?;
+        let bytes = read_exact(&mut self.reader, size as usize)?;
+
+        if location.compressed_size != 0 {
+            self.decompress(bytes)
+        } else {
+            Ok(bytes)
+        }
+    }
+
+    pub fn resource(&mut self, name: &str) -> Result<Vec<u8>, String> {
+        match self.find(name) {
+            Some(location) => self.read(&location),
+            None => Err(format!("{} not found in image", name)),
+        }
+    }
+
+    /// Reads a class by module and internal name, e.g.
+    /// java.base/java/lang/String.
+    pub fn class(&mut self, name: &str) -> Result<ClassFile, String> {
+        let bytes = self.resource(&format!("/{}.class", name))?;
+
+        read_classfile(&mut &bytes[..]).map_err(|e| e.to_string())
+    }
+
+    fn location_at(&self, offset: usize) -> Option<Location> {
+        let mut attributes = [0u64; ATTRIBUTE_COUNT];
+        let mut i = offset;
+
+        loop {
+            let byte = *self.locations.get(i)?;
+            i += 1;
+
+            let kind = byte >> 3;
+            if kind == ATTRIBUTE_END {
+                break;
+            }
+
+            // Values are big-endian whatever the image byte order.
+            let length = (byte & 0x7) as usize + 1;
+            let mut value = 0u64;
+            for _ in 0..length {
+                value = value << 8 | *self.locations.get(i)? as u64;
+                i += 1;
+            }
+
+            *attributes.get_mut(kind as usize)? = value;
+        }
+
+        Some(Location {
+            module: self.string(attributes[ATTRIBUTE_MODULE] as usize)?,
+            parent: self.string(attributes[ATTRIBUTE_PARENT] as usize)?,
+            base: self.string(attributes[ATTRIBUTE_BASE] as usize)?,
+            extension: self.string(attributes[ATTRIBUTE_EXTENSION] as usize)?,
+            offset: attributes[ATTRIBUTE_OFFSET],
+            compressed_size: attributes[ATTRIBUTE_COMPRESSED],
+            uncompressed_size: attributes[ATTRIBUTE_UNCOMPRESSED],
+        })
+    }
+
+    // Strings are NUL-terminated modified UTF-8.
+    fn string_bytes(&self, offset: usize) -> Option<&[u8]> {
+        let rest = self.strings.get(offset..)?;
+        let end = rest.iter().position(|&b| b == 0)?;
+
+        Some(&rest[..end])
+    }
+
+    fn string(&self, offset: usize) -> Option<String> {
+        self.string_bytes(offset).map(|bytes| String::from_utf8_lossy(bytes).into_owned())
+    }
+
+    // Undoes each layer of compression in turn; every layer starts with a
+    // header naming its decompressor.
+    fn decompress(&self, mut bytes: Vec<u8>) -> Result<Vec<u8>, String> {
+        while bytes.len() >= COMPRESSED_HEADER_SIZE && u32_at(&bytes, 0, self.big_endian) == COMPRESSED_MAGIC {
+            let uncompressed_size = u64_at(&bytes, 12, self.big_endian);
+            let name_offset = u32_at(&bytes, 20, self.big_endian) as usize;
+            let content = &bytes[COMPRESSED_HEADER_SIZE..];
+
+            let decompressed = match self.string(name_offset) {
+                Some(ref name) if name == "zip" => {
+                    let mut out = Vec::with_capacity(uncompressed_size as usize);
+                    ZlibDecoder::new(content).read_to_end(&mut out).map_err(|e| e.to_string())?;
+                    out
+                }
+                Some(ref name) if name == "compact-cp" => self.expand_strings(content)?,
+                Some(name) => return Err(format!("unknown decompressor {}", name)),
+                None => return Err(String::from("bad decompressor name")),
+            };
+
+            if decompressed.len() as u64 != uncompressed_size {
+                return Err(String::from("decompressed resource has the wrong size"));
+            }
+
+            bytes = decompressed;
+        }
+
+        Ok(bytes)
+    }
+
+    // The compact-cp decompressor: copies a class file, bringing back the
+    // Utf8 constants that were replaced by references to the string table.
+    fn expand_strings(&self, content: &[u8]) -> Result<Vec<u8>, String> {
+        let mut f = Cursor { bytes: content, position: 0 };
+        let mut out = Vec::with_capacity(content.len() * 2);
+
+        // Magic and version.
+        out.extend_from_slice(f.take(8)?);
+
+        let count = f.u16()?;
+        out.extend_from_slice(&count.to_be_bytes());
+
+        let mut index = 1;
+        while index < count {
+            let tag = f.u8()?;
+
+            let utf8 = match tag {
+                EXTERNALIZED_STRING => self.shared_string(f.compressed_int()?)?.to_vec(),
+                EXTERNALIZED_STRING_DESCRIPTOR => self.shared_descriptor(&mut f)?,
+                _ => {
+                    let size = match tag {
+                        1 => f.u16_at(f.position)? as usize + 2,
+                        3 | 4 | 9 | 10 | 11 | 12 | 17 | 18 => 4,
+                        5 | 6 => 8,
+                        7 | 8 | 16 | 19 | 20 => 2,
+                        15 => 3,
+                        _ => return Err(format!("unknown constant pool tag {}", tag)),
+                    };
+
+                    out.push(tag);
+                    out.extend_from_slice(f.take(size)?);
+
+                    // Long and Double take two entries.
+                    index += if tag == 5 || tag == 6 { 2 } else { 1 };
+                    continue;
+                }
+            };
+
+            out.push(1);
+            out.extend_from_slice(&(utf8.len() as u16).to_be_bytes());
+            out.extend_from_slice(&utf8);
+            index += 1;
+        }
+
+        out.extend_from_slice(&content[f.position..]);
+
+        Ok(out)
+    }
+
+    fn shared_string(&self, offset: u32) -> Result<&[u8], String> {
+        self.string_bytes(offset as usize)
+            .ok_or_else(|| format!("bad string table offset {}", offset))
+    }
+
+    // A descriptor stored as a skeleton with the class names taken out, e.g.
+    // (L;)V, followed by package and simple name offsets for each L.
+    fn shared_descriptor(&self, f: &mut Cursor) -> Result<Vec<u8>, String> {
+        let skeleton = self.shared_string(f.compressed_int()?)?;
+        let length = f.compressed_int()? as usize;
+
+        let mut indexes = Cursor { bytes: f.take(length)?, position: 0 };
+        let mut descriptor = Vec::new();
+
+        for &c in skeleton {
+            descriptor.push(c);
+
+            if c == b'L' {
+                let package = self.shared_string(indexes.compressed_int()?)?;
+                if !package.is_empty() {
+                    descriptor.extend_from_slice(package);
+                    descriptor.push(b'/');
+                }
+                descriptor.extend_from_slice(self.shared_string(indexes.compressed_int()?)?);
+            }
+        }
+
+        Ok(descriptor)
+    }
+}
+
+// The table hash: FNV-1 over the bytes of the name, kept positive.
+fn hash_code(name: &[u8], seed: i32) -> i32 {
+    let mut hash = seed;
+
+    for &b in name {
+        hash = hash.wrapping_mul(HASH_MULTIPLIER) ^ b as i32;
+    }
+
+    hash & 0x7fff_ffff
+}
+
+fn read_exact(reader: &mut dyn Read, length: usize) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    reader.take(length as u64).read_to_end(&mut bytes).map_err(|e| e.to_string())?;
+
+    if bytes.len() < length {
+        return Err(String::from("truncated image"));
+    }
+
+    Ok(bytes)
+}
+
+fn u32_at(bytes: &[u8], offset: usize, big_endian: bool) -> u32 {
+    let mut buf = [0u8; 4];
+    buf.copy_from_slice(&bytes[offset..offset + 4]);
+
+    if big_endian {
+        u32::from_be_bytes(buf)
+    } else {
+        u32::from_le_bytes(buf)
+    }
+}
+
+fn u64_at(bytes: &[u8], offset: usize, big_endian: bool) -> u64 {
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(&bytes[offset..offset + 8]);
+
+    if big_endian {
+        u64::from_be_bytes(buf)
+    } else {
+        u64::from_le_bytes(buf)
+    }
+}
+
+// A big-endian reader over a compressed class file.
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position + length)
+            .ok_or_else(|| String::from("truncated compressed resource"))?;
+        self.position += length;
+
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let bytes = self.take(2)?;
+
+        Ok((bytes[0] as u16) << 8 | bytes[1] as u16)
+    }
+
+    fn u16_at(&self, position: usize) -> Result<u16, String> {
+        match self.bytes.get(position..position + 2) {
+            Some(bytes) => Ok((bytes[0] as u16) << 8 | bytes[1] as u16),
+            None => Err(String::from("truncated compressed resource")),
+        }
+    }
+
+    // A variable-length int: if the top bit of the first byte is set, the
+    // next two bits give the total length in bytes and the low five bits
+    // start the value, otherwise the value is a plain four-byte int.
+    fn compressed_int(&mut self) -> Result<u32, String> {
+        let first = self.u8()?;
+
+        let (length, mut value) = if first & 0x80 != 0 {
+            ((first >> 5) & 0x3, (first & 0x1f) as u32)
+        } else {
+            (4, first as u32)
+        };
+
+        for _ in 1..length {
+            value = value << 8 | self.u8()? as u32;
+        }
+
+        Ok(value)
+    }
+}
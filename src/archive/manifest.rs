@@ -0,0 +1,135 @@
+// JAR manifests (META-INF/MANIFEST.MF).
+
+use std::collections::BTreeMap;
+
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde::ser::{Serialize, SerializeMap, Serializer};
+
+pub const MANIFEST_PATH: &str = "META-INF/MANIFEST.MF";
+
+/// The main section and the per-entry sections of a manifest, the latter
+/// keyed by their Name attribute.
+#[derive(Debug, Default, Clone, serde::Serialize, JsonSchema)]
+pub struct Manifest {
+    pub main: Attributes,
+    pub entries: BTreeMap<String, Attributes>,
+}
+
+/// Attributes of one manifest section in the order they appear. Names are
+/// compared case-insensitively, as in java.util.jar.Attributes.
+#[derive(Debug, Default, Clone)]
+pub struct Attributes {
+    pairs: Vec<(String, String)>,
+}
+
+impl Attributes {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.pairs
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.pairs.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    // Later duplicates replace earlier ones.
+    fn insert(&mut self, name: String, value: String) {
+        match self.pairs.iter_mut().find(|(n, _)| n.eq_ignore_ascii_case(&name)) {
+            Some(pair) => pair.1 = value,
+            None => self.pairs.push((name, value)),
+        }
+    }
+}
+
+impl Serialize for Attributes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.pairs.len()))?;
+        for (name, value) in &self.pairs {
+            map.serialize_entry(name, value)?;
+        }
+        map.end()
+    }
+}
+
+// A map of names to values.
+impl JsonSchema for Attributes {
+    fn schema_name() -> String {
+        String::from("ManifestAttributes")
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        BTreeMap::<String, String>::json_schema(gen)
+    }
+}
+
+impl Manifest {
+    /// Parses a manifest leniently: lines without a colon are ignored, and
+    /// per-entry sections without a Name attribute are dropped.
+    pub fn parse(text: &str) -> Manifest {
+        let mut manifest = Manifest::default();
+        let mut sections = vec![Attributes::default()];
+        let mut last_value: Option<usize> = None;
+
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+
+        for line in text.split('\n') {
+            let section = sections.last_mut().unwrap();
+
+            if line.is_empty() {
+                if !section.is_empty() {
+                    sections.push(Attributes::default());
+                }
+                last_value = None;
+            } else if let Some(continuation) = line.strip_prefix(' ') {
+                // Long values are wrapped onto lines starting with a space.
+                if let Some(index) = last_value {
+                    section.pairs[index].1.push_str(continuation);
+                }
+            } else if let Some((name, value)) = line.split_once(':') {
+                let value = value.strip_prefix(' ').unwrap_or(value);
+                section.insert(name.trim().to_string(), value.to_string());
+                last_value = section.pairs.iter().position(|(n, _)| n.eq_ignore_ascii_case(name.trim()));
+            } else {
+                last_value = None;
+            }
+        }
+
+        let mut sections = sections.into_iter();
+        manifest.main = sections.next().unwrap();
+
+        for section in sections {
+            if let Some(name) = section.get("Name").map(String::from) {
+                let mut attributes = Attributes::default();
+                for (n, v) in section.iter().filter(|(n, _)| !n.eq_ignore_ascii_case("Name")) {
+                    attributes.insert(n.to_string(), v.to_string());
+                }
+                manifest.entries.insert(name, attributes);
+            }
+        }
+
+        manifest
+    }
+
+    pub fn is_multi_release(&self) -> bool {
+        self.main
+            .get("Multi-Release")
+            .is_some_and(|value| value.trim().eq_ignore_ascii_case("true"))
+    }
+
+    /// Attributes of the per-entry section for an archive path.
+    pub fn entry(&self, path: &str) -> Option<&Attributes> {
+        self.entries.get(path)
+    }
+}
@@ -0,0 +1,370 @@
+// Reading class files out of JAR, WAR, EAR, ZIP and JMOD archives and jimage
+// files.
+
+use std::collections::HashMap;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{InstanceType, ObjectValidation, Schema, SchemaObject, SubschemaValidation};
+use schemars::JsonSchema;
+use serde::Serialize;
+use zip::read::ZipFile;
+use zip::ZipArchive;
+
+use class_file::ClassFile;
+use class_file::reader::read_classfile;
+
+use self::manifest::{Manifest, MANIFEST_PATH};
+
+pub mod jimage;
+pub mod manifest;
+pub mod pack200;
+
+/// Signature at the start of a ZIP local file header, and so of most
+/// archives.
+pub const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+/// JMOD files are a ZIP archive behind this header. Their classes are under
+/// classes/.
+pub const JMOD_MAGIC: &[u8] = b"JM\x01\x00";
+
+// Where the application classes of Spring Boot jars and WARs are; libraries
+// are nested archives in BOOT-INF/lib/ and WEB-INF/lib/.
+const CLASS_DIRECTORIES: &[&str] = &["BOOT-INF/classes/", "WEB-INF/classes/"];
+
+// Versioned entries of multi-release jars live under META-INF/versions/N/.
+const VERSIONS_PREFIX: &str = "META-INF/versions/";
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ArchiveEntry {
+    pub path: String,
+
+    // For entries under META-INF/versions/N/ of a multi-release jar, N.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub release: Option<u32>,
+
+    #[serde(flatten)]
+    pub content: EntryContent,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryContent {
+    Class(ClassFile),
+    Manifest(Manifest),
+    // An entry that could not be read, or a .class entry that could not be
+    // parsed.
+    Error(String),
+    // Any other file.
+    Resource { size: u64 },
+}
+
+// One of the variants as a property of the entry it is flattened into, which
+// has other properties besides.
+impl JsonSchema for EntryContent {
+    fn schema_name() -> String {
+        String::from("EntryContent")
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        let resource = object_schema(vec![("size", gen.subschema_for::<u64>())]);
+        let variants = vec![
+            object_schema(vec![("class", gen.subschema_for::<ClassFile>())]),
+            object_schema(vec![("manifest", gen.subschema_for::<Manifest>())]),
+            object_schema(vec![("error", gen.subschema_for::<String>())]),
+            object_schema(vec![("resource", resource)]),
+        ];
+
+        SchemaObject {
+            subschemas: Some(Box::new(SubschemaValidation {
+                one_of: Some(variants),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+// An object with all of the given properties.
+fn object_schema(properties: Vec<(&str, Schema)>) -> Schema {
+    let mut object = ObjectValidation::default();
+
+    for (name, schema) in properties {
+        object.required.insert(name.to_string());
+        object.properties.insert(name.to_string(), schema);
+    }
+
+    SchemaObject {
+        instance_type: Some(InstanceType::Object.into()),
+        object: Some(Box::new(object)),
+        ..Default::default()
+    }
+    .into()
+}
+
+/// An entry that has been read but not parsed yet, so that parsing can be
+/// done elsewhere, such as on another thread.
+#[derive(Debug)]
+pub struct RawEntry {
+    pub path: String,
+    pub release: Option<u32>,
+    pub content: RawContent,
+}
+
+#[derive(Debug)]
+pub enum RawContent {
+    // The contents of a class file or manifest.
+    Bytes(Vec<u8>),
+    Error(String),
+    Resource { size: u64 },
+}
+
+pub struct Archive<R: Read + Seek> {
+    zip: ZipArchive<R>,
+    // Entry names by index, None where the entry header is unreadable.
+    names: Vec<Option<String>>,
+    manifest: Option<Manifest>,
+    jmod: bool,
+}
+
+pub fn is_archive(header: &[u8]) -> bool {
+    header.starts_with(ZIP_MAGIC) || header.starts_with(JMOD_MAGIC) || pack200::is_packed(header)
+}
+
+/// Whether an entry is an archive whose classes can be loaded too: the
+/// libraries of Spring Boot jars and WARs, and the modules of an EAR.
+pub fn is_nested_archive(path: &str) -> bool {
+    let path = path.to_ascii_lowercase();
+
+    path.ends_with(".jar") || path.ends_with(".war")
+}
+
+/// Whether an entry is parsed rather than listed as a resource.
+pub fn is_parsed(path: &str) -> bool {
+    path.ends_with(".class") || path == MANIFEST_PATH
+}
+
+impl RawEntry {
+    pub fn parse(self) -> ArchiveEntry {
+        let content = match self.content {
+            // The path may be prefixed with those of enclosing archives.
+            RawContent::Bytes(bytes) if self.path.rsplit("!/").next() == Some(MANIFEST_PATH) => {
+                EntryContent::Manifest(Manifest::parse(&String::from_utf8_lossy(&bytes)))
+            }
+            RawContent::Bytes(bytes) => match read_classfile(&mut &bytes[..]) {
+                Ok(cf) => EntryContent::Class(cf),
+                Err(e) => EntryContent::Error(e.to_string()),
+            },
+            RawContent::Error(e) => EntryContent::Error(e),
+            RawContent::Resource { size } => EntryContent::Resource { size },
+        };
+
+        ArchiveEntry {
+            path: self.path,
+            release: self.release,
+            content,
+        }
+    }
+}
+
+impl<R: Read + Seek> Archive<R> {
+    pub fn new(mut reader: R) -> Result<Archive<R>, String> {
+        let mut header = [0u8; 4];
+        reader.seek(SeekFrom::Start(0)).map_err(|e| e.to_string())?;
+        let jmod = reader.read_exact(&mut header).is_ok() && header == JMOD_MAGIC;
+        reader.seek(SeekFrom::Start(0)).map_err(|e| e.to_string())?;
+
+        let mut zip = ZipArchive::new(reader).map_err(|e| e.to_string())?;
+
+        let names = (0..zip.len())
+            .map(|index| zip.by_index_raw(index).ok().map(|file| file.name().to_string()))
+            .collect();
+
+        let manifest = read_manifest(&mut zip).ok();
+
+        Ok(Archive {
+            zip,
+            names,
+            manifest,
+            jmod,
+        })
+    }
+
+    /// Number of entries, including directories.
+    pub fn len(&self) -> usize {
+        self.zip.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.zip.is_empty()
+    }
+
+    /// The parsed META-INF/MANIFEST.MF, if there is one.
+    pub fn manifest(&self) -> Option<&Manifest> {
+        self.manifest.as_ref()
+    }
+
+    pub fn is_multi_release(&self) -> bool {
+        self.manifest.as_ref().is_some_and(Manifest::is_multi_release)
+    }
+
+    /// Indices of the entries a JVM of the given Java release would load, in
+    /// archive order. In a multi-release jar each path resolves to the entry
+    /// in the highest META-INF/versions/N/ with N <= release, falling back to
+    /// the unversioned entry; other archives are returned whole.
+    pub fn release_entries(&self, release: u32) -> Vec<usize> {
+        if !self.is_multi_release() {
+            return (0..self.len()).collect();
+        }
+
+        let mut order = Vec::new();
+        let mut chosen: HashMap<&str, (usize, u32)> = HashMap::new();
+
+        for (index, name) in self.names.iter().enumerate() {
+            let name = match *name {
+                Some(ref name) => name.as_str(),
+                None => {
+                    order.push(Err(index));
+                    continue;
+                }
+            };
+
+            let (path, version) = match split_versioned(name) {
+                Some((version, _)) if version > release => continue,
+                Some((version, path)) => (path, version),
+                // The versions directories themselves, and malformed names.
+                None if name.starts_with(VERSIONS_PREFIX) => continue,
+                None => (name, 0),
+            };
+
+            match chosen.get(path) {
+                Some(&(_, best)) if best >= version => {}
+                Some(_) => {
+                    chosen.insert(path, (index, version));
+                }
+                None => {
+                    chosen.insert(path, (index, version));
+                    order.push(Ok(path));
+                }
+            }
+        }
+
+        order
+            .into_iter()
+            .map(|item| match item {
+                Ok(path) => chosen[path].0,
+                Err(index) => index,
+            })
+            .collect()
+    }
+
+    pub fn name(&self, index: usize) -> Option<&str> {
+        self.names.get(index)?.as_deref()
+    }
+
+    /// The path of an entry relative to where classes are loaded from,
+    /// leaving out the classes/ directory of a JMOD, BOOT-INF/classes/ of a
+    /// Spring Boot jar, WEB-INF/classes/ of a WAR and the
+    /// META-INF/versions/N/ of a multi-release jar.
+    pub fn class_path(&self, index: usize) -> Option<&str> {
+        let mut name = self.name(index)?;
+
+        if self.jmod {
+            name = name.strip_prefix("classes/")?;
+        } else if let Some(path) = CLASS_DIRECTORIES.iter().find_map(|dir| name.strip_prefix(dir)) {
+            name = path;
+        }
+        if self.is_multi_release() {
+            if let Some((_, path)) = split_versioned(name) {
+                name = path;
+            }
+        }
+
+        Some(name)
+    }
+
+    /// Reads the entry at `index` in central directory order, parsing it if
+    /// it is a class file or the manifest. Returns None for directories.
+    pub fn entry(&mut self, index: usize) -> Option<ArchiveEntry> {
+        self.read(index).map(RawEntry::parse)
+    }
+
+    /// Reads an archive stored in an entry, such as a library in
+    /// BOOT-INF/lib/ of a Spring Boot jar, into memory.
+    pub fn open_nested(&mut self, index: usize) -> Result<Archive<Cursor<Vec<u8>>>, String> {
+        let mut file = self.zip.by_index(index).map_err(|e| e.to_string())?;
+        let mut bytes = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut bytes).map_err(|e| e.to_string())?;
+
+        Archive::new(Cursor::new(bytes))
+    }
+
+    /// Like `entry`, but leaves the parsing to the caller.
+    pub fn read(&mut self, index: usize) -> Option<RawEntry> {
+        let multi_release = self.is_multi_release();
+
+        let error = match self.zip.by_index(index) {
+            Ok(file) => return read_entry(file, multi_release),
+            Err(e) => e.to_string(),
+        };
+
+        // The raw entry may still be readable enough to name it.
+        let path = match self.names[index] {
+            Some(ref name) => name.clone(),
+            None => format!("#{}", index),
+        };
+
+        Some(RawEntry {
+            path,
+            release: None,
+            content: RawContent::Error(error),
+        })
+    }
+}
+
+// Splits "META-INF/versions/N/path" into N and path.
+fn split_versioned(name: &str) -> Option<(u32, &str)> {
+    let rest = name.strip_prefix(VERSIONS_PREFIX)?;
+    let (version, path) = rest.split_once('/')?;
+
+    if path.is_empty() {
+        return None;
+    }
+
+    version.parse().ok().map(|version| (version, path))
+}
+
+fn read_manifest<R: Read + Seek>(zip: &mut ZipArchive<R>) -> Result<Manifest, String> {
+    let mut file = zip.by_name(MANIFEST_PATH).map_err(|e| e.to_string())?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes).map_err(|e| e.to_string())?;
+
+    Ok(Manifest::parse(&String::from_utf8_lossy(&bytes)))
+}
+
+fn read_entry(mut file: ZipFile, multi_release: bool) -> Option<RawEntry> {
+    if file.is_dir() {
+        return None;
+    }
+
+    let path = file.name().to_string();
+    let release = if multi_release {
+        split_versioned(&path).map(|(version, _)| version)
+    } else {
+        None
+    };
+
+    let content = if is_parsed(&path) {
+        let mut bytes = Vec::new();
+
+        match file.read_to_end(&mut bytes) {
+            Ok(_) => RawContent::Bytes(bytes),
+            Err(e) => RawContent::Error(e.to_string()),
+        }
+    } else {
+        RawContent::Resource { size: file.size() }
+    };
+
+    Some(RawEntry { path, release, content })
+}
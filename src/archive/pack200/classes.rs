@@ -0,0 +1,1650 @@
+// The class bands of a Pack200 segment: attribute definitions, inner
+// classes, classes with their fields, methods, attributes and bytecodes,
+// and their reassembly into class files.
+
+use std::collections::{HashMap, HashSet};
+
+use super::coding::{Band, Coding, Reader, BCI5, BRANCH5, BYTE1, DELTA5, MDELTA5, UDELTA5, UNSIGNED5};
+use super::layout::{to_bci, Context, Layout};
+use super::pool::{self, Entry, Index, Output, Pool};
+
+// Archive options.
+pub const HAVE_ALL_CODE_FLAGS: u32 = 1 << 2;
+pub const HAVE_CLASS_FLAGS_HI: u32 = 1 << 9;
+pub const HAVE_FIELD_FLAGS_HI: u32 = 1 << 10;
+pub const HAVE_METHOD_FLAGS_HI: u32 = 1 << 11;
+pub const HAVE_CODE_FLAGS_HI: u32 = 1 << 12;
+
+const CLASS: usize = 0;
+const FIELD: usize = 1;
+const METHOD: usize = 2;
+const CODE: usize = 3;
+
+// Attribute indexes with a fixed meaning unless an archive redefines them.
+const OVERFLOW: usize = 16;
+const SOURCE_FILE: usize = 17;
+const ENCLOSING_METHOD: usize = 18;
+const SIGNATURE: usize = 19;
+const DEPRECATED: usize = 20;
+const RUNTIME_VISIBLE_ANNOTATIONS: usize = 21;
+const RUNTIME_INVISIBLE_ANNOTATIONS: usize = 22;
+const INNER_CLASSES: usize = 23;
+const CLASS_FILE_VERSION: usize = 24;
+const CONSTANT_VALUE: usize = 17;
+const CODE_ATTRIBUTE: usize = 17;
+const EXCEPTIONS: usize = 18;
+const RUNTIME_VISIBLE_PARAMETER_ANNOTATIONS: usize = 23;
+const RUNTIME_INVISIBLE_PARAMETER_ANNOTATIONS: usize = 24;
+const ANNOTATION_DEFAULT: usize = 25;
+const METHOD_PARAMETERS: usize = 26;
+const RUNTIME_VISIBLE_TYPE_ANNOTATIONS: usize = 27;
+const RUNTIME_INVISIBLE_TYPE_ANNOTATIONS: usize = 28;
+const STACK_MAP_TABLE: usize = 0;
+const LINE_NUMBER_TABLE: usize = 1;
+const LOCAL_VARIABLE_TABLE: usize = 2;
+const LOCAL_VARIABLE_TYPE_TABLE: usize = 3;
+
+// Layouts of the annotation attributes, which are read like attributes
+// defined by the archive.
+const MDL0: &str = "[NB[(1)]]";
+const MDL1: &str = "[NH[(1)]]";
+const MDL2: &str = "[RSHNH[RUH(1)]]";
+const MDL3: &str = "[TB(66,67,73,83,90)[KIH](68)[KDH](70)[KFH](74)[KJH](99)[RSH](101)[RSHRUH](115)[RUH](91)[NH[(0)]](64)[RSHNH[RUH(0)]]()[]]";
+const TYPE_ANNOTATION: &str = "[NH[(1)(2)(3)]][TB(0,1)[B](16)[FH](17,18)[BB](19,20,21)[](22)[B](23)[H](64,65)[NH[PHOHH]](66)[H](67,68,69,70)[PH](71,72,73,74,75)[PHB]()[]][NB[BB]]";
+
+// The predefined attributes of each context that have a layout.
+fn metadata_layouts(context: usize) -> Vec<(usize, &'static str, String)> {
+    let annotations = format!("{}{}{}", MDL1, MDL2, MDL3);
+    let type_annotations = format!("{}{}{}", TYPE_ANNOTATION, MDL2, MDL3);
+    let mut layouts = vec![
+        (RUNTIME_VISIBLE_TYPE_ANNOTATIONS, "RuntimeVisibleTypeAnnotations", type_annotations.clone()),
+        (RUNTIME_INVISIBLE_TYPE_ANNOTATIONS, "RuntimeInvisibleTypeAnnotations", type_annotations),
+    ];
+
+    if context != CODE {
+        layouts.push((RUNTIME_VISIBLE_ANNOTATIONS, "RuntimeVisibleAnnotations", annotations.clone()));
+        layouts.push((RUNTIME_INVISIBLE_ANNOTATIONS, "RuntimeInvisibleAnnotations", annotations));
+    }
+
+    if context == METHOD {
+        let parameters = format!("{}{}{}{}", MDL0, MDL1, MDL2, MDL3);
+        layouts.push((
+            RUNTIME_VISIBLE_PARAMETER_ANNOTATIONS,
+            "RuntimeVisibleParameterAnnotations",
+            parameters.clone(),
+        ));
+        layouts.push((RUNTIME_INVISIBLE_PARAMETER_ANNOTATIONS, "RuntimeInvisibleParameterAnnotations", parameters));
+        layouts.push((ANNOTATION_DEFAULT, "AnnotationDefault", MDL3.to_string()));
+    }
+
+    layouts
+}
+
+fn predefined_bits(context: usize) -> &'static [usize] {
+    match context {
+        CLASS => &[OVERFLOW, SOURCE_FILE, ENCLOSING_METHOD, SIGNATURE, DEPRECATED, 21, 22, INNER_CLASSES, CLASS_FILE_VERSION, 27, 28],
+        FIELD => &[OVERFLOW, CONSTANT_VALUE, SIGNATURE, DEPRECATED, 21, 22, 27, 28],
+        METHOD => &[OVERFLOW, CODE_ATTRIBUTE, EXCEPTIONS, SIGNATURE, DEPRECATED, 21, 22, 23, 24, 25, 26, 27, 28],
+        _ => &[OVERFLOW, STACK_MAP_TABLE, LINE_NUMBER_TABLE, LOCAL_VARIABLE_TABLE, LOCAL_VARIABLE_TYPE_TABLE, 27, 28],
+    }
+}
+
+// Names of the attributes written without a layout, and of those added to
+// classes, so that the segment pool has a Utf8 entry for each.
+const ATTRIBUTE_NAMES: &[&str] = &[
+    "SourceFile", "EnclosingMethod", "Signature", "Deprecated", "InnerClasses", "BootstrapMethods",
+    "ConstantValue", "Code", "Exceptions", "MethodParameters", "StackMapTable", "LineNumberTable",
+    "LocalVariableTable", "LocalVariableTypeTable",
+];
+
+/// The attributes the objects of one context can have, by index, and the
+/// bands saying which each object has.
+struct Attributes {
+    flag_limit: usize,
+    long_flags: bool,
+    predefined: u64,
+    redefined: u64,
+    layouts: Vec<Option<Layout>>,
+    counts: Vec<usize>,
+    flags_hi: Band,
+    flags_lo: Band,
+    attr_count: Band,
+    attr_indexes: Band,
+    attr_calls: Band,
+}
+
+impl Attributes {
+    fn new(context: usize, options: u32) -> Result<Attributes, String> {
+        let hi = [HAVE_CLASS_FLAGS_HI, HAVE_FIELD_FLAGS_HI, HAVE_METHOD_FLAGS_HI, HAVE_CODE_FLAGS_HI][context];
+        let long_flags = options & hi != 0;
+        let flag_limit = if long_flags { 63 } else { 32 };
+        let mut attributes = Attributes {
+            flag_limit,
+            long_flags,
+            predefined: 0,
+            redefined: 0,
+            layouts: (0..flag_limit).map(|_| None).collect(),
+            counts: vec![0; flag_limit],
+            flags_hi: Band::default(),
+            flags_lo: Band::default(),
+            attr_count: Band::default(),
+            attr_indexes: Band::default(),
+            attr_calls: Band::default(),
+        };
+
+        for &bit in predefined_bits(context) {
+            attributes.predefined |= 1 << bit;
+        }
+
+        for (bit, name, layout) in metadata_layouts(context) {
+            attributes.layouts[bit] = Some(Layout::parse(name.as_bytes(), layout.as_bytes())?);
+        }
+
+        Ok(attributes)
+    }
+
+    fn index_mask(&self) -> u64 {
+        let mask = self.predefined | self.redefined;
+        if self.flag_limit < 64 { mask & ((1 << self.flag_limit) - 1) } else { mask }
+    }
+
+    fn is_predefined(&self, index: usize) -> bool {
+        index < self.flag_limit && self.predefined & !self.redefined & (1 << index) != 0
+    }
+
+    fn is_index(&self, index: usize) -> bool {
+        if index < self.flag_limit {
+            self.index_mask() & (1 << index) != 0
+        } else {
+            index < self.layouts.len()
+        }
+    }
+
+    // Number of attributes of a predefined index, or 0 if it was redefined.
+    fn predefined_count(&self, index: usize) -> usize {
+        if self.is_predefined(index) { self.counts[index] } else { 0 }
+    }
+
+    fn define(&mut self, index: Option<usize>, layout: Layout) -> Result<(), String> {
+        match index {
+            Some(index) if index < self.flag_limit => {
+                if self.redefined & (1 << index) != 0 {
+                    return Err(format!("attribute index {} defined twice", index));
+                }
+
+                self.redefined |= 1 << index;
+                self.layouts[index] = Some(layout);
+            }
+            Some(index) => return Err(format!("bad attribute index {}", index)),
+            None => {
+                self.layouts.push(Some(layout));
+                self.counts.push(0);
+            }
+        }
+
+        Ok(())
+    }
+
+    // The flags of the next object.
+    fn next_flags(&mut self) -> Result<u64, String> {
+        let hi = if self.long_flags { self.flags_hi.get()? as u32 as u64 } else { 0 };
+        Ok(hi << 32 | self.flags_lo.get()? as u32 as u64)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct InnerClass {
+    inner: usize,
+    outer: Option<usize>,
+    name: Option<usize>,
+    flags: u32,
+}
+
+impl PartialEq for InnerClass {
+    fn eq(&self, other: &InnerClass) -> bool {
+        self.inner == other.inner && self.outer == other.outer && self.name == other.name && self.flags == other.flags
+    }
+}
+
+// A code attribute whose stack and locals sizes and handler count are in
+// its header byte, or in bands when negative.
+struct CodeHeader {
+    max_stack: i32,
+    max_na_locals: i32,
+    handler_count: i32,
+    has_flags: bool,
+}
+
+// Bytecodes and the pseudo-opcodes Pack200 adds.
+const ACC_STATIC: u32 = 0x0008;
+
+const ALOAD_0: u8 = 42;
+const IINC: u8 = 132;
+const TABLESWITCH: u8 = 170;
+const LOOKUPSWITCH: u8 = 171;
+const GETSTATIC: u8 = 178;
+const INVOKEVIRTUAL: u8 = 182;
+const INVOKESPECIAL: u8 = 183;
+const INVOKESTATIC: u8 = 184;
+const INVOKEINTERFACE: u8 = 185;
+const INVOKEDYNAMIC: u8 = 186;
+const NEW: u8 = 187;
+const MULTIANEWARRAY: u8 = 197;
+const WIDE: u8 = 196;
+const SELF_LINKER_FIRST: u8 = 202;
+const SELF_LINKER_LAST: u8 = 229;
+const INVOKEINIT_FIRST: u8 = 230;
+const INVOKEINIT_LAST: u8 = 232;
+const REF_ESCAPE: u8 = 253;
+const BYTE_ESCAPE: u8 = 254;
+const END_MARKER: u8 = 255;
+
+// The bands references of a bytecode are in, and the opcode it stands for.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum RefBand {
+    Int,
+    Float,
+    Long,
+    Double,
+    String,
+    Loadable,
+    Class,
+    Field,
+    Method,
+    InterfaceMethod,
+    Indy,
+}
+
+// Codings of the reference bands, in RefBand order, which is also the order
+// the bands are in.
+const REF_CODINGS: [Coding; 11] =
+    [DELTA5, DELTA5, DELTA5, DELTA5, DELTA5, DELTA5, UNSIGNED5, DELTA5, UNSIGNED5, DELTA5, DELTA5];
+
+fn ref_band(bc: u8) -> Option<(RefBand, u8)> {
+    Some(match bc {
+        18 => (RefBand::String, 18),
+        19 => (RefBand::String, 19),
+        20 => (RefBand::Long, 20),
+        233 => (RefBand::Class, 18),
+        234 => (RefBand::Int, 18),
+        235 => (RefBand::Float, 18),
+        236 => (RefBand::Class, 19),
+        237 => (RefBand::Int, 19),
+        238 => (RefBand::Float, 19),
+        239 => (RefBand::Double, 20),
+        240 => (RefBand::Loadable, 18),
+        241 => (RefBand::Loadable, 19),
+        178..=181 => (RefBand::Field, bc),
+        182..=184 => (RefBand::Method, bc),
+        185 => (RefBand::InterfaceMethod, bc),
+        242 => (RefBand::InterfaceMethod, INVOKESPECIAL),
+        243 => (RefBand::InterfaceMethod, INVOKESTATIC),
+        186 => (RefBand::Indy, bc),
+        187 | 189 | 192 | 193 | 197 => (RefBand::Class, bc),
+        _ => return None,
+    })
+}
+
+fn is_branch(bc: u8) -> bool {
+    matches!(bc, 153..=168 | 198..=201)
+}
+
+fn is_local(bc: u8) -> bool {
+    matches!(bc, 21..=25 | 54..=58 | 169)
+}
+
+// Argument slots of a method descriptor.
+fn argument_size(descriptor: &[u8]) -> usize {
+    let mut size = 0;
+    let mut i = 1;
+    while i < descriptor.len() && descriptor[i] != b')' {
+        let start = i;
+
+        while descriptor[i] == b'[' {
+            i += 1;
+        }
+
+        if descriptor[i] == b'L' {
+            while i < descriptor.len() && descriptor[i] != b';' {
+                i += 1;
+            }
+        }
+
+        size += if i == start && (descriptor[i] == b'J' || descriptor[i] == b'D') { 2 } else { 1 };
+        i += 1;
+    }
+
+    size
+}
+
+fn rfind(bytes: &[u8], c: u8, end: usize) -> Option<usize> {
+    bytes[..end.min(bytes.len())].iter().rposition(|&b| b == c)
+}
+
+fn is_digits(bytes: &[u8]) -> bool {
+    !bytes.is_empty() && bytes.iter().all(|c| c.is_ascii_digit())
+}
+
+pub struct Classes {
+    options: u32,
+    pub pool: Pool,
+    attributes: Vec<Attributes>,
+    attribute_names: HashMap<&'static str, usize>,
+    inner_classes: Vec<InnerClass>,
+    inner_class_index: HashMap<usize, usize>,
+    children: HashMap<usize, Vec<usize>>,
+    class_count: usize,
+    default_version: (u16, u16),
+
+    class_this: Band,
+    class_super: Band,
+    class_interface_count: Band,
+    class_interface: Band,
+    class_field_count: Band,
+    class_method_count: Band,
+    field_descr: Band,
+    method_descr: Band,
+
+    source_file: Band,
+    enclosing_method_class: Band,
+    enclosing_method_descr: Band,
+    class_signature: Band,
+    inner_classes_count: Band,
+    inner_classes_class: Band,
+    inner_classes_flags: Band,
+    inner_classes_outer: Band,
+    inner_classes_name: Band,
+    version_minor: Band,
+    version_major: Band,
+    constant_value: Band,
+    field_signature: Band,
+    exceptions_count: Band,
+    exceptions_class: Band,
+    method_signature: Band,
+    method_parameters_count: Band,
+    method_parameters_name: Band,
+    method_parameters_flags: Band,
+
+    code_headers: Vec<CodeHeader>,
+    code_header_position: usize,
+    code_max_stack: Band,
+    code_max_na_locals: Band,
+    code_handler_count: Band,
+    code_handler_start: Band,
+    code_handler_end: Band,
+    code_handler_catch: Band,
+    code_handler_class: Band,
+    stack_map_count: Band,
+    stack_map_frame: Band,
+    stack_map_local_count: Band,
+    stack_map_stack_count: Band,
+    stack_map_offset: Band,
+    stack_map_type: Band,
+    stack_map_class: Band,
+    stack_map_bci: Band,
+    line_number_count: Band,
+    line_number_bci: Band,
+    line_number_line: Band,
+    local_variable_tables: [LocalVariableBands; 2],
+
+    bc_codes: Vec<u8>,
+    bc_position: usize,
+    bc_case_count: Band,
+    bc_case_value: Band,
+    bc_byte: Band,
+    bc_short: Band,
+    bc_local: Band,
+    bc_label: Band,
+    bc_refs: Vec<Band>,
+    bc_thisfield: Band,
+    bc_superfield: Band,
+    bc_thismethod: Band,
+    bc_supermethod: Band,
+    bc_initref: Band,
+    bc_escref: Band,
+    bc_escrefsize: Band,
+    bc_escsize: Band,
+    bc_escbyte: Band,
+
+    // State of the class being written.
+    this_class: usize,
+    super_class: Option<usize>,
+    descriptor: usize,
+    member_flags: u32,
+    bci_map: Vec<u32>,
+    version: (u16, u16),
+    local_inner_classes: Option<Vec<InnerClass>>,
+}
+
+#[derive(Default)]
+struct LocalVariableBands {
+    count: Band,
+    bci: Band,
+    span: Band,
+    name: Band,
+    descriptor: Band,
+    slot: Band,
+}
+
+impl Classes {
+    pub fn new(options: u32, mut pool: Pool, class_count: usize, default_version: (u16, u16)) -> Result<Classes, String> {
+        let attributes = (0..4).map(|context| Attributes::new(context, options)).collect::<Result<Vec<_>, _>>()?;
+        let mut attribute_names = HashMap::new();
+
+        for &name in ATTRIBUTE_NAMES {
+            attribute_names.insert(name, pool.ensure_utf8(name.as_bytes()));
+        }
+
+        for layout in attributes.iter().flat_map(|a| a.layouts.iter()).filter_map(|l| l.as_ref()) {
+            pool.ensure_utf8(&layout.name);
+        }
+
+        Ok(Classes {
+            options,
+            pool,
+            attributes,
+            attribute_names,
+            inner_classes: Vec::new(),
+            inner_class_index: HashMap::new(),
+            children: HashMap::new(),
+            class_count,
+            default_version,
+            class_this: Band::default(),
+            class_super: Band::default(),
+            class_interface_count: Band::default(),
+            class_interface: Band::default(),
+            class_field_count: Band::default(),
+            class_method_count: Band::default(),
+            field_descr: Band::default(),
+            method_descr: Band::default(),
+            source_file: Band::default(),
+            enclosing_method_class: Band::default(),
+            enclosing_method_descr: Band::default(),
+            class_signature: Band::default(),
+            inner_classes_count: Band::default(),
+            inner_classes_class: Band::default(),
+            inner_classes_flags: Band::default(),
+            inner_classes_outer: Band::default(),
+            inner_classes_name: Band::default(),
+            version_minor: Band::default(),
+            version_major: Band::default(),
+            constant_value: Band::default(),
+            field_signature: Band::default(),
+            exceptions_count: Band::default(),
+            exceptions_class: Band::default(),
+            method_signature: Band::default(),
+            method_parameters_count: Band::default(),
+            method_parameters_name: Band::default(),
+            method_parameters_flags: Band::default(),
+            code_headers: Vec::new(),
+            code_header_position: 0,
+            code_max_stack: Band::default(),
+            code_max_na_locals: Band::default(),
+            code_handler_count: Band::default(),
+            code_handler_start: Band::default(),
+            code_handler_end: Band::default(),
+            code_handler_catch: Band::default(),
+            code_handler_class: Band::default(),
+            stack_map_count: Band::default(),
+            stack_map_frame: Band::default(),
+            stack_map_local_count: Band::default(),
+            stack_map_stack_count: Band::default(),
+            stack_map_offset: Band::default(),
+            stack_map_type: Band::default(),
+            stack_map_class: Band::default(),
+            stack_map_bci: Band::default(),
+            line_number_count: Band::default(),
+            line_number_bci: Band::default(),
+            line_number_line: Band::default(),
+            local_variable_tables: Default::default(),
+            bc_codes: Vec::new(),
+            bc_position: 0,
+            bc_case_count: Band::default(),
+            bc_case_value: Band::default(),
+            bc_byte: Band::default(),
+            bc_short: Band::default(),
+            bc_local: Band::default(),
+            bc_label: Band::default(),
+            bc_refs: Vec::new(),
+            bc_thisfield: Band::default(),
+            bc_superfield: Band::default(),
+            bc_thismethod: Band::default(),
+            bc_supermethod: Band::default(),
+            bc_initref: Band::default(),
+            bc_escref: Band::default(),
+            bc_escrefsize: Band::default(),
+            bc_escsize: Band::default(),
+            bc_escbyte: Band::default(),
+            this_class: 0,
+            super_class: None,
+            descriptor: 0,
+            member_flags: 0,
+            bci_map: Vec::new(),
+            version: default_version,
+            local_inner_classes: None,
+        })
+    }
+
+    pub fn read_attribute_definitions(&mut self, r: &mut Reader, count: usize) -> Result<(), String> {
+        let mut headers = r.band(BYTE1, count)?;
+        let mut names = r.band(UNSIGNED5, count)?;
+        let mut layouts = r.band(UNSIGNED5, count)?;
+
+        for _ in 0..count {
+            let header = headers.get()?;
+            let name = self.pool.reference(&mut names, Index::Tag(pool::UTF8))?;
+            let layout = self.pool.reference(&mut layouts, Index::Tag(pool::UTF8))?;
+            let layout = Layout::parse(self.pool.utf8(name)?, self.pool.utf8(layout)?)?;
+            let index = (header >> 2) - 1;
+            let index = if index < 0 { None } else { Some(index as usize) };
+            self.attributes[(header & 3) as usize].define(index, layout)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn read_inner_classes(&mut self, r: &mut Reader, count: usize) -> Result<(), String> {
+        let mut classes = r.band(UDELTA5, count)?;
+        let mut flags = r.band(UNSIGNED5, count)?;
+        let long_forms = flags.values().iter().filter(|&&f| f & (1 << 16) != 0).count();
+        let mut outers = r.band(DELTA5, long_forms)?;
+        let mut names = r.band(DELTA5, long_forms)?;
+
+        for i in 0..count {
+            let inner = self.pool.reference(&mut classes, Index::Tag(pool::CLASS))?;
+            let f = flags.get()? as u32;
+            let (outer, name) = if f & (1 << 16) != 0 {
+                let outer = self.pool.nullable_reference(&mut outers, Index::Tag(pool::CLASS))?;
+                let name = self.pool.nullable_reference(&mut names, Index::Tag(pool::UTF8))?;
+                (outer, name)
+            } else {
+                self.implied_outer_and_name(inner)?
+            };
+
+            if let Some(outer) = outer {
+                self.children.entry(outer).or_default().push(i);
+            }
+
+            self.inner_class_index.entry(inner).or_insert(i);
+            self.inner_classes.push(InnerClass { inner, outer, name, flags: f & !(1 << 16) });
+        }
+
+        Ok(())
+    }
+
+    // Inner classes in short form have their outer class and simple name
+    // implied by their name, as in pkg/Outer$Inner or pkg/Outer$1.
+    fn implied_outer_and_name(&mut self, inner: usize) -> Result<(Option<usize>, Option<usize>), String> {
+        let n = self.pool.class_name(inner)?.to_vec();
+        let package_length = rfind(&n, b'/', n.len()).map_or(0, |i| i + 1);
+        let bad = || format!("bad inner class name {}", String::from_utf8_lossy(&n));
+        let dollar2 = rfind(&n, b'$', n.len()).ok_or_else(bad)?;
+        let (dollar1, numbered, name) = if is_digits(&n[dollar2 + 1..]) {
+            (Some(dollar2), true, None)
+        } else {
+            let dollar1 = if dollar2 > 0 { rfind(&n, b'$', dollar2 - 1) } else { None };
+            match dollar1 {
+                Some(d1) if package_length < d1 && is_digits(&n[d1 + 1..dollar2]) => {
+                    (Some(d1), true, Some(&n[dollar2 + 1..]))
+                }
+                _ => (Some(dollar2), false, Some(&n[dollar2 + 1..])),
+            }
+        };
+
+        let outer = if numbered {
+            None
+        } else {
+            Some(self.pool.ensure_class(&n[..dollar1.ok_or_else(bad)?]))
+        };
+
+        let name = name.map(|name| self.pool.ensure_utf8(name));
+        Ok((outer, name))
+    }
+
+    pub fn read_classes(&mut self, r: &mut Reader) -> Result<(), String> {
+        let n = self.class_count;
+        self.class_this = r.band(DELTA5, n)?;
+        self.class_super = r.band(DELTA5, n)?;
+        self.class_interface_count = r.band(DELTA5, n)?;
+        self.class_interface = r.band(DELTA5, self.class_interface_count.total()?)?;
+        self.class_field_count = r.band(DELTA5, n)?;
+        self.class_method_count = r.band(DELTA5, n)?;
+        let fields = self.class_field_count.total()?;
+        let methods = self.class_method_count.total()?;
+        self.field_descr = r.band(DELTA5, fields)?;
+        self.read_attributes(r, FIELD, fields)?;
+        self.method_descr = r.band(MDELTA5, methods)?;
+        self.read_attributes(r, METHOD, methods)?;
+        self.read_attributes(r, CLASS, n)?;
+        self.read_code_headers(r)
+    }
+
+    fn read_attributes(&mut self, r: &mut Reader, context: usize, n: usize) -> Result<(), String> {
+        {
+            let attributes = &mut self.attributes[context];
+            if attributes.long_flags {
+                attributes.flags_hi = r.band(UNSIGNED5, n)?;
+            }
+
+            attributes.flags_lo = r.band(UNSIGNED5, n)?;
+            let mask = attributes.index_mask();
+
+            for _ in 0..n {
+                let flags = attributes.next_flags()?;
+                if flags & !mask > 0xffff {
+                    return Err("undefined attribute flag".to_string());
+                }
+
+                for bit in 0..attributes.flag_limit {
+                    if flags & mask & (1 << bit) != 0 {
+                        attributes.counts[bit] += 1;
+                    }
+                }
+            }
+
+            attributes.flags_hi.rewind();
+            attributes.flags_lo.rewind();
+            let overflow = attributes.predefined_count(OVERFLOW);
+            attributes.attr_count = r.band(UNSIGNED5, overflow)?;
+            attributes.attr_indexes = r.band(UNSIGNED5, attributes.attr_count.total()?)?;
+
+            for &index in attributes.attr_indexes.values() {
+                if index < 0 || !attributes.is_index(index as usize) {
+                    return Err(format!("bad attribute index {}", index));
+                }
+
+                attributes.counts[index as usize] += 1;
+            }
+
+            let calls = attributes
+                .layouts
+                .iter()
+                .zip(attributes.counts.iter())
+                .filter(|&(_, &count)| count > 0)
+                .filter_map(|(layout, _)| layout.as_ref().map(|l| l.backward_calls()))
+                .sum();
+            attributes.attr_calls = r.band(UNSIGNED5, calls)?;
+        }
+
+        match context {
+            CLASS => {
+                let count = |c: &Classes, i| c.attributes[CLASS].predefined_count(i);
+                self.source_file = r.band(UNSIGNED5, count(self, SOURCE_FILE))?;
+                self.enclosing_method_class = r.band(UNSIGNED5, count(self, ENCLOSING_METHOD))?;
+                self.enclosing_method_descr = r.band(UNSIGNED5, count(self, ENCLOSING_METHOD))?;
+                self.class_signature = r.band(UNSIGNED5, count(self, SIGNATURE))?;
+                self.read_metadata(r, CLASS, &[RUNTIME_VISIBLE_ANNOTATIONS, RUNTIME_INVISIBLE_ANNOTATIONS])?;
+                self.inner_classes_count = r.band(UNSIGNED5, count(self, INNER_CLASSES))?;
+                let total = self.inner_classes_count.total()?;
+                self.inner_classes_class = r.band(UNSIGNED5, total)?;
+                self.inner_classes_flags = r.band(UNSIGNED5, total)?;
+                let long_forms = total - self.inner_classes_flags.count(0);
+                self.inner_classes_outer = r.band(UNSIGNED5, long_forms)?;
+                self.inner_classes_name = r.band(UNSIGNED5, long_forms)?;
+                self.version_minor = r.band(UNSIGNED5, count(self, CLASS_FILE_VERSION))?;
+                self.version_major = r.band(UNSIGNED5, count(self, CLASS_FILE_VERSION))?;
+                self.read_metadata(r, CLASS, &[RUNTIME_VISIBLE_TYPE_ANNOTATIONS, RUNTIME_INVISIBLE_TYPE_ANNOTATIONS])?;
+            }
+            FIELD => {
+                let count = |c: &Classes, i| c.attributes[FIELD].predefined_count(i);
+                self.constant_value = r.band(UNSIGNED5, count(self, CONSTANT_VALUE))?;
+                self.field_signature = r.band(UNSIGNED5, count(self, SIGNATURE))?;
+                self.read_metadata(
+                    r,
+                    FIELD,
+                    &[
+                        RUNTIME_VISIBLE_ANNOTATIONS,
+                        RUNTIME_INVISIBLE_ANNOTATIONS,
+                        RUNTIME_VISIBLE_TYPE_ANNOTATIONS,
+                        RUNTIME_INVISIBLE_TYPE_ANNOTATIONS,
+                    ],
+                )?;
+            }
+            METHOD => {
+                let count = |c: &Classes, i| c.attributes[METHOD].predefined_count(i);
+                self.exceptions_count = r.band(UNSIGNED5, count(self, EXCEPTIONS))?;
+                self.exceptions_class = r.band(UNSIGNED5, self.exceptions_count.total()?)?;
+                self.method_signature = r.band(UNSIGNED5, count(self, SIGNATURE))?;
+                self.read_metadata(
+                    r,
+                    METHOD,
+                    &[
+                        RUNTIME_VISIBLE_ANNOTATIONS,
+                        RUNTIME_INVISIBLE_ANNOTATIONS,
+                        RUNTIME_VISIBLE_PARAMETER_ANNOTATIONS,
+                        RUNTIME_INVISIBLE_PARAMETER_ANNOTATIONS,
+                        ANNOTATION_DEFAULT,
+                    ],
+                )?;
+                self.method_parameters_count = r.band(BYTE1, count(self, METHOD_PARAMETERS))?;
+                let total = self.method_parameters_count.total()?;
+                self.method_parameters_name = r.band(UNSIGNED5, total)?;
+                self.method_parameters_flags = r.band(UNSIGNED5, total)?;
+                self.read_metadata(r, METHOD, &[RUNTIME_VISIBLE_TYPE_ANNOTATIONS, RUNTIME_INVISIBLE_TYPE_ANNOTATIONS])?;
+            }
+            _ => {
+                self.read_stack_map_tables(r)?;
+                let count = |c: &Classes, i| c.attributes[CODE].predefined_count(i);
+                self.line_number_count = r.band(UNSIGNED5, count(self, LINE_NUMBER_TABLE))?;
+                let total = self.line_number_count.total()?;
+                self.line_number_bci = r.band(BCI5, total)?;
+                self.line_number_line = r.band(UNSIGNED5, total)?;
+
+                for (i, &index) in [LOCAL_VARIABLE_TABLE, LOCAL_VARIABLE_TYPE_TABLE].iter().enumerate() {
+                    let n = count(self, index);
+                    let bands = &mut self.local_variable_tables[i];
+                    bands.count = r.band(UNSIGNED5, n)?;
+                    let total = bands.count.total()?;
+                    bands.bci = r.band(BCI5, total)?;
+                    bands.span = r.band(BRANCH5, total)?;
+                    bands.name = r.band(UNSIGNED5, total)?;
+                    bands.descriptor = r.band(UNSIGNED5, total)?;
+                    bands.slot = r.band(UNSIGNED5, total)?;
+                }
+
+                self.read_metadata(r, CODE, &[RUNTIME_VISIBLE_TYPE_ANNOTATIONS, RUNTIME_INVISIBLE_TYPE_ANNOTATIONS])?;
+            }
+        }
+
+        // Attributes the archive defined itself.
+        let attributes = &mut self.attributes[context];
+        for index in 0..attributes.layouts.len() {
+            let count = attributes.counts[index];
+            if count == 0 || attributes.is_predefined(index) {
+                continue;
+            }
+
+            if let Some(ref mut layout) = attributes.layouts[index] {
+                layout.read(r, count, &mut attributes.attr_calls)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_metadata(&mut self, r: &mut Reader, context: usize, indexes: &[usize]) -> Result<(), String> {
+        let attributes = &mut self.attributes[context];
+
+        for &index in indexes {
+            let count = attributes.predefined_count(index);
+            if count == 0 {
+                continue;
+            }
+
+            if let Some(ref mut layout) = attributes.layouts[index] {
+                layout.read(r, count, &mut attributes.attr_calls)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_stack_map_tables(&mut self, r: &mut Reader) -> Result<(), String> {
+        let n = self.attributes[CODE].predefined_count(STACK_MAP_TABLE);
+        self.stack_map_count = r.band(UNSIGNED5, n)?;
+        self.stack_map_frame = r.band(BYTE1, self.stack_map_count.total()?)?;
+        let (mut offsets, mut types, mut fat) = (0, 0, 0);
+
+        for &tag in self.stack_map_frame.values() {
+            match tag {
+                64..=127 => types += 1,
+                247 => {
+                    offsets += 1;
+                    types += 1;
+                }
+                248..=251 => offsets += 1,
+                252..=254 => {
+                    offsets += 1;
+                    types += (tag - 251) as usize;
+                }
+                255 => {
+                    offsets += 1;
+                    fat += 1;
+                }
+                _ => {}
+            }
+        }
+
+        self.stack_map_local_count = r.band(UNSIGNED5, fat)?;
+        self.stack_map_stack_count = r.band(UNSIGNED5, fat)?;
+        types += self.stack_map_local_count.total()? + self.stack_map_stack_count.total()?;
+        self.stack_map_offset = r.band(UNSIGNED5, offsets)?;
+        self.stack_map_type = r.band(BYTE1, types)?;
+        self.stack_map_class = r.band(UNSIGNED5, self.stack_map_type.count(7))?;
+        self.stack_map_bci = r.band(BCI5, self.stack_map_type.count(8))?;
+
+        Ok(())
+    }
+
+    fn read_code_headers(&mut self, r: &mut Reader) -> Result<(), String> {
+        let n = self.attributes[METHOD].predefined_count(CODE_ATTRIBUTE);
+        let all_flags = self.options & HAVE_ALL_CODE_FLAGS != 0;
+        let headers = r.band(BYTE1, n)?;
+        for &sc in headers.values() {
+            let (sc, handler_count, modulus) = match sc {
+                0 => {
+                    self.code_headers.push(CodeHeader {
+                        max_stack: -1,
+                        max_na_locals: -1,
+                        handler_count: -1,
+                        has_flags: true,
+                    });
+                    continue;
+                }
+                1..=144 => (sc - 1, 0, 12),
+                145..=208 => (sc - 145, 1, 8),
+                _ => (sc - 209, 2, 7),
+            };
+
+            self.code_headers.push(CodeHeader {
+                max_stack: sc % modulus,
+                max_na_locals: sc / modulus,
+                handler_count,
+                has_flags: all_flags,
+            });
+        }
+
+        // Headers of 0 take all three from bands.
+        let unknown = self.code_headers.iter().filter(|h| h.max_stack < 0).count();
+        self.code_max_stack = r.band(UNSIGNED5, unknown)?;
+        self.code_max_na_locals = r.band(UNSIGNED5, unknown)?;
+        self.code_handler_count = r.band(UNSIGNED5, unknown)?;
+        let handlers = self.code_handler_count.total()?
+            + self.code_headers.iter().filter(|h| h.handler_count > 0).map(|h| h.handler_count as usize).sum::<usize>();
+        self.code_handler_start = r.band(BCI5, handlers)?;
+        self.code_handler_end = r.band(BRANCH5, handlers)?;
+        self.code_handler_catch = r.band(BRANCH5, handlers)?;
+        self.code_handler_class = r.band(UNSIGNED5, handlers)?;
+        let flagged = self.code_headers.iter().filter(|h| h.has_flags).count();
+        self.read_attributes(r, CODE, flagged)
+    }
+
+    /// Reads the bytecodes of all code attributes and their operand bands.
+    pub fn read_bytecodes(&mut self, r: &mut Reader) -> Result<(), String> {
+        let mut counts = HashMap::new();
+        let mut ref_counts = [0; 11];
+        let mut switches = Vec::new();
+        let start = r.input.position;
+
+        for _ in 0..self.code_headers.len() {
+            loop {
+                let mut bc = r.input.byte()?;
+                let wide = bc == WIDE;
+                if wide {
+                    bc = r.input.byte()?;
+                }
+
+                let band: &str = match bc {
+                    END_MARKER => break,
+                    TABLESWITCH | LOOKUPSWITCH => {
+                        switches.push(bc);
+                        continue;
+                    }
+                    IINC => {
+                        *counts.entry("local").or_insert(0) += 1;
+                        if wide { "short" } else { "byte" }
+                    }
+                    17 => "short",
+                    16 | 188 => "byte",
+                    MULTIANEWARRAY => {
+                        ref_counts[RefBand::Class as usize] += 1;
+                        "byte"
+                    }
+                    REF_ESCAPE => {
+                        *counts.entry("escrefsize").or_insert(0) += 1;
+                        "escref"
+                    }
+                    BYTE_ESCAPE => "escsize",
+                    INVOKEINIT_FIRST..=INVOKEINIT_LAST => "initref",
+                    SELF_LINKER_FIRST..=SELF_LINKER_LAST => {
+                        let index = (bc - SELF_LINKER_FIRST) as usize;
+                        let is_super = index >= 14;
+                        let op = GETSTATIC + (index % 7) as u8;
+                        match (op < INVOKEVIRTUAL, is_super) {
+                            (true, false) => "thisfield",
+                            (true, true) => "superfield",
+                            (false, false) => "thismethod",
+                            (false, true) => "supermethod",
+                        }
+                    }
+                    _ if is_branch(bc) => "label",
+                    _ if is_local(bc) => "local",
+                    _ => match ref_band(bc) {
+                        Some((band, _)) => {
+                            ref_counts[band as usize] += 1;
+                            continue;
+                        }
+                        None if bc < SELF_LINKER_FIRST => continue,
+                        None => return Err(format!("bad bytecode {}", bc)),
+                    },
+                };
+
+                *counts.entry(band).or_insert(0) += 1;
+            }
+        }
+
+        let end = r.input.position;
+        r.input.position = start;
+        self.bc_codes = r.input.take(end - start)?.to_vec();
+        self.bc_case_count = r.band(UNSIGNED5, switches.len())?;
+        let mut case_values = 0;
+        let mut labels = 0;
+
+        for (&bc, &n) in switches.iter().zip(self.bc_case_count.values()) {
+            if n < 0 {
+                return Err("bad switch case count".to_string());
+            }
+
+            labels += 1 + n as usize;
+            case_values += if bc == TABLESWITCH { 1 } else { n as usize };
+        }
+
+        let count = |name: &str| *counts.get(name).unwrap_or(&0);
+        self.bc_case_value = r.band(DELTA5, case_values)?;
+        self.bc_byte = r.band(BYTE1, count("byte"))?;
+        self.bc_short = r.band(DELTA5, count("short"))?;
+        self.bc_local = r.band(UNSIGNED5, count("local"))?;
+        self.bc_label = r.band(BRANCH5, count("label") + labels)?;
+
+        for (i, &coding) in REF_CODINGS.iter().enumerate() {
+            let values = r.band(coding, ref_counts[i])?;
+            self.bc_refs.push(values);
+        }
+
+        self.bc_thisfield = r.band(UNSIGNED5, count("thisfield"))?;
+        self.bc_superfield = r.band(UNSIGNED5, count("superfield"))?;
+        self.bc_thismethod = r.band(UNSIGNED5, count("thismethod"))?;
+        self.bc_supermethod = r.band(UNSIGNED5, count("supermethod"))?;
+        self.bc_initref = r.band(UNSIGNED5, count("initref"))?;
+        self.bc_escref = r.band(UNSIGNED5, count("escref"))?;
+        self.bc_escrefsize = r.band(UNSIGNED5, count("escrefsize"))?;
+        self.bc_escsize = r.band(UNSIGNED5, count("escsize"))?;
+        self.bc_escbyte = r.band(BYTE1, self.bc_escsize.total()?)?;
+
+        Ok(())
+    }
+
+    /// Writes the next class file, returning the name of the class and its
+    /// bytes. Classes are written in order, as they take their values from
+    /// the bands in turn.
+    pub fn write_class(&mut self) -> Result<(Vec<u8>, Vec<u8>), String> {
+        let mut out = Output::new();
+        let flags = self.attributes[CLASS].next_flags()?;
+        self.this_class = self.pool.reference(&mut self.class_this, Index::Tag(pool::CLASS))?;
+        let super_class = self.pool.reference(&mut self.class_super, Index::Tag(pool::CLASS))?;
+        self.super_class = if super_class == self.this_class { None } else { Some(super_class) };
+        self.version = self.default_version;
+        self.local_inner_classes = None;
+        let mask = self.attributes[CLASS].index_mask();
+        out.u2((flags & !mask & 0xffff) as u32);
+        out.reference(&self.pool, Some(self.this_class));
+        out.reference(&self.pool, self.super_class);
+        let interfaces = self.class_interface_count.length()?;
+        out.u2(interfaces as u32);
+
+        for _ in 0..interfaces {
+            let interface = self.pool.reference(&mut self.class_interface, Index::Tag(pool::CLASS))?;
+            out.reference(&self.pool, Some(interface));
+        }
+
+        self.write_members(&mut out, FIELD)?;
+        self.write_members(&mut out, METHOD)?;
+        let count_offset = out.len();
+        let mut count = self.write_attributes(&mut out, CLASS, flags & mask)?;
+        let bootstrap_methods = self.attribute_names["BootstrapMethods"];
+        if out.write_bootstrap_methods(&self.pool, bootstrap_methods) {
+            count += 1;
+        }
+
+        if self.write_inner_classes(&mut out)? {
+            count += 1;
+        }
+
+        out.patch_u2(count_offset, count as u32);
+        let (minor, major) = self.version;
+        let name = self.pool.class_name(self.this_class)?.to_vec();
+        Ok((name, out.finish(&self.pool, minor, major)?))
+    }
+
+    fn write_members(&mut self, out: &mut Output, context: usize) -> Result<(), String> {
+        let n = if context == FIELD { self.class_field_count.length()? } else { self.class_method_count.length()? };
+        out.u2(n as u32);
+
+        for _ in 0..n {
+            let flags = self.attributes[context].next_flags()?;
+
+            let descriptor = if context == FIELD {
+                self.pool.reference(&mut self.field_descr, Index::Tag(pool::NAME_AND_TYPE))?
+            } else {
+                self.pool.reference(&mut self.method_descr, Index::Tag(pool::NAME_AND_TYPE))?
+            };
+
+            let (name, signature) = match self.pool.entries[descriptor] {
+                Entry::NameAndType(name, signature) => (name, signature),
+                _ => unreachable!(),
+            };
+
+            self.descriptor = descriptor;
+            self.member_flags = flags as u32;
+            let mask = self.attributes[context].index_mask();
+            out.u2((flags & !mask & 0xffff) as u32);
+            out.reference(&self.pool, Some(name));
+            out.reference(&self.pool, Some(signature));
+            self.write_attributes(out, context, flags & mask)?;
+        }
+
+        Ok(())
+    }
+
+    // Writes the attribute count and attributes of an object, given its
+    // attribute flag bits. Returns the attribute count.
+    fn write_attributes(&mut self, out: &mut Output, context: usize, bits: u64) -> Result<usize, String> {
+        let mut indexes = (0..64).filter(|&bit| bits & (1 << bit) != 0).collect::<Vec<_>>();
+        if indexes.contains(&OVERFLOW) && self.attributes[context].is_predefined(OVERFLOW) {
+            indexes.retain(|&i| i != OVERFLOW);
+            let n = self.attributes[context].attr_count.length()?;
+            for _ in 0..n {
+                let index = self.attributes[context].attr_indexes.length()?;
+                indexes.push(index);
+            }
+        }
+
+        let count_offset = out.len();
+        out.u2(0);
+        let mut count = 0;
+
+        for index in indexes {
+            if self.write_attribute(out, context, index)? {
+                count += 1;
+            }
+        }
+
+        out.patch_u2(count_offset, count);
+
+        Ok(count as usize)
+    }
+
+    fn named(&self, out: &mut Output, name: &str) -> usize {
+        out.reference(&self.pool, Some(self.attribute_names[name]));
+        let offset = out.len();
+        out.u4(0);
+
+        offset
+    }
+
+    // Writes one attribute, unless it only says what to leave out of the
+    // class's implied InnerClasses attribute.
+    fn write_attribute(&mut self, out: &mut Output, context: usize, index: usize) -> Result<bool, String> {
+        let length_offset;
+
+        if self.attributes[context].is_predefined(index) && self.attributes[context].layouts[index].is_none() {
+            match (context, index) {
+                (_, DEPRECATED) => {
+                    length_offset = self.named(out, "Deprecated");
+                }
+                (CLASS, SOURCE_FILE) => {
+                    length_offset = self.named(out, "SourceFile");
+
+                    let name = match self.pool.nullable_reference(&mut self.source_file, Index::Tag(pool::UTF8))? {
+                        Some(name) => name,
+                        None => {
+                            let class = self.pool.class_name(self.this_class)?.to_vec();
+                            let simple = &class[rfind(&class, b'/', class.len()).map_or(0, |i| i + 1)..];
+                            let simple = &simple[..simple.iter().position(|&c| c == b'$').unwrap_or(simple.len())];
+                            let name = [simple, b".java"].concat();
+                            self.pool.ensure_utf8(&name)
+                        }
+                    };
+
+                    out.reference(&self.pool, Some(name));
+                }
+                (CLASS, ENCLOSING_METHOD) => {
+                    length_offset = self.named(out, "EnclosingMethod");
+                    let class = self.pool.reference(&mut self.enclosing_method_class, Index::Tag(pool::CLASS))?;
+                    let method = self.pool.nullable_reference(&mut self.enclosing_method_descr, Index::Tag(pool::NAME_AND_TYPE))?;
+                    out.reference(&self.pool, Some(class));
+                    out.reference(&self.pool, method);
+                }
+                (_, SIGNATURE) => {
+                    length_offset = self.named(out, "Signature");
+
+                    let band = match context {
+                        CLASS => &mut self.class_signature,
+                        FIELD => &mut self.field_signature,
+                        _ => &mut self.method_signature,
+                    };
+
+                    let signature = self.pool.reference(band, Index::Tag(pool::SIGNATURE))?;
+                    out.reference(&self.pool, Some(signature));
+                }
+                (CLASS, INNER_CLASSES) => {
+                    let n = self.inner_classes_count.length()?;
+                    let mut local = Vec::with_capacity(n);
+
+                    for _ in 0..n {
+                        let inner = self.pool.reference(&mut self.inner_classes_class, Index::Tag(pool::CLASS))?;
+                        let flags = self.inner_classes_flags.get()? as u32;
+                        let (outer, name) = if flags == 0 {
+                            let global = self.inner_class_index.get(&inner).ok_or("inner class not in archive")?;
+                            let global = &self.inner_classes[*global];
+                            (global.outer, global.name)
+                        } else {
+                            let outer = self.pool.nullable_reference(&mut self.inner_classes_outer, Index::Tag(pool::CLASS))?;
+                            let name = self.pool.nullable_reference(&mut self.inner_classes_name, Index::Tag(pool::UTF8))?;
+                            (outer, name)
+                        };
+
+                        let flags = if flags == 0 {
+                            self.inner_classes[self.inner_class_index[&inner]].flags
+                        } else {
+                            flags & !(1 << 16)
+                        };
+
+                        local.push(InnerClass { inner, outer, name, flags });
+                    }
+
+                    self.local_inner_classes = Some(local);
+
+                    return Ok(false);
+                }
+                (CLASS, CLASS_FILE_VERSION) => {
+                    let minor = self.version_minor.get()?;
+                    let major = self.version_major.get()?;
+                    self.version = (minor as u16, major as u16);
+
+                    return Ok(false);
+                }
+                (FIELD, CONSTANT_VALUE) => {
+                    length_offset = self.named(out, "ConstantValue");
+                    let descriptor = self.pool.name_and_type(self.descriptor)?.1;
+                    let tag = match descriptor.first() {
+                        Some(&b'J') => pool::LONG,
+                        Some(&b'F') => pool::FLOAT,
+                        Some(&b'D') => pool::DOUBLE,
+                        Some(&b'L') => pool::STRING,
+                        _ => pool::INTEGER,
+                    };
+
+                    let value = self.pool.reference(&mut self.constant_value, Index::Tag(tag))?;
+                    out.reference(&self.pool, Some(value));
+                }
+                (METHOD, CODE_ATTRIBUTE) => {
+                    length_offset = self.named(out, "Code");
+                    self.write_code(out)?;
+                }
+                (METHOD, EXCEPTIONS) => {
+                    length_offset = self.named(out, "Exceptions");
+                    let n = self.exceptions_count.length()?;
+                    out.u2(n as u32);
+
+                    for _ in 0..n {
+                        let class = self.pool.reference(&mut self.exceptions_class, Index::Tag(pool::CLASS))?;
+                        out.reference(&self.pool, Some(class));
+                    }
+                }
+                (METHOD, METHOD_PARAMETERS) => {
+                    length_offset = self.named(out, "MethodParameters");
+                    let n = self.method_parameters_count.length()?;
+                    out.u1(n as u32);
+
+                    for _ in 0..n {
+                        let name = self.pool.nullable_reference(&mut self.method_parameters_name, Index::Tag(pool::UTF8))?;
+                        out.reference(&self.pool, name);
+                        out.u2(self.method_parameters_flags.get()? as u32);
+                    }
+                }
+                (CODE, STACK_MAP_TABLE) => {
+                    length_offset = self.named(out, "StackMapTable");
+                    self.write_stack_map_table(out)?;
+                }
+                (CODE, LINE_NUMBER_TABLE) => {
+                    length_offset = self.named(out, "LineNumberTable");
+                    let n = self.line_number_count.length()?;
+                    out.u2(n as u32);
+
+                    for _ in 0..n {
+                        let bci = to_bci(&self.bci_map, self.line_number_bci.get()?)?;
+                        out.u2(bci as u32);
+                        out.u2(self.line_number_line.get()? as u32);
+                    }
+                }
+                (CODE, LOCAL_VARIABLE_TABLE) | (CODE, LOCAL_VARIABLE_TYPE_TABLE) => {
+                    let (table, name) = if index == LOCAL_VARIABLE_TABLE {
+                        (0, "LocalVariableTable")
+                    } else {
+                        (1, "LocalVariableTypeTable")
+                    };
+
+                    length_offset = self.named(out, name);
+                    let bands = &mut self.local_variable_tables[table];
+                    let n = bands.count.length()?;
+                    out.u2(n as u32);
+
+                    for _ in 0..n {
+                        let bii = bands.bci.get()?;
+                        let start = to_bci(&self.bci_map, bii)?;
+                        let end = to_bci(&self.bci_map, bii + bands.span.get()?)?;
+                        out.u2(start as u32);
+                        out.u2((end - start) as u32);
+                        let name = self.pool.reference(&mut bands.name, Index::Tag(pool::UTF8))?;
+                        let descriptor = self.pool.reference(&mut bands.descriptor, Index::Tag(pool::SIGNATURE))?;
+                        out.reference(&self.pool, Some(name));
+                        out.reference(&self.pool, Some(descriptor));
+                        out.u2(bands.slot.get()? as u32);
+                    }
+                }
+                _ => return Err(format!("bad attribute index {}", index)),
+            }
+        } else {
+            let pool = &self.pool;
+            let field_descriptor = if context == FIELD { pool.name_and_type(self.descriptor)?.1 } else { &[] };
+            let layout = self.attributes[context]
+                .layouts
+                .get_mut(index)
+                .and_then(|l| l.as_mut())
+                .ok_or_else(|| format!("undefined attribute index {}", index))?;
+            let name = pool.utf8_id(&layout.name).ok_or("attribute name not in constant pool")?;
+            out.reference(pool, Some(name));
+            length_offset = out.len();
+            out.u4(0);
+            let context = Context { pool, bci_map: &self.bci_map, field_descriptor };
+            layout.write(out, &context)?;
+        }
+
+        let length = out.len() - length_offset - 4;
+        out.patch_u4(length_offset, length as u32);
+
+        Ok(true)
+    }
+
+    fn write_stack_map_table(&mut self, out: &mut Output) -> Result<(), String> {
+        let n = self.stack_map_count.length()?;
+        out.u2(n as u32);
+
+        for _ in 0..n {
+            let tag = self.stack_map_frame.get()?;
+            out.u1(tag as u32);
+
+            let types = match tag {
+                64..=127 => 1,
+                247..=254 => {
+                    out.u2(self.stack_map_offset.get()? as u32);
+                    if tag == 247 { 1 } else { tag.saturating_sub(251) as usize }
+                }
+                255 => {
+                    out.u2(self.stack_map_offset.get()? as u32);
+                    let locals = self.stack_map_local_count.length()?;
+                    out.u2(locals as u32);
+                    self.write_verification_types(out, locals)?;
+                    let stack = self.stack_map_stack_count.length()?;
+                    out.u2(stack as u32);
+                    stack
+                }
+                _ => 0,
+            };
+
+            self.write_verification_types(out, types)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_verification_types(&mut self, out: &mut Output, n: usize) -> Result<(), String> {
+        for _ in 0..n {
+            let tag = self.stack_map_type.get()?;
+            out.u1(tag as u32);
+
+            match tag {
+                7 => {
+                    let class = self.pool.reference(&mut self.stack_map_class, Index::Tag(pool::CLASS))?;
+                    out.reference(&self.pool, Some(class));
+                }
+                8 => {
+                    let bci = to_bci(&self.bci_map, self.stack_map_bci.get()?)?;
+                    out.u2(bci as u32);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_code(&mut self, out: &mut Output) -> Result<(), String> {
+        let header = self.code_headers.get(self.code_header_position).ok_or("too many code attributes")?;
+        self.code_header_position += 1;
+        let (max_stack, max_na_locals, handler_count, has_flags) =
+            (header.max_stack, header.max_na_locals, header.handler_count, header.has_flags);
+        let max_stack = if max_stack < 0 { self.code_max_stack.get()? } else { max_stack };
+        let max_na_locals = if max_na_locals < 0 { self.code_max_na_locals.get()? } else { max_na_locals };
+        let handler_count = if handler_count < 0 { self.code_handler_count.length()? } else { handler_count as usize };
+        let (_, descriptor) = self.pool.name_and_type(self.descriptor)?;
+        let is_static = self.member_flags & ACC_STATIC != 0;
+        let max_locals = max_na_locals as usize + argument_size(descriptor) + if is_static { 0 } else { 1 };
+        out.u2(max_stack as u32);
+        out.u2(max_locals as u32);
+        let length_offset = out.len();
+        out.u4(0);
+        self.write_bytecodes(out)?;
+        let code_length = out.len() - length_offset - 4;
+        out.patch_u4(length_offset, code_length as u32);
+        out.u2(handler_count as u32);
+
+        for _ in 0..handler_count {
+            let mut bii = self.code_handler_start.get()?;
+            out.u2(to_bci(&self.bci_map, bii)? as u32);
+            bii += self.code_handler_end.get()?;
+            out.u2(to_bci(&self.bci_map, bii)? as u32);
+            bii += self.code_handler_catch.get()?;
+            out.u2(to_bci(&self.bci_map, bii)? as u32);
+            let class = self.pool.nullable_reference(&mut self.code_handler_class, Index::Tag(pool::CLASS))?;
+            out.reference(&self.pool, class);
+        }
+
+        let flags = if has_flags { self.attributes[CODE].next_flags()? } else { 0 };
+        let mask = self.attributes[CODE].index_mask();
+        self.write_attributes(out, CODE, flags & mask)?;
+
+        Ok(())
+    }
+
+    fn next_bytecode(&mut self) -> Result<u8, String> {
+        let bc = *self.bc_codes.get(self.bc_position).ok_or("truncated bytecodes")?;
+        self.bc_position += 1;
+
+        Ok(bc)
+    }
+
+    // Writes a placeholder for a branch offset, which is patched once the
+    // offsets of all instructions are known.
+    fn label(&mut self, out: &mut Output, labels: &mut Vec<(usize, usize, usize, i32)>, ip: usize, size: usize) -> Result<(), String> {
+        labels.push((out.len(), size, ip, self.bc_label.get()?));
+
+        for _ in 0..size {
+            out.u1(0);
+        }
+
+        Ok(())
+    }
+
+    fn write_bytecodes(&mut self, out: &mut Output) -> Result<(), String> {
+        let start = out.len();
+        let mut labels = Vec::new();
+        let mut new_class = None;
+        self.bci_map.clear();
+
+        loop {
+            let ip = self.bci_map.len();
+            self.bci_map.push((out.len() - start) as u32);
+            let mut bc = self.next_bytecode()?;
+            if bc == END_MARKER {
+                break;
+            }
+
+            let wide = bc == WIDE;
+            if wide {
+                out.u1(WIDE as u32);
+                bc = self.next_bytecode()?;
+            }
+
+            match bc {
+                TABLESWITCH | LOOKUPSWITCH => {
+                    out.u1(bc as u32);
+
+                    while !(out.len() - start).is_multiple_of(4) {
+                        out.u1(0);
+                    }
+
+                    let cases = self.bc_case_count.length()?;
+                    self.label(out, &mut labels, ip, 4)?;
+
+                    if bc == TABLESWITCH {
+                        let low = self.bc_case_value.get()?;
+                        out.u4(low as u32);
+                        out.u4(low.wrapping_add(cases as i32 - 1) as u32);
+
+                        for _ in 0..cases {
+                            self.label(out, &mut labels, ip, 4)?;
+                        }
+                    } else {
+                        out.u4(cases as u32);
+
+                        for _ in 0..cases {
+                            out.u4(self.bc_case_value.get()? as u32);
+                            self.label(out, &mut labels, ip, 4)?;
+                        }
+                    }
+                }
+                IINC => {
+                    out.u1(IINC as u32);
+                    let local = self.bc_local.get()? as u32;
+
+                    if wide {
+                        out.u2(local);
+                        out.u2(self.bc_short.get()? as u32);
+                    } else {
+                        out.u1(local);
+                        out.u1(self.bc_byte.get()? as u32);
+                    }
+                }
+                17 => {
+                    out.u1(bc as u32);
+                    out.u2(self.bc_short.get()? as u32);
+                }
+                16 | 188 => {
+                    out.u1(bc as u32);
+                    out.u1(self.bc_byte.get()? as u32);
+                }
+                REF_ESCAPE => {
+                    let size = self.bc_escrefsize.get()?;
+                    let id = self.pool.reference(&mut self.bc_escref, Index::All)?;
+
+                    match size {
+                        1 => out.ldc_reference(&self.pool, Some(id)),
+                        2 => out.reference(&self.pool, Some(id)),
+                        _ => return Err(format!("bad escaped reference size {}", size)),
+                    }
+                }
+                BYTE_ESCAPE => {
+                    for _ in 0..self.bc_escsize.length()? {
+                        out.u1(self.bc_escbyte.get()? as u32);
+                    }
+                }
+                INVOKEINIT_FIRST..=INVOKEINIT_LAST => {
+                    out.u1(INVOKESPECIAL as u32);
+
+                    let class = match bc - INVOKEINIT_FIRST {
+                        0 => Some(self.this_class),
+                        1 => self.super_class,
+                        _ => new_class,
+                    };
+
+                    let class = class.ok_or("constructor call without a class")?;
+                    let n = self.bc_initref.length()?;
+                    let mut constructors = Vec::new();
+
+                    for &id in self.pool.methods_of(class) {
+                        if let Entry::Methodref(_, descriptor) = self.pool.entries[id] {
+                            if self.pool.name_and_type(descriptor)?.0 == b"<init>" {
+                                constructors.push(id);
+                            }
+                        }
+                    }
+
+                    let id = *constructors.get(n).ok_or("bad constructor reference")?;
+                    out.reference(&self.pool, Some(id));
+                }
+                SELF_LINKER_FIRST..=SELF_LINKER_LAST => {
+                    let index = bc - SELF_LINKER_FIRST;
+                    let is_super = index >= 14;
+                    let is_aload = index % 14 >= 7;
+                    let op = GETSTATIC + index % 7;
+
+                    // The aload_0 is an instruction of its own, with an
+                    // index of its own.
+                    if is_aload {
+                        out.u1(ALOAD_0 as u32);
+                        self.bci_map.push((out.len() - start) as u32);
+                    }
+
+                    out.u1(op as u32);
+
+                    let class = if is_super { self.super_class.ok_or("no super class")? } else { self.this_class };
+
+                    let (members, band) = match (op < INVOKEVIRTUAL, is_super) {
+                        (true, false) => (self.pool.fields_of(class), &mut self.bc_thisfield),
+                        (true, true) => (self.pool.fields_of(class), &mut self.bc_superfield),
+                        (false, false) => (self.pool.methods_of(class), &mut self.bc_thismethod),
+                        (false, true) => (self.pool.methods_of(class), &mut self.bc_supermethod),
+                    };
+
+                    let id = *members.get(band.length()?).ok_or("bad self-linking reference")?;
+                    out.reference(&self.pool, Some(id));
+                }
+                _ if is_branch(bc) => {
+                    out.u1(bc as u32);
+                    self.label(out, &mut labels, ip, if bc >= 200 { 4 } else { 2 })?;
+                }
+                _ if is_local(bc) => {
+                    out.u1(bc as u32);
+                    let local = self.bc_local.get()? as u32;
+
+                    if wide {
+                        out.u2(local);
+                    } else {
+                        out.u1(local);
+                    }
+                }
+                _ => match ref_band(bc) {
+                    Some((band, op)) => {
+                        out.u1(op as u32);
+
+                        let index = match band {
+                            RefBand::Int => Index::Tag(pool::INTEGER),
+                            RefBand::Float => Index::Tag(pool::FLOAT),
+                            RefBand::Long => Index::Tag(pool::LONG),
+                            RefBand::Double => Index::Tag(pool::DOUBLE),
+                            RefBand::String => Index::Tag(pool::STRING),
+                            RefBand::Loadable => Index::Loadable,
+                            RefBand::Class => Index::Tag(pool::CLASS),
+                            RefBand::Field => Index::Tag(pool::FIELDREF),
+                            RefBand::Method => Index::Tag(pool::METHODREF),
+                            RefBand::InterfaceMethod => Index::Tag(pool::INTERFACE_METHODREF),
+                            RefBand::Indy => Index::Tag(pool::INVOKE_DYNAMIC),
+                        };
+
+                        let values = &mut self.bc_refs[band as usize];
+
+                        // A missing class is the class itself.
+                        let id = if band == RefBand::Class {
+                            self.pool.nullable_reference(values, index)?.unwrap_or(self.this_class)
+                        } else {
+                            self.pool.reference(values, index)?
+                        };
+
+                        if op == 18 {
+                            out.ldc_reference(&self.pool, Some(id));
+                        } else {
+                            out.reference(&self.pool, Some(id));
+                        }
+
+                        match op {
+                            NEW => new_class = Some(id),
+                            MULTIANEWARRAY => out.u1(self.bc_byte.get()? as u32),
+                            INVOKEINTERFACE => {
+                                let descriptor = match self.pool.entries[id] {
+                                    Entry::InterfaceMethodref(_, descriptor) => self.pool.name_and_type(descriptor)?.1,
+                                    _ => unreachable!(),
+                                };
+
+                                out.u1(1 + argument_size(descriptor) as u32);
+                                out.u1(0);
+                            }
+                            INVOKEDYNAMIC => out.u2(0),
+                            _ => {}
+                        }
+                    }
+                    None if bc < SELF_LINKER_FIRST => out.u1(bc as u32),
+                    None => return Err(format!("bad bytecode {}", bc)),
+                },
+            }
+        }
+
+        for (offset, size, ip, label) in labels {
+            let target = to_bci(&self.bci_map, ip as i32 + label)?;
+            let relative = target - self.bci_map[ip] as i32;
+
+            if size == 2 {
+                out.patch_u2(offset, relative as u32);
+            } else {
+                out.patch_u4(offset, relative as u32);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Writes the InnerClasses attribute: the inner classes of the segment
+    // that this class or its constant pool mention, adjusted by what the
+    // class's own attribute says. Returns whether it was written.
+    fn write_inner_classes(&mut self, out: &mut Output) -> Result<bool, String> {
+        if let Some(children) = self.children.get(&self.this_class) {
+            for &i in children {
+                out.request(&self.pool, self.inner_classes[i].inner, false);
+            }
+        }
+
+        let mut implied = HashSet::new();
+
+        for class in out.classes(&self.pool) {
+            let mut class = Some(class);
+            while let Some(i) = class.and_then(|c| self.inner_class_index.get(&c).cloned()) {
+                if !implied.insert(i) {
+                    break;
+                }
+
+                class = self.inner_classes[i].outer;
+            }
+        }
+
+        let mut extra = Vec::new();
+
+        match self.local_inner_classes.take() {
+            // An empty attribute leaves out the implied one.
+            Some(ref local) if local.is_empty() => return Ok(false),
+            Some(local) => {
+                for ic in local {
+                    match self.inner_class_index.get(&ic.inner) {
+                        Some(&i) if implied.contains(&i) && self.inner_classes[i] == ic => {
+                            implied.remove(&i);
+                        }
+                        _ => extra.push(ic),
+                    }
+                }
+            }
+            None => {}
+        }
+
+        let mut implied = implied.into_iter().collect::<Vec<_>>();
+        implied.sort();
+        let entries = implied.iter().map(|&i| self.inner_classes[i].clone()).chain(extra).collect::<Vec<_>>();
+        if entries.is_empty() {
+            return Ok(false);
+        }
+
+        let length_offset = self.named(out, "InnerClasses");
+        out.u2(entries.len() as u32);
+
+        for ic in entries {
+            out.reference(&self.pool, Some(ic.inner));
+            out.reference(&self.pool, ic.outer);
+            out.reference(&self.pool, ic.name);
+            out.u2(ic.flags);
+        }
+
+        let length = out.len() - length_offset - 4;
+        out.patch_u4(length_offset, length as u32);
+
+        Ok(true)
+    }
+}
@@ -0,0 +1,472 @@
+// Pack200 band codings. Every value of a band is coded in a (B, H, S, D)
+// coding: at most B bytes, of which byte values at or above L = 256 - H
+// continue the number, S bits of sign, and with D set the values are the
+// differences between consecutive elements. A band can start with an escape
+// value selecting another coding for it, or a run or population coding
+// described in the band headers of the segment.
+
+/// A cursor over the bytes of a segment.
+pub struct Input<'a> {
+    bytes: &'a [u8],
+    pub position: usize,
+}
+
+impl<'a> Input<'a> {
+    pub fn new(bytes: &'a [u8]) -> Input<'a> {
+        Input { bytes, position: 0 }
+    }
+
+    pub fn byte(&mut self) -> Result<u8, String> {
+        let b = *self.bytes.get(self.position).ok_or("truncated Pack200 archive")?;
+        self.position += 1;
+
+        Ok(b)
+    }
+
+    pub fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() - self.position < n {
+            return Err("truncated Pack200 archive".to_string());
+        }
+
+        let bytes = &self.bytes[self.position..self.position + n];
+        self.position += n;
+
+        Ok(bytes)
+    }
+
+    pub fn rest(&self) -> &'a [u8] {
+        &self.bytes[self.position..]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Coding {
+    b: u32,
+    h: u32,
+    s: u32,
+    d: bool,
+}
+
+pub const BYTE1: Coding = Coding::new(1, 256, 0, false);
+pub const CHAR3: Coding = Coding::new(3, 128, 0, false);
+pub const BCI5: Coding = Coding::new(5, 4, 0, false);
+pub const BRANCH5: Coding = Coding::new(5, 4, 2, false);
+pub const UNSIGNED5: Coding = Coding::new(5, 64, 0, false);
+pub const UDELTA5: Coding = Coding::new(5, 64, 0, true);
+pub const SIGNED5: Coding = Coding::new(5, 64, 1, false);
+pub const DELTA5: Coding = Coding::new(5, 64, 1, true);
+pub const MDELTA5: Coding = Coding::new(5, 64, 2, true);
+
+// The codings escapes 1 to 115 stand for, as (B, H, S, D).
+const CANONICAL: [(u32, u32, u32, bool); 115] = [
+    (1, 256, 0, false), (1, 256, 1, false), (1, 256, 0, true), (1, 256, 1, true),
+    (2, 256, 0, false), (2, 256, 1, false), (2, 256, 0, true), (2, 256, 1, true),
+    (3, 256, 0, false), (3, 256, 1, false), (3, 256, 0, true), (3, 256, 1, true),
+    (4, 256, 0, false), (4, 256, 1, false), (4, 256, 0, true), (4, 256, 1, true),
+    (5, 4, 0, false), (5, 4, 1, false), (5, 4, 2, false),
+    (5, 16, 0, false), (5, 16, 1, false), (5, 16, 2, false),
+    (5, 32, 0, false), (5, 32, 1, false), (5, 32, 2, false),
+    (5, 64, 0, false), (5, 64, 1, false), (5, 64, 2, false),
+    (5, 128, 0, false), (5, 128, 1, false), (5, 128, 2, false),
+    (5, 4, 0, true), (5, 4, 1, true), (5, 4, 2, true),
+    (5, 16, 0, true), (5, 16, 1, true), (5, 16, 2, true),
+    (5, 32, 0, true), (5, 32, 1, true), (5, 32, 2, true),
+    (5, 64, 0, true), (5, 64, 1, true), (5, 64, 2, true),
+    (5, 128, 0, true), (5, 128, 1, true), (5, 128, 2, true),
+    (2, 192, 0, false), (2, 224, 0, false), (2, 240, 0, false), (2, 248, 0, false), (2, 252, 0, false),
+    (2, 8, 0, true), (2, 8, 1, true), (2, 16, 0, true), (2, 16, 1, true),
+    (2, 32, 0, true), (2, 32, 1, true), (2, 64, 0, true), (2, 64, 1, true),
+    (2, 128, 0, true), (2, 128, 1, true), (2, 192, 0, true), (2, 192, 1, true),
+    (2, 224, 0, true), (2, 224, 1, true), (2, 240, 0, true), (2, 240, 1, true),
+    (2, 248, 0, true), (2, 248, 1, true),
+    (3, 192, 0, false), (3, 224, 0, false), (3, 240, 0, false), (3, 248, 0, false), (3, 252, 0, false),
+    (3, 8, 0, true), (3, 8, 1, true), (3, 16, 0, true), (3, 16, 1, true),
+    (3, 32, 0, true), (3, 32, 1, true), (3, 64, 0, true), (3, 64, 1, true),
+    (3, 128, 0, true), (3, 128, 1, true), (3, 192, 0, true), (3, 192, 1, true),
+    (3, 224, 0, true), (3, 224, 1, true), (3, 240, 0, true), (3, 240, 1, true),
+    (3, 248, 0, true), (3, 248, 1, true),
+    (4, 192, 0, false), (4, 224, 0, false), (4, 240, 0, false), (4, 248, 0, false), (4, 252, 0, false),
+    (4, 8, 0, true), (4, 8, 1, true), (4, 16, 0, true), (4, 16, 1, true),
+    (4, 32, 0, true), (4, 32, 1, true), (4, 64, 0, true), (4, 64, 1, true),
+    (4, 128, 0, true), (4, 128, 1, true), (4, 192, 0, true), (4, 192, 1, true),
+    (4, 224, 0, true), (4, 224, 1, true), (4, 240, 0, true), (4, 240, 1, true),
+    (4, 248, 0, true), (4, 248, 1, true),
+];
+
+// Meta-coding specifiers: an arbitrary (B, H, S, D) in the next two bytes,
+// and the ranges of run and population codings.
+const ARBITRARY: u8 = 116;
+const RUN_FIRST: u8 = 117;
+const RUN_LAST: u8 = 140;
+const POPULATION_FIRST: u8 = 141;
+const POPULATION_LAST: u8 = 188;
+
+// The L of the token coding of population codings, by the TDefL field of
+// their specifier.
+const TOKEN_L: [u32; 12] = [0, 4, 8, 16, 32, 64, 128, 192, 224, 240, 248, 252];
+
+impl Coding {
+    pub const fn new(b: u32, h: u32, s: u32, d: bool) -> Coding {
+        Coding { b, h, s, d }
+    }
+
+    fn l(&self) -> u64 {
+        256 - self.h as u64
+    }
+
+    // Number of distinct byte sequences.
+    fn cardinality(&self) -> u64 {
+        let (mut card, mut weight) = (0, 1);
+
+        for _ in 1..self.b {
+            card += self.l() * weight;
+            weight *= self.h as u64;
+        }
+
+        card + 256 * weight
+    }
+
+    // Codings that cannot represent every 32-bit value keep delta sums within
+    // their range.
+    fn is_subrange(&self) -> bool {
+        self.cardinality() <= i32::MAX as u64
+    }
+
+    fn read_one(&self, input: &mut Input) -> Result<i32, String> {
+        let (mut sum, mut weight) = (0u64, 1u64);
+
+        for _ in 0..self.b {
+            let byte = input.byte()? as u64;
+            sum += byte * weight;
+
+            if byte < self.l() {
+                break;
+            }
+
+            weight *= self.h as u64;
+        }
+
+        let u = sum as u32;
+
+        if self.s == 0 {
+            return Ok(u as i32);
+        }
+
+        let mask = (1 << self.s) - 1;
+        Ok(if u & mask == mask { !(u >> self.s) as i32 } else { u.wrapping_sub(u >> self.s) as i32 })
+    }
+
+    // Applies the delta of the coding to a value just read.
+    fn accumulate(&self, state: &mut i64, value: i32) -> i32 {
+        if !self.d {
+            return value;
+        }
+
+        *state += value as i64;
+        *state = if self.is_subrange() {
+            state.rem_euclid(self.cardinality() as i64)
+        } else {
+            *state as i32 as i64
+        };
+
+        *state as i32
+    }
+
+    pub fn read(&self, input: &mut Input, n: usize) -> Result<Vec<i32>, String> {
+        let mut state = 0;
+        let mut values = Vec::with_capacity(n.min(input.rest().len()));
+
+        for _ in 0..n {
+            let value = self.read_one(input)?;
+            values.push(self.accumulate(&mut state, value));
+        }
+
+        Ok(values)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Method {
+    Plain(Coding),
+    // The first values in one coding and the rest in another.
+    Run { length: usize, head: Box<Method>, tail: Box<Method> },
+    // A table of favored values, then a token per value indexing the table,
+    // with 0 standing for a value that is not in it, read from the unfavored
+    // values.
+    Population {
+        favored: Box<Method>,
+        tokens: Option<Box<Method>>,
+        token_l: u32,
+        unfavored: Box<Method>,
+    },
+}
+
+// Parses the coding a band escape or meta-coding byte stands for, reading
+// any further bytes it needs from the band headers.
+fn parse_method(op: u8, meta: &mut Input, default: Coding) -> Result<Method, String> {
+    match op {
+        0 => Ok(Method::Plain(default)),
+        1..=115 => {
+            let (b, h, s, d) = CANONICAL[op as usize - 1];
+            Ok(Method::Plain(Coding::new(b, h, s, d)))
+        }
+        ARBITRARY => {
+            let dsb = meta.byte()? as u32;
+            let h = meta.byte()? as u32 + 1;
+            let (d, s, b) = (dsb & 1 != 0, (dsb >> 1) & 3, (dsb >> 3) + 1);
+            if b > 5 || s > 2 || (b == 1 && h != 256) {
+                return Err(format!("bad coding ({}, {}, {})", b, h, s));
+            }
+
+            Ok(Method::Plain(Coding::new(b, h, s, d)))
+        }
+        RUN_FIRST..=RUN_LAST => {
+            let op = op - RUN_FIRST;
+            let (kx, kb_given, ab_default) = (op % 4, !(op / 4).is_multiple_of(2), op / 8);
+            let kb = if kb_given { meta.byte()? as usize } else { 3 };
+            let length = (kb + 1) << (4 * kx);
+
+            let head = if ab_default & 1 == 0 {
+                let op = meta.byte()?;
+                parse_method(op, meta, default)?
+            } else {
+                Method::Plain(default)
+            };
+
+            let tail = if ab_default & 2 == 0 {
+                let op = meta.byte()?;
+                parse_method(op, meta, default)?
+            } else {
+                Method::Plain(default)
+            };
+
+            Ok(Method::Run { length, head: Box::new(head), tail: Box::new(tail) })
+        }
+        POPULATION_FIRST..=POPULATION_LAST => {
+            let op = op - POPULATION_FIRST;
+            let (f_default, u_default, t_l) = (!op.is_multiple_of(2), !(op / 2).is_multiple_of(2), op / 4);
+            let favored = if f_default {
+                Method::Plain(default)
+            } else {
+                let op = meta.byte()?;
+                parse_method(op, meta, default)?
+            };
+
+            let tokens = if t_l == 0 {
+                let op = meta.byte()?;
+                Some(Box::new(parse_method(op, meta, default)?))
+            } else {
+                None
+            };
+
+            let unfavored = if u_default {
+                Method::Plain(default)
+            } else {
+                let op = meta.byte()?;
+                parse_method(op, meta, default)?
+            };
+
+            Ok(Method::Population {
+                favored: Box::new(favored),
+                tokens,
+                token_l: TOKEN_L[t_l as usize],
+                unfavored: Box::new(unfavored),
+            })
+        }
+        _ => Err(format!("bad coding specifier {}", op)),
+    }
+}
+
+// Orders values by distance from zero, negative values after positive ones.
+fn more_central(x: i32, y: i32) -> i32 {
+    let key = |v: i32| ((v >> 31) ^ (v << 1)) as u32;
+    if key(x) < key(y) { x } else { y }
+}
+
+impl Method {
+    pub fn read(&self, input: &mut Input, n: usize) -> Result<Vec<i32>, String> {
+        match *self {
+            Method::Plain(coding) => coding.read(input, n),
+            Method::Run { length, ref head, ref tail } => {
+                let k = length.min(n);
+                let mut values = head.read(input, k)?;
+                values.extend(tail.read(input, n - k)?);
+
+                Ok(values)
+            }
+            Method::Population { ref favored, ref tokens, token_l, ref unfavored } => {
+                let favored = read_favored(favored, input)?;
+
+                let tokens = match *tokens {
+                    Some(ref method) => method.read(input, n)?,
+                    None => token_coding(favored.len(), token_l)?.read(input, n)?,
+                };
+
+                let unfavored_count = tokens.iter().filter(|&&t| t == 0).count();
+                let mut unfavored = unfavored.read(input, unfavored_count)?.into_iter();
+
+                tokens
+                    .into_iter()
+                    .map(|t| match t {
+                        0 => Ok(unfavored.next().unwrap()),
+                        t if t > 0 && t as usize <= favored.len() => Ok(favored[t as usize - 1]),
+                        t => Err(format!("bad population token {}", t)),
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+// The favored values of a population coding end with a repeat of the last
+// value or of the most central value so far.
+fn read_favored(method: &Method, input: &mut Input) -> Result<Vec<i32>, String> {
+    let mut values = Vec::new();
+    let (mut last, mut central) = (0, i32::MIN);
+    let mut method = method;
+    while let Method::Run { length, ref head, ref tail } = *method {
+        for v in head.read(input, length)? {
+            values.push(v);
+            last = v;
+            central = more_central(central, v);
+        }
+
+        method = tail;
+    }
+
+    let coding = match *method {
+        Method::Plain(coding) => coding,
+        _ => return Err("bad favored value coding".to_string()),
+    };
+
+    let mut state = 0;
+
+    loop {
+        let value = coding.read_one(input)?;
+        let value = coding.accumulate(&mut state, value);
+        if !values.is_empty() && (value == last || value == central) {
+            return Ok(values);
+        }
+
+        values.push(value);
+        last = value;
+        central = more_central(central, value);
+    }
+}
+
+// The smallest coding of tokens for k favored values.
+fn token_coding(k: usize, l: u32) -> Result<Coding, String> {
+    if k < 256 {
+        return Ok(BYTE1);
+    }
+
+    let mut coding = Coding::new(5, 256 - l, 0, false);
+    if coding.cardinality() <= k as u64 {
+        return Err("too many favored values".to_string());
+    }
+
+    while coding.b > 1 {
+        let shorter = Coding::new(coding.b - 1, coding.h, 0, false);
+        if shorter.cardinality() <= k as u64 {
+            break;
+        }
+
+        coding = shorter;
+    }
+
+    Ok(coding)
+}
+
+/// The values of a band, consumed in order.
+#[derive(Debug, Default)]
+pub struct Band {
+    values: Vec<i32>,
+    position: usize,
+}
+
+impl Band {
+    pub fn new(values: Vec<i32>) -> Band {
+        Band { values, position: 0 }
+    }
+
+    pub fn get(&mut self) -> Result<i32, String> {
+        let v = *self.values.get(self.position).ok_or("Pack200 band overflow")?;
+        self.position += 1;
+
+        Ok(v)
+    }
+
+    // The next value as a count or length.
+    pub fn length(&mut self) -> Result<usize, String> {
+        let v = self.get()?;
+        if v < 0 {
+            return Err(format!("bad Pack200 length {}", v));
+        }
+
+        Ok(v as usize)
+    }
+
+    pub fn rewind(&mut self) {
+        self.position = 0;
+    }
+
+    pub fn values(&self) -> &[i32] {
+        &self.values
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn total(&self) -> Result<usize, String> {
+        let total = self.values.iter().map(|&v| v as i64).sum::<i64>();
+        if total < 0 || total > u32::MAX as i64 {
+            return Err("bad Pack200 band total".to_string());
+        }
+
+        Ok(total as usize)
+    }
+
+    pub fn count(&self, value: i32) -> usize {
+        self.values.iter().filter(|&&v| v == value).count()
+    }
+}
+
+/// Reads the bands of a segment, taking the meta-codings of escaped bands
+/// from its band headers.
+pub struct Reader<'a> {
+    pub input: Input<'a>,
+    meta: Input<'a>,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(input: Input<'a>, band_headers: &'a [u8]) -> Reader<'a> {
+        Reader { input, meta: Input::new(band_headers) }
+    }
+
+    pub fn band(&mut self, coding: Coding, length: usize) -> Result<Band, String> {
+        if length == 0 {
+            return Ok(Band::default());
+        }
+
+        let mut method = Method::Plain(coding);
+
+        if coding.b > 1 && coding.h < 256 {
+            let start = self.input.position;
+            let x = Coding { d: false, ..coding }.read_one(&mut self.input)? as i64;
+            let escape = if coding.s != 0 { -1 - x } else { x - coding.l() as i64 };
+            if (0..256).contains(&escape) {
+                method = parse_method(escape as u8, &mut self.meta, coding)?;
+            } else {
+                self.input.position = start;
+            }
+        }
+
+        Ok(Band::new(method.read(&mut self.input, length)?))
+    }
+
+    // A value of the segment header, which are never escaped.
+    pub fn header(&mut self) -> Result<u32, String> {
+        Ok(UNSIGNED5.read_one(&mut self.input)? as u32)
+    }
+}
@@ -0,0 +1,538 @@
+// The attribute layout language, in which Pack200 describes how the
+// contents of an attribute are split over bands, e.g. NH[PHH] for a
+// LineNumberTable: a count, then pairs of a bytecode index and a line.
+
+use super::coding::{Band, Coding, Reader, BCI5, BRANCH5, BYTE1, SIGNED5, UNSIGNED5};
+use super::pool::{self, Index, Output, Pool};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Bci {
+    None,
+    // P: a bytecode index.
+    Index,
+    // PO: a bytecode index relative to the previous one.
+    IndexDelta,
+    // O: the distance in bytes from the previous bytecode index.
+    Offset,
+}
+
+// Where a reference points before knowing the field it is in, for KQ.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RefKind {
+    Index(Index),
+    FieldConstant,
+}
+
+#[derive(Debug)]
+enum Element {
+    Integral { band: usize, size: u32, bci: Bci },
+    Replication { band: usize, size: u32, body: Vec<Element> },
+    Union { band: usize, size: u32, cases: Vec<Case> },
+    Call { callable: usize, backward: bool },
+    Reference { band: usize, size: u32, kind: RefKind, nullable: bool },
+}
+
+#[derive(Debug)]
+struct Case {
+    // None for the default case.
+    tags: Option<Vec<(i32, i32)>>,
+    body: Vec<Element>,
+}
+
+#[derive(Debug)]
+pub struct Layout {
+    pub name: Vec<u8>,
+    // A layout of callables starts with the first one; other layouts have a
+    // single body.
+    callables: Vec<Vec<Element>>,
+    has_callables: bool,
+    backward_called: Vec<bool>,
+    codings: Vec<Coding>,
+    bands: Vec<Band>,
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    position: usize,
+    codings: Vec<Coding>,
+    callable: usize,
+    calls: Vec<(usize, i32)>,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self) -> String {
+        format!("bad attribute layout {:?} at {}", String::from_utf8_lossy(self.text), self.position)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.position).cloned()
+    }
+
+    fn next(&mut self) -> Result<u8, String> {
+        let c = self.peek().ok_or_else(|| self.error())?;
+        self.position += 1;
+
+        Ok(c)
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), String> {
+        if self.next()? != c {
+            return Err(self.error());
+        }
+
+        Ok(())
+    }
+
+    fn band(&mut self, coding: Coding) -> usize {
+        self.codings.push(coding);
+        self.codings.len() - 1
+    }
+
+    // The size of an integral type: B, H, I or V.
+    fn size(&mut self) -> Result<u32, String> {
+        match self.next()? {
+            b'B' => Ok(1),
+            b'H' => Ok(2),
+            b'I' => Ok(4),
+            b'V' => Ok(0),
+            _ => Err(self.error()),
+        }
+    }
+
+    // An optionally signed integral type and its coding.
+    fn integral(&mut self, signed_ok: bool) -> Result<(u32, Coding), String> {
+        let signed = signed_ok && self.peek() == Some(b'S');
+        if signed {
+            self.position += 1;
+        }
+
+        let size = self.size()?;
+
+        let coding = if signed {
+            SIGNED5
+        } else if size == 1 {
+            BYTE1
+        } else {
+            UNSIGNED5
+        };
+
+        Ok((size, coding))
+    }
+
+    fn numeral(&mut self) -> Result<i32, String> {
+        let start = self.position;
+
+        if self.peek() == Some(b'-') {
+            self.position += 1;
+        }
+
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.position += 1;
+        }
+
+        String::from_utf8_lossy(&self.text[start..self.position]).parse().map_err(|_| self.error())
+    }
+
+    // Elements up to a closing bracket, or the end of the layout.
+    fn body(&mut self, bracketed: bool) -> Result<Vec<Element>, String> {
+        let mut elements = Vec::new();
+
+        loop {
+            match self.peek() {
+                None if !bracketed => return Ok(elements),
+                Some(b']') if bracketed => {
+                    self.position += 1;
+                    return Ok(elements);
+                }
+                None | Some(b']') => return Err(self.error()),
+                Some(_) => elements.push(self.element()?),
+            }
+        }
+    }
+
+    fn element(&mut self) -> Result<Element, String> {
+        let c = self.next()?;
+        Ok(match c {
+            b'B' | b'H' | b'I' | b'V' | b'S' => {
+                self.position -= 1;
+                let (size, coding) = self.integral(true)?;
+                Element::Integral { band: self.band(coding), size, bci: Bci::None }
+            }
+            b'F' => {
+                let (size, coding) = self.integral(false)?;
+                Element::Integral { band: self.band(coding), size, bci: Bci::None }
+            }
+            b'P' => {
+                let delta = self.peek() == Some(b'O');
+                if delta {
+                    self.position += 1;
+                }
+
+                let (size, _) = self.integral(false)?;
+                let band = self.band(if delta { BRANCH5 } else { BCI5 });
+                Element::Integral { band, size, bci: if delta { Bci::IndexDelta } else { Bci::Index } }
+            }
+            b'O' => {
+                let (size, _) = self.integral(true)?;
+                Element::Integral { band: self.band(BRANCH5), size, bci: Bci::Offset }
+            }
+            b'N' => {
+                let (size, coding) = self.integral(false)?;
+                let band = self.band(coding);
+                self.expect(b'[')?;
+                Element::Replication { band, size, body: self.body(true)? }
+            }
+            b'T' => {
+                let (size, coding) = self.integral(true)?;
+                let band = self.band(coding);
+                let mut cases = Vec::new();
+
+                loop {
+                    self.expect(b'(')?;
+
+                    if self.peek() == Some(b')') {
+                        self.position += 1;
+                        self.expect(b'[')?;
+                        cases.push(Case { tags: None, body: self.body(true)? });
+                        break;
+                    }
+
+                    let mut tags = Vec::new();
+
+                    loop {
+                        let low = self.numeral()?;
+
+                        let high = if self.peek() == Some(b'-') {
+                            self.position += 1;
+                            self.numeral()?
+                        } else {
+                            low
+                        };
+
+                        tags.push((low, high));
+
+                        match self.next()? {
+                            b',' => continue,
+                            b')' => break,
+                            _ => return Err(self.error()),
+                        }
+                    }
+
+                    self.expect(b'[')?;
+                    cases.push(Case { tags: Some(tags), body: self.body(true)? });
+                }
+
+                Element::Union { band, size, cases }
+            }
+            b'(' => {
+                let offset = self.numeral()?;
+                self.expect(b')')?;
+                let callable = self.callable as i64 + offset as i64;
+                if callable < 0 {
+                    return Err(self.error());
+                }
+
+                self.calls.push((callable as usize, offset));
+                Element::Call { callable: callable as usize, backward: offset <= 0 }
+            }
+            b'K' | b'R' => {
+                let kind = match (c, self.next()?) {
+                    (b'K', b'I') => RefKind::Index(Index::Tag(pool::INTEGER)),
+                    (b'K', b'J') => RefKind::Index(Index::Tag(pool::LONG)),
+                    (b'K', b'F') => RefKind::Index(Index::Tag(pool::FLOAT)),
+                    (b'K', b'D') => RefKind::Index(Index::Tag(pool::DOUBLE)),
+                    (b'K', b'S') => RefKind::Index(Index::Tag(pool::STRING)),
+                    (b'K', b'Q') => RefKind::FieldConstant,
+                    (b'K', b'M') => RefKind::Index(Index::Tag(pool::METHOD_HANDLE)),
+                    (b'K', b'T') => RefKind::Index(Index::Tag(pool::METHOD_TYPE)),
+                    (b'K', b'L') => RefKind::Index(Index::Loadable),
+                    (b'R', b'C') => RefKind::Index(Index::Tag(pool::CLASS)),
+                    (b'R', b'S') => RefKind::Index(Index::Tag(pool::SIGNATURE)),
+                    (b'R', b'D') => RefKind::Index(Index::Tag(pool::NAME_AND_TYPE)),
+                    (b'R', b'F') => RefKind::Index(Index::Tag(pool::FIELDREF)),
+                    (b'R', b'M') => RefKind::Index(Index::Tag(pool::METHODREF)),
+                    (b'R', b'I') => RefKind::Index(Index::Tag(pool::INTERFACE_METHODREF)),
+                    (b'R', b'U') => RefKind::Index(Index::Tag(pool::UTF8)),
+                    (b'R', b'Q') => RefKind::Index(Index::All),
+                    (b'R', b'Y') => RefKind::Index(Index::Tag(pool::INVOKE_DYNAMIC)),
+                    (b'R', b'B') => RefKind::Index(Index::Tag(pool::BOOTSTRAP_METHOD)),
+                    (b'R', b'N') => RefKind::Index(Index::AnyMember),
+                    _ => return Err(self.error()),
+                };
+
+                let nullable = self.peek() == Some(b'N');
+                if nullable {
+                    self.position += 1;
+                }
+
+                let (size, _) = self.integral(false)?;
+                Element::Reference { band: self.band(UNSIGNED5), size, kind, nullable }
+            }
+            _ => {
+                self.position -= 1;
+                return Err(self.error());
+            }
+        })
+    }
+}
+
+// Maps the bytecode indexes of a layout to offsets in the code of the
+// method, given the offset of each instruction and of the end of the code.
+// Indexes past the end stand for offsets past the end.
+pub fn to_bci(map: &[u32], bii: i32) -> Result<i32, String> {
+    if bii < 0 || map.is_empty() {
+        return Err(format!("bad bytecode index {}", bii));
+    }
+
+    let len = map.len() as i64;
+    let bii = bii as i64;
+    if bii < len {
+        return Ok(map[bii as usize] as i32);
+    }
+
+    let key = bii - len;
+    let mut result = bii;
+
+    for i in (1..=len as usize).rev() {
+        if map[i - 1] as i64 - (i as i64 - 1) <= key {
+            break;
+        }
+
+        result -= 1;
+    }
+
+    Ok(result as i32)
+}
+
+/// What the references and bytecode indexes of an attribute depend on.
+pub struct Context<'a> {
+    pub pool: &'a Pool,
+    pub bci_map: &'a [u32],
+    // The descriptor of the field whose attributes are being written.
+    pub field_descriptor: &'a [u8],
+}
+
+impl Layout {
+    pub fn parse(name: &[u8], text: &[u8]) -> Result<Layout, String> {
+        let mut parser = Parser { text, position: 0, codings: Vec::new(), callable: 0, calls: Vec::new() };
+        let has_callables = text.first() == Some(&b'[');
+        let mut callables = Vec::new();
+
+        if has_callables {
+            while parser.peek().is_some() {
+                parser.callable = callables.len();
+                parser.expect(b'[')?;
+                callables.push(parser.body(true)?);
+            }
+        } else {
+            callables.push(parser.body(false)?);
+        }
+
+        let mut backward_called = vec![false; callables.len()];
+
+        for &(callable, offset) in &parser.calls {
+            if !has_callables || callable >= callables.len() {
+                return Err(parser.error());
+            }
+
+            if offset <= 0 {
+                backward_called[callable] = true;
+            }
+        }
+
+        let bands = (0..parser.codings.len()).map(|_| Band::default()).collect();
+
+        Ok(Layout {
+            name: name.to_vec(),
+            callables,
+            has_callables,
+            backward_called,
+            codings: parser.codings,
+            bands,
+        })
+    }
+
+    /// How many values the attr_calls band has for this layout.
+    pub fn backward_calls(&self) -> usize {
+        self.backward_called.iter().filter(|&&b| b).count()
+    }
+
+    /// Reads the bands of count attributes of this layout.
+    pub fn read(&mut self, r: &mut Reader, count: usize, calls: &mut Band) -> Result<(), String> {
+        let mut counts = vec![0; self.callables.len()];
+        counts[0] = count;
+
+        if self.has_callables {
+            for (i, &backward) in self.backward_called.iter().enumerate() {
+                if backward {
+                    counts[i] += calls.length()?;
+                }
+            }
+        }
+
+        let Layout { ref callables, ref codings, ref mut bands, .. } = *self;
+
+        for (i, body) in callables.iter().enumerate() {
+            let count = counts[i];
+            read_body(body, count, r, codings, bands, &mut counts)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the contents of the next attribute of this layout.
+    pub fn write(&mut self, out: &mut Output, context: &Context) -> Result<(), String> {
+        let Layout { ref callables, ref mut bands, .. } = *self;
+        write_body(&callables[0], callables, bands, out, context)
+    }
+}
+
+fn read_body(
+    elements: &[Element],
+    count: usize,
+    r: &mut Reader,
+    codings: &[Coding],
+    bands: &mut [Band],
+    counts: &mut [usize],
+) -> Result<(), String> {
+    for element in elements {
+        match *element {
+            Element::Integral { band, .. } | Element::Reference { band, .. } => {
+                bands[band] = r.band(codings[band], count)?;
+            }
+            Element::Replication { band, ref body, .. } => {
+                bands[band] = r.band(codings[band], count)?;
+                let total = bands[band].total()?;
+                read_body(body, total, r, codings, bands, counts)?;
+            }
+            Element::Union { band, ref cases, .. } => {
+                bands[band] = r.band(codings[band], count)?;
+                let mut remaining = count;
+
+                for case in cases {
+                    let n = match case.tags {
+                        Some(ref tags) => {
+                            let values = bands[band].values();
+                            values.iter().filter(|&&v| tags.iter().any(|&(lo, hi)| lo <= v && v <= hi)).count()
+                        }
+                        None => remaining,
+                    };
+
+                    remaining = remaining.saturating_sub(n);
+                    read_body(&case.body, n, r, codings, bands, counts)?;
+                }
+            }
+            Element::Call { callable, backward } => {
+                if !backward {
+                    counts[callable] += count;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn put(out: &mut Output, size: u32, v: i32) {
+    match size {
+        1 => out.u1(v as u32),
+        2 => out.u2(v as u32),
+        4 => out.u4(v as u32),
+        _ => {}
+    }
+}
+
+fn write_body(
+    elements: &[Element],
+    callables: &[Vec<Element>],
+    bands: &mut [Band],
+    out: &mut Output,
+    context: &Context,
+) -> Result<(), String> {
+    let (mut prev_bii, mut prev_bci) = (0, 0);
+
+    for element in elements {
+        match *element {
+            Element::Integral { band, size, bci } => {
+                let x = bands[band].get()?;
+                let x = match bci {
+                    Bci::None => x,
+                    Bci::Index => {
+                        prev_bii = x;
+                        prev_bci = to_bci(context.bci_map, x)?;
+
+                        prev_bci
+                    }
+                    Bci::IndexDelta => {
+                        prev_bii += x;
+                        prev_bci = to_bci(context.bci_map, prev_bii)?;
+
+                        prev_bci
+                    }
+                    Bci::Offset => {
+                        prev_bii += x;
+                        let offset = to_bci(context.bci_map, prev_bii)? - prev_bci;
+                        prev_bci += offset;
+
+                        offset
+                    }
+                };
+
+                put(out, size, x);
+            }
+            Element::Replication { band, size, ref body } => {
+                let n = bands[band].length()?;
+                put(out, size, n as i32);
+
+                for _ in 0..n {
+                    write_body(body, callables, bands, out, context)?;
+                }
+            }
+            Element::Union { band, size, ref cases } => {
+                let tag = bands[band].get()?;
+                put(out, size, tag);
+                let case = cases
+                    .iter()
+                    .find(|case| match case.tags {
+                        Some(ref tags) => tags.iter().any(|&(lo, hi)| lo <= tag && tag <= hi),
+                        None => true,
+                    })
+                    .unwrap();
+                write_body(&case.body, callables, bands, out, context)?;
+            }
+            Element::Call { callable, .. } => {
+                write_body(&callables[callable], callables, bands, out, context)?;
+            }
+            Element::Reference { band, size, kind, nullable } => {
+                let index = match kind {
+                    RefKind::Index(index) => index,
+                    RefKind::FieldConstant => Index::Tag(match context.field_descriptor.first() {
+                        Some(&b'J') => pool::LONG,
+                        Some(&b'F') => pool::FLOAT,
+                        Some(&b'D') => pool::DOUBLE,
+                        Some(&b'L') => pool::STRING,
+                        _ => pool::INTEGER,
+                    }),
+                };
+
+                let id = if nullable {
+                    context.pool.nullable_reference(&mut bands[band], index)?
+                } else {
+                    Some(context.pool.reference(&mut bands[band], index)?)
+                };
+
+                match size {
+                    1 => out.ldc_reference(context.pool, id),
+                    2 => out.reference(context.pool, id),
+                    4 => {
+                        out.u2(0);
+                        out.reference(context.pool, id);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
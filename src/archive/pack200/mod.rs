@@ -0,0 +1,167 @@
+// Decoding of Pack200 archives, the compressed form of jars that the JDK's
+// pack200 tool produced (usually gzipped, as .pack.gz), back into the class
+// files and other files of the jar.
+//
+// An archive is a sequence of segments. Each starts with a header of
+// counts, then has all of its constant pool entries, classes, fields,
+// methods, attributes and bytecodes split over bands of like values, each
+// compressed with a variable-length coding. The constant pool is shared
+// by the classes of the segment; each class file gets back a constant pool
+// of the entries it refers to. The contents of files other than classes
+// follow the bands as they are.
+
+use std::io::Read;
+
+use flate2::read::MultiGzDecoder;
+
+use self::classes::Classes;
+use self::coding::{Input, Reader, BYTE1, DELTA5, UNSIGNED5};
+use self::pool::{Index, Pool};
+
+mod classes;
+mod coding;
+mod layout;
+mod pool;
+
+/// Signature of a Pack200 archive.
+pub const PACK_MAGIC: &[u8] = b"\xca\xfe\xd0\x0d";
+
+// Pack200 archives are usually gzipped.
+const GZIP_MAGIC: &[u8] = b"\x1f\x8b";
+
+// Archive options of the segment header.
+const HAVE_SPECIAL_FORMATS: u32 = 1 << 0;
+const HAVE_CP_NUMBERS: u32 = 1 << 1;
+const HAVE_CP_EXTRAS: u32 = 1 << 3;
+const HAVE_FILE_HEADERS: u32 = 1 << 4;
+const HAVE_FILE_MODTIME: u32 = 1 << 6;
+const HAVE_FILE_OPTIONS: u32 = 1 << 7;
+const HAVE_FILE_SIZE_HI: u32 = 1 << 8;
+const UNUSED_OPTIONS: u32 = !0 << 13;
+
+// A file whose contents is the next class of the segment.
+const FILE_IS_CLASS_STUB: i32 = 1 << 1;
+
+// Versions of the archive format, as (minor, major).
+const VERSIONS: &[(u32, u32)] = &[(7, 150), (1, 160), (1, 170), (0, 171)];
+
+/// A file of the jar an archive was packed from.
+#[derive(Debug)]
+pub struct PackedFile {
+    pub name: String,
+    pub contents: Vec<u8>,
+}
+
+/// Whether a file header is that of a Pack200 archive, or of a gzipped file
+/// which might be one.
+pub fn is_packed(header: &[u8]) -> bool {
+    header.starts_with(PACK_MAGIC) || header.starts_with(GZIP_MAGIC)
+}
+
+/// Unpacks the files of a Pack200 archive, gzipped or not.
+pub fn unpack(bytes: &[u8]) -> Result<Vec<PackedFile>, String> {
+    if bytes.starts_with(GZIP_MAGIC) {
+        let mut unzipped = Vec::new();
+        MultiGzDecoder::new(bytes).read_to_end(&mut unzipped).map_err(|e| e.to_string())?;
+        return unpack(&unzipped);
+    }
+    let mut files = Vec::new();
+    let mut input = Input::new(bytes);
+    while !input.rest().is_empty() {
+        read_segment(&mut input, &mut files)?;
+    }
+    Ok(files)
+}
+
+fn read_segment(input: &mut Input, files: &mut Vec<PackedFile>) -> Result<(), String> {
+    if input.take(4)? != PACK_MAGIC {
+        return Err("not a Pack200 archive".to_string());
+    }
+    let header = |input: &mut Input| -> Result<usize, String> {
+        let mut r = Reader::new(Input::new(input.rest()), &[]);
+        let v = r.header()?;
+        input.take(r.input.position)?;
+        Ok(v as usize)
+    };
+    let minor_version = header(input)? as u32;
+    let major_version = header(input)? as u32;
+    if !VERSIONS.contains(&(minor_version, major_version)) {
+        return Err(format!("unsupported Pack200 version {}.{}", major_version, minor_version));
+    }
+    let options = header(input)? as u32;
+    if options & UNUSED_OPTIONS != 0 || (major_version < 170 && options & HAVE_CP_EXTRAS != 0) {
+        return Err(format!("bad Pack200 archive options {:#x}", options));
+    }
+    let mut file_count = 0;
+    if options & HAVE_FILE_HEADERS != 0 {
+        // Archive size, segments to come and default modification time.
+        for _ in 0..4 {
+            header(input)?;
+        }
+        file_count = header(input)?;
+    }
+    let (mut band_headers_size, mut attribute_definitions) = (0, 0);
+    if options & HAVE_SPECIAL_FORMATS != 0 {
+        band_headers_size = header(input)?;
+        attribute_definitions = header(input)?;
+    }
+    let mut counts = [0; 16];
+    for (i, count) in counts.iter_mut().enumerate() {
+        let present = match i {
+            1..=4 => options & HAVE_CP_NUMBERS != 0,
+            12..=15 => options & HAVE_CP_EXTRAS != 0,
+            _ => true,
+        };
+        if present {
+            *count = header(input)?;
+        }
+    }
+    let inner_class_count = header(input)?;
+    let default_minor = header(input)?;
+    let default_major = header(input)?;
+    let class_count = header(input)?;
+
+    let band_headers = BYTE1.read(input, band_headers_size)?.into_iter().map(|b| b as u8).collect::<Vec<_>>();
+    let mut r = Reader::new(Input::new(input.rest()), &band_headers);
+    let pool = Pool::read(&mut r, &counts)?;
+    let mut classes = Classes::new(options, pool, class_count, (default_minor as u16, default_major as u16))?;
+    classes.read_attribute_definitions(&mut r, attribute_definitions)?;
+    classes.read_inner_classes(&mut r, inner_class_count)?;
+    classes.read_classes(&mut r)?;
+    classes.read_bytecodes(&mut r)?;
+
+    let mut names = r.band(UNSIGNED5, file_count)?;
+    let mut sizes_hi = r.band(UNSIGNED5, if options & HAVE_FILE_SIZE_HI != 0 { file_count } else { 0 })?;
+    let mut sizes_lo = r.band(UNSIGNED5, file_count)?;
+    r.band(DELTA5, if options & HAVE_FILE_MODTIME != 0 { file_count } else { 0 })?;
+    let mut file_options = r.band(UNSIGNED5, if options & HAVE_FILE_OPTIONS != 0 { file_count } else { 0 })?;
+
+    let mut written = 0;
+    for _ in 0..file_count {
+        let name = classes.pool.reference(&mut names, Index::Tag(pool::UTF8))?;
+        let name = String::from_utf8_lossy(classes.pool.utf8(name)?).into_owned();
+        let hi = if options & HAVE_FILE_SIZE_HI != 0 { sizes_hi.get()? as u32 as u64 } else { 0 };
+        let size = hi << 32 | sizes_lo.get()? as u32 as u64;
+        let flags = if options & HAVE_FILE_OPTIONS != 0 { file_options.get()? } else { 0 };
+        if flags & FILE_IS_CLASS_STUB != 0 && written < class_count {
+            let (class_name, contents) = classes.write_class()?;
+            written += 1;
+            let name = if name.is_empty() { class_file_name(&class_name) } else { name };
+            files.push(PackedFile { name, contents });
+        } else {
+            let contents = r.input.take(size as usize)?.to_vec();
+            files.push(PackedFile { name, contents });
+        }
+    }
+    for _ in written..class_count {
+        let (class_name, contents) = classes.write_class()?;
+        files.push(PackedFile { name: class_file_name(&class_name), contents });
+    }
+    let consumed = r.input.position;
+    input.take(consumed)?;
+    Ok(())
+}
+
+fn class_file_name(class_name: &[u8]) -> String {
+    format!("{}.class", String::from_utf8_lossy(class_name))
+}
@@ -0,0 +1,729 @@
+// The constant pool of a Pack200 segment, shared by all of its classes, and
+// the constant pools of the class files written out of it, which hold only
+// the entries each class refers to.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+
+use write_util::{write_u16, write_u32, write_u8};
+
+use super::coding::{Band, Reader, CHAR3, DELTA5, UDELTA5, UNSIGNED5};
+
+pub const UTF8: u8 = 1;
+pub const INTEGER: u8 = 3;
+pub const FLOAT: u8 = 4;
+pub const LONG: u8 = 5;
+pub const DOUBLE: u8 = 6;
+pub const CLASS: u8 = 7;
+pub const STRING: u8 = 8;
+pub const FIELDREF: u8 = 9;
+pub const METHODREF: u8 = 10;
+pub const INTERFACE_METHODREF: u8 = 11;
+pub const NAME_AND_TYPE: u8 = 12;
+// Pack200 only: a descriptor or generic signature transmitted as a form
+// with the class names taken out, and the bootstrap method specifiers of
+// invokedynamic entries.
+pub const SIGNATURE: u8 = 13;
+pub const METHOD_HANDLE: u8 = 15;
+pub const METHOD_TYPE: u8 = 16;
+pub const BOOTSTRAP_METHOD: u8 = 17;
+pub const INVOKE_DYNAMIC: u8 = 18;
+
+// The order the entries of each tag are transmitted in, which is also the
+// order of references to any entry.
+const TAGS_IN_ORDER: [u8; 16] = [
+    UTF8, INTEGER, FLOAT, LONG, DOUBLE, STRING, CLASS, SIGNATURE, NAME_AND_TYPE, FIELDREF,
+    METHODREF, INTERFACE_METHODREF, METHOD_HANDLE, METHOD_TYPE, BOOTSTRAP_METHOD, INVOKE_DYNAMIC,
+];
+
+// The entries ldc can load, in the order of references to them.
+const LOADABLE: [u8; 8] = [INTEGER, FLOAT, LONG, DOUBLE, STRING, CLASS, METHOD_HANDLE, METHOD_TYPE];
+
+#[derive(Debug, Clone)]
+pub enum Entry {
+    // Modified UTF-8, as in class files.
+    Utf8(Vec<u8>),
+    Integer(u32),
+    Float(u32),
+    Long(u64),
+    Double(u64),
+    String(usize),
+    Class(usize),
+    // A signature that is the same string as an earlier Utf8 entry, which
+    // references resolve to instead. Signatures that are not become Utf8
+    // entries themselves.
+    Signature(usize),
+    NameAndType(usize, usize),
+    Fieldref(usize, usize),
+    Methodref(usize, usize),
+    InterfaceMethodref(usize, usize),
+    MethodHandle(u8, usize),
+    MethodType(usize),
+    BootstrapMethod(usize, Vec<usize>),
+    InvokeDynamic(usize, usize),
+}
+
+/// What kind of entry the values of a band refer to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Index {
+    Tag(u8),
+    // Any entry, in transmission order.
+    All,
+    Loadable,
+    // A Fieldref, Methodref or InterfaceMethodref.
+    AnyMember,
+}
+
+pub struct Pool {
+    pub entries: Vec<Entry>,
+    // Where the entries of each tag start, and how many there are.
+    ranges: HashMap<u8, (usize, usize)>,
+    transmitted: usize,
+    utf8s: HashMap<Vec<u8>, usize>,
+    classes: HashMap<usize, usize>,
+    fields: HashMap<usize, Vec<usize>>,
+    methods: HashMap<usize, Vec<usize>>,
+}
+
+// Appends a UTF-16 code unit to modified UTF-8.
+fn push_char(out: &mut Vec<u8>, c: u16) {
+    match c {
+        0x01..=0x7f => out.push(c as u8),
+        0x00 | 0x80..=0x7ff => out.extend_from_slice(&[0xc0 | (c >> 6) as u8, 0x80 | (c & 0x3f) as u8]),
+        _ => out.extend_from_slice(&[
+            0xe0 | (c >> 12) as u8,
+            0x80 | ((c >> 6) & 0x3f) as u8,
+            0x80 | (c & 0x3f) as u8,
+        ]),
+    }
+}
+
+fn chars(band: &mut Band, n: usize) -> Result<Vec<u16>, String> {
+    (0..n).map(|_| band.get().map(|c| c as u16)).collect()
+}
+
+impl Pool {
+    /// Reads the constant pool bands, given the number of entries of each
+    /// tag in TAGS_IN_ORDER.
+    pub fn read(r: &mut Reader, counts: &[usize; 16]) -> Result<Pool, String> {
+        let mut pool = Pool {
+            entries: Vec::new(),
+            ranges: HashMap::new(),
+            transmitted: counts.iter().sum(),
+            utf8s: HashMap::new(),
+            classes: HashMap::new(),
+            fields: HashMap::new(),
+            methods: HashMap::new(),
+        };
+
+        let mut start = 0;
+
+        for (&tag, &count) in TAGS_IN_ORDER.iter().zip(counts.iter()) {
+            pool.ranges.insert(tag, (start, count));
+            start += count;
+        }
+
+        for (&tag, &n) in TAGS_IN_ORDER.iter().zip(counts.iter()) {
+            match tag {
+                UTF8 => pool.read_utf8s(r, n)?,
+                INTEGER | FLOAT => {
+                    let mut values = r.band(UDELTA5, n)?;
+
+                    for _ in 0..n {
+                        let v = values.get()? as u32;
+                        pool.entries.push(if tag == INTEGER { Entry::Integer(v) } else { Entry::Float(v) });
+                    }
+                }
+                LONG | DOUBLE => {
+                    let mut hi = r.band(UDELTA5, n)?;
+                    let mut lo = r.band(DELTA5, n)?;
+
+                    for _ in 0..n {
+                        let v = (hi.get()? as u32 as u64) << 32 | lo.get()? as u32 as u64;
+                        pool.entries.push(if tag == LONG { Entry::Long(v) } else { Entry::Double(v) });
+                    }
+                }
+                STRING | CLASS => {
+                    let mut names = r.band(UDELTA5, n)?;
+
+                    for _ in 0..n {
+                        let name = pool.reference(&mut names, Index::Tag(UTF8))?;
+
+                        if tag == CLASS {
+                            pool.classes.entry(name).or_insert(pool.entries.len());
+                            pool.entries.push(Entry::Class(name));
+                        } else {
+                            pool.entries.push(Entry::String(name));
+                        }
+                    }
+                }
+                SIGNATURE => pool.read_signatures(r, n)?,
+                NAME_AND_TYPE => {
+                    let mut names = r.band(DELTA5, n)?;
+                    let mut types = r.band(UDELTA5, n)?;
+
+                    for _ in 0..n {
+                        let name = pool.reference(&mut names, Index::Tag(UTF8))?;
+                        let descriptor = pool.reference(&mut types, Index::Tag(SIGNATURE))?;
+                        pool.entries.push(Entry::NameAndType(name, descriptor));
+                    }
+                }
+                FIELDREF | METHODREF | INTERFACE_METHODREF => {
+                    let mut classes = r.band(DELTA5, n)?;
+                    let mut descriptors = r.band(UDELTA5, n)?;
+
+                    for _ in 0..n {
+                        let class = pool.reference(&mut classes, Index::Tag(CLASS))?;
+                        let descriptor = pool.reference(&mut descriptors, Index::Tag(NAME_AND_TYPE))?;
+                        let id = pool.entries.len();
+                        pool.entries.push(match tag {
+                            FIELDREF => {
+                                pool.fields.entry(class).or_default().push(id);
+                                Entry::Fieldref(class, descriptor)
+                            }
+                            METHODREF => {
+                                pool.methods.entry(class).or_default().push(id);
+                                Entry::Methodref(class, descriptor)
+                            }
+                            _ => Entry::InterfaceMethodref(class, descriptor),
+                        });
+                    }
+                }
+                METHOD_HANDLE => {
+                    let mut kinds = r.band(DELTA5, n)?;
+                    let mut members = r.band(UDELTA5, n)?;
+
+                    for _ in 0..n {
+                        let kind = kinds.get()?;
+                        if !(1..=9).contains(&kind) {
+                            return Err(format!("bad method handle kind {}", kind));
+                        }
+
+                        let member = pool.reference(&mut members, Index::AnyMember)?;
+                        pool.entries.push(Entry::MethodHandle(kind as u8, member));
+                    }
+                }
+                METHOD_TYPE => {
+                    let mut types = r.band(UDELTA5, n)?;
+
+                    for _ in 0..n {
+                        let descriptor = pool.reference(&mut types, Index::Tag(SIGNATURE))?;
+                        pool.entries.push(Entry::MethodType(descriptor));
+                    }
+                }
+                BOOTSTRAP_METHOD => {
+                    let mut handles = r.band(DELTA5, n)?;
+                    let mut arg_counts = r.band(UDELTA5, n)?;
+                    let mut args = r.band(DELTA5, arg_counts.total()?)?;
+
+                    for _ in 0..n {
+                        let handle = pool.reference(&mut handles, Index::Tag(METHOD_HANDLE))?;
+                        let args = (0..arg_counts.length()?)
+                            .map(|_| pool.reference(&mut args, Index::Loadable))
+                            .collect::<Result<_, _>>()?;
+                        pool.entries.push(Entry::BootstrapMethod(handle, args));
+                    }
+                }
+                INVOKE_DYNAMIC => {
+                    let mut specifiers = r.band(DELTA5, n)?;
+                    let mut descriptors = r.band(UDELTA5, n)?;
+
+                    for _ in 0..n {
+                        let bootstrap = pool.reference(&mut specifiers, Index::Tag(BOOTSTRAP_METHOD))?;
+                        let descriptor = pool.reference(&mut descriptors, Index::Tag(NAME_AND_TYPE))?;
+                        pool.entries.push(Entry::InvokeDynamic(bootstrap, descriptor));
+                    }
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        Ok(pool)
+    }
+
+    // Strings are transmitted as the number of UTF-16 chars they share with
+    // the previous one and the chars that follow. Long suffixes are sent in
+    // bands of their own.
+    fn read_utf8s(&mut self, r: &mut Reader, n: usize) -> Result<(), String> {
+        let mut prefixes = r.band(DELTA5, n.saturating_sub(2))?;
+        let suffixes = r.band(UNSIGNED5, n.saturating_sub(1))?;
+        let mut chars_band = r.band(CHAR3, suffixes.total()?)?;
+        let mut big_suffixes = r.band(DELTA5, suffixes.count(0))?;
+        let mut big = Vec::new();
+
+        for _ in 0..big_suffixes.len() {
+            let length = big_suffixes.length()?;
+            let mut band = r.band(DELTA5, length)?;
+            big.push(chars(&mut band, length)?);
+        }
+
+        let mut big = big.into_iter();
+        let mut suffixes = Band::new(suffixes.values().to_vec());
+        let mut previous: Vec<u16> = Vec::new();
+
+        for i in 0..n {
+            let prefix = if i < 2 { 0 } else { prefixes.length()? };
+            if prefix > previous.len() {
+                return Err("bad Pack200 string prefix".to_string());
+            }
+
+            let mut s = previous[..prefix].to_vec();
+
+            if i > 0 {
+                let suffix = suffixes.length()?;
+                if suffix > 0 {
+                    s.extend(chars(&mut chars_band, suffix)?);
+                } else {
+                    s.extend(big.next().unwrap());
+                }
+            }
+
+            let mut bytes = Vec::with_capacity(s.len());
+
+            for &c in &s {
+                push_char(&mut bytes, c);
+            }
+
+            self.utf8s.entry(bytes.clone()).or_insert(self.entries.len());
+            self.entries.push(Entry::Utf8(bytes));
+            previous = s;
+        }
+
+        Ok(())
+    }
+
+    // Expands each signature form by putting the class names after its 'L's.
+    fn read_signatures(&mut self, r: &mut Reader, n: usize) -> Result<(), String> {
+        let mut forms = r.band(DELTA5, n)?;
+        let mut form_ids = Vec::with_capacity(n);
+        let mut class_count = 0;
+
+        for _ in 0..n {
+            let form = self.reference(&mut forms, Index::Tag(UTF8))?;
+            class_count += self.utf8(form)?.iter().filter(|&&c| c == b'L').count();
+            form_ids.push(form);
+        }
+
+        let mut classes = r.band(UDELTA5, class_count)?;
+
+        for form in form_ids {
+            let mut signature = Vec::new();
+
+            for &c in self.utf8(form)? {
+                signature.push(c);
+
+                if c == b'L' {
+                    let class = self.reference(&mut classes, Index::Tag(CLASS))?;
+                    signature.extend_from_slice(self.class_name(class)?);
+                }
+            }
+
+            let id = self.entries.len();
+
+            match self.utf8s.get(&signature) {
+                Some(&utf8) => self.entries.push(Entry::Signature(utf8)),
+                None => {
+                    self.utf8s.insert(signature.clone(), id);
+                    self.entries.push(Entry::Utf8(signature));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The entry the next value of a band refers to.
+    pub fn reference(&self, band: &mut Band, index: Index) -> Result<usize, String> {
+        let v = band.get()?;
+        self.lookup(index, v)
+    }
+
+    /// Like reference, for bands where 0 stands for no entry.
+    pub fn nullable_reference(&self, band: &mut Band, index: Index) -> Result<Option<usize>, String> {
+        match band.get()? {
+            0 => Ok(None),
+            v => self.lookup(index, v - 1).map(Some),
+        }
+    }
+
+    fn range(&self, tag: u8) -> (usize, usize) {
+        *self.ranges.get(&tag).unwrap_or(&(0, 0))
+    }
+
+    fn lookup(&self, index: Index, v: i32) -> Result<usize, String> {
+        let bad = || format!("bad Pack200 constant pool reference {}", v);
+
+        if v < 0 {
+            return Err(bad());
+        }
+
+        let v = v as usize;
+
+        let id = match index {
+            Index::Tag(tag) => {
+                let (start, count) = self.range(tag);
+                if v >= count {
+                    return Err(bad());
+                }
+
+                start + v
+            }
+            Index::All if v < self.transmitted => v,
+            Index::All => return Err(bad()),
+            Index::Loadable => {
+                let mut v = v;
+                let mut id = None;
+
+                for &tag in &LOADABLE {
+                    let (start, count) = self.range(tag);
+                    if v < count {
+                        id = Some(start + v);
+                        break;
+                    }
+
+                    v -= count;
+                }
+
+                id.ok_or_else(bad)?
+            }
+            Index::AnyMember => {
+                let (start, _) = self.range(FIELDREF);
+                let count = [FIELDREF, METHODREF, INTERFACE_METHODREF].iter().map(|&t| self.range(t).1).sum();
+                if v >= count {
+                    return Err(bad());
+                }
+
+                start + v
+            }
+        };
+
+        Ok(match self.entries.get(id) {
+            Some(&Entry::Signature(utf8)) => utf8,
+            _ => id,
+        })
+    }
+
+    pub fn utf8(&self, id: usize) -> Result<&[u8], String> {
+        match self.entries.get(id) {
+            Some(Entry::Utf8(bytes)) => Ok(bytes),
+            _ => Err("expected a Utf8 constant".to_string()),
+        }
+    }
+
+    pub fn class_name(&self, id: usize) -> Result<&[u8], String> {
+        match self.entries.get(id) {
+            Some(&Entry::Class(name)) => self.utf8(name),
+            _ => Err("expected a Class constant".to_string()),
+        }
+    }
+
+    /// The name and descriptor of a NameAndType entry.
+    pub fn name_and_type(&self, id: usize) -> Result<(&[u8], &[u8]), String> {
+        match self.entries.get(id) {
+            Some(&Entry::NameAndType(name, descriptor)) => Ok((self.utf8(name)?, self.utf8(descriptor)?)),
+            _ => Err("expected a NameAndType constant".to_string()),
+        }
+    }
+
+    pub fn utf8_id(&self, bytes: &[u8]) -> Option<usize> {
+        self.utf8s.get(bytes).cloned()
+    }
+
+    /// The Utf8 entry for a string, added if the segment has none.
+    pub fn ensure_utf8(&mut self, bytes: &[u8]) -> usize {
+        if let Some(&id) = self.utf8s.get(bytes) {
+            return id;
+        }
+
+        let id = self.entries.len();
+        self.entries.push(Entry::Utf8(bytes.to_vec()));
+        self.utf8s.insert(bytes.to_vec(), id);
+
+        id
+    }
+
+    // The order of entries in a class's constant pool: those of the segment
+    // as they were transmitted, then those added while unpacking by tag and
+    // contents, as the JDK's unpacker writes them.
+    fn output_order(&self, a: usize, b: usize) -> Ordering {
+        if a < self.transmitted || b < self.transmitted {
+            return a.cmp(&b);
+        }
+
+        let key = |id: usize| match self.entries[id] {
+            Entry::Utf8(ref bytes) => (UTF8, &bytes[..]),
+            Entry::Class(name) => (CLASS, self.utf8(name).unwrap_or_default()),
+            _ => (0, &[][..]),
+        };
+        key(a).cmp(&key(b))
+    }
+
+    pub fn ensure_class(&mut self, name: &[u8]) -> usize {
+        let name = self.ensure_utf8(name);
+        if let Some(&id) = self.classes.get(&name) {
+            return id;
+        }
+
+        let id = self.entries.len();
+        self.entries.push(Entry::Class(name));
+        self.classes.insert(name, id);
+
+        id
+    }
+
+    /// The Fieldref entries of a class, which self-linking bytecodes index.
+    pub fn fields_of(&self, class: usize) -> &[usize] {
+        self.fields.get(&class).map(|v| &v[..]).unwrap_or(&[])
+    }
+
+    pub fn methods_of(&self, class: usize) -> &[usize] {
+        self.methods.get(&class).map(|v| &v[..]).unwrap_or(&[])
+    }
+}
+
+/// A class file being written: its bytes after the constant pool, with
+/// places to patch with the indexes of the constants it refers to once
+/// its constant pool has been laid out.
+pub struct Output {
+    pub bytes: Vec<u8>,
+    // Offset, entry and whether the index takes one byte, as for ldc.
+    fixups: Vec<(usize, usize, bool)>,
+    // Entries in the constant pool, and whether ldc loads them.
+    requested: HashMap<usize, bool>,
+    bootstrap_methods: BTreeSet<usize>,
+    bootstrap_indexes: HashMap<usize, usize>,
+}
+
+impl Output {
+    pub fn new() -> Output {
+        Output {
+            bytes: Vec::new(),
+            fixups: Vec::new(),
+            requested: HashMap::new(),
+            bootstrap_methods: BTreeSet::new(),
+            bootstrap_indexes: HashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn u1(&mut self, v: u32) {
+        write_u8(&mut self.bytes, v as u8);
+    }
+
+    pub fn u2(&mut self, v: u32) {
+        write_u16(&mut self.bytes, v as u16);
+    }
+
+    pub fn u4(&mut self, v: u32) {
+        write_u32(&mut self.bytes, v);
+    }
+
+    pub fn patch_u2(&mut self, offset: usize, v: u32) {
+        self.bytes[offset] = (v >> 8) as u8;
+        self.bytes[offset + 1] = v as u8;
+    }
+
+    pub fn patch_u4(&mut self, offset: usize, v: u32) {
+        self.bytes[offset..offset + 4].copy_from_slice(&[(v >> 24) as u8, (v >> 16) as u8, (v >> 8) as u8, v as u8]);
+    }
+
+    /// Writes the two-byte index of an entry, or 0 for none.
+    pub fn reference(&mut self, pool: &Pool, id: Option<usize>) {
+        if let Some(id) = id {
+            self.request(pool, id, false);
+            self.fixups.push((self.bytes.len(), id, false));
+        }
+
+        self.u2(0);
+    }
+
+    /// Writes the one-byte index of an entry, as ldc takes.
+    pub fn ldc_reference(&mut self, pool: &Pool, id: Option<usize>) {
+        if let Some(id) = id {
+            self.request(pool, id, true);
+            self.fixups.push((self.bytes.len(), id, true));
+        }
+
+        self.u1(0);
+    }
+
+    pub fn request(&mut self, pool: &Pool, id: usize, ldc: bool) {
+        if let Entry::BootstrapMethod(..) = pool.entries[id] {
+            self.bootstrap_methods.insert(id);
+            return;
+        }
+
+        if let Some(seen) = self.requested.get_mut(&id) {
+            *seen |= ldc;
+            return;
+        }
+
+        self.requested.insert(id, ldc);
+
+        match pool.entries[id] {
+            Entry::String(r) | Entry::Class(r) | Entry::MethodType(r) | Entry::MethodHandle(_, r) => {
+                self.request(pool, r, false)
+            }
+
+            Entry::NameAndType(a, b)
+            | Entry::Fieldref(a, b)
+            | Entry::Methodref(a, b)
+            | Entry::InterfaceMethodref(a, b) => {
+                self.request(pool, a, false);
+                self.request(pool, b, false);
+            }
+            Entry::InvokeDynamic(bootstrap, descriptor) => {
+                self.bootstrap_methods.insert(bootstrap);
+                self.request(pool, descriptor, false);
+            }
+            _ => {}
+        }
+    }
+
+    /// The Class entries of the constant pool so far.
+    pub fn classes(&self, pool: &Pool) -> Vec<usize> {
+        self.requested
+            .keys()
+            .cloned()
+            .filter(|&id| matches!(pool.entries[id], Entry::Class(_)))
+            .collect()
+    }
+
+    /// Writes the BootstrapMethods attribute, if any invokedynamic
+    /// constant needs it. Returns whether it was written.
+    pub fn write_bootstrap_methods(&mut self, pool: &Pool, name: usize) -> bool {
+        if self.bootstrap_methods.is_empty() {
+            return false;
+        }
+
+        let bootstrap_methods = self.bootstrap_methods.iter().cloned().collect::<Vec<_>>();
+        self.reference(pool, Some(name));
+        let length_offset = self.len();
+        self.u4(0);
+        self.u2(bootstrap_methods.len() as u32);
+
+        for (i, &id) in bootstrap_methods.iter().enumerate() {
+            self.bootstrap_indexes.insert(id, i);
+
+            if let Entry::BootstrapMethod(handle, ref args) = pool.entries[id] {
+                self.reference(pool, Some(handle));
+                self.u2(args.len() as u32);
+
+                for &arg in args {
+                    self.reference(pool, Some(arg));
+                }
+            }
+        }
+
+        let length = self.len() - length_offset - 4;
+        self.patch_u4(length_offset, length as u32);
+
+        true
+    }
+
+    /// Lays out the constant pool and returns the whole class file.
+    pub fn finish(mut self, pool: &Pool, minor_version: u16, major_version: u16) -> Result<Vec<u8>, String> {
+        // Entries ldc loads come first, as their indexes have to fit in a
+        // byte, then the rest.
+        let mut order = self.requested.iter().map(|(&id, &ldc)| (!ldc, id)).collect::<Vec<_>>();
+        order.sort_by(|&(a_ldc, a), &(b_ldc, b)| a_ldc.cmp(&b_ldc).then_with(|| pool.output_order(a, b)));
+        let mut indexes = HashMap::new();
+        let mut head = Vec::new();
+        let mut next = 1;
+
+        for &(not_ldc, id) in &order {
+            if !not_ldc && next > 0xff {
+                return Err("too many constants loaded by ldc".to_string());
+            }
+
+            indexes.insert(id, next);
+            next += match pool.entries[id] {
+                Entry::Long(_) | Entry::Double(_) => 2,
+                _ => 1,
+            };
+        }
+
+        if next > 0xffff {
+            return Err("too many constants for a class file".to_string());
+        }
+
+        let index = |id: usize| indexes[&id] as u16;
+        write_u32(&mut head, 0xcafe_babe);
+        write_u16(&mut head, minor_version);
+        write_u16(&mut head, major_version);
+        write_u16(&mut head, next as u16);
+
+        for &(_, id) in &order {
+            match pool.entries[id] {
+                Entry::Utf8(ref bytes) => {
+                    write_u8(&mut head, UTF8);
+                    write_u16(&mut head, bytes.len() as u16);
+                    head.extend_from_slice(bytes);
+                }
+                Entry::Integer(v) | Entry::Float(v) => {
+                    write_u8(&mut head, if let Entry::Integer(_) = pool.entries[id] { INTEGER } else { FLOAT });
+                    write_u32(&mut head, v);
+                }
+                Entry::Long(v) | Entry::Double(v) => {
+                    write_u8(&mut head, if let Entry::Long(_) = pool.entries[id] { LONG } else { DOUBLE });
+                    write_u32(&mut head, (v >> 32) as u32);
+                    write_u32(&mut head, v as u32);
+                }
+                Entry::String(r) | Entry::Class(r) | Entry::MethodType(r) => {
+                    write_u8(&mut head, match pool.entries[id] {
+                        Entry::String(_) => STRING,
+                        Entry::Class(_) => CLASS,
+                        _ => METHOD_TYPE,
+                    });
+                    write_u16(&mut head, index(r));
+                }
+
+                Entry::NameAndType(a, b)
+                | Entry::Fieldref(a, b)
+                | Entry::Methodref(a, b)
+                | Entry::InterfaceMethodref(a, b) => {
+                    write_u8(&mut head, match pool.entries[id] {
+                        Entry::NameAndType(..) => NAME_AND_TYPE,
+                        Entry::Fieldref(..) => FIELDREF,
+                        Entry::Methodref(..) => METHODREF,
+                        _ => INTERFACE_METHODREF,
+                    });
+                    write_u16(&mut head, index(a));
+                    write_u16(&mut head, index(b));
+                }
+                Entry::MethodHandle(kind, member) => {
+                    write_u8(&mut head, METHOD_HANDLE);
+                    write_u8(&mut head, kind);
+                    write_u16(&mut head, index(member));
+                }
+                Entry::InvokeDynamic(bootstrap, descriptor) => {
+                    write_u8(&mut head, INVOKE_DYNAMIC);
+                    write_u16(&mut head, self.bootstrap_indexes[&bootstrap] as u16);
+                    write_u16(&mut head, index(descriptor));
+                }
+                Entry::Signature(_) | Entry::BootstrapMethod(..) => unreachable!(),
+            }
+        }
+
+        for &(offset, id, one_byte) in &self.fixups {
+            let i = match pool.entries[id] {
+                Entry::BootstrapMethod(..) => *self.bootstrap_indexes.get(&id).ok_or("unwritten bootstrap method")? as u16,
+                _ => index(id),
+            };
+
+            if one_byte {
+                self.bytes[offset] = i as u8;
+            } else {
+                self.bytes[offset] = (i >> 8) as u8;
+                self.bytes[offset + 1] = i as u8;
+            }
+        }
+
+        head.extend_from_slice(&self.bytes);
+
+        Ok(head)
+    }
+}
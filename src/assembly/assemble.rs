@@ -0,0 +1,1483 @@
+// Assembles a listing in the syntax written by the disassembler into a class
+// file. Constants may be written symbolically and are added to the pool as
+// needed; branch offsets, switch padding, `wide` prefixes and `ldc_w` are
+// worked out from the code.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use assembly::pool::*;
+use assembly::syntax::*;
+use bytecode::{self, OperandKind};
+use class_file::{Array, ClassFile};
+use class_file::attributes::AttributeInfo;
+use class_file::constant_pool::encode_modified_utf8;
+use class_file::descriptor::parse_method_descriptor;
+use class_file::fields::FieldInfo;
+use class_file::methods::MethodInfo;
+use write_util::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssembleError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+type Result<T> = ::std::result::Result<T, AssembleError>;
+
+fn error<T>(line: usize, message: String) -> Result<T> {
+    Err(AssembleError { line, message })
+}
+
+pub fn assemble(source: &str) -> Result<ClassFile> {
+    let mut lines = Vec::new();
+
+    for (i, text) in source.lines().enumerate() {
+        let tokens = tokenize(i + 1, text)?;
+
+        if !tokens.is_empty() {
+            lines.push(Line {
+                number: i + 1,
+                tokens,
+            });
+        }
+    }
+
+    let mut assembler = Assembler {
+        lines,
+        pos: 0,
+        pool: PoolBuilder::new(),
+    };
+
+    assembler.declare_constants()?;
+    assembler.class()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+    Bytes(Vec<u8>),
+}
+
+#[derive(Debug, Clone)]
+struct Line {
+    number: usize,
+    tokens: Vec<Token>,
+}
+
+fn tokenize(number: usize, text: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    loop {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+
+        match chars.peek() {
+            None | Some(&';') => break,
+            Some(&'"') => {
+                chars.next();
+                match read_string(&mut chars) {
+                    Some(s) => tokens.push(Token::Str(s)),
+                    None => return error(number, "malformed string literal".to_string()),
+                }
+                continue;
+            }
+            _ => {}
+        }
+
+        let mut word = String::new();
+
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() {
+                break;
+            }
+            chars.next();
+
+            if c == '"' && word == "b" {
+                word.clear();
+                break;
+            }
+            word.push(c);
+        }
+
+        if word.is_empty() {
+            match read_bytes(&mut chars) {
+                Some(bytes) => tokens.push(Token::Bytes(bytes)),
+                None => return error(number, "malformed byte string literal".to_string()),
+            }
+        } else {
+            tokens.push(Token::Word(word));
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn read_string<I: Iterator<Item = char>>(chars: &mut I) -> Option<String> {
+    let mut s = String::new();
+
+    loop {
+        match chars.next()? {
+            '"' => return Some(s),
+            '\\' => match chars.next()? {
+                'n' => s.push('\n'),
+                't' => s.push('\t'),
+                'r' => s.push('\r'),
+                'u' => {
+                    let hex: String = chars.take(4).collect();
+                    let code = u32::from_str_radix(&hex, 16).ok()?;
+                    s.push(::std::char::from_u32(code)?);
+                }
+                c => s.push(c),
+            },
+            c => s.push(c),
+        }
+    }
+}
+
+fn read_bytes<I: Iterator<Item = char>>(chars: &mut I) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+
+    loop {
+        match chars.next()? {
+            '"' => return Some(bytes),
+            '\\' => match chars.next()? {
+                'x' => {
+                    let hex: String = chars.take(2).collect();
+                    bytes.push(u8::from_str_radix(&hex, 16).ok()?);
+                }
+                c if c.is_ascii() => bytes.push(c as u8),
+                _ => return None,
+            },
+            c if c.is_ascii() => bytes.push(c as u8),
+            _ => return None,
+        }
+    }
+}
+
+struct Tokens<'a> {
+    line: usize,
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(line: &'a Line) -> Tokens<'a> {
+        Tokens {
+            line: line.number,
+            tokens: &line.tokens,
+            pos: 0,
+        }
+    }
+
+    fn error<T>(&self, message: String) -> Result<T> {
+        error(self.line, message)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.tokens.len()
+    }
+
+    fn remaining(&self) -> usize {
+        self.tokens.len().saturating_sub(self.pos)
+    }
+
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_word(&self) -> Option<&'a str> {
+        match self.peek() {
+            Some(Token::Word(w)) => Some(w),
+            _ => None,
+        }
+    }
+
+    fn next(&mut self) -> Result<&'a Token> {
+        match self.tokens.get(self.pos) {
+            Some(token) => {
+                self.pos += 1;
+                Ok(token)
+            }
+            None => self.error("unexpected end of line".to_string()),
+        }
+    }
+
+    fn word(&mut self) -> Result<&'a str> {
+        match *self.next()? {
+            Token::Word(ref w) => Ok(w),
+            ref other => self.error(format!("expected a word, found {:?}", other)),
+        }
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<()> {
+        let word = self.word()?;
+
+        if word == expected {
+            Ok(())
+        } else {
+            self.error(format!("expected '{}', found '{}'", expected, word))
+        }
+    }
+
+    fn parse<T: FromStr>(&mut self, what: &str) -> Result<T> {
+        let word = self.word()?;
+
+        match word.parse() {
+            Ok(value) => Ok(value),
+            Err(_) => self.error(format!("expected {}, found '{}'", what, word)),
+        }
+    }
+
+    fn end(&self) -> Result<()> {
+        match self.peek() {
+            None => Ok(()),
+            Some(token) => self.error(format!("unexpected {:?}", token)),
+        }
+    }
+
+    // `[n]`, a constant referred to by index.
+    fn index(&mut self) -> Option<u16> {
+        let index = self.peek_word().filter(|w| is_ref(w)).and_then(|w| w[1..w.len() - 1].parse().ok());
+
+        if index.is_some() {
+            self.pos += 1;
+        }
+
+        index
+    }
+
+    fn flags(&mut self, context: FlagContext, leave: usize) -> Result<u16> {
+        let mut flags = 0;
+
+        while self.remaining() > leave {
+            let word = self.word()?;
+
+            match parse_flag(word, context) {
+                Some(flag) => flags |= flag,
+                None => return self.error(format!("unknown access flag '{}'", word)),
+            }
+        }
+
+        Ok(flags)
+    }
+
+    fn utf8(&mut self) -> Result<Ref> {
+        if let Some(index) = self.index() {
+            return Ok(Ref::Index(index));
+        }
+
+        let bytes = match *self.next()? {
+            Token::Word(ref s) | Token::Str(ref s) => encode_modified_utf8(s),
+            Token::Bytes(ref bytes) => bytes.clone(),
+        };
+
+        Ok(spec(Constant::Utf8(bytes)))
+    }
+
+    fn class(&mut self) -> Result<Ref> {
+        if let Some(index) = self.index() {
+            return Ok(Ref::Index(index));
+        }
+
+        Ok(spec(Constant::Class(self.utf8()?)))
+    }
+
+    fn name_and_type(&mut self) -> Result<Ref> {
+        if let Some(index) = self.index() {
+            return Ok(Ref::Index(index));
+        }
+
+        Ok(spec(Constant::NameAndType(self.utf8()?, self.utf8()?)))
+    }
+
+    // A tagged constant specification or `[n]`.
+    fn reference(&mut self) -> Result<Ref> {
+        if let Some(index) = self.index() {
+            return Ok(Ref::Index(index));
+        }
+
+        Ok(spec(self.constant()?))
+    }
+
+    fn constant(&mut self) -> Result<Constant> {
+        let tag = self.word()?;
+
+        Ok(match tag {
+            "Utf8" => match *self.next()? {
+                Token::Word(ref s) | Token::Str(ref s) => Constant::Utf8(encode_modified_utf8(s)),
+                Token::Bytes(ref bytes) => Constant::Utf8(bytes.clone()),
+            },
+            "Int" => Constant::Integer(self.parse("an int")?),
+            "Float" => Constant::Float(self.float()?),
+            "Long" => Constant::Long(self.parse("a long")?),
+            "Double" => Constant::Double(self.double()?),
+            "Class" => Constant::Class(self.utf8()?),
+            "String" => Constant::String(self.utf8()?),
+            "Field" => Constant::Fieldref(self.class()?, self.name_and_type()?),
+            "Method" => Constant::Methodref(self.class()?, self.name_and_type()?),
+            "InterfaceMethod" => Constant::InterfaceMethodref(self.class()?, self.name_and_type()?),
+            "NameAndType" => Constant::NameAndType(self.utf8()?, self.utf8()?),
+            "MethodHandle" => {
+                let word = self.word()?;
+                let kind = match parse_reference_kind(word) {
+                    Some(kind) => kind,
+                    None => return self.error(format!("unknown reference kind '{}'", word)),
+                };
+                Constant::MethodHandle(kind, self.reference()?)
+            }
+            "MethodType" => Constant::MethodType(self.utf8()?),
+            "Dynamic" => Constant::Dynamic(self.parse("a bootstrap method index")?, self.name_and_type()?),
+            "InvokeDynamic" => {
+                Constant::InvokeDynamic(self.parse("a bootstrap method index")?, self.name_and_type()?)
+            }
+            "Module" => Constant::Module(self.utf8()?),
+            "Package" => Constant::Package(self.utf8()?),
+            _ => return self.error(format!("unknown constant type '{}'", tag)),
+        })
+    }
+
+    // Floats are written as decimals, or as raw bits in hex.
+    fn float(&mut self) -> Result<u32> {
+        let word = self.word()?;
+
+        let bits = match word.strip_prefix("0x") {
+            Some(hex) => u32::from_str_radix(hex, 16).ok(),
+            None => word.parse::<f32>().ok().map(f32::to_bits),
+        };
+
+        match bits {
+            Some(bits) => Ok(bits),
+            None => self.error(format!("expected a float, found '{}'", word)),
+        }
+    }
+
+    fn double(&mut self) -> Result<u64> {
+        let word = self.word()?;
+
+        let bits = match word.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16).ok(),
+            None => word.parse::<f64>().ok().map(f64::to_bits),
+        };
+
+        match bits {
+            Some(bits) => Ok(bits),
+            None => self.error(format!("expected a double, found '{}'", word)),
+        }
+    }
+
+    // The operand of ldc, ldc_w and ldc2_w, which may also be a bare number
+    // or string literal.
+    fn loadable(&mut self, wide: bool) -> Result<Ref> {
+        match self.peek() {
+            Some(Token::Str(s)) => {
+                self.pos += 1;
+                return Ok(spec(Constant::String(spec(Constant::Utf8(encode_modified_utf8(s))))));
+            }
+            Some(Token::Word(w)) if w.starts_with(|c: char| c.is_ascii_digit() || c == '-') => {
+                let constant = match (wide, w.parse::<i64>()) {
+                    (true, Ok(value)) => Constant::Long(value),
+                    (true, Err(_)) => Constant::Double(self.double()?),
+                    (false, Ok(value)) if value >= i32::MIN as i64 && value <= i32::MAX as i64 => {
+                        Constant::Integer(value as i32)
+                    }
+                    (false, _) => Constant::Float(self.float()?),
+                };
+
+                if let Constant::Long(_) | Constant::Integer(_) = constant {
+                    self.pos += 1;
+                }
+                return Ok(spec(constant));
+            }
+            _ => {}
+        }
+
+        self.reference()
+    }
+}
+
+fn spec(constant: Constant) -> Ref {
+    Ref::Spec(Box::new(constant))
+}
+
+#[derive(Debug)]
+enum Operand {
+    None,
+    Local(u16),
+    Int(i32),
+    Constant(u16),
+    Label(String),
+    Iinc(u16, i16),
+    InvokeInterface(u16, u8),
+    InvokeDynamic(u16),
+    MultiANewArray(u16, u8),
+    NewArray(u8),
+    TableSwitch {
+        low: i32,
+        targets: Vec<String>,
+        default: String,
+    },
+    LookupSwitch {
+        pairs: Vec<(i32, String)>,
+        default: String,
+    },
+}
+
+#[derive(Debug)]
+struct Instruction {
+    line: usize,
+    offset: u32,
+    opcode: u8,
+    kind: OperandKind,
+    wide: bool,
+    operand: Operand,
+}
+
+impl Instruction {
+    fn size(&self) -> u32 {
+        let padding = 3 - self.offset % 4;
+
+        match self.operand {
+            Operand::TableSwitch { ref targets, .. } => 1 + padding + 12 + 4 * targets.len() as u32,
+            Operand::LookupSwitch { ref pairs, .. } => 1 + padding + 8 + 8 * pairs.len() as u32,
+            Operand::Iinc(..) if self.wide => 6,
+            _ if self.wide => 4,
+            _ => match self.kind {
+                OperandKind::None | OperandKind::Wide => 1,
+                OperandKind::Local | OperandKind::Byte | OperandKind::ConstantU8 | OperandKind::NewArray => 2,
+                OperandKind::Short | OperandKind::Constant | OperandKind::Branch | OperandKind::Iinc => 3,
+                OperandKind::MultiANewArray => 4,
+                _ => 5,
+            },
+        }
+    }
+}
+
+#[derive(Debug)]
+enum FrameStyle {
+    Same,
+    SameExtended,
+    SameLocals1StackItem,
+    SameLocals1StackItemExtended,
+    Chop(u8),
+    Append,
+    Full,
+}
+
+#[derive(Debug)]
+enum VerificationType {
+    // Any of the types without data, by tag.
+    Simple(u8),
+    Object(u16),
+    Uninitialized(String),
+}
+
+#[derive(Debug)]
+struct Frame {
+    line: usize,
+    offset: u32,
+    style: FrameStyle,
+    locals: Vec<VerificationType>,
+    stack: Vec<VerificationType>,
+}
+
+enum CodeAttribute {
+    LineNumbers(u16, Vec<(usize, String, u16)>),
+    LocalVariables(u16, Vec<(usize, u16, u16, u16, String, String)>),
+    StackMap(u16),
+    Raw(AttributeInfo),
+}
+
+#[derive(Default)]
+struct Code {
+    offset: u32,
+    labels: HashMap<String, u32>,
+    instructions: Vec<Instruction>,
+    exception_table: Vec<(usize, String, String, String, u16)>,
+    frames: Vec<Frame>,
+    attributes: Vec<CodeAttribute>,
+}
+
+impl Code {
+    fn label(&self, line: usize, name: &str) -> Result<u32> {
+        match self.labels.get(name) {
+            Some(&offset) => Ok(offset),
+            None => error(line, format!("undefined label '{}'", name)),
+        }
+    }
+
+    fn label_u16(&self, line: usize, name: &str) -> Result<u16> {
+        let offset = self.label(line, name)?;
+
+        if offset > 0xffff {
+            return error(line, format!("label '{}' is out of range", name));
+        }
+
+        Ok(offset as u16)
+    }
+}
+
+struct Assembler {
+    lines: Vec<Line>,
+    pos: usize,
+    pool: PoolBuilder,
+}
+
+fn is_const(line: &Line) -> bool {
+    line.tokens.first() == Some(&Token::Word(".const".to_string()))
+}
+
+impl Assembler {
+    // Lines other than constant declarations, which are handled up front.
+    fn next_line(&mut self) -> Option<Line> {
+        while self.pos < self.lines.len() {
+            self.pos += 1;
+
+            if !is_const(&self.lines[self.pos - 1]) {
+                return Some(self.lines[self.pos - 1].clone());
+            }
+        }
+
+        None
+    }
+
+    fn block_line(&mut self, block: &str) -> Result<Option<Line>> {
+        let last = self.lines.last().map_or(0, |l| l.number);
+
+        let line = match self.next_line() {
+            Some(line) => line,
+            None => return error(last, format!("missing .end {}", block)),
+        };
+
+        let end = [Token::Word(".end".to_string()), Token::Word(block.to_string())];
+        if line.tokens == end {
+            Ok(None)
+        } else {
+            Ok(Some(line))
+        }
+    }
+
+    fn resolve(&mut self, line: usize, r: &Ref) -> Result<u16> {
+        match self.pool.resolve(r) {
+            Ok(index) => Ok(index),
+            Err(message) => error(line, message),
+        }
+    }
+
+    fn utf8(&mut self, line: usize, s: &str) -> Result<u16> {
+        self.resolve(line, &spec(Constant::Utf8(encode_modified_utf8(s))))
+    }
+
+    fn declare_constants(&mut self) -> Result<()> {
+        for line in &self.lines {
+            if !is_const(line) {
+                continue;
+            }
+
+            let mut t = Tokens::new(line);
+            t.word()?;
+
+            let index = match t.index() {
+                Some(index) => index,
+                None => return t.error("expected a constant pool index".to_string()),
+            };
+            t.expect("=")?;
+            let constant = t.constant()?;
+            t.end()?;
+
+            if let Err(message) = self.pool.declare(index, constant) {
+                return t.error(message);
+            }
+        }
+
+        self.pool.finish_declarations();
+        Ok(())
+    }
+
+    fn class(&mut self) -> Result<ClassFile> {
+        let mut cf = ClassFile {
+            magic: 0xCAFEBABE,
+            major_version: 52,
+            ..Default::default()
+        };
+
+        let mut this_class = None;
+        let mut super_class = None;
+        let mut fields = Vec::new();
+        let mut methods = Vec::new();
+        let mut attributes = Vec::new();
+
+        while let Some(line) = self.next_line() {
+            let mut t = Tokens::new(&line);
+            let directive = t.word()?;
+
+            match directive {
+                ".version" => {
+                    cf.major_version = t.parse("a major version")?;
+                    cf.minor_version = t.parse("a minor version")?;
+                }
+                ".class" => {
+                    cf.access_flags = t.flags(FlagContext::Class, 1)?;
+                    let class = t.class()?;
+                    this_class = Some(self.resolve(line.number, &class)?);
+                }
+                ".super" => {
+                    let class = t.class()?;
+                    super_class = Some(self.resolve(line.number, &class)?);
+                }
+                ".implements" => {
+                    let class = t.class()?;
+                    cf.interfaces.push(self.resolve(line.number, &class)?);
+                }
+                ".field" => {
+                    fields.push(self.field(&mut t)?);
+                    continue;
+                }
+                ".method" => {
+                    methods.push(self.method(&mut t)?);
+                    continue;
+                }
+                ".end" => {
+                    t.expect("class")?;
+                    t.end()?;
+                    break;
+                }
+                _ => match self.attribute(directive, &mut t)? {
+                    Some(attribute) => attributes.push(attribute),
+                    None => return t.error(format!("unknown directive '{}'", directive)),
+                },
+            }
+
+            t.end()?;
+        }
+
+        cf.this_class = match this_class {
+            Some(index) => index,
+            None => return error(1, "missing .class directive".to_string()),
+        };
+        cf.super_class = match super_class {
+            Some(index) => index,
+            None => self.resolve(1, &spec(Constant::Class(spec(Constant::Utf8(b"java/lang/Object".to_vec())))))?,
+        };
+
+        cf.interfaces_count = cf.interfaces.len() as u16;
+        cf.fields_count = fields.len() as u16;
+        cf.fields = Array { array: fields };
+        cf.methods_count = methods.len() as u16;
+        cf.methods = Array { array: methods };
+        cf.attributes_count = attributes.len() as u16;
+        cf.attributes = Array { array: attributes };
+
+        cf.constant_pool = match ::std::mem::take(&mut self.pool).build() {
+            Ok(constant_pool) => constant_pool,
+            Err(message) => return error(self.lines.last().map_or(0, |l| l.number), message),
+        };
+        cf.constant_pool_count = cf.constant_pool.count();
+
+        Ok(cf)
+    }
+
+    // A field only has a body if an `.end field` follows before the next
+    // member or the end of the class.
+    fn field_has_body(&self) -> bool {
+        for line in &self.lines[self.pos..] {
+            let words: Vec<&str> = line
+                .tokens
+                .iter()
+                .take(2)
+                .filter_map(|t| match *t {
+                    Token::Word(ref w) => Some(w.as_str()),
+                    _ => None,
+                })
+                .collect();
+
+            match words.as_slice() {
+                [".end", "field"] => return true,
+                [".field", ..] | [".method", ..] | [".end", ..] => return false,
+                _ => {}
+            }
+        }
+
+        false
+    }
+
+    fn field(&mut self, t: &mut Tokens) -> Result<FieldInfo> {
+        let access_flags = t.flags(FlagContext::Field, 2)?;
+        let name = t.utf8()?;
+        let descriptor = t.utf8()?;
+        t.end()?;
+
+        let name_index = self.resolve(t.line, &name)?;
+        let descriptor_index = self.resolve(t.line, &descriptor)?;
+
+        let attributes = if self.field_has_body() {
+            self.member_attributes("field")?
+        } else {
+            Vec::new()
+        };
+
+        Ok(FieldInfo {
+            access_flags,
+            name_index,
+            descriptor_index,
+            attributes_count: attributes.len() as u16,
+            attributes: Array { array: attributes },
+        })
+    }
+
+    fn method(&mut self, t: &mut Tokens) -> Result<MethodInfo> {
+        let access_flags = t.flags(FlagContext::Method, 3)?;
+        let name = t.utf8()?;
+        t.expect(":")?;
+        let descriptor = t.utf8()?;
+        t.end()?;
+
+        let name_index = self.resolve(t.line, &name)?;
+        let descriptor_index = self.resolve(t.line, &descriptor)?;
+        let attributes = self.member_attributes("method")?;
+
+        Ok(MethodInfo {
+            access_flags,
+            name_index,
+            descriptor_index,
+            attributes_count: attributes.len() as u16,
+            attributes: Array { array: attributes },
+        })
+    }
+
+    fn member_attributes(&mut self, block: &str) -> Result<Vec<AttributeInfo>> {
+        let mut attributes = Vec::new();
+
+        while let Some(line) = self.block_line(block)? {
+            let mut t = Tokens::new(&line);
+            let directive = t.word()?;
+
+            match self.attribute(directive, &mut t)? {
+                Some(attribute) => attributes.push(attribute),
+                None => return t.error(format!("unknown directive '{}'", directive)),
+            }
+            t.end()?;
+        }
+
+        Ok(attributes)
+    }
+
+    // Parses an attribute directive, or returns None if `directive` is not one.
+    fn attribute(&mut self, directive: &str, t: &mut Tokens) -> Result<Option<AttributeInfo>> {
+        let name = match directive {
+            ".sourcefile" => "SourceFile",
+            ".signature" => "Signature",
+            ".deprecated" => "Deprecated",
+            ".synthetic" => "Synthetic",
+            ".constantvalue" => "ConstantValue",
+            ".exceptions" => "Exceptions",
+            ".nesthost" => "NestHost",
+            ".nestmembers" => "NestMembers",
+            ".permittedsubclasses" => "PermittedSubclasses",
+            ".enclosing" => "EnclosingMethod",
+            ".innerclasses" => "InnerClasses",
+            ".bootstrapmethods" => "BootstrapMethods",
+            ".code" => "Code",
+            ".attribute" => return self.raw_attribute(t).map(Some),
+            _ => return Ok(None),
+        };
+
+        let line = t.line;
+        let attribute_name_index = self.utf8(line, name)?;
+        let mut info = Vec::new();
+
+        match directive {
+            ".sourcefile" | ".signature" => {
+                let value = t.utf8()?;
+                write_u16(&mut info, self.resolve(line, &value)?);
+            }
+            ".constantvalue" => {
+                let value = t.reference()?;
+                write_u16(&mut info, self.resolve(line, &value)?);
+            }
+            ".nesthost" => {
+                let class = t.class()?;
+                write_u16(&mut info, self.resolve(line, &class)?);
+            }
+            ".exceptions" | ".nestmembers" | ".permittedsubclasses" => {
+                let mut classes = Vec::new();
+                while !t.is_empty() {
+                    let class = t.class()?;
+                    classes.push(self.resolve(line, &class)?);
+                }
+                write_u16(&mut info, classes.len() as u16);
+                write_vec_u16(&mut info, &classes);
+            }
+            ".enclosing" => {
+                t.expect("method")?;
+                let class = t.class()?;
+                let method = t.name_and_type()?;
+                write_u16(&mut info, self.resolve(line, &class)?);
+                write_u16(&mut info, self.resolve(line, &method)?);
+            }
+            ".innerclasses" => {
+                t.end()?;
+                info = self.inner_classes()?;
+            }
+            ".bootstrapmethods" => {
+                t.end()?;
+                info = self.bootstrap_methods()?;
+            }
+            ".code" => info = self.code(t)?,
+            _ => {}
+        }
+
+        Ok(Some(AttributeInfo {
+            attribute_name_index,
+            attribute_length: info.len() as u32,
+            info,
+        }))
+    }
+
+    fn raw_attribute(&mut self, t: &mut Tokens) -> Result<AttributeInfo> {
+        let name = t.utf8()?;
+        let attribute_name_index = self.resolve(t.line, &name)?;
+
+        let info = match *t.next()? {
+            Token::Bytes(ref bytes) => bytes.clone(),
+            ref other => return t.error(format!("expected a byte string, found {:?}", other)),
+        };
+
+        Ok(AttributeInfo {
+            attribute_name_index,
+            attribute_length: info.len() as u32,
+            info,
+        })
+    }
+
+    fn inner_classes(&mut self) -> Result<Vec<u8>> {
+        let mut entries = Vec::new();
+
+        while let Some(line) = self.block_line("innerclasses")? {
+            let mut t = Tokens::new(&line);
+            let inner = t.class()?;
+            let outer = t.class()?;
+            let name = t.utf8()?;
+            let flags = t.flags(FlagContext::InnerClass, 0)?;
+
+            write_u16(&mut entries, self.resolve(line.number, &inner)?);
+            write_u16(&mut entries, self.resolve(line.number, &outer)?);
+            write_u16(&mut entries, self.resolve(line.number, &name)?);
+            write_u16(&mut entries, flags);
+        }
+
+        let mut info = Vec::new();
+        write_u16(&mut info, (entries.len() / 8) as u16);
+        info.extend(entries);
+        Ok(info)
+    }
+
+    fn bootstrap_methods(&mut self) -> Result<Vec<u8>> {
+        let mut count = 0;
+        let mut entries = Vec::new();
+
+        while let Some(line) = self.block_line("bootstrapmethods")? {
+            let mut t = Tokens::new(&line);
+            let method = t.reference()?;
+            t.expect(":")?;
+
+            let mut arguments = Vec::new();
+            while !t.is_empty() {
+                arguments.push(t.reference()?);
+            }
+
+            write_u16(&mut entries, self.resolve(line.number, &method)?);
+            write_u16(&mut entries, arguments.len() as u16);
+            for argument in &arguments {
+                write_u16(&mut entries, self.resolve(line.number, argument)?);
+            }
+            count += 1;
+        }
+
+        let mut info = Vec::new();
+        write_u16(&mut info, count);
+        info.extend(entries);
+        Ok(info)
+    }
+
+    fn code(&mut self, t: &mut Tokens) -> Result<Vec<u8>> {
+        t.expect("stack")?;
+        let max_stack: u16 = t.parse("a stack size")?;
+        t.expect("locals")?;
+        let max_locals: u16 = t.parse("a local variable count")?;
+
+        let mut code = Code::default();
+
+        while let Some(line) = self.block_line("code")? {
+            let mut t = Tokens::new(&line);
+
+            if let Some(label) = t.peek_word().filter(|w| w.len() > 1 && w.ends_with(':')) {
+                t.pos += 1;
+
+                let name = label[..label.len() - 1].to_string();
+                if code.labels.insert(name, code.offset).is_some() {
+                    return t.error(format!("label '{}' is defined twice", label));
+                }
+                if t.is_empty() {
+                    continue;
+                }
+            }
+
+            let word = t.word()?;
+
+            match word {
+                ".catch" => {
+                    let catch_type = match t.peek_word() {
+                        Some("any") => {
+                            t.pos += 1;
+                            0
+                        }
+                        _ => {
+                            let class = t.class()?;
+                            self.resolve(line.number, &class)?
+                        }
+                    };
+                    t.expect("from")?;
+                    let start = t.word()?.to_string();
+                    t.expect("to")?;
+                    let end = t.word()?.to_string();
+                    t.expect("using")?;
+                    let handler = t.word()?.to_string();
+
+                    code.exception_table.push((line.number, start, end, handler, catch_type));
+                }
+                ".stack" => {
+                    let frame = self.frame(&mut t, code.offset)?;
+                    code.frames.push(frame);
+                }
+                ".linenumbertable" => {
+                    t.end()?;
+                    let name_index = self.utf8(line.number, "LineNumberTable")?;
+                    let mut table = Vec::new();
+
+                    while let Some(line) = self.block_line("linenumbertable")? {
+                        let mut t = Tokens::new(&line);
+                        let start = t.word()?.to_string();
+                        table.push((line.number, start, t.parse("a line number")?));
+                        t.end()?;
+                    }
+
+                    code.attributes.push(CodeAttribute::LineNumbers(name_index, table));
+                }
+                ".localvariabletable" | ".localvariabletypetable" => {
+                    t.end()?;
+                    let block = &word[1..];
+                    let name = if block == "localvariabletable" {
+                        "LocalVariableTable"
+                    } else {
+                        "LocalVariableTypeTable"
+                    };
+                    let name_index = self.utf8(line.number, name)?;
+                    let mut table = Vec::new();
+
+                    while let Some(line) = self.block_line(block)? {
+                        let mut t = Tokens::new(&line);
+                        let index = t.parse("a local variable index")?;
+                        t.expect("is")?;
+                        let name = t.utf8()?;
+                        let descriptor = t.utf8()?;
+                        t.expect("from")?;
+                        let start = t.word()?.to_string();
+                        t.expect("to")?;
+                        let end = t.word()?.to_string();
+                        t.end()?;
+
+                        let name = self.resolve(line.number, &name)?;
+                        let descriptor = self.resolve(line.number, &descriptor)?;
+                        table.push((line.number, index, name, descriptor, start, end));
+                    }
+
+                    code.attributes.push(CodeAttribute::LocalVariables(name_index, table));
+                }
+                ".stackmaptable" => {
+                    let name_index = self.utf8(line.number, "StackMapTable")?;
+                    code.attributes.push(CodeAttribute::StackMap(name_index));
+                }
+                ".attribute" => {
+                    let attribute = self.raw_attribute(&mut t)?;
+                    code.attributes.push(CodeAttribute::Raw(attribute));
+                }
+                _ => {
+                    let instruction = self.instruction(word, &mut t, code.offset)?;
+                    code.offset += instruction.size();
+                    code.instructions.push(instruction);
+                }
+            }
+
+            t.end()?;
+        }
+
+        if !code.frames.is_empty() && !code.attributes.iter().any(|a| matches!(*a, CodeAttribute::StackMap(_))) {
+            let name_index = self.utf8(t.line, "StackMapTable")?;
+            code.attributes.push(CodeAttribute::StackMap(name_index));
+        }
+
+        encode_code(&code, max_stack, max_locals)
+    }
+
+    fn instruction(&mut self, mnemonic: &str, t: &mut Tokens, offset: u32) -> Result<Instruction> {
+        let line = t.line;
+        let mut wide = false;
+        let mut mnemonic = mnemonic;
+
+        if mnemonic == "wide" {
+            wide = true;
+            mnemonic = t.word()?;
+        }
+
+        let mut opcode = match bytecode::opcode(mnemonic) {
+            Some(opcode) if opcode != bytecode::WIDE => opcode,
+            _ => return t.error(format!("unknown instruction '{}'", mnemonic)),
+        };
+        let mut kind = bytecode::info(opcode).unwrap().1;
+
+        if wide && kind != OperandKind::Local && kind != OperandKind::Iinc {
+            return t.error(format!("{} cannot be widened", mnemonic));
+        }
+
+        let operand = match kind {
+            OperandKind::None => Operand::None,
+            OperandKind::Local => {
+                let index: u16 = t.parse("a local variable index")?;
+                wide |= index > 0xff;
+                Operand::Local(index)
+            }
+            OperandKind::Byte => Operand::Int(t.parse::<i8>("a byte")? as i32),
+            OperandKind::Short => Operand::Int(t.parse::<i16>("a short")? as i32),
+            OperandKind::ConstantU8 | OperandKind::Constant => {
+                let r = match opcode {
+                    // ldc, ldc_w and ldc2_w
+                    0x12..=0x14 => t.loadable(opcode == 0x14)?,
+                    // new, anewarray, checkcast and instanceof
+                    0xbb | 0xbd | 0xc0 | 0xc1 => t.class()?,
+                    _ => t.reference()?,
+                };
+                let index = self.resolve(line, &r)?;
+
+                if kind == OperandKind::ConstantU8 && index > 0xff {
+                    opcode = 0x13;
+                    kind = OperandKind::Constant;
+                }
+                Operand::Constant(index)
+            }
+            OperandKind::Branch | OperandKind::BranchWide => Operand::Label(t.word()?.to_string()),
+            OperandKind::Iinc => {
+                let index: u16 = t.parse("a local variable index")?;
+                let value: i16 = t.parse("an increment")?;
+                wide |= index > 0xff || value < i8::MIN as i16 || value > i8::MAX as i16;
+                Operand::Iinc(index, value)
+            }
+            OperandKind::InvokeInterface => {
+                let r = t.reference()?;
+                let count = if t.is_empty() {
+                    match interface_argument_count(&r) {
+                        Some(count) => count,
+                        None => return t.error("invokeinterface needs an argument count".to_string()),
+                    }
+                } else {
+                    t.parse("an argument count")?
+                };
+                Operand::InvokeInterface(self.resolve(line, &r)?, count)
+            }
+            OperandKind::InvokeDynamic => {
+                let r = t.reference()?;
+                Operand::InvokeDynamic(self.resolve(line, &r)?)
+            }
+            OperandKind::MultiANewArray => {
+                let class = t.class()?;
+                let index = self.resolve(line, &class)?;
+                Operand::MultiANewArray(index, t.parse("a dimension count")?)
+            }
+            OperandKind::NewArray => {
+                let word = t.word()?;
+                match bytecode::array_type_code(word).or_else(|| word.parse().ok()) {
+                    Some(atype) => Operand::NewArray(atype),
+                    None => return t.error(format!("unknown array type '{}'", word)),
+                }
+            }
+            OperandKind::TableSwitch => {
+                let low = t.parse("a low index")?;
+                let mut targets = Vec::new();
+
+                let default = loop {
+                    let (key, target) = self.switch_case(line)?;
+                    match key {
+                        None => targets.push(target),
+                        Some(ref k) if k == "default" => break target,
+                        Some(k) => return error(line, format!("unexpected '{} :' in tableswitch", k)),
+                    }
+                };
+
+                if targets.is_empty() {
+                    return t.error("tableswitch needs at least one target".to_string());
+                }
+                Operand::TableSwitch { low, targets, default }
+            }
+            OperandKind::LookupSwitch => {
+                let mut pairs = Vec::new();
+
+                let default = loop {
+                    match self.switch_case(line)? {
+                        (Some(ref k), target) if k == "default" => break target,
+                        (Some(k), target) => match k.parse() {
+                            Ok(key) => pairs.push((key, target)),
+                            Err(_) => return error(line, format!("bad lookupswitch key '{}'", k)),
+                        },
+                        (None, _) => return error(line, "lookupswitch cases need a key".to_string()),
+                    }
+                };
+
+                Operand::LookupSwitch { pairs, default }
+            }
+            OperandKind::Wide => unreachable!(),
+        };
+
+        Ok(Instruction {
+            line,
+            offset,
+            opcode,
+            kind,
+            wide,
+            operand,
+        })
+    }
+
+    // A `label`, `key : label` or `default : label` line of a switch.
+    fn switch_case(&mut self, line: usize) -> Result<(Option<String>, String)> {
+        let case = match self.next_line() {
+            Some(case) => case,
+            None => return error(line, "missing switch default".to_string()),
+        };
+        let mut t = Tokens::new(&case);
+
+        let result = if t.remaining() == 3 {
+            let key = t.word()?.to_string();
+            t.expect(":")?;
+            (Some(key), t.word()?.to_string())
+        } else {
+            (None, t.word()?.to_string())
+        };
+
+        t.end()?;
+        Ok(result)
+    }
+
+    fn frame(&mut self, t: &mut Tokens, offset: u32) -> Result<Frame> {
+        let mut frame = Frame {
+            line: t.line,
+            offset,
+            style: FrameStyle::Same,
+            locals: Vec::new(),
+            stack: Vec::new(),
+        };
+
+        match t.word()? {
+            "same" => {}
+            "same_extended" => frame.style = FrameStyle::SameExtended,
+            "same_locals_1_stack_item" => {
+                frame.style = FrameStyle::SameLocals1StackItem;
+                frame.stack.push(self.verification_type(t)?);
+            }
+            "same_locals_1_stack_item_extended" => {
+                frame.style = FrameStyle::SameLocals1StackItemExtended;
+                frame.stack.push(self.verification_type(t)?);
+            }
+            "chop" => match t.parse("a local count")? {
+                count @ 1..=3 => frame.style = FrameStyle::Chop(count),
+                count => return t.error(format!("cannot chop {} locals", count)),
+            },
+            "append" => {
+                frame.style = FrameStyle::Append;
+                while !t.is_empty() {
+                    frame.locals.push(self.verification_type(t)?);
+                }
+                if frame.locals.is_empty() || frame.locals.len() > 3 {
+                    return t.error("append takes one to three locals".to_string());
+                }
+            }
+            "full" => {
+                frame.style = FrameStyle::Full;
+                t.expect("locals")?;
+                while t.peek_word() != Some("stack") {
+                    frame.locals.push(self.verification_type(t)?);
+                }
+                t.expect("stack")?;
+                while !t.is_empty() {
+                    frame.stack.push(self.verification_type(t)?);
+                }
+            }
+            other => return t.error(format!("unknown frame type '{}'", other)),
+        }
+
+        Ok(frame)
+    }
+
+    fn verification_type(&mut self, t: &mut Tokens) -> Result<VerificationType> {
+        let word = t.word()?;
+
+        Ok(match word {
+            "Top" => VerificationType::Simple(0),
+            "Integer" => VerificationType::Simple(1),
+            "Float" => VerificationType::Simple(2),
+            "Double" => VerificationType::Simple(3),
+            "Long" => VerificationType::Simple(4),
+            "Null" => VerificationType::Simple(5),
+            "UninitializedThis" => VerificationType::Simple(6),
+            "Object" => {
+                let class = t.class()?;
+                VerificationType::Object(self.resolve(t.line, &class)?)
+            }
+            "Uninitialized" => VerificationType::Uninitialized(t.word()?.to_string()),
+            _ => return t.error(format!("unknown verification type '{}'", word)),
+        })
+    }
+}
+
+// The argument count of an invokeinterface, in slots, including the receiver.
+fn interface_argument_count(r: &Ref) -> Option<u8> {
+    let name_and_type = match *r {
+        Ref::Spec(ref constant) => match **constant {
+            Constant::InterfaceMethodref(_, Ref::Spec(ref name_and_type)) => name_and_type,
+            _ => return None,
+        },
+        Ref::Index(_) => return None,
+    };
+
+    let descriptor = match **name_and_type {
+        Constant::NameAndType(_, Ref::Spec(ref descriptor)) => match **descriptor {
+            Constant::Utf8(ref bytes) => String::from_utf8(bytes.clone()).ok()?,
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    Some(parse_method_descriptor(&descriptor)?.parameter_slots() as u8 + 1)
+}
+
+fn encode_code(code: &Code, max_stack: u16, max_locals: u16) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+
+    for insn in &code.instructions {
+        encode_instruction(code, insn, &mut bytes)?;
+    }
+
+    let mut info = Vec::new();
+    write_u16(&mut info, max_stack);
+    write_u16(&mut info, max_locals);
+    write_u32(&mut info, bytes.len() as u32);
+    info.extend(bytes);
+
+    write_u16(&mut info, code.exception_table.len() as u16);
+    for &(line, ref start, ref end, ref handler, catch_type) in &code.exception_table {
+        write_u16(&mut info, code.label_u16(line, start)?);
+        write_u16(&mut info, code.label_u16(line, end)?);
+        write_u16(&mut info, code.label_u16(line, handler)?);
+        write_u16(&mut info, catch_type);
+    }
+
+    write_u16(&mut info, code.attributes.len() as u16);
+    for attribute in &code.attributes {
+        let (name_index, data) = match *attribute {
+            CodeAttribute::LineNumbers(name_index, ref table) => {
+                let mut data = Vec::new();
+                write_u16(&mut data, table.len() as u16);
+                for &(line, ref start, line_number) in table {
+                    write_u16(&mut data, code.label_u16(line, start)?);
+                    write_u16(&mut data, line_number);
+                }
+                (name_index, data)
+            }
+            CodeAttribute::LocalVariables(name_index, ref table) => {
+                let mut data = Vec::new();
+                write_u16(&mut data, table.len() as u16);
+                for &(line, index, name, descriptor, ref start, ref end) in table {
+                    let start = code.label_u16(line, start)?;
+                    let end = code.label_u16(line, end)?;
+                    if end < start {
+                        return error(line, "local variable range ends before it starts".to_string());
+                    }
+
+                    write_u16(&mut data, start);
+                    write_u16(&mut data, end - start);
+                    write_u16(&mut data, name);
+                    write_u16(&mut data, descriptor);
+                    write_u16(&mut data, index);
+                }
+                (name_index, data)
+            }
+            CodeAttribute::StackMap(name_index) => (name_index, encode_stack_map_table(code)?),
+            CodeAttribute::Raw(ref attribute) => (attribute.attribute_name_index, attribute.info.clone()),
+        };
+
+        write_u16(&mut info, name_index);
+        write_u32(&mut info, data.len() as u32);
+        info.extend(data);
+    }
+
+    Ok(info)
+}
+
+fn encode_instruction(code: &Code, insn: &Instruction, out: &mut Vec<u8>) -> Result<()> {
+    let branch = |label: &str, wide: bool| -> Result<i32> {
+        let delta = code.label(insn.line, label)? as i64 - insn.offset as i64;
+
+        if !wide && (delta < i16::MIN as i64 || delta > i16::MAX as i64) {
+            return error(insn.line, format!("branch to '{}' is out of range", label));
+        }
+
+        Ok(delta as i32)
+    };
+
+    if insn.wide {
+        write_u8(out, bytecode::WIDE);
+    }
+    write_u8(out, insn.opcode);
+
+    match insn.operand {
+        Operand::None => {}
+        Operand::Local(index) if insn.wide => write_u16(out, index),
+        Operand::Local(index) => write_u8(out, index as u8),
+        Operand::Int(value) if insn.kind == OperandKind::Byte => write_u8(out, value as u8),
+        Operand::Int(value) => write_u16(out, value as u16),
+        Operand::Constant(index) if insn.opcode == 0x12 => write_u8(out, index as u8),
+        Operand::Constant(index) => write_u16(out, index),
+        Operand::Label(ref label) if insn.kind == OperandKind::BranchWide => {
+            write_u32(out, branch(label, true)? as u32)
+        }
+        Operand::Label(ref label) => write_u16(out, branch(label, false)? as u16),
+        Operand::Iinc(index, value) if insn.wide => {
+            write_u16(out, index);
+            write_u16(out, value as u16);
+        }
+        Operand::Iinc(index, value) => {
+            write_u8(out, index as u8);
+            write_u8(out, value as u8);
+        }
+        Operand::InvokeInterface(index, count) => {
+            write_u16(out, index);
+            write_u8(out, count);
+            write_u8(out, 0);
+        }
+        Operand::InvokeDynamic(index) => {
+            write_u16(out, index);
+            write_u16(out, 0);
+        }
+        Operand::MultiANewArray(index, dimensions) => {
+            write_u16(out, index);
+            write_u8(out, dimensions);
+        }
+        Operand::NewArray(atype) => write_u8(out, atype),
+        Operand::TableSwitch {
+            low,
+            ref targets,
+            ref default,
+        } => {
+            out.extend(vec![0; (3 - insn.offset % 4) as usize]);
+            write_u32(out, branch(default, true)? as u32);
+            write_u32(out, low as u32);
+            write_u32(out, (low as i64 + targets.len() as i64 - 1) as u32);
+            for target in targets {
+                write_u32(out, branch(target, true)? as u32);
+            }
+        }
+        Operand::LookupSwitch { ref pairs, ref default } => {
+            out.extend(vec![0; (3 - insn.offset % 4) as usize]);
+            write_u32(out, branch(default, true)? as u32);
+            write_u32(out, pairs.len() as u32);
+            for &(key, ref target) in pairs {
+                write_u32(out, key as u32);
+                write_u32(out, branch(target, true)? as u32);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn encode_stack_map_table(code: &Code) -> Result<Vec<u8>> {
+    let mut info = Vec::new();
+    let mut previous: Option<u32> = None;
+
+    write_u16(&mut info, code.frames.len() as u16);
+
+    for frame in &code.frames {
+        let delta = match previous {
+            None => frame.offset as i64,
+            Some(previous) => frame.offset as i64 - previous as i64 - 1,
+        };
+        if !(0..=0xffff).contains(&delta) {
+            return error(frame.line, "stack map frames must be at increasing offsets".to_string());
+        }
+        let delta = delta as u16;
+        previous = Some(frame.offset);
+
+        match frame.style {
+            FrameStyle::Same if delta < 64 => write_u8(&mut info, delta as u8),
+            FrameStyle::SameLocals1StackItem if delta < 64 => write_u8(&mut info, 64 + delta as u8),
+            FrameStyle::Same | FrameStyle::SameExtended => {
+                write_u8(&mut info, 251);
+                write_u16(&mut info, delta);
+            }
+            FrameStyle::SameLocals1StackItem | FrameStyle::SameLocals1StackItemExtended => {
+                write_u8(&mut info, 247);
+                write_u16(&mut info, delta);
+            }
+            FrameStyle::Chop(count) => {
+                write_u8(&mut info, 251 - count);
+                write_u16(&mut info, delta);
+            }
+            FrameStyle::Append => {
+                write_u8(&mut info, 251 + frame.locals.len() as u8);
+                write_u16(&mut info, delta);
+            }
+            FrameStyle::Full => {
+                write_u8(&mut info, 255);
+                write_u16(&mut info, delta);
+                write_u16(&mut info, frame.locals.len() as u16);
+            }
+        }
+
+        for t in &frame.locals {
+            encode_verification_type(code, frame.line, t, &mut info)?;
+        }
+        if let FrameStyle::Full = frame.style {
+            write_u16(&mut info, frame.stack.len() as u16);
+        }
+        for t in &frame.stack {
+            encode_verification_type(code, frame.line, t, &mut info)?;
+        }
+    }
+
+    Ok(info)
+}
+
+fn encode_verification_type(code: &Code, line: usize, t: &VerificationType, out: &mut Vec<u8>) -> Result<()> {
+    match *t {
+        VerificationType::Simple(tag) => write_u8(out, tag),
+        VerificationType::Object(index) => {
+            write_u8(out, 7);
+            write_u16(out, index);
+        }
+        VerificationType::Uninitialized(ref label) => {
+            write_u8(out, 8);
+            write_u16(out, code.label_u16(line, label)?);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,748 @@
+// Renders a class file as an assembly listing that the assembler can read
+// back into an identical class file.
+
+use std::collections::{BTreeSet, HashMap};
+
+use assembly::syntax::*;
+use bytecode::{self, Instruction, Operand};
+use class_file::ClassFile;
+use class_file::attributes::*;
+use class_file::constant_pool::*;
+use class_file::stack_map_table::*;
+
+const MEMBER_INDENT: usize = 4;
+const CODE_INDENT: usize = 8;
+
+pub fn disassemble(cf: &ClassFile) -> String {
+    let mut d = Disassembler::new(&cf.constant_pool);
+    d.class(cf);
+    d.out
+}
+
+struct Disassembler<'a> {
+    cp: &'a ConstantPool,
+
+    // The first pool index with each symbolic spec. Only that entry may be
+    // referred to symbolically, any other one by its index.
+    first: HashMap<String, u16>,
+
+    out: String,
+}
+
+// How a sub-attribute of Code is listed.
+enum CodeAttribute<'a> {
+    LineNumbers(Vec<LineNumber>),
+    LocalVariables(&'static str, Vec<LocalVariable>),
+    StackMap,
+    Raw(&'a AttributeInfo),
+}
+
+impl<'a> Disassembler<'a> {
+    fn new(cp: &'a ConstantPool) -> Disassembler<'a> {
+        let mut first = HashMap::new();
+
+        for (index, _) in cp.entries() {
+            if let Some(spec) = constant_spec(cp, index) {
+                first.entry(spec).or_insert(index);
+            }
+        }
+
+        Disassembler {
+            cp,
+            first,
+            out: String::new(),
+        }
+    }
+
+    fn line(&mut self, indent: usize, text: &str) {
+        for _ in 0..indent {
+            self.out.push(' ');
+        }
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    fn is_canonical(&self, index: u16) -> bool {
+        constant_spec(self.cp, index).is_some_and(|spec| self.first.get(&spec) == Some(&index))
+    }
+
+    // A constant of any kind, written as a tagged spec.
+    fn reference(&self, index: u16) -> String {
+        match constant_spec(self.cp, index) {
+            Some(spec) if self.first.get(&spec) == Some(&index) => spec,
+            _ => format!("[{}]", index),
+        }
+    }
+
+    fn class_ref(&self, index: u16) -> String {
+        match self.cp.get_as::<ClassInfo>(index) {
+            Some(info) if self.is_canonical(index) => utf8_word(self.cp, info.name_index).unwrap(),
+            _ => format!("[{}]", index),
+        }
+    }
+
+    fn utf8_ref(&self, index: u16) -> String {
+        match self.cp.tag(index) {
+            Some(CpTag::Utf8) if self.is_canonical(index) => utf8_word(self.cp, index).unwrap(),
+            _ => format!("[{}]", index),
+        }
+    }
+
+    fn name_and_type_ref(&self, index: u16) -> String {
+        match self.cp.get_as::<NameAndTypeInfo>(index) {
+            Some(info) if self.is_canonical(index) => format!(
+                "{} {}",
+                utf8_word(self.cp, info.name_index).unwrap(),
+                utf8_word(self.cp, info.descriptor_index).unwrap()
+            ),
+            _ => format!("[{}]", index),
+        }
+    }
+
+    fn class_refs(&self, indices: &[u16]) -> String {
+        indices.iter().map(|&i| self.class_ref(i)).collect::<Vec<_>>().join(" ")
+    }
+
+    // The name of an attribute, if it can be written as a directive. The name
+    // must be the entry the assembler would pick when creating the attribute.
+    fn attribute_name(&self, attr: &AttributeInfo) -> Option<String> {
+        match self.cp.tag(attr.attribute_name_index) {
+            Some(CpTag::Utf8) if self.is_canonical(attr.attribute_name_index) => {
+                attr.name(self.cp)
+            }
+            _ => None,
+        }
+    }
+
+    fn raw_attribute(&mut self, indent: usize, attr: &AttributeInfo) {
+        let text = format!(
+            ".attribute {} {}",
+            self.utf8_ref(attr.attribute_name_index),
+            quote_bytes(&attr.info)
+        );
+        self.line(indent, &text);
+    }
+
+    fn class(&mut self, cf: &ClassFile) {
+        let mut header = vec![".class".to_string()];
+        header.extend(format_flags(cf.access_flags, FlagContext::Class));
+        header.push(self.class_ref(cf.this_class));
+
+        self.line(0, &format!(".version {} {}", cf.major_version, cf.minor_version));
+        self.line(0, &header.join(" "));
+        self.line(0, &format!(".super {}", self.class_ref(cf.super_class)));
+
+        for &interface in &cf.interfaces {
+            let text = format!(".implements {}", self.class_ref(interface));
+            self.line(0, &text);
+        }
+
+        for field in &cf.fields.array {
+            let mut text = vec![".field".to_string()];
+            text.extend(format_flags(field.access_flags, FlagContext::Field));
+            text.push(self.utf8_ref(field.name_index));
+            text.push(self.utf8_ref(field.descriptor_index));
+
+            self.line(0, "");
+            self.line(0, &text.join(" "));
+
+            if !field.attributes.array.is_empty() {
+                self.member_attributes(&field.attributes);
+                self.line(0, ".end field");
+            }
+        }
+
+        for method in &cf.methods.array {
+            let mut text = vec![".method".to_string()];
+            text.extend(format_flags(method.access_flags, FlagContext::Method));
+            text.push(self.utf8_ref(method.name_index));
+            text.push(":".to_string());
+            text.push(self.utf8_ref(method.descriptor_index));
+
+            self.line(0, "");
+            self.line(0, &text.join(" "));
+            self.member_attributes(&method.attributes);
+            self.line(0, ".end method");
+        }
+
+        if !cf.attributes.array.is_empty() {
+            self.line(0, "");
+        }
+
+        for attr in &cf.attributes.array {
+            if !self.class_attribute(attr) && !self.common_attribute(0, attr) {
+                self.raw_attribute(0, attr);
+            }
+        }
+
+        self.line(0, "");
+        self.constants();
+        self.line(0, ".end class");
+    }
+
+    fn member_attributes(&mut self, attributes: &Attributes) {
+        for attr in &attributes.array {
+            if !self.member_attribute(attr) && !self.common_attribute(MEMBER_INDENT, attr) {
+                self.raw_attribute(MEMBER_INDENT, attr);
+            }
+        }
+    }
+
+    // Attributes valid on classes, fields and methods alike.
+    fn common_attribute(&mut self, indent: usize, attr: &AttributeInfo) -> bool {
+        let info = &attr.info;
+
+        let text = match self.attribute_name(attr).as_deref() {
+            Some("Signature") if info.len() == 2 => read_index(info).map(|index| format!(".signature {}", self.utf8_ref(index))),
+            Some("Deprecated") if info.is_empty() => Ok(".deprecated".to_string()),
+            Some("Synthetic") if info.is_empty() => Ok(".synthetic".to_string()),
+            _ => return false,
+        };
+
+        match text {
+            Ok(text) => self.line(indent, &text),
+            Err(_) => return false,
+        }
+        true
+    }
+
+    fn member_attribute(&mut self, attr: &AttributeInfo) -> bool {
+        let info = &attr.info;
+
+        let text = match self.attribute_name(attr).as_deref() {
+            Some("Code") => return self.code(attr),
+            Some("ConstantValue") if info.len() == 2 => {
+                read_index(info).map(|index| format!(".constantvalue {}", self.reference(index)))
+            }
+            Some("Exceptions") if is_index_table(info) => {
+                read_index_table(info).map(|table| format!(".exceptions {}", self.class_refs(&table)))
+            }
+            _ => return false,
+        };
+
+        match text {
+            Ok(text) => self.line(MEMBER_INDENT, &text),
+            Err(_) => return false,
+        }
+        true
+    }
+
+    fn class_attribute(&mut self, attr: &AttributeInfo) -> bool {
+        let info = &attr.info;
+
+        let text = match self.attribute_name(attr).as_deref() {
+            Some("SourceFile") if info.len() == 2 => read_index(info).map(|index| format!(".sourcefile {}", self.utf8_ref(index))),
+            Some("NestHost") if info.len() == 2 => read_index(info).map(|index| format!(".nesthost {}", self.class_ref(index))),
+            Some("NestMembers") if is_index_table(info) => {
+                read_index_table(info).map(|table| format!(".nestmembers {}", self.class_refs(&table)))
+            }
+            Some("PermittedSubclasses") if is_index_table(info) => {
+                read_index_table(info).map(|table| format!(".permittedsubclasses {}", self.class_refs(&table)))
+            }
+            Some("EnclosingMethod") if info.len() == 4 => {
+                read_enclosing_method(info).map(|(class_index, method_index)| {
+                    format!(
+                        ".enclosing method {} {}",
+                        self.class_ref(class_index),
+                        self.name_and_type_ref(method_index)
+                    )
+                })
+            }
+            Some("InnerClasses") if table_length(info, 2, 8) => {
+                let inner_classes = match read_inner_classes(info) {
+                    Ok(inner_classes) => inner_classes,
+                    Err(_) => return false,
+                };
+
+                self.line(0, ".innerclasses");
+                for inner in inner_classes {
+                    let mut text = vec![
+                        self.class_ref(inner.inner_class_info_index),
+                        self.class_ref(inner.outer_class_info_index),
+                        self.utf8_ref(inner.inner_name_index),
+                    ];
+                    text.extend(format_flags(inner.inner_class_access_flags, FlagContext::InnerClass));
+                    self.line(MEMBER_INDENT, &text.join(" "));
+                }
+                Ok(".end innerclasses".to_string())
+            }
+            Some("BootstrapMethods") if is_bootstrap_methods(info) => {
+                let bootstrap_methods = match read_bootstrap_methods(info) {
+                    Ok(bootstrap_methods) => bootstrap_methods,
+                    Err(_) => return false,
+                };
+
+                self.line(0, ".bootstrapmethods");
+                for method in bootstrap_methods {
+                    let mut text = vec![self.reference(method.bootstrap_method_ref), ":".to_string()];
+                    text.extend(method.bootstrap_arguments.iter().map(|&i| self.reference(i)));
+                    self.line(MEMBER_INDENT, &text.join(" "));
+                }
+                Ok(".end bootstrapmethods".to_string())
+            }
+            _ => return false,
+        };
+
+        match text {
+            Ok(text) => self.line(0, &text),
+            Err(_) => return false,
+        }
+        true
+    }
+
+    // Lists a Code attribute, or returns false if it cannot be listed without
+    // losing information.
+    fn code(&mut self, attr: &AttributeInfo) -> bool {
+        if code_attribute_length(&attr.info) != Some(attr.info.len()) {
+            return false;
+        }
+
+        let code = match read_code(&attr.info) {
+            Ok(code) => code,
+            Err(_) => return false,
+        };
+        let instructions = match bytecode::decode(&code.code) {
+            Ok(instructions) => instructions,
+            Err(_) => return false,
+        };
+
+        if !instructions.iter().all(|insn| has_zero_padding(&code.code, insn)) {
+            return false;
+        }
+
+        // Offsets a label may name: every instruction and the end of the code.
+        let mut boundaries: BTreeSet<u32> = instructions.iter().map(|insn| insn.offset).collect();
+        boundaries.insert(code.code.len() as u32);
+
+        let mut labels = BTreeSet::new();
+        for insn in &instructions {
+            labels.extend(insn.branch_targets());
+        }
+        for entry in &code.exception_table {
+            labels.extend(vec![entry.start_pc as u32, entry.end_pc as u32, entry.handler_pc as u32]);
+        }
+        if !labels.is_subset(&boundaries) {
+            return false;
+        }
+
+        let mut frames = HashMap::new();
+        let mut sub_attributes = Vec::new();
+
+        for sub in &code.attributes.array {
+            let decoded = match self.attribute_name(sub).as_deref() {
+                Some("LineNumberTable") if table_length(&sub.info, 2, 4) => read_line_number_table(&sub.info).ok().and_then(|table| {
+                    let offsets: BTreeSet<u32> = table.iter().map(|l| l.start_pc as u32).collect();
+
+                    if offsets.is_subset(&boundaries) {
+                        labels.extend(offsets);
+                        Some(CodeAttribute::LineNumbers(table))
+                    } else {
+                        None
+                    }
+                }),
+                Some(name @ "LocalVariableTable") | Some(name @ "LocalVariableTypeTable")
+                    if table_length(&sub.info, 2, 10) =>
+                {
+                    let directive = if name == "LocalVariableTable" {
+                        "localvariabletable"
+                    } else {
+                        "localvariabletypetable"
+                    };
+
+                    read_local_variable_table(&sub.info).ok().and_then(|table| {
+                        let offsets: BTreeSet<u32> = table
+                            .iter()
+                            .flat_map(|v| vec![v.start_pc as u32, v.start_pc as u32 + v.length as u32])
+                            .collect();
+
+                        if offsets.is_subset(&boundaries) {
+                            labels.extend(offsets);
+                            Some(CodeAttribute::LocalVariables(directive, table))
+                        } else {
+                            None
+                        }
+                    })
+                }
+                // Frames are listed inline, so only one table can be.
+                Some("StackMapTable") if frames.is_empty() && stack_map_table_length(&sub.info) == Some(sub.info.len()) => {
+                    read_stack_map_table(&sub.info).ok().and_then(|table| {
+                        let offsets = frame_offsets(&table);
+                        let uninitialized: BTreeSet<u32> = table
+                            .iter()
+                            .flat_map(frame_types)
+                            .filter_map(|t| match *t {
+                                VerificationType::Uninitialized(offset) => Some(offset as u32),
+                                _ => None,
+                            })
+                            .collect();
+                        let at_instructions = offsets.iter().all(|o| boundaries.contains(o) && *o != code.code.len() as u32);
+
+                        if !table.is_empty() && at_instructions && uninitialized.is_subset(&boundaries) {
+                            labels.extend(uninitialized);
+                            frames.extend(offsets.into_iter().zip(table));
+                            Some(CodeAttribute::StackMap)
+                        } else {
+                            None
+                        }
+                    })
+                }
+                _ => None,
+            };
+
+            sub_attributes.push(decoded.unwrap_or(CodeAttribute::Raw(sub)));
+        }
+
+        self.line(
+            MEMBER_INDENT,
+            &format!(".code stack {} locals {}", code.max_stack, code.max_locals),
+        );
+
+        for entry in &code.exception_table {
+            let catch_type = match entry.catch_type {
+                0 => "any".to_string(),
+                index => match self.class_ref(index) {
+                    // A class named `any` would read back as a catch-all.
+                    ref name if name == "any" => format!("[{}]", index),
+                    name => name,
+                },
+            };
+            let text = format!(
+                ".catch {} from L{} to L{} using L{}",
+                catch_type, entry.start_pc, entry.end_pc, entry.handler_pc
+            );
+            self.line(CODE_INDENT, &text);
+        }
+
+        for insn in &instructions {
+            if let Some(frame) = frames.get(&insn.offset) {
+                let text = self.frame(frame);
+                self.line(CODE_INDENT, &text);
+            }
+
+            let label = if labels.contains(&insn.offset) {
+                format!("L{}:", insn.offset)
+            } else {
+                String::new()
+            };
+            let text = format!("{:<8}{}", label, self.instruction(insn));
+            self.line(0, &text);
+            self.switch_targets(insn);
+        }
+
+        if labels.contains(&(code.code.len() as u32)) {
+            self.line(0, &format!("L{}:", code.code.len()));
+        }
+
+        for sub in sub_attributes {
+            match sub {
+                CodeAttribute::LineNumbers(table) => {
+                    self.line(CODE_INDENT, ".linenumbertable");
+                    for entry in table {
+                        self.line(CODE_INDENT + 4, &format!("L{} {}", entry.start_pc, entry.line_number));
+                    }
+                    self.line(CODE_INDENT, ".end linenumbertable");
+                }
+                CodeAttribute::LocalVariables(directive, table) => {
+                    self.line(CODE_INDENT, &format!(".{}", directive));
+                    for var in table {
+                        let text = format!(
+                            "{} is {} {} from L{} to L{}",
+                            var.index,
+                            self.utf8_ref(var.name_index),
+                            self.utf8_ref(var.descriptor_index),
+                            var.start_pc,
+                            var.start_pc as u32 + var.length as u32
+                        );
+                        self.line(CODE_INDENT + 4, &text);
+                    }
+                    self.line(CODE_INDENT, &format!(".end {}", directive));
+                }
+                CodeAttribute::StackMap => self.line(CODE_INDENT, ".stackmaptable"),
+                CodeAttribute::Raw(attr) => self.raw_attribute(CODE_INDENT, attr),
+            }
+        }
+
+        self.line(MEMBER_INDENT, ".end code");
+        true
+    }
+
+    fn instruction(&self, insn: &Instruction) -> String {
+        let mut text = if insn.wide {
+            format!("wide {}", insn.mnemonic())
+        } else {
+            insn.mnemonic().to_string()
+        };
+
+        let operand = match insn.operand {
+            Operand::None => return text,
+            Operand::Local(index) => index.to_string(),
+            Operand::Int(value) => value.to_string(),
+            // new, anewarray, checkcast and instanceof
+            Operand::Constant(index) if [0xbb, 0xbd, 0xc0, 0xc1].contains(&insn.opcode) => self.class_ref(index),
+            Operand::Constant(index) => self.reference(index),
+            Operand::Branch(target) => format!("L{}", target),
+            Operand::Iinc { index, value } => format!("{} {}", index, value),
+            Operand::InvokeInterface { index, count } => format!("{} {}", self.reference(index), count),
+            Operand::InvokeDynamic(index) => self.reference(index),
+            Operand::MultiANewArray { index, dimensions } => format!("{} {}", self.class_ref(index), dimensions),
+            Operand::NewArray(atype) => match bytecode::array_type_name(atype) {
+                Some(name) => name.to_string(),
+                None => atype.to_string(),
+            },
+            Operand::TableSwitch { low, .. } => low.to_string(),
+            Operand::LookupSwitch { .. } => return text,
+        };
+
+        text.push(' ');
+        text.push_str(&operand);
+        text
+    }
+
+    fn switch_targets(&mut self, insn: &Instruction) {
+        let default = match insn.operand {
+            Operand::TableSwitch {
+                default,
+                ref targets,
+                ..
+            } => {
+                for target in targets {
+                    self.line(CODE_INDENT + 4, &format!("L{}", target));
+                }
+                default
+            }
+            Operand::LookupSwitch { default, ref pairs } => {
+                for &(key, target) in pairs {
+                    self.line(CODE_INDENT + 4, &format!("{} : L{}", key, target));
+                }
+                default
+            }
+            _ => return,
+        };
+
+        self.line(CODE_INDENT + 4, &format!("default : L{}", default));
+    }
+
+    fn frame(&self, frame: &StackMapFrame) -> String {
+        let extended = frame.frame_type == 247 || frame.frame_type == 251;
+
+        match frame.kind {
+            FrameKind::Same if extended => ".stack same_extended".to_string(),
+            FrameKind::Same => ".stack same".to_string(),
+            FrameKind::SameLocals1StackItem(ref t) if extended => {
+                format!(".stack same_locals_1_stack_item_extended {}", self.verification_type(t))
+            }
+            FrameKind::SameLocals1StackItem(ref t) => {
+                format!(".stack same_locals_1_stack_item {}", self.verification_type(t))
+            }
+            FrameKind::Chop(n) => format!(".stack chop {}", n),
+            FrameKind::Append(ref locals) => format!(".stack append {}", self.verification_types(locals)),
+            FrameKind::Full { ref locals, ref stack } => {
+                let mut text = ".stack full locals".to_string();
+                for t in locals {
+                    text.push(' ');
+                    text.push_str(&self.verification_type(t));
+                }
+                text.push_str(" stack");
+                for t in stack {
+                    text.push(' ');
+                    text.push_str(&self.verification_type(t));
+                }
+                text
+            }
+        }
+    }
+
+    fn verification_types(&self, types: &[VerificationType]) -> String {
+        types.iter().map(|t| self.verification_type(t)).collect::<Vec<_>>().join(" ")
+    }
+
+    fn verification_type(&self, t: &VerificationType) -> String {
+        match *t {
+            VerificationType::Top => "Top".to_string(),
+            VerificationType::Integer => "Integer".to_string(),
+            VerificationType::Float => "Float".to_string(),
+            VerificationType::Double => "Double".to_string(),
+            VerificationType::Long => "Long".to_string(),
+            VerificationType::Null => "Null".to_string(),
+            VerificationType::UninitializedThis => "UninitializedThis".to_string(),
+            VerificationType::Object(index) => format!("Object {}", self.class_ref(index)),
+            VerificationType::Uninitialized(offset) => format!("Uninitialized L{}", offset),
+        }
+    }
+
+    // The whole pool, with every index, so that indices survive reassembly.
+    fn constants(&mut self) {
+        for (index, info) in self.cp.entries() {
+            let any = info.as_any();
+            let tag = cp_tag_from(info.tag());
+
+            let value = match tag {
+                CpTag::Utf8 => utf8_literal(&any.downcast_ref::<Utf8Info>().unwrap().bytes),
+                CpTag::Class => format!("[{}]", any.downcast_ref::<ClassInfo>().unwrap().name_index),
+                CpTag::String => format!("[{}]", any.downcast_ref::<StringInfo>().unwrap().string_index),
+                CpTag::Fieldref | CpTag::Methodref | CpTag::InterfaceMethodref => {
+                    let (class_index, name_and_type_index) = member_ref_indices(self.cp, index).unwrap();
+                    format!("[{}] [{}]", class_index, name_and_type_index)
+                }
+                CpTag::NameAndType => {
+                    let info = any.downcast_ref::<NameAndTypeInfo>().unwrap();
+                    format!("[{}] [{}]", info.name_index, info.descriptor_index)
+                }
+                CpTag::MethodHandle => {
+                    let info = any.downcast_ref::<MethodHandleInfo>().unwrap();
+                    format!("{} [{}]", reference_kind_name(info.reference_kind), info.reference_index)
+                }
+                CpTag::MethodType => format!("[{}]", any.downcast_ref::<MethodTypeInfo>().unwrap().descriptor_index),
+                CpTag::Dynamic => {
+                    let info = any.downcast_ref::<DynamicInfo>().unwrap();
+                    format!("{} [{}]", info.bootstrap_method_attr_index, info.name_and_type_index)
+                }
+                CpTag::InvokeDynamic => {
+                    let info = any.downcast_ref::<InvokeDynamicInfo>().unwrap();
+                    format!("{} [{}]", info.bootstrap_method_attr_index, info.name_and_type_index)
+                }
+                CpTag::Module => format!("[{}]", any.downcast_ref::<ModuleInfo>().unwrap().name_index),
+                CpTag::Package => format!("[{}]", any.downcast_ref::<PackageInfo>().unwrap().name_index),
+                // Numeric constants are the same in either form.
+                _ => {
+                    let spec = constant_spec(self.cp, index).unwrap();
+                    self.line(0, &format!(".const [{}] = {}", index, spec));
+                    continue;
+                }
+            };
+
+            let mut text = format!(".const [{}] = {} {}", index, tag_name(tag), value);
+            match constant_spec(self.cp, index) {
+                Some(ref spec) if tag != CpTag::Utf8 => {
+                    text.push_str(" ; ");
+                    text.push_str(spec.split_once(' ').map_or("", |(_, rest)| rest));
+                }
+                _ => {}
+            }
+            self.line(0, &text);
+        }
+    }
+}
+
+fn frame_types(frame: &StackMapFrame) -> Vec<&VerificationType> {
+    match frame.kind {
+        FrameKind::Same | FrameKind::Chop(_) => vec![],
+        FrameKind::SameLocals1StackItem(ref t) => vec![t],
+        FrameKind::Append(ref locals) => locals.iter().collect(),
+        FrameKind::Full { ref locals, ref stack } => locals.iter().chain(stack).collect(),
+    }
+}
+
+// Padding in switches and the reserved bytes of invokeinterface and
+// invokedynamic are not listed, so they must hold the zeros the assembler writes.
+fn has_zero_padding(code: &[u8], insn: &Instruction) -> bool {
+    let pc = insn.offset as usize;
+
+    let padding = match insn.operand {
+        Operand::TableSwitch { .. } | Operand::LookupSwitch { .. } => pc + 1..(pc + 4) & !3,
+        Operand::InvokeInterface { .. } => pc + 4..pc + 5,
+        Operand::InvokeDynamic(_) => pc + 3..pc + 5,
+        _ => return true,
+    };
+
+    code[padding].iter().all(|&b| b == 0)
+}
+
+fn u16_at(info: &[u8], at: usize) -> Option<usize> {
+    info.get(at..at + 2).map(|b| (b[0] as usize) << 8 | b[1] as usize)
+}
+
+fn u32_at(info: &[u8], at: usize) -> Option<usize> {
+    info.get(at..at + 4)
+        .map(|b| (b[0] as usize) << 24 | (b[1] as usize) << 16 | (b[2] as usize) << 8 | b[3] as usize)
+}
+
+// Whether `info` is a table with a count of `count_size` bytes followed by
+// that many entries of `entry_size` bytes.
+fn table_length(info: &[u8], count_size: usize, entry_size: usize) -> bool {
+    let count = match count_size {
+        1 => info.first().map(|&b| b as usize),
+        _ => u16_at(info, 0),
+    };
+
+    count.is_some_and(|count| info.len() == count_size + count * entry_size)
+}
+
+fn is_index_table(info: &[u8]) -> bool {
+    table_length(info, 2, 2)
+}
+
+fn is_bootstrap_methods(info: &[u8]) -> bool {
+    let mut at = 2;
+
+    for _ in 0..u16_at(info, 0).unwrap_or(0) {
+        match u16_at(info, at + 2) {
+            Some(arguments) => at += 4 + arguments * 2,
+            None => return false,
+        }
+    }
+
+    info.len() == at
+}
+
+// The number of bytes a well-formed Code attribute starting in `info` spans.
+fn code_attribute_length(info: &[u8]) -> Option<usize> {
+    let code_length = u32_at(info, 4)?;
+    let mut at = 8 + code_length;
+    at += 2 + u16_at(info, at)? * 8;
+
+    let attributes_count = u16_at(info, at)?;
+    at += 2;
+    for _ in 0..attributes_count {
+        at += 6 + u32_at(info, at + 2)?;
+    }
+
+    if at <= info.len() {
+        Some(at)
+    } else {
+        None
+    }
+}
+
+fn stack_map_table_length(info: &[u8]) -> Option<usize> {
+    let verification_type = |at: usize| -> Option<usize> {
+        match *info.get(at)? {
+            0..=6 => Some(1),
+            7 | 8 => Some(3),
+            _ => None,
+        }
+    };
+    let verification_types = |mut at: usize, count: usize| -> Option<usize> {
+        let start = at;
+        for _ in 0..count {
+            at += verification_type(at)?;
+        }
+        Some(at - start)
+    };
+
+    let mut at = 2;
+    for _ in 0..u16_at(info, 0)? {
+        let frame_type = *info.get(at)?;
+        at += 1;
+
+        at += match frame_type {
+            0..=63 => 0,
+            64..=127 => verification_type(at)?,
+            247 => 2 + verification_type(at + 2)?,
+            248..=251 => 2,
+            252..=254 => 2 + verification_types(at + 2, frame_type as usize - 251)?,
+            255 => {
+                let locals = verification_types(at + 4, u16_at(info, at + 2)?)?;
+                let stack = verification_types(at + 6 + locals, u16_at(info, at + 4 + locals)?)?;
+                6 + locals + stack
+            }
+            _ => return None,
+        };
+    }
+
+    if at <= info.len() {
+        Some(at)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,4 @@
+pub mod syntax;
+pub mod disassemble;
+pub mod pool;
+pub mod assemble;
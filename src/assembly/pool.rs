@@ -0,0 +1,408 @@
+// Constant pool construction for the assembler. Entries declared with `.const`
+// keep their index; everything else referred to symbolically is looked up by
+// content and allocated in the first free slot if it does not exist yet.
+
+use std::collections::HashMap;
+
+use class_file::constant_pool::*;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Constant {
+    Utf8(Vec<u8>),
+    Integer(i32),
+    Float(u32),
+    Long(i64),
+    Double(u64),
+    Class(Ref),
+    String(Ref),
+    Fieldref(Ref, Ref),
+    Methodref(Ref, Ref),
+    InterfaceMethodref(Ref, Ref),
+    NameAndType(Ref, Ref),
+    MethodHandle(u8, Ref),
+    MethodType(Ref),
+    Dynamic(u16, Ref),
+    InvokeDynamic(u16, Ref),
+    Module(Ref),
+    Package(Ref),
+}
+
+/// A reference to a constant, either by pool index or by content.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Ref {
+    Index(u16),
+    Spec(Box<Constant>),
+}
+
+enum Slot {
+    Free,
+    Entry(Constant),
+    // The unusable slot after a Long or Double.
+    Wide,
+}
+
+// Guards against reference cycles between declared entries.
+const MAX_DEPTH: usize = 16;
+
+impl Constant {
+    pub fn tag(&self) -> CpTag {
+        match *self {
+            Constant::Utf8(_) => CpTag::Utf8,
+            Constant::Integer(_) => CpTag::Integer,
+            Constant::Float(_) => CpTag::Float,
+            Constant::Long(_) => CpTag::Long,
+            Constant::Double(_) => CpTag::Double,
+            Constant::Class(_) => CpTag::Class,
+            Constant::String(_) => CpTag::String,
+            Constant::Fieldref(..) => CpTag::Fieldref,
+            Constant::Methodref(..) => CpTag::Methodref,
+            Constant::InterfaceMethodref(..) => CpTag::InterfaceMethodref,
+            Constant::NameAndType(..) => CpTag::NameAndType,
+            Constant::MethodHandle(..) => CpTag::MethodHandle,
+            Constant::MethodType(_) => CpTag::MethodType,
+            Constant::Dynamic(..) => CpTag::Dynamic,
+            Constant::InvokeDynamic(..) => CpTag::InvokeDynamic,
+            Constant::Module(_) => CpTag::Module,
+            Constant::Package(_) => CpTag::Package,
+        }
+    }
+
+    fn is_wide(&self) -> bool {
+        matches!(*self, Constant::Long(_) | Constant::Double(_))
+    }
+
+    fn refs(&self) -> Vec<&Ref> {
+        match *self {
+            Constant::Class(ref a)
+            | Constant::String(ref a)
+            | Constant::MethodHandle(_, ref a)
+            | Constant::MethodType(ref a)
+            | Constant::Dynamic(_, ref a)
+            | Constant::InvokeDynamic(_, ref a)
+            | Constant::Module(ref a)
+            | Constant::Package(ref a) => vec![a],
+            Constant::Fieldref(ref a, ref b)
+            | Constant::Methodref(ref a, ref b)
+            | Constant::InterfaceMethodref(ref a, ref b)
+            | Constant::NameAndType(ref a, ref b) => vec![a, b],
+            _ => vec![],
+        }
+    }
+
+    fn map_refs<F>(&self, mut f: F) -> Result<Constant, String>
+    where
+        F: FnMut(&Ref) -> Result<Ref, String>,
+    {
+        Ok(match *self {
+            Constant::Class(ref a) => Constant::Class(f(a)?),
+            Constant::String(ref a) => Constant::String(f(a)?),
+            Constant::Fieldref(ref a, ref b) => Constant::Fieldref(f(a)?, f(b)?),
+            Constant::Methodref(ref a, ref b) => Constant::Methodref(f(a)?, f(b)?),
+            Constant::InterfaceMethodref(ref a, ref b) => Constant::InterfaceMethodref(f(a)?, f(b)?),
+            Constant::NameAndType(ref a, ref b) => Constant::NameAndType(f(a)?, f(b)?),
+            Constant::MethodHandle(kind, ref a) => Constant::MethodHandle(kind, f(a)?),
+            Constant::MethodType(ref a) => Constant::MethodType(f(a)?),
+            Constant::Dynamic(bsm, ref a) => Constant::Dynamic(bsm, f(a)?),
+            Constant::InvokeDynamic(bsm, ref a) => Constant::InvokeDynamic(bsm, f(a)?),
+            Constant::Module(ref a) => Constant::Module(f(a)?),
+            Constant::Package(ref a) => Constant::Package(f(a)?),
+            ref other => other.clone(),
+        })
+    }
+
+    // Tags the referenced entries must have for the constant to have a
+    // symbolic form, mirroring `syntax::constant_spec`.
+    fn expected_tags(&self) -> Vec<Option<CpTag>> {
+        match *self {
+            Constant::Class(_)
+            | Constant::String(_)
+            | Constant::MethodType(_)
+            | Constant::Module(_)
+            | Constant::Package(_) => vec![Some(CpTag::Utf8)],
+            Constant::Fieldref(..) | Constant::Methodref(..) | Constant::InterfaceMethodref(..) => {
+                vec![Some(CpTag::Class), Some(CpTag::NameAndType)]
+            }
+            Constant::NameAndType(..) => vec![Some(CpTag::Utf8), Some(CpTag::Utf8)],
+            Constant::MethodHandle(..) => vec![None],
+            Constant::Dynamic(..) | Constant::InvokeDynamic(..) => vec![Some(CpTag::NameAndType)],
+            _ => vec![],
+        }
+    }
+}
+
+pub struct PoolBuilder {
+    slots: Vec<Slot>,
+
+    // Fully expanded content of each entry, mapped to the first index holding it.
+    first: HashMap<Constant, u16>,
+}
+
+impl Default for PoolBuilder {
+    fn default() -> PoolBuilder {
+        PoolBuilder::new()
+    }
+}
+
+impl PoolBuilder {
+    pub fn new() -> PoolBuilder {
+        PoolBuilder {
+            slots: vec![Slot::Wide],
+            first: HashMap::new(),
+        }
+    }
+
+    /// Places a constant at a fixed index. All declarations must be made
+    /// before anything is resolved.
+    pub fn declare(&mut self, index: u16, constant: Constant) -> Result<(), String> {
+        let wide = constant.is_wide();
+        let end = index as usize + if wide { 2 } else { 1 };
+
+        if index == 0 || end > 0xffff + 1 {
+            return Err(format!("constant pool index {} is out of range", index));
+        }
+
+        while self.slots.len() < end {
+            self.slots.push(Slot::Free);
+        }
+
+        for i in index as usize..end {
+            if !matches!(self.slots[i], Slot::Free) {
+                return Err(format!("constant pool index {} is declared twice", i));
+            }
+        }
+
+        self.slots[index as usize] = Slot::Entry(constant);
+        if wide {
+            self.slots[index as usize + 1] = Slot::Wide;
+        }
+
+        Ok(())
+    }
+
+    /// Indexes the declared entries by content, so symbolic references resolve
+    /// to the first of them, as the disassembler expects.
+    pub fn finish_declarations(&mut self) {
+        for index in 1..self.slots.len() {
+            let key = match self.slots[index] {
+                Slot::Entry(ref constant) => self.expand(constant, 0),
+                _ => None,
+            };
+
+            if let Some(key) = key {
+                self.first.entry(key).or_insert(index as u16);
+            }
+        }
+    }
+
+    pub fn resolve(&mut self, r: &Ref) -> Result<u16, String> {
+        let constant = match *r {
+            Ref::Index(index) => return Ok(index),
+            Ref::Spec(ref constant) => constant,
+        };
+
+        let key = self.expand(constant, 0);
+
+        if let Some(&index) = key.as_ref().and_then(|key| self.first.get(key)) {
+            return Ok(index);
+        }
+
+        let resolved = constant.map_refs(|r| self.resolve(r).map(Ref::Index))?;
+        let index = self.allocate(resolved)?;
+
+        if let Some(key) = key {
+            self.first.entry(key).or_insert(index);
+        }
+
+        Ok(index)
+    }
+
+    fn allocate(&mut self, constant: Constant) -> Result<u16, String> {
+        let size = if constant.is_wide() { 2 } else { 1 };
+
+        let mut index = 1;
+        while index < self.slots.len() {
+            let free = (index..index + size).all(|i| matches!(self.slots.get(i), None | Some(&Slot::Free)));
+            if free {
+                break;
+            }
+            index += 1;
+        }
+
+        if index + size > 0xffff + 1 {
+            return Err("constant pool is full".to_string());
+        }
+
+        while self.slots.len() < index + size {
+            self.slots.push(Slot::Free);
+        }
+
+        self.slots[index] = Slot::Entry(constant);
+        if size == 2 {
+            self.slots[index + 1] = Slot::Wide;
+        }
+
+        Ok(index as u16)
+    }
+
+    // The content of a constant with every reference replaced by the content
+    // it points to, or None if it has no symbolic form.
+    fn expand(&self, constant: &Constant, depth: usize) -> Option<Constant> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+
+        let expected = constant.expected_tags();
+        let mut tags = expected.iter();
+
+        constant
+            .map_refs(|r| {
+                let expanded = match *r {
+                    Ref::Index(index) => match self.slots.get(index as usize) {
+                        Some(Slot::Entry(target)) => self.expand(target, depth + 1),
+                        _ => None,
+                    },
+                    Ref::Spec(ref target) => self.expand(target, depth + 1),
+                };
+
+                match (expanded, tags.next()) {
+                    (Some(c), Some(&None)) => Ok(Ref::Spec(Box::new(c))),
+                    (Some(ref c), Some(&Some(tag))) if c.tag() == tag => Ok(Ref::Spec(Box::new(c.clone()))),
+                    _ => Err(String::new()),
+                }
+            })
+            .ok()
+    }
+
+    pub fn build(mut self) -> Result<ConstantPool, String> {
+        // Declared entries may name other constants by content.
+        for index in 1..self.slots.len() {
+            let pending = match self.slots[index] {
+                Slot::Entry(ref constant) if constant.refs().iter().any(|r| matches!(**r, Ref::Spec(_))) => {
+                    Some(constant.clone())
+                }
+                _ => None,
+            };
+
+            if let Some(constant) = pending {
+                let resolved = constant.map_refs(|r| self.resolve(r).map(Ref::Index))?;
+                self.slots[index] = Slot::Entry(resolved);
+            }
+        }
+
+        let mut array: Vec<Box<dyn CpInfo>> = Vec::new();
+
+        for (index, slot) in self.slots.iter().enumerate().skip(1) {
+            match *slot {
+                Slot::Free => return Err(format!("constant pool index {} is never defined", index)),
+                Slot::Wide => {}
+                Slot::Entry(ref constant) => array.push(cp_info(constant)),
+            }
+        }
+
+        Ok(ConstantPool::new(array))
+    }
+}
+
+fn index(r: &Ref) -> u16 {
+    match *r {
+        Ref::Index(index) => index,
+        Ref::Spec(_) => unreachable!("unresolved constant reference"),
+    }
+}
+
+fn cp_info(constant: &Constant) -> Box<dyn CpInfo> {
+    let tag = match constant.tag() {
+        CpTag::Utf8 => 1,
+        CpTag::Integer => 3,
+        CpTag::Float => 4,
+        CpTag::Long => 5,
+        CpTag::Double => 6,
+        CpTag::Class => 7,
+        CpTag::String => 8,
+        CpTag::Fieldref => 9,
+        CpTag::Methodref => 10,
+        CpTag::InterfaceMethodref => 11,
+        CpTag::NameAndType => 12,
+        CpTag::MethodHandle => 15,
+        CpTag::MethodType => 16,
+        CpTag::Dynamic => 17,
+        CpTag::InvokeDynamic => 18,
+        CpTag::Module => 19,
+        CpTag::Package => 20,
+    };
+
+    match *constant {
+        Constant::Utf8(ref bytes) => Box::new(Utf8Info {
+            tag,
+            length: bytes.len() as u16,
+            bytes: bytes.clone(),
+        }),
+        Constant::Integer(value) => Box::new(IntegerInfo { tag, bytes: value as u32 }),
+        Constant::Float(bits) => Box::new(FloatInfo { tag, bytes: bits }),
+        Constant::Long(value) => Box::new(LongInfo {
+            tag,
+            high_bytes: (value as u64 >> 32) as u32,
+            low_bytes: value as u32,
+        }),
+        Constant::Double(bits) => Box::new(DoubleInfo {
+            tag,
+            high_bytes: (bits >> 32) as u32,
+            low_bytes: bits as u32,
+        }),
+        Constant::Class(ref name) => Box::new(ClassInfo {
+            tag,
+            name_index: index(name),
+        }),
+        Constant::String(ref string) => Box::new(StringInfo {
+            tag,
+            string_index: index(string),
+        }),
+        Constant::Fieldref(ref class, ref name_and_type) => Box::new(FieldrefInfo {
+            tag,
+            class_index: index(class),
+            name_and_type_index: index(name_and_type),
+        }),
+        Constant::Methodref(ref class, ref name_and_type) => Box::new(MethodrefInfo {
+            tag,
+            class_index: index(class),
+            name_and_type_index: index(name_and_type),
+        }),
+        Constant::InterfaceMethodref(ref class, ref name_and_type) => Box::new(InterfaceMethodrefInfo {
+            tag,
+            class_index: index(class),
+            name_and_type_index: index(name_and_type),
+        }),
+        Constant::NameAndType(ref name, ref descriptor) => Box::new(NameAndTypeInfo {
+            tag,
+            name_index: index(name),
+            descriptor_index: index(descriptor),
+        }),
+        Constant::MethodHandle(reference_kind, ref reference) => Box::new(MethodHandleInfo {
+            tag,
+            reference_kind,
+            reference_index: index(reference),
+        }),
+        Constant::MethodType(ref descriptor) => Box::new(MethodTypeInfo {
+            tag,
+            descriptor_index: index(descriptor),
+        }),
+        Constant::Dynamic(bootstrap_method_attr_index, ref name_and_type) => Box::new(DynamicInfo {
+            tag,
+            bootstrap_method_attr_index,
+            name_and_type_index: index(name_and_type),
+        }),
+        Constant::InvokeDynamic(bootstrap_method_attr_index, ref name_and_type) => Box::new(InvokeDynamicInfo {
+            tag,
+            bootstrap_method_attr_index,
+            name_and_type_index: index(name_and_type),
+        }),
+        Constant::Module(ref name) => Box::new(ModuleInfo {
+            tag,
+            name_index: index(name),
+        }),
+        Constant::Package(ref name) => Box::new(PackageInfo {
+            tag,
+            name_index: index(name),
+        }),
+    }
+}
@@ -0,0 +1,338 @@
+// Pieces of the assembly syntax shared by the disassembler and the assembler.
+
+use class_file::access_flags::*;
+use class_file::constant_pool::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlagContext {
+    Class,
+    Field,
+    Method,
+    InnerClass,
+}
+
+pub fn flag_names(context: FlagContext) -> &'static [(u16, &'static str)] {
+    match context {
+        FlagContext::Class => &[
+            (ACC_PUBLIC, "public"),
+            (ACC_FINAL, "final"),
+            (ACC_SUPER, "super"),
+            (ACC_INTERFACE, "interface"),
+            (ACC_ABSTRACT, "abstract"),
+            (ACC_SYNTHETIC, "synthetic"),
+            (ACC_ANNOTATION, "annotation"),
+            (ACC_ENUM, "enum"),
+            (ACC_MODULE, "module"),
+        ],
+        FlagContext::Field => &[
+            (ACC_PUBLIC, "public"),
+            (ACC_PRIVATE, "private"),
+            (ACC_PROTECTED, "protected"),
+            (ACC_STATIC, "static"),
+            (ACC_FINAL, "final"),
+            (ACC_VOLATILE, "volatile"),
+            (ACC_TRANSIENT, "transient"),
+            (ACC_SYNTHETIC, "synthetic"),
+            (ACC_ENUM, "enum"),
+        ],
+        FlagContext::Method => &[
+            (ACC_PUBLIC, "public"),
+            (ACC_PRIVATE, "private"),
+            (ACC_PROTECTED, "protected"),
+            (ACC_STATIC, "static"),
+            (ACC_FINAL, "final"),
+            (ACC_SYNCHRONIZED, "synchronized"),
+            (ACC_BRIDGE, "bridge"),
+            (ACC_VARARGS, "varargs"),
+            (ACC_NATIVE, "native"),
+            (ACC_ABSTRACT, "abstract"),
+            (ACC_STRICT, "strict"),
+            (ACC_SYNTHETIC, "synthetic"),
+        ],
+        FlagContext::InnerClass => &[
+            (ACC_PUBLIC, "public"),
+            (ACC_PRIVATE, "private"),
+            (ACC_PROTECTED, "protected"),
+            (ACC_STATIC, "static"),
+            (ACC_FINAL, "final"),
+            (ACC_INTERFACE, "interface"),
+            (ACC_ABSTRACT, "abstract"),
+            (ACC_SYNTHETIC, "synthetic"),
+            (ACC_ANNOTATION, "annotation"),
+            (ACC_ENUM, "enum"),
+        ],
+    }
+}
+
+/// Renders access flags as keywords, with any bits that have no name in this
+/// context written as a hex literal.
+pub fn format_flags(flags: u16, context: FlagContext) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut rest = flags;
+
+    for &(flag, name) in flag_names(context) {
+        if flags & flag != 0 {
+            words.push(name.to_string());
+            rest &= !flag;
+        }
+    }
+
+    if rest != 0 {
+        words.push(format!("0x{:04x}", rest));
+    }
+
+    words
+}
+
+pub fn parse_flag(word: &str, context: FlagContext) -> Option<u16> {
+    if let Some(hex) = word.strip_prefix("0x") {
+        return u16::from_str_radix(hex, 16).ok();
+    }
+
+    flag_names(context)
+        .iter()
+        .find(|&&(_, name)| name == word)
+        .map(|&(flag, _)| flag)
+}
+
+pub const REFERENCE_KINDS: [&str; 9] = [
+    "getField",
+    "getStatic",
+    "putField",
+    "putStatic",
+    "invokeVirtual",
+    "invokeStatic",
+    "invokeSpecial",
+    "newInvokeSpecial",
+    "invokeInterface",
+];
+
+pub fn reference_kind_name(kind: u8) -> String {
+    match kind {
+        1..=9 => REFERENCE_KINDS[kind as usize - 1].to_string(),
+        _ => kind.to_string(),
+    }
+}
+
+pub fn parse_reference_kind(word: &str) -> Option<u8> {
+    match REFERENCE_KINDS.iter().position(|&k| k == word) {
+        Some(i) => Some(i as u8 + 1),
+        None => word.parse().ok(),
+    }
+}
+
+/// Tag names used in constant specifications.
+pub fn tag_name(tag: CpTag) -> &'static str {
+    match tag {
+        CpTag::Utf8 => "Utf8",
+        CpTag::Integer => "Int",
+        CpTag::Float => "Float",
+        CpTag::Long => "Long",
+        CpTag::Double => "Double",
+        CpTag::Class => "Class",
+        CpTag::String => "String",
+        CpTag::Fieldref => "Field",
+        CpTag::Methodref => "Method",
+        CpTag::InterfaceMethodref => "InterfaceMethod",
+        CpTag::NameAndType => "NameAndType",
+        CpTag::MethodHandle => "MethodHandle",
+        CpTag::MethodType => "MethodType",
+        CpTag::Dynamic => "Dynamic",
+        CpTag::InvokeDynamic => "InvokeDynamic",
+        CpTag::Module => "Module",
+        CpTag::Package => "Package",
+    }
+}
+
+/// A bare word if `s` can be read back as one, a quoted string otherwise.
+pub fn word(s: &str) -> String {
+    let is_word = !s.is_empty()
+        && s.chars().all(|c| c.is_ascii_graphic() && c != '"' && c != '\'' && c != ':')
+        && !s.starts_with(';')
+        && !s.starts_with('.')
+        && !s.starts_with("b\"")
+        && !is_ref(s);
+
+    if is_word {
+        s.to_string()
+    } else {
+        quote(s)
+    }
+}
+
+/// `[n]`, a reference to a constant pool entry by index.
+pub fn is_ref(s: &str) -> bool {
+    s.len() > 2 && s.starts_with('[') && s.ends_with(']') && s[1..s.len() - 1].chars().all(|c| c.is_ascii_digit())
+}
+
+pub fn quote(s: &str) -> String {
+    let mut out = String::from("\"");
+
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c if (c as u32) < 0x20 || c as u32 == 0x7f => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+
+    out.push('"');
+    out
+}
+
+/// A `b"..."` literal for raw bytes.
+pub fn quote_bytes(bytes: &[u8]) -> String {
+    let mut out = String::from("b\"");
+
+    for &b in bytes {
+        match b {
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            0x20..=0x7e => out.push(b as char),
+            _ => out.push_str(&format!("\\x{:02x}", b)),
+        }
+    }
+
+    out.push('"');
+    out
+}
+
+/// Renders Utf8 constant contents, falling back to a byte literal for data
+/// that does not survive a round trip through a string.
+pub fn utf8_literal(bytes: &[u8]) -> String {
+    let s = decode_modified_utf8(bytes);
+
+    if encode_modified_utf8(&s) == bytes {
+        quote(&s)
+    } else {
+        quote_bytes(bytes)
+    }
+}
+
+/// Canonical symbolic form of a constant, naming the entries it refers to by
+/// content. The assembler resolves a spec to the first entry rendering the same.
+pub fn constant_spec(cp: &ConstantPool, index: u16) -> Option<String> {
+    let info = cp.get(index)?.as_any();
+
+    let spec = match cp.tag(index)? {
+        CpTag::Utf8 => format!("Utf8 {}", utf8_literal(&info.downcast_ref::<Utf8Info>()?.bytes)),
+        CpTag::Integer => format!("Int {}", info.downcast_ref::<IntegerInfo>()?.bytes as i32),
+        CpTag::Float => format!("Float {}", float_literal(info.downcast_ref::<FloatInfo>()?.bytes)),
+        CpTag::Long => {
+            let info = info.downcast_ref::<LongInfo>()?;
+            format!("Long {}", ((info.high_bytes as u64) << 32 | info.low_bytes as u64) as i64)
+        }
+        CpTag::Double => {
+            let info = info.downcast_ref::<DoubleInfo>()?;
+            format!("Double {}", double_literal((info.high_bytes as u64) << 32 | info.low_bytes as u64))
+        }
+        CpTag::Class => format!("Class {}", utf8_word(cp, info.downcast_ref::<ClassInfo>()?.name_index)?),
+        CpTag::String => {
+            let info = info.downcast_ref::<StringInfo>()?;
+            format!("String {}", utf8_literal(&cp.get_as::<Utf8Info>(info.string_index)?.bytes))
+        }
+        tag @ CpTag::Fieldref | tag @ CpTag::Methodref | tag @ CpTag::InterfaceMethodref => {
+            let (class_index, name_and_type_index) = member_ref_indices(cp, index)?;
+            let class_name = cp.get_as::<ClassInfo>(class_index)?.name_index;
+
+            format!(
+                "{} {} {}",
+                tag_name(tag),
+                utf8_word(cp, class_name)?,
+                name_and_type_words(cp, name_and_type_index)?
+            )
+        }
+        CpTag::NameAndType => format!("NameAndType {}", name_and_type_words(cp, index)?),
+        CpTag::MethodHandle => {
+            let info = info.downcast_ref::<MethodHandleInfo>()?;
+            format!(
+                "MethodHandle {} {}",
+                reference_kind_name(info.reference_kind),
+                constant_spec(cp, info.reference_index)?
+            )
+        }
+        CpTag::MethodType => format!(
+            "MethodType {}",
+            utf8_word(cp, info.downcast_ref::<MethodTypeInfo>()?.descriptor_index)?
+        ),
+        CpTag::Dynamic => {
+            let info = info.downcast_ref::<DynamicInfo>()?;
+            format!(
+                "Dynamic {} {}",
+                info.bootstrap_method_attr_index,
+                name_and_type_words(cp, info.name_and_type_index)?
+            )
+        }
+        CpTag::InvokeDynamic => {
+            let info = info.downcast_ref::<InvokeDynamicInfo>()?;
+            format!(
+                "InvokeDynamic {} {}",
+                info.bootstrap_method_attr_index,
+                name_and_type_words(cp, info.name_and_type_index)?
+            )
+        }
+        CpTag::Module => format!("Module {}", utf8_word(cp, info.downcast_ref::<ModuleInfo>()?.name_index)?),
+        CpTag::Package => format!("Package {}", utf8_word(cp, info.downcast_ref::<PackageInfo>()?.name_index)?),
+    };
+
+    Some(spec)
+}
+
+pub fn member_ref_indices(cp: &ConstantPool, index: u16) -> Option<(u16, u16)> {
+    let info = cp.get(index)?.as_any();
+
+    if let Some(info) = info.downcast_ref::<FieldrefInfo>() {
+        Some((info.class_index, info.name_and_type_index))
+    } else if let Some(info) = info.downcast_ref::<MethodrefInfo>() {
+        Some((info.class_index, info.name_and_type_index))
+    } else {
+        info.downcast_ref::<InterfaceMethodrefInfo>()
+            .map(|info| (info.class_index, info.name_and_type_index))
+    }
+}
+
+/// A Utf8 constant as a word, quoted string or byte literal.
+pub fn utf8_word(cp: &ConstantPool, index: u16) -> Option<String> {
+    let bytes = &cp.get_as::<Utf8Info>(index)?.bytes;
+    let s = decode_modified_utf8(bytes);
+
+    if encode_modified_utf8(&s) == *bytes {
+        Some(word(&s))
+    } else {
+        Some(quote_bytes(bytes))
+    }
+}
+
+fn name_and_type_words(cp: &ConstantPool, index: u16) -> Option<String> {
+    let info = cp.get_as::<NameAndTypeInfo>(index)?;
+
+    Some(format!(
+        "{} {}",
+        utf8_word(cp, info.name_index)?,
+        utf8_word(cp, info.descriptor_index)?
+    ))
+}
+
+pub fn float_literal(bits: u32) -> String {
+    let value = f32::from_bits(bits);
+
+    if value.is_finite() {
+        format!("{:?}", value)
+    } else {
+        format!("0x{:08x}", bits)
+    }
+}
+
+pub fn double_literal(bits: u64) -> String {
+    let value = f64::from_bits(bits);
+
+    if value.is_finite() {
+        format!("{:?}", value)
+    } else {
+        format!("0x{:016x}", bits)
+    }
+}
@@ -0,0 +1,317 @@
+// Basic blocks and control flow edges of a method's code.
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use serde::Serialize;
+
+use bytecode::{decode, DecodeError, Instruction, Operand};
+use class_file::attributes::CodeAttribute;
+use class_file::constant_pool::ConstantPool;
+use serialization::serialize_instructions;
+
+#[derive(Debug, Serialize)]
+pub struct ControlFlowGraph {
+    pub blocks: Vec<BasicBlock>,
+    pub edges: Vec<Edge>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BasicBlock {
+    pub id: usize,
+    pub start: u32,
+    // Offset just past the last instruction.
+    pub end: u32,
+    pub reachable: bool,
+
+    #[serde(serialize_with = "serialize_instructions")]
+    pub instructions: Vec<Instruction>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    pub kind: EdgeKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EdgeKind {
+    FallThrough,
+    // goto, or a conditional branch being taken.
+    Jump,
+    // `key` is None for the default target.
+    Switch { key: Option<i32> },
+    // `catch_type` is None for handlers catching everything.
+    Exception { catch_type: Option<String> },
+    // From jsr to the subroutine, and from ret back to each return site.
+    Subroutine,
+}
+
+pub(crate) fn is_conditional_branch(opcode: u8) -> bool {
+    // ifeq..if_acmpne, ifnull and ifnonnull
+    (0x99..=0xa6).contains(&opcode) || opcode == 0xc6 || opcode == 0xc7
+}
+
+pub(crate) fn is_jsr(opcode: u8) -> bool {
+    opcode == 0xa8 || opcode == 0xc9
+}
+
+/// Whether control never continues to the next instruction.
+pub(crate) fn ends_flow(opcode: u8) -> bool {
+    match opcode {
+        // goto, jsr, ret, tableswitch, lookupswitch, returns
+        0xa7..=0xb1 => true,
+        // athrow, goto_w, jsr_w
+        0xbf | 0xc8 | 0xc9 => true,
+        _ => false,
+    }
+}
+
+impl ControlFlowGraph {
+    pub fn build(code: &CodeAttribute, constant_pool: &ConstantPool) -> Result<ControlFlowGraph, DecodeError> {
+        let instructions = decode(&code.code)?;
+        let code_length = code.code.len() as u32;
+        let offsets: BTreeSet<u32> = instructions.iter().map(|insn| insn.offset).collect();
+
+        let check = |offset: u32, what: &str| {
+            if offsets.contains(&offset) {
+                Ok(offset)
+            } else {
+                Err(DecodeError {
+                    offset,
+                    message: format!("{} is not at an instruction", what),
+                })
+            }
+        };
+
+        let mut leaders = BTreeSet::new();
+        leaders.insert(0);
+
+        for (i, insn) in instructions.iter().enumerate() {
+            for target in insn.branch_targets() {
+                leaders.insert(check(target, "branch target")?);
+            }
+
+            let ends_block = ends_flow(insn.opcode) || is_conditional_branch(insn.opcode);
+            if ends_block && i + 1 < instructions.len() {
+                leaders.insert(instructions[i + 1].offset);
+            }
+        }
+
+        for entry in &code.exception_table {
+            leaders.insert(check(entry.start_pc as u32, "exception range start")?);
+            leaders.insert(check(entry.handler_pc as u32, "exception handler")?);
+
+            if (entry.end_pc as u32) < code_length {
+                leaders.insert(check(entry.end_pc as u32, "exception range end")?);
+            }
+        }
+
+        let mut blocks: Vec<BasicBlock> = Vec::new();
+
+        for insn in instructions {
+            if leaders.contains(&insn.offset) || blocks.is_empty() {
+                blocks.push(BasicBlock {
+                    id: blocks.len(),
+                    start: insn.offset,
+                    end: insn.offset,
+                    reachable: false,
+                    instructions: Vec::new(),
+                });
+            }
+
+            let block = blocks.last_mut().unwrap();
+            block.instructions.push(insn);
+        }
+
+        for i in 0..blocks.len() {
+            blocks[i].end = match blocks.get(i + 1) {
+                Some(next) => next.start,
+                None => code_length,
+            };
+        }
+
+        let block_at: BTreeMap<u32, usize> = blocks.iter().map(|b| (b.start, b.id)).collect();
+        let mut edges = Vec::new();
+
+        // Instructions following a jsr, where a ret may return to.
+        let return_sites: Vec<usize> = blocks
+            .iter()
+            .filter(|b| is_jsr(b.instructions.last().unwrap().opcode))
+            .filter_map(|b| block_at.get(&b.end).cloned())
+            .collect();
+
+        for block in &blocks {
+            let last = block.instructions.last().unwrap();
+            let mut edge = |to: u32, kind: EdgeKind| {
+                edges.push(Edge {
+                    from: block.id,
+                    to: block_at[&to],
+                    kind,
+                });
+            };
+
+            match last.operand {
+                Operand::Branch(target) if is_jsr(last.opcode) => edge(target, EdgeKind::Subroutine),
+                Operand::Branch(target) => edge(target, EdgeKind::Jump),
+                Operand::TableSwitch {
+                    default,
+                    low,
+                    ref targets,
+                } => {
+                    for (i, &target) in targets.iter().enumerate() {
+                        edge(target, EdgeKind::Switch { key: Some(low + i as i32) });
+                    }
+                    edge(default, EdgeKind::Switch { key: None });
+                }
+                Operand::LookupSwitch { default, ref pairs } => {
+                    for &(key, target) in pairs {
+                        edge(target, EdgeKind::Switch { key: Some(key) });
+                    }
+                    edge(default, EdgeKind::Switch { key: None });
+                }
+                _ => {}
+            }
+
+            // ret
+            if last.opcode == 0xa9 {
+                for &site in &return_sites {
+                    edge(blocks[site].start, EdgeKind::Subroutine);
+                }
+            }
+
+            if !ends_flow(last.opcode) && block.end < code_length {
+                edge(block.end, EdgeKind::FallThrough);
+            }
+
+            for entry in &code.exception_table {
+                if block.start >= entry.start_pc as u32 && block.start < entry.end_pc as u32 {
+                    let catch_type = match entry.catch_type {
+                        0 => None,
+                        index => Some(constant_pool.class_name(index).unwrap_or_else(|| format!("#{}", index))),
+                    };
+                    edge(entry.handler_pc as u32, EdgeKind::Exception { catch_type });
+                }
+            }
+        }
+
+        let mut graph = ControlFlowGraph { blocks, edges };
+        graph.mark_reachable();
+
+        Ok(graph)
+    }
+
+    fn mark_reachable(&mut self) {
+        let mut queue = VecDeque::new();
+
+        if !self.blocks.is_empty() {
+            self.blocks[0].reachable = true;
+            queue.push_back(0);
+        }
+
+        while let Some(id) = queue.pop_front() {
+            for to in self.successors(id) {
+                if !self.blocks[to].reachable {
+                    self.blocks[to].reachable = true;
+                    queue.push_back(to);
+                }
+            }
+        }
+    }
+
+    pub fn successors(&self, block: usize) -> Vec<usize> {
+        self.edges.iter().filter(|e| e.from == block).map(|e| e.to).collect()
+    }
+
+    pub fn predecessors(&self, block: usize) -> Vec<usize> {
+        self.edges.iter().filter(|e| e.to == block).map(|e| e.from).collect()
+    }
+
+    /// The block containing the instruction at `offset`.
+    pub fn block_at(&self, offset: u32) -> Option<&BasicBlock> {
+        self.blocks.iter().find(|b| b.start <= offset && offset < b.end)
+    }
+
+    pub fn unreachable_blocks(&self) -> Vec<&BasicBlock> {
+        self.blocks.iter().filter(|b| !b.reachable).collect()
+    }
+
+    /// Renders the graph in GraphViz DOT syntax, with constant pool operands
+    /// described in comments.
+    pub fn to_dot(&self, name: &str, constant_pool: &ConstantPool) -> String {
+        let mut out = format!("digraph {} {{\n", dot_string(name));
+        out.push_str("    node [shape=box, fontname=monospace];\n");
+
+        for block in &self.blocks {
+            // Instructions are left-aligned by ending each line with \l.
+            let mut label = String::new();
+
+            for insn in &block.instructions {
+                let mut line = format!("{}: {}", insn.offset, insn);
+                if let Some(comment) = describe_operand(insn, constant_pool) {
+                    line.push_str(" // ");
+                    line.push_str(&comment);
+                }
+                label.push_str(&dot_escape(&line));
+                label.push_str("\\l");
+            }
+
+            let style = if block.reachable { "" } else { ", style=dashed, color=gray" };
+            out.push_str(&format!(
+                "    b{} [label=\"{}\"{}];\n",
+                block.id,
+                label,
+                style
+            ));
+        }
+
+        for edge in &self.edges {
+            let attributes = match edge.kind {
+                EdgeKind::FallThrough => String::new(),
+                EdgeKind::Jump => " [color=blue]".to_string(),
+                EdgeKind::Switch { key: Some(key) } => format!(" [label=\"{}\"]", key),
+                EdgeKind::Switch { key: None } => " [label=\"default\"]".to_string(),
+                EdgeKind::Exception { ref catch_type } => format!(
+                    " [style=dashed, color=red, label={}]",
+                    dot_string(catch_type.as_deref().unwrap_or("any"))
+                ),
+                EdgeKind::Subroutine => " [style=dotted]".to_string(),
+            };
+
+            out.push_str(&format!("    b{} -> b{}{};\n", edge.from, edge.to, attributes));
+        }
+
+        out.push_str("}\n");
+        out
+    }
+}
+
+fn describe_operand(insn: &Instruction, constant_pool: &ConstantPool) -> Option<String> {
+    match insn.operand {
+        Operand::Constant(index)
+        | Operand::InvokeInterface { index, .. }
+        | Operand::InvokeDynamic(index)
+        | Operand::MultiANewArray { index, .. } => constant_pool.describe(index),
+        _ => None,
+    }
+}
+
+pub(crate) fn dot_string(s: &str) -> String {
+    format!("\"{}\"", dot_escape(s))
+}
+
+pub(crate) fn dot_escape(s: &str) -> String {
+    let mut out = String::new();
+
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+
+    out
+}
@@ -0,0 +1,1423 @@
+// Data-flow analysis of the operand stack and local variable types at every
+// instruction of a method, in the style of the JVM type inference verifier.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+
+use serde::{Serialize, Serializer};
+
+use bytecode::cfg::{ends_flow, is_jsr};
+use bytecode::hierarchy::{ClassHierarchy, NoHierarchy, WithClass};
+use bytecode::{decode, DecodeError, Instruction, Operand};
+use class_file::access_flags::ACC_STATIC;
+use class_file::attributes::{CodeAttribute, ExceptionTableEntry};
+use class_file::constant_pool::*;
+use class_file::descriptor::*;
+use class_file::methods::MethodInfo;
+use class_file::stack_map_table::*;
+use class_file::ClassFile;
+use serialization::serialize_instruction;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Type {
+    Top,
+    Integer,
+    Float,
+    Long,
+    Double,
+    Null,
+    UninitializedThis,
+    // Offset of the `new` instruction that created the object.
+    Uninitialized(u32),
+    // Internal name of a class, or the descriptor of an array type.
+    Reference(String),
+    // Pushed by jsr and consumed by ret.
+    ReturnAddress,
+}
+
+/// Types of the local variables and operand stack before an instruction.
+/// Both hold one entry per slot, so a long or double is followed by a Top
+/// standing for its second half.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Frame {
+    #[serde(serialize_with = "serialize_types")]
+    pub locals: Vec<Type>,
+
+    #[serde(serialize_with = "serialize_types")]
+    pub stack: Vec<Type>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InstructionFrame {
+    pub offset: u32,
+
+    #[serde(serialize_with = "serialize_instruction")]
+    pub instruction: Instruction,
+
+    // None for unreachable instructions.
+    #[serde(flatten)]
+    pub frame: Option<Frame>,
+}
+
+/// A difference between the inferred types and a frame of the method's
+/// StackMapTable.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Mismatch {
+    pub offset: u32,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FrameAnalysis {
+    pub frames: Vec<InstructionFrame>,
+    pub mismatches: Vec<Mismatch>,
+}
+
+/// Limits and stack map frames recomputed from a method's code.
+#[derive(Debug)]
+pub struct ComputedCode {
+    pub max_stack: u16,
+    pub max_locals: u16,
+
+    // The original code, except that unreachable instructions are replaced
+    // by nops ending in athrow so that they verify with any frame.
+    pub code: Vec<u8>,
+
+    // The original exception table, minus the ranges of unreachable code.
+    pub exception_table: Vec<ExceptionTableEntry>,
+
+    // Locals of the implicit initial frame, in StackMapTable form.
+    pub initial_locals: Vec<Type>,
+
+    // Frames at every offset where the JVM requires one, in offset order.
+    // Empty for classes older than version 50.
+    pub frames: Vec<(u32, Frame)>,
+}
+
+const OBJECT: &str = "java/lang/Object";
+
+impl Type {
+    pub fn is_category2(&self) -> bool {
+        matches!(*self, Type::Long | Type::Double)
+    }
+
+    /// Whether a value of this type may be used where a reference is
+    /// expected, such as by aload or putfield.
+    pub fn is_reference(&self) -> bool {
+        matches!(
+            *self,
+            Type::Null | Type::UninitializedThis | Type::Uninitialized(_) | Type::Reference(_)
+        )
+    }
+
+    pub fn from_field_type(field_type: &FieldType) -> Type {
+        match *field_type {
+            FieldType::Byte | FieldType::Char | FieldType::Short | FieldType::Boolean | FieldType::Int => Type::Integer,
+            FieldType::Float => Type::Float,
+            FieldType::Long => Type::Long,
+            FieldType::Double => Type::Double,
+            FieldType::Object(ref name) => Type::Reference(name.clone()),
+            FieldType::Array(_) => Type::Reference(field_type.to_descriptor()),
+        }
+    }
+
+    pub fn from_verification_type(verification_type: &VerificationType, constant_pool: &ConstantPool) -> Type {
+        match *verification_type {
+            VerificationType::Top => Type::Top,
+            VerificationType::Integer => Type::Integer,
+            VerificationType::Float => Type::Float,
+            VerificationType::Double => Type::Double,
+            VerificationType::Long => Type::Long,
+            VerificationType::Null => Type::Null,
+            VerificationType::UninitializedThis => Type::UninitializedThis,
+            VerificationType::Object(index) => {
+                Type::Reference(constant_pool.class_name(index).unwrap_or_else(|| format!("#{}", index)))
+            }
+            VerificationType::Uninitialized(offset) => Type::Uninitialized(offset as u32),
+        }
+    }
+
+    /// Whether a value of this type is acceptable where `expected` is
+    /// declared.
+    pub fn is_assignable_to(&self, expected: &Type, hierarchy: &dyn ClassHierarchy) -> bool {
+        match (self, expected) {
+            (_, &Type::Top) => true,
+            (a, b) if a == b => true,
+            (&Type::Null, &Type::Reference(_)) => true,
+            (Type::Reference(from), Type::Reference(to)) => is_reference_assignable(from, to, hierarchy),
+            _ => false,
+        }
+    }
+}
+
+fn is_reference_assignable(from: &str, to: &str, hierarchy: &dyn ClassHierarchy) -> bool {
+    if to == OBJECT {
+        return true;
+    }
+
+    match (from.strip_prefix('['), to.strip_prefix('[')) {
+        (Some(from), Some(to)) => {
+            let from_reference = from.starts_with('L') || from.starts_with('[');
+            let to_reference = to.starts_with('L') || to.starts_with('[');
+
+            if from_reference && to_reference {
+                is_reference_assignable(&component_name(from), &component_name(to), hierarchy)
+            } else {
+                from == to
+            }
+        }
+        (Some(_), None) => to == "java/lang/Cloneable" || to == "java/io/Serializable",
+        (None, Some(_)) => false,
+        (None, None) => hierarchy.is_assignable(from, to),
+    }
+}
+
+// Internal name of an array component given its descriptor.
+fn component_name(descriptor: &str) -> String {
+    match descriptor.strip_prefix('L') {
+        Some(name) => name.trim_end_matches(';').to_string(),
+        None => descriptor.to_string(),
+    }
+}
+
+fn array_of(component: &str) -> String {
+    if component.starts_with('[') {
+        format!("[{}", component)
+    } else {
+        format!("[L{};", component)
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Type::Top => write!(f, "top"),
+            Type::Integer => write!(f, "int"),
+            Type::Float => write!(f, "float"),
+            Type::Long => write!(f, "long"),
+            Type::Double => write!(f, "double"),
+            Type::Null => write!(f, "null"),
+            Type::UninitializedThis => write!(f, "uninitializedThis"),
+            Type::Uninitialized(offset) => write!(f, "uninitialized({})", offset),
+            Type::Reference(ref name) => write!(f, "{}", name),
+            Type::ReturnAddress => write!(f, "returnAddress"),
+        }
+    }
+}
+
+impl Serialize for Type {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer {
+        serializer.collect_str(self)
+    }
+}
+
+// Slots are written the way a StackMapTable lists them: the second half of a
+// long or double is left out, and so are trailing unused locals.
+fn serialize_types<S>(v: &[Type], s: S) -> Result<S::Ok, S::Error>
+    where S: Serializer {
+    s.collect_seq(compress(v))
+}
+
+/// Folds slot lists back into StackMapTable form, where a long or double
+/// takes a single entry and trailing Tops are dropped.
+pub fn compress(slots: &[Type]) -> Vec<&Type> {
+    let mut types = Vec::new();
+    let mut i = 0;
+
+    while i < slots.len() {
+        types.push(&slots[i]);
+        i += if slots[i].is_category2() { 2 } else { 1 };
+    }
+
+    while types.last() == Some(&&Type::Top) {
+        types.pop();
+    }
+
+    types
+}
+
+/// Expands StackMapTable-style type lists into one entry per slot.
+pub fn expand(types: &[Type]) -> Vec<Type> {
+    let mut slots = Vec::new();
+
+    for t in types {
+        slots.push(t.clone());
+        if t.is_category2() {
+            slots.push(Type::Top);
+        }
+    }
+
+    slots
+}
+
+fn merge_types(a: &Type, b: &Type, hierarchy: &dyn ClassHierarchy) -> Type {
+    match (a, b) {
+        (a, b) if a == b => a.clone(),
+        (&Type::Null, &Type::Reference(_)) => b.clone(),
+        (&Type::Reference(_), &Type::Null) => a.clone(),
+        (Type::Reference(a), Type::Reference(b)) => Type::Reference(common_superclass(a, b, hierarchy)),
+        _ => Type::Top,
+    }
+}
+
+// Arrays of references merge component-wise, other arrays only have Object
+// in common with anything else.
+fn common_superclass(a: &str, b: &str, hierarchy: &dyn ClassHierarchy) -> String {
+    match (a.strip_prefix('['), b.strip_prefix('[')) {
+        (Some(a), Some(b)) => {
+            let a_reference = a.starts_with('L') || a.starts_with('[');
+            let b_reference = b.starts_with('L') || b.starts_with('[');
+
+            if a_reference && b_reference {
+                array_of(&common_superclass(&component_name(a), &component_name(b), hierarchy))
+            } else {
+                String::from(OBJECT)
+            }
+        }
+        (None, None) => hierarchy.common_superclass(a, b),
+        _ => String::from(OBJECT),
+    }
+}
+
+impl Frame {
+    fn merge(&mut self, other: &Frame, hierarchy: &dyn ClassHierarchy) -> Result<bool, String> {
+        if self.stack.len() != other.stack.len() {
+            return Err(format!(
+                "stack height {} does not match {} on another path",
+                other.stack.len(),
+                self.stack.len()
+            ));
+        }
+
+        let mut changed = false;
+
+        for (i, (mine, theirs)) in self.stack.iter_mut().zip(&other.stack).enumerate() {
+            let merged = merge_types(mine, theirs, hierarchy);
+
+            if merged == Type::Top && *mine != Type::Top {
+                return Err(format!("stack slot {} holds {} and {} on different paths", i, mine, theirs));
+            }
+            if merged != *mine {
+                *mine = merged;
+                changed = true;
+            }
+        }
+
+        for (mine, theirs) in self.locals.iter_mut().zip(&other.locals) {
+            let merged = merge_types(mine, theirs, hierarchy);
+
+            if merged != *mine {
+                *mine = merged;
+                changed = true;
+            }
+        }
+
+        Ok(changed)
+    }
+
+    fn push(&mut self, t: Type) {
+        let wide = t.is_category2();
+        self.stack.push(t);
+
+        if wide {
+            self.stack.push(Type::Top);
+        }
+    }
+
+    fn pop_slot(&mut self) -> Result<Type, String> {
+        self.stack.pop().ok_or_else(|| String::from("operand stack underflow"))
+    }
+
+    pub(crate) fn pop(&mut self) -> Result<Type, String> {
+        let t = self.pop_slot()?;
+
+        if t == Type::Top && self.stack.last().is_some_and(Type::is_category2) {
+            return self.pop_slot();
+        }
+
+        Ok(t)
+    }
+
+    fn pop_expect(&mut self, expected: &Type) -> Result<(), String> {
+        let t = self.pop()?;
+
+        if t == *expected {
+            Ok(())
+        } else {
+            Err(format!("expected {} on the stack, found {}", expected, t))
+        }
+    }
+
+    fn pop_reference(&mut self) -> Result<Type, String> {
+        let t = self.pop()?;
+
+        if t.is_reference() {
+            Ok(t)
+        } else {
+            Err(format!("expected a reference on the stack, found {}", t))
+        }
+    }
+
+    fn pop_value(&mut self, expected: &Type) -> Result<(), String> {
+        if expected.is_reference() {
+            self.pop_reference().map(|_| ())
+        } else {
+            self.pop_expect(expected)
+        }
+    }
+
+    fn load(&self, index: u16, expected: &Type) -> Result<Type, String> {
+        let t = self
+            .locals
+            .get(index as usize)
+            .ok_or_else(|| format!("local {} is out of range", index))?;
+
+        let matches = if *expected == Type::Top {
+            t.is_reference()
+        } else {
+            t == expected
+        };
+
+        if !matches {
+            return Err(format!("local {} holds {}", index, t));
+        }
+        if t.is_category2() && index as usize + 1 >= self.locals.len() {
+            return Err(format!("local {} is out of range", index + 1));
+        }
+
+        Ok(t.clone())
+    }
+
+    fn store(&mut self, index: u16, t: Type) -> Result<(), String> {
+        let index = index as usize;
+        let size = if t.is_category2() { 2 } else { 1 };
+
+        if index + size > self.locals.len() {
+            return Err(format!("local {} is out of range", index + size - 1));
+        }
+
+        // Overwriting the second half of a long or double invalidates it.
+        if index > 0 && self.locals[index - 1].is_category2() {
+            self.locals[index - 1] = Type::Top;
+        }
+
+        self.locals[index] = t;
+        if size == 2 {
+            self.locals[index + 1] = Type::Top;
+        }
+
+        Ok(())
+    }
+
+    // Replaces an object being constructed with its initialized type once
+    // its constructor has been called.
+    fn initialize(&mut self, uninitialized: &Type, initialized: &Type) {
+        for t in self.locals.iter_mut().chain(self.stack.iter_mut()) {
+            if t == uninitialized {
+                *t = initialized.clone();
+            }
+        }
+    }
+}
+
+pub(crate) struct Interpreter<'a> {
+    constant_pool: &'a ConstantPool,
+    hierarchy: &'a dyn ClassHierarchy,
+    this_class: String,
+    instructions: &'a [Instruction],
+    index_of: HashMap<u32, usize>,
+}
+
+impl FrameAnalysis {
+    /// Infers the frame before every instruction of a method and compares
+    /// them with its StackMapTable, if the class version requires one.
+    pub fn analyze(cf: &ClassFile, method: &MethodInfo, code: &CodeAttribute) -> Result<FrameAnalysis, DecodeError> {
+        FrameAnalysis::analyze_with(cf, method, code, &NoHierarchy)
+    }
+
+    /// Like `analyze`, but merges and compares classes using `hierarchy`.
+    pub fn analyze_with(
+        cf: &ClassFile,
+        method: &MethodInfo,
+        code: &CodeAttribute,
+        hierarchy: &dyn ClassHierarchy,
+    ) -> Result<FrameAnalysis, DecodeError> {
+        let instructions = decode(&code.code)?;
+        let hierarchy = WithClass::new(hierarchy, cf);
+        let interpreter = Interpreter::new(cf, &hierarchy, &instructions);
+
+        let initial = initial_locals(cf, method);
+        let states = interpreter.run(code, initial_frame(&initial, code.max_locals)?)?;
+
+        let mut analysis = FrameAnalysis {
+            frames: Vec::new(),
+            mismatches: Vec::new(),
+        };
+
+        if cf.major_version >= 50 {
+            analysis.mismatches = interpreter.check_stack_map_table(code, initial, &states);
+        }
+
+        analysis.frames = instructions
+            .iter()
+            .cloned()
+            .zip(states)
+            .map(|(instruction, frame)| InstructionFrame {
+                offset: instruction.offset,
+                instruction,
+                frame,
+            })
+            .collect();
+
+        Ok(analysis)
+    }
+
+    pub fn frame_at(&self, offset: u32) -> Option<&Frame> {
+        self.frames
+            .iter()
+            .find(|f| f.offset == offset)
+            .and_then(|f| f.frame.as_ref())
+    }
+}
+
+pub(crate) fn initial_frame(initial: &[Type], max_locals: u16) -> Result<Frame, DecodeError> {
+    let mut locals = expand(initial);
+
+    if locals.len() > max_locals as usize {
+        return Err(DecodeError {
+            offset: 0,
+            message: format!("parameters need more than max_locals {}", max_locals),
+        });
+    }
+    locals.resize(max_locals as usize, Type::Top);
+
+    Ok(Frame {
+        locals,
+        stack: Vec::new(),
+    })
+}
+
+// The locals of the implicit initial frame, in StackMapTable form.
+pub(crate) fn initial_locals(cf: &ClassFile, method: &MethodInfo) -> Vec<Type> {
+    let cp = &cf.constant_pool;
+    let this_class = cf.name().unwrap_or_default();
+    let mut locals = Vec::new();
+
+    if method.access_flags & ACC_STATIC == 0 {
+        let constructor = cp.utf8(method.name_index).is_some_and(|n| n == "<init>");
+
+        if constructor && this_class != OBJECT {
+            locals.push(Type::UninitializedThis);
+        } else {
+            locals.push(Type::Reference(this_class));
+        }
+    }
+
+    let descriptor = cp.utf8(method.descriptor_index).and_then(|d| parse_method_descriptor(&d));
+
+    if let Some(descriptor) = descriptor {
+        locals.extend(descriptor.parameters.iter().map(Type::from_field_type));
+    }
+
+    locals
+}
+
+impl<'a> Interpreter<'a> {
+    pub(crate) fn new(cf: &'a ClassFile, hierarchy: &'a dyn ClassHierarchy, instructions: &'a [Instruction]) -> Interpreter<'a> {
+        Interpreter {
+            constant_pool: &cf.constant_pool,
+            hierarchy,
+            this_class: cf.name().unwrap_or_default(),
+            instructions,
+            index_of: instructions.iter().enumerate().map(|(i, insn)| (insn.offset, i)).collect(),
+        }
+    }
+
+    // Iterates to a fixed point, returning the frame before each instruction.
+    fn run(&self, code: &CodeAttribute, initial: Frame) -> Result<Vec<Option<Frame>>, DecodeError> {
+        let mut states: Vec<Option<Frame>> = vec![None; self.instructions.len()];
+        let mut pending = BTreeSet::new();
+
+        if self.instructions.is_empty() {
+            return Ok(states);
+        }
+
+        states[0] = Some(initial);
+        pending.insert(0);
+
+        // Instructions following a jsr, where a ret may return to.
+        let return_sites: Vec<usize> = (0..self.instructions.len() - 1)
+            .filter(|&i| is_jsr(self.instructions[i].opcode))
+            .map(|i| i + 1)
+            .collect();
+
+        while let Some(i) = pending.pop_first() {
+            let insn = &self.instructions[i];
+            let before = states[i].clone().unwrap();
+            let error = |message: String| DecodeError {
+                offset: insn.offset,
+                message,
+            };
+
+            let mut successors = Vec::new();
+
+            for entry in &code.exception_table {
+                if insn.offset >= entry.start_pc as u32 && insn.offset < entry.end_pc as u32 {
+                    let catch_type = match entry.catch_type {
+                        0 => String::from("java/lang/Throwable"),
+                        index => self.class_name(index).map_err(&error)?,
+                    };
+
+                    let handler = Frame {
+                        locals: before.locals.clone(),
+                        stack: vec![Type::Reference(catch_type)],
+                    };
+                    successors.push((entry.handler_pc as u32, handler));
+                }
+            }
+
+            let mut after = before;
+            self.execute(insn, &mut after).map_err(&error)?;
+
+            if insn.opcode == 0xa9 {
+                // ret
+                for &site in &return_sites {
+                    successors.push((self.instructions[site].offset, after.clone()));
+                }
+            } else {
+                for target in insn.branch_targets() {
+                    successors.push((target, after.clone()));
+                }
+            }
+
+            if !ends_flow(insn.opcode) {
+                match self.instructions.get(i + 1) {
+                    Some(next) => successors.push((next.offset, after)),
+                    None => return Err(error(String::from("execution falls off the end of the code"))),
+                }
+            }
+
+            for (offset, frame) in successors {
+                let target = match self.index_of.get(&offset) {
+                    Some(&target) => target,
+                    None => return Err(error(format!("offset {} is not at an instruction", offset))),
+                };
+
+                let changed = match states[target] {
+                    Some(ref mut existing) => existing
+                        .merge(&frame, self.hierarchy)
+                        .map_err(|message| DecodeError { offset, message })?,
+                    None => {
+                        states[target] = Some(frame);
+                        true
+                    }
+                };
+
+                if changed {
+                    pending.insert(target);
+                }
+            }
+        }
+
+        Ok(states)
+    }
+
+    pub(crate) fn class_name(&self, index: u16) -> Result<String, String> {
+        self.constant_pool
+            .class_name(index)
+            .ok_or_else(|| format!("constant #{} is not a class", index))
+    }
+
+    pub(crate) fn member_descriptor(&self, index: u16) -> Result<(String, String, String), String> {
+        self.constant_pool
+            .member_ref(index)
+            .ok_or_else(|| format!("constant #{} is not a member reference", index))
+    }
+
+    pub(crate) fn dynamic_descriptor(&self, index: u16) -> Result<String, String> {
+        let name_and_type_index = match self.constant_pool.get(index).map(|info| info.as_any()) {
+            Some(info) if info.is::<InvokeDynamicInfo>() => {
+                info.downcast_ref::<InvokeDynamicInfo>().unwrap().name_and_type_index
+            }
+            Some(info) if info.is::<DynamicInfo>() => info.downcast_ref::<DynamicInfo>().unwrap().name_and_type_index,
+            _ => return Err(format!("constant #{} is not dynamically computed", index)),
+        };
+
+        self.constant_pool
+            .name_and_type(name_and_type_index)
+            .map(|(_, descriptor)| descriptor)
+            .ok_or_else(|| format!("constant #{} has a bad name and type", index))
+    }
+
+    fn constant_type(&self, index: u16) -> Result<Type, String> {
+        let t = match self.constant_pool.tag(index) {
+            Some(CpTag::Integer) => Type::Integer,
+            Some(CpTag::Float) => Type::Float,
+            Some(CpTag::Long) => Type::Long,
+            Some(CpTag::Double) => Type::Double,
+            Some(CpTag::String) => Type::Reference(String::from("java/lang/String")),
+            Some(CpTag::Class) => Type::Reference(String::from("java/lang/Class")),
+            Some(CpTag::MethodType) => Type::Reference(String::from("java/lang/invoke/MethodType")),
+            Some(CpTag::MethodHandle) => Type::Reference(String::from("java/lang/invoke/MethodHandle")),
+            Some(CpTag::Dynamic) => {
+                let descriptor = self.dynamic_descriptor(index)?;
+                let field_type = parse_field_descriptor(&descriptor)
+                    .ok_or_else(|| format!("bad descriptor {}", descriptor))?;
+                Type::from_field_type(&field_type)
+            }
+            _ => return Err(format!("constant #{} is not loadable", index)),
+        };
+
+        Ok(t)
+    }
+
+    fn invoke(&self, insn: &Instruction, index: u16, frame: &mut Frame) -> Result<(), String> {
+        let (class, name, descriptor) = if insn.opcode == 0xba {
+            (String::new(), String::new(), self.dynamic_descriptor(index)?)
+        } else {
+            self.member_descriptor(index)?
+        };
+
+        let method = parse_method_descriptor(&descriptor).ok_or_else(|| format!("bad descriptor {}", descriptor))?;
+
+        for parameter in method.parameters.iter().rev() {
+            frame.pop_value(&Type::from_field_type(parameter))?;
+        }
+
+        // invokestatic and invokedynamic have no receiver.
+        if insn.opcode != 0xb8 && insn.opcode != 0xba {
+            let receiver = frame.pop_reference()?;
+
+            if name == "<init>" {
+                let initialized = match receiver {
+                    Type::UninitializedThis => Type::Reference(self.this_class.clone()),
+                    Type::Uninitialized(offset) => Type::Reference(self.new_class(offset).unwrap_or(class)),
+                    _ => return Err(format!("<init> called on initialized {}", receiver)),
+                };
+                frame.initialize(&receiver, &initialized);
+            }
+        }
+
+        if let Some(ref return_type) = method.return_type {
+            frame.push(Type::from_field_type(return_type));
+        }
+
+        Ok(())
+    }
+
+    // Class named by the `new` instruction at an offset.
+    pub(crate) fn new_class(&self, offset: u32) -> Option<String> {
+        let insn = &self.instructions[*self.index_of.get(&offset)?];
+
+        match insn.operand {
+            Operand::Constant(index) if insn.opcode == 0xbb => self.constant_pool.class_name(index),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn execute(&self, insn: &Instruction, frame: &mut Frame) -> Result<(), String> {
+        let op = insn.opcode;
+        let local = match insn.operand {
+            Operand::Local(index) | Operand::Iinc { index, .. } => index,
+            _ => 0,
+        };
+        let index = match insn.operand {
+            Operand::Constant(index)
+            | Operand::InvokeInterface { index, .. }
+            | Operand::InvokeDynamic(index)
+            | Operand::MultiANewArray { index, .. } => index,
+            _ => 0,
+        };
+
+        // Element types of the xaload/xastore and typed load/store families,
+        // in opcode order.
+        const KINDS: [Type; 4] = [Type::Integer, Type::Long, Type::Float, Type::Double];
+
+        match op {
+            // nop
+            0x00 => {}
+            // aconst_null
+            0x01 => frame.push(Type::Null),
+            // iconst_m1..iconst_5, bipush, sipush
+            0x02..=0x08 | 0x10 | 0x11 => frame.push(Type::Integer),
+            0x09 | 0x0a => frame.push(Type::Long),
+            0x0b..=0x0d => frame.push(Type::Float),
+            0x0e | 0x0f => frame.push(Type::Double),
+            // ldc, ldc_w, ldc2_w
+            0x12..=0x14 => {
+                let t = self.constant_type(index)?;
+                if t.is_category2() != (op == 0x14) {
+                    return Err(format!("{} cannot load constant #{}", insn.mnemonic(), index));
+                }
+                frame.push(t);
+            }
+            // iload, lload, fload, dload
+            0x15..=0x18 => {
+                let t = frame.load(local, &KINDS[(op - 0x15) as usize])?;
+                frame.push(t);
+            }
+            // aload
+            0x19 => {
+                let t = frame.load(local, &Type::Top)?;
+                frame.push(t);
+            }
+            // iload_0..dload_3
+            0x1a..=0x29 => {
+                let t = frame.load((op - 0x1a) as u16 % 4, &KINDS[((op - 0x1a) / 4) as usize])?;
+                frame.push(t);
+            }
+            // aload_0..aload_3
+            0x2a..=0x2d => {
+                let t = frame.load((op - 0x2a) as u16, &Type::Top)?;
+                frame.push(t);
+            }
+            // iaload, laload, faload, daload
+            0x2e..=0x31 => {
+                frame.pop_expect(&Type::Integer)?;
+                frame.pop_reference()?;
+                frame.push(KINDS[(op - 0x2e) as usize].clone());
+            }
+            // aaload
+            0x32 => {
+                frame.pop_expect(&Type::Integer)?;
+                let element = match frame.pop_reference()? {
+                    Type::Reference(ref array) if array.starts_with('[') => Type::Reference(component_name(&array[1..])),
+                    Type::Null => Type::Null,
+                    _ => Type::Reference(String::from(OBJECT)),
+                };
+                frame.push(element);
+            }
+            // baload, caload, saload
+            0x33..=0x35 => {
+                frame.pop_expect(&Type::Integer)?;
+                frame.pop_reference()?;
+                frame.push(Type::Integer);
+            }
+            // istore, lstore, fstore, dstore
+            0x36..=0x39 => {
+                let t = KINDS[(op - 0x36) as usize].clone();
+                frame.pop_expect(&t)?;
+                frame.store(local, t)?;
+            }
+            // astore, which may also store a return address
+            0x3a => {
+                let t = frame.pop()?;
+                if !t.is_reference() && t != Type::ReturnAddress {
+                    return Err(format!("expected a reference on the stack, found {}", t));
+                }
+                frame.store(local, t)?;
+            }
+            // istore_0..dstore_3
+            0x3b..=0x4a => {
+                let t = KINDS[((op - 0x3b) / 4) as usize].clone();
+                frame.pop_expect(&t)?;
+                frame.store((op - 0x3b) as u16 % 4, t)?;
+            }
+            // astore_0..astore_3
+            0x4b..=0x4e => {
+                let t = frame.pop()?;
+                if !t.is_reference() && t != Type::ReturnAddress {
+                    return Err(format!("expected a reference on the stack, found {}", t));
+                }
+                frame.store((op - 0x4b) as u16, t)?;
+            }
+            // iastore, lastore, fastore, dastore
+            0x4f..=0x52 => {
+                frame.pop_expect(&KINDS[(op - 0x4f) as usize])?;
+                frame.pop_expect(&Type::Integer)?;
+                frame.pop_reference()?;
+            }
+            // aastore
+            0x53 => {
+                frame.pop_reference()?;
+                frame.pop_expect(&Type::Integer)?;
+                frame.pop_reference()?;
+            }
+            // bastore, castore, sastore
+            0x54..=0x56 => {
+                frame.pop_expect(&Type::Integer)?;
+                frame.pop_expect(&Type::Integer)?;
+                frame.pop_reference()?;
+            }
+            // pop
+            0x57 => {
+                frame.pop_slot()?;
+            }
+            // pop2
+            0x58 => {
+                frame.pop_slot()?;
+                frame.pop_slot()?;
+            }
+            // dup, dup_x1, dup_x2
+            0x59..=0x5b => {
+                let depth = (op - 0x59) as usize;
+                let v1 = frame.pop_slot()?;
+                let below = pop_slots(frame, depth)?;
+                frame.stack.push(v1.clone());
+                frame.stack.extend(below);
+                frame.stack.push(v1);
+            }
+            // dup2, dup2_x1, dup2_x2
+            0x5c..=0x5e => {
+                let depth = (op - 0x5c) as usize;
+                let top = pop_slots(frame, 2)?;
+                let below = pop_slots(frame, depth)?;
+                frame.stack.extend(top.iter().cloned());
+                frame.stack.extend(below);
+                frame.stack.extend(top);
+            }
+            // swap
+            0x5f => {
+                let v1 = frame.pop_slot()?;
+                let v2 = frame.pop_slot()?;
+                frame.stack.push(v1);
+                frame.stack.push(v2);
+            }
+            // iadd..drem
+            0x60..=0x73 => {
+                let t = &KINDS[((op - 0x60) % 4) as usize];
+                frame.pop_expect(t)?;
+                frame.pop_expect(t)?;
+                frame.push(t.clone());
+            }
+            // ineg..dneg
+            0x74..=0x77 => {
+                let t = &KINDS[(op - 0x74) as usize];
+                frame.pop_expect(t)?;
+                frame.push(t.clone());
+            }
+            // ishl..lushr
+            0x78..=0x7d => {
+                let t = &KINDS[((op - 0x78) % 2) as usize];
+                frame.pop_expect(&Type::Integer)?;
+                frame.pop_expect(t)?;
+                frame.push(t.clone());
+            }
+            // iand..lxor
+            0x7e..=0x83 => {
+                let t = &KINDS[((op - 0x7e) % 2) as usize];
+                frame.pop_expect(t)?;
+                frame.pop_expect(t)?;
+                frame.push(t.clone());
+            }
+            // iinc
+            0x84 => {
+                frame.load(local, &Type::Integer)?;
+            }
+            // i2l..d2f
+            0x85..=0x90 => {
+                let from = ((op - 0x85) / 3) as usize;
+                let to = [1, 2, 3, 0, 2, 3, 0, 1, 3, 0, 1, 2][(op - 0x85) as usize];
+                frame.pop_expect(&KINDS[from])?;
+                frame.push(KINDS[to].clone());
+            }
+            // i2b, i2c, i2s
+            0x91..=0x93 => {
+                frame.pop_expect(&Type::Integer)?;
+                frame.push(Type::Integer);
+            }
+            // lcmp, fcmpl, fcmpg, dcmpl, dcmpg
+            0x94..=0x98 => {
+                let t = &KINDS[[1, 2, 2, 3, 3][(op - 0x94) as usize]];
+                frame.pop_expect(t)?;
+                frame.pop_expect(t)?;
+                frame.push(Type::Integer);
+            }
+            // ifeq..ifle, tableswitch, lookupswitch
+            0x99..=0x9e | 0xaa | 0xab => frame.pop_expect(&Type::Integer)?,
+            // if_icmpeq..if_icmple
+            0x9f..=0xa4 => {
+                frame.pop_expect(&Type::Integer)?;
+                frame.pop_expect(&Type::Integer)?;
+            }
+            // if_acmpeq, if_acmpne
+            0xa5 | 0xa6 => {
+                frame.pop_reference()?;
+                frame.pop_reference()?;
+            }
+            // goto, goto_w, return
+            0xa7 | 0xc8 | 0xb1 => {}
+            // jsr, jsr_w
+            0xa8 | 0xc9 => frame.push(Type::ReturnAddress),
+            // ret
+            0xa9 => {
+                frame.load(local, &Type::ReturnAddress)?;
+            }
+            // ireturn, lreturn, freturn, dreturn
+            0xac..=0xaf => frame.pop_expect(&KINDS[(op - 0xac) as usize])?,
+            // areturn, athrow, monitorenter, monitorexit
+            0xb0 | 0xbf | 0xc2 | 0xc3 => {
+                frame.pop_reference()?;
+            }
+            // getstatic, putstatic, getfield, putfield
+            0xb2..=0xb5 => {
+                let (_, _, descriptor) = self.member_descriptor(index)?;
+                let field_type = parse_field_descriptor(&descriptor).ok_or_else(|| format!("bad descriptor {}", descriptor))?;
+                let t = Type::from_field_type(&field_type);
+
+                if op == 0xb3 || op == 0xb5 {
+                    frame.pop_value(&t)?;
+                }
+                if op == 0xb4 || op == 0xb5 {
+                    frame.pop_reference()?;
+                }
+                if op == 0xb2 || op == 0xb4 {
+                    frame.push(t);
+                }
+            }
+            // invokevirtual, invokespecial, invokestatic, invokeinterface,
+            // invokedynamic
+            0xb6..=0xba => self.invoke(insn, index, frame)?,
+            // new
+            0xbb => {
+                self.class_name(index)?;
+                frame.push(Type::Uninitialized(insn.offset));
+            }
+            // newarray
+            0xbc => {
+                let descriptor = match insn.operand {
+                    Operand::NewArray(4) => "[Z",
+                    Operand::NewArray(5) => "[C",
+                    Operand::NewArray(6) => "[F",
+                    Operand::NewArray(7) => "[D",
+                    Operand::NewArray(8) => "[B",
+                    Operand::NewArray(9) => "[S",
+                    Operand::NewArray(10) => "[I",
+                    Operand::NewArray(11) => "[J",
+                    _ => return Err(String::from("bad newarray type")),
+                };
+                frame.pop_expect(&Type::Integer)?;
+                frame.push(Type::Reference(String::from(descriptor)));
+            }
+            // anewarray
+            0xbd => {
+                let component = self.class_name(index)?;
+                frame.pop_expect(&Type::Integer)?;
+                frame.push(Type::Reference(array_of(&component)));
+            }
+            // arraylength
+            0xbe => {
+                frame.pop_reference()?;
+                frame.push(Type::Integer);
+            }
+            // checkcast
+            0xc0 => {
+                let class = self.class_name(index)?;
+                frame.pop_reference()?;
+                frame.push(Type::Reference(class));
+            }
+            // instanceof
+            0xc1 => {
+                self.class_name(index)?;
+                frame.pop_reference()?;
+                frame.push(Type::Integer);
+            }
+            // multianewarray
+            0xc5 => {
+                let class = self.class_name(index)?;
+                if let Operand::MultiANewArray { dimensions, .. } = insn.operand {
+                    for _ in 0..dimensions {
+                        frame.pop_expect(&Type::Integer)?;
+                    }
+                }
+                frame.push(Type::Reference(class));
+            }
+            // ifnull, ifnonnull
+            0xc6 | 0xc7 => {
+                frame.pop_reference()?;
+            }
+            _ => return Err(format!("{} is not allowed in method code", insn.mnemonic())),
+        }
+
+        Ok(())
+    }
+
+    fn check_stack_map_table(&self, code: &CodeAttribute, initial: Vec<Type>, states: &[Option<Frame>]) -> Vec<Mismatch> {
+        let (declared, mut mismatches) = declared_frames(self.constant_pool, code, initial);
+        let declared_at: HashMap<u32, Frame> = declared.into_iter().collect();
+
+        let mut mismatch = |offset: u32, message: String| mismatches.push(Mismatch { offset, message });
+
+        // Every branch target and exception handler must have a frame, and
+        // so must any instruction following an unconditional transfer.
+        let mut required = BTreeSet::new();
+
+        for (i, insn) in self.instructions.iter().enumerate() {
+            required.extend(insn.branch_targets());
+
+            if ends_flow(insn.opcode) {
+                if let Some(next) = self.instructions.get(i + 1) {
+                    required.insert(next.offset);
+                }
+            }
+        }
+        required.extend(code.exception_table.iter().map(|e| e.handler_pc as u32));
+
+        for &offset in &required {
+            if !declared_at.contains_key(&offset) {
+                mismatch(offset, String::from("no StackMapTable frame at a branch target"));
+            }
+        }
+
+        let mut offsets: Vec<&u32> = declared_at.keys().collect();
+        offsets.sort();
+
+        for &offset in offsets {
+            let declared = &declared_at[&offset];
+
+            let inferred = match self.index_of.get(&offset) {
+                Some(&i) => &states[i],
+                None => {
+                    mismatch(offset, String::from("StackMapTable frame is not at an instruction"));
+                    continue;
+                }
+            };
+
+            let inferred = match *inferred {
+                Some(ref inferred) => inferred,
+                None => {
+                    mismatch(offset, String::from("StackMapTable frame at unreachable code"));
+                    continue;
+                }
+            };
+
+            for (i, (found, expected)) in inferred.locals.iter().zip(&declared.locals).enumerate() {
+                if !found.is_assignable_to(expected, self.hierarchy) {
+                    mismatch(offset, format!("local {} is {} but the frame declares {}", i, found, expected));
+                }
+            }
+
+            if inferred.stack.len() != declared.stack.len() {
+                mismatch(
+                    offset,
+                    format!(
+                        "stack has {} slots but the frame declares {}",
+                        inferred.stack.len(),
+                        declared.stack.len()
+                    ),
+                );
+                continue;
+            }
+
+            for (i, (found, expected)) in inferred.stack.iter().zip(&declared.stack).enumerate() {
+                if !found.is_assignable_to(expected, self.hierarchy) {
+                    mismatch(offset, format!("stack slot {} is {} but the frame declares {}", i, found, expected));
+                }
+            }
+        }
+
+        mismatches
+    }
+}
+
+/// The frames a method's StackMapTable declares, by offset, with one entry per
+/// slot and the locals padded to max_locals, given the locals of the implicit
+/// initial frame. Frames with more locals than max_locals are reported.
+pub(crate) fn declared_frames(cp: &ConstantPool, code: &CodeAttribute, initial: Vec<Type>) -> (Vec<(u32, Frame)>, Vec<Mismatch>) {
+    let mut declared = Vec::new();
+    let mut mismatches = Vec::new();
+
+    let frames = match code.attributes.find(cp, "StackMapTable").map(|a| read_stack_map_table(&a.info)) {
+        Some(Ok(frames)) => frames,
+        Some(Err(e)) => {
+            let message = format!("malformed StackMapTable attribute: {}", e);
+            return (declared, vec![Mismatch { offset: 0, message }]);
+        }
+        None => Vec::new(),
+    };
+
+    let mut locals = initial;
+
+    for (frame, offset) in frames.iter().zip(frame_offsets(&frames)) {
+        let convert = |types: &[VerificationType]| -> Vec<Type> {
+            types.iter().map(|t| Type::from_verification_type(t, cp)).collect()
+        };
+
+        let stack = match frame.kind {
+            FrameKind::Same => Vec::new(),
+            FrameKind::SameLocals1StackItem(ref item) => convert(::std::slice::from_ref(item)),
+            FrameKind::Chop(k) => {
+                let remaining = locals.len().saturating_sub(k as usize);
+                locals.truncate(remaining);
+                Vec::new()
+            }
+            FrameKind::Append(ref appended) => {
+                locals.extend(convert(appended));
+                Vec::new()
+            }
+            FrameKind::Full {
+                locals: ref full_locals,
+                ref stack,
+            } => {
+                locals = convert(full_locals);
+                convert(stack)
+            }
+        };
+
+        let mut expanded = expand(&locals);
+
+        if expanded.len() > code.max_locals as usize {
+            mismatches.push(Mismatch {
+                offset,
+                message: format!("frame declares more locals than max_locals {}", code.max_locals),
+            });
+        }
+        expanded.resize(code.max_locals as usize, Type::Top);
+
+        declared.push((
+            offset,
+            Frame {
+                locals: expanded,
+                stack: expand(&stack),
+            },
+        ));
+    }
+
+    (declared, mismatches)
+}
+
+impl ComputedCode {
+    /// Recomputes `max_stack`, `max_locals` and the frames a StackMapTable
+    /// needs for a method, merging classes using `hierarchy`.
+    pub fn compute(
+        cf: &ClassFile,
+        method: &MethodInfo,
+        code: &CodeAttribute,
+        hierarchy: &dyn ClassHierarchy,
+    ) -> Result<ComputedCode, DecodeError> {
+        let instructions = decode(&code.code)?;
+        let hierarchy = WithClass::new(hierarchy, cf);
+        let interpreter = Interpreter::new(cf, &hierarchy, &instructions);
+
+        let initial_locals = initial_locals(cf, method);
+        let max_locals = ::std::cmp::max(expand(&initial_locals).len(), locals_used(&instructions));
+
+        if max_locals > 0xffff {
+            return Err(DecodeError {
+                offset: 0,
+                message: String::from("method needs more than 65535 locals"),
+            });
+        }
+
+        let states = interpreter.run(code, initial_frame(&initial_locals, max_locals as u16)?)?;
+        let mut max_stack = 0;
+
+        for (insn, state) in instructions.iter().zip(&states) {
+            if let Some(ref before) = *state {
+                let mut after = before.clone();
+                interpreter.execute(insn, &mut after).map_err(|message| DecodeError {
+                    offset: insn.offset,
+                    message,
+                })?;
+
+                max_stack = max_stack.max(before.stack.len()).max(after.stack.len());
+            }
+        }
+
+        let mut computed = ComputedCode {
+            max_stack: max_stack as u16,
+            max_locals: max_locals as u16,
+            code: code.code.clone(),
+            exception_table: code.exception_table.clone(),
+            initial_locals,
+            frames: Vec::new(),
+        };
+
+        if cf.major_version >= 50 {
+            computed.compute_frames(&instructions, &states);
+        }
+
+        Ok(computed)
+    }
+
+    fn compute_frames(&mut self, instructions: &[Instruction], states: &[Option<Frame>]) {
+        let code_length = self.code.len() as u32;
+        let mut required: BTreeMap<u32, Frame> = BTreeMap::new();
+
+        // Runs of unreachable instructions, as [start, end) offsets.
+        let mut dead: Vec<(u32, u32)> = Vec::new();
+
+        for (i, insn) in instructions.iter().enumerate() {
+            let end = instructions.get(i + 1).map_or(code_length, |next| next.offset);
+
+            if states[i].is_none() {
+                match dead.last_mut() {
+                    Some(range) if range.1 == insn.offset => range.1 = end,
+                    _ => dead.push((insn.offset, end)),
+                }
+            }
+        }
+
+        for &(start, end) in &dead {
+            for b in &mut self.code[start as usize..end as usize - 1] {
+                *b = 0x00;
+            }
+            self.code[end as usize - 1] = 0xbf;
+
+            let throwable = Frame {
+                locals: vec![Type::Top; self.max_locals as usize],
+                stack: vec![Type::Reference(String::from("java/lang/Throwable"))],
+            };
+            required.insert(start, throwable);
+        }
+
+        if !dead.is_empty() {
+            self.max_stack = self.max_stack.max(1);
+        }
+
+        let mut exception_table = Vec::new();
+
+        for entry in &self.exception_table {
+            let end_pc = entry.end_pc as u32;
+            let mut start = entry.start_pc as u32;
+
+            let before_end = dead.iter().filter(|range| range.0 < end_pc);
+
+            for &(dead_start, dead_end) in before_end.chain(&[(end_pc, end_pc)]) {
+                if start < dead_start {
+                    exception_table.push(ExceptionTableEntry {
+                        start_pc: start as u16,
+                        end_pc: dead_start as u16,
+                        ..entry.clone()
+                    });
+                }
+                start = start.max(dead_end);
+            }
+        }
+
+        self.exception_table = exception_table;
+
+        let index_of: HashMap<u32, usize> = instructions.iter().enumerate().map(|(i, insn)| (insn.offset, i)).collect();
+        let mut targets: BTreeSet<u32> = self.exception_table.iter().map(|e| e.handler_pc as u32).collect();
+
+        for (i, insn) in instructions.iter().enumerate() {
+            if states[i].is_some() {
+                targets.extend(insn.branch_targets());
+
+                if ends_flow(insn.opcode) {
+                    if let Some(next) = instructions.get(i + 1) {
+                        targets.insert(next.offset);
+                    }
+                }
+            }
+        }
+
+        for offset in targets {
+            if let Some(frame) = states[index_of[&offset]].clone() {
+                required.insert(offset, frame);
+            }
+        }
+
+        self.frames = required.into_iter().collect();
+    }
+
+    /// Encodes the frames as compactly as the StackMapTable format allows,
+    /// using `class_index` to find or add the constant pool entries of
+    /// classes.
+    pub fn stack_map_frames<F>(&self, mut class_index: F) -> Result<Vec<StackMapFrame>, String>
+        where F: FnMut(&str) -> Result<u16, String> {
+        let mut convert = |types: &[&Type]| -> Result<Vec<VerificationType>, String> {
+            types
+                .iter()
+                .map(|t| {
+                    Ok(match **t {
+                        Type::Top | Type::ReturnAddress => VerificationType::Top,
+                        Type::Integer => VerificationType::Integer,
+                        Type::Float => VerificationType::Float,
+                        Type::Long => VerificationType::Long,
+                        Type::Double => VerificationType::Double,
+                        Type::Null => VerificationType::Null,
+                        Type::UninitializedThis => VerificationType::UninitializedThis,
+                        Type::Uninitialized(offset) => VerificationType::Uninitialized(offset as u16),
+                        Type::Reference(ref name) => VerificationType::Object(class_index(name)?),
+                    })
+                })
+                .collect()
+        };
+
+        let mut frames = Vec::new();
+        let mut previous: Vec<&Type> = self.initial_locals.iter().collect();
+        let mut previous_offset: Option<u32> = None;
+
+        for &(offset, ref frame) in &self.frames {
+            let delta = match previous_offset {
+                None => offset,
+                Some(previous_offset) => offset - previous_offset - 1,
+            } as u16;
+            previous_offset = Some(offset);
+
+            let locals = compress(&frame.locals);
+            let stack = compress(&frame.stack);
+
+            let (frame_type, kind) = if locals == previous && stack.is_empty() {
+                (if delta < 64 { delta as u8 } else { 251 }, FrameKind::Same)
+            } else if locals == previous && stack.len() == 1 {
+                let frame_type = if delta < 64 { 64 + delta as u8 } else { 247 };
+                (frame_type, FrameKind::SameLocals1StackItem(convert(&stack)?.remove(0)))
+            } else if stack.is_empty() && extends_by_few(&locals, &previous) {
+                let chopped = (previous.len() - locals.len()) as u8;
+                (251 - chopped, FrameKind::Chop(chopped))
+            } else if stack.is_empty() && extends_by_few(&previous, &locals) {
+                let appended = convert(&locals[previous.len()..])?;
+                (251 + appended.len() as u8, FrameKind::Append(appended))
+            } else {
+                let full = FrameKind::Full {
+                    locals: convert(&locals)?,
+                    stack: convert(&stack)?,
+                };
+                (255, full)
+            };
+
+            frames.push(StackMapFrame {
+                frame_type,
+                offset_delta: delta,
+                kind,
+            });
+            previous = locals;
+        }
+
+        Ok(frames)
+    }
+}
+
+// Whether `longer` is `shorter` followed by one to three more locals, the
+// most a chop or append frame can express.
+fn extends_by_few(shorter: &[&Type], longer: &[&Type]) -> bool {
+    longer.len() > shorter.len() && longer.len() - shorter.len() <= 3 && longer.starts_with(shorter)
+}
+
+// Number of local variable slots the instructions refer to.
+fn locals_used(instructions: &[Instruction]) -> usize {
+    let mut max = 0;
+
+    for insn in instructions {
+        let op = insn.opcode;
+        let wide = match op {
+            0x16 | 0x18 | 0x37 | 0x39 | 0x1e..=0x21 | 0x26..=0x29 | 0x3f..=0x42 | 0x47..=0x4a => 1,
+            _ => 0,
+        };
+
+        let index = match (op, &insn.operand) {
+            (_, &Operand::Local(index)) | (_, &Operand::Iinc { index, .. }) => index as usize,
+            // iload_0..aload_3
+            (0x1a..=0x2d, _) => ((op - 0x1a) % 4) as usize,
+            // istore_0..astore_3
+            (0x3b..=0x4e, _) => ((op - 0x3b) % 4) as usize,
+            _ => continue,
+        };
+
+        max = max.max(index + 1 + wide);
+    }
+
+    max
+}
+
+fn pop_slots(frame: &mut Frame, count: usize) -> Result<Vec<Type>, String> {
+    if frame.stack.len() < count {
+        return Err(String::from("operand stack underflow"));
+    }
+
+    let at = frame.stack.len() - count;
+    Ok(frame.stack.split_off(at))
+}
@@ -0,0 +1,230 @@
+// Class hierarchy lookups needed to merge and compare reference types.
+
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
+
+use class_file::access_flags::ACC_INTERFACE;
+use class_file::ClassFile;
+
+const OBJECT: &str = "java/lang/Object";
+
+/// Answers questions about classes other than the one being analyzed.
+/// Classes the oracle doesn't know about are treated leniently: they merge
+/// to java/lang/Object, and anything is assumed assignable to them.
+pub trait ClassHierarchy {
+    /// Whether the oracle knows the class.
+    fn is_known(&self, name: &str) -> bool;
+
+    /// Internal name of the direct superclass, or None for java/lang/Object
+    /// and unknown classes.
+    fn superclass(&self, name: &str) -> Option<String>;
+
+    fn is_interface(&self, name: &str) -> bool;
+
+    /// The class itself followed by its known superclasses.
+    fn superclasses(&self, name: &str) -> Vec<String> {
+        let mut chain = vec![name.to_string()];
+
+        while chain.last().unwrap() != OBJECT {
+            let parent = match self.superclass(chain.last().unwrap()) {
+                Some(parent) => parent,
+                None => break,
+            };
+            if chain.contains(&parent) {
+                break;
+            }
+            chain.push(parent);
+        }
+
+        chain
+    }
+
+    /// The most specific class both are subclasses of. As in the JVM
+    /// verifier, interfaces merge to java/lang/Object.
+    fn common_superclass(&self, a: &str, b: &str) -> String {
+        if a == b {
+            return a.to_string();
+        }
+        if a == OBJECT || b == OBJECT {
+            return String::from(OBJECT);
+        }
+        if self.is_interface(a) || self.is_interface(b) {
+            return String::from(OBJECT);
+        }
+
+        let chain = self.superclasses(a);
+
+        self.superclasses(b)
+            .into_iter()
+            .find(|c| chain.contains(c))
+            .unwrap_or_else(|| String::from(OBJECT))
+    }
+
+    /// Whether a class can be used where `to` is expected. Interfaces accept
+    /// any class, since the verifier leaves those checks to run time, and
+    /// unknown classes might be interfaces.
+    fn is_assignable(&self, from: &str, to: &str) -> bool {
+        if from == to || to == OBJECT || !self.is_known(to) || self.is_interface(to) {
+            return true;
+        }
+
+        let chain = self.superclasses(from);
+
+        // When the chain doesn't reach java/lang/Object part of it is unknown.
+        chain.iter().any(|c| c == to) || chain.last().is_none_or(|c| c != OBJECT)
+    }
+}
+
+/// Knows nothing beyond the classes being compared.
+pub struct NoHierarchy;
+
+impl ClassHierarchy for NoHierarchy {
+    fn is_known(&self, _: &str) -> bool {
+        false
+    }
+
+    fn superclass(&self, _: &str) -> Option<String> {
+        None
+    }
+
+    fn is_interface(&self, _: &str) -> bool {
+        false
+    }
+}
+
+/// A hierarchy built from a set of class files.
+#[derive(Debug, Default)]
+pub struct ClassHierarchyMap {
+    classes: HashMap<String, (Option<String>, bool)>,
+}
+
+impl ClassHierarchyMap {
+    pub fn new() -> ClassHierarchyMap {
+        ClassHierarchyMap::default()
+    }
+
+    pub fn insert(&mut self, name: &str, superclass: Option<&str>, is_interface: bool) {
+        self.classes
+            .insert(name.to_string(), (superclass.map(String::from), is_interface));
+    }
+
+    pub fn add_class(&mut self, cf: &ClassFile) {
+        if let Some(name) = cf.name() {
+            let superclass = cf.constant_pool.class_name(cf.super_class);
+            self.insert(&name, superclass.as_deref(), cf.access_flags & ACC_INTERFACE != 0);
+        }
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.classes.contains_key(name)
+    }
+}
+
+impl ClassHierarchy for ClassHierarchyMap {
+    fn is_known(&self, name: &str) -> bool {
+        self.contains(name)
+    }
+
+    fn superclass(&self, name: &str) -> Option<String> {
+        self.classes.get(name).and_then(|(superclass, _)| superclass.clone())
+    }
+
+    fn is_interface(&self, name: &str) -> bool {
+        self.classes.get(name).is_some_and(|&(_, is_interface)| is_interface)
+    }
+}
+
+/// A hierarchy that loads classes the first time it is asked about them, e.g.
+/// from a class path, and remembers them.
+pub struct LoadingHierarchy<F> {
+    load: RefCell<F>,
+    loaded: RefCell<ClassHierarchyMap>,
+    missing: RefCell<BTreeSet<String>>,
+}
+
+impl<F: FnMut(&str) -> Option<ClassFile>> LoadingHierarchy<F> {
+    pub fn new(load: F) -> LoadingHierarchy<F> {
+        LoadingHierarchy {
+            load: RefCell::new(load),
+            loaded: RefCell::new(ClassHierarchyMap::new()),
+            missing: RefCell::new(BTreeSet::new()),
+        }
+    }
+
+    fn ensure_loaded(&self, name: &str) {
+        if self.loaded.borrow().contains(name) || self.missing.borrow().contains(name) {
+            return;
+        }
+
+        match (self.load.borrow_mut())(name) {
+            Some(cf) => self.loaded.borrow_mut().add_class(&cf),
+            None => {
+                self.missing.borrow_mut().insert(name.to_string());
+            }
+        }
+    }
+
+    /// The classes asked about that couldn't be loaded, and so were treated
+    /// leniently.
+    pub fn missing(&self) -> Vec<String> {
+        self.missing.borrow().iter().cloned().collect()
+    }
+}
+
+impl<F: FnMut(&str) -> Option<ClassFile>> ClassHierarchy for LoadingHierarchy<F> {
+    fn is_known(&self, name: &str) -> bool {
+        self.ensure_loaded(name);
+        self.loaded.borrow().contains(name)
+    }
+
+    fn superclass(&self, name: &str) -> Option<String> {
+        self.ensure_loaded(name);
+        self.loaded.borrow().superclass(name)
+    }
+
+    fn is_interface(&self, name: &str) -> bool {
+        self.ensure_loaded(name);
+        self.loaded.borrow().is_interface(name)
+    }
+}
+
+// Adds the class being analyzed to another hierarchy.
+pub(crate) struct WithClass<'a> {
+    hierarchy: &'a dyn ClassHierarchy,
+    name: String,
+    superclass: Option<String>,
+    is_interface: bool,
+}
+
+impl<'a> WithClass<'a> {
+    pub(crate) fn new(hierarchy: &'a dyn ClassHierarchy, cf: &ClassFile) -> WithClass<'a> {
+        WithClass {
+            hierarchy,
+            name: cf.name().unwrap_or_default(),
+            superclass: cf.constant_pool.class_name(cf.super_class),
+            is_interface: cf.access_flags & ACC_INTERFACE != 0,
+        }
+    }
+}
+
+impl<'a> ClassHierarchy for WithClass<'a> {
+    fn is_known(&self, name: &str) -> bool {
+        name == self.name || self.hierarchy.is_known(name)
+    }
+
+    fn superclass(&self, name: &str) -> Option<String> {
+        if name == self.name {
+            self.superclass.clone()
+        } else {
+            self.hierarchy.superclass(name)
+        }
+    }
+
+    fn is_interface(&self, name: &str) -> bool {
+        if name == self.name {
+            self.is_interface
+        } else {
+            self.hierarchy.is_interface(name)
+        }
+    }
+}
@@ -1,219 +1,600 @@
-macro_rules! instruction {
-    ($instr:expr, [$(eval $a:expr),*]) => {{
-        let mut vector: Vec<i32> = Vec::new();
-        $(vector.push($x as u8);)*
-        (instr, vector.sum(), vector)
-    }};
-}
-
-pub fn to_string(bytecode: u8) -> (&'static str, i32, Vec<u32>) {
-    match bytecode {
-        0x19 => ("aload", 1, vec![1]),
-        0x3a => ("astore", 1, vec![1]),
-        0x10 => ("bipush", 1, vec![1]),
-        0x18 => ("dload", 1, vec![1]),
-        0x39 => ("dstore", 1, vec![1]),
-        0x17 => ("fload", 1, vec![1]),
-        0x38 => ("fstore", 1, vec![1]),
-        0x15 => ("iload", 1, vec![1]),
-        0x36 => ("istore", 1, vec![1]),
-        0x12 => ("ldc", 1, vec![1]),
-        0x16 => ("lload", 1, vec![1]),
-        0x37 => ("lstore", 1, vec![1]),
-        0xbc => ("newarray", 1, vec![1]),
-        0xa9 => ("ret", 1, vec![1]),
-        0xbd => ("anewarray", 2, vec![2]),
-        0xc0 => ("checkcast", 2, vec![2]),
-        0xb4 => ("getfield", 2, vec![2]),
-        0xb2 => ("getstatic", 2, vec![2]),
-        0xa7 => ("goto", 2, vec![2]),
-        0xa5 => ("if_acmpeq", 2, vec![2]),
-        0xa6 => ("if_acmpne", 2, vec![2]),
-        0x9f => ("if_icmpeq", 2, vec![2]),
-        0xa2 => ("if_icmpge", 2, vec![2]),
-        0xa3 => ("if_icmpgt", 2, vec![2]),
-        0xa4 => ("if_icmple", 2, vec![2]),
-        0xa1 => ("if_icmplt", 2, vec![2]),
-        0xa0 => ("if_icmpne", 2, vec![2]),
-        0x99 => ("ifeq", 2, vec![2]),
-        0x9c => ("ifge", 2, vec![2]),
-        0x9d => ("ifgt", 2, vec![2]),
-        0x9e => ("ifle", 2, vec![2]),
-        0x9b => ("iflt", 2, vec![2]),
-        0x9a => ("ifne", 2, vec![2]),
-        0xc7 => ("ifnonnull", 2, vec![2]),
-        0xc6 => ("ifnull", 2, vec![2]),
-        0x84 => ("iinc", 2, vec![1, 1]),
-        0xc1 => ("instanceof", 2, vec![2]),
-        0xb7 => ("invokespecial", 2, vec![2]),
-        0xb8 => ("invokestatic", 2, vec![2]),
-        0xb6 => ("invokevirtual", 2, vec![2]),
-        0xa8 => ("jsr", 2, vec![2]),
-        0x13 => ("ldc_w", 2, vec![2]),
-        0x14 => ("ldc2_w", 2, vec![2]),
-        0xbb => ("new", 2, vec![2]),
-        0xb5 => ("putfield", 2, vec![2]),
-        0xb3 => ("putstatic", 2, vec![2]),
-        0x11 => ("sipush", 2, vec![2]),
-        0xc5 => ("multianewarray", 3, vec![2, 1]),
-        0xc8 => ("goto_w", 4, vec![4]),
-        0xba => ("invokedynamic", 4, vec![2, 1, 1]),
-        0xb9 => ("invokeinterface", 4, vec![2, 1, 1]),
-        0xc9 => ("jsr_w", 4, vec![4]),
-        0xaa => ("tableswitch", -1, vec![]),
-        0xc4 => ("wide", -1, vec![]),
-        0xab => ("lookupswitch", -1, vec![]),
-        0x32 => ("aaload", 0, vec![]),
-        0x53 => ("aastore", 0, vec![]),
-        0x01 => ("const_null", 0, vec![]),
-        0x2a => ("aload_0", 0, vec![]),
-        0x2b => ("aload_1", 0, vec![]),
-        0x2c => ("aload_2", 0, vec![]),
-        0x2d => ("aload_3", 0, vec![]),
-        0xb0 => ("areturn", 0, vec![]),
-        0xbe => ("arraylength", 0, vec![]),
-        0x4b => ("astore_0", 0, vec![]),
-        0x4c => ("astore_1", 0, vec![]),
-        0x4d => ("astore_2", 0, vec![]),
-        0x4e => ("astore_3", 0, vec![]),
-        0xbf => ("athrow", 0, vec![]),
-        0x33 => ("baload", 0, vec![]),
-        0x54 => ("bastore", 0, vec![]),
-        0xca => ("breakpoint", 0, vec![]),
-        0x34 => ("caload", 0, vec![]),
-        0x55 => ("castore", 0, vec![]),
-        0x90 => ("d2f", 0, vec![]),
-        0x8e => ("d2i", 0, vec![]),
-        0x8f => ("d2l", 0, vec![]),
-        0x63 => ("dadd", 0, vec![]),
-        0x31 => ("daload", 0, vec![]),
-        0x52 => ("dastore", 0, vec![]),
-        0x98 => ("dcmpg", 0, vec![]),
-        0x97 => ("dcmpl", 0, vec![]),
-        0x0e => ("dconst_0", 0, vec![]),
-        0x0f => ("dconst_1", 0, vec![]),
-        0x6f => ("ddiv", 0, vec![]),
-        0x26 => ("dload_0", 0, vec![]),
-        0x27 => ("dload_1", 0, vec![]),
-        0x28 => ("dload_2", 0, vec![]),
-        0x29 => ("dload_3", 0, vec![]),
-        0x6b => ("dmul", 0, vec![]),
-        0x77 => ("dneg", 0, vec![]),
-        0x73 => ("drem", 0, vec![]),
-        0xaf => ("dreturn", 0, vec![]),
-        0x47 => ("dstore_0", 0, vec![]),
-        0x48 => ("dstore_1", 0, vec![]),
-        0x49 => ("dstore_2", 0, vec![]),
-        0x4a => ("dstore_3", 0, vec![]),
-        0x67 => ("dsub", 0, vec![]),
-        0x59 => ("dup", 0, vec![]),
-        0x5a => ("dup_x1", 0, vec![]),
-        0x5b => ("dup_x2", 0, vec![]),
-        0x5c => ("dup2", 0, vec![]),
-        0x5d => ("dup2_x1", 0, vec![]),
-        0x5e => ("dup2_x2", 0, vec![]),
-        0x8d => ("f2d", 0, vec![]),
-        0x8b => ("f2i", 0, vec![]),
-        0x8c => ("f2l", 0, vec![]),
-        0x62 => ("fadd", 0, vec![]),
-        0x30 => ("faload", 0, vec![]),
-        0x51 => ("fastore", 0, vec![]),
-        0x96 => ("fcmpg", 0, vec![]),
-        0x95 => ("fcmpl", 0, vec![]),
-        0x0b => ("fconst_0", 0, vec![]),
-        0x0c => ("fconst_1", 0, vec![]),
-        0x0d => ("fconst_2", 0, vec![]),
-        0x6e => ("fdiv", 0, vec![]),
-        0x22 => ("fload_0", 0, vec![]),
-        0x23 => ("fload_1", 0, vec![]),
-        0x24 => ("fload_2", 0, vec![]),
-        0x25 => ("fload_3", 0, vec![]),
-        0x6a => ("fmul", 0, vec![]),
-        0x76 => ("fneg", 0, vec![]),
-        0x72 => ("frem", 0, vec![]),
-        0xae => ("freturn", 0, vec![]),
-        0x43 => ("fstore_0", 0, vec![]),
-        0x44 => ("fstore_1", 0, vec![]),
-        0x45 => ("fstore_2", 0, vec![]),
-        0x46 => ("fstore_3", 0, vec![]),
-        0x66 => ("fsub", 0, vec![]),
-        0x91 => ("i2b", 0, vec![]),
-        0x92 => ("i2c", 0, vec![]),
-        0x87 => ("i2d", 0, vec![]),
-        0x86 => ("i2f", 0, vec![]),
-        0x85 => ("i2l", 0, vec![]),
-        0x93 => ("i2s", 0, vec![]),
-        0x60 => ("iadd", 0, vec![]),
-        0x2e => ("iaload", 0, vec![]),
-        0x7e => ("iand", 0, vec![]),
-        0x4f => ("iastore", 0, vec![]),
-        0x02 => ("const_m1", 0, vec![]),
-        0x03 => ("const_0", 0, vec![]),
-        0x04 => ("const_1", 0, vec![]),
-        0x05 => ("const_2", 0, vec![]),
-        0x06 => ("const_3", 0, vec![]),
-        0x07 => ("const_4", 0, vec![]),
-        0x08 => ("const_5", 0, vec![]),
-        0x6c => ("idiv", 0, vec![]),
-        0x1a => ("iload_0", 0, vec![]),
-        0x1b => ("iload_1", 0, vec![]),
-        0x1c => ("iload_2", 0, vec![]),
-        0x1d => ("iload_3", 0, vec![]),
-        0xfe => ("impdep1", 0, vec![]),
-        0xff => ("impdep2", 0, vec![]),
-        0x68 => ("imul", 0, vec![]),
-        0x74 => ("ineg", 0, vec![]),
-        0x80 => ("ior", 0, vec![]),
-        0x70 => ("irem", 0, vec![]),
-        0xac => ("ireturn", 0, vec![]),
-        0x78 => ("ishl", 0, vec![]),
-        0x7a => ("ishr", 0, vec![]),
-        0x3b => ("istore_0", 0, vec![]),
-        0x3c => ("istore_1", 0, vec![]),
-        0x3d => ("istore_2", 0, vec![]),
-        0x3e => ("istore_3", 0, vec![]),
-        0x64 => ("isub", 0, vec![]),
-        0x7c => ("iushr", 0, vec![]),
-        0x82 => ("ixor", 0, vec![]),
-        0x8a => ("l2d", 0, vec![]),
-        0x89 => ("l2f", 0, vec![]),
-        0x88 => ("l2i", 0, vec![]),
-        0x61 => ("ladd", 0, vec![]),
-        0x2f => ("laload", 0, vec![]),
-        0x7f => ("land", 0, vec![]),
-        0x50 => ("lastore", 0, vec![]),
-        0x94 => ("lcmp", 0, vec![]),
-        0x09 => ("const_0", 0, vec![]),
-        0x0a => ("lconst_1", 0, vec![]),
-        0x6d => ("ldiv", 0, vec![]),
-        0x1e => ("lload_0", 0, vec![]),
-        0x1f => ("lload_1", 0, vec![]),
-        0x20 => ("lload_2", 0, vec![]),
-        0x21 => ("lload_3", 0, vec![]),
-        0x69 => ("lmul", 0, vec![]),
-        0x75 => ("lneg", 0, vec![]),
-        0x81 => ("lor", 0, vec![]),
-        0x71 => ("lrem", 0, vec![]),
-        0xad => ("lreturn", 0, vec![]),
-        0x79 => ("lshl", 0, vec![]),
-        0x7b => ("lshr", 0, vec![]),
-        0x3f => ("lstore_0", 0, vec![]),
-        0x40 => ("lstore_1", 0, vec![]),
-        0x41 => ("lstore_2", 0, vec![]),
-        0x42 => ("lstore_3", 0, vec![]),
-        0x65 => ("lsub", 0, vec![]),
-        0x7d => ("lushr", 0, vec![]),
-        0x83 => ("lxor", 0, vec![]),
-        0xc2 => ("monitorenter", 0, vec![]),
-        0xc3 => ("monitorexit", 0, vec![]),
-        0x00 => ("op", 0, vec![]),
-        0x57 => ("pop", 0, vec![]),
-        0x58 => ("pop2", 0, vec![]),
-        0xb1 => ("return", 0, vec![]),
-        0x35 => ("saload", 0, vec![]),
-        0x56 => ("sastore", 0, vec![]),
-        0x5f => ("swap", 0, vec![]),
-
-        _ => panic!("Unknown bytecode {:x}", bytecode),
+use std::fmt;
+
+pub mod cfg;
+pub mod frames;
+pub mod hierarchy;
+pub mod verifier;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperandKind {
+    None,
+    // Local variable index, one byte or two after a wide prefix.
+    Local,
+    Byte,
+    Short,
+    // One-byte constant pool index of ldc.
+    ConstantU8,
+    Constant,
+    Branch,
+    BranchWide,
+    Iinc,
+    InvokeInterface,
+    InvokeDynamic,
+    MultiANewArray,
+    NewArray,
+    TableSwitch,
+    LookupSwitch,
+    Wide,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    pub offset: u32,
+    pub opcode: u8,
+    pub wide: bool,
+    pub operand: Operand,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operand {
+    None,
+    Local(u16),
+    Int(i32),
+    Constant(u16),
+    // Branch targets are absolute offsets into the code array.
+    Branch(u32),
+    Iinc { index: u16, value: i16 },
+    InvokeInterface { index: u16, count: u8 },
+    InvokeDynamic(u16),
+    MultiANewArray { index: u16, dimensions: u8 },
+    NewArray(u8),
+    TableSwitch { default: u32, low: i32, targets: Vec<u32> },
+    LookupSwitch { default: u32, pairs: Vec<(i32, u32)> },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodeError {
+    pub offset: u32,
+    pub message: String,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at offset {}", self.message, self.offset)
+    }
+}
+
+pub const WIDE: u8 = 0xc4;
+
+pub fn info(opcode: u8) -> Option<(&'static str, OperandKind)> {
+    use self::OperandKind::*;
+
+    let info = match opcode {
+        0x00 => ("nop", None),
+        0x01 => ("aconst_null", None),
+        0x02 => ("iconst_m1", None),
+        0x03 => ("iconst_0", None),
+        0x04 => ("iconst_1", None),
+        0x05 => ("iconst_2", None),
+        0x06 => ("iconst_3", None),
+        0x07 => ("iconst_4", None),
+        0x08 => ("iconst_5", None),
+        0x09 => ("lconst_0", None),
+        0x0a => ("lconst_1", None),
+        0x0b => ("fconst_0", None),
+        0x0c => ("fconst_1", None),
+        0x0d => ("fconst_2", None),
+        0x0e => ("dconst_0", None),
+        0x0f => ("dconst_1", None),
+        0x10 => ("bipush", Byte),
+        0x11 => ("sipush", Short),
+        0x12 => ("ldc", ConstantU8),
+        0x13 => ("ldc_w", Constant),
+        0x14 => ("ldc2_w", Constant),
+        0x15 => ("iload", Local),
+        0x16 => ("lload", Local),
+        0x17 => ("fload", Local),
+        0x18 => ("dload", Local),
+        0x19 => ("aload", Local),
+        0x1a => ("iload_0", None),
+        0x1b => ("iload_1", None),
+        0x1c => ("iload_2", None),
+        0x1d => ("iload_3", None),
+        0x1e => ("lload_0", None),
+        0x1f => ("lload_1", None),
+        0x20 => ("lload_2", None),
+        0x21 => ("lload_3", None),
+        0x22 => ("fload_0", None),
+        0x23 => ("fload_1", None),
+        0x24 => ("fload_2", None),
+        0x25 => ("fload_3", None),
+        0x26 => ("dload_0", None),
+        0x27 => ("dload_1", None),
+        0x28 => ("dload_2", None),
+        0x29 => ("dload_3", None),
+        0x2a => ("aload_0", None),
+        0x2b => ("aload_1", None),
+        0x2c => ("aload_2", None),
+        0x2d => ("aload_3", None),
+        0x2e => ("iaload", None),
+        0x2f => ("laload", None),
+        0x30 => ("faload", None),
+        0x31 => ("daload", None),
+        0x32 => ("aaload", None),
+        0x33 => ("baload", None),
+        0x34 => ("caload", None),
+        0x35 => ("saload", None),
+        0x36 => ("istore", Local),
+        0x37 => ("lstore", Local),
+        0x38 => ("fstore", Local),
+        0x39 => ("dstore", Local),
+        0x3a => ("astore", Local),
+        0x3b => ("istore_0", None),
+        0x3c => ("istore_1", None),
+        0x3d => ("istore_2", None),
+        0x3e => ("istore_3", None),
+        0x3f => ("lstore_0", None),
+        0x40 => ("lstore_1", None),
+        0x41 => ("lstore_2", None),
+        0x42 => ("lstore_3", None),
+        0x43 => ("fstore_0", None),
+        0x44 => ("fstore_1", None),
+        0x45 => ("fstore_2", None),
+        0x46 => ("fstore_3", None),
+        0x47 => ("dstore_0", None),
+        0x48 => ("dstore_1", None),
+        0x49 => ("dstore_2", None),
+        0x4a => ("dstore_3", None),
+        0x4b => ("astore_0", None),
+        0x4c => ("astore_1", None),
+        0x4d => ("astore_2", None),
+        0x4e => ("astore_3", None),
+        0x4f => ("iastore", None),
+        0x50 => ("lastore", None),
+        0x51 => ("fastore", None),
+        0x52 => ("dastore", None),
+        0x53 => ("aastore", None),
+        0x54 => ("bastore", None),
+        0x55 => ("castore", None),
+        0x56 => ("sastore", None),
+        0x57 => ("pop", None),
+        0x58 => ("pop2", None),
+        0x59 => ("dup", None),
+        0x5a => ("dup_x1", None),
+        0x5b => ("dup_x2", None),
+        0x5c => ("dup2", None),
+        0x5d => ("dup2_x1", None),
+        0x5e => ("dup2_x2", None),
+        0x5f => ("swap", None),
+        0x60 => ("iadd", None),
+        0x61 => ("ladd", None),
+        0x62 => ("fadd", None),
+        0x63 => ("dadd", None),
+        0x64 => ("isub", None),
+        0x65 => ("lsub", None),
+        0x66 => ("fsub", None),
+        0x67 => ("dsub", None),
+        0x68 => ("imul", None),
+        0x69 => ("lmul", None),
+        0x6a => ("fmul", None),
+        0x6b => ("dmul", None),
+        0x6c => ("idiv", None),
+        0x6d => ("ldiv", None),
+        0x6e => ("fdiv", None),
+        0x6f => ("ddiv", None),
+        0x70 => ("irem", None),
+        0x71 => ("lrem", None),
+        0x72 => ("frem", None),
+        0x73 => ("drem", None),
+        0x74 => ("ineg", None),
+        0x75 => ("lneg", None),
+        0x76 => ("fneg", None),
+        0x77 => ("dneg", None),
+        0x78 => ("ishl", None),
+        0x79 => ("lshl", None),
+        0x7a => ("ishr", None),
+        0x7b => ("lshr", None),
+        0x7c => ("iushr", None),
+        0x7d => ("lushr", None),
+        0x7e => ("iand", None),
+        0x7f => ("land", None),
+        0x80 => ("ior", None),
+        0x81 => ("lor", None),
+        0x82 => ("ixor", None),
+        0x83 => ("lxor", None),
+        0x84 => ("iinc", Iinc),
+        0x85 => ("i2l", None),
+        0x86 => ("i2f", None),
+        0x87 => ("i2d", None),
+        0x88 => ("l2i", None),
+        0x89 => ("l2f", None),
+        0x8a => ("l2d", None),
+        0x8b => ("f2i", None),
+        0x8c => ("f2l", None),
+        0x8d => ("f2d", None),
+        0x8e => ("d2i", None),
+        0x8f => ("d2l", None),
+        0x90 => ("d2f", None),
+        0x91 => ("i2b", None),
+        0x92 => ("i2c", None),
+        0x93 => ("i2s", None),
+        0x94 => ("lcmp", None),
+        0x95 => ("fcmpl", None),
+        0x96 => ("fcmpg", None),
+        0x97 => ("dcmpl", None),
+        0x98 => ("dcmpg", None),
+        0x99 => ("ifeq", Branch),
+        0x9a => ("ifne", Branch),
+        0x9b => ("iflt", Branch),
+        0x9c => ("ifge", Branch),
+        0x9d => ("ifgt", Branch),
+        0x9e => ("ifle", Branch),
+        0x9f => ("if_icmpeq", Branch),
+        0xa0 => ("if_icmpne", Branch),
+        0xa1 => ("if_icmplt", Branch),
+        0xa2 => ("if_icmpge", Branch),
+        0xa3 => ("if_icmpgt", Branch),
+        0xa4 => ("if_icmple", Branch),
+        0xa5 => ("if_acmpeq", Branch),
+        0xa6 => ("if_acmpne", Branch),
+        0xa7 => ("goto", Branch),
+        0xa8 => ("jsr", Branch),
+        0xa9 => ("ret", Local),
+        0xaa => ("tableswitch", TableSwitch),
+        0xab => ("lookupswitch", LookupSwitch),
+        0xac => ("ireturn", None),
+        0xad => ("lreturn", None),
+        0xae => ("freturn", None),
+        0xaf => ("dreturn", None),
+        0xb0 => ("areturn", None),
+        0xb1 => ("return", None),
+        0xb2 => ("getstatic", Constant),
+        0xb3 => ("putstatic", Constant),
+        0xb4 => ("getfield", Constant),
+        0xb5 => ("putfield", Constant),
+        0xb6 => ("invokevirtual", Constant),
+        0xb7 => ("invokespecial", Constant),
+        0xb8 => ("invokestatic", Constant),
+        0xb9 => ("invokeinterface", InvokeInterface),
+        0xba => ("invokedynamic", InvokeDynamic),
+        0xbb => ("new", Constant),
+        0xbc => ("newarray", NewArray),
+        0xbd => ("anewarray", Constant),
+        0xbe => ("arraylength", None),
+        0xbf => ("athrow", None),
+        0xc0 => ("checkcast", Constant),
+        0xc1 => ("instanceof", Constant),
+        0xc2 => ("monitorenter", None),
+        0xc3 => ("monitorexit", None),
+        0xc4 => ("wide", Wide),
+        0xc5 => ("multianewarray", MultiANewArray),
+        0xc6 => ("ifnull", Branch),
+        0xc7 => ("ifnonnull", Branch),
+        0xc8 => ("goto_w", BranchWide),
+        0xc9 => ("jsr_w", BranchWide),
+        0xca => ("breakpoint", None),
+        0xfe => ("impdep1", None),
+        0xff => ("impdep2", None),
+        _ => return Option::None,
+    };
+
+    Some(info)
+}
+
+pub fn mnemonic(opcode: u8) -> Option<&'static str> {
+    info(opcode).map(|(name, _)| name)
+}
+
+pub fn opcode(mnemonic: &str) -> Option<u8> {
+    (0..=255u8).find(|&op| self::mnemonic(op) == Some(mnemonic))
+}
+
+/// Name of the primitive array type created by `newarray` with the given
+/// `atype` operand.
+pub fn array_type_name(atype: u8) -> Option<&'static str> {
+    match atype {
+        4 => Some("boolean"),
+        5 => Some("char"),
+        6 => Some("float"),
+        7 => Some("double"),
+        8 => Some("byte"),
+        9 => Some("short"),
+        10 => Some("int"),
+        11 => Some("long"),
+        _ => None,
+    }
+}
+
+pub fn array_type_code(name: &str) -> Option<u8> {
+    (4..=11u8).find(|&atype| array_type_name(atype) == Some(name))
+}
+
+impl Instruction {
+    pub fn mnemonic(&self) -> &'static str {
+        mnemonic(self.opcode).unwrap_or("?")
+    }
+
+    /// Offsets this instruction may transfer control to, besides falling
+    /// through to the next one.
+    pub fn branch_targets(&self) -> Vec<u32> {
+        match self.operand {
+            Operand::Branch(target) => vec![target],
+            Operand::TableSwitch {
+                default,
+                ref targets,
+                ..
+            } => {
+                let mut all = targets.clone();
+                all.push(default);
+                all
+            }
+            Operand::LookupSwitch { default, ref pairs } => {
+                let mut all: Vec<u32> = pairs.iter().map(|&(_, target)| target).collect();
+                all.push(default);
+                all
+            }
+            _ => vec![],
+        }
+    }
+}
+
+// Plain rendering with numeric operands, as used in the JSON output.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.wide {
+            write!(f, "wide ")?;
+        }
+        write!(f, "{}", self.mnemonic())?;
+
+        match self.operand {
+            Operand::None => Ok(()),
+            Operand::Local(index) => write!(f, " {}", index),
+            Operand::Int(value) => write!(f, " {}", value),
+            Operand::Constant(index) => write!(f, " {}", index),
+            Operand::Branch(target) => write!(f, " {}", target),
+            Operand::Iinc { index, value } => write!(f, " {} {}", index, value),
+            Operand::InvokeInterface { index, count } => write!(f, " {} {}", index, count),
+            Operand::InvokeDynamic(index) => write!(f, " {}", index),
+            Operand::MultiANewArray { index, dimensions } => write!(f, " {} {}", index, dimensions),
+            Operand::NewArray(atype) => write!(f, " {}", array_type_name(atype).unwrap_or("?")),
+            Operand::TableSwitch {
+                default,
+                low,
+                ref targets,
+            } => write!(f, " {} {:?} default {}", low, targets, default),
+            Operand::LookupSwitch { default, ref pairs } => {
+                for &(key, target) in pairs {
+                    write!(f, " {}:{}", key, target)?;
+                }
+                write!(f, " default:{}", default)
+            }
+        }
+    }
+}
+
+/// Decodes a method's code array into instructions.
+pub fn decode(code: &[u8]) -> Result<Vec<Instruction>, DecodeError> {
+    let mut instructions = Vec::new();
+    let mut pc = 0;
+
+    while pc < code.len() {
+        let instruction = decode_at(code, pc)?;
+        pc += length(code, pc)?;
+        instructions.push(instruction);
+    }
+
+    Ok(instructions)
+}
+
+/// Decodes a single instruction at offset `pc`.
+pub fn decode_at(code: &[u8], pc: usize) -> Result<Instruction, DecodeError> {
+    let mut r = CodeReader { code, pc, start: pc };
+    let opcode = r.u8()?;
+
+    let (_, kind) = match info(opcode) {
+        Some(info) => info,
+        None => return Err(r.error(format!("unknown opcode 0x{:02x}", opcode))),
+    };
+
+    let mut instruction = Instruction {
+        offset: pc as u32,
+        opcode,
+        wide: false,
+        operand: Operand::None,
+    };
+
+    instruction.operand = match kind {
+        OperandKind::None => Operand::None,
+        OperandKind::Local => Operand::Local(r.u8()? as u16),
+        OperandKind::Byte => Operand::Int(r.u8()? as i8 as i32),
+        OperandKind::Short => Operand::Int(r.u16()? as i16 as i32),
+        OperandKind::ConstantU8 => Operand::Constant(r.u8()? as u16),
+        OperandKind::Constant => Operand::Constant(r.u16()?),
+        OperandKind::Branch => {
+            let delta = r.u16()? as i16 as i64;
+            Operand::Branch(r.target(delta)?)
+        }
+        OperandKind::BranchWide => {
+            let delta = r.u32()? as i32 as i64;
+            Operand::Branch(r.target(delta)?)
+        }
+        OperandKind::Iinc => Operand::Iinc {
+            index: r.u8()? as u16,
+            value: r.u8()? as i8 as i16,
+        },
+        OperandKind::InvokeInterface => {
+            let index = r.u16()?;
+            let count = r.u8()?;
+            r.u8()?;
+            Operand::InvokeInterface { index, count }
+        }
+        OperandKind::InvokeDynamic => {
+            let index = r.u16()?;
+            r.u16()?;
+            Operand::InvokeDynamic(index)
+        }
+        OperandKind::MultiANewArray => Operand::MultiANewArray {
+            index: r.u16()?,
+            dimensions: r.u8()?,
+        },
+        OperandKind::NewArray => Operand::NewArray(r.u8()?),
+        OperandKind::TableSwitch => {
+            r.align();
+            let default = r.u32()? as i32 as i64;
+            let default = r.target(default)?;
+            let low = r.u32()? as i32;
+            let high = r.u32()? as i32;
+
+            if high < low || (high as i64 - low as i64) >= 0x10000 {
+                return Err(r.error(format!("bad tableswitch range {}..{}", low, high)));
+            }
+
+            let mut targets = Vec::new();
+            for _ in low..=high {
+                let delta = r.u32()? as i32 as i64;
+                targets.push(r.target(delta)?);
+            }
+
+            Operand::TableSwitch { default, low, targets }
+        }
+        OperandKind::LookupSwitch => {
+            r.align();
+            let default = r.u32()? as i32 as i64;
+            let default = r.target(default)?;
+            let npairs = r.u32()? as i32;
+
+            if !(0..0x10000).contains(&npairs) {
+                return Err(r.error(format!("bad lookupswitch npairs {}", npairs)));
+            }
+
+            let mut pairs = Vec::new();
+            for _ in 0..npairs {
+                let key = r.u32()? as i32;
+                let delta = r.u32()? as i32 as i64;
+                pairs.push((key, r.target(delta)?));
+            }
+
+            Operand::LookupSwitch { default, pairs }
+        }
+        OperandKind::Wide => {
+            let opcode = r.u8()?;
+            instruction.opcode = opcode;
+            instruction.wide = true;
+
+            match info(opcode) {
+                Some((_, OperandKind::Local)) => Operand::Local(r.u16()?),
+                Some((_, OperandKind::Iinc)) => Operand::Iinc {
+                    index: r.u16()?,
+                    value: r.u16()? as i16,
+                },
+                _ => return Err(r.error(format!("opcode 0x{:02x} cannot be widened", opcode))),
+            }
+        }
+    };
+
+    Ok(instruction)
+}
+
+/// Length in bytes of the instruction at offset `pc`, including switch
+/// padding and the wide prefix.
+pub fn length(code: &[u8], pc: usize) -> Result<usize, DecodeError> {
+    let error = |message: &str| DecodeError {
+        offset: pc as u32,
+        message: message.to_string(),
+    };
+
+    let opcode = *code.get(pc).ok_or_else(|| error("truncated instruction"))?;
+    let kind = info(opcode).ok_or_else(|| error("unknown opcode"))?.1;
+
+    let length = match kind {
+        OperandKind::None => 1,
+        OperandKind::Local | OperandKind::Byte | OperandKind::ConstantU8 | OperandKind::NewArray => 2,
+        OperandKind::Short | OperandKind::Constant | OperandKind::Branch | OperandKind::Iinc => 3,
+        OperandKind::MultiANewArray => 4,
+        OperandKind::BranchWide | OperandKind::InvokeInterface | OperandKind::InvokeDynamic => 5,
+        OperandKind::Wide => match code.get(pc + 1) {
+            Some(&0x84) => 6,
+            Some(_) => 4,
+            None => return Err(error("truncated instruction")),
+        },
+        OperandKind::TableSwitch | OperandKind::LookupSwitch => {
+            let base = (pc + 4) & !3;
+            let word = |i: usize| -> Result<i64, DecodeError> {
+                let at = base + i * 4;
+                match code.get(at..at + 4) {
+                    Some(b) => Ok((b[0] as u32) << 24 | (b[1] as u32) << 16 | (b[2] as u32) << 8 | b[3] as u32)
+                        .map(|w| w as i32 as i64),
+                    None => Err(error("truncated switch")),
+                }
+            };
+
+            if kind == OperandKind::TableSwitch {
+                let count = word(2)? - word(1)? + 1;
+                if !(1..=0x10000).contains(&count) {
+                    return Err(error("bad tableswitch range"));
+                }
+                base - pc + 12 + 4 * count as usize
+            } else {
+                let npairs = word(1)?;
+                if !(0..0x10000).contains(&npairs) {
+                    return Err(error("bad lookupswitch npairs"));
+                }
+                base - pc + 8 + 8 * npairs as usize
+            }
+        }
+    };
+
+    if pc + length > code.len() {
+        return Err(error("truncated instruction"));
+    }
+
+    Ok(length)
+}
+
+struct CodeReader<'a> {
+    code: &'a [u8],
+    pc: usize,
+    start: usize,
+}
+
+impl<'a> CodeReader<'a> {
+    fn error(&self, message: String) -> DecodeError {
+        DecodeError {
+            offset: self.start as u32,
+            message,
+        }
+    }
+
+    fn u8(&mut self) -> Result<u8, DecodeError> {
+        match self.code.get(self.pc) {
+            Some(&b) => {
+                self.pc += 1;
+                Ok(b)
+            }
+            None => Err(self.error(String::from("truncated instruction"))),
+        }
+    }
+
+    fn u16(&mut self) -> Result<u16, DecodeError> {
+        Ok((self.u8()? as u16) << 8 | self.u8()? as u16)
+    }
+
+    fn u32(&mut self) -> Result<u32, DecodeError> {
+        Ok((self.u16()? as u32) << 16 | self.u16()? as u32)
+    }
+
+    // Skips the padding that aligns switch operands to four bytes.
+    fn align(&mut self) {
+        self.pc = (self.pc + 3) & !3;
+    }
+
+    fn target(&self, delta: i64) -> Result<u32, DecodeError> {
+        let target = self.start as i64 + delta;
+
+        if target < 0 || target > u32::MAX as i64 {
+            Err(self.error(format!("branch target {} out of range", target)))
+        } else {
+            Ok(target as u32)
+        }
     }
 }
@@ -0,0 +1,467 @@
+// The type-checking verifier of JVMS 4.10.1, which JVMs run on class files of
+// version 50 and later. Rather than inferring frames, it checks each
+// instruction once, in order, against the frames the StackMapTable declares
+// where control flows other than to the next instruction, and against the
+// types the instruction's descriptors and the method's return type require.
+// Older class files are verified by type inference instead.
+
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+
+use bytecode::cfg::ends_flow;
+use bytecode::frames::{declared_frames, initial_frame, initial_locals, Frame, FrameAnalysis, Interpreter, Type};
+use bytecode::hierarchy::{ClassHierarchy, WithClass};
+use bytecode::{decode, DecodeError, Instruction, Operand};
+use class_file::attributes::{malformed, read_code, CodeAttribute};
+use class_file::descriptor::*;
+use class_file::methods::MethodInfo;
+use class_file::ClassFile;
+
+const THROWABLE: &str = "java/lang/Throwable";
+
+/// Why a JVM would reject a method with a VerifyError.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct VerifyError {
+    /// Name and descriptor of the method, e.g. "run()V".
+    pub method: String,
+
+    /// Offset of the failing instruction.
+    pub offset: u32,
+
+    pub message: String,
+
+    /// The StackMapTable frame that the actual one isn't assignable to, for
+    /// errors where control flows to a frame.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expected: Option<Box<Frame>>,
+
+    /// The frame before the instruction, or the one flowing to `expected`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actual: Option<Box<Frame>>,
+}
+
+/// Verifies every method of a class that has code, returning the first error
+/// of each failing method. As in a JVM, the class must have passed format
+/// checking first.
+pub fn verify_class(cf: &ClassFile, hierarchy: &dyn ClassHierarchy) -> Vec<VerifyError> {
+    let cp = &cf.constant_pool;
+
+    cf.methods
+        .array
+        .iter()
+        .filter_map(|method| {
+            let result = read_code(&method.attributes.find(cp, "Code")?.info)
+                .map_err(|e| VerifyError::at(&method_name(cf, method), DecodeError { offset: 0, message: malformed("Code", e) }))
+                .and_then(|code| verify_method(cf, method, &code, hierarchy));
+            result.err()
+        })
+        .collect()
+}
+
+/// Verifies a method, looking up the classes its types refer to in
+/// `hierarchy`. Classes the hierarchy doesn't know are assumed assignable.
+pub fn verify_method(
+    cf: &ClassFile,
+    method: &MethodInfo,
+    code: &CodeAttribute,
+    hierarchy: &dyn ClassHierarchy,
+) -> Result<(), VerifyError> {
+    let name = method_name(cf, method);
+
+    let infer = || {
+        FrameAnalysis::analyze_with(cf, method, code, hierarchy)
+            .map(|_| ())
+            .map_err(|e| VerifyError::at(&name, e))
+    };
+
+    if cf.major_version < 50 {
+        return infer();
+    }
+
+    match type_check(cf, method, code, hierarchy, &name) {
+        // Like HotSpot, fall back to type inference for version 50, whose
+        // StackMapTable compilers could still omit.
+        Err(_) if cf.major_version == 50 => infer(),
+        result => result,
+    }
+}
+
+// Name and descriptor, as VerifyError has them.
+fn method_name(cf: &ClassFile, method: &MethodInfo) -> String {
+    let cp = &cf.constant_pool;
+    format!(
+        "{}{}",
+        cp.utf8(method.name_index).unwrap_or_default(),
+        cp.utf8(method.descriptor_index).unwrap_or_default()
+    )
+}
+
+impl VerifyError {
+    fn at(method: &str, e: DecodeError) -> VerifyError {
+        VerifyError {
+            method: method.to_string(),
+            offset: e.offset,
+            message: e.message,
+            expected: None,
+            actual: None,
+        }
+    }
+}
+
+// What the checks of one method need besides the frames.
+struct TypeChecker<'a> {
+    method: &'a str,
+    interpreter: &'a Interpreter<'a>,
+    hierarchy: &'a dyn ClassHierarchy,
+    this_class: String,
+    superclass: Option<String>,
+    // None for void methods.
+    return_type: Option<Type>,
+    declared: HashMap<u32, Frame>,
+}
+
+fn type_check(
+    cf: &ClassFile,
+    method: &MethodInfo,
+    code: &CodeAttribute,
+    hierarchy: &dyn ClassHierarchy,
+    name: &str,
+) -> Result<(), VerifyError> {
+    let cp = &cf.constant_pool;
+    let error = |offset: u32, message: String| VerifyError::at(name, DecodeError { offset, message });
+
+    let instructions = decode(&code.code).map_err(|e| VerifyError::at(name, e))?;
+    let hierarchy = WithClass::new(hierarchy, cf);
+    let interpreter = Interpreter::new(cf, &hierarchy, &instructions);
+
+    let initial = initial_locals(cf, method);
+    let mut current = Some(initial_frame(&initial, code.max_locals).map_err(|e| VerifyError::at(name, e))?);
+    let mut this_uninitialized = initial.contains(&Type::UninitializedThis);
+
+    let (declared, mismatches) = declared_frames(cp, code, initial);
+    if let Some(mismatch) = mismatches.into_iter().next() {
+        return Err(error(mismatch.offset, mismatch.message));
+    }
+
+    let offsets: HashSet<u32> = instructions.iter().map(|insn| insn.offset).collect();
+    if let Some(&(offset, _)) = declared.iter().find(|(offset, _)| !offsets.contains(offset)) {
+        return Err(error(offset, String::from("StackMapTable frame is not at an instruction")));
+    }
+
+    let return_type = cp
+        .utf8(method.descriptor_index)
+        .and_then(|descriptor| parse_method_descriptor(&descriptor))
+        .and_then(|descriptor| descriptor.return_type)
+        .map(|return_type| Type::from_field_type(&return_type));
+
+    let checker = TypeChecker {
+        method: name,
+        interpreter: &interpreter,
+        hierarchy: &hierarchy,
+        this_class: cf.name().unwrap_or_default(),
+        superclass: cp.class_name(cf.super_class),
+        return_type,
+        declared: declared.into_iter().collect(),
+    };
+
+    let mut handlers = Vec::new();
+    for entry in &code.exception_table {
+        let catch_type = match entry.catch_type {
+            0 => String::from(THROWABLE),
+            index => interpreter.class_name(index).map_err(|message| error(entry.handler_pc as u32, message))?,
+        };
+        let catch_type = Type::Reference(catch_type);
+
+        if !catch_type.is_assignable_to(&Type::Reference(String::from(THROWABLE)), &hierarchy) {
+            return Err(error(entry.handler_pc as u32, format!("catch type {} is not a Throwable", catch_type)));
+        }
+        handlers.push((entry.start_pc as u32..entry.end_pc as u32, entry.handler_pc as u32, catch_type));
+    }
+
+    for (i, insn) in instructions.iter().enumerate() {
+        if let Some(frame) = checker.declared.get(&insn.offset) {
+            if let Some(ref current) = current {
+                checker.flows_to(insn.offset, current, this_uninitialized, insn.offset)?;
+            }
+
+            current = Some(frame.clone());
+            this_uninitialized = frame.locals.contains(&Type::UninitializedThis);
+        }
+
+        let before = current
+            .take()
+            .ok_or_else(|| error(insn.offset, String::from("no StackMapTable frame after an unconditional branch")))?;
+        let fail = |message: String| VerifyError {
+            actual: Some(Box::new(before.clone())),
+            ..error(insn.offset, message)
+        };
+
+        for (range, handler_pc, catch_type) in &handlers {
+            if range.contains(&insn.offset) {
+                let thrown = Frame {
+                    locals: before.locals.clone(),
+                    stack: vec![catch_type.clone()],
+                };
+                checker.flows_to(insn.offset, &thrown, this_uninitialized, *handler_pc)?;
+            }
+        }
+
+        checker.check_operands(insn, &before, this_uninitialized).map_err(&fail)?;
+
+        let mut after = before.clone();
+        interpreter.execute(insn, &mut after).map_err(&fail)?;
+
+        if after.stack.len() > code.max_stack as usize {
+            return Err(fail(format!("operand stack exceeds max_stack {}", code.max_stack)));
+        }
+        if checker.initializes_this(insn, &before) {
+            this_uninitialized = false;
+        }
+
+        for target in insn.branch_targets() {
+            checker.flows_to(insn.offset, &after, this_uninitialized, target)?;
+        }
+
+        if !ends_flow(insn.opcode) {
+            if i + 1 == instructions.len() {
+                return Err(fail(String::from("execution falls off the end of the code")));
+            }
+            current = Some(after);
+        }
+    }
+
+    Ok(())
+}
+
+impl<'a> TypeChecker<'a> {
+    // Checks that a frame flowing from the instruction at `offset` is
+    // assignable to the one declared at `target`.
+    fn flows_to(&self, offset: u32, frame: &Frame, this_uninitialized: bool, target: u32) -> Result<(), VerifyError> {
+        let error = |message: String, expected: Option<&Frame>| VerifyError {
+            method: self.method.to_string(),
+            offset,
+            message,
+            expected: expected.cloned().map(Box::new),
+            actual: Some(Box::new(frame.clone())),
+        };
+
+        let declared = match self.declared.get(&target) {
+            Some(declared) => declared,
+            None => return Err(error(format!("no StackMapTable frame at {}", target), None)),
+        };
+
+        for (i, (found, expected)) in frame.locals.iter().zip(&declared.locals).enumerate() {
+            if !found.is_assignable_to(expected, self.hierarchy) {
+                let message = format!("local {} is {} but the frame at {} declares {}", i, found, target, expected);
+                return Err(error(message, Some(declared)));
+            }
+        }
+
+        if frame.stack.len() != declared.stack.len() {
+            let message = format!(
+                "stack has {} slots but the frame at {} declares {}",
+                frame.stack.len(),
+                target,
+                declared.stack.len()
+            );
+            return Err(error(message, Some(declared)));
+        }
+
+        for (i, (found, expected)) in frame.stack.iter().zip(&declared.stack).enumerate() {
+            if !found.is_assignable_to(expected, self.hierarchy) {
+                let message = format!("stack slot {} is {} but the frame at {} declares {}", i, found, target, expected);
+                return Err(error(message, Some(declared)));
+            }
+        }
+
+        if this_uninitialized && !declared.locals.contains(&Type::UninitializedThis) {
+            let message = format!("this is not initialized but the frame at {} declares it is", target);
+            return Err(error(message, Some(declared)));
+        }
+
+        Ok(())
+    }
+
+    // The checks of an instruction beyond those computing its effect makes:
+    // that values are assignable to the types of descriptors, that arrays
+    // have the right element type, and the rules of constructors.
+    fn check_operands(&self, insn: &Instruction, frame: &Frame, this_uninitialized: bool) -> Result<(), String> {
+        let op = insn.opcode;
+        let index = match insn.operand {
+            Operand::Constant(index) | Operand::InvokeInterface { index, .. } | Operand::InvokeDynamic(index) => index,
+            _ => 0,
+        };
+        let mut stack = frame.clone();
+
+        match op {
+            // jsr, ret, jsr_w
+            0xa8 | 0xa9 | 0xc9 => return Err(format!("{} is not allowed in class files of version 51 and later", insn.mnemonic())),
+            // iaload..saload
+            0x2e..=0x35 => {
+                stack.pop()?;
+                self.check_array(insn, &stack.pop()?, (op - 0x2e) as usize)?;
+            }
+            // iastore..sastore
+            0x4f..=0x56 => {
+                stack.pop()?;
+                stack.pop()?;
+                self.check_array(insn, &stack.pop()?, (op - 0x4f) as usize)?;
+            }
+            // arraylength
+            0xbe => match stack.pop()? {
+                Type::Null => {}
+                Type::Reference(ref array) if array.starts_with('[') => {}
+                t => return Err(format!("arraylength needs an array, found {}", t)),
+            },
+            // ireturn..areturn
+            0xac..=0xb0 => {
+                let value = stack.pop()?;
+
+                match self.return_type {
+                    None => return Err(format!("{} in a void method", insn.mnemonic())),
+                    Some(ref return_type) if !value.is_assignable_to(return_type, self.hierarchy) => {
+                        return Err(format!("returns {} but the method returns {}", value, return_type));
+                    }
+                    _ => {}
+                }
+            }
+            // return
+            0xb1 => {
+                if let Some(ref return_type) = self.return_type {
+                    return Err(format!("return in a method returning {}", return_type));
+                }
+                if this_uninitialized {
+                    return Err(String::from("return before this is initialized by a call to <init>"));
+                }
+            }
+            // athrow
+            0xbf => {
+                let value = stack.pop()?;
+                if !value.is_assignable_to(&Type::Reference(String::from(THROWABLE)), self.hierarchy) {
+                    return Err(format!("athrow of {}, which is not a Throwable", value));
+                }
+            }
+            // putstatic, getfield, putfield
+            0xb3..=0xb5 => {
+                let (class, name, descriptor) = self.interpreter.member_descriptor(index)?;
+                let field_type = parse_field_descriptor(&descriptor).ok_or_else(|| format!("bad descriptor {}", descriptor))?;
+                let t = Type::from_field_type(&field_type);
+
+                if op != 0xb4 {
+                    let value = stack.pop()?;
+                    if !value.is_assignable_to(&t, self.hierarchy) {
+                        return Err(format!("{} stores {} in {}.{} of type {}", insn.mnemonic(), value, class, name, t));
+                    }
+                }
+                if op != 0xb3 {
+                    let receiver = stack.pop()?;
+
+                    // Constructors may assign their own class's fields
+                    // before calling <init>.
+                    let allowed = match receiver {
+                        Type::UninitializedThis => op == 0xb5 && class == self.this_class,
+                        ref receiver => receiver.is_assignable_to(&Type::Reference(class.clone()), self.hierarchy),
+                    };
+                    if !allowed {
+                        return Err(format!("{} of {}.{} on {}", insn.mnemonic(), class, name, receiver));
+                    }
+                }
+            }
+            // invokevirtual, invokespecial, invokestatic, invokeinterface,
+            // invokedynamic
+            0xb6..=0xba => {
+                let (class, name, descriptor) = if op == 0xba {
+                    (String::new(), String::new(), self.interpreter.dynamic_descriptor(index)?)
+                } else {
+                    self.interpreter.member_descriptor(index)?
+                };
+                let method = parse_method_descriptor(&descriptor).ok_or_else(|| format!("bad descriptor {}", descriptor))?;
+
+                for (i, parameter) in method.parameters.iter().enumerate().rev() {
+                    let value = stack.pop()?;
+                    let t = Type::from_field_type(parameter);
+
+                    if !value.is_assignable_to(&t, self.hierarchy) {
+                        return Err(format!("argument {} of {}{} is {} but must be {}", i, name, descriptor, value, t));
+                    }
+                }
+
+                if op == 0xb8 || op == 0xba {
+                    return Ok(());
+                }
+                let receiver = stack.pop()?;
+
+                if name == "<init>" {
+                    if op != 0xb7 {
+                        return Err(format!("<init> called with {}", insn.mnemonic()));
+                    }
+
+                    match receiver {
+                        Type::UninitializedThis if class != self.this_class && Some(&class) != self.superclass.as_ref() => {
+                            return Err(format!("this initialized by {}.<init>, which is not of this class or its superclass", class));
+                        }
+                        Type::Uninitialized(offset) => {
+                            let created = self.interpreter.new_class(offset).unwrap_or_default();
+                            if created != class {
+                                return Err(format!("{} created at {} initialized by {}.<init>", created, offset, class));
+                            }
+                        }
+                        _ => {}
+                    }
+                } else {
+                    // invokespecial calls methods of superclasses and
+                    // interfaces, but only on this.
+                    let expected = if op == 0xb7 { &self.this_class } else { &class };
+
+                    if !receiver.is_assignable_to(&Type::Reference(expected.clone()), self.hierarchy) {
+                        return Err(format!("{} of {}.{}{} on {}, which is not a {}", insn.mnemonic(), class, name, descriptor, receiver, expected));
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    // Checks the array of an xaload or xastore, by its position in either
+    // family.
+    fn check_array(&self, insn: &Instruction, array: &Type, kind: usize) -> Result<(), String> {
+        const ELEMENTS: [(&[&str], &str); 8] = [
+            (&["[I"], "an int array"),
+            (&["[J"], "a long array"),
+            (&["[F"], "a float array"),
+            (&["[D"], "a double array"),
+            (&["[L", "[["], "an array of references"),
+            (&["[B", "[Z"], "a byte or boolean array"),
+            (&["[C"], "a char array"),
+            (&["[S"], "a short array"),
+        ];
+        let (prefixes, description) = ELEMENTS[kind];
+
+        match *array {
+            Type::Null => Ok(()),
+            Type::Reference(ref descriptor) if prefixes.iter().any(|prefix| descriptor.starts_with(prefix)) => Ok(()),
+            _ => Err(format!("{} needs {}, found {}", insn.mnemonic(), description, array)),
+        }
+    }
+
+    // Whether an instruction is the call to <init> that initializes this.
+    fn initializes_this(&self, insn: &Instruction, frame: &Frame) -> bool {
+        let index = match insn.operand {
+            Operand::Constant(index) if insn.opcode == 0xb7 => index,
+            _ => return false,
+        };
+        let descriptor = match self.interpreter.member_descriptor(index) {
+            Ok((_, ref name, ref descriptor)) if name == "<init>" => descriptor.clone(),
+            _ => return false,
+        };
+
+        let arguments: usize = parse_method_descriptor(&descriptor)
+            .map(|method| method.parameters.iter().map(|parameter| parameter.slots() as usize).sum())
+            .unwrap_or(0);
+
+        frame.stack.len() > arguments && frame.stack[frame.stack.len() - 1 - arguments] == Type::UninitializedThis
+    }
+}
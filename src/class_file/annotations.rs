@@ -0,0 +1,80 @@
+use std::io::Read;
+
+use read_util::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annotation {
+    pub type_index: u16,
+    pub element_value_pairs: Vec<ElementValuePair>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ElementValuePair {
+    pub element_name_index: u16,
+    pub value: ElementValue,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ElementValue {
+    // Tag is one of B C D F I J S Z s, indexing a constant of matching type.
+    Const { tag: u8, const_value_index: u16 },
+    Enum { type_name_index: u16, const_name_index: u16 },
+    Class { class_info_index: u16 },
+    Annotation(Annotation),
+    Array(Vec<ElementValue>),
+}
+
+pub fn read_annotations(f: &mut dyn Read) -> Result<Vec<Annotation>, String> {
+    let num_annotations = read_u16(f)?;
+
+    (0..num_annotations).map(|_| read_annotation(f)).collect()
+}
+
+pub fn read_parameter_annotations(f: &mut dyn Read) -> Result<Vec<Vec<Annotation>>, String> {
+    let num_parameters = read_u8(f)?;
+
+    (0..num_parameters).map(|_| read_annotations(f)).collect()
+}
+
+pub fn read_annotation(f: &mut dyn Read) -> Result<Annotation, String> {
+    let type_index = read_u16(f)?;
+    let num_element_value_pairs = read_u16(f)?;
+
+    let element_value_pairs = (0..num_element_value_pairs)
+        .map(|_| {
+            Ok(ElementValuePair {
+                element_name_index: read_u16(f)?,
+                value: read_element_value(f)?,
+            })
+        })
+        .collect::<Result<_, String>>()?;
+
+    Ok(Annotation {
+        type_index,
+        element_value_pairs,
+    })
+}
+
+pub fn read_element_value(f: &mut dyn Read) -> Result<ElementValue, String> {
+    let tag = read_u8(f)?;
+
+    Ok(match tag {
+        b'B' | b'C' | b'D' | b'F' | b'I' | b'J' | b'S' | b'Z' | b's' => ElementValue::Const {
+            tag,
+            const_value_index: read_u16(f)?,
+        },
+        b'e' => ElementValue::Enum {
+            type_name_index: read_u16(f)?,
+            const_name_index: read_u16(f)?,
+        },
+        b'c' => ElementValue::Class {
+            class_info_index: read_u16(f)?,
+        },
+        b'@' => ElementValue::Annotation(read_annotation(f)?),
+        b'[' => {
+            let num_values = read_u16(f)?;
+            ElementValue::Array((0..num_values).map(|_| read_element_value(f)).collect::<Result<_, String>>()?)
+        }
+        _ => return Err(format!("unknown element_value tag {:#x}", tag)),
+    })
+}
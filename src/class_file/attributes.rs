@@ -1,15 +1,259 @@
+use schemars::JsonSchema;
 use serde::Serialize;
 
 use class_file::Array;
-use serialization::serialize_bytecode;
+use class_file::constant_pool::ConstantPool;
+use class_file::reader::read_attributes;
+use read_util::*;
+use serialization::{hex_bytes_schema, serialize_hex_bytes};
 
 pub type Attributes = Array<AttributeInfo>;
 
-#[derive(Debug, Default, Serialize)]
+#[derive(Debug, Default, Serialize, JsonSchema)]
 pub struct AttributeInfo {
     pub attribute_name_index: u16,
     pub attribute_length: u32,
 
-    #[serde(serialize_with = "serialize_bytecode")]
+    #[serde(serialize_with = "serialize_hex_bytes")]
+    #[schemars(schema_with = "hex_bytes_schema")]
     pub info: Vec<u8>,
-}
\ No newline at end of file
+}
+
+impl AttributeInfo {
+    pub fn name(&self, constant_pool: &ConstantPool) -> Option<String> {
+        constant_pool.utf8(self.attribute_name_index)
+    }
+}
+
+impl Attributes {
+    pub fn find(&self, constant_pool: &ConstantPool, name: &str) -> Option<&AttributeInfo> {
+        self.array
+            .iter()
+            .find(|a| a.name(constant_pool).is_some_and(|n| n == name))
+    }
+
+    /// Decodes the attribute of a name with `read`, if there is one.
+    pub fn decode<T, F>(&self, constant_pool: &ConstantPool, name: &str, read: F) -> Result<Option<T>, String>
+        where F: FnOnce(&[u8]) -> Result<T, String> {
+        match self.find(constant_pool, name) {
+            Some(attribute) => read(&attribute.info).map(Some).map_err(|e| malformed(name, e)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct CodeAttribute {
+    pub max_stack: u16,
+    pub max_locals: u16,
+    pub code: Vec<u8>,
+    pub exception_table: Vec<ExceptionTableEntry>,
+    pub attributes: Attributes,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExceptionTableEntry {
+    pub start_pc: u16,
+    pub end_pc: u16,
+    pub handler_pc: u16,
+    pub catch_type: u16,
+}
+
+#[derive(Debug, Clone)]
+pub struct InnerClass {
+    pub inner_class_info_index: u16,
+    pub outer_class_info_index: u16,
+    pub inner_name_index: u16,
+    pub inner_class_access_flags: u16,
+}
+
+#[derive(Debug, Clone)]
+pub struct LineNumber {
+    pub start_pc: u16,
+    pub line_number: u16,
+}
+
+#[derive(Debug, Clone)]
+pub struct LocalVariable {
+    pub start_pc: u16,
+    pub length: u16,
+    pub name_index: u16,
+    pub descriptor_index: u16,
+    pub index: u16,
+}
+
+#[derive(Debug, Clone)]
+pub struct MethodParameter {
+    pub name_index: u16,
+    pub access_flags: u16,
+}
+
+#[derive(Debug, Clone)]
+pub struct BootstrapMethod {
+    pub bootstrap_method_ref: u16,
+    pub bootstrap_arguments: Vec<u16>,
+}
+
+#[derive(Debug)]
+pub struct RecordComponent {
+    pub name_index: u16,
+    pub descriptor_index: u16,
+    pub attributes: Attributes,
+}
+
+pub fn read_code(mut info: &[u8]) -> Result<CodeAttribute, String> {
+    let f = &mut info;
+
+    let max_stack = read_u16(f)?;
+    let max_locals = read_u16(f)?;
+    let code_length = read_u32(f)?;
+    let code = read_vec_u8(f, code_length as usize)?;
+
+    let exception_table_length = read_u16(f)?;
+    let exception_table = (0..exception_table_length)
+        .map(|_| {
+            Ok(ExceptionTableEntry {
+                start_pc: read_u16(f)?,
+                end_pc: read_u16(f)?,
+                handler_pc: read_u16(f)?,
+                catch_type: read_u16(f)?,
+            })
+        })
+        .collect::<Result<_, String>>()?;
+
+    let attributes_count = read_u16(f)?;
+
+    Ok(CodeAttribute {
+        max_stack,
+        max_locals,
+        code,
+        exception_table,
+        attributes: read_attributes(f, attributes_count)?,
+    })
+}
+
+// ConstantValue, Signature, SourceFile, NestHost and other attributes made of
+// a single constant pool index.
+pub fn read_index(mut info: &[u8]) -> Result<u16, String> {
+    read_u16(&mut info)
+}
+
+// Exceptions, NestMembers, PermittedSubclasses and other attributes made of a
+// counted table of constant pool indices.
+pub fn read_index_table(mut info: &[u8]) -> Result<Vec<u16>, String> {
+    let f = &mut info;
+    let length = read_u16(f)?;
+
+    read_vec_u16(f, length as usize)
+}
+
+pub fn read_inner_classes(mut info: &[u8]) -> Result<Vec<InnerClass>, String> {
+    let f = &mut info;
+    let number_of_classes = read_u16(f)?;
+
+    (0..number_of_classes)
+        .map(|_| {
+            Ok(InnerClass {
+                inner_class_info_index: read_u16(f)?,
+                outer_class_info_index: read_u16(f)?,
+                inner_name_index: read_u16(f)?,
+                inner_class_access_flags: read_u16(f)?,
+            })
+        })
+        .collect()
+}
+
+pub fn read_line_number_table(mut info: &[u8]) -> Result<Vec<LineNumber>, String> {
+    let f = &mut info;
+    let line_number_table_length = read_u16(f)?;
+
+    (0..line_number_table_length)
+        .map(|_| {
+            Ok(LineNumber {
+                start_pc: read_u16(f)?,
+                line_number: read_u16(f)?,
+            })
+        })
+        .collect()
+}
+
+// Also used for LocalVariableTypeTable, where descriptor_index points to a
+// signature instead.
+pub fn read_local_variable_table(mut info: &[u8]) -> Result<Vec<LocalVariable>, String> {
+    let f = &mut info;
+    let local_variable_table_length = read_u16(f)?;
+
+    (0..local_variable_table_length)
+        .map(|_| {
+            Ok(LocalVariable {
+                start_pc: read_u16(f)?,
+                length: read_u16(f)?,
+                name_index: read_u16(f)?,
+                descriptor_index: read_u16(f)?,
+                index: read_u16(f)?,
+            })
+        })
+        .collect()
+}
+
+pub fn read_method_parameters(mut info: &[u8]) -> Result<Vec<MethodParameter>, String> {
+    let f = &mut info;
+    let parameters_count = read_u8(f)?;
+
+    (0..parameters_count)
+        .map(|_| {
+            Ok(MethodParameter {
+                name_index: read_u16(f)?,
+                access_flags: read_u16(f)?,
+            })
+        })
+        .collect()
+}
+
+pub fn read_bootstrap_methods(mut info: &[u8]) -> Result<Vec<BootstrapMethod>, String> {
+    let f = &mut info;
+    let num_bootstrap_methods = read_u16(f)?;
+
+    (0..num_bootstrap_methods)
+        .map(|_| {
+            let bootstrap_method_ref = read_u16(f)?;
+            let num_bootstrap_arguments = read_u16(f)?;
+
+            Ok(BootstrapMethod {
+                bootstrap_method_ref,
+                bootstrap_arguments: read_vec_u16(f, num_bootstrap_arguments as usize)?,
+            })
+        })
+        .collect()
+}
+
+// Returns the class_index and method_index of an EnclosingMethod attribute.
+pub fn read_enclosing_method(mut info: &[u8]) -> Result<(u16, u16), String> {
+    let f = &mut info;
+
+    Ok((read_u16(f)?, read_u16(f)?))
+}
+
+pub fn read_record(mut info: &[u8]) -> Result<Vec<RecordComponent>, String> {
+    let f = &mut info;
+    let components_count = read_u16(f)?;
+
+    (0..components_count)
+        .map(|_| {
+            let name_index = read_u16(f)?;
+            let descriptor_index = read_u16(f)?;
+            let attributes_count = read_u16(f)?;
+
+            Ok(RecordComponent {
+                name_index,
+                descriptor_index,
+                attributes: read_attributes(f, attributes_count)?,
+            })
+        })
+        .collect()
+}
+
+/// The error of an attribute whose contents couldn't be decoded.
+pub fn malformed(name: &str, e: String) -> String {
+    format!("malformed {} attribute: {}", name, e)
+}
@@ -0,0 +1,769 @@
+// A canonical form of a class file, for checking that builds are
+// reproducible and for stable snapshots. The constant pool is renumbered in
+// the order its entries are first used, without duplicates or unused
+// entries, and members and attributes, whose order has no meaning, are
+// sorted by name. Every index into the pool is rewritten to match.
+//
+// Attributes that aren't defined by the JVM specification or the JDK's module
+// tools may hold pool indices in a layout only their authors know, so classes
+// with any can't be canonicalized.
+//
+// The same walk over every reference a class makes to its pool tells which
+// entries are in use.
+
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeSet, HashMap};
+
+use assembly::pool::{Constant, PoolBuilder, Ref};
+use assembly::syntax::{constant_spec, member_ref_indices};
+use bytecode::{self, Operand};
+use class_file::attributes::*;
+use class_file::constant_pool::*;
+use class_file::fields::FieldInfo;
+use class_file::methods::MethodInfo;
+use class_file::writer::{write_attributes, write_code};
+use class_file::ClassFile;
+use write_util::*;
+
+const LDC: u8 = 0x12;
+
+pub fn canonicalize(cf: &ClassFile) -> Result<ClassFile, String> {
+    let mut canonicalizer = Canonicalizer::new(cf)?;
+
+    // Constants loaded by ldc come first, as it can only refer to the first
+    // 256 entries.
+    for method in sorted_members(cf, &cf.methods.array, |m| (m.name_index, m.descriptor_index)) {
+        if let Some(attribute) = method.attributes.find(&cf.constant_pool, "Code") {
+            let code = read_code(&attribute.info).map_err(|e| malformed("Code", e))?;
+            for insn in bytecode::decode(&code.code).map_err(|e| e.to_string())? {
+                if let (LDC, Operand::Constant(index)) = (insn.opcode, &insn.operand) {
+                    canonicalizer.allocate(*index)?;
+                }
+            }
+        }
+    }
+
+    canonicalizer.class(cf)?;
+    canonicalizer.finish_collecting()?;
+
+    let mut canonical = canonicalizer.class(cf)?;
+    canonical.constant_pool = canonicalizer.pool()?;
+    canonical.constant_pool_count = canonical.constant_pool.count();
+
+    Ok(canonical)
+}
+
+/// The references of a class to its constant pool.
+#[derive(Debug, Default)]
+pub struct References {
+    /// Entries referred to from outside the pool, or from entries that are.
+    pub used: BTreeSet<u16>,
+
+    /// Entries with the same content as an earlier one, and that one.
+    pub duplicates: Vec<(u16, u16)>,
+
+    /// Indices of bootstrap methods no dynamically computed constant or call
+    /// site refers to.
+    pub unused_bootstrap_methods: Vec<u16>,
+
+    /// Attributes of unknown layout, which may refer to entries that aren't
+    /// counted as used.
+    pub unknown_attributes: Vec<String>,
+}
+
+pub fn references(cf: &ClassFile) -> Result<References, String> {
+    let cp = &cf.constant_pool;
+    let mut canonicalizer = Canonicalizer::new(cf)?;
+    canonicalizer.lenient = true;
+
+    canonicalizer.class(cf)?;
+    canonicalizer.finish_collecting()?;
+
+    let mut first = HashMap::new();
+    let mut duplicates = Vec::new();
+    for (index, _) in cp.entries() {
+        if let Some(content) = constant_spec(cp, index) {
+            match first.entry(content) {
+                Entry::Occupied(entry) => duplicates.push((index, *entry.get())),
+                Entry::Vacant(entry) => {
+                    entry.insert(index);
+                }
+            }
+        }
+    }
+
+    Ok(References {
+        used: canonicalizer.new_index.keys().cloned().collect(),
+        duplicates,
+        unused_bootstrap_methods: canonicalizer.bootstrap_order[canonicalizer.used_bootstrap_methods..].to_vec(),
+        unknown_attributes: canonicalizer.unknown_attributes,
+    })
+}
+
+#[derive(PartialEq)]
+enum Pass {
+    // Allocates new indices for the entries used, leaving indices as they are.
+    Collect,
+    Rewrite,
+}
+
+struct Canonicalizer<'a> {
+    cp: &'a ConstantPool,
+    pass: Pass,
+    // Whether attributes of unknown layout are left as they are rather than
+    // failing, when only collecting.
+    lenient: bool,
+    unknown_attributes: Vec<String>,
+
+    // The old index of each new entry, in order.
+    order: Vec<u16>,
+    // Every old index in use, in the order first used.
+    visited: Vec<u16>,
+    new_index: HashMap<u16, u16>,
+    // Entries with the same content share an index.
+    by_content: HashMap<String, u16>,
+    next: usize,
+
+    bootstrap_methods: Vec<BootstrapMethod>,
+    // Old bootstrap method indices in the order they are first used, then
+    // the unused ones.
+    bootstrap_order: Vec<u16>,
+    used_bootstrap_methods: usize,
+}
+
+impl<'a> Canonicalizer<'a> {
+    fn new(cf: &'a ClassFile) -> Result<Canonicalizer<'a>, String> {
+        let cp = &cf.constant_pool;
+        let bootstrap_methods = match cf.attributes.find(cp, "BootstrapMethods") {
+            Some(attribute) => read_bootstrap_methods(&attribute.info).map_err(|e| malformed("BootstrapMethods", e))?,
+            None => Vec::new(),
+        };
+
+        Ok(Canonicalizer {
+            cp,
+            pass: Pass::Collect,
+            lenient: false,
+            unknown_attributes: Vec::new(),
+            order: Vec::new(),
+            visited: Vec::new(),
+            new_index: HashMap::new(),
+            by_content: HashMap::new(),
+            next: 1,
+            bootstrap_methods,
+            bootstrap_order: Vec::new(),
+            used_bootstrap_methods: 0,
+        })
+    }
+
+    fn allocate(&mut self, old: u16) -> Result<(), String> {
+        if self.new_index.contains_key(&old) {
+            return Ok(());
+        }
+
+        let content = constant_spec(self.cp, old).ok_or_else(|| format!("constant pool index {} is not a valid entry", old))?;
+        self.visited.push(old);
+        if let Some(&new) = self.by_content.get(&content) {
+            self.new_index.insert(old, new);
+            return Ok(());
+        }
+
+        let wide = matches!(self.cp.tag(old), Some(CpTag::Long) | Some(CpTag::Double));
+        let new = self.next;
+        self.next += if wide { 2 } else { 1 };
+        if self.next > 0x10000 {
+            return Err("constant pool is full".to_string());
+        }
+
+        self.by_content.insert(content, new as u16);
+        self.new_index.insert(old, new as u16);
+        self.order.push(old);
+        Ok(())
+    }
+
+    // Allocates the entries that the entries in use refer to, level by
+    // level, along with the arguments of bootstrap methods as they are first
+    // used, and finally those of unused bootstrap methods.
+    fn finish_collecting(&mut self) -> Result<(), String> {
+        let mut i = 0;
+        let mut all_used = false;
+
+        loop {
+            // Duplicates are visited too, though what they refer to only has
+            // the content of entries already allocated.
+            while i < self.visited.len() {
+                let (referenced, bootstrap) = entry_references(self.cp, self.visited[i]);
+
+                for index in referenced {
+                    self.allocate(index)?;
+                }
+                if let Some(bootstrap) = bootstrap {
+                    self.use_bootstrap_method(bootstrap)?;
+                }
+
+                i += 1;
+            }
+
+            if !all_used {
+                self.used_bootstrap_methods = self.bootstrap_order.len();
+                all_used = true;
+            }
+
+            match (0..self.bootstrap_methods.len() as u16).find(|b| !self.bootstrap_order.contains(b)) {
+                Some(unused) => self.use_bootstrap_method(unused)?,
+                None => break,
+            }
+        }
+
+        self.pass = Pass::Rewrite;
+        Ok(())
+    }
+
+    fn use_bootstrap_method(&mut self, index: u16) -> Result<(), String> {
+        if self.bootstrap_order.contains(&index) {
+            return Ok(());
+        }
+
+        let method = match self.bootstrap_methods.get(index as usize) {
+            Some(method) => method.clone(),
+            None => return Err(format!("bootstrap method index {} is out of range", index)),
+        };
+        self.bootstrap_order.push(index);
+
+        self.allocate(method.bootstrap_method_ref)?;
+        for argument in method.bootstrap_arguments {
+            self.allocate(argument)?;
+        }
+
+        Ok(())
+    }
+
+    fn index(&mut self, old: u16) -> Result<u16, String> {
+        if old == 0 {
+            return Ok(0);
+        }
+
+        match self.pass {
+            Pass::Collect => {
+                self.allocate(old)?;
+                Ok(old)
+            }
+            Pass::Rewrite => Ok(self.new_index[&old]),
+        }
+    }
+
+    fn pool(&self) -> Result<ConstantPool, String> {
+        let mut builder = PoolBuilder::new();
+
+        for &old in &self.order {
+            builder.declare(self.new_index[&old], self.constant(old)?)?;
+        }
+
+        builder.build()
+    }
+
+    // An entry with the indices it holds rewritten.
+    fn constant(&self, old: u16) -> Result<Constant, String> {
+        let cp = self.cp;
+        let info = cp.get(old).unwrap().as_any();
+        let r = |index: u16| Ref::Index(self.new_index[&index]);
+        let bootstrap = |index: u16| self.bootstrap_order.iter().position(|&b| b == index).unwrap() as u16;
+
+        Ok(match cp.tag(old).unwrap() {
+            CpTag::Utf8 => Constant::Utf8(info.downcast_ref::<Utf8Info>().unwrap().bytes.clone()),
+            CpTag::Integer => Constant::Integer(info.downcast_ref::<IntegerInfo>().unwrap().bytes as i32),
+            CpTag::Float => Constant::Float(info.downcast_ref::<FloatInfo>().unwrap().bytes),
+            CpTag::Long => {
+                let info = info.downcast_ref::<LongInfo>().unwrap();
+                Constant::Long(((info.high_bytes as u64) << 32 | info.low_bytes as u64) as i64)
+            }
+            CpTag::Double => {
+                let info = info.downcast_ref::<DoubleInfo>().unwrap();
+                Constant::Double((info.high_bytes as u64) << 32 | info.low_bytes as u64)
+            }
+            CpTag::Class => Constant::Class(r(info.downcast_ref::<ClassInfo>().unwrap().name_index)),
+            CpTag::String => Constant::String(r(info.downcast_ref::<StringInfo>().unwrap().string_index)),
+            tag @ CpTag::Fieldref | tag @ CpTag::Methodref | tag @ CpTag::InterfaceMethodref => {
+                let (class, name_and_type) = member_ref_indices(cp, old).unwrap();
+                match tag {
+                    CpTag::Fieldref => Constant::Fieldref(r(class), r(name_and_type)),
+                    CpTag::Methodref => Constant::Methodref(r(class), r(name_and_type)),
+                    _ => Constant::InterfaceMethodref(r(class), r(name_and_type)),
+                }
+            }
+            CpTag::NameAndType => {
+                let info = info.downcast_ref::<NameAndTypeInfo>().unwrap();
+                Constant::NameAndType(r(info.name_index), r(info.descriptor_index))
+            }
+            CpTag::MethodHandle => {
+                let info = info.downcast_ref::<MethodHandleInfo>().unwrap();
+                Constant::MethodHandle(info.reference_kind, r(info.reference_index))
+            }
+            CpTag::MethodType => Constant::MethodType(r(info.downcast_ref::<MethodTypeInfo>().unwrap().descriptor_index)),
+            CpTag::Dynamic => {
+                let info = info.downcast_ref::<DynamicInfo>().unwrap();
+                Constant::Dynamic(bootstrap(info.bootstrap_method_attr_index), r(info.name_and_type_index))
+            }
+            CpTag::InvokeDynamic => {
+                let info = info.downcast_ref::<InvokeDynamicInfo>().unwrap();
+                Constant::InvokeDynamic(bootstrap(info.bootstrap_method_attr_index), r(info.name_and_type_index))
+            }
+            CpTag::Module => Constant::Module(r(info.downcast_ref::<ModuleInfo>().unwrap().name_index)),
+            CpTag::Package => Constant::Package(r(info.downcast_ref::<PackageInfo>().unwrap().name_index)),
+        })
+    }
+
+    // The class with its indices rewritten, or as they are while collecting,
+    // and without a constant pool.
+    fn class(&mut self, cf: &ClassFile) -> Result<ClassFile, String> {
+        let mut canonical = ClassFile {
+            magic: cf.magic,
+            minor_version: cf.minor_version,
+            major_version: cf.major_version,
+            access_flags: cf.access_flags,
+            this_class: self.index(cf.this_class)?,
+            super_class: self.index(cf.super_class)?,
+            ..Default::default()
+        };
+
+        for &interface in &cf.interfaces {
+            canonical.interfaces.push(self.index(interface)?);
+        }
+
+        for field in sorted_members(cf, &cf.fields.array, |f| (f.name_index, f.descriptor_index)) {
+            let attributes = self.attributes(&field.attributes)?;
+            canonical.fields.array.push(FieldInfo {
+                access_flags: field.access_flags,
+                name_index: self.index(field.name_index)?,
+                descriptor_index: self.index(field.descriptor_index)?,
+                attributes_count: attributes.array.len() as u16,
+                attributes,
+            });
+        }
+
+        for method in sorted_members(cf, &cf.methods.array, |m| (m.name_index, m.descriptor_index)) {
+            let attributes = self.attributes(&method.attributes)?;
+            canonical.methods.array.push(MethodInfo {
+                access_flags: method.access_flags,
+                name_index: self.index(method.name_index)?,
+                descriptor_index: self.index(method.descriptor_index)?,
+                attributes_count: attributes.array.len() as u16,
+                attributes,
+            });
+        }
+
+        canonical.attributes = self.attributes(&cf.attributes)?;
+
+        canonical.interfaces_count = canonical.interfaces.len() as u16;
+        canonical.fields_count = canonical.fields.array.len() as u16;
+        canonical.methods_count = canonical.methods.array.len() as u16;
+        canonical.attributes_count = canonical.attributes.array.len() as u16;
+
+        Ok(canonical)
+    }
+
+    fn attributes(&mut self, attributes: &Attributes) -> Result<Attributes, String> {
+        let mut sorted: Vec<(String, &AttributeInfo)> = attributes
+            .array
+            .iter()
+            .map(|attribute| (attribute.name(self.cp).unwrap_or_default(), attribute))
+            .collect();
+        sorted.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut canonical = Attributes::default();
+
+        for (name, attribute) in sorted {
+            let info = self.attribute(&name, &attribute.info).map_err(|e| format!("{} attribute: {}", name, e))?;
+
+            canonical.array.push(AttributeInfo {
+                attribute_name_index: self.index(attribute.attribute_name_index)?,
+                attribute_length: info.len() as u32,
+                info,
+            });
+        }
+
+        Ok(canonical)
+    }
+
+    fn attribute(&mut self, name: &str, info: &[u8]) -> Result<Vec<u8>, String> {
+        match name {
+            "Code" => return self.code(info),
+            "Record" => return self.record(info),
+            "BootstrapMethods" => return self.bootstrap_methods(),
+            _ => {}
+        }
+
+        let mut info = info.to_vec();
+        let mut c = Cursor { info: &mut info, at: 0 };
+
+        match name {
+            "ConstantValue" | "Signature" | "SourceFile" | "NestHost" | "ModuleMainClass" => self.rewrite(&mut c)?,
+            "Exceptions" | "NestMembers" | "PermittedSubclasses" | "ModulePackages" => {
+                let count = c.u16()?;
+                self.rewrite_many(&mut c, count)?;
+            }
+            "InnerClasses" => {
+                for _ in 0..c.u16()? {
+                    self.rewrite_many(&mut c, 3)?;
+                    c.skip(2)?;
+                }
+            }
+            "EnclosingMethod" => self.rewrite_many(&mut c, 2)?,
+            "LocalVariableTable" | "LocalVariableTypeTable" => {
+                for _ in 0..c.u16()? {
+                    c.skip(4)?;
+                    self.rewrite_many(&mut c, 2)?;
+                    c.skip(2)?;
+                }
+            }
+            "MethodParameters" => {
+                for _ in 0..c.u8()? {
+                    self.rewrite(&mut c)?;
+                    c.skip(2)?;
+                }
+            }
+            "Synthetic" | "Deprecated" | "SourceDebugExtension" | "LineNumberTable" | "ModuleResolution" => {
+                c.at = c.info.len()
+            }
+            "StackMapTable" => self.stack_map_table(&mut c)?,
+            "RuntimeVisibleAnnotations" | "RuntimeInvisibleAnnotations" => self.annotations(&mut c)?,
+            "RuntimeVisibleParameterAnnotations" | "RuntimeInvisibleParameterAnnotations" => {
+                for _ in 0..c.u8()? {
+                    self.annotations(&mut c)?;
+                }
+            }
+            "RuntimeVisibleTypeAnnotations" | "RuntimeInvisibleTypeAnnotations" => {
+                for _ in 0..c.u16()? {
+                    self.type_annotation(&mut c)?;
+                }
+            }
+            "AnnotationDefault" => self.element_value(&mut c)?,
+            "Module" => self.module(&mut c)?,
+            // Added to modules by the JDK's jlink and jmod tools.
+            "ModuleTarget" => self.rewrite(&mut c)?,
+            "ModuleHashes" => {
+                self.rewrite(&mut c)?;
+                for _ in 0..c.u16()? {
+                    self.rewrite(&mut c)?;
+                    let length = c.u16()?;
+                    c.skip(length as usize)?;
+                }
+            }
+            _ if self.lenient && self.pass == Pass::Collect => {
+                self.unknown_attributes.push(name.to_string());
+                c.at = c.info.len();
+            }
+            _ => return Err("unknown attributes may refer to the constant pool, so can't be rewritten".to_string()),
+        }
+
+        if c.at != c.info.len() {
+            return Err("unexpected data at the end".to_string());
+        }
+
+        Ok(info)
+    }
+
+    fn code(&mut self, info: &[u8]) -> Result<Vec<u8>, String> {
+        let code = read_code(info)?;
+        let mut bytes = code.code.clone();
+
+        for insn in bytecode::decode(&code.code).map_err(|e| e.to_string())? {
+            let at = insn.offset as usize + 1;
+
+            match insn.operand {
+                Operand::Constant(index) if insn.opcode == LDC => {
+                    let new = self.index(index)?;
+                    if new > 0xff {
+                        return Err(format!("ldc at {} would need constant pool index {}", insn.offset, new));
+                    }
+                    bytes[at] = new as u8;
+                }
+                Operand::Constant(index)
+                | Operand::InvokeInterface { index, .. }
+                | Operand::InvokeDynamic(index)
+                | Operand::MultiANewArray { index, .. } => {
+                    let new = self.index(index)?;
+                    bytes[at..at + 2].copy_from_slice(&new.to_be_bytes());
+                }
+                _ => {}
+            }
+        }
+
+        let mut exception_table = code.exception_table.clone();
+        for entry in &mut exception_table {
+            entry.catch_type = self.index(entry.catch_type)?;
+        }
+
+        Ok(write_code(&CodeAttribute {
+            max_stack: code.max_stack,
+            max_locals: code.max_locals,
+            code: bytes,
+            exception_table,
+            attributes: self.attributes(&code.attributes)?,
+        }))
+    }
+
+    fn record(&mut self, info: &[u8]) -> Result<Vec<u8>, String> {
+        let components = read_record(info)?;
+        let mut out = Vec::new();
+
+        write_u16(&mut out, components.len() as u16);
+        for component in components {
+            write_u16(&mut out, self.index(component.name_index)?);
+            write_u16(&mut out, self.index(component.descriptor_index)?);
+            write_attributes(&mut out, &self.attributes(&component.attributes)?);
+        }
+
+        Ok(out)
+    }
+
+    // The bootstrap methods in the order they are first used, which calls to
+    // them already refer to. Their entries are collected along with those.
+    fn bootstrap_methods(&mut self) -> Result<Vec<u8>, String> {
+        let mut out = Vec::new();
+        if self.pass == Pass::Collect {
+            return Ok(out);
+        }
+
+        write_u16(&mut out, self.bootstrap_order.len() as u16);
+        for &index in &self.bootstrap_order {
+            let method = &self.bootstrap_methods[index as usize];
+
+            write_u16(&mut out, self.new_index[&method.bootstrap_method_ref]);
+            write_u16(&mut out, method.bootstrap_arguments.len() as u16);
+            for argument in &method.bootstrap_arguments {
+                write_u16(&mut out, self.new_index[argument]);
+            }
+        }
+
+        Ok(out)
+    }
+
+    // Rewrites the index at the cursor.
+    fn rewrite(&mut self, c: &mut Cursor) -> Result<(), String> {
+        let at = c.at;
+        let new = self.index(c.u16()?)?;
+        c.info[at..at + 2].copy_from_slice(&new.to_be_bytes());
+
+        Ok(())
+    }
+
+    fn rewrite_many(&mut self, c: &mut Cursor, count: u16) -> Result<(), String> {
+        for _ in 0..count {
+            self.rewrite(c)?;
+        }
+
+        Ok(())
+    }
+
+    fn stack_map_table(&mut self, c: &mut Cursor) -> Result<(), String> {
+        for _ in 0..c.u16()? {
+            match c.u8()? {
+                0..=63 => {}
+                64..=127 => self.verification_types(c, 1)?,
+                247 => {
+                    c.skip(2)?;
+                    self.verification_types(c, 1)?;
+                }
+                248..=251 => c.skip(2)?,
+                frame_type @ 252..=254 => {
+                    c.skip(2)?;
+                    self.verification_types(c, frame_type as u16 - 251)?;
+                }
+                255 => {
+                    c.skip(2)?;
+                    let locals = c.u16()?;
+                    self.verification_types(c, locals)?;
+                    let stack = c.u16()?;
+                    self.verification_types(c, stack)?;
+                }
+                frame_type => return Err(format!("unknown frame type {}", frame_type)),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn verification_types(&mut self, c: &mut Cursor, count: u16) -> Result<(), String> {
+        for _ in 0..count {
+            match c.u8()? {
+                0..=6 => {}
+                7 => self.rewrite(c)?,
+                8 => c.skip(2)?,
+                tag => return Err(format!("unknown verification type {}", tag)),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn annotations(&mut self, c: &mut Cursor) -> Result<(), String> {
+        for _ in 0..c.u16()? {
+            self.annotation(c)?;
+        }
+
+        Ok(())
+    }
+
+    fn annotation(&mut self, c: &mut Cursor) -> Result<(), String> {
+        self.rewrite(c)?;
+
+        for _ in 0..c.u16()? {
+            self.rewrite(c)?;
+            self.element_value(c)?;
+        }
+
+        Ok(())
+    }
+
+    fn element_value(&mut self, c: &mut Cursor) -> Result<(), String> {
+        match c.u8()? {
+            b'B' | b'C' | b'D' | b'F' | b'I' | b'J' | b'S' | b'Z' | b's' | b'c' => self.rewrite(c),
+            b'e' => self.rewrite_many(c, 2),
+            b'@' => self.annotation(c),
+            b'[' => {
+                for _ in 0..c.u16()? {
+                    self.element_value(c)?;
+                }
+                Ok(())
+            }
+            tag => Err(format!("unknown element value tag {:?}", tag as char)),
+        }
+    }
+
+    fn type_annotation(&mut self, c: &mut Cursor) -> Result<(), String> {
+        // The target_info, by target_type.
+        match c.u8()? {
+            0x00 | 0x01 | 0x16 => c.skip(1)?,
+            0x10 | 0x17 | 0x42..=0x46 => c.skip(2)?,
+            0x11 | 0x12 => c.skip(2)?,
+            0x13..=0x15 => {}
+            0x40 | 0x41 => {
+                let length = c.u16()?;
+                c.skip(6 * length as usize)?;
+            }
+            0x47..=0x4b => c.skip(3)?,
+            target_type => return Err(format!("unknown type annotation target 0x{:02x}", target_type)),
+        }
+
+        let path_length = c.u8()?;
+        c.skip(2 * path_length as usize)?;
+
+        self.annotation(c)
+    }
+
+    fn module(&mut self, c: &mut Cursor) -> Result<(), String> {
+        self.rewrite(c)?;
+        c.skip(2)?;
+        self.rewrite(c)?;
+
+        // requires
+        for _ in 0..c.u16()? {
+            self.rewrite(c)?;
+            c.skip(2)?;
+            self.rewrite(c)?;
+        }
+
+        // exports and opens
+        for _ in 0..2 {
+            for _ in 0..c.u16()? {
+                self.rewrite(c)?;
+                c.skip(2)?;
+                let count = c.u16()?;
+                self.rewrite_many(c, count)?;
+            }
+        }
+
+        // uses
+        let count = c.u16()?;
+        self.rewrite_many(c, count)?;
+
+        // provides
+        for _ in 0..c.u16()? {
+            self.rewrite(c)?;
+            let count = c.u16()?;
+            self.rewrite_many(c, count)?;
+        }
+
+        Ok(())
+    }
+}
+
+// The entries an entry refers to, and the bootstrap method of a dynamically
+// computed constant or call site.
+fn entry_references(cp: &ConstantPool, index: u16) -> (Vec<u16>, Option<u16>) {
+    let info = match cp.get(index) {
+        Some(info) => info.as_any(),
+        None => return (vec![], None),
+    };
+
+    match cp.tag(index) {
+        Some(CpTag::Class) => (vec![info.downcast_ref::<ClassInfo>().unwrap().name_index], None),
+        Some(CpTag::String) => (vec![info.downcast_ref::<StringInfo>().unwrap().string_index], None),
+        Some(CpTag::Fieldref) | Some(CpTag::Methodref) | Some(CpTag::InterfaceMethodref) => {
+            let (class, name_and_type) = member_ref_indices(cp, index).unwrap();
+            (vec![class, name_and_type], None)
+        }
+        Some(CpTag::NameAndType) => {
+            let info = info.downcast_ref::<NameAndTypeInfo>().unwrap();
+            (vec![info.name_index, info.descriptor_index], None)
+        }
+        Some(CpTag::MethodHandle) => (vec![info.downcast_ref::<MethodHandleInfo>().unwrap().reference_index], None),
+        Some(CpTag::MethodType) => (vec![info.downcast_ref::<MethodTypeInfo>().unwrap().descriptor_index], None),
+        Some(CpTag::Dynamic) => {
+            let info = info.downcast_ref::<DynamicInfo>().unwrap();
+            (vec![info.name_and_type_index], Some(info.bootstrap_method_attr_index))
+        }
+        Some(CpTag::InvokeDynamic) => {
+            let info = info.downcast_ref::<InvokeDynamicInfo>().unwrap();
+            (vec![info.name_and_type_index], Some(info.bootstrap_method_attr_index))
+        }
+        Some(CpTag::Module) => (vec![info.downcast_ref::<ModuleInfo>().unwrap().name_index], None),
+        Some(CpTag::Package) => (vec![info.downcast_ref::<PackageInfo>().unwrap().name_index], None),
+        _ => (vec![], None),
+    }
+}
+
+// Fields or methods sorted by name and descriptor.
+fn sorted_members<'c, T, F>(cf: &ClassFile, members: &'c [T], indices: F) -> Vec<&'c T>
+    where F: Fn(&T) -> (u16, u16) {
+    let cp = &cf.constant_pool;
+    let mut sorted: Vec<(String, String, &T)> = members
+        .iter()
+        .map(|member| {
+            let (name, descriptor) = indices(member);
+            (cp.utf8(name).unwrap_or_default(), cp.utf8(descriptor).unwrap_or_default(), member)
+        })
+        .collect();
+    sorted.sort_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+
+    sorted.into_iter().map(|(_, _, member)| member).collect()
+}
+
+// Reads through attribute contents, which indices are rewritten in place.
+struct Cursor<'b> {
+    info: &'b mut [u8],
+    at: usize,
+}
+
+impl<'b> Cursor<'b> {
+    fn skip(&mut self, length: usize) -> Result<(), String> {
+        if self.at + length > self.info.len() {
+            return Err("truncated".to_string());
+        }
+
+        self.at += length;
+        Ok(())
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        self.skip(1)?;
+
+        Ok(self.info[self.at - 1])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        self.skip(2)?;
+
+        Ok((self.info[self.at - 2] as u16) << 8 | self.info[self.at - 1] as u16)
+    }
+}
@@ -0,0 +1,1150 @@
+// Format checking (JVMS 4.8): what a JVM checks of a class file when loading
+// it, short of verifying its code. Reading a class only needs the layout of
+// its constant pool, members and attribute headers to be right; checking also
+// looks at what every index refers to, at names and descriptors, and at the
+// contents of the attributes the specification defines, and at which access
+// flags may be combined. Each problem is
+// reported with where it is, rather than stopping at the first.
+//
+// Attributes of unknown names are ignored, as by a JVM, and so are known ones
+// in the wrong place or in class files older than they are, after reporting
+// that.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use serde::Serialize;
+
+use assembly::syntax::{format_flags, FlagContext};
+use bytecode::{decode, Operand};
+use class_file::access_flags::*;
+use class_file::attributes::{AttributeInfo, Attributes};
+use class_file::constant_pool::*;
+use class_file::descriptor::{parse_field_descriptor, parse_method_descriptor, FieldType};
+use class_file::reader::MAGIC;
+use class_file::version::JavaRelease;
+use class_file::ClassFile;
+
+pub const MAX_CODE_LENGTH: u32 = 65535;
+
+// Method descriptors and multianewarray are limited to this many parameter
+// slots and array dimensions.
+const MAX_DIMENSIONS: usize = 255;
+const MAX_PARAMETER_SLOTS: u16 = 255;
+
+const OBJECT: &str = "java/lang/Object";
+
+const VISIBILITY: u16 = ACC_PUBLIC | ACC_PRIVATE | ACC_PROTECTED;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    /// Where the problem is, such as `method f(I)V, Code attribute, offset 4`.
+    pub location: String,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)
+    }
+}
+
+/// The problems a JVM's format checking would find in a class.
+pub fn check(cf: &ClassFile) -> Vec<Diagnostic> {
+    let mut checker = Checker {
+        cf,
+        cp: &cf.constant_pool,
+        bootstrap_methods: 0,
+        diagnostics: Vec::new(),
+    };
+    checker.class();
+
+    checker.diagnostics
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Context {
+    Class,
+    Field,
+    Method,
+    Code,
+    RecordComponent,
+}
+
+impl Context {
+    fn name(self) -> &'static str {
+        match self {
+            Context::Class => "classes",
+            Context::Field => "fields",
+            Context::Method => "methods",
+            Context::Code => "Code attributes",
+            Context::RecordComponent => "record components",
+        }
+    }
+}
+
+const MEMBERS: &[Context] = &[Context::Class, Context::Field, Context::Method];
+const ANNOTATED: &[Context] = &[Context::Class, Context::Field, Context::Method, Context::RecordComponent];
+const TYPE_ANNOTATED: &[Context] =
+    &[Context::Class, Context::Field, Context::Method, Context::Code, Context::RecordComponent];
+
+// The attributes of JVMS 4.7, and those the JDK's module tools add, with the
+// first major version a JVM recognizes them in and where they may be.
+const ATTRIBUTES: &[(&str, u16, &[Context])] = &[
+    ("ConstantValue", 45, &[Context::Field]),
+    ("Code", 45, &[Context::Method]),
+    ("StackMapTable", 50, &[Context::Code]),
+    ("Exceptions", 45, &[Context::Method]),
+    ("InnerClasses", 45, &[Context::Class]),
+    ("EnclosingMethod", 49, &[Context::Class]),
+    ("Synthetic", 45, MEMBERS),
+    ("Signature", 49, ANNOTATED),
+    ("SourceFile", 45, &[Context::Class]),
+    ("SourceDebugExtension", 49, &[Context::Class]),
+    ("LineNumberTable", 45, &[Context::Code]),
+    ("LocalVariableTable", 45, &[Context::Code]),
+    ("LocalVariableTypeTable", 49, &[Context::Code]),
+    ("Deprecated", 45, MEMBERS),
+    ("RuntimeVisibleAnnotations", 49, ANNOTATED),
+    ("RuntimeInvisibleAnnotations", 49, ANNOTATED),
+    ("RuntimeVisibleParameterAnnotations", 49, &[Context::Method]),
+    ("RuntimeInvisibleParameterAnnotations", 49, &[Context::Method]),
+    ("RuntimeVisibleTypeAnnotations", 52, TYPE_ANNOTATED),
+    ("RuntimeInvisibleTypeAnnotations", 52, TYPE_ANNOTATED),
+    ("AnnotationDefault", 49, &[Context::Method]),
+    ("BootstrapMethods", 51, &[Context::Class]),
+    ("MethodParameters", 52, &[Context::Method]),
+    ("Module", 53, &[Context::Class]),
+    ("ModulePackages", 53, &[Context::Class]),
+    ("ModuleMainClass", 53, &[Context::Class]),
+    ("ModuleHashes", 53, &[Context::Class]),
+    ("ModuleResolution", 53, &[Context::Class]),
+    ("ModuleTarget", 53, &[Context::Class]),
+    ("NestHost", 55, &[Context::Class]),
+    ("NestMembers", 55, &[Context::Class]),
+    ("Record", 60, &[Context::Class]),
+    ("PermittedSubclasses", 61, &[Context::Class]),
+];
+
+// Attributes a structure may have more than one of.
+const REPEATABLE: &[&str] = &["LineNumberTable", "LocalVariableTable", "LocalVariableTypeTable", "Synthetic", "Deprecated"];
+
+const LOADABLE: &[CpTag] = &[
+    CpTag::Integer,
+    CpTag::Float,
+    CpTag::Long,
+    CpTag::Double,
+    CpTag::Class,
+    CpTag::String,
+    CpTag::MethodHandle,
+    CpTag::MethodType,
+    CpTag::Dynamic,
+];
+
+struct Checker<'a> {
+    cf: &'a ClassFile,
+    cp: &'a ConstantPool,
+    // The number of entries of the BootstrapMethods attribute.
+    bootstrap_methods: usize,
+    diagnostics: Vec<Diagnostic>,
+}
+
+// Attribute contents ended before their layout did.
+struct Truncated;
+
+impl<'a> Checker<'a> {
+    fn report<S: Into<String>>(&mut self, location: &str, message: S) {
+        self.diagnostics.push(Diagnostic { location: location.to_string(), message: message.into() });
+    }
+
+    fn class(&mut self) {
+        let cf = self.cf;
+        let cp = self.cp;
+
+        if cf.magic != MAGIC {
+            self.report("class", format!("magic is 0x{:08x}, not 0xcafebabe", cf.magic));
+        }
+        if cf.java_release().is_none() {
+            self.report("class", format!("version {}.{} is not one of any Java release", cf.major_version, cf.minor_version));
+        }
+
+        if let Some(attribute) = cf.attributes.find(cp, "BootstrapMethods") {
+            if let [high, low, ..] = attribute.info[..] {
+                self.bootstrap_methods = (high as usize) << 8 | low as usize;
+            }
+        }
+
+        for (index, _) in cp.entries() {
+            self.entry(index);
+        }
+
+        self.this_and_super();
+        self.class_flags();
+
+        let mut seen = HashSet::new();
+        for field in &cf.fields.array {
+            let (name, descriptor) = (cp.utf8(field.name_index), cp.utf8(field.descriptor_index));
+            let location = format!("field {} {}", display(&name, field.name_index), display(&descriptor, field.descriptor_index));
+
+            self.expect(&location, "name_index", field.name_index, &[CpTag::Utf8]);
+            self.expect(&location, "descriptor_index", field.descriptor_index, &[CpTag::Utf8]);
+            if let Some(ref name) = name {
+                if !is_unqualified_name(name) {
+                    self.report(&location, format!("invalid field name {:?}", name));
+                }
+            }
+            if let Some(ref descriptor) = descriptor {
+                self.field_descriptor(&location, descriptor);
+            }
+            if !seen.insert((name.clone(), descriptor.clone())) {
+                self.report(&location, "duplicate field");
+            }
+            self.field_flags(&location, field.access_flags);
+
+            self.attributes(&field.attributes, Context::Field, &location, descriptor.as_deref());
+        }
+
+        let mut seen = HashSet::new();
+        for method in &cf.methods.array {
+            let (name, descriptor) = (cp.utf8(method.name_index), cp.utf8(method.descriptor_index));
+            let location = format!("method {}{}", display(&name, method.name_index), display(&descriptor, method.descriptor_index));
+
+            self.expect(&location, "name_index", method.name_index, &[CpTag::Utf8]);
+            self.expect(&location, "descriptor_index", method.descriptor_index, &[CpTag::Utf8]);
+            if let Some(ref name) = name {
+                if !is_method_name(name) {
+                    self.report(&location, format!("invalid method name {:?}", name));
+                }
+            }
+            if let Some(ref descriptor) = descriptor {
+                let is_static = method.access_flags & ACC_STATIC != 0;
+                self.method_descriptor(&location, descriptor, !is_static, name.as_deref());
+            }
+            if !seen.insert((name.clone(), descriptor.clone())) {
+                self.report(&location, "duplicate method");
+            }
+            self.method_flags(&location, method.access_flags, name.as_deref());
+
+            let has_code = method.attributes.find(cp, "Code").is_some();
+            if method.access_flags & (ACC_ABSTRACT | ACC_NATIVE) != 0 {
+                if has_code {
+                    self.report(&location, "abstract and native methods can't have a Code attribute");
+                }
+            } else if !has_code {
+                self.report(&location, "missing Code attribute");
+            }
+
+            self.attributes(&method.attributes, Context::Method, &location, None);
+        }
+
+        self.attributes(&cf.attributes, Context::Class, "class", None);
+    }
+
+    fn this_and_super(&mut self) {
+        let cf = self.cf;
+        let cp = self.cp;
+        let is_module = cf.access_flags & ACC_MODULE != 0;
+
+        self.expect("class", "this_class", cf.this_class, &[CpTag::Class]);
+        let this_class = cp.class_name(cf.this_class).unwrap_or_default();
+        if this_class.starts_with('[') {
+            self.report("class", format!("this_class names the array class {}", this_class));
+        }
+
+        if is_module {
+            if this_class != "module-info" {
+                self.report("class", format!("module-info classes must be named module-info, not {}", this_class));
+            }
+            if cf.super_class != 0 || !cf.interfaces.is_empty() || !cf.fields.array.is_empty() || !cf.methods.array.is_empty() {
+                self.report("class", "module-info classes can't have a superclass, interfaces, fields or methods");
+            }
+            if cf.major_version < 53 {
+                self.report("class", "module-info classes need class files of Java 9 or later");
+            }
+            return;
+        }
+
+        if cf.super_class == 0 {
+            if this_class != OBJECT {
+                self.report("class", "super_class is 0, but only java/lang/Object has no superclass");
+            }
+        } else if self.expect("class", "super_class", cf.super_class, &[CpTag::Class]) {
+            let super_class = cp.class_name(cf.super_class).unwrap_or_default();
+
+            if this_class == OBJECT {
+                self.report("class", "java/lang/Object can't have a superclass");
+            } else if super_class == this_class {
+                self.report("class", "the class is its own superclass");
+            } else if super_class.starts_with('[') {
+                self.report("class", format!("super_class names the array class {}", super_class));
+            } else if cf.access_flags & ACC_INTERFACE != 0 && super_class != OBJECT {
+                self.report("class", format!("the superclass of an interface must be java/lang/Object, not {}", super_class));
+            }
+        }
+
+        let mut seen = HashSet::new();
+        for &interface in &cf.interfaces {
+            if !self.expect("class", "interface", interface, &[CpTag::Class]) {
+                continue;
+            }
+
+            let name = cp.class_name(interface).unwrap_or_default();
+            if name.starts_with('[') {
+                self.report("class", format!("interface {} is an array class", name));
+            } else if !seen.insert(name.clone()) {
+                self.report("class", format!("duplicate interface {}", name));
+            }
+        }
+    }
+
+    // The flags a class may not combine (JVMS 4.1). Those of module-info
+    // classes are checked with its name.
+    fn class_flags(&mut self) {
+        let flags = self.cf.access_flags;
+        if flags & ACC_MODULE != 0 {
+            return;
+        }
+
+        if flags & ACC_INTERFACE != 0 {
+            if flags & ACC_ABSTRACT == 0 {
+                self.report("class", "interfaces must be abstract");
+            }
+            // Older compilers set ACC_SUPER on interfaces, which HotSpot
+            // accepts before Java 5.
+            let forbidden = if self.cf.major_version < 49 { ACC_FINAL } else { ACC_FINAL | ACC_SUPER | ACC_ENUM };
+            self.forbid("class", flags, forbidden, FlagContext::Class, "interfaces");
+        } else {
+            self.forbid("class", flags, ACC_ANNOTATION, FlagContext::Class, "classes other than interfaces");
+            if flags & (ACC_FINAL | ACC_ABSTRACT) == ACC_FINAL | ACC_ABSTRACT {
+                self.report("class", "classes can't be both final and abstract");
+            }
+        }
+    }
+
+    // The flags a field may not combine (JVMS 4.5).
+    fn field_flags(&mut self, location: &str, flags: u16) {
+        if (flags & VISIBILITY).count_ones() > 1 {
+            self.report(location, "fields can only be one of public, private and protected");
+        }
+        if flags & (ACC_FINAL | ACC_VOLATILE) == ACC_FINAL | ACC_VOLATILE {
+            self.report(location, "fields can't be both final and volatile");
+        }
+
+        let constant = ACC_PUBLIC | ACC_STATIC | ACC_FINAL;
+        if self.cf.access_flags & ACC_INTERFACE != 0 && (flags & constant != constant || flags & !(constant | ACC_SYNTHETIC) != 0) {
+            self.report(location, "interface fields must be public, static and final, and nothing else but synthetic");
+        }
+    }
+
+    // The flags a method may not combine (JVMS 4.6). Those of class
+    // initializers are ignored, except that from Java 7 on only static
+    // methods are.
+    fn method_flags(&mut self, location: &str, flags: u16, name: Option<&str>) {
+        if name == Some("<clinit>") {
+            if flags & ACC_STATIC == 0 && self.cf.major_version >= 51 {
+                self.report(location, "<clinit> methods must be static");
+            }
+            return;
+        }
+
+        if (flags & VISIBILITY).count_ones() > 1 {
+            self.report(location, "methods can only be one of public, private and protected");
+        }
+
+        if self.cf.access_flags & ACC_INTERFACE != 0 {
+            if self.cf.major_version < 52 {
+                if flags & (ACC_PUBLIC | ACC_ABSTRACT) != ACC_PUBLIC | ACC_ABSTRACT {
+                    self.report(location, "interface methods must be public and abstract before Java 8");
+                }
+            } else if (flags & (ACC_PUBLIC | ACC_PRIVATE)).count_ones() != 1 {
+                self.report(location, "interface methods must be either public or private");
+            }
+            let forbidden = ACC_PROTECTED | ACC_FINAL | ACC_SYNCHRONIZED | ACC_NATIVE;
+            self.forbid(location, flags, forbidden, FlagContext::Method, "interface methods");
+        }
+
+        if flags & ACC_ABSTRACT != 0 {
+            // strictfp had no effect before Java 1.2 and has none since 17.
+            let strict = if (46..61).contains(&self.cf.major_version) { ACC_STRICT } else { 0 };
+            let forbidden = ACC_PRIVATE | ACC_STATIC | ACC_FINAL | ACC_SYNCHRONIZED | ACC_NATIVE | strict;
+            self.forbid(location, flags, forbidden, FlagContext::Method, "abstract methods");
+        }
+
+        if name == Some("<init>") {
+            let forbidden = ACC_STATIC | ACC_FINAL | ACC_SYNCHRONIZED | ACC_BRIDGE | ACC_NATIVE | ACC_ABSTRACT;
+            self.forbid(location, flags, forbidden, FlagContext::Method, "<init> methods");
+        }
+    }
+
+    // Reports the flags of `forbidden` that are set, as what `subject` can't
+    // be.
+    fn forbid(&mut self, location: &str, flags: u16, forbidden: u16, context: FlagContext, subject: &str) {
+        if flags & forbidden != 0 {
+            let names = format_flags(flags & forbidden, context).join(" or ");
+            self.report(location, format!("{} can't be {}", subject, names));
+        }
+    }
+
+    // Whether the entry at `index` has one of `tags`, reporting it if not.
+    fn expect(&mut self, location: &str, what: &str, index: u16, tags: &[CpTag]) -> bool {
+        let found = match self.cp.tag(index) {
+            Some(tag) if tags.contains(&tag) => return true,
+            Some(tag) => format!("a CONSTANT_{:?}", tag),
+            None => "no constant pool entry".to_string(),
+        };
+
+        let expected: Vec<String> = tags.iter().map(|tag| format!("CONSTANT_{:?}", tag)).collect();
+        self.report(location, format!("{} {} refers to {}, not {}", what, index, found, expected.join(" or ")));
+
+        false
+    }
+
+    // As `expect`, allowing 0 for none.
+    fn expect_optional(&mut self, location: &str, what: &str, index: u16, tags: &[CpTag]) -> bool {
+        index == 0 || self.expect(location, what, index, tags)
+    }
+
+    fn entry(&mut self, index: u16) {
+        let cp = self.cp;
+        let location = format!("constant pool #{}", index);
+        let info = cp.get(index).unwrap().as_any();
+        let tag = cp.tag(index).unwrap();
+
+        let since = match tag {
+            CpTag::MethodHandle | CpTag::MethodType | CpTag::InvokeDynamic => 51,
+            CpTag::Module | CpTag::Package => 53,
+            CpTag::Dynamic => 55,
+            _ => 45,
+        };
+        if self.cf.major_version < since {
+            self.report(&location, format!("CONSTANT_{:?} entries need class files of Java {} or later", tag, release_name(since)));
+        }
+
+        match tag {
+            CpTag::Utf8 => {
+                if !is_modified_utf8(&info.downcast_ref::<Utf8Info>().unwrap().bytes) {
+                    self.report(&location, "invalid modified UTF-8");
+                }
+            }
+            CpTag::Class => {
+                let name_index = info.downcast_ref::<ClassInfo>().unwrap().name_index;
+                if self.expect(&location, "name_index", name_index, &[CpTag::Utf8]) {
+                    let name = cp.utf8(name_index).unwrap_or_default();
+                    let valid = if name.starts_with('[') {
+                        parse_field_descriptor(&name).is_some_and(|field_type| is_valid_field_type(&field_type))
+                    } else {
+                        is_binary_name(&name)
+                    };
+                    if !valid {
+                        self.report(&location, format!("invalid class name {:?}", name));
+                    }
+                }
+            }
+            CpTag::String => {
+                self.expect(&location, "string_index", info.downcast_ref::<StringInfo>().unwrap().string_index, &[CpTag::Utf8]);
+            }
+            CpTag::Fieldref | CpTag::Methodref | CpTag::InterfaceMethodref => self.member_ref(&location, index, tag),
+            CpTag::NameAndType => {
+                let info = info.downcast_ref::<NameAndTypeInfo>().unwrap();
+                self.expect(&location, "name_index", info.name_index, &[CpTag::Utf8]);
+                self.expect(&location, "descriptor_index", info.descriptor_index, &[CpTag::Utf8]);
+            }
+            CpTag::MethodHandle => self.method_handle(&location, info.downcast_ref::<MethodHandleInfo>().unwrap()),
+            CpTag::MethodType => {
+                let descriptor_index = info.downcast_ref::<MethodTypeInfo>().unwrap().descriptor_index;
+                if self.expect(&location, "descriptor_index", descriptor_index, &[CpTag::Utf8]) {
+                    let descriptor = cp.utf8(descriptor_index).unwrap_or_default();
+                    self.method_descriptor(&location, &descriptor, false, None);
+                }
+            }
+            CpTag::Dynamic => {
+                let info = info.downcast_ref::<DynamicInfo>().unwrap();
+                self.dynamic(&location, info.bootstrap_method_attr_index, info.name_and_type_index, false);
+            }
+            CpTag::InvokeDynamic => {
+                let info = info.downcast_ref::<InvokeDynamicInfo>().unwrap();
+                self.dynamic(&location, info.bootstrap_method_attr_index, info.name_and_type_index, true);
+            }
+            CpTag::Module | CpTag::Package => {
+                if self.cf.access_flags & ACC_MODULE == 0 {
+                    self.report(&location, format!("only module-info classes can have CONSTANT_{:?} entries", tag));
+                }
+
+                let name_index = match info.downcast_ref::<ModuleInfo>() {
+                    Some(info) => info.name_index,
+                    None => info.downcast_ref::<PackageInfo>().unwrap().name_index,
+                };
+                self.expect(&location, "name_index", name_index, &[CpTag::Utf8]);
+            }
+            CpTag::Integer | CpTag::Float | CpTag::Long | CpTag::Double => {}
+        }
+    }
+
+    fn member_ref(&mut self, location: &str, index: u16, tag: CpTag) {
+        let cp = self.cp;
+        let (class_index, name_and_type_index) = match cp.get_as::<FieldrefInfo>(index) {
+            Some(info) => (info.class_index, info.name_and_type_index),
+            None => match cp.get_as::<MethodrefInfo>(index) {
+                Some(info) => (info.class_index, info.name_and_type_index),
+                None => {
+                    let info = cp.get_as::<InterfaceMethodrefInfo>(index).unwrap();
+                    (info.class_index, info.name_and_type_index)
+                }
+            },
+        };
+
+        self.expect(location, "class_index", class_index, &[CpTag::Class]);
+        if !self.expect(location, "name_and_type_index", name_and_type_index, &[CpTag::NameAndType]) {
+            return;
+        }
+
+        let (name, descriptor) = match cp.name_and_type(name_and_type_index) {
+            Some(name_and_type) => name_and_type,
+            None => return,
+        };
+
+        if tag == CpTag::Fieldref {
+            if !is_unqualified_name(&name) {
+                self.report(location, format!("invalid field name {:?}", name));
+            }
+            self.field_descriptor(location, &descriptor);
+        } else {
+            // Of the special names, only that of instance initialization
+            // methods may be referred to.
+            if name == "<clinit>" || !is_method_name(&name) {
+                self.report(location, format!("invalid method name {:?}", name));
+            }
+            self.method_descriptor(location, &descriptor, false, Some(&name));
+        }
+    }
+
+    fn method_handle(&mut self, location: &str, info: &MethodHandleInfo) {
+        let interface_methods = self.cf.major_version >= 52;
+        let tags: &[CpTag] = match info.reference_kind {
+            // getField, getStatic, putField and putStatic
+            1..=4 => &[CpTag::Fieldref],
+            // invokeVirtual and newInvokeSpecial
+            5 | 8 => &[CpTag::Methodref],
+            // invokeStatic and invokeSpecial
+            6 | 7 if interface_methods => &[CpTag::Methodref, CpTag::InterfaceMethodref],
+            6 | 7 => &[CpTag::Methodref],
+            // invokeInterface
+            9 => &[CpTag::InterfaceMethodref],
+            kind => {
+                self.report(location, format!("invalid reference_kind {}", kind));
+                return;
+            }
+        };
+
+        if !self.expect(location, "reference_index", info.reference_index, tags) {
+            return;
+        }
+
+        if let Some((_, name, _)) = self.cp.member_ref(info.reference_index) {
+            match info.reference_kind {
+                8 if name != "<init>" => self.report(location, "newInvokeSpecial method handles must refer to <init>"),
+                5..=7 | 9 if name.starts_with('<') => {
+                    self.report(location, format!("method handles of kind {} can't refer to {}", info.reference_kind, name))
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn dynamic(&mut self, location: &str, bootstrap_method: u16, name_and_type_index: u16, call_site: bool) {
+        if bootstrap_method as usize >= self.bootstrap_methods {
+            self.report(location, format!("bootstrap_method_attr_index {} is out of range", bootstrap_method));
+        }
+
+        if !self.expect(location, "name_and_type_index", name_and_type_index, &[CpTag::NameAndType]) {
+            return;
+        }
+
+        if let Some((name, descriptor)) = self.cp.name_and_type(name_and_type_index) {
+            if !is_unqualified_name(&name) {
+                self.report(location, format!("invalid name {:?}", name));
+            }
+
+            if call_site {
+                self.method_descriptor(location, &descriptor, false, None);
+            } else {
+                self.field_descriptor(location, &descriptor);
+            }
+        }
+    }
+
+    fn field_descriptor(&mut self, location: &str, descriptor: &str) {
+        if !parse_field_descriptor(descriptor).is_some_and(|field_type| is_valid_field_type(&field_type)) {
+            self.report(location, format!("invalid field descriptor {:?}", descriptor));
+        }
+    }
+
+    // Checks a method descriptor, with the slots taken by `this` for
+    // instance methods and that of the method named `name`.
+    fn method_descriptor(&mut self, location: &str, descriptor: &str, instance: bool, name: Option<&str>) {
+        let parsed = parse_method_descriptor(descriptor).filter(|parsed| {
+            parsed.parameters.iter().chain(&parsed.return_type).all(is_valid_field_type)
+        });
+
+        let parsed = match parsed {
+            Some(parsed) => parsed,
+            None => {
+                self.report(location, format!("invalid method descriptor {:?}", descriptor));
+                return;
+            }
+        };
+
+        if parsed.parameter_slots() + instance as u16 > MAX_PARAMETER_SLOTS {
+            self.report(location, format!("the parameters take more than {} slots", MAX_PARAMETER_SLOTS));
+        }
+        if name == Some("<init>") && parsed.return_type.is_some() {
+            self.report(location, "<init> methods must return void");
+        }
+    }
+
+    // Checks attributes of a structure, given the descriptor of a field for
+    // its ConstantValue.
+    fn attributes(&mut self, attributes: &Attributes, context: Context, location: &str, descriptor: Option<&str>) {
+        let mut seen = HashSet::new();
+
+        for attribute in &attributes.array {
+            let name = match self.cp.utf8(attribute.attribute_name_index) {
+                Some(name) => name,
+                None => {
+                    self.expect(location, "attribute_name_index", attribute.attribute_name_index, &[CpTag::Utf8]);
+                    continue;
+                }
+            };
+            let location = format!("{}, {} attribute", location, name);
+
+            let (since, contexts) = match ATTRIBUTES.iter().find(|&&(known, _, _)| known == name) {
+                Some(&(_, since, contexts)) => (since, contexts),
+                None => continue,
+            };
+
+            if !contexts.contains(&context) {
+                self.report(&location, format!("{} attributes are not attributes of {}, so are ignored", name, context.name()));
+                continue;
+            }
+            if self.cf.major_version < since {
+                self.report(
+                    &location,
+                    format!("{} attributes need class files of Java {} or later, so are ignored", name, release_name(since)),
+                );
+                continue;
+            }
+            if !seen.insert(name.clone()) && !REPEATABLE.contains(&name.as_str()) {
+                self.report(&location, format!("more than one {} attribute", name));
+            }
+
+            let mut c = Cursor { info: &attribute.info, at: 0 };
+            match self.attribute(&mut c, &name, &location, descriptor) {
+                Err(Truncated) => self.report(
+                    &location,
+                    format!("attribute_length {} is less than its contents need", attribute.info.len()),
+                ),
+                Ok(()) if c.at < c.info.len() => self.report(
+                    &location,
+                    format!("attribute_length {} is {} more than its contents need", attribute.info.len(), c.info.len() - c.at),
+                ),
+                Ok(()) => {}
+            }
+        }
+    }
+
+    fn attribute(&mut self, c: &mut Cursor, name: &str, location: &str, descriptor: Option<&str>) -> Result<(), Truncated> {
+        let class = &[CpTag::Class];
+        let utf8 = &[CpTag::Utf8];
+
+        match name {
+            "ConstantValue" => {
+                let tags: &[CpTag] = match descriptor.unwrap_or_default() {
+                    "J" => &[CpTag::Long],
+                    "F" => &[CpTag::Float],
+                    "D" => &[CpTag::Double],
+                    "I" | "S" | "C" | "B" | "Z" => &[CpTag::Integer],
+                    "Ljava/lang/String;" => &[CpTag::String],
+                    descriptor => {
+                        self.report(location, format!("fields of type {} can't have a constant value", descriptor));
+                        c.at = c.info.len();
+                        return Ok(());
+                    }
+                };
+                self.index(c, location, "constantvalue_index", tags)?;
+            }
+            "Code" => self.code(c, location)?,
+            "StackMapTable" => {
+                for _ in 0..c.u16()? {
+                    match c.u8()? {
+                        0..=63 => {}
+                        64..=127 => self.verification_types(c, location, 1)?,
+                        247 => {
+                            c.skip(2)?;
+                            self.verification_types(c, location, 1)?;
+                        }
+                        248..=251 => c.skip(2)?,
+                        frame_type @ 252..=254 => {
+                            c.skip(2)?;
+                            self.verification_types(c, location, frame_type as u16 - 251)?;
+                        }
+                        255 => {
+                            c.skip(2)?;
+                            let locals = c.u16()?;
+                            self.verification_types(c, location, locals)?;
+                            let stack = c.u16()?;
+                            self.verification_types(c, location, stack)?;
+                        }
+                        frame_type => {
+                            self.report(location, format!("invalid frame type {}", frame_type));
+                            c.at = c.info.len();
+                            return Ok(());
+                        }
+                    }
+                }
+            }
+            "Exceptions" | "NestMembers" | "PermittedSubclasses" => {
+                for _ in 0..c.u16()? {
+                    self.index(c, location, "class", class)?;
+                }
+            }
+            "ModulePackages" => {
+                for _ in 0..c.u16()? {
+                    self.index(c, location, "package", &[CpTag::Package])?;
+                }
+            }
+            "InnerClasses" => {
+                for _ in 0..c.u16()? {
+                    self.index(c, location, "inner_class_info_index", class)?;
+                    self.optional_index(c, location, "outer_class_info_index", class)?;
+                    self.optional_index(c, location, "inner_name_index", utf8)?;
+                    c.skip(2)?;
+                }
+            }
+            "EnclosingMethod" => {
+                self.index(c, location, "class_index", class)?;
+                self.optional_index(c, location, "method_index", &[CpTag::NameAndType])?;
+            }
+            "Synthetic" | "Deprecated" => {}
+            "Signature" | "SourceFile" | "ModuleTarget" => {
+                self.index(c, location, "index", utf8)?;
+            }
+            "NestHost" | "ModuleMainClass" => {
+                self.index(c, location, "class", class)?;
+            }
+            "SourceDebugExtension" => c.at = c.info.len(),
+            "LineNumberTable" => {
+                let count = c.u16()?;
+                c.skip(4 * count as usize)?;
+            }
+            "LocalVariableTable" | "LocalVariableTypeTable" => {
+                for _ in 0..c.u16()? {
+                    c.skip(4)?;
+                    self.index(c, location, "name_index", utf8)?;
+                    let descriptor_index = c.u16()?;
+                    if self.expect(location, "descriptor_index", descriptor_index, utf8) && name == "LocalVariableTable" {
+                        let descriptor = self.cp.utf8(descriptor_index).unwrap_or_default();
+                        self.field_descriptor(location, &descriptor);
+                    }
+                    c.skip(2)?;
+                }
+            }
+            "RuntimeVisibleAnnotations" | "RuntimeInvisibleAnnotations" => self.annotations(c, location)?,
+            "RuntimeVisibleParameterAnnotations" | "RuntimeInvisibleParameterAnnotations" => {
+                for _ in 0..c.u8()? {
+                    self.annotations(c, location)?;
+                }
+            }
+            "RuntimeVisibleTypeAnnotations" | "RuntimeInvisibleTypeAnnotations" => {
+                for _ in 0..c.u16()? {
+                    self.type_annotation(c, location)?;
+                }
+            }
+            "AnnotationDefault" => self.element_value(c, location)?,
+            "BootstrapMethods" => {
+                for _ in 0..c.u16()? {
+                    self.index(c, location, "bootstrap_method_ref", &[CpTag::MethodHandle])?;
+                    for _ in 0..c.u16()? {
+                        self.index(c, location, "bootstrap_argument", LOADABLE)?;
+                    }
+                }
+            }
+            "MethodParameters" => {
+                for _ in 0..c.u8()? {
+                    self.optional_index(c, location, "name_index", utf8)?;
+                    c.skip(2)?;
+                }
+            }
+            "Module" => self.module(c, location)?,
+            "ModuleHashes" => {
+                self.index(c, location, "algorithm_index", utf8)?;
+                for _ in 0..c.u16()? {
+                    self.index(c, location, "module_name_index", &[CpTag::Module])?;
+                    let length = c.u16()?;
+                    c.skip(length as usize)?;
+                }
+            }
+            "ModuleResolution" => c.skip(2)?,
+            "Record" => {
+                for _ in 0..c.u16()? {
+                    let name_index = c.u16()?;
+                    let descriptor_index = c.u16()?;
+                    let component = format!("{}, component {}", location, self.cp.utf8(name_index).unwrap_or_default());
+
+                    self.expect(&component, "name_index", name_index, utf8);
+                    let descriptor = self.cp.utf8(descriptor_index);
+                    if self.expect(&component, "descriptor_index", descriptor_index, utf8) {
+                        self.field_descriptor(&component, descriptor.as_deref().unwrap_or_default());
+                    }
+
+                    let attributes = c.attributes()?;
+                    self.attributes(&attributes, Context::RecordComponent, &component, descriptor.as_deref());
+                }
+            }
+            _ => c.at = c.info.len(),
+        }
+
+        Ok(())
+    }
+
+    // Checks the index at the cursor, returning whether it is of one of
+    // `tags`.
+    fn index(&mut self, c: &mut Cursor, location: &str, what: &str, tags: &[CpTag]) -> Result<bool, Truncated> {
+        let index = c.u16()?;
+
+        Ok(self.expect(location, what, index, tags))
+    }
+
+    fn optional_index(&mut self, c: &mut Cursor, location: &str, what: &str, tags: &[CpTag]) -> Result<bool, Truncated> {
+        let index = c.u16()?;
+
+        Ok(self.expect_optional(location, what, index, tags))
+    }
+
+    fn code(&mut self, c: &mut Cursor, location: &str) -> Result<(), Truncated> {
+        c.skip(4)?;
+        let code_length = (c.u16()? as u32) << 16 | c.u16()? as u32;
+        let code = c.bytes(code_length as usize)?;
+
+        if code_length == 0 || code_length > MAX_CODE_LENGTH {
+            self.report(location, format!("code_length {} is not between 1 and {}", code_length, MAX_CODE_LENGTH));
+        }
+
+        let mut boundaries = HashSet::new();
+        match decode(code) {
+            Ok(instructions) => {
+                for insn in &instructions {
+                    boundaries.insert(insn.offset);
+                    self.instruction(&format!("{}, offset {}", location, insn.offset), insn.opcode, &insn.operand);
+                }
+            }
+            Err(e) => self.report(location, e.to_string()),
+        }
+
+        for _ in 0..c.u16()? {
+            let (start, end, handler) = (c.u16()? as u32, c.u16()? as u32, c.u16()? as u32);
+
+            let on_boundary = |offset: u32| boundaries.is_empty() || boundaries.contains(&offset);
+            let end_valid = end == code_length || on_boundary(end);
+            if start >= end || end > code_length || !on_boundary(start) || !end_valid || !on_boundary(handler) || handler >= code_length {
+                self.report(location, format!("invalid exception handler {}-{} -> {}", start, end, handler));
+            }
+
+            self.optional_index(c, location, "catch_type", &[CpTag::Class])?;
+        }
+
+        let attributes = c.attributes()?;
+        self.attributes(&attributes, Context::Code, location, None);
+
+        Ok(())
+    }
+
+    fn instruction(&mut self, location: &str, opcode: u8, operand: &Operand) {
+        let index = match *operand {
+            Operand::Constant(index) | Operand::InvokeDynamic(index) => index,
+            Operand::InvokeInterface { index, .. } | Operand::MultiANewArray { index, .. } => index,
+            _ => return,
+        };
+
+        let single: &[CpTag] = &[
+            CpTag::Integer,
+            CpTag::Float,
+            CpTag::String,
+            CpTag::Class,
+            CpTag::MethodType,
+            CpTag::MethodHandle,
+            CpTag::Dynamic,
+        ];
+        let interface_methods = self.cf.major_version >= 52;
+
+        let tags: &[CpTag] = match opcode {
+            // ldc and ldc_w
+            0x12 | 0x13 => single,
+            // ldc2_w
+            0x14 => &[CpTag::Long, CpTag::Double, CpTag::Dynamic],
+            // getstatic, putstatic, getfield and putfield
+            0xb2..=0xb5 => &[CpTag::Fieldref],
+            // invokevirtual
+            0xb6 => &[CpTag::Methodref],
+            // invokespecial and invokestatic
+            0xb7 | 0xb8 if interface_methods => &[CpTag::Methodref, CpTag::InterfaceMethodref],
+            0xb7 | 0xb8 => &[CpTag::Methodref],
+            0xb9 => &[CpTag::InterfaceMethodref],
+            0xba => &[CpTag::InvokeDynamic],
+            // new, anewarray, checkcast, instanceof and multianewarray
+            0xbb | 0xbd | 0xc0 | 0xc1 | 0xc5 => &[CpTag::Class],
+            _ => return,
+        };
+
+        if !self.expect(location, "index", index, tags) {
+            return;
+        }
+
+        // Dynamically computed constants of long and double are loaded with
+        // ldc2_w, and others with ldc.
+        if let Some(info) = self.cp.get_as::<DynamicInfo>(index) {
+            let descriptor = self.cp.name_and_type(info.name_and_type_index).map(|(_, descriptor)| descriptor);
+            let wide = descriptor.as_deref().is_some_and(|descriptor| descriptor == "J" || descriptor == "D");
+            if wide != (opcode == 0x14) {
+                self.report(location, "long and double constants are loaded with ldc2_w, and others with ldc and ldc_w");
+            }
+        }
+    }
+
+    fn verification_types(&mut self, c: &mut Cursor, location: &str, count: u16) -> Result<(), Truncated> {
+        for _ in 0..count {
+            match c.u8()? {
+                0..=6 => {}
+                7 => {
+                    self.index(c, location, "cpool_index", &[CpTag::Class])?;
+                }
+                8 => c.skip(2)?,
+                tag => {
+                    self.report(location, format!("invalid verification type tag {}", tag));
+                    c.at = c.info.len();
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn annotations(&mut self, c: &mut Cursor, location: &str) -> Result<(), Truncated> {
+        for _ in 0..c.u16()? {
+            self.annotation(c, location)?;
+        }
+
+        Ok(())
+    }
+
+    fn annotation(&mut self, c: &mut Cursor, location: &str) -> Result<(), Truncated> {
+        self.index(c, location, "type_index", &[CpTag::Utf8])?;
+
+        for _ in 0..c.u16()? {
+            self.index(c, location, "element_name_index", &[CpTag::Utf8])?;
+            self.element_value(c, location)?;
+        }
+
+        Ok(())
+    }
+
+    fn element_value(&mut self, c: &mut Cursor, location: &str) -> Result<(), Truncated> {
+        let tags: &[CpTag] = match c.u8()? {
+            b'B' | b'C' | b'I' | b'S' | b'Z' => &[CpTag::Integer],
+            b'D' => &[CpTag::Double],
+            b'F' => &[CpTag::Float],
+            b'J' => &[CpTag::Long],
+            b's' | b'c' => &[CpTag::Utf8],
+            b'e' => {
+                self.index(c, location, "type_name_index", &[CpTag::Utf8])?;
+                self.index(c, location, "const_name_index", &[CpTag::Utf8])?;
+                return Ok(());
+            }
+            b'@' => return self.annotation(c, location),
+            b'[' => {
+                for _ in 0..c.u16()? {
+                    self.element_value(c, location)?;
+                }
+                return Ok(());
+            }
+            tag => {
+                self.report(location, format!("invalid element value tag {:?}", tag as char));
+                c.at = c.info.len();
+                return Ok(());
+            }
+        };
+
+        self.index(c, location, "const_value_index", tags)?;
+        Ok(())
+    }
+
+    fn type_annotation(&mut self, c: &mut Cursor, location: &str) -> Result<(), Truncated> {
+        // The target_info, by target_type.
+        match c.u8()? {
+            0x00 | 0x01 | 0x16 => c.skip(1)?,
+            0x10 | 0x17 | 0x42..=0x46 => c.skip(2)?,
+            0x11 | 0x12 => c.skip(2)?,
+            0x13..=0x15 => {}
+            0x40 | 0x41 => {
+                let length = c.u16()?;
+                c.skip(6 * length as usize)?;
+            }
+            0x47..=0x4b => c.skip(3)?,
+            target_type => {
+                self.report(location, format!("invalid type annotation target 0x{:02x}", target_type));
+                c.at = c.info.len();
+                return Ok(());
+            }
+        }
+
+        let path_length = c.u8()?;
+        c.skip(2 * path_length as usize)?;
+
+        self.annotation(c, location)
+    }
+
+    fn module(&mut self, c: &mut Cursor, location: &str) -> Result<(), Truncated> {
+        let module = &[CpTag::Module];
+        let utf8 = &[CpTag::Utf8];
+
+        self.index(c, location, "module_name_index", module)?;
+        c.skip(2)?;
+        self.optional_index(c, location, "module_version_index", utf8)?;
+
+        for _ in 0..c.u16()? {
+            self.index(c, location, "requires_index", module)?;
+            c.skip(2)?;
+            self.optional_index(c, location, "requires_version_index", utf8)?;
+        }
+
+        // exports and opens
+        for what in &["exports_index", "opens_index"] {
+            for _ in 0..c.u16()? {
+                self.index(c, location, what, &[CpTag::Package])?;
+                c.skip(2)?;
+                for _ in 0..c.u16()? {
+                    self.index(c, location, "to_index", module)?;
+                }
+            }
+        }
+
+        for _ in 0..c.u16()? {
+            self.index(c, location, "uses_index", &[CpTag::Class])?;
+        }
+
+        for _ in 0..c.u16()? {
+            self.index(c, location, "provides_index", &[CpTag::Class])?;
+            for _ in 0..c.u16()? {
+                self.index(c, location, "provides_with_index", &[CpTag::Class])?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Reads through attribute contents.
+struct Cursor<'b> {
+    info: &'b [u8],
+    at: usize,
+}
+
+impl<'b> Cursor<'b> {
+    fn bytes(&mut self, length: usize) -> Result<&'b [u8], Truncated> {
+        if self.at + length > self.info.len() {
+            return Err(Truncated);
+        }
+
+        self.at += length;
+        Ok(&self.info[self.at - length..self.at])
+    }
+
+    fn skip(&mut self, length: usize) -> Result<(), Truncated> {
+        self.bytes(length).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8, Truncated> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Truncated> {
+        let bytes = self.bytes(2)?;
+
+        Ok((bytes[0] as u16) << 8 | bytes[1] as u16)
+    }
+
+    // A count of attributes and the attributes, of Code attributes and
+    // record components.
+    fn attributes(&mut self) -> Result<Attributes, Truncated> {
+        let mut attributes = Attributes::default();
+
+        for _ in 0..self.u16()? {
+            let attribute_name_index = self.u16()?;
+            let attribute_length = (self.u16()? as u32) << 16 | self.u16()? as u32;
+            let info = self.bytes(attribute_length as usize)?.to_vec();
+
+            attributes.array.push(AttributeInfo { attribute_name_index, attribute_length, info });
+        }
+
+        Ok(attributes)
+    }
+}
+
+fn release_name(major_version: u16) -> String {
+    JavaRelease { major_version, preview: false }.name()
+}
+
+// A name for locations, or the index if it isn't a Utf8 entry.
+fn display(name: &Option<String>, index: u16) -> String {
+    match *name {
+        Some(ref name) => name.clone(),
+        None => format!("#{}", index),
+    }
+}
+
+// Names of fields and methods, and parts of class names (JVMS 4.2.2).
+fn is_unqualified_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains(['.', ';', '[', '/'])
+}
+
+fn is_method_name(name: &str) -> bool {
+    name == "<init>" || name == "<clinit>" || is_unqualified_name(name) && !name.contains(['<', '>'])
+}
+
+// Class names in internal form, such as java/lang/Object (JVMS 4.2.1).
+fn is_binary_name(name: &str) -> bool {
+    name.split('/').all(is_unqualified_name)
+}
+
+fn is_valid_field_type(field_type: &FieldType) -> bool {
+    let mut component = field_type;
+    let mut dimensions = 0;
+
+    while let FieldType::Array(ref inner) = *component {
+        component = inner;
+        dimensions += 1;
+    }
+
+    match *component {
+        FieldType::Object(ref name) => dimensions <= MAX_DIMENSIONS && is_binary_name(name),
+        _ => dimensions <= MAX_DIMENSIONS,
+    }
+}
+
+// The bytes of CONSTANT_Utf8 entries (JVMS 4.4.7): UTF-8 without zero bytes,
+// with the code points of supplementary characters as surrogate pairs.
+fn is_modified_utf8(bytes: &[u8]) -> bool {
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let continuation = |at: usize| bytes.get(at).is_some_and(|&b| b & 0xc0 == 0x80);
+
+        i += match bytes[i] {
+            0x01..=0x7f => 1,
+            0xc0..=0xdf if continuation(i + 1) => 2,
+            0xe0..=0xef if continuation(i + 1) && continuation(i + 2) => 3,
+            _ => return false,
+        };
+    }
+
+    true
+}
@@ -1,18 +1,177 @@
+use std::any::Any;
 use std::fmt;
 
 use serde::ser::{Serialize, Serializer, SerializeSeq};
 use serde::Serialize as SerializeDer;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{ArrayValidation, InstanceType, Schema, SchemaObject, SubschemaValidation};
+use schemars::JsonSchema;
 
 use serialization::*;
 
 
-pub trait CpInfo: fmt::Debug + erased_serde::Serialize {}
+// Send and Sync so that class files can be parsed on worker threads.
+pub trait CpInfo: fmt::Debug + erased_serde::Serialize + Send + Sync {
+    fn tag(&self) -> u8;
+    fn as_any(&self) -> &dyn Any;
+}
 serialize_trait_object!(CpInfo);
 
+macro_rules! impl_cp_info {
+    ($info:ty) => {
+        impl CpInfo for $info {
+            fn tag(&self) -> u8 {
+                self.tag
+            }
+
+            fn as_any(&self) -> &dyn Any {
+                self
+            }
+        }
+    };
+}
+
 
 #[derive(Debug, Default)]
 pub struct ConstantPool {
-    pub array: Vec<Box<CpInfo>>,
+    pub array: Vec<Box<dyn CpInfo>>,
+
+    // Maps constant pool indices to positions in `array`, accounting for the
+    // unusable slot following every Long and Double entry.
+    slots: Vec<Option<usize>>,
+}
+
+impl ConstantPool {
+    pub fn new(array: Vec<Box<dyn CpInfo>>) -> ConstantPool {
+        let mut slots = vec![None];
+
+        for (i, info) in array.iter().enumerate() {
+            slots.push(Some(i));
+
+            match cp_tag_from(info.tag()) {
+                CpTag::Long | CpTag::Double => slots.push(None),
+                _ => {}
+            }
+        }
+
+        ConstantPool { array, slots }
+    }
+
+    /// Value of `constant_pool_count` for this pool.
+    pub fn count(&self) -> u16 {
+        self.slots.len() as u16
+    }
+
+    pub fn get(&self, index: u16) -> Option<&dyn CpInfo> {
+        match self.slots.get(index as usize) {
+            Some(&Some(i)) => Some(self.array[i].as_ref()),
+            _ => None,
+        }
+    }
+
+    pub fn get_as<T: CpInfo + 'static>(&self, index: u16) -> Option<&T> {
+        self.get(index).and_then(|info| info.as_any().downcast_ref::<T>())
+    }
+
+    pub fn tag(&self, index: u16) -> Option<CpTag> {
+        self.get(index).map(|info| cp_tag_from(info.tag()))
+    }
+
+    /// Iterates over `(index, entry)` pairs in pool order.
+    pub fn entries(&self) -> Vec<(u16, &dyn CpInfo)> {
+        let mut entries = Vec::new();
+
+        for index in 1..self.count() {
+            if let Some(info) = self.get(index) {
+                entries.push((index, info));
+            }
+        }
+
+        entries
+    }
+
+    pub fn utf8(&self, index: u16) -> Option<String> {
+        self.get_as::<Utf8Info>(index).map(|info| info.string())
+    }
+
+    pub fn class_name(&self, index: u16) -> Option<String> {
+        self.get_as::<ClassInfo>(index).and_then(|info| self.utf8(info.name_index))
+    }
+
+    pub fn name_and_type(&self, index: u16) -> Option<(String, String)> {
+        let info = self.get_as::<NameAndTypeInfo>(index)?;
+
+        Some((self.utf8(info.name_index)?, self.utf8(info.descriptor_index)?))
+    }
+
+    /// Resolves a Fieldref, Methodref or InterfaceMethodref entry into its
+    /// class name, member name and descriptor.
+    pub fn member_ref(&self, index: u16) -> Option<(String, String, String)> {
+        let info = self.get(index)?.as_any();
+
+        let (class_index, name_and_type_index) =
+            if let Some(info) = info.downcast_ref::<FieldrefInfo>() {
+                (info.class_index, info.name_and_type_index)
+            } else if let Some(info) = info.downcast_ref::<MethodrefInfo>() {
+                (info.class_index, info.name_and_type_index)
+            } else if let Some(info) = info.downcast_ref::<InterfaceMethodrefInfo>() {
+                (info.class_index, info.name_and_type_index)
+            } else {
+                return None;
+            };
+
+        let (name, descriptor) = self.name_and_type(name_and_type_index)?;
+
+        Some((self.class_name(class_index)?, name, descriptor))
+    }
+
+    /// A short javap-style description of an entry, for comments and labels.
+    pub fn describe(&self, index: u16) -> Option<String> {
+        let info = self.get(index)?.as_any();
+
+        let description = match self.tag(index)? {
+            CpTag::Utf8 => self.utf8(index)?,
+            CpTag::Integer => (info.downcast_ref::<IntegerInfo>()?.bytes as i32).to_string(),
+            CpTag::Float => format!("{:?}f", f32::from_bits(info.downcast_ref::<FloatInfo>()?.bytes)),
+            CpTag::Long => {
+                let info = info.downcast_ref::<LongInfo>()?;
+                format!("{}L", ((info.high_bytes as u64) << 32 | info.low_bytes as u64) as i64)
+            }
+            CpTag::Double => {
+                let info = info.downcast_ref::<DoubleInfo>()?;
+                format!("{:?}d", f64::from_bits((info.high_bytes as u64) << 32 | info.low_bytes as u64))
+            }
+            CpTag::Class => self.class_name(index)?,
+            CpTag::String => format!("{:?}", self.utf8(info.downcast_ref::<StringInfo>()?.string_index)?),
+            CpTag::Fieldref | CpTag::Methodref | CpTag::InterfaceMethodref => {
+                let (class, name, descriptor) = self.member_ref(index)?;
+                format!("{}.{}:{}", class, name, descriptor)
+            }
+            CpTag::NameAndType => {
+                let (name, descriptor) = self.name_and_type(index)?;
+                format!("{}:{}", name, descriptor)
+            }
+            CpTag::MethodHandle => {
+                let info = info.downcast_ref::<MethodHandleInfo>()?;
+                format!("REF_{} {}", info.reference_kind, self.describe(info.reference_index)?)
+            }
+            CpTag::MethodType => self.utf8(info.downcast_ref::<MethodTypeInfo>()?.descriptor_index)?,
+            CpTag::Dynamic => {
+                let info = info.downcast_ref::<DynamicInfo>()?;
+                let (name, descriptor) = self.name_and_type(info.name_and_type_index)?;
+                format!("#{}:{}:{}", info.bootstrap_method_attr_index, name, descriptor)
+            }
+            CpTag::InvokeDynamic => {
+                let info = info.downcast_ref::<InvokeDynamicInfo>()?;
+                let (name, descriptor) = self.name_and_type(info.name_and_type_index)?;
+                format!("#{}:{}:{}", info.bootstrap_method_attr_index, name, descriptor)
+            }
+            CpTag::Module => self.utf8(info.downcast_ref::<ModuleInfo>()?.name_index)?,
+            CpTag::Package => self.utf8(info.downcast_ref::<PackageInfo>()?.name_index)?,
+        };
+
+        Some(description)
+    }
 }
 
 impl Serialize for ConstantPool {
@@ -30,197 +189,347 @@ impl Serialize for ConstantPool {
     }
 }
 
+// An array of entries of any of the kinds above.
+impl JsonSchema for ConstantPool {
+    fn schema_name() -> String {
+        String::from("ConstantPool")
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        let entries = vec![
+            gen.subschema_for::<ClassInfo>(),
+            gen.subschema_for::<FieldrefInfo>(),
+            gen.subschema_for::<MethodrefInfo>(),
+            gen.subschema_for::<InterfaceMethodrefInfo>(),
+            gen.subschema_for::<StringInfo>(),
+            gen.subschema_for::<IntegerInfo>(),
+            gen.subschema_for::<FloatInfo>(),
+            gen.subschema_for::<LongInfo>(),
+            gen.subschema_for::<DoubleInfo>(),
+            gen.subschema_for::<NameAndTypeInfo>(),
+            gen.subschema_for::<Utf8Info>(),
+            gen.subschema_for::<MethodHandleInfo>(),
+            gen.subschema_for::<MethodTypeInfo>(),
+            gen.subschema_for::<DynamicInfo>(),
+            gen.subschema_for::<InvokeDynamicInfo>(),
+            gen.subschema_for::<ModuleInfo>(),
+            gen.subschema_for::<PackageInfo>(),
+        ];
+
+        SchemaObject {
+            instance_type: Some(InstanceType::Array.into()),
+            array: Some(Box::new(ArrayValidation {
+                items: Some(
+                    Schema::Object(SchemaObject {
+                        subschemas: Some(Box::new(SubschemaValidation {
+                            any_of: Some(entries),
+                            ..Default::default()
+                        })),
+                        ..Default::default()
+                    })
+                    .into(),
+                ),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
 
-#[derive(Debug, SerializeDer)]
+#[derive(Debug, SerializeDer, JsonSchema)]
 pub struct ClassInfo {
     #[serde(serialize_with = "serialize_u8_hex")]
+    #[schemars(schema_with = "u8_hex_schema")]
     pub tag: u8,
     pub name_index: u16,
 }
 
-impl CpInfo for ClassInfo {}
+impl_cp_info!(ClassInfo);
 
 
-#[derive(Debug, SerializeDer)]
+#[derive(Debug, SerializeDer, JsonSchema)]
 pub struct FieldrefInfo {
     #[serde(serialize_with = "serialize_u8_hex")]
+    #[schemars(schema_with = "u8_hex_schema")]
     pub tag: u8,
     pub class_index: u16,
     pub name_and_type_index: u16,
 }
 
-impl CpInfo for FieldrefInfo {}
+impl_cp_info!(FieldrefInfo);
 
 
-#[derive(Debug, SerializeDer)]
+#[derive(Debug, SerializeDer, JsonSchema)]
 pub struct MethodrefInfo {
     #[serde(serialize_with = "serialize_u8_hex")]
+    #[schemars(schema_with = "u8_hex_schema")]
     pub tag: u8,
     pub class_index: u16,
     pub name_and_type_index: u16,
 }
 
-impl CpInfo for MethodrefInfo {}
+impl_cp_info!(MethodrefInfo);
 
 
-#[derive(Debug, SerializeDer)]
+#[derive(Debug, SerializeDer, JsonSchema)]
 pub struct InterfaceMethodrefInfo {
     #[serde(serialize_with = "serialize_u8_hex")]
+    #[schemars(schema_with = "u8_hex_schema")]
     pub tag: u8,
     pub class_index: u16,
     pub name_and_type_index: u16,
 }
 
-impl CpInfo for InterfaceMethodrefInfo {}
+impl_cp_info!(InterfaceMethodrefInfo);
 
 
-#[derive(Debug, SerializeDer)]
+#[derive(Debug, SerializeDer, JsonSchema)]
 pub struct StringInfo {
     #[serde(serialize_with = "serialize_u8_hex")]
+    #[schemars(schema_with = "u8_hex_schema")]
     pub tag: u8,
     pub string_index: u16,
 }
 
-impl CpInfo for StringInfo {}
+impl_cp_info!(StringInfo);
 
 
-#[derive(Debug, SerializeDer)]
+#[derive(Debug, SerializeDer, JsonSchema)]
 pub struct IntegerInfo {
     #[serde(serialize_with = "serialize_u8_hex")]
+    #[schemars(schema_with = "u8_hex_schema")]
     pub tag: u8,
     pub bytes: u32,
 }
 
-impl CpInfo for IntegerInfo {}
+impl_cp_info!(IntegerInfo);
 
 
-#[derive(Debug, SerializeDer)]
+#[derive(Debug, SerializeDer, JsonSchema)]
 pub struct FloatInfo {
     #[serde(serialize_with = "serialize_u8_hex")]
+    #[schemars(schema_with = "u8_hex_schema")]
     pub tag: u8,
     pub bytes: u32,
 }
 
-impl CpInfo for FloatInfo {}
+impl_cp_info!(FloatInfo);
 
 
-#[derive(Debug, SerializeDer)]
+#[derive(Debug, SerializeDer, JsonSchema)]
 pub struct LongInfo {
     #[serde(serialize_with = "serialize_u8_hex")]
+    #[schemars(schema_with = "u8_hex_schema")]
     pub tag: u8,
 
     #[serde(serialize_with = "serialize_u32_hex")]
+    #[schemars(schema_with = "u32_hex_schema")]
     pub high_bytes: u32,
     #[serde(serialize_with = "serialize_u32_hex")]
+    #[schemars(schema_with = "u32_hex_schema")]
     pub low_bytes: u32,
 }
 
-impl CpInfo for LongInfo {}
+impl_cp_info!(LongInfo);
 
 
-#[derive(Debug, SerializeDer)]
+#[derive(Debug, SerializeDer, JsonSchema)]
 pub struct DoubleInfo {
     #[serde(serialize_with = "serialize_u8_hex")]
+    #[schemars(schema_with = "u8_hex_schema")]
     pub tag: u8,
 
     #[serde(serialize_with = "serialize_u32_hex")]
+    #[schemars(schema_with = "u32_hex_schema")]
     pub high_bytes: u32,
     #[serde(serialize_with = "serialize_u32_hex")]
+    #[schemars(schema_with = "u32_hex_schema")]
     pub low_bytes: u32,
 }
 
-impl CpInfo for DoubleInfo {}
+impl_cp_info!(DoubleInfo);
 
 
-#[derive(Debug, SerializeDer)]
+#[derive(Debug, SerializeDer, JsonSchema)]
 pub struct NameAndTypeInfo {
     #[serde(serialize_with = "serialize_u8_hex")]
+    #[schemars(schema_with = "u8_hex_schema")]
     pub tag: u8,
     pub name_index: u16,
     pub descriptor_index: u16,
 }
 
-impl CpInfo for NameAndTypeInfo {}
+impl_cp_info!(NameAndTypeInfo);
 
 
-#[derive(SerializeDer)]
+#[derive(SerializeDer, JsonSchema)]
 pub struct Utf8Info {
     #[serde(serialize_with = "serialize_u8_hex")]
+    #[schemars(schema_with = "u8_hex_schema")]
     pub tag: u8,
     pub length: u16,
     #[serde(serialize_with = "serialize_utf8info_bytes")]
+    #[schemars(with = "String")]
     pub bytes: Vec<u8>,
 }
 
-impl CpInfo for Utf8Info {}
+impl_cp_info!(Utf8Info);
+
+impl Utf8Info {
+    pub fn string(&self) -> String {
+        decode_modified_utf8(&self.bytes)
+    }
+
+    pub fn units(&self) -> Vec<u16> {
+        decode_modified_utf16(&self.bytes)
+    }
+}
 
 impl fmt::Debug for Utf8Info {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("Utf8Info")
             .field("tag", &self.tag)
             .field("length", &self.length)
-            .field("bytes", &String::from_utf8((&self.bytes).clone()).unwrap())
+            .field("bytes", &self.string())
             .finish()
     }
 }
 
+pub fn encode_modified_utf8(s: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(s.len());
+
+    for unit in s.encode_utf16() {
+        match unit {
+            0x0001..=0x007f => bytes.push(unit as u8),
+            0x0000 | 0x0080..=0x07ff => {
+                bytes.push(0xc0 | (unit >> 6) as u8);
+                bytes.push(0x80 | (unit & 0x3f) as u8);
+            }
+            _ => {
+                bytes.push(0xe0 | (unit >> 12) as u8);
+                bytes.push(0x80 | ((unit >> 6) & 0x3f) as u8);
+                bytes.push(0x80 | (unit & 0x3f) as u8);
+            }
+        }
+    }
 
-#[derive(Debug, SerializeDer)]
+    bytes
+}
+
+/// Decodes the "modified UTF-8" used by the class file format (JVMS 4.4.7),
+/// in which NUL is encoded in two bytes and supplementary characters as
+/// surrogate pairs.
+pub fn decode_modified_utf8(bytes: &[u8]) -> String {
+    String::from_utf16_lossy(&decode_modified_utf16(bytes))
+}
+
+/// Decodes modified UTF-8 into UTF-16 code units, keeping unpaired
+/// surrogates, which Java strings may hold but Rust strings can't.
+pub fn decode_modified_utf16(bytes: &[u8]) -> Vec<u16> {
+    let mut units: Vec<u16> = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let x = bytes[i] as u16;
+
+        if x & 0x80 == 0 {
+            units.push(x);
+            i += 1;
+        } else if x & 0xe0 == 0xc0 && i + 1 < bytes.len() {
+            let y = bytes[i + 1] as u16;
+            units.push((x & 0x1f) << 6 | (y & 0x3f));
+            i += 2;
+        } else if x & 0xf0 == 0xe0 && i + 2 < bytes.len() {
+            let y = bytes[i + 1] as u16;
+            let z = bytes[i + 2] as u16;
+            units.push((x & 0x0f) << 12 | (y & 0x3f) << 6 | (z & 0x3f));
+            i += 3;
+        } else {
+            units.push(0xfffd);
+            i += 1;
+        }
+    }
+
+    units
+}
+
+
+#[derive(Debug, SerializeDer, JsonSchema)]
 pub struct MethodHandleInfo {
     #[serde(serialize_with = "serialize_u8_hex")]
+    #[schemars(schema_with = "u8_hex_schema")]
     pub tag: u8,
 
     #[serde(serialize_with = "serialize_u8_hex")]
+    #[schemars(schema_with = "u8_hex_schema")]
     pub reference_kind: u8,
 
     pub reference_index: u16,
 }
 
-impl CpInfo for MethodHandleInfo {}
+impl_cp_info!(MethodHandleInfo);
 
 
-#[derive(Debug, SerializeDer)]
+#[derive(Debug, SerializeDer, JsonSchema)]
 pub struct MethodTypeInfo {
     #[serde(serialize_with = "serialize_u8_hex")]
+    #[schemars(schema_with = "u8_hex_schema")]
     pub tag: u8,
     pub descriptor_index: u16,
 }
 
-impl CpInfo for MethodTypeInfo {}
+impl_cp_info!(MethodTypeInfo);
 
 
-#[derive(Debug, SerializeDer)]
+#[derive(Debug, SerializeDer, JsonSchema)]
 pub struct InvokeDynamicInfo {
     #[serde(serialize_with = "serialize_u8_hex")]
+    #[schemars(schema_with = "u8_hex_schema")]
+    pub tag: u8,
+    pub bootstrap_method_attr_index: u16,
+    pub name_and_type_index: u16,
+}
+
+impl_cp_info!(InvokeDynamicInfo);
+
+
+#[derive(Debug, SerializeDer, JsonSchema)]
+pub struct DynamicInfo {
+    #[serde(serialize_with = "serialize_u8_hex")]
+    #[schemars(schema_with = "u8_hex_schema")]
     pub tag: u8,
     pub bootstrap_method_attr_index: u16,
     pub name_and_type_index: u16,
 }
 
-impl CpInfo for InvokeDynamicInfo {}
+impl_cp_info!(DynamicInfo);
 
 
-#[derive(Debug, SerializeDer)]
+#[derive(Debug, SerializeDer, JsonSchema)]
 pub struct ModuleInfo {
     #[serde(serialize_with = "serialize_u8_hex")]
+    #[schemars(schema_with = "u8_hex_schema")]
     pub tag: u8,
     pub name_index: u16,
 }
 
-impl CpInfo for ModuleInfo {}
+impl_cp_info!(ModuleInfo);
 
 
-#[derive(Debug, SerializeDer)]
+#[derive(Debug, SerializeDer, JsonSchema)]
 pub struct PackageInfo {
     #[serde(serialize_with = "serialize_u8_hex")]
+    #[schemars(schema_with = "u8_hex_schema")]
     pub tag: u8,
     pub name_index: u16,
 }
 
-impl CpInfo for PackageInfo {}
+impl_cp_info!(PackageInfo);
 
 
-#[derive(Debug, SerializeDer)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, SerializeDer)]
 pub enum CpTag {
     Class,
     Fieldref,
@@ -235,13 +544,21 @@ pub enum CpTag {
     Utf8,
     MethodHandle,
     MethodType,
+    Dynamic,
     InvokeDynamic,
     Module,
     Package,
 }
 
 pub fn cp_tag_from(tag: u8) -> CpTag {
-    match tag {
+    match try_cp_tag_from(tag) {
+        Some(tag) => tag,
+        None => panic!("Unknown u8 cptag {:X}", tag),
+    }
+}
+
+pub fn try_cp_tag_from(tag: u8) -> Option<CpTag> {
+    let tag = match tag {
         7 => CpTag::Class,
         9 => CpTag::Fieldref,
         10 => CpTag::Methodref,
@@ -255,9 +572,12 @@ pub fn cp_tag_from(tag: u8) -> CpTag {
         1 => CpTag::Utf8,
         15 => CpTag::MethodHandle,
         16 => CpTag::MethodType,
+        17 => CpTag::Dynamic,
         18 => CpTag::InvokeDynamic,
         19 => CpTag::Module,
         20 => CpTag::Package,
-        _ => panic!("Unknown u8 cptag {:X}", tag),
-    }
+        _ => return None,
+    };
+
+    Some(tag)
 }
@@ -0,0 +1,171 @@
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FieldType {
+    Byte,
+    Char,
+    Double,
+    Float,
+    Int,
+    Long,
+    Short,
+    Boolean,
+    Object(String),
+    Array(Box<FieldType>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MethodDescriptor {
+    pub parameters: Vec<FieldType>,
+
+    // None for void methods.
+    pub return_type: Option<FieldType>,
+}
+
+impl FieldType {
+    /// Number of local variable or operand stack slots taken by a value of
+    /// this type.
+    pub fn slots(&self) -> u16 {
+        match *self {
+            FieldType::Long | FieldType::Double => 2,
+            _ => 1,
+        }
+    }
+
+    pub fn is_primitive(&self) -> bool {
+        !matches!(*self, FieldType::Object(_) | FieldType::Array(_))
+    }
+
+    pub fn to_descriptor(&self) -> String {
+        match *self {
+            FieldType::Byte => String::from("B"),
+            FieldType::Char => String::from("C"),
+            FieldType::Double => String::from("D"),
+            FieldType::Float => String::from("F"),
+            FieldType::Int => String::from("I"),
+            FieldType::Long => String::from("J"),
+            FieldType::Short => String::from("S"),
+            FieldType::Boolean => String::from("Z"),
+            FieldType::Object(ref name) => format!("L{};", name),
+            FieldType::Array(ref component) => format!("[{}", component.to_descriptor()),
+        }
+    }
+
+    /// Name of a primitive type as written in Java source.
+    pub fn primitive_name(&self) -> Option<&'static str> {
+        match *self {
+            FieldType::Byte => Some("byte"),
+            FieldType::Char => Some("char"),
+            FieldType::Double => Some("double"),
+            FieldType::Float => Some("float"),
+            FieldType::Int => Some("int"),
+            FieldType::Long => Some("long"),
+            FieldType::Short => Some("short"),
+            FieldType::Boolean => Some("boolean"),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for FieldType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FieldType::Object(ref name) => write!(f, "{}", name.replace('/', ".")),
+            FieldType::Array(ref component) => write!(f, "{}[]", component),
+            _ => write!(f, "{}", self.primitive_name().unwrap()),
+        }
+    }
+}
+
+impl MethodDescriptor {
+    /// Number of local variable slots taken by the parameters, not counting
+    /// `this`.
+    pub fn parameter_slots(&self) -> u16 {
+        self.parameters.iter().map(|p| p.slots()).sum()
+    }
+
+    pub fn to_descriptor(&self) -> String {
+        let mut s = String::from("(");
+
+        for parameter in &self.parameters {
+            s.push_str(&parameter.to_descriptor());
+        }
+
+        s.push(')');
+
+        match self.return_type {
+            Some(ref t) => s.push_str(&t.to_descriptor()),
+            None => s.push('V'),
+        }
+
+        s
+    }
+}
+
+pub fn parse_field_descriptor(descriptor: &str) -> Option<FieldType> {
+    let (field_type, rest) = read_field_type(descriptor)?;
+
+    if rest.is_empty() {
+        Some(field_type)
+    } else {
+        None
+    }
+}
+
+pub fn parse_method_descriptor(descriptor: &str) -> Option<MethodDescriptor> {
+    if !descriptor.starts_with('(') {
+        return None;
+    }
+
+    let mut rest = &descriptor[1..];
+    let mut parameters = Vec::new();
+
+    while !rest.starts_with(')') {
+        let (parameter, r) = read_field_type(rest)?;
+        parameters.push(parameter);
+        rest = r;
+    }
+
+    rest = &rest[1..];
+
+    let return_type = if rest == "V" {
+        None
+    } else {
+        Some(parse_field_descriptor(rest)?)
+    };
+
+    Some(MethodDescriptor {
+        parameters,
+        return_type,
+    })
+}
+
+fn read_field_type(s: &str) -> Option<(FieldType, &str)> {
+    let first = s.chars().next()?;
+    let rest = &s[first.len_utf8()..];
+
+    let field_type = match first {
+        'B' => FieldType::Byte,
+        'C' => FieldType::Char,
+        'D' => FieldType::Double,
+        'F' => FieldType::Float,
+        'I' => FieldType::Int,
+        'J' => FieldType::Long,
+        'S' => FieldType::Short,
+        'Z' => FieldType::Boolean,
+        'L' => {
+            let end = rest.find(';')?;
+            if end == 0 {
+                return None;
+            }
+            return Some((FieldType::Object(String::from(&rest[..end])), &rest[end + 1..]));
+        }
+        '[' => {
+            let (component, rest) = read_field_type(rest)?;
+            return Some((FieldType::Array(Box::new(component)), rest));
+        }
+        _ => return None,
+    };
+
+    Some((field_type, rest))
+}
@@ -1,3 +1,4 @@
+use schemars::JsonSchema;
 use serde::Serialize;
 
 use class_file::Array;
@@ -6,9 +7,10 @@ use serialization::*;
 
 pub type Fields = Array<FieldInfo>;
 
-#[derive(Debug, Default, Serialize)]
+#[derive(Debug, Default, Serialize, JsonSchema)]
 pub struct FieldInfo {
     #[serde(serialize_with = "serialize_u16_hex")]
+    #[schemars(schema_with = "u16_hex_schema")]
     pub access_flags: u16,
 
     pub name_index: u16,
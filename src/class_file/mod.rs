@@ -1,5 +1,11 @@
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
 use serde::ser::{Serialize, Serializer, SerializeSeq};
 
+use assembly::disassemble;
+use java_stub;
+use schema::Document;
 use serialization::*;
 
 use self::attributes::*;
@@ -11,22 +17,36 @@ pub mod constant_pool;
 pub mod fields;
 pub mod methods;
 pub mod attributes;
-
-#[derive(Debug, Default, serde::Serialize)]
+pub mod access_flags;
+pub mod annotations;
+pub mod descriptor;
+pub mod signature;
+pub mod stack_map_table;
+pub mod reader;
+pub mod writer;
+pub mod canonical;
+pub mod check;
+pub mod version;
+
+#[derive(Debug, Default, serde::Serialize, JsonSchema)]
 pub struct ClassFile {
     #[serde(serialize_with = "serialize_u32_hex")]
+    #[schemars(schema_with = "u32_hex_schema")]
     pub magic: u32,
 
     #[serde(serialize_with = "serialize_u16_hex")]
+    #[schemars(schema_with = "u16_hex_schema")]
     pub minor_version: u16,
 
     #[serde(serialize_with = "serialize_u16_hex")]
+    #[schemars(schema_with = "u16_hex_schema")]
     pub major_version: u16,
 
     pub constant_pool_count: u16,
     pub constant_pool: ConstantPool,
 
     #[serde(serialize_with = "serialize_u16_hex")]
+    #[schemars(schema_with = "u16_hex_schema")]
     pub access_flags: u16,
 
     pub this_class: u16,
@@ -46,12 +66,76 @@ pub struct ClassFile {
 }
 
 impl ClassFile {
+    /// The class as a JSON document of the current format version.
     pub fn to_json(&self) -> String {
-        serde_json::to_string(&self).unwrap()
+        serde_json::to_string(&Document::new(self)).unwrap()
     }
 
     pub fn to_pretty_json(&self) -> String {
-        serde_json::to_string_pretty(&self).unwrap()
+        serde_json::to_string_pretty(&Document::new(self)).unwrap()
+    }
+
+    pub fn to_java_stub(&self) -> Result<String, String> {
+        java_stub::generate(self)
+    }
+
+    pub fn to_assembly(&self) -> String {
+        disassemble::disassemble(self)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        writer::write_classfile(self)
+    }
+
+    pub fn to_bytes_with(&self, options: &writer::WriteOptions) -> Result<Vec<u8>, String> {
+        writer::write_classfile_with(self, options)
+    }
+
+    /// The class with its constant pool renumbered in order of use and its
+    /// members and attributes sorted, as `canonical` describes.
+    pub fn to_canonical(&self) -> Result<ClassFile, String> {
+        canonical::canonicalize(self)
+    }
+
+    pub fn name(&self) -> Option<String> {
+        self.constant_pool.class_name(self.this_class)
+    }
+
+    /// The Java release of the class file's version, if it is a valid one.
+    pub fn java_release(&self) -> Option<version::JavaRelease> {
+        version::JavaRelease::of(self.major_version, self.minor_version)
+    }
+
+    /// Methods matching a selector of the form `[Class.]name[descriptor]`,
+    /// where `Class` is either the simple or the fully qualified class name.
+    pub fn select_methods(&self, selector: &str) -> Vec<&MethodInfo> {
+        let (path, descriptor) = match selector.find('(') {
+            Some(i) => (&selector[..i], Some(&selector[i..])),
+            None => (selector, None),
+        };
+
+        let (class, name) = match path.rfind('.') {
+            Some(i) => (Some(&path[..i]), &path[i + 1..]),
+            None => (None, path),
+        };
+
+        if let Some(class) = class {
+            let this_name = self.name().unwrap_or_default().replace('/', ".");
+            let simple_name = this_name.rsplit(['.', '$']).next().unwrap_or("");
+
+            if class != this_name && class != simple_name {
+                return Vec::new();
+            }
+        }
+
+        let cp = &self.constant_pool;
+
+        self.methods
+            .array
+            .iter()
+            .filter(|m| cp.utf8(m.name_index).is_some_and(|n| n == name))
+            .filter(|m| descriptor.is_none() || cp.utf8(m.descriptor_index).as_deref() == descriptor)
+            .collect()
     }
 }
 
@@ -72,3 +156,13 @@ impl<T: Serialize> Serialize for Array<T> {
         seq.end()
     }
 }
+
+impl<T: Serialize + JsonSchema> JsonSchema for Array<T> {
+    fn schema_name() -> String {
+        Vec::<T>::schema_name()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        Vec::<T>::json_schema(gen)
+    }
+}
@@ -0,0 +1,258 @@
+use std::fmt;
+use std::io;
+use std::io::Read;
+
+use class_file::ClassFile;
+use class_file::attributes::*;
+use class_file::constant_pool::*;
+use class_file::fields::*;
+use class_file::methods::*;
+use read_util::*;
+
+pub const MAGIC: u32 = 0xcafe_babe;
+
+/// Why a class file could not be read.
+#[derive(Debug)]
+pub enum ReadError {
+    Io(io::Error),
+    Format(String),
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReadError::Io(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => write!(f, "truncated class file"),
+            ReadError::Io(ref e) => write!(f, "{}", e),
+            ReadError::Format(ref message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl From<io::Error> for ReadError {
+    fn from(e: io::Error) -> ReadError {
+        ReadError::Io(e)
+    }
+}
+
+pub type Result<T> = ::std::result::Result<T, ReadError>;
+
+pub fn read_classfile(f: &mut dyn Read) -> Result<ClassFile> {
+    let mut cf: ClassFile = ClassFile {
+        ..Default::default()
+    };
+
+    cf.magic = try_read_u32(f)?;
+    if cf.magic != MAGIC {
+        return Err(ReadError::Format(format!("bad magic number {:08x}", cf.magic)));
+    }
+
+    cf.minor_version = try_read_u16(f)?;
+    cf.major_version = try_read_u16(f)?;
+    cf.constant_pool_count = try_read_u16(f)?;
+    cf.constant_pool = read_constant_pool(f, cf.constant_pool_count)?;
+    cf.access_flags = try_read_u16(f)?;
+    cf.this_class = try_read_u16(f)?;
+    cf.super_class = try_read_u16(f)?;
+    cf.interfaces_count = try_read_u16(f)?;
+    cf.interfaces = try_read_vec_u16(f, cf.interfaces_count as usize)?;
+    cf.fields_count = try_read_u16(f)?;
+    cf.fields = read_fields(f, cf.fields_count)?;
+    cf.methods_count = try_read_u16(f)?;
+    cf.methods = read_methods(f, cf.methods_count)?;
+    cf.attributes_count = try_read_u16(f)?;
+    cf.attributes = try_read_attributes(f, cf.attributes_count)?;
+
+    Ok(cf)
+}
+
+pub fn read_constant_pool(f: &mut dyn Read, constant_pool_count: u16) -> Result<ConstantPool> {
+    let mut constant_pool: Vec<Box<dyn CpInfo>> = Vec::new();
+    let mut constant_pool_remaining = constant_pool_count.saturating_sub(1);
+
+    while constant_pool_remaining > 0 {
+        let tag_u8 = try_read_u8(f)?;
+        let tag = match try_cp_tag_from(tag_u8) {
+            Some(tag) => tag,
+            None => return Err(ReadError::Format(format!("unknown constant pool tag {}", tag_u8))),
+        };
+
+        let cp_info: Box<dyn CpInfo> = match tag {
+            CpTag::Class => Box::new(ClassInfo {
+                tag: tag_u8,
+                name_index: try_read_u16(f)?,
+            }),
+
+            CpTag::Fieldref => Box::new(FieldrefInfo {
+                tag: tag_u8,
+                class_index: try_read_u16(f)?,
+                name_and_type_index: try_read_u16(f)?,
+            }),
+
+            CpTag::Methodref => Box::new(MethodrefInfo {
+                tag: tag_u8,
+                class_index: try_read_u16(f)?,
+                name_and_type_index: try_read_u16(f)?,
+            }),
+
+            CpTag::InterfaceMethodref => Box::new(InterfaceMethodrefInfo {
+                tag: tag_u8,
+                class_index: try_read_u16(f)?,
+                name_and_type_index: try_read_u16(f)?,
+            }),
+
+            CpTag::String => Box::new(StringInfo {
+                tag: tag_u8,
+                string_index: try_read_u16(f)?,
+            }),
+
+            CpTag::Integer => Box::new(IntegerInfo {
+                tag: tag_u8,
+                bytes: try_read_u32(f)?,
+            }),
+
+            CpTag::Float => Box::new(FloatInfo {
+                tag: tag_u8,
+                bytes: try_read_u32(f)?,
+            }),
+
+            CpTag::Long => Box::new(LongInfo {
+                tag: tag_u8,
+                high_bytes: try_read_u32(f)?,
+                low_bytes: try_read_u32(f)?,
+            }),
+
+            CpTag::Double => Box::new(DoubleInfo {
+                tag: tag_u8,
+                high_bytes: try_read_u32(f)?,
+                low_bytes: try_read_u32(f)?,
+            }),
+
+            CpTag::NameAndType => Box::new(NameAndTypeInfo {
+                tag: tag_u8,
+                name_index: try_read_u16(f)?,
+                descriptor_index: try_read_u16(f)?,
+            }),
+
+            CpTag::Utf8 => {
+                let length = try_read_u16(f)?;
+                Box::new(Utf8Info {
+                    tag: tag_u8,
+                    length,
+                    bytes: try_read_vec_u8(f, length as usize)?,
+                })
+            }
+
+            CpTag::MethodHandle => Box::new(MethodHandleInfo {
+                tag: tag_u8,
+                reference_kind: try_read_u8(f)?,
+                reference_index: try_read_u16(f)?,
+            }),
+
+            CpTag::MethodType => Box::new(MethodTypeInfo {
+                tag: tag_u8,
+                descriptor_index: try_read_u16(f)?,
+            }),
+
+            CpTag::Dynamic => Box::new(DynamicInfo {
+                tag: tag_u8,
+                bootstrap_method_attr_index: try_read_u16(f)?,
+                name_and_type_index: try_read_u16(f)?,
+            }),
+
+            CpTag::InvokeDynamic => Box::new(InvokeDynamicInfo {
+                tag: tag_u8,
+                bootstrap_method_attr_index: try_read_u16(f)?,
+                name_and_type_index: try_read_u16(f)?,
+            }),
+
+            CpTag::Module => Box::new(ModuleInfo {
+                tag: tag_u8,
+                name_index: try_read_u16(f)?,
+            }),
+
+            CpTag::Package => Box::new(PackageInfo {
+                tag: tag_u8,
+                name_index: try_read_u16(f)?,
+            }),
+        };
+
+        let slots = match tag {
+            CpTag::Long | CpTag::Double => 2,
+            _ => 1,
+        };
+
+        if slots > constant_pool_remaining {
+            return Err(ReadError::Format(String::from("8-byte constant in the last constant pool slot")));
+        }
+        constant_pool_remaining -= slots;
+
+        constant_pool.push(cp_info);
+    }
+
+    Ok(ConstantPool::new(constant_pool))
+}
+
+pub fn read_fields(f: &mut dyn Read, fields_count: u16) -> Result<Fields> {
+    let mut fields = Vec::new();
+
+    for _ in 0..fields_count {
+        let access_flags = try_read_u16(f)?;
+        let name_index = try_read_u16(f)?;
+        let descriptor_index = try_read_u16(f)?;
+        let attributes_count = try_read_u16(f)?;
+
+        fields.push(FieldInfo {
+            access_flags,
+            name_index,
+            descriptor_index,
+            attributes_count,
+            attributes: try_read_attributes(f, attributes_count)?,
+        });
+    }
+
+    Ok(Fields { array: fields })
+}
+
+pub fn read_methods(f: &mut dyn Read, methods_count: u16) -> Result<Methods> {
+    let mut methods = Vec::new();
+
+    for _ in 0..methods_count {
+        let access_flags = try_read_u16(f)?;
+        let name_index = try_read_u16(f)?;
+        let descriptor_index = try_read_u16(f)?;
+        let attributes_count = try_read_u16(f)?;
+
+        methods.push(MethodInfo {
+            access_flags,
+            name_index,
+            descriptor_index,
+            attributes_count,
+            attributes: try_read_attributes(f, attributes_count)?,
+        });
+    }
+
+    Ok(Methods { array: methods })
+}
+
+pub fn try_read_attributes(f: &mut dyn Read, attributes_count: u16) -> Result<Attributes> {
+    let mut attributes = Vec::new();
+
+    for _ in 0..attributes_count {
+        let attribute_name_index = try_read_u16(f)?;
+        let attribute_length = try_read_u32(f)?;
+
+        attributes.push(AttributeInfo {
+            attribute_name_index,
+            attribute_length,
+            info: try_read_vec_u8(f, attribute_length as usize)?,
+        });
+    }
+
+    Ok(Attributes { array: attributes })
+}
+
+// For attributes nested in others, such as those of a Code attribute, which
+// are truncated when they run past the end of the enclosing attribute.
+pub fn read_attributes(f: &mut dyn Read, attributes_count: u16) -> ::std::result::Result<Attributes, String> {
+    try_read_attributes(f, attributes_count).map_err(|_| String::from("truncated"))
+}
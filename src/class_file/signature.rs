@@ -0,0 +1,302 @@
+// Generic signatures as stored in the Signature attribute (JVMS 4.7.9.1).
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeSignature {
+    // One of the descriptor base type characters, or 'V' for void results.
+    Base(char),
+    Class(ClassTypeSignature),
+    TypeVariable(String),
+    Array(Box<TypeSignature>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassTypeSignature {
+    // Package prefix in internal form, including the trailing '/'.
+    pub package: String,
+    pub segments: Vec<SimpleClassTypeSignature>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimpleClassTypeSignature {
+    pub name: String,
+    pub type_arguments: Vec<TypeArgument>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeArgument {
+    Any,
+    Extends(TypeSignature),
+    Super(TypeSignature),
+    Exact(TypeSignature),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeParameter {
+    pub name: String,
+    pub class_bound: Option<TypeSignature>,
+    pub interface_bounds: Vec<TypeSignature>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassSignature {
+    pub type_parameters: Vec<TypeParameter>,
+    pub superclass: ClassTypeSignature,
+    pub interfaces: Vec<ClassTypeSignature>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MethodSignature {
+    pub type_parameters: Vec<TypeParameter>,
+    pub parameters: Vec<TypeSignature>,
+    pub result: TypeSignature,
+    pub throws: Vec<TypeSignature>,
+}
+
+impl ClassTypeSignature {
+    /// Binary name of the erased class in internal form, e.g. `java/util/Map$Entry`.
+    pub fn binary_name(&self) -> String {
+        let names: Vec<&str> = self.segments.iter().map(|s| s.name.as_str()).collect();
+
+        format!("{}{}", self.package, names.join("$"))
+    }
+}
+
+pub fn parse_class_signature(signature: &str) -> Option<ClassSignature> {
+    let mut p = Parser::new(signature);
+
+    let type_parameters = p.type_parameters()?;
+    let superclass = p.class_type_signature()?;
+    let mut interfaces = Vec::new();
+
+    while !p.at_end() {
+        interfaces.push(p.class_type_signature()?);
+    }
+
+    Some(ClassSignature {
+        type_parameters,
+        superclass,
+        interfaces,
+    })
+}
+
+pub fn parse_method_signature(signature: &str) -> Option<MethodSignature> {
+    let mut p = Parser::new(signature);
+
+    let type_parameters = p.type_parameters()?;
+    p.expect('(')?;
+
+    let mut parameters = Vec::new();
+    while p.peek()? != ')' {
+        parameters.push(p.java_type_signature()?);
+    }
+    p.expect(')')?;
+
+    let result = if p.peek()? == 'V' {
+        p.next();
+        TypeSignature::Base('V')
+    } else {
+        p.java_type_signature()?
+    };
+
+    let mut throws = Vec::new();
+    while !p.at_end() {
+        p.expect('^')?;
+        throws.push(p.reference_type_signature()?);
+    }
+
+    Some(MethodSignature {
+        type_parameters,
+        parameters,
+        result,
+        throws,
+    })
+}
+
+pub fn parse_field_signature(signature: &str) -> Option<TypeSignature> {
+    let mut p = Parser::new(signature);
+    let t = p.reference_type_signature()?;
+
+    if p.at_end() {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn new(s: &str) -> Parser {
+        Parser {
+            chars: s.chars().collect(),
+            pos: 0,
+        }
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.chars.len()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).cloned()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    fn expect(&mut self, c: char) -> Option<()> {
+        if self.next()? == c {
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    fn identifier(&mut self) -> Option<String> {
+        let start = self.pos;
+
+        while let Some(c) = self.peek() {
+            match c {
+                '.' | ';' | '[' | '/' | '<' | '>' | ':' => break,
+                _ => self.pos += 1,
+            }
+        }
+
+        if self.pos == start {
+            None
+        } else {
+            Some(self.chars[start..self.pos].iter().collect())
+        }
+    }
+
+    fn type_parameters(&mut self) -> Option<Vec<TypeParameter>> {
+        let mut type_parameters = Vec::new();
+
+        if self.peek() != Some('<') {
+            return Some(type_parameters);
+        }
+        self.next();
+
+        while self.peek()? != '>' {
+            let name = self.identifier()?;
+
+            self.expect(':')?;
+            let class_bound = match self.peek()? {
+                ':' | '>' => None,
+                _ => Some(self.reference_type_signature()?),
+            };
+
+            let mut interface_bounds = Vec::new();
+            while self.peek()? == ':' {
+                self.next();
+                interface_bounds.push(self.reference_type_signature()?);
+            }
+
+            type_parameters.push(TypeParameter {
+                name,
+                class_bound,
+                interface_bounds,
+            });
+        }
+        self.next();
+
+        Some(type_parameters)
+    }
+
+    fn java_type_signature(&mut self) -> Option<TypeSignature> {
+        match self.peek()? {
+            c @ 'B' | c @ 'C' | c @ 'D' | c @ 'F' | c @ 'I' | c @ 'J' | c @ 'S' | c @ 'Z' => {
+                self.next();
+                Some(TypeSignature::Base(c))
+            }
+            _ => self.reference_type_signature(),
+        }
+    }
+
+    fn reference_type_signature(&mut self) -> Option<TypeSignature> {
+        match self.peek()? {
+            'L' => Some(TypeSignature::Class(self.class_type_signature()?)),
+            'T' => {
+                self.next();
+                let name = self.identifier()?;
+                self.expect(';')?;
+                Some(TypeSignature::TypeVariable(name))
+            }
+            '[' => {
+                self.next();
+                Some(TypeSignature::Array(Box::new(self.java_type_signature()?)))
+            }
+            _ => None,
+        }
+    }
+
+    fn class_type_signature(&mut self) -> Option<ClassTypeSignature> {
+        self.expect('L')?;
+
+        let mut package = String::new();
+        let mut name = self.identifier()?;
+
+        while self.peek()? == '/' {
+            self.next();
+            package.push_str(&name);
+            package.push('/');
+            name = self.identifier()?;
+        }
+
+        let mut segments = vec![SimpleClassTypeSignature {
+            type_arguments: self.type_arguments()?,
+            name,
+        }];
+
+        while self.peek()? == '.' {
+            self.next();
+            let name = self.identifier()?;
+            segments.push(SimpleClassTypeSignature {
+                type_arguments: self.type_arguments()?,
+                name,
+            });
+        }
+
+        self.expect(';')?;
+
+        Some(ClassTypeSignature { package, segments })
+    }
+
+    fn type_arguments(&mut self) -> Option<Vec<TypeArgument>> {
+        let mut type_arguments = Vec::new();
+
+        if self.peek()? != '<' {
+            return Some(type_arguments);
+        }
+        self.next();
+
+        while self.peek()? != '>' {
+            let type_argument = match self.peek()? {
+                '*' => {
+                    self.next();
+                    TypeArgument::Any
+                }
+                '+' => {
+                    self.next();
+                    TypeArgument::Extends(self.reference_type_signature()?)
+                }
+                '-' => {
+                    self.next();
+                    TypeArgument::Super(self.reference_type_signature()?)
+                }
+                _ => TypeArgument::Exact(self.reference_type_signature()?),
+            };
+
+            type_arguments.push(type_argument);
+        }
+        self.next();
+
+        Some(type_arguments)
+    }
+}
@@ -0,0 +1,177 @@
+use std::io::Read;
+
+use read_util::*;
+use write_util::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerificationType {
+    Top,
+    Integer,
+    Float,
+    Double,
+    Long,
+    Null,
+    UninitializedThis,
+    Object(u16),
+    // Offset of the `new` instruction that created the object.
+    Uninitialized(u16),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackMapFrame {
+    pub frame_type: u8,
+    pub offset_delta: u16,
+    pub kind: FrameKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameKind {
+    Same,
+    SameLocals1StackItem(VerificationType),
+    Chop(u8),
+    Append(Vec<VerificationType>),
+    Full {
+        locals: Vec<VerificationType>,
+        stack: Vec<VerificationType>,
+    },
+}
+
+impl VerificationType {
+    pub fn tag(&self) -> u8 {
+        match *self {
+            VerificationType::Top => 0,
+            VerificationType::Integer => 1,
+            VerificationType::Float => 2,
+            VerificationType::Double => 3,
+            VerificationType::Long => 4,
+            VerificationType::Null => 5,
+            VerificationType::UninitializedThis => 6,
+            VerificationType::Object(_) => 7,
+            VerificationType::Uninitialized(_) => 8,
+        }
+    }
+}
+
+pub fn read_stack_map_table(mut info: &[u8]) -> Result<Vec<StackMapFrame>, String> {
+    let f = &mut info;
+    let number_of_entries = read_u16(f)?;
+
+    (0..number_of_entries).map(|_| read_frame(f)).collect()
+}
+
+fn read_frame(f: &mut dyn Read) -> Result<StackMapFrame, String> {
+    let frame_type = read_u8(f)?;
+
+    let (offset_delta, kind) = match frame_type {
+        0..=63 => (frame_type as u16, FrameKind::Same),
+        64..=127 => (
+            frame_type as u16 - 64,
+            FrameKind::SameLocals1StackItem(read_verification_type(f)?),
+        ),
+        247 => {
+            let offset_delta = read_u16(f)?;
+            (offset_delta, FrameKind::SameLocals1StackItem(read_verification_type(f)?))
+        }
+        248..=250 => (read_u16(f)?, FrameKind::Chop(251 - frame_type)),
+        251 => (read_u16(f)?, FrameKind::Same),
+        252..=254 => {
+            let offset_delta = read_u16(f)?;
+            let locals = read_verification_types(f, (frame_type - 251) as u16)?;
+            (offset_delta, FrameKind::Append(locals))
+        }
+        255 => {
+            let offset_delta = read_u16(f)?;
+            let number_of_locals = read_u16(f)?;
+            let locals = read_verification_types(f, number_of_locals)?;
+            let number_of_stack_items = read_u16(f)?;
+            let stack = read_verification_types(f, number_of_stack_items)?;
+            (offset_delta, FrameKind::Full { locals, stack })
+        }
+        _ => return Err(format!("unknown stack map frame type {}", frame_type)),
+    };
+
+    Ok(StackMapFrame {
+        frame_type,
+        offset_delta,
+        kind,
+    })
+}
+
+fn read_verification_types(f: &mut dyn Read, count: u16) -> Result<Vec<VerificationType>, String> {
+    (0..count).map(|_| read_verification_type(f)).collect()
+}
+
+fn read_verification_type(f: &mut dyn Read) -> Result<VerificationType, String> {
+    let tag = read_u8(f)?;
+
+    Ok(match tag {
+        0 => VerificationType::Top,
+        1 => VerificationType::Integer,
+        2 => VerificationType::Float,
+        3 => VerificationType::Double,
+        4 => VerificationType::Long,
+        5 => VerificationType::Null,
+        6 => VerificationType::UninitializedThis,
+        7 => VerificationType::Object(read_u16(f)?),
+        8 => VerificationType::Uninitialized(read_u16(f)?),
+        _ => return Err(format!("unknown verification type tag {}", tag)),
+    })
+}
+
+pub fn write_stack_map_table(frames: &[StackMapFrame]) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_u16(&mut out, frames.len() as u16);
+
+    for frame in frames {
+        write_u8(&mut out, frame.frame_type);
+
+        if frame.frame_type >= 247 {
+            write_u16(&mut out, frame.offset_delta);
+        }
+
+        match frame.kind {
+            FrameKind::Same | FrameKind::Chop(_) => {}
+            FrameKind::SameLocals1StackItem(ref item) => write_verification_type(&mut out, item),
+            FrameKind::Append(ref locals) => {
+                for t in locals {
+                    write_verification_type(&mut out, t);
+                }
+            }
+            FrameKind::Full { ref locals, ref stack } => {
+                write_u16(&mut out, locals.len() as u16);
+                for t in locals {
+                    write_verification_type(&mut out, t);
+                }
+                write_u16(&mut out, stack.len() as u16);
+                for t in stack {
+                    write_verification_type(&mut out, t);
+                }
+            }
+        }
+    }
+
+    out
+}
+
+fn write_verification_type(out: &mut Vec<u8>, t: &VerificationType) {
+    write_u8(out, t.tag());
+
+    match *t {
+        VerificationType::Object(index) | VerificationType::Uninitialized(index) => write_u16(out, index),
+        _ => {}
+    }
+}
+
+/// Resolves the relative offsets of a StackMapTable into the absolute code
+/// offsets each frame applies to.
+pub fn frame_offsets(frames: &[StackMapFrame]) -> Vec<u32> {
+    let mut offsets = Vec::with_capacity(frames.len());
+    let mut offset: i64 = -1;
+
+    for frame in frames {
+        offset += frame.offset_delta as i64 + 1;
+        offsets.push(offset as u32);
+    }
+
+    offsets
+}
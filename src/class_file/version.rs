@@ -0,0 +1,64 @@
+// Class file versions and the Java releases that introduced them. Java 1.0.2
+// and 1.1 both wrote 45.3, and from Java 12 a minor version of 0xFFFF marks a
+// class that uses preview features of exactly its release.
+
+use std::fmt;
+
+use serde::{Serialize, Serializer};
+
+pub const PREVIEW_MINOR_VERSION: u16 = 0xffff;
+
+// The first version whose minor version is either 0 or marks preview
+// features, that of Java 12.
+const PREVIEW_MAJOR_VERSION: u16 = 56;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct JavaRelease {
+    pub major_version: u16,
+    pub preview: bool,
+}
+
+impl JavaRelease {
+    /// The release of a class file version, if it is a valid one.
+    pub fn of(major_version: u16, minor_version: u16) -> Option<JavaRelease> {
+        if major_version < 45 {
+            return None;
+        }
+
+        let preview = match minor_version {
+            0 => false,
+            PREVIEW_MINOR_VERSION if major_version >= PREVIEW_MAJOR_VERSION => true,
+            _ if major_version >= PREVIEW_MAJOR_VERSION => return None,
+            _ => false,
+        };
+
+        Some(JavaRelease { major_version, preview })
+    }
+
+    /// The name of the release, 1.1 to 1.4 and then 5, 6 and so on.
+    pub fn name(&self) -> String {
+        match self.major_version {
+            45 => "1.1".to_string(),
+            46..=48 => format!("1.{}", self.major_version - 44),
+            major_version => (major_version - 44).to_string(),
+        }
+    }
+}
+
+impl fmt::Display for JavaRelease {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.preview {
+            write!(f, "{} with preview features", self.name())
+        } else {
+            write!(f, "{}", self.name())
+        }
+    }
+}
+
+// As its name, with preview classes reported separately.
+impl Serialize for JavaRelease {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer {
+        serializer.serialize_str(&self.name())
+    }
+}
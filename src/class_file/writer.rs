@@ -0,0 +1,311 @@
+// Serializes a ClassFile back to the binary class file format. Counts are
+// taken from the contents rather than the `*_count` fields.
+
+use std::collections::HashMap;
+
+use bytecode::frames::ComputedCode;
+use bytecode::hierarchy::ClassHierarchy;
+use class_file::ClassFile;
+use class_file::attributes::*;
+use class_file::constant_pool::*;
+use class_file::stack_map_table::write_stack_map_table;
+use write_util::*;
+
+#[derive(Default)]
+pub struct WriteOptions<'a> {
+    // Recompute max_stack, max_locals and the StackMapTable of every method,
+    // merging classes with this hierarchy.
+    pub compute_frames: Option<&'a dyn ClassHierarchy>,
+}
+
+pub fn write_classfile(cf: &ClassFile) -> Vec<u8> {
+    let methods: Vec<&Attributes> = cf.methods.array.iter().map(|m| &m.attributes).collect();
+
+    write(cf, &[], &methods)
+}
+
+pub fn write_classfile_with(cf: &ClassFile, options: &WriteOptions) -> Result<Vec<u8>, String> {
+    let hierarchy = match options.compute_frames {
+        Some(hierarchy) => hierarchy,
+        None => return Ok(write_classfile(cf)),
+    };
+
+    let cp = &cf.constant_pool;
+    let mut pool = PoolAdditions::new(cp);
+    let mut methods = Vec::new();
+
+    for method in &cf.methods.array {
+        let mut attributes = Attributes::default();
+
+        for attribute in &method.attributes.array {
+            let mut info = attribute.info.clone();
+
+            if attribute.name(cp).is_some_and(|n| n == "Code") {
+                info = read_code(&attribute.info)
+                    .map_err(|e| malformed("Code", e))
+                    .and_then(|code| {
+                        let computed = ComputedCode::compute(cf, method, &code, hierarchy).map_err(|e| e.to_string())?;
+                        recompute_code(cf, code, computed, &mut pool)
+                    })
+                    .map_err(|e| {
+                        let name = cp.utf8(method.name_index).unwrap_or_default();
+                        let descriptor = cp.utf8(method.descriptor_index).unwrap_or_default();
+                        format!("{}{}: {}", name, descriptor, e)
+                    })?;
+            }
+
+            attributes.array.push(AttributeInfo {
+                attribute_name_index: attribute.attribute_name_index,
+                attribute_length: info.len() as u32,
+                info,
+            });
+        }
+
+        methods.push(attributes);
+    }
+
+    Ok(write(cf, &pool.added, &methods.iter().collect::<Vec<_>>()))
+}
+
+// Rebuilds a Code attribute from recomputed limits and frames. Classes older
+// than version 50 keep whatever StackMapTable they had.
+fn recompute_code(cf: &ClassFile, code: CodeAttribute, computed: ComputedCode, pool: &mut PoolAdditions) -> Result<Vec<u8>, String> {
+    let cp = &cf.constant_pool;
+    let mut attributes = code.attributes;
+
+    if cf.major_version >= 50 {
+        attributes.array.retain(|a| a.name(cp).is_none_or(|n| n != "StackMapTable"));
+
+        if !computed.frames.is_empty() {
+            let frames = computed.stack_map_frames(|name| pool.class(name))?;
+            let info = write_stack_map_table(&frames);
+
+            attributes.array.push(AttributeInfo {
+                attribute_name_index: pool.utf8("StackMapTable")?,
+                attribute_length: info.len() as u32,
+                info,
+            });
+        }
+    }
+
+    Ok(write_code(&CodeAttribute {
+        max_stack: computed.max_stack,
+        max_locals: computed.max_locals,
+        code: computed.code,
+        exception_table: computed.exception_table,
+        attributes,
+    }))
+}
+
+pub fn write_code(code: &CodeAttribute) -> Vec<u8> {
+    let mut out = Vec::new();
+
+    write_u16(&mut out, code.max_stack);
+    write_u16(&mut out, code.max_locals);
+    write_u32(&mut out, code.code.len() as u32);
+    out.extend_from_slice(&code.code);
+
+    write_u16(&mut out, code.exception_table.len() as u16);
+    for entry in &code.exception_table {
+        write_u16(&mut out, entry.start_pc);
+        write_u16(&mut out, entry.end_pc);
+        write_u16(&mut out, entry.handler_pc);
+        write_u16(&mut out, entry.catch_type);
+    }
+
+    write_attributes(&mut out, &code.attributes);
+
+    out
+}
+
+// Writes the class with extra constant pool entries appended and the given
+// attributes in place of each method's own.
+fn write(cf: &ClassFile, added: &[Box<dyn CpInfo>], method_attributes: &[&Attributes]) -> Vec<u8> {
+    let mut out = Vec::new();
+
+    write_u32(&mut out, cf.magic);
+    write_u16(&mut out, cf.minor_version);
+    write_u16(&mut out, cf.major_version);
+
+    write_u16(&mut out, cf.constant_pool.count() + added.len() as u16);
+    for info in cf.constant_pool.array.iter().chain(added) {
+        write_cp_info(&mut out, info.as_ref());
+    }
+
+    write_u16(&mut out, cf.access_flags);
+    write_u16(&mut out, cf.this_class);
+    write_u16(&mut out, cf.super_class);
+    write_u16(&mut out, cf.interfaces.len() as u16);
+    write_vec_u16(&mut out, &cf.interfaces);
+
+    write_u16(&mut out, cf.fields.array.len() as u16);
+    for field in &cf.fields.array {
+        write_u16(&mut out, field.access_flags);
+        write_u16(&mut out, field.name_index);
+        write_u16(&mut out, field.descriptor_index);
+        write_attributes(&mut out, &field.attributes);
+    }
+
+    write_u16(&mut out, cf.methods.array.len() as u16);
+    for (method, attributes) in cf.methods.array.iter().zip(method_attributes) {
+        write_u16(&mut out, method.access_flags);
+        write_u16(&mut out, method.name_index);
+        write_u16(&mut out, method.descriptor_index);
+        write_attributes(&mut out, attributes);
+    }
+
+    write_attributes(&mut out, &cf.attributes);
+
+    out
+}
+
+pub fn write_attributes(out: &mut Vec<u8>, attributes: &Attributes) {
+    write_u16(out, attributes.array.len() as u16);
+
+    for attr in &attributes.array {
+        write_u16(out, attr.attribute_name_index);
+        write_u32(out, attr.info.len() as u32);
+        out.extend_from_slice(&attr.info);
+    }
+}
+
+fn write_cp_info(out: &mut Vec<u8>, info: &dyn CpInfo) {
+    let any = info.as_any();
+    write_u8(out, info.tag());
+
+    match cp_tag_from(info.tag()) {
+        CpTag::Class => write_u16(out, any.downcast_ref::<ClassInfo>().unwrap().name_index),
+        CpTag::Fieldref => {
+            let info = any.downcast_ref::<FieldrefInfo>().unwrap();
+            write_u16(out, info.class_index);
+            write_u16(out, info.name_and_type_index);
+        }
+        CpTag::Methodref => {
+            let info = any.downcast_ref::<MethodrefInfo>().unwrap();
+            write_u16(out, info.class_index);
+            write_u16(out, info.name_and_type_index);
+        }
+        CpTag::InterfaceMethodref => {
+            let info = any.downcast_ref::<InterfaceMethodrefInfo>().unwrap();
+            write_u16(out, info.class_index);
+            write_u16(out, info.name_and_type_index);
+        }
+        CpTag::String => write_u16(out, any.downcast_ref::<StringInfo>().unwrap().string_index),
+        CpTag::Integer => write_u32(out, any.downcast_ref::<IntegerInfo>().unwrap().bytes),
+        CpTag::Float => write_u32(out, any.downcast_ref::<FloatInfo>().unwrap().bytes),
+        CpTag::Long => {
+            let info = any.downcast_ref::<LongInfo>().unwrap();
+            write_u32(out, info.high_bytes);
+            write_u32(out, info.low_bytes);
+        }
+        CpTag::Double => {
+            let info = any.downcast_ref::<DoubleInfo>().unwrap();
+            write_u32(out, info.high_bytes);
+            write_u32(out, info.low_bytes);
+        }
+        CpTag::NameAndType => {
+            let info = any.downcast_ref::<NameAndTypeInfo>().unwrap();
+            write_u16(out, info.name_index);
+            write_u16(out, info.descriptor_index);
+        }
+        CpTag::Utf8 => {
+            let info = any.downcast_ref::<Utf8Info>().unwrap();
+            write_u16(out, info.bytes.len() as u16);
+            out.extend_from_slice(&info.bytes);
+        }
+        CpTag::MethodHandle => {
+            let info = any.downcast_ref::<MethodHandleInfo>().unwrap();
+            write_u8(out, info.reference_kind);
+            write_u16(out, info.reference_index);
+        }
+        CpTag::MethodType => write_u16(out, any.downcast_ref::<MethodTypeInfo>().unwrap().descriptor_index),
+        CpTag::Dynamic => {
+            let info = any.downcast_ref::<DynamicInfo>().unwrap();
+            write_u16(out, info.bootstrap_method_attr_index);
+            write_u16(out, info.name_and_type_index);
+        }
+        CpTag::InvokeDynamic => {
+            let info = any.downcast_ref::<InvokeDynamicInfo>().unwrap();
+            write_u16(out, info.bootstrap_method_attr_index);
+            write_u16(out, info.name_and_type_index);
+        }
+        CpTag::Module => write_u16(out, any.downcast_ref::<ModuleInfo>().unwrap().name_index),
+        CpTag::Package => write_u16(out, any.downcast_ref::<PackageInfo>().unwrap().name_index),
+    }
+}
+
+// Class and Utf8 entries appended to an existing constant pool, reusing
+// entries already present.
+struct PoolAdditions<'a> {
+    pool: &'a ConstantPool,
+    added: Vec<Box<dyn CpInfo>>,
+    utf8s: HashMap<String, u16>,
+    classes: HashMap<String, u16>,
+}
+
+impl<'a> PoolAdditions<'a> {
+    fn new(pool: &'a ConstantPool) -> PoolAdditions<'a> {
+        let mut utf8s = HashMap::new();
+        let mut classes = HashMap::new();
+
+        for (index, info) in pool.entries() {
+            match cp_tag_from(info.tag()) {
+                CpTag::Utf8 => {
+                    utf8s.entry(pool.utf8(index).unwrap()).or_insert(index);
+                }
+                CpTag::Class => {
+                    if let Some(name) = pool.class_name(index) {
+                        classes.entry(name).or_insert(index);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        PoolAdditions {
+            pool,
+            added: Vec::new(),
+            utf8s,
+            classes,
+        }
+    }
+
+    fn add(&mut self, info: Box<dyn CpInfo>) -> Result<u16, String> {
+        let index = self.pool.count() as usize + self.added.len();
+
+        if index > 0xfffe {
+            return Err(String::from("constant pool is full"));
+        }
+
+        self.added.push(info);
+        Ok(index as u16)
+    }
+
+    fn utf8(&mut self, s: &str) -> Result<u16, String> {
+        if let Some(&index) = self.utf8s.get(s) {
+            return Ok(index);
+        }
+
+        let bytes = encode_modified_utf8(s);
+        let index = self.add(Box::new(Utf8Info {
+            tag: 1,
+            length: bytes.len() as u16,
+            bytes,
+        }))?;
+
+        self.utf8s.insert(s.to_string(), index);
+        Ok(index)
+    }
+
+    fn class(&mut self, name: &str) -> Result<u16, String> {
+        if let Some(&index) = self.classes.get(name) {
+            return Ok(index);
+        }
+
+        let name_index = self.utf8(name)?;
+        let index = self.add(Box::new(ClassInfo { tag: 7, name_index }))?;
+
+        self.classes.insert(name.to_string(), index);
+        Ok(index)
+    }
+}
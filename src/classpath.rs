@@ -0,0 +1,693 @@
+// Scanning directories, archives and class paths for class files, parsing
+// them in parallel while reporting them in a fixed order, and finding
+// classes on a class path by name.
+
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::{Cursor, Read, Seek};
+use std::mem;
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use archive::pack200::{self, PackedFile};
+use archive::{self, Archive, ArchiveEntry, EntryContent, RawContent, RawEntry};
+use class_file::ClassFile;
+use class_file::reader::read_classfile;
+
+// Entries are read in batches of this size, and each batch parsed in
+// parallel, so memory use doesn't grow with the size of the input.
+const BATCH_SIZE: usize = 256;
+
+/// Selects entries by their path relative to the class path root, e.g.
+/// java/util/List.class. An empty filter selects everything.
+#[derive(Debug, Default, Clone)]
+pub struct Filter {
+    packages: Vec<String>,
+    globs: Vec<String>,
+}
+
+impl Filter {
+    pub fn new() -> Filter {
+        Filter::default()
+    }
+
+    /// Selects classes in a package, given as java.util or java/util, or in
+    /// its subpackages.
+    pub fn package(&mut self, name: &str) -> &mut Filter {
+        self.packages.push(name.replace('.', "/").trim_matches('/').to_string());
+        self
+    }
+
+    /// Selects paths matching a glob. `*` and `?` match within a directory
+    /// name and `**` across directories.
+    pub fn glob(&mut self, pattern: &str) -> &mut Filter {
+        self.globs.push(pattern.to_string());
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.packages.is_empty() && self.globs.is_empty()
+    }
+
+    pub fn matches(&self, path: &str) -> bool {
+        let package = path.rsplit_once('/').map_or("", |(package, _)| package);
+
+        let in_package = self.packages.is_empty()
+            || self.packages.iter().any(|p| {
+                package == p || package.strip_prefix(p.as_str()).is_some_and(|rest| rest.starts_with('/')) || p.is_empty()
+            });
+        let matches_glob = self.globs.is_empty() || self.globs.iter().any(|g| glob_matches(g.as_bytes(), path.as_bytes()));
+
+        in_package && matches_glob
+    }
+}
+
+fn glob_matches(pattern: &[u8], path: &[u8]) -> bool {
+    match pattern.first() {
+        None => path.is_empty(),
+        Some(b'*') if pattern.get(1) == Some(&b'*') => {
+            let rest = &pattern[2..];
+
+            // a/**/b also matches a/b.
+            (rest.first() == Some(&b'/') && glob_matches(&rest[1..], path))
+                || (0..=path.len()).any(|i| glob_matches(rest, &path[i..]))
+        }
+        Some(b'*') => {
+            let rest = &pattern[1..];
+            let segment = path.iter().position(|&c| c == b'/').unwrap_or(path.len());
+
+            (0..=segment).any(|i| glob_matches(rest, &path[i..]))
+        }
+        Some(b'?') => !path.is_empty() && path[0] != b'/' && glob_matches(&pattern[1..], &path[1..]),
+        Some(&c) => path.first() == Some(&c) && glob_matches(&pattern[1..], &path[1..]),
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ScanOptions {
+    pub filter: Filter,
+    /// For multi-release jars, the Java release to select classes for.
+    pub release: Option<u32>,
+    /// Whether to report files other than classes and manifests.
+    pub resources: bool,
+}
+
+/// An entry found in one of the roots of a class path.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ScannedEntry {
+    /// The directory, archive or class file the entry was found in.
+    pub source: String,
+
+    /// The path is relative to the source if it is a directory, and else
+    /// starts with its file name, e.g. app.jar!/BOOT-INF/lib/x.jar!/A.class.
+    /// It is empty if the source itself could not be read.
+    #[serde(flatten)]
+    pub entry: ArchiveEntry,
+}
+
+impl ScannedEntry {
+    /// The entry's path next to the source, such as
+    /// libs/app.jar!/BOOT-INF/lib/x.jar!/com/Foo.class or classes/com/Foo.class.
+    pub fn location(&self) -> String {
+        let source = Path::new(&self.source);
+
+        if self.entry.path.is_empty() {
+            self.source.clone()
+        } else if source.is_dir() {
+            source.join(&self.entry.path).display().to_string()
+        } else {
+            source.with_file_name(&self.entry.path).display().to_string()
+        }
+    }
+
+    /// The entry's path in the source, without the file name of an archive.
+    pub fn path_in_source(&self) -> &str {
+        let source = Path::new(&self.source);
+
+        match self.entry.path.strip_prefix(&archive_prefix(source)) {
+            Some(path) if !source.is_dir() => path,
+            _ => &self.entry.path,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct Summary {
+    pub roots: usize,
+    pub classes: usize,
+    pub resources: usize,
+    pub failed: usize,
+}
+
+/// Splits a class path in the platform's syntax, such as
+/// build/classes:lib/a.jar on Unix.
+pub fn split_classpath(classpath: &str) -> Vec<PathBuf> {
+    env::split_paths(classpath)
+        .filter(|path| !path.as_os_str().is_empty())
+        .collect()
+}
+
+/// Reads every selected entry of the given directories, archives and class
+/// files, and passes them to `each` in order: roots in the order given,
+/// directories sorted by name and archives in central directory order.
+pub fn scan<F>(roots: &[PathBuf], options: &ScanOptions, mut each: F) -> Summary
+    where F: FnMut(ScannedEntry) {
+    let mut summary = Summary::default();
+
+    for root in roots {
+        summary.roots += 1;
+        let source = root.display().to_string();
+
+        let mut emit = |entries: Vec<RawEntry>| {
+            let parsed: Vec<ArchiveEntry> = entries.into_par_iter().map(RawEntry::parse).collect();
+
+            for entry in parsed {
+                match entry.content {
+                    EntryContent::Class(_) => summary.classes += 1,
+                    EntryContent::Resource { .. } => summary.resources += 1,
+                    EntryContent::Error(_) => summary.failed += 1,
+                    EntryContent::Manifest(_) => {}
+                }
+
+                each(ScannedEntry { source: source.clone(), entry });
+            }
+        };
+
+        if let Err(e) = scan_root(root, options, &mut emit) {
+            emit(vec![RawEntry {
+                path: String::new(),
+                release: None,
+                content: RawContent::Error(e),
+            }]);
+        }
+    }
+
+    summary
+}
+
+// Reads the selected entries of a root and hands them over in batches.
+fn scan_root(root: &Path, options: &ScanOptions, emit: &mut dyn FnMut(Vec<RawEntry>)) -> Result<(), String> {
+    if fs::metadata(root).map_err(|e| e.to_string())?.is_dir() {
+        let mut files = Vec::new();
+        walk(root, "", &mut files).map_err(|e| e.to_string())?;
+
+        files.retain(|path| selected(options, path, path));
+
+        for batch in files.chunks(BATCH_SIZE) {
+            emit(batch.iter().map(|path| read_file(&root.join(path), path)).collect());
+        }
+
+        return Ok(());
+    }
+
+    let mut f = File::open(root).map_err(|e| e.to_string())?;
+    let mut header = Vec::new();
+    (&mut f).take(4).read_to_end(&mut header).map_err(|e| e.to_string())?;
+
+    if !archive::is_archive(&header) {
+        let name = root.file_name().and_then(OsStr::to_str).unwrap_or_default();
+        emit(vec![read_file(root, name)]);
+        return Ok(());
+    }
+
+    let prefix = archive_prefix(root);
+
+    if pack200::is_packed(&header) {
+        let mut bytes = header;
+        f.read_to_end(&mut bytes).map_err(|e| e.to_string())?;
+        let mut files = pack200::unpack(&bytes)?;
+        files.retain(|file| selected(options, &file.name, &file.name));
+
+        while !files.is_empty() {
+            let rest = files.split_off(files.len().min(BATCH_SIZE));
+            emit(files.into_iter().map(|file| packed_entry(&prefix, file)).collect());
+            files = rest;
+        }
+
+        return Ok(());
+    }
+
+    let mut archive = Archive::new(f)?;
+    scan_archive(&mut archive, &prefix, 0, options, emit);
+
+    Ok(())
+}
+
+// Nested archives are only searched this deep, so that an archive that
+// contains itself can't recurse forever.
+const MAX_NESTING: usize = 4;
+
+// Entries of an archive have its file name and !/ before their path.
+fn archive_prefix(path: &Path) -> String {
+    format!("{}!/", path.file_name().and_then(OsStr::to_str).unwrap_or_default())
+}
+
+// Hands over the selected entries of an archive in batches, descending into
+// nested archives where they appear. Paths are prefixed with the path of the
+// archive and !/, from the root archive on.
+fn scan_archive<R: Read + Seek>(
+    archive: &mut Archive<R>,
+    prefix: &str,
+    depth: usize,
+    options: &ScanOptions,
+    emit: &mut dyn FnMut(Vec<RawEntry>),
+) {
+    let indices = match options.release {
+        Some(release) => archive.release_entries(release),
+        None => (0..archive.len()).collect(),
+    };
+
+    let mut batch = Vec::new();
+
+    for index in indices {
+        let (name, class_path) = match archive.name(index) {
+            Some(name) if name.ends_with('/') => continue,
+            Some(name) => (name.to_string(), archive.class_path(index).map(String::from)),
+            // Unreadable entries are reported.
+            None => (String::new(), None),
+        };
+
+        let nested = depth < MAX_NESTING && archive::is_nested_archive(&name);
+        let wanted = match class_path {
+            Some(ref class_path) => selected(options, &name, class_path),
+            None => name.is_empty() || (options.resources && options.filter.is_empty()),
+        };
+
+        if wanted {
+            if let Some(mut entry) = archive.read(index) {
+                entry.path = format!("{}{}", prefix, entry.path);
+                batch.push(entry);
+            }
+        }
+
+        if nested {
+            emit(std::mem::take(&mut batch));
+
+            match archive.open_nested(index) {
+                Ok(mut inner) => {
+                    let prefix = format!("{}{}!/", prefix, name);
+                    scan_archive(&mut inner, &prefix, depth + 1, options, emit);
+                }
+                Err(e) => emit(vec![RawEntry {
+                    path: format!("{}{}", prefix, name),
+                    release: None,
+                    content: RawContent::Error(e),
+                }]),
+            }
+        } else if batch.len() == BATCH_SIZE {
+            emit(std::mem::take(&mut batch));
+        }
+    }
+
+    emit(batch);
+}
+
+// Pack200 archives are unpacked whole, so their files are at hand.
+fn packed_entry(prefix: &str, file: PackedFile) -> RawEntry {
+    let content = if archive::is_parsed(&file.name) {
+        RawContent::Bytes(file.contents)
+    } else {
+        RawContent::Resource { size: file.contents.len() as u64 }
+    };
+
+    RawEntry { path: format!("{}{}", prefix, file.name), release: None, content }
+}
+
+fn selected(options: &ScanOptions, name: &str, class_path: &str) -> bool {
+    (archive::is_parsed(name) || options.resources) && options.filter.matches(class_path)
+}
+
+// Collects the files under a directory, sorted by name, as /-separated paths
+// relative to the root.
+fn walk(dir: &Path, prefix: &str, files: &mut Vec<String>) -> std::io::Result<()> {
+    let mut entries: Vec<_> = fs::read_dir(dir)?.collect::<Result<_, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let path = format!("{}{}", prefix, name);
+
+        if entry.file_type()?.is_dir() {
+            walk(&entry.path(), &format!("{}/", path), files)?;
+        } else {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+fn read_file(file: &Path, path: &str) -> RawEntry {
+    let content = if archive::is_parsed(path) {
+        match fs::read(file) {
+            Ok(bytes) => RawContent::Bytes(bytes),
+            Err(e) => RawContent::Error(e.to_string()),
+        }
+    } else {
+        match fs::metadata(file) {
+            Ok(metadata) => RawContent::Resource { size: metadata.len() },
+            Err(e) => RawContent::Error(e.to_string()),
+        }
+    };
+
+    RawEntry {
+        path: path.to_string(),
+        release: None,
+        content,
+    }
+}
+
+/// Finds classes by name on a class path the way the JVM's application class
+/// loader does: roots are searched in order, each archive followed by the
+/// archives nested in it and those its manifest lists in Class-Path. Roots
+/// are only opened once the search reaches them, and those that can't be
+/// read are skipped.
+pub struct ClassPath {
+    release: Option<u32>,
+    locations: Vec<Location>,
+    // Canonical paths of the roots opened so far, so that each is searched
+    // once however often it is listed.
+    seen: HashSet<PathBuf>,
+}
+
+enum Location {
+    Unopened(PathBuf),
+    // An archive stored in the one at `parent` in the locations.
+    UnopenedNested { parent: usize, index: usize, depth: usize },
+    Directory(PathBuf),
+    Class { source: String, name: Option<String>, bytes: Vec<u8> },
+    Archive(IndexedArchive<File>),
+    Nested(IndexedArchive<Cursor<Vec<u8>>>),
+    Packed { source: String, classes: HashMap<String, Vec<u8>> },
+    Skipped,
+}
+
+// Nested archives are read into memory.
+type NestedArchive = Archive<Cursor<Vec<u8>>>;
+
+struct IndexedArchive<R: Read + Seek> {
+    source: String,
+    // The file name of the root archive and the path of a nested archive in
+    // it, each followed by !/.
+    prefix: String,
+    archive: Archive<R>,
+    // Entry indices by class path, e.g. java/util/List.class.
+    classes: HashMap<String, usize>,
+}
+
+impl ClassPath {
+    /// A class path of directories, archives and class files. For
+    /// multi-release jars, classes are found as a JVM of `release` would
+    /// load them, or as Java 8 would if there is none.
+    pub fn new(roots: &[PathBuf], release: Option<u32>) -> ClassPath {
+        ClassPath {
+            release,
+            locations: roots.iter().cloned().map(Location::Unopened).collect(),
+            seen: HashSet::new(),
+        }
+    }
+
+    /// Finds a class by its binary name in internal form, such as
+    /// java/util/Map$Entry. The entry is a parsed class, or an error if the
+    /// first class file of that name can't be read.
+    pub fn find(&mut self, name: &str) -> Option<ScannedEntry> {
+        let path = format!("{}.class", name);
+        let mut i = 0;
+
+        while i < self.locations.len() {
+            self.open(i);
+
+            if let Some(found) = self.locations[i].find(&path) {
+                return Some(found);
+            }
+
+            i += 1;
+        }
+
+        None
+    }
+
+    /// Finds a class by a name as written in Java, such as java.util.Map.Entry
+    /// or java.util.Map$Entry, or in internal form. A dotted name may stand
+    /// for a member class, so failing a top level class of that name, the
+    /// last dots are taken for $ one by one.
+    pub fn lookup(&mut self, name: &str) -> Option<ScannedEntry> {
+        let name = name.trim_end_matches(".class").replace('.', "/");
+        let mut candidate = name.clone();
+
+        loop {
+            if let Some(found) = self.find(&candidate) {
+                return Some(found);
+            }
+
+            match candidate.rfind('/') {
+                Some(i) => candidate.replace_range(i..=i, "$"),
+                None => return None,
+            }
+        }
+    }
+
+    /// Like `find`, but reads the class itself.
+    pub fn class(&mut self, name: &str) -> Result<ClassFile, String> {
+        match self.find(name).map(|found| found.entry.content) {
+            Some(EntryContent::Class(cf)) => Ok(cf),
+            Some(EntryContent::Error(e)) => Err(e),
+            _ => Err(format!("{} not found on the class path", name)),
+        }
+    }
+
+    // Opens the location at `i` if it hasn't been yet, inserting the
+    // archives it brings in right after it.
+    fn open(&mut self, i: usize) {
+        let (opened, following) = match mem::replace(&mut self.locations[i], Location::Skipped) {
+            Location::Unopened(path) => self.open_root(path, i),
+            Location::UnopenedNested { parent, index, depth } => self.open_nested(parent, index, depth, i),
+            location => (location, Vec::new()),
+        };
+
+        self.locations[i] = opened;
+        self.locations.splice(i + 1..i + 1, following);
+    }
+
+    fn open_root(&mut self, path: PathBuf, i: usize) -> (Location, Vec<Location>) {
+        let skipped = (Location::Skipped, Vec::new());
+
+        if !self.seen.insert(fs::canonicalize(&path).unwrap_or_else(|_| path.clone())) {
+            return skipped;
+        }
+
+        match fs::metadata(&path) {
+            Ok(metadata) if metadata.is_dir() => return (Location::Directory(path), Vec::new()),
+            Ok(_) => {}
+            Err(_) => return skipped,
+        }
+
+        let mut f = match File::open(&path) {
+            Ok(f) => f,
+            Err(_) => return skipped,
+        };
+        let mut header = Vec::new();
+        if (&mut f).take(4).read_to_end(&mut header).is_err() {
+            return skipped;
+        }
+
+        let source = path.display().to_string();
+
+        if !archive::is_archive(&header) {
+            let mut bytes = header;
+            if f.read_to_end(&mut bytes).is_err() {
+                return skipped;
+            }
+
+            let name = read_classfile(&mut &bytes[..]).ok().and_then(|cf| cf.name());
+            return (Location::Class { source, name, bytes }, Vec::new());
+        }
+
+        if pack200::is_packed(&header) {
+            let mut bytes = header;
+            let files = match f.read_to_end(&mut bytes).map_err(|e| e.to_string()).and_then(|_| pack200::unpack(&bytes)) {
+                Ok(files) => files,
+                Err(_) => return skipped,
+            };
+
+            let mut classes = HashMap::new();
+            for file in files.into_iter().filter(|file| file.name.ends_with(".class")) {
+                classes.entry(file.name).or_insert(file.contents);
+            }
+
+            return (Location::Packed { source, classes }, Vec::new());
+        }
+
+        let archive = match Archive::new(f) {
+            Ok(archive) => archive,
+            Err(_) => return skipped,
+        };
+
+        let class_path = archive
+            .manifest()
+            .and_then(|manifest| manifest.main.get("Class-Path"))
+            .map(|value| manifest_class_path(&path, value))
+            .unwrap_or_default();
+
+        let (indexed, mut following) = self.index(archive, source, archive_prefix(&path), 0, i);
+        following.extend(class_path.into_iter().map(Location::Unopened));
+
+        (Location::Archive(indexed), following)
+    }
+
+    fn open_nested(&mut self, parent: usize, index: usize, depth: usize, i: usize) -> (Location, Vec<Location>) {
+        let opened = match self.locations[parent] {
+            Location::Archive(ref mut outer) => nested(outer, index),
+            Location::Nested(ref mut outer) => nested(outer, index),
+            _ => None,
+        };
+
+        match opened {
+            Some((archive, source, prefix)) => {
+                let (indexed, following) = self.index(archive, source, prefix, depth, i);
+                (Location::Nested(indexed), following)
+            }
+            None => (Location::Skipped, Vec::new()),
+        }
+    }
+
+    // Indexes the classes of an archive, and lists the archives nested in it.
+    fn index<R: Read + Seek>(
+        &self,
+        archive: Archive<R>,
+        source: String,
+        prefix: String,
+        depth: usize,
+        i: usize,
+    ) -> (IndexedArchive<R>, Vec<Location>) {
+        let mut classes = HashMap::new();
+        let mut nested = Vec::new();
+
+        // Java 8 ignores the versioned entries of multi-release jars.
+        for index in archive.release_entries(self.release.unwrap_or(8)) {
+            let name = match archive.name(index) {
+                Some(name) => name,
+                None => continue,
+            };
+
+            if depth < MAX_NESTING && archive::is_nested_archive(name) {
+                nested.push(Location::UnopenedNested { parent: i, index, depth: depth + 1 });
+            } else if let Some(class_path) = archive.class_path(index).filter(|path| path.ends_with(".class")) {
+                classes.entry(class_path.to_string()).or_insert(index);
+            }
+        }
+
+        let indexed = IndexedArchive { source, prefix, archive, classes };
+        (indexed, nested)
+    }
+}
+
+impl Location {
+    fn find(&mut self, path: &str) -> Option<ScannedEntry> {
+        let (source, entry) = match *self {
+            Location::Directory(ref root) => {
+                let file = root.join(path);
+                if !file.is_file() {
+                    return None;
+                }
+
+                (root.display().to_string(), read_file(&file, path).parse())
+            }
+            Location::Class { ref source, ref name, ref bytes } => {
+                if name.as_ref().map(|name| format!("{}.class", name)).as_deref() != Some(path) {
+                    return None;
+                }
+
+                let name = Path::new(source).file_name().and_then(OsStr::to_str).unwrap_or_default();
+                (source.clone(), class_entry(name.to_string(), bytes.clone()))
+            }
+            Location::Archive(ref mut indexed) => return indexed.find(path),
+            Location::Nested(ref mut indexed) => return indexed.find(path),
+            Location::Packed { ref source, ref classes } => {
+                let bytes = classes.get(path)?.clone();
+                (source.clone(), class_entry(format!("{}{}", archive_prefix(Path::new(source)), path), bytes))
+            }
+            _ => return None,
+        };
+
+        Some(ScannedEntry { source, entry })
+    }
+}
+
+impl<R: Read + Seek> IndexedArchive<R> {
+    fn find(&mut self, path: &str) -> Option<ScannedEntry> {
+        let index = *self.classes.get(path)?;
+        let mut entry = self.archive.entry(index)?;
+        entry.path = format!("{}{}", self.prefix, entry.path);
+
+        Some(ScannedEntry { source: self.source.clone(), entry })
+    }
+}
+
+fn nested<R: Read + Seek>(
+    outer: &mut IndexedArchive<R>,
+    index: usize,
+) -> Option<(NestedArchive, String, String)> {
+    let archive = outer.archive.open_nested(index).ok()?;
+    let prefix = format!("{}{}!/", outer.prefix, outer.archive.name(index)?);
+
+    Some((archive, outer.source.clone(), prefix))
+}
+
+fn class_entry(path: String, bytes: Vec<u8>) -> ArchiveEntry {
+    RawEntry {
+        path,
+        release: None,
+        content: RawContent::Bytes(bytes),
+    }
+    .parse()
+}
+
+// The Class-Path attribute of a jar manifest lists the URLs of further
+// archives and directories, separated by spaces and relative to the jar.
+// Only local files can be searched.
+fn manifest_class_path(jar: &Path, value: &str) -> Vec<PathBuf> {
+    let base = jar.parent().unwrap_or_else(|| Path::new(""));
+
+    value
+        .split_whitespace()
+        .filter_map(|url| {
+            let path = match url.strip_prefix("file:") {
+                Some(path) => path.trim_start_matches("//"),
+                None if url.split('/').next()?.contains(':') => return None,
+                None => url,
+            };
+
+            // Absolute paths replace the base.
+            Some(base.join(percent_decode(path)))
+        })
+        .collect()
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|hex| std::str::from_utf8(hex).ok());
+
+        match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+            Some(byte) if bytes[i] == b'%' => {
+                decoded.push(byte);
+                i += 3;
+            }
+            _ => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,679 @@
+// Structural differences between two class files. Both are rendered with
+// every constant pool reference resolved to what it refers to and every code
+// offset replaced by a label, so that recompiling a class, which renumbers
+// its constant pool, makes no difference by itself.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use serde::Serialize;
+
+use assembly::syntax::{constant_spec, format_flags, quote_bytes, tag_name, FlagContext};
+use bytecode::{self, Instruction, Operand};
+use class_file::annotations::*;
+use class_file::attributes::*;
+use class_file::constant_pool::{ConstantPool, CpTag, DynamicInfo, InvokeDynamicInfo};
+use class_file::stack_map_table::*;
+use class_file::ClassFile;
+
+// Lines of context around each hunk of a line diff.
+const CONTEXT: usize = 2;
+
+// Beyond this many cells, differing runs of lines aren't matched up but
+// reported as removed and added as a whole.
+const MAX_LCS_CELLS: usize = 4_000_000;
+
+// Attributes only compilers' debugging options add.
+const DEBUG_ATTRIBUTES: &[&str] = &[
+    "SourceFile",
+    "SourceDebugExtension",
+    "LineNumberTable",
+    "LocalVariableTable",
+    "LocalVariableTypeTable",
+];
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DiffOptions {
+    /// Leaves out source file names, line numbers and local variables.
+    pub ignore_debug: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Difference {
+    pub change: ChangeKind,
+
+    /// The field or method, such as `method run ()V`, or none for the class
+    /// itself.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub member: Option<String>,
+
+    /// The part of the class or member, such as `access_flags` or an
+    /// attribute name, or none when a whole member was added or removed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub element: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new: Option<String>,
+
+    /// For elements of several lines, such as code, the lines that differ
+    /// as unified diff hunks.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub lines: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClassDiff {
+    pub differences: Vec<Difference>,
+}
+
+impl ClassDiff {
+    pub fn is_empty(&self) -> bool {
+        self.differences.is_empty()
+    }
+}
+
+// A class or member as named elements of any number of lines.
+type Elements = BTreeMap<String, Vec<String>>;
+
+struct Rendered {
+    class: Elements,
+    members: BTreeMap<String, Elements>,
+}
+
+pub fn diff(old: &ClassFile, new: &ClassFile, options: &DiffOptions) -> ClassDiff {
+    let old = Renderer::new(old, options).class();
+    let new = Renderer::new(new, options).class();
+    let mut differences = Vec::new();
+
+    compare_elements(None, &old.class, &new.class, &mut differences);
+
+    let names: BTreeSet<&String> = old.members.keys().chain(new.members.keys()).collect();
+    for name in names {
+        match (old.members.get(name), new.members.get(name)) {
+            (Some(old), Some(new)) => compare_elements(Some(name), old, new, &mut differences),
+            (old, _) => differences.push(Difference {
+                change: if old.is_some() { ChangeKind::Removed } else { ChangeKind::Added },
+                member: Some(name.clone()),
+                element: None,
+                old: None,
+                new: None,
+                lines: Vec::new(),
+            }),
+        }
+    }
+
+    ClassDiff { differences }
+}
+
+fn compare_elements(member: Option<&String>, old: &Elements, new: &Elements, differences: &mut Vec<Difference>) {
+    let names: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    let empty = Vec::new();
+
+    for name in names {
+        let change = match (old.get(name), new.get(name)) {
+            (Some(old), Some(new)) if old == new => continue,
+            (Some(_), Some(_)) => ChangeKind::Changed,
+            (Some(_), None) => ChangeKind::Removed,
+            (None, _) => ChangeKind::Added,
+        };
+        let old_lines = old.get(name).unwrap_or(&empty);
+        let new_lines = new.get(name).unwrap_or(&empty);
+
+        // Single values are given as they are, longer ones as a diff.
+        let (old_value, new_value, lines) = if old_lines.len() <= 1 && new_lines.len() <= 1 {
+            (old_lines.first().cloned(), new_lines.first().cloned(), Vec::new())
+        } else {
+            (None, None, line_diff(old_lines, new_lines))
+        };
+
+        differences.push(Difference {
+            change,
+            member: member.cloned(),
+            element: Some(name.clone()),
+            old: old_value,
+            new: new_value,
+            lines,
+        });
+    }
+}
+
+struct Renderer<'a> {
+    cf: &'a ClassFile,
+    cp: &'a ConstantPool,
+    bootstrap_methods: Vec<BootstrapMethod>,
+    options: &'a DiffOptions,
+}
+
+impl<'a> Renderer<'a> {
+    fn new(cf: &'a ClassFile, options: &'a DiffOptions) -> Renderer<'a> {
+        let cp = &cf.constant_pool;
+        let bootstrap_methods = cf
+            .attributes
+            .find(cp, "BootstrapMethods")
+            .and_then(|attribute| read_bootstrap_methods(&attribute.info).ok())
+            .unwrap_or_default();
+
+        Renderer { cf, cp, bootstrap_methods, options }
+    }
+
+    fn class(&self) -> Rendered {
+        let cf = self.cf;
+        let mut class = Elements::new();
+
+        class.insert("version".to_string(), vec![format!("{}.{}", cf.major_version, cf.minor_version)]);
+        class.insert("access_flags".to_string(), vec![flags(cf.access_flags, FlagContext::Class)]);
+        class.insert("this_class".to_string(), vec![self.class_name(cf.this_class)]);
+        if cf.super_class != 0 {
+            class.insert("super_class".to_string(), vec![self.class_name(cf.super_class)]);
+        }
+        if !cf.interfaces.is_empty() {
+            let interfaces = cf.interfaces.iter().map(|&index| self.class_name(index)).collect();
+            class.insert("interfaces".to_string(), interfaces);
+        }
+        self.attributes(&cf.attributes, &mut class);
+
+        let mut members = BTreeMap::new();
+
+        for field in &cf.fields.array {
+            let mut elements = Elements::new();
+            elements.insert("access_flags".to_string(), vec![flags(field.access_flags, FlagContext::Field)]);
+            self.attributes(&field.attributes, &mut elements);
+
+            let name = format!("field {} {}", self.utf8(field.name_index), self.utf8(field.descriptor_index));
+            members.entry(name).or_insert(elements);
+        }
+
+        for method in &cf.methods.array {
+            let mut elements = Elements::new();
+            elements.insert("access_flags".to_string(), vec![flags(method.access_flags, FlagContext::Method)]);
+            self.attributes(&method.attributes, &mut elements);
+
+            let name = format!("method {} {}", self.utf8(method.name_index), self.utf8(method.descriptor_index));
+            members.entry(name).or_insert(elements);
+        }
+
+        Rendered { class, members }
+    }
+
+    // Adds the attributes as elements named after them, numbered from the
+    // second one of a name on.
+    fn attributes(&self, attributes: &Attributes, elements: &mut Elements) {
+        let mut seen: HashMap<String, usize> = HashMap::new();
+
+        for attribute in &attributes.array {
+            let name = attribute.name(self.cp).unwrap_or_else(|| format!("[{}]", attribute.attribute_name_index));
+            if self.options.ignore_debug && DEBUG_ATTRIBUTES.contains(&name.as_str()) {
+                continue;
+            }
+
+            let count = seen.entry(name.clone()).or_insert(0);
+            *count += 1;
+            let key = if *count == 1 { name.clone() } else { format!("{} {}", name, count) };
+
+            match name.as_str() {
+                // Compared through the call sites that use them, unless they
+                // can't be decoded.
+                "BootstrapMethods" if read_bootstrap_methods(&attribute.info).is_ok() => {}
+                "Code" => {
+                    for (sub_name, lines) in self.code(&attribute.info) {
+                        let sub_key = if sub_name.is_empty() { key.clone() } else { format!("{} {}", key, sub_name) };
+                        elements.insert(sub_key, lines);
+                    }
+                }
+                _ => {
+                    elements.insert(key, self.attribute(&name, &attribute.info));
+                }
+            }
+        }
+    }
+
+    // Attributes that can't be decoded are compared as bytes.
+    fn attribute(&self, name: &str, info: &[u8]) -> Vec<String> {
+        self.decoded_attribute(name, info)
+            .unwrap_or_else(|e| vec![format!("malformed: {}", e), quote_bytes(info)])
+    }
+
+    fn decoded_attribute(&self, name: &str, info: &[u8]) -> Result<Vec<String>, String> {
+        Ok(match name {
+            "ConstantValue" => vec![self.constant(read_index(info)?)],
+            "Signature" | "SourceFile" => vec![self.utf8(read_index(info)?)],
+            "NestHost" => vec![self.class_name(read_index(info)?)],
+            "Exceptions" | "NestMembers" | "PermittedSubclasses" => {
+                read_index_table(info)?.into_iter().map(|index| self.class_name(index)).collect()
+            }
+            "EnclosingMethod" => {
+                let (class_index, method_index) = read_enclosing_method(info)?;
+                let method = match self.cp.name_and_type(method_index) {
+                    Some((name, descriptor)) => format!(" {} {}", name, descriptor),
+                    None => String::new(),
+                };
+                vec![format!("{}{}", self.class_name(class_index), method)]
+            }
+            "InnerClasses" => read_inner_classes(info)?
+                .into_iter()
+                .map(|inner| {
+                    format!(
+                        "{} {} {} {}",
+                        self.optional_class_name(inner.inner_class_info_index),
+                        self.optional_class_name(inner.outer_class_info_index),
+                        self.cp.utf8(inner.inner_name_index).unwrap_or_else(|| "-".to_string()),
+                        flags(inner.inner_class_access_flags, FlagContext::InnerClass)
+                    )
+                })
+                .collect(),
+            "MethodParameters" => read_method_parameters(info)?
+                .into_iter()
+                .map(|parameter| {
+                    let name = self.cp.utf8(parameter.name_index).unwrap_or_else(|| "-".to_string());
+                    format!("{} {}", name, flags(parameter.access_flags, FlagContext::Field)).trim_end().to_string()
+                })
+                .collect(),
+            "RuntimeVisibleAnnotations" | "RuntimeInvisibleAnnotations" => read_annotations(&mut &info[..])?
+                .iter()
+                .map(|annotation| self.annotation(annotation))
+                .collect(),
+            "RuntimeVisibleParameterAnnotations" | "RuntimeInvisibleParameterAnnotations" => {
+                let mut lines = Vec::new();
+                for (i, annotations) in read_parameter_annotations(&mut &info[..])?.iter().enumerate() {
+                    for annotation in annotations {
+                        lines.push(format!("parameter {} {}", i, self.annotation(annotation)));
+                    }
+                }
+                lines
+            }
+            "AnnotationDefault" => vec![self.element_value(&read_element_value(&mut &info[..])?)],
+            "Record" => {
+                let mut lines = Vec::new();
+                for component in read_record(info)? {
+                    lines.push(format!("{} {}", self.utf8(component.name_index), self.utf8(component.descriptor_index)));
+
+                    let mut elements = Elements::new();
+                    self.attributes(&component.attributes, &mut elements);
+                    for (name, values) in elements {
+                        lines.push(format!("    {}", name));
+                        lines.extend(values.into_iter().map(|value| format!("        {}", value)));
+                    }
+                }
+                lines
+            }
+            "Deprecated" | "Synthetic" => Vec::new(),
+            _ => vec![quote_bytes(info)],
+        })
+    }
+
+    // The parts of a Code attribute: the code itself, under an empty name,
+    // with the frames of its StackMapTable and its line numbers inline, and
+    // its other attributes under their own names.
+    fn code(&self, info: &[u8]) -> Vec<(String, Vec<String>)> {
+        let code = match read_code(info) {
+            Ok(code) => code,
+            Err(e) => return vec![(String::new(), vec![format!("malformed: {}", e), quote_bytes(info)])],
+        };
+        let header = format!("stack {} locals {}", code.max_stack, code.max_locals);
+
+        let instructions = match bytecode::decode(&code.code) {
+            Ok(instructions) => instructions,
+            Err(e) => return vec![(String::new(), vec![header, format!("undecodable: {}", e), quote_bytes(&code.code)])],
+        };
+
+        let cp = self.cp;
+        let mut frames = BTreeMap::new();
+        let mut line_numbers = Vec::new();
+        let mut local_variables = Vec::new();
+        let mut others = Vec::new();
+
+        for attribute in &code.attributes.array {
+            let name = attribute.name(cp).unwrap_or_else(|| format!("[{}]", attribute.attribute_name_index));
+            if self.options.ignore_debug && DEBUG_ATTRIBUTES.contains(&name.as_str()) {
+                continue;
+            }
+
+            // Those that can't be decoded are compared as bytes.
+            match name.as_str() {
+                "StackMapTable" if frames.is_empty() => match read_stack_map_table(&attribute.info) {
+                    Ok(table) => frames.extend(frame_offsets(&table).into_iter().zip(table)),
+                    Err(_) => others.push((name, attribute)),
+                },
+                "LineNumberTable" => match read_line_number_table(&attribute.info) {
+                    Ok(table) => line_numbers.extend(table),
+                    Err(_) => others.push((name, attribute)),
+                },
+                "LocalVariableTable" | "LocalVariableTypeTable" => match read_local_variable_table(&attribute.info) {
+                    Ok(table) => local_variables.push((name, table)),
+                    Err(_) => others.push((name, attribute)),
+                },
+                _ => others.push((name, attribute)),
+            }
+        }
+        line_numbers.sort_by_key(|line| line.start_pc);
+
+        // Labels for every offset referred to, numbered in order.
+        let mut offsets = BTreeSet::new();
+        for insn in &instructions {
+            offsets.extend(insn.branch_targets());
+        }
+        for entry in &code.exception_table {
+            offsets.extend(vec![entry.start_pc as u32, entry.end_pc as u32, entry.handler_pc as u32]);
+        }
+        for frame in frames.values() {
+            offsets.extend(frame_types(frame).into_iter().filter_map(|t| match *t {
+                VerificationType::Uninitialized(offset) => Some(offset as u32),
+                _ => None,
+            }));
+        }
+        for (_, table) in &local_variables {
+            offsets.extend(table.iter().flat_map(|v| vec![v.start_pc as u32, v.start_pc as u32 + v.length as u32]));
+        }
+        let labels: HashMap<u32, String> = offsets.into_iter().enumerate().map(|(i, offset)| (offset, format!("L{}", i))).collect();
+        let label = |offset: u32| labels.get(&offset).cloned().unwrap_or_else(|| format!("@{}", offset));
+
+        let mut lines = vec![header];
+
+        for entry in &code.exception_table {
+            let catch_type = if entry.catch_type == 0 { "any".to_string() } else { self.class_name(entry.catch_type) };
+            lines.push(format!(
+                "catch {} from {} to {} using {}",
+                catch_type,
+                label(entry.start_pc as u32),
+                label(entry.end_pc as u32),
+                label(entry.handler_pc as u32)
+            ));
+        }
+
+        let mut line_numbers = line_numbers.into_iter().peekable();
+        let mut frames = frames.into_iter().peekable();
+
+        for insn in &instructions {
+            if let Some(name) = labels.get(&insn.offset) {
+                lines.push(format!("{}:", name));
+            }
+            while let Some(line) = line_numbers.next_if(|line| line.start_pc as u32 <= insn.offset) {
+                lines.push(format!("    .line {}", line.line_number));
+            }
+            while let Some((_, frame)) = frames.next_if(|&(offset, _)| offset <= insn.offset) {
+                lines.push(format!("    .stack {}", self.frame(&frame, &label)));
+            }
+            lines.push(format!("    {}", self.instruction(insn, &label)));
+        }
+        if let Some(name) = labels.get(&(code.code.len() as u32)) {
+            lines.push(format!("{}:", name));
+        }
+
+        let mut parts = vec![(String::new(), lines)];
+
+        for (name, table) in local_variables {
+            let lines = table
+                .iter()
+                .map(|v| {
+                    format!(
+                        "{} {} {} from {} to {}",
+                        v.index,
+                        self.utf8(v.name_index),
+                        self.utf8(v.descriptor_index),
+                        label(v.start_pc as u32),
+                        label(v.start_pc as u32 + v.length as u32)
+                    )
+                })
+                .collect();
+            parts.push((name, lines));
+        }
+        for (name, attribute) in others {
+            parts.push((name, vec![quote_bytes(&attribute.info)]));
+        }
+
+        parts
+    }
+
+    fn instruction(&self, insn: &Instruction, label: &dyn Fn(u32) -> String) -> String {
+        let mut text = if insn.wide {
+            format!("wide {}", insn.mnemonic())
+        } else {
+            insn.mnemonic().to_string()
+        };
+
+        let operand = match insn.operand {
+            Operand::None => return text,
+            Operand::Local(index) => index.to_string(),
+            Operand::Int(value) => value.to_string(),
+            Operand::Constant(index) | Operand::InvokeDynamic(index) => self.constant(index),
+            Operand::Branch(target) => label(target),
+            Operand::Iinc { index, value } => format!("{} {}", index, value),
+            Operand::InvokeInterface { index, count } => format!("{} {}", self.constant(index), count),
+            Operand::MultiANewArray { index, dimensions } => format!("{} {}", self.class_name(index), dimensions),
+            Operand::NewArray(atype) => match bytecode::array_type_name(atype) {
+                Some(name) => name.to_string(),
+                None => atype.to_string(),
+            },
+            Operand::TableSwitch { default, low, ref targets } => {
+                let targets: Vec<String> = targets.iter().map(|&target| label(target)).collect();
+                format!("{} [{}] default {}", low, targets.join(", "), label(default))
+            }
+            Operand::LookupSwitch { default, ref pairs } => {
+                let pairs: Vec<String> = pairs.iter().map(|&(key, target)| format!("{}: {}", key, label(target))).collect();
+                format!("[{}] default {}", pairs.join(", "), label(default))
+            }
+        };
+
+        text.push(' ');
+        text.push_str(&operand);
+        text
+    }
+
+    fn frame(&self, frame: &StackMapFrame, label: &dyn Fn(u32) -> String) -> String {
+        let types = |types: &[VerificationType]| -> String {
+            types.iter().map(|t| self.verification_type(t, label)).collect::<Vec<_>>().join(" ")
+        };
+
+        match frame.kind {
+            FrameKind::Same => "same".to_string(),
+            FrameKind::SameLocals1StackItem(ref t) => format!("same_locals_1_stack_item {}", self.verification_type(t, label)),
+            FrameKind::Chop(n) => format!("chop {}", n),
+            FrameKind::Append(ref locals) => format!("append {}", types(locals)),
+            FrameKind::Full { ref locals, ref stack } => format!("full locals {} stack {}", types(locals), types(stack)),
+        }
+    }
+
+    fn verification_type(&self, t: &VerificationType, label: &dyn Fn(u32) -> String) -> String {
+        match *t {
+            VerificationType::Object(index) => format!("Object {}", self.class_name(index)),
+            VerificationType::Uninitialized(offset) => format!("Uninitialized {}", label(offset as u32)),
+            ref t => format!("{:?}", t),
+        }
+    }
+
+    fn annotation(&self, annotation: &Annotation) -> String {
+        let pairs: Vec<String> = annotation
+            .element_value_pairs
+            .iter()
+            .map(|pair| format!("{}={}", self.utf8(pair.element_name_index), self.element_value(&pair.value)))
+            .collect();
+
+        if pairs.is_empty() {
+            format!("@{}", self.utf8(annotation.type_index))
+        } else {
+            format!("@{}({})", self.utf8(annotation.type_index), pairs.join(", "))
+        }
+    }
+
+    fn element_value(&self, value: &ElementValue) -> String {
+        match *value {
+            ElementValue::Const { const_value_index, .. } => self.constant(const_value_index),
+            ElementValue::Enum { type_name_index, const_name_index } => {
+                format!("{}.{}", self.utf8(type_name_index), self.utf8(const_name_index))
+            }
+            ElementValue::Class { class_info_index } => format!("{}.class", self.utf8(class_info_index)),
+            ElementValue::Annotation(ref annotation) => self.annotation(annotation),
+            ElementValue::Array(ref values) => {
+                let values: Vec<String> = values.iter().map(|v| self.element_value(v)).collect();
+                format!("{{{}}}", values.join(", "))
+            }
+        }
+    }
+
+    fn constant(&self, index: u16) -> String {
+        self.constant_at_depth(index, 0)
+    }
+
+    // A constant by what it holds. Dynamically computed ones and call sites
+    // name their bootstrap method and its arguments rather than its index in
+    // the BootstrapMethods attribute. Arguments can be dynamically computed
+    // constants in turn, but not indefinitely in a valid class.
+    fn constant_at_depth(&self, index: u16, depth: usize) -> String {
+        let cp = self.cp;
+        let dynamic = match cp.tag(index) {
+            Some(tag @ CpTag::InvokeDynamic) => cp
+                .get_as::<InvokeDynamicInfo>(index)
+                .map(|info| (tag, info.bootstrap_method_attr_index, info.name_and_type_index)),
+            Some(tag @ CpTag::Dynamic) => cp
+                .get_as::<DynamicInfo>(index)
+                .map(|info| (tag, info.bootstrap_method_attr_index, info.name_and_type_index)),
+            _ => None,
+        };
+
+        let (tag, bootstrap_index, name_and_type_index) = match dynamic {
+            Some(dynamic) if depth < 8 => dynamic,
+            _ => return constant_spec(cp, index).unwrap_or_else(|| format!("[{}]", index)),
+        };
+
+        let (name, descriptor) = cp.name_and_type(name_and_type_index).unwrap_or_default();
+        let bootstrap = match self.bootstrap_methods.get(bootstrap_index as usize) {
+            Some(method) => {
+                let arguments: Vec<String> = method
+                    .bootstrap_arguments
+                    .iter()
+                    .map(|&argument| self.constant_at_depth(argument, depth + 1))
+                    .collect();
+                format!("{} ({})", self.constant_at_depth(method.bootstrap_method_ref, depth + 1), arguments.join(", "))
+            }
+            None => format!("[{}]", bootstrap_index),
+        };
+
+        format!("{} {} {} bootstrap {}", tag_name(tag), name, descriptor, bootstrap)
+    }
+
+    fn utf8(&self, index: u16) -> String {
+        self.cp.utf8(index).unwrap_or_else(|| format!("[{}]", index))
+    }
+
+    fn class_name(&self, index: u16) -> String {
+        self.cp.class_name(index).unwrap_or_else(|| format!("[{}]", index))
+    }
+
+    // For the optional classes of InnerClasses entries, where 0 is none.
+    fn optional_class_name(&self, index: u16) -> String {
+        if index == 0 {
+            "-".to_string()
+        } else {
+            self.class_name(index)
+        }
+    }
+}
+
+fn flags(flags: u16, context: FlagContext) -> String {
+    format_flags(flags, context).join(" ")
+}
+
+fn frame_types(frame: &StackMapFrame) -> Vec<&VerificationType> {
+    match frame.kind {
+        FrameKind::Same | FrameKind::Chop(_) => vec![],
+        FrameKind::SameLocals1StackItem(ref t) => vec![t],
+        FrameKind::Append(ref locals) => locals.iter().collect(),
+        FrameKind::Full { ref locals, ref stack } => locals.iter().chain(stack).collect(),
+    }
+}
+
+// A unified diff of two lists of lines, as hunks with a `@@ -a,b +c,d @@`
+// header and lines prefixed with `-`, `+` or a space for context.
+fn line_diff(old: &[String], new: &[String]) -> Vec<String> {
+    let mut ops = Vec::new();
+    let prefix = old.iter().zip(new).take_while(|&(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|&(a, b)| a == b).count();
+
+    ops.extend((0..prefix).map(|i| (' ', i, i)));
+    ops.extend(edit_script(&old[prefix..old.len() - suffix], &new[prefix..new.len() - suffix], prefix));
+    ops.extend((0..suffix).map(|i| (' ', old.len() - suffix + i, new.len() - suffix + i)));
+
+    // Hunks are the changed operations with the context around them, joined
+    // when their context overlaps.
+    let changed: Vec<usize> = ops.iter().enumerate().filter(|&(_, op)| op.0 != ' ').map(|(i, _)| i).collect();
+    let mut out = Vec::new();
+    let mut i = 0;
+
+    while i < changed.len() {
+        let start = changed[i].saturating_sub(CONTEXT);
+        let mut end = changed[i];
+        while i < changed.len() && changed[i] <= end + 2 * CONTEXT + 1 {
+            end = changed[i];
+            i += 1;
+        }
+        let end = (end + CONTEXT + 1).min(ops.len());
+        let hunk = &ops[start..end];
+
+        let old_count = hunk.iter().filter(|op| op.0 != '+').count();
+        let new_count = hunk.iter().filter(|op| op.0 != '-').count();
+        let old_start = hunk.iter().find(|op| op.0 != '+').map_or(hunk[0].1, |op| op.1 + 1);
+        let new_start = hunk.iter().find(|op| op.0 != '-').map_or(hunk[0].2, |op| op.2 + 1);
+        out.push(format!("@@ -{},{} +{},{} @@", old_start, old_count, new_start, new_count));
+
+        for &(kind, old_index, new_index) in hunk {
+            let line = if kind == '+' { &new[new_index] } else { &old[old_index] };
+            out.push(format!("{}{}", kind, line));
+        }
+    }
+
+    out
+}
+
+// The operations turning `old` into `new`, from a longest common
+// subsequence, as the kind of operation and the indices of the lines in
+// either, offset by `base`. Indices of lines only on one side are those of
+// the next line on the other.
+fn edit_script(old: &[String], new: &[String], base: usize) -> Vec<(char, usize, usize)> {
+    let (n, m) = (old.len(), new.len());
+    let mut ops = Vec::new();
+
+    if n * m > MAX_LCS_CELLS {
+        ops.extend((0..n).map(|i| ('-', base + i, base)));
+        ops.extend((0..m).map(|j| ('+', base + n, base + j)));
+        return ops;
+    }
+
+    // lengths[i][j] is the length of the longest common subsequence of
+    // old[i..] and new[j..].
+    let mut lengths = vec![vec![0u32; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && old[i] == new[j] {
+            ops.push((' ', base + i, base + j));
+            i += 1;
+            j += 1;
+        } else if j == m || (i < n && lengths[i + 1][j] >= lengths[i][j + 1]) {
+            ops.push(('-', base + i, base + j));
+            i += 1;
+        } else {
+            ops.push(('+', base + i, base + j));
+            j += 1;
+        }
+    }
+
+    ops
+}
@@ -0,0 +1,1271 @@
+use std::borrow::Borrow;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use class_file::access_flags::*;
+use class_file::annotations::*;
+use class_file::attributes::*;
+use class_file::constant_pool::*;
+use class_file::descriptor::*;
+use class_file::fields::FieldInfo;
+use class_file::methods::MethodInfo;
+use class_file::signature::*;
+use class_file::ClassFile;
+use bytecode;
+use bytecode::Operand;
+
+const INDENT: &str = "    ";
+const STUB_BODY: &str = "{ throw new UnsupportedOperationException(); }";
+
+/// Generates a compilable Java source skeleton for a class file, with method
+/// bodies replaced by `throw new UnsupportedOperationException()`. Fails if
+/// an attribute the skeleton needs can't be decoded.
+pub fn generate(cf: &ClassFile) -> Result<String, String> {
+    generate_with(cf, |_| None::<ClassFile>)
+}
+
+/// Like `generate`, but uses `load` to find the class files of member classes
+/// by binary name so they can be declared inside their enclosing class, and
+/// of supertypes, which decide whether a class is non-sealed. Classes can be
+/// loaded or borrowed from ones already read.
+pub fn generate_with<F, C>(cf: &ClassFile, load: F) -> Result<String, String>
+    where F: Fn(&str) -> Option<C>, C: Borrow<ClassFile> {
+    let mut loaded: Vec<C> = Vec::new();
+    let mut pending = member_classes(cf)?;
+
+    while let Some(name) = pending.pop() {
+        if let Some(member) = load(&name) {
+            pending.extend(member_classes(member.borrow()).map_err(|e| format!("{}: {}", name, e))?);
+            loaded.push(member);
+        }
+    }
+    let loaded: Vec<&ClassFile> = loaded.iter().map(Borrow::borrow).collect();
+
+    let mut classes = HashMap::new();
+    for &member in &loaded {
+        if let Some(name) = member.constant_pool.class_name(member.this_class) {
+            classes.insert(name, member);
+        }
+    }
+
+    // Whether a class must be declared non-sealed depends on the
+    // PermittedSubclasses of its direct supertypes.
+    let mut supertypes = Vec::new();
+    let mut tried = HashSet::new();
+    for &class in loaded.iter().chain(Some(&cf)) {
+        for name in direct_supertypes(class) {
+            if classes.contains_key(&name) || cf.name().as_ref() == Some(&name) || !tried.insert(name.clone()) {
+                continue;
+            }
+            if let Some(supertype) = load(&name).filter(|supertype| supertype.borrow().name().as_ref() == Some(&name)) {
+                supertypes.push(supertype);
+            }
+        }
+    }
+
+    let mut sealed_subclasses = HashSet::new();
+    for class in loaded.iter().cloned().chain(supertypes.iter().map(Borrow::borrow)).chain(Some(cf)) {
+        let name = class.name().unwrap_or_default();
+        sealed_subclasses.extend(permitted_subclasses(class).map_err(|e| format!("{}: {}", name, e))?);
+    }
+
+    StubWriter::new(cf, classes, sealed_subclasses)?.write()
+}
+
+fn direct_supertypes(cf: &ClassFile) -> Vec<String> {
+    let cp = &cf.constant_pool;
+
+    Some(cf.super_class)
+        .into_iter()
+        .chain(cf.interfaces.iter().cloned())
+        .filter_map(|i| cp.class_name(i))
+        .filter(|name| name != "java/lang/Object")
+        .collect()
+}
+
+fn permitted_subclasses(cf: &ClassFile) -> Result<Vec<String>, String> {
+    let cp = &cf.constant_pool;
+
+    Ok(cf
+        .attributes
+        .decode(cp, "PermittedSubclasses", read_index_table)?
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|&i| cp.class_name(i))
+        .collect())
+}
+
+/// The binary name of the class a member class is declared in, and so whose
+/// stub declares it too.
+pub fn declaring_class(cf: &ClassFile) -> Result<Option<String>, String> {
+    let cp = &cf.constant_pool;
+    let this_name = cp.class_name(cf.this_class);
+
+    Ok(inner_classes(cf)?
+        .iter()
+        .find(|inner| inner.inner_name_index != 0 && cp.class_name(inner.inner_class_info_index) == this_name)
+        .and_then(|inner| cp.class_name(inner.outer_class_info_index)))
+}
+
+// Binary names of the member classes declared directly in a class.
+fn member_classes(cf: &ClassFile) -> Result<Vec<String>, String> {
+    let cp = &cf.constant_pool;
+    let this_name = cp.class_name(cf.this_class);
+
+    Ok(inner_classes(cf)?
+        .iter()
+        .filter(|inner| inner.inner_name_index != 0 && cp.class_name(inner.outer_class_info_index) == this_name)
+        .filter_map(|inner| cp.class_name(inner.inner_class_info_index))
+        .collect())
+}
+
+fn inner_classes(cf: &ClassFile) -> Result<Vec<InnerClass>, String> {
+    Ok(cf.attributes.decode(&cf.constant_pool, "InnerClasses", read_inner_classes)?.unwrap_or_default())
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Class,
+    Interface,
+    Annotation,
+    Enum,
+    Record,
+}
+
+struct StubWriter<'a> {
+    // The class currently being written, which changes while member classes
+    // are written inside their enclosing class.
+    cf: &'a ClassFile,
+    cp: &'a ConstantPool,
+    this_name: String,
+    simple_name: String,
+    kind: Kind,
+
+    package: String,
+    members: HashMap<String, &'a ClassFile>,
+
+    // Member classes known from InnerClasses, keyed by binary name and mapped
+    // to their outer class, simple name and source-level access flags.
+    nested: HashMap<String, (String, String, u16)>,
+
+    // Simple names claimed by a fully qualified name, and the imports needed
+    // to use them.
+    claimed: HashMap<String, String>,
+    imports: BTreeSet<String>,
+
+    // Classes a sealed supertype permits, which have to be final, sealed or
+    // non-sealed.
+    sealed_subclasses: HashSet<String>,
+}
+
+impl<'a> StubWriter<'a> {
+    fn new(
+        cf: &'a ClassFile,
+        members: HashMap<String, &'a ClassFile>,
+        sealed_subclasses: HashSet<String>,
+    ) -> Result<StubWriter<'a>, String> {
+        let cp = &cf.constant_pool;
+        let this_name = cp.class_name(cf.this_class).unwrap_or_default();
+
+        let package = match this_name.rfind('/') {
+            Some(i) => this_name[..i].to_string(),
+            None => String::new(),
+        };
+
+        let mut nested = HashMap::new();
+        for class in members.values().cloned().chain(Some(cf)) {
+            let cp = &class.constant_pool;
+
+            for inner in inner_classes(class)? {
+                if inner.outer_class_info_index == 0 || inner.inner_name_index == 0 {
+                    continue;
+                }
+
+                if let (Some(inner_name), Some(outer_name), Some(simple)) = (
+                    cp.class_name(inner.inner_class_info_index),
+                    cp.class_name(inner.outer_class_info_index),
+                    cp.utf8(inner.inner_name_index),
+                ) {
+                    nested.insert(inner_name, (outer_name, simple, inner.inner_class_access_flags));
+                }
+            }
+        }
+
+        let mut writer = StubWriter {
+            cf,
+            cp,
+            this_name: String::new(),
+            simple_name: String::new(),
+            kind: Kind::Class,
+            package,
+            members,
+            nested,
+            claimed: HashMap::new(),
+            imports: BTreeSet::new(),
+            sealed_subclasses,
+        };
+
+        writer.enter(cf);
+        Ok(writer)
+    }
+
+    fn enter(&mut self, cf: &'a ClassFile) {
+        let cp = &cf.constant_pool;
+        let this_name = cp.class_name(cf.this_class).unwrap_or_default();
+
+        let simple_name = match self.nested.get(&this_name) {
+            Some((_, simple, _)) => simple.clone(),
+            None => match this_name.rfind('/') {
+                Some(i) => this_name[i + 1..].to_string(),
+                None => this_name.clone(),
+            },
+        };
+
+        self.kind = if cf.access_flags & ACC_ANNOTATION != 0 {
+            Kind::Annotation
+        } else if cf.access_flags & ACC_INTERFACE != 0 {
+            Kind::Interface
+        } else if cf.access_flags & ACC_ENUM != 0 {
+            Kind::Enum
+        } else if cf.attributes.find(cp, "Record").is_some() {
+            Kind::Record
+        } else {
+            Kind::Class
+        };
+
+        self.claimed
+            .entry(simple_name.clone())
+            .or_insert_with(|| this_name.clone());
+
+        self.cf = cf;
+        self.cp = cp;
+        self.this_name = this_name;
+        self.simple_name = simple_name;
+    }
+
+    fn write(mut self) -> Result<String, String> {
+        let body = if self.cf.access_flags & ACC_MODULE != 0 {
+            String::from("// Module descriptors have no Java class skeleton.\n")
+        } else if self.simple_name == "package-info" {
+            self.annotations(&self.cf.attributes, "")?
+        } else {
+            let mut body = String::new();
+            if let Some((outer, _, _)) = self.nested.get(&self.this_name) {
+                body.push_str(&format!("// Member of {}\n", outer.replace('/', ".")));
+            }
+            body.push_str(&self.class_declaration()?);
+            body
+        };
+
+        let mut out = String::new();
+
+        if !self.package.is_empty() {
+            out.push_str(&format!("package {};\n\n", self.package.replace('/', ".")));
+        }
+
+        if !self.imports.is_empty() {
+            for import in &self.imports {
+                out.push_str(&format!("import {};\n", import));
+            }
+            out.push('\n');
+        }
+
+        out.push_str(&body);
+        Ok(out)
+    }
+
+    fn member_class(&mut self, name: &str) -> Result<Option<String>, String> {
+        let member = match self.members.get(name) {
+            Some(&member) => member,
+            None => return Ok(None),
+        };
+        let enclosing = self.cf;
+
+        self.enter(member);
+        let declaration = self.class_declaration();
+        self.enter(enclosing);
+        let declaration = declaration?;
+
+        let mut out = String::new();
+        for line in declaration.lines() {
+            if !line.is_empty() {
+                out.push_str(INDENT);
+            }
+            out.push_str(line);
+            out.push('\n');
+        }
+
+        Ok(Some(out))
+    }
+
+    fn class_declaration(&mut self) -> Result<String, String> {
+        let cf = self.cf;
+        let cp = self.cp;
+        let mut out = self.annotations(&cf.attributes, "")?;
+
+        let signature = cf
+            .attributes
+            .decode(cp, "Signature", read_index)?
+            .and_then(|index| cp.utf8(index))
+            .and_then(|s| parse_class_signature(&s));
+
+        if let Some(ref signature) = signature {
+            for type_parameter in &signature.type_parameters {
+                self.claimed
+                    .insert(type_parameter.name.clone(), type_parameter.name.clone());
+            }
+        }
+
+        // Member classes carry their source-level modifiers in InnerClasses.
+        let mut flags = cf.access_flags;
+        for inner in inner_classes(cf)? {
+            if cp.class_name(inner.inner_class_info_index).as_ref() == Some(&self.this_name) {
+                flags = inner.inner_class_access_flags;
+            }
+        }
+
+        // Enums with constant bodies are implicitly sealed.
+        let permitted = match self.kind {
+            Kind::Enum => Vec::new(),
+            _ => permitted_subclasses(cf)?,
+        };
+
+        let mut modifiers = access_modifiers(flags);
+        if flags & ACC_STATIC != 0 && self.kind == Kind::Class {
+            modifiers.push("static");
+        }
+        if self.kind == Kind::Class && flags & ACC_ABSTRACT != 0 {
+            modifiers.push("abstract");
+        }
+        if self.kind == Kind::Class && flags & ACC_FINAL != 0 {
+            modifiers.push("final");
+        }
+        if !permitted.is_empty() {
+            modifiers.push("sealed");
+        } else if self.sealed_subclasses.contains(&self.this_name)
+            && flags & ACC_FINAL == 0
+            && (self.kind == Kind::Class || self.kind == Kind::Interface)
+        {
+            modifiers.push("non-sealed");
+        }
+
+        for modifier in modifiers {
+            out.push_str(modifier);
+            out.push(' ');
+        }
+
+        out.push_str(match self.kind {
+            Kind::Class => "class ",
+            Kind::Interface => "interface ",
+            Kind::Annotation => "@interface ",
+            Kind::Enum => "enum ",
+            Kind::Record => "record ",
+        });
+        out.push_str(&self.simple_name);
+
+        let (superclass, interfaces) = match signature {
+            Some(ref signature) => {
+                out.push_str(&self.type_parameters(&signature.type_parameters));
+
+                let superclass = (signature.superclass.binary_name(), signature.superclass.clone());
+                (superclass, signature.interfaces.clone())
+            }
+            None => {
+                let superclass = cp.class_name(cf.super_class).unwrap_or_default();
+                let interfaces = cf
+                    .interfaces
+                    .iter()
+                    .filter_map(|&i| cp.class_name(i))
+                    .map(|name| erased_class_type(&name))
+                    .collect();
+                ((superclass.clone(), erased_class_type(&superclass)), interfaces)
+            }
+        };
+
+        if self.kind == Kind::Record {
+            out.push_str(&self.record_header()?);
+        }
+
+        if self.kind == Kind::Class && !superclass.0.is_empty() && superclass.0 != "java/lang/Object" {
+            out.push_str(&format!(" extends {}", self.class_type(&superclass.1)));
+        }
+
+        if self.kind != Kind::Annotation && !interfaces.is_empty() {
+            let interfaces: Vec<String> = interfaces.iter().map(|i| self.class_type(i)).collect();
+            let keyword = if self.kind == Kind::Interface { "extends" } else { "implements" };
+            out.push_str(&format!(" {} {}", keyword, interfaces.join(", ")));
+        }
+
+        if !permitted.is_empty() {
+            let permitted: Vec<String> = permitted.iter().map(|p| self.class_name(p)).collect();
+            out.push_str(&format!(" permits {}", permitted.join(", ")));
+        }
+
+        out.push_str(" {\n");
+
+        let mut members: Vec<String> = Vec::new();
+
+        if self.kind == Kind::Enum {
+            let constants = cf
+                .fields
+                .array
+                .iter()
+                .filter(|f| f.access_flags & ACC_ENUM != 0)
+                .map(|f| {
+                    let annotations = self.annotations(&f.attributes, INDENT)?;
+                    Ok(format!("{}{}{}", annotations, INDENT, cp.utf8(f.name_index).unwrap_or_default()))
+                })
+                .collect::<Result<Vec<String>, String>>()?;
+            members.push(format!("{};\n", constants.join(",\n")));
+        }
+
+        for field in &cf.fields.array {
+            if let Some(field) = self.field(field)? {
+                members.push(field);
+            }
+        }
+
+        // Enum constructors are left out, but blank final fields still need
+        // one that cannot complete normally.
+        let blank_finals = cf
+            .fields
+            .array
+            .iter()
+            .any(|f| f.access_flags & (ACC_STATIC | ACC_FINAL) == ACC_FINAL);
+        if self.kind == Kind::Enum && blank_finals {
+            members.push(format!("{}{}() {}\n", INDENT, self.simple_name, STUB_BODY));
+        }
+
+        for method in &cf.methods.array {
+            if let Some(method) = self.method(method)? {
+                members.push(method);
+            }
+        }
+
+        for name in member_classes(cf)? {
+            if let Some(member) = self.member_class(&name)? {
+                members.push(member);
+            }
+        }
+
+        out.push_str(&members.join("\n"));
+        out.push_str("}\n");
+
+        Ok(out)
+    }
+
+    fn record_header(&mut self) -> Result<String, String> {
+        let cp = self.cp;
+        let components = self.cf.attributes.decode(cp, "Record", read_record)?.unwrap_or_default();
+
+        let mut rendered = Vec::new();
+        for component in &components {
+            let descriptor = cp.utf8(component.descriptor_index).unwrap_or_default();
+            let signature = component
+                .attributes
+                .decode(cp, "Signature", read_index)?
+                .and_then(|index| cp.utf8(index))
+                .and_then(|s| parse_field_signature(&s));
+
+            let annotations = self.annotations(&component.attributes, "")?.replace('\n', " ");
+            let component_type = match signature {
+                Some(ref signature) => self.type_signature(signature),
+                None => match parse_field_descriptor(&descriptor) {
+                    Some(ref t) => self.field_type(t),
+                    None => descriptor.clone(),
+                },
+            };
+
+            rendered.push(format!(
+                "{}{} {}",
+                annotations,
+                component_type,
+                cp.utf8(component.name_index).unwrap_or_default()
+            ));
+        }
+
+        Ok(format!("({})", rendered.join(", ")))
+    }
+
+    // The descriptor of the canonical constructor, which a record header
+    // declares implicitly. The Record attribute has been decoded already.
+    fn record_descriptor(&self) -> Option<String> {
+        let cp = self.cp;
+        let components = self.cf.attributes.decode(cp, "Record", read_record).ok()??;
+
+        let mut descriptor = String::from("(");
+        for component in &components {
+            descriptor.push_str(&cp.utf8(component.descriptor_index)?);
+        }
+        descriptor.push_str(")V");
+
+        Some(descriptor)
+    }
+
+    fn field(&mut self, field: &FieldInfo) -> Result<Option<String>, String> {
+        let cp = self.cp;
+        let flags = field.access_flags;
+
+        if flags & (ACC_SYNTHETIC | ACC_ENUM) != 0 {
+            return Ok(None);
+        }
+        if self.kind == Kind::Record && flags & ACC_STATIC == 0 {
+            return Ok(None);
+        }
+
+        let descriptor = cp.utf8(field.descriptor_index);
+        let (name, field_type) = match (cp.utf8(field.name_index), descriptor.and_then(|d| parse_field_descriptor(&d))) {
+            (Some(name), Some(field_type)) => (name, field_type),
+            _ => return Ok(None),
+        };
+
+        let mut out = self.annotations(&field.attributes, INDENT)?;
+        out.push_str(INDENT);
+
+        let interface_like = self.kind == Kind::Interface || self.kind == Kind::Annotation;
+        if !interface_like {
+            let mut modifiers = access_modifiers(flags);
+            for &(flag, modifier) in &[
+                (ACC_STATIC, "static"),
+                (ACC_FINAL, "final"),
+                (ACC_TRANSIENT, "transient"),
+                (ACC_VOLATILE, "volatile"),
+            ] {
+                if flags & flag != 0 {
+                    modifiers.push(modifier);
+                }
+            }
+
+            for modifier in modifiers {
+                out.push_str(modifier);
+                out.push(' ');
+            }
+        }
+
+        let signature = field
+            .attributes
+            .decode(cp, "Signature", read_index)?
+            .and_then(|index| cp.utf8(index))
+            .and_then(|s| parse_field_signature(&s));
+
+        let rendered_type = match signature {
+            Some(ref signature) => self.type_signature(signature),
+            None => self.field_type(&field_type),
+        };
+
+        out.push_str(&format!("{} {}", rendered_type, name));
+
+        let constant_value = field
+            .attributes
+            .decode(cp, "ConstantValue", read_index)?
+            .and_then(|index| constant_literal(cp, index, &field_type));
+
+        match constant_value {
+            Some(value) => out.push_str(&format!(" = {}", value)),
+            // Blank static finals would need a static initializer; give them a
+            // value that is not a compile-time constant so nothing gets inlined.
+            None if interface_like || flags & (ACC_STATIC | ACC_FINAL) == ACC_STATIC | ACC_FINAL => {
+                out.push_str(&format!(" = {}", non_constant_default(&field_type)));
+            }
+            None => {}
+        }
+
+        out.push_str(";\n");
+
+        Ok(Some(out))
+    }
+
+    fn method(&mut self, method: &MethodInfo) -> Result<Option<String>, String> {
+        let cp = self.cp;
+        let flags = method.access_flags;
+
+        let descriptor_string = cp.utf8(method.descriptor_index).unwrap_or_default();
+        let (name, descriptor) = match (cp.utf8(method.name_index), parse_method_descriptor(&descriptor_string)) {
+            (Some(name), Some(descriptor)) => (name, descriptor),
+            _ => return Ok(None),
+        };
+
+        if flags & (ACC_SYNTHETIC | ACC_BRIDGE) != 0 || name == "<clinit>" {
+            return Ok(None);
+        }
+
+        let is_constructor = name == "<init>";
+
+        match self.kind {
+            Kind::Enum if is_constructor => return Ok(None),
+            Kind::Enum if flags & ACC_STATIC != 0 && (name == "values" || name == "valueOf") => {
+                return Ok(None)
+            }
+            Kind::Record if is_constructor && self.record_descriptor() == Some(descriptor_string) => {
+                return Ok(None)
+            }
+            _ => {}
+        }
+
+        let code = method.attributes.decode(cp, "Code", read_code)?;
+        let signature = method
+            .attributes
+            .decode(cp, "Signature", read_index)?
+            .and_then(|index| cp.utf8(index))
+            .and_then(|s| parse_method_signature(&s));
+
+        if let Some(ref signature) = signature {
+            for type_parameter in &signature.type_parameters {
+                self.claimed
+                    .entry(type_parameter.name.clone())
+                    .or_insert_with(|| type_parameter.name.clone());
+            }
+        }
+
+        let mut out = self.annotations(&method.attributes, INDENT)?;
+        out.push_str(INDENT);
+
+        let interface_like = self.kind == Kind::Interface || self.kind == Kind::Annotation;
+        // Enum constants with bodies are compiled to separate classes, so
+        // abstract enum methods get a body to keep the constants valid.
+        let has_body = flags & ACC_NATIVE == 0 && (flags & ACC_ABSTRACT == 0 || self.kind == Kind::Enum);
+
+        let mut modifiers = Vec::new();
+        if interface_like {
+            if flags & ACC_PRIVATE != 0 {
+                modifiers.push("private");
+            }
+            if flags & ACC_STATIC != 0 {
+                modifiers.push("static");
+            } else if has_body && flags & ACC_PRIVATE == 0 {
+                modifiers.push("default");
+            }
+        } else {
+            modifiers = access_modifiers(flags);
+            if !has_body && flags & ACC_ABSTRACT != 0 {
+                modifiers.push("abstract");
+            }
+            for &(flag, modifier) in &[
+                (ACC_STATIC, "static"),
+                (ACC_FINAL, "final"),
+                (ACC_SYNCHRONIZED, "synchronized"),
+                (ACC_NATIVE, "native"),
+            ] {
+                if flags & flag != 0 {
+                    modifiers.push(modifier);
+                }
+            }
+
+            // strictfp is implicit, and deprecated, from Java 17 on.
+            if flags & ACC_STRICT != 0 && self.cf.major_version < 61 {
+                modifiers.push("strictfp");
+            }
+        }
+
+        for modifier in modifiers {
+            out.push_str(modifier);
+            out.push(' ');
+        }
+
+        if let Some(ref signature) = signature {
+            if !signature.type_parameters.is_empty() {
+                out.push_str(&self.type_parameters(&signature.type_parameters));
+                out.push(' ');
+            }
+        }
+
+        // Constructors of inner classes take the enclosing instance as a
+        // synthetic first parameter, which is not part of the source.
+        let mut parameter_types = descriptor.parameters.clone();
+        let mut skipped = 0;
+        if is_constructor && signature.is_none() && self.is_inner_class()? && !parameter_types.is_empty() {
+            parameter_types.remove(0);
+            skipped = 1;
+        }
+
+        let rendered_types: Vec<String> = match signature {
+            Some(ref signature) if signature.parameters.len() <= descriptor.parameters.len() => {
+                skipped = descriptor.parameters.len() - signature.parameters.len();
+                signature.parameters.iter().map(|p| self.type_signature(p)).collect()
+            }
+            _ => parameter_types.iter().map(|p| self.field_type(p)).collect(),
+        };
+
+        if is_constructor {
+            out.push_str(&self.simple_name);
+        } else {
+            let return_type = match signature {
+                Some(ref signature) => self.type_signature(&signature.result),
+                None => match descriptor.return_type {
+                    Some(ref t) => self.field_type(t),
+                    None => String::from("void"),
+                },
+            };
+            out.push_str(&format!("{} {}", return_type, name));
+        }
+
+        let names = self.parameter_names(method, code.as_ref(), &descriptor, skipped)?;
+        let parameter_annotations = self.parameter_annotations(method)?;
+
+        let mut parameters = Vec::new();
+        for (i, rendered_type) in rendered_types.iter().enumerate() {
+            let mut parameter = String::new();
+
+            // Parameter annotations may or may not count synthetic parameters.
+            let annotation_index = if parameter_annotations.len() == descriptor.parameters.len() {
+                i + skipped
+            } else {
+                i
+            };
+            if let Some(annotations) = parameter_annotations.get(annotation_index) {
+                for annotation in annotations {
+                    parameter.push_str(&self.annotation(annotation));
+                    parameter.push(' ');
+                }
+            }
+
+            let is_last = i + 1 == rendered_types.len();
+            if is_last && flags & ACC_VARARGS != 0 && rendered_type.ends_with("[]") {
+                parameter.push_str(&format!("{}...", &rendered_type[..rendered_type.len() - 2]));
+            } else {
+                parameter.push_str(rendered_type);
+            }
+
+            parameter.push(' ');
+            parameter.push_str(&names[i + skipped]);
+            parameters.push(parameter);
+        }
+
+        out.push_str(&format!("({})", parameters.join(", ")));
+
+        let throws: Vec<String> = match signature {
+            Some(ref signature) if !signature.throws.is_empty() => {
+                signature.throws.iter().map(|t| self.type_signature(t)).collect()
+            }
+            _ => method
+                .attributes
+                .decode(cp, "Exceptions", read_index_table)?
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|&i| cp.class_name(i))
+                .map(|name| self.class_name(&name))
+                .collect(),
+        };
+
+        if !throws.is_empty() {
+            out.push_str(&format!(" throws {}", throws.join(", ")));
+        }
+
+        if self.kind == Kind::Annotation {
+            if let Some(value) = method.attributes.decode(cp, "AnnotationDefault", |info| read_element_value(&mut &info[..]))? {
+                out.push_str(&format!(" default {}", self.element_value(&value)));
+            }
+        }
+
+        if has_body && is_constructor {
+            match code.and_then(|code| self.constructor_call(&code)) {
+                Some(call) => out.push_str(&format!(" {{ {} throw new UnsupportedOperationException(); }}\n", call)),
+                None => out.push_str(&format!(" {}\n", STUB_BODY)),
+            }
+        } else if has_body {
+            out.push(' ');
+            out.push_str(STUB_BODY);
+            out.push('\n');
+        } else {
+            out.push_str(";\n");
+        }
+
+        Ok(Some(out))
+    }
+
+    // Finds the explicit `super(...)` or `this(...)` call a constructor has to
+    // start with, passing typed default values so the right overload is picked.
+    fn constructor_call(&mut self, code: &CodeAttribute) -> Option<String> {
+        let cp = self.cp;
+        let super_name = cp.class_name(self.cf.super_class)?;
+
+        // Constructor calls virtually never follow a switch or anything else
+        // the decoder could choke on, so stop at the first undecodable byte.
+        let mut pc = 0;
+        while let Ok(instruction) = bytecode::decode_at(&code.code, pc) {
+            if let Operand::Constant(index) = instruction.operand {
+                if let Some((class, name, descriptor)) = cp.member_ref(index) {
+                    if instruction.opcode == 0xb7 && name == "<init>" && (class == super_name || class == self.this_name) {
+                        let mut parameters = parse_method_descriptor(&descriptor)?.parameters;
+
+                        // The enclosing instance of an inner superclass is
+                        // passed implicitly.
+                        let inner = match self.nested.get(&class) {
+                            Some(&(_, _, flags)) => flags & (ACC_STATIC | ACC_INTERFACE) == 0,
+                            None => false,
+                        };
+                        if inner && !parameters.is_empty() {
+                            parameters.remove(0);
+                        }
+
+                        if class == super_name && parameters.is_empty() {
+                            return None;
+                        }
+
+                        let arguments: Vec<String> = parameters.iter().map(|p| self.default_argument(p)).collect();
+                        let keyword = if class == self.this_name { "this" } else { "super" };
+
+                        return Some(format!("{}({});", keyword, arguments.join(", ")));
+                    }
+                }
+            }
+
+            pc += bytecode::length(&code.code, pc).ok()?;
+        }
+
+        None
+    }
+
+    fn default_argument(&mut self, field_type: &FieldType) -> String {
+        match *field_type {
+            FieldType::Boolean => String::from("false"),
+            FieldType::Char => String::from("'\\0'"),
+            FieldType::Long => String::from("0L"),
+            FieldType::Float => String::from("0.0f"),
+            FieldType::Double => String::from("0.0"),
+            // Object parameters are usually erased type variables, which a
+            // cast to Object would not satisfy.
+            FieldType::Object(ref name) if name == "java/lang/Object" => String::from("null"),
+            FieldType::Object(_) | FieldType::Array(_) => format!("({}) null", self.field_type(field_type)),
+            _ => format!("({}) 0", field_type.primitive_name().unwrap()),
+        }
+    }
+
+    fn is_inner_class(&self) -> Result<bool, String> {
+        let cp = self.cp;
+
+        Ok(inner_classes(self.cf)?.iter().any(|inner| {
+            cp.class_name(inner.inner_class_info_index).as_ref() == Some(&self.this_name)
+                && inner.outer_class_info_index != 0
+                && inner.inner_class_access_flags & (ACC_STATIC | ACC_INTERFACE) == 0
+        }))
+    }
+
+    // Picks parameter names from MethodParameters, then LocalVariableTable,
+    // falling back to argN. Names are indexed like the descriptor parameters.
+    fn parameter_names(
+        &self,
+        method: &MethodInfo,
+        code: Option<&CodeAttribute>,
+        descriptor: &MethodDescriptor,
+        skipped: usize,
+    ) -> Result<Vec<String>, String> {
+        let cp = self.cp;
+        let count = descriptor.parameters.len();
+        let mut names: Vec<Option<String>> = vec![None; count];
+
+        if let Some(parameters) = method.attributes.decode(cp, "MethodParameters", read_method_parameters)? {
+            let offset = count.saturating_sub(parameters.len());
+
+            for (i, parameter) in parameters.iter().enumerate() {
+                if parameter.name_index != 0 && i + offset < count {
+                    names[i + offset] = cp.utf8(parameter.name_index);
+                }
+            }
+        } else if let Some(code) = code {
+            if let Some(variables) = code.attributes.decode(cp, "LocalVariableTable", read_local_variable_table)? {
+                let mut slot = if method.access_flags & ACC_STATIC != 0 { 0 } else { 1 };
+                for (i, parameter) in descriptor.parameters.iter().enumerate() {
+                    names[i] = variables
+                        .iter()
+                        .find(|v| v.index == slot && v.start_pc == 0)
+                        .and_then(|v| cp.utf8(v.name_index));
+                    slot += parameter.slots();
+                }
+            }
+        }
+
+        let mut used = HashSet::new();
+        Ok(names
+            .into_iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let name = match name {
+                    Some(ref name) if is_identifier(name) && !used.contains(name) => name.clone(),
+                    _ => format!("arg{}", i.saturating_sub(skipped)),
+                };
+                used.insert(name.clone());
+                name
+            })
+            .collect())
+    }
+
+    fn parameter_annotations(&self, method: &MethodInfo) -> Result<Vec<Vec<Annotation>>, String> {
+        let cp = self.cp;
+        let mut merged: Vec<Vec<Annotation>> = Vec::new();
+
+        for name in &[
+            "RuntimeVisibleParameterAnnotations",
+            "RuntimeInvisibleParameterAnnotations",
+        ] {
+            if let Some(parameters) = method.attributes.decode(cp, name, |info| read_parameter_annotations(&mut &info[..]))? {
+                if merged.len() < parameters.len() {
+                    merged.resize(parameters.len(), Vec::new());
+                }
+                for (i, annotations) in parameters.into_iter().enumerate() {
+                    merged[i].extend(annotations);
+                }
+            }
+        }
+
+        Ok(merged)
+    }
+
+    fn annotations(&mut self, attributes: &Attributes, indent: &str) -> Result<String, String> {
+        let cp = self.cp;
+        let mut out = String::new();
+
+        for name in &["RuntimeVisibleAnnotations", "RuntimeInvisibleAnnotations"] {
+            for annotation in attributes.decode(cp, name, |info| read_annotations(&mut &info[..]))?.unwrap_or_default() {
+                out.push_str(indent);
+                out.push_str(&self.annotation(&annotation));
+                out.push('\n');
+            }
+        }
+
+        Ok(out)
+    }
+
+    fn annotation(&mut self, annotation: &Annotation) -> String {
+        let cp = self.cp;
+        let type_name = cp
+            .utf8(annotation.type_index)
+            .and_then(|d| parse_field_descriptor(&d))
+            .map(|t| self.field_type(&t))
+            .unwrap_or_default();
+
+        let mut out = format!("@{}", type_name);
+
+        let pairs = &annotation.element_value_pairs;
+        if pairs.len() == 1 && cp.utf8(pairs[0].element_name_index).as_deref() == Some("value") {
+            out.push_str(&format!("({})", self.element_value(&pairs[0].value)));
+        } else if !pairs.is_empty() {
+            let rendered: Vec<String> = pairs
+                .iter()
+                .map(|pair| {
+                    format!(
+                        "{} = {}",
+                        cp.utf8(pair.element_name_index).unwrap_or_default(),
+                        self.element_value(&pair.value)
+                    )
+                })
+                .collect();
+            out.push_str(&format!("({})", rendered.join(", ")));
+        }
+
+        out
+    }
+
+    fn element_value(&mut self, value: &ElementValue) -> String {
+        let cp = self.cp;
+
+        match *value {
+            ElementValue::Const { tag, const_value_index } => {
+                let field_type = match tag {
+                    b's' => FieldType::Object(String::from("java/lang/String")),
+                    _ => parse_field_descriptor(&(tag as char).to_string()).unwrap_or(FieldType::Int),
+                };
+                constant_literal(cp, const_value_index, &field_type).unwrap_or_default()
+            }
+            ElementValue::Enum {
+                type_name_index,
+                const_name_index,
+            } => {
+                let enum_type = cp
+                    .utf8(type_name_index)
+                    .and_then(|d| parse_field_descriptor(&d))
+                    .map(|t| self.field_type(&t))
+                    .unwrap_or_default();
+                format!("{}.{}", enum_type, cp.utf8(const_name_index).unwrap_or_default())
+            }
+            ElementValue::Class { class_info_index } => {
+                let descriptor = cp.utf8(class_info_index).unwrap_or_default();
+                let class_type = if descriptor == "V" {
+                    String::from("void")
+                } else {
+                    parse_field_descriptor(&descriptor)
+                        .map(|t| self.field_type(&t))
+                        .unwrap_or(descriptor)
+                };
+                format!("{}.class", class_type)
+            }
+            ElementValue::Annotation(ref annotation) => self.annotation(annotation),
+            ElementValue::Array(ref values) => {
+                let rendered: Vec<String> = values.iter().map(|v| self.element_value(v)).collect();
+                format!("{{{}}}", rendered.join(", "))
+            }
+        }
+    }
+
+    fn type_parameters(&mut self, type_parameters: &[TypeParameter]) -> String {
+        if type_parameters.is_empty() {
+            return String::new();
+        }
+
+        let rendered: Vec<String> = type_parameters
+            .iter()
+            .map(|p| {
+                let mut bounds = Vec::new();
+
+                if let Some(ref class_bound) = p.class_bound {
+                    let is_object = match *class_bound {
+                        TypeSignature::Class(ref c) => c.binary_name() == "java/lang/Object",
+                        _ => false,
+                    };
+                    if !is_object || !p.interface_bounds.is_empty() {
+                        bounds.push(self.type_signature(class_bound));
+                    }
+                }
+                for bound in &p.interface_bounds {
+                    bounds.push(self.type_signature(bound));
+                }
+
+                if bounds.is_empty() {
+                    p.name.clone()
+                } else {
+                    format!("{} extends {}", p.name, bounds.join(" & "))
+                }
+            })
+            .collect();
+
+        format!("<{}>", rendered.join(", "))
+    }
+
+    fn type_signature(&mut self, signature: &TypeSignature) -> String {
+        match *signature {
+            TypeSignature::Base('V') => String::from("void"),
+            TypeSignature::Base(c) => parse_field_descriptor(&c.to_string())
+                .and_then(|t| t.primitive_name())
+                .unwrap_or("?")
+                .to_string(),
+            TypeSignature::Class(ref class) => self.class_type(class),
+            TypeSignature::TypeVariable(ref name) => name.clone(),
+            TypeSignature::Array(ref component) => format!("{}[]", self.type_signature(component)),
+        }
+    }
+
+    fn class_type(&mut self, class: &ClassTypeSignature) -> String {
+        let generic_outer = class.segments[..class.segments.len() - 1]
+            .iter()
+            .any(|s| !s.type_arguments.is_empty());
+
+        if !generic_outer {
+            let last = &class.segments[class.segments.len() - 1];
+            return format!("{}{}", self.class_name(&class.binary_name()), self.type_arguments(&last.type_arguments));
+        }
+
+        let first = &class.segments[0];
+        let mut out = format!(
+            "{}{}",
+            self.class_name(&format!("{}{}", class.package, first.name)),
+            self.type_arguments(&first.type_arguments)
+        );
+        for segment in &class.segments[1..] {
+            out.push_str(&format!(".{}{}", segment.name, self.type_arguments(&segment.type_arguments)));
+        }
+
+        out
+    }
+
+    fn type_arguments(&mut self, type_arguments: &[TypeArgument]) -> String {
+        if type_arguments.is_empty() {
+            return String::new();
+        }
+
+        let rendered: Vec<String> = type_arguments
+            .iter()
+            .map(|a| match *a {
+                TypeArgument::Any => String::from("?"),
+                TypeArgument::Extends(ref t) => format!("? extends {}", self.type_signature(t)),
+                TypeArgument::Super(ref t) => format!("? super {}", self.type_signature(t)),
+                TypeArgument::Exact(ref t) => self.type_signature(t),
+            })
+            .collect();
+
+        format!("<{}>", rendered.join(", "))
+    }
+
+    fn field_type(&mut self, field_type: &FieldType) -> String {
+        match *field_type {
+            FieldType::Object(ref name) => self.class_name(name),
+            FieldType::Array(ref component) => format!("{}[]", self.field_type(component)),
+            _ => field_type.primitive_name().unwrap().to_string(),
+        }
+    }
+
+    // Renders a binary class name as a source-level reference, importing it if
+    // its simple name is still free and qualifying it fully otherwise.
+    fn class_name(&mut self, name: &str) -> String {
+        if name == self.this_name {
+            return self.simple_name.clone();
+        }
+
+        if let Some((outer, simple, _)) = self.nested.get(name).cloned() {
+            return format!("{}.{}", self.class_name(&outer), simple);
+        }
+
+        let (package, simple) = match name.rfind('/') {
+            Some(i) => (&name[..i], &name[i + 1..]),
+            None => ("", name),
+        };
+
+        let qualified = name.replace('/', ".");
+
+        match self.claimed.get(simple) {
+            Some(owner) if owner == name => return simple.to_string(),
+            Some(_) => return qualified,
+            None => {}
+        }
+
+        self.claimed.insert(simple.to_string(), name.to_string());
+        if !package.is_empty() && package != self.package && package != "java/lang" {
+            self.imports.insert(qualified);
+        }
+
+        simple.to_string()
+    }
+}
+
+fn erased_class_type(name: &str) -> ClassTypeSignature {
+    let (package, simple) = match name.rfind('/') {
+        Some(i) => (&name[..i + 1], &name[i + 1..]),
+        None => ("", name),
+    };
+
+    ClassTypeSignature {
+        package: package.to_string(),
+        segments: vec![SimpleClassTypeSignature {
+            name: simple.to_string(),
+            type_arguments: Vec::new(),
+        }],
+    }
+}
+
+fn access_modifiers(flags: u16) -> Vec<&'static str> {
+    if flags & ACC_PUBLIC != 0 {
+        vec!["public"]
+    } else if flags & ACC_PROTECTED != 0 {
+        vec!["protected"]
+    } else if flags & ACC_PRIVATE != 0 {
+        vec!["private"]
+    } else {
+        vec![]
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    match chars.next() {
+        Some(c) if c.is_alphabetic() || c == '_' || c == '$' => {}
+        _ => return false,
+    }
+
+    chars.all(|c| c.is_alphanumeric() || c == '_' || c == '$') && name != "_"
+}
+
+fn non_constant_default(field_type: &FieldType) -> &'static str {
+    match *field_type {
+        FieldType::Boolean => "Boolean.valueOf(false)",
+        FieldType::Byte => "Byte.valueOf((byte) 0)",
+        FieldType::Char => "Character.valueOf('\\0')",
+        FieldType::Short => "Short.valueOf((short) 0)",
+        FieldType::Int => "Integer.valueOf(0)",
+        FieldType::Long => "Long.valueOf(0L)",
+        FieldType::Float => "Float.valueOf(0.0f)",
+        FieldType::Double => "Double.valueOf(0.0)",
+        _ => "null",
+    }
+}
+
+/// Renders a loadable constant as a Java literal of the given type.
+pub fn constant_literal(cp: &ConstantPool, index: u16, field_type: &FieldType) -> Option<String> {
+    let info = cp.get(index)?.as_any();
+
+    if let Some(info) = info.downcast_ref::<IntegerInfo>() {
+        let value = info.bytes as i32;
+
+        return Some(match *field_type {
+            FieldType::Boolean => (value != 0).to_string(),
+            FieldType::Char => char_literal(value as u16),
+            _ => value.to_string(),
+        });
+    }
+
+    if let Some(info) = info.downcast_ref::<FloatInfo>() {
+        return Some(float_literal(f32::from_bits(info.bytes)));
+    }
+
+    if let Some(info) = info.downcast_ref::<LongInfo>() {
+        let value = ((info.high_bytes as u64) << 32 | info.low_bytes as u64) as i64;
+        return Some(format!("{}L", value));
+    }
+
+    if let Some(info) = info.downcast_ref::<DoubleInfo>() {
+        let value = f64::from_bits((info.high_bytes as u64) << 32 | info.low_bytes as u64);
+        return Some(double_literal(value));
+    }
+
+    if let Some(info) = info.downcast_ref::<StringInfo>() {
+        return Some(string_literal(&cp.get_as::<Utf8Info>(info.string_index)?.units()));
+    }
+
+    if let Some(info) = info.downcast_ref::<Utf8Info>() {
+        return Some(string_literal(&info.units()));
+    }
+
+    None
+}
+
+pub fn float_literal(value: f32) -> String {
+    if value.is_nan() {
+        String::from("0.0f / 0.0f")
+    } else if value.is_infinite() {
+        String::from(if value > 0.0 { "1.0f / 0.0f" } else { "-1.0f / 0.0f" })
+    } else {
+        format!("{:?}f", value)
+    }
+}
+
+pub fn double_literal(value: f64) -> String {
+    if value.is_nan() {
+        String::from("0.0 / 0.0")
+    } else if value.is_infinite() {
+        String::from(if value > 0.0 { "1.0 / 0.0" } else { "-1.0 / 0.0" })
+    } else {
+        format!("{:?}", value)
+    }
+}
+
+pub fn string_literal(units: &[u16]) -> String {
+    let mut out = String::from("\"");
+
+    for &unit in units {
+        match unit {
+            0x22 => out.push_str("\\\""),
+            _ => out.push_str(&escape_char(unit)),
+        }
+    }
+
+    out.push('"');
+    out
+}
+
+fn char_literal(unit: u16) -> String {
+    match unit {
+        0x27 => String::from("'\\''"),
+        _ => format!("'{}'", escape_char(unit)),
+    }
+}
+
+fn escape_char(unit: u16) -> String {
+    match unit {
+        0x08 => String::from("\\b"),
+        0x09 => String::from("\\t"),
+        0x0a => String::from("\\n"),
+        0x0c => String::from("\\f"),
+        0x0d => String::from("\\r"),
+        0x5c => String::from("\\\\"),
+        0x20..=0x7e => (unit as u8 as char).to_string(),
+        _ => format!("\\u{:04x}", unit),
+    }
+}
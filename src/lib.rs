@@ -0,0 +1,20 @@
+#[macro_use]
+extern crate erased_serde;
+extern crate serde;
+extern crate zip;
+extern crate flate2;
+extern crate rayon;
+extern crate schemars;
+
+mod read_util;
+mod write_util;
+mod serialization;
+pub mod schema;
+pub mod class_file;
+pub mod bytecode;
+pub mod java_stub;
+pub mod assembly;
+pub mod archive;
+pub mod classpath;
+pub mod analysis;
+pub mod diff;
@@ -1,240 +1,1075 @@
-#![allow(dead_code, unused_imports)]
-
 #[macro_use]
 extern crate clap;
-#[macro_use]
-extern crate erased_serde;
+extern crate class2json;
 extern crate serde;
+extern crate serde_json;
 
-use std::fs::File;
-use std::io::Read;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 
 use clap::App;
 
-use class_file::attributes::*;
-use class_file::ClassFile;
-use class_file::constant_pool::*;
-use class_file::fields::*;
-use class_file::methods::*;
-use read_util::*;
+use serde::Serialize;
 
-mod read_util;
-mod class_file;
-mod serialization;
-mod bytecode;
+use class2json::archive::{self, EntryContent};
+use class2json::archive::jimage::Image;
+use class2json::assembly;
+use class2json::assembly::syntax::{format_flags, FlagContext};
+use class2json::analysis::callgraph::CallGraphBuilder;
+use class2json::analysis::compat::{self, ApiSnapshot};
+use class2json::analysis::deps::DependencyAnalysis;
+use class2json::analysis::features::FeatureAnalysis;
+use class2json::analysis::hierarchy::TypeHierarchy;
+use class2json::analysis::unused::UnusedAnalysis;
+use class2json::classpath::{self, ClassPath, ScanOptions, ScannedEntry, Summary};
+use class2json::bytecode::cfg::ControlFlowGraph;
+use class2json::bytecode::frames::FrameAnalysis;
+use class2json::bytecode::hierarchy::{ClassHierarchy, LoadingHierarchy};
+use class2json::bytecode::verifier::{self, VerifyError};
+use class2json::class_file::attributes::{malformed, read_code, CodeAttribute};
+use class2json::class_file::check::{self, Diagnostic};
+use class2json::class_file::methods::MethodInfo;
+use class2json::class_file::ClassFile;
+use class2json::class_file::reader::read_classfile;
+use class2json::class_file::version::JavaRelease;
+use class2json::class_file::writer::WriteOptions;
+use class2json::diff::{self, DiffOptions};
+use class2json::java_stub;
+use class2json::schema::{self, Document};
 
 fn main() {
     let yaml = load_yaml!("cli.yml");
     let matches = App::from_yaml(yaml).get_matches();
 
-    let mut f = File::open(matches.value_of("CLASS_FILE").unwrap()).expect("Class file not found");
-    let cf = read_classfile(&mut f);
+    if matches.is_present("schema") {
+        println!("{}", serde_json::to_string_pretty(&schema::schema()).unwrap());
+        return;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("compat") {
+        check_compatibility(matches);
+        return;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("diff") {
+        print_diff(matches);
+        return;
+    }
+
+    if ["hierarchy", "subtypes", "call-graph", "deps", "unused", "features", "check", "verify"].iter().any(|option| matches.is_present(option)) {
+        if matches.is_present("classpath") && matches.is_present("CLASS_FILE") {
+            eprintln!("Cross-class views, --check and --verify are of either CLASS_FILE or --classpath, not both");
+            std::process::exit(1);
+        }
+
+        if matches.is_present("call-graph") {
+            print_call_graph(&matches);
+        } else if matches.is_present("deps") {
+            print_dependencies(&matches);
+        } else if matches.is_present("unused") {
+            print_unused(&matches);
+        } else if matches.is_present("features") {
+            print_features(&matches);
+        } else if matches.is_present("check") || matches.is_present("verify") {
+            check_classes(&matches);
+        } else {
+            print_hierarchy(&matches);
+        }
+        return;
+    }
+
+    if let (Some(classpath), None) = (matches.value_of("classpath"), matches.value_of("CLASS_FILE")) {
+        dump_classpath(&classpath::split_classpath(classpath), true, &matches);
+        return;
+    }
+
+    let path = Path::new(matches.value_of("CLASS_FILE").unwrap());
+    let mut image = None;
+
+    if matches.is_present("output-dir") {
+        if ["classpath", "image", "assemble"].iter().any(|option| matches.is_present(option)) {
+            eprintln!("--output-dir is only supported for archives, directories, class files and whole class paths");
+            std::process::exit(1);
+        }
+
+        dump_classpath(&[path.to_path_buf()], false, &matches);
+        return;
+    }
+    let mut class_path = None;
+
+    let cf = if matches.is_present("assemble") {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        };
+
+        // The class path and image only provide the classes --compute-frames
+        // merges.
+        if let Some(roots) = matches.value_of("classpath") {
+            class_path = Some(RefCell::new(ClassPath::new(&classpath::split_classpath(roots), release(&matches))));
+        }
+        if let Some(image_path) = matches.value_of("image") {
+            image = Some(RefCell::new(open_image(Path::new(image_path))));
+        }
+
+        match assembly::assemble::assemble(&source) {
+            Ok(cf) => cf,
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        }
+    } else if let Some(roots) = matches.value_of("classpath") {
+        if matches.is_present("image") {
+            eprintln!("A class is looked up either on --classpath or in --image, not both");
+            std::process::exit(1);
+        }
+
+        let mut opened = ClassPath::new(&classpath::split_classpath(roots), release(&matches));
+
+        let cf = match opened.lookup(path.to_str().unwrap()).map(|found| found.entry.content) {
+            Some(EntryContent::Class(cf)) => cf,
+            Some(EntryContent::Error(e)) => {
+                eprintln!("{}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+            _ => {
+                eprintln!("{} not found on the class path", path.display());
+                std::process::exit(1);
+            }
+        };
+
+        class_path = Some(RefCell::new(opened));
+        cf
+    } else if let Some(image_path) = matches.value_of("image") {
+        let mut opened = open_image(Path::new(image_path));
+        let name = path.to_str().unwrap().trim_start_matches('/').trim_end_matches(".class");
+
+        let cf = match opened.class(name) {
+            Ok(cf) => cf,
+            Err(e) => {
+                eprintln!("{}: {}", image_path, e);
+                std::process::exit(1);
+            }
+        };
 
-    if matches.is_present("pretty") {
-        println!("{}", cf.to_pretty_json());
+        image = Some(RefCell::new(opened));
+        cf
+    } else if path.is_dir() {
+        dump_classpath(&[path.to_path_buf()], false, &matches);
+        return;
     } else {
-        println!("{}", cf.to_json());
+        let mut f = File::open(path).expect("Class file not found");
+        let mut header = [0u8; 4];
+        let header_len = f.read(&mut header).expect("could not read input file");
+        f.seek(SeekFrom::Start(0)).expect("could not read input file");
+
+        if archive::is_archive(&header[..header_len]) {
+            dump_classpath(&[path.to_path_buf()], false, &matches);
+            return;
+        }
+
+        match read_classfile(&mut f) {
+            Ok(cf) => cf,
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        }
+    };
+
+    let cf = if matches.is_present("canonical") {
+        match cf.to_canonical() {
+            Ok(cf) => cf,
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        cf
+    };
+
+    if let Some(output) = matches.value_of("output") {
+        // Platform classes come first, as the bootstrap loader finds them.
+        let modules = image.as_ref().map(|image| class_modules(&image.borrow()));
+        let hierarchy = LoadingHierarchy::new(|name: &str| {
+            if let (Some(image), Some(modules)) = (&image, &modules) {
+                if let Some(module) = modules.get(name) {
+                    return image.borrow_mut().class(&format!("{}/{}", module, name)).ok();
+                }
+            }
+            class_path.as_ref()?.borrow_mut().class(name).ok()
+        });
+
+        let options = WriteOptions {
+            compute_frames: if matches.is_present("compute-frames") {
+                Some(&hierarchy)
+            } else {
+                None
+            },
+        };
+
+        let bytes = match cf.to_bytes_with(&options) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        };
+
+        // As with ASM, the frames need every class they merge, but rather
+        // than failing, missing classes merge to java/lang/Object.
+        let missing = hierarchy.missing();
+        if !missing.is_empty() {
+            eprintln!(
+                "warning: {} classes were not found on --classpath or in --image, so frames merging them use java/lang/Object and may not verify: {}",
+                missing.len(),
+                missing.join(", ")
+            );
+        }
+
+        let mut out = File::create(output).expect("could not create output file");
+        out.write_all(&bytes).expect("could not write output file");
+        return;
+    }
+
+    let format = matches.value_of("format").unwrap();
+
+    if let Some(selector) = matches.value_of("cfg") {
+        print_cfg(&cf, selector, format, matches.is_present("pretty"));
+        return;
+    }
+
+    if let Some(selector) = matches.value_of("frames") {
+        print_frames(&cf, selector, matches.is_present("pretty"));
+        return;
+    }
+
+    match format {
+        "asm" => print!("{}", cf.to_assembly()),
+        "java" => {
+            let stub = java_stub::generate_with(&cf, |name| match (&image, &class_path) {
+                // Member classes are in the same module.
+                (Some(image), _) => {
+                    let module = path.iter().find(|c| !c.is_empty() && *c != "/")?.to_str()?;
+                    image.borrow_mut().class(&format!("{}/{}", module, name)).ok()
+                }
+                (_, Some(class_path)) => class_path.borrow_mut().class(name).ok(),
+                _ => read_sibling_class(path, name),
+            });
+
+            match stub {
+                Ok(stub) => print!("{}", stub),
+                Err(e) => {
+                    eprintln!("{}: {}", path.display(), e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        "dot" => {
+            eprintln!("DOT output is only available with --cfg, --hierarchy and --call-graph");
+            std::process::exit(1);
+        }
+        "csv" => {
+            eprintln!("CSV output is only available with --call-graph");
+            std::process::exit(1);
+        }
+        _ if matches.is_present("pretty") => println!("{}", cf.to_pretty_json()),
+        _ => println!("{}", cf.to_json()),
     }
 }
 
-fn read_classfile(f: &mut Read) -> ClassFile {
-    let mut cf: ClassFile = ClassFile {
+fn open_image(path: &Path) -> Image<File> {
+    let f = File::open(path).expect("Image file not found");
+
+    match Image::new(f) {
+        Ok(image) => image,
+        Err(e) => {
+            eprintln!("{}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Member classes are compiled to separate files next to their outer class.
+fn read_sibling_class(path: &Path, name: &str) -> Option<ClassFile> {
+    let file_name = format!("{}.class", name.rsplit('/').next().unwrap());
+    let mut f = File::open(path.with_file_name(file_name)).ok()?;
+
+    read_classfile(&mut f).ok()
+}
+
+// Prints one JSON object per class, and other entries as requested, from
+// directories, archives and class files, or with --output-dir writes them to
+// files. Entries that can't be read are reported and skipped, and the exit
+// status is 1 if there were any.
+fn dump_classpath(roots: &[PathBuf], show_source: bool, matches: &clap::ArgMatches) {
+    for option in &["output", "cfg", "frames"] {
+        if matches.is_present(option) {
+            eprintln!("--{} is not supported for archives, directories and class paths", option);
+            std::process::exit(1);
+        }
+    }
+
+    let format = matches.value_of("format").unwrap();
+    let mut mirror = matches.value_of("output-dir").map(|dir| Mirror::new(Path::new(dir), format));
+
+    if format != "json" && (mirror.is_none() || !["java", "asm"].contains(&format)) {
+        eprintln!("Archives, directories and class paths can only be dumped as JSON, or with --output-dir as Java or assembly");
+        std::process::exit(1);
+    }
+
+    let options = scan_options(matches);
+    let pretty = matches.is_present("pretty");
+    let canonical = matches.is_present("canonical");
+    let mut failed_writes = 0;
+    let mut failed_canonicalizations = 0;
+
+    let summary = classpath::scan(roots, &options, |mut scanned| {
+        if canonical && !canonicalize_entry(&mut scanned.entry.content) {
+            failed_canonicalizations += 1;
+        }
+        report_error(&scanned);
+
+        if let Some(ref mut mirror) = mirror {
+            let location = scanned.location();
+            if let Err(e) = mirror.write(scanned, show_source, pretty) {
+                eprintln!("{}: {}", location, e);
+                failed_writes += 1;
+            }
+        } else if show_source {
+            print_json(&scanned, pretty);
+        } else {
+            print_json(&scanned.entry, pretty);
+        }
+    });
+
+    if let Some(mut mirror) = mirror {
+        for (path, e) in mirror.write_stubs() {
+            eprintln!("{}: {}", path, e);
+            failed_writes += 1;
+        }
+        if let Err(e) = mirror.write_index(pretty) {
+            eprintln!("{}: {}", mirror.dir.join(INDEX_FILE).display(), e);
+            failed_writes += 1;
+        }
+    }
+
+    report_summary(&summary);
+
+    if summary.failed > 0 || failed_writes > 0 || failed_canonicalizations > 0 {
+        std::process::exit(1);
+    }
+}
+
+// Replaces a class with its canonical form, or with an error if it has none.
+// Returns false in that case.
+fn canonicalize_entry(content: &mut EntryContent) -> bool {
+    let canonical = match *content {
+        EntryContent::Class(ref cf) => cf.to_canonical(),
+        _ => return true,
+    };
+
+    match canonical {
+        Ok(cf) => {
+            *content = EntryContent::Class(cf);
+            true
+        }
+        Err(e) => {
+            *content = EntryContent::Error(e);
+            false
+        }
+    }
+}
+
+fn scan_options(matches: &clap::ArgMatches) -> ScanOptions {
+    let mut options = ScanOptions {
+        resources: matches.is_present("list-resources"),
+        release: release(matches),
         ..Default::default()
     };
 
-    cf.magic = read_u32(f);
-    cf.minor_version = read_u16(f);
-    cf.major_version = read_u16(f);
-    cf.constant_pool_count = read_u16(f);
-    cf.constant_pool = read_constant_pool(f, cf.constant_pool_count);
-    cf.access_flags = read_u16(f);
-    cf.this_class = read_u16(f);
-    cf.super_class = read_u16(f);
-    cf.interfaces_count = read_u16(f);
-    cf.interfaces = read_vec_u16(f, cf.interfaces_count as usize);
-    cf.fields_count = read_u16(f);
-    cf.fields = read_fields(f, cf.fields_count);
-    cf.methods_count = read_u16(f);
-    cf.methods = read_methods(f, cf.methods_count);
-    cf.attributes_count = read_u16(f);
-    cf.attributes = read_attributes(f, cf.attributes_count);
-
-    cf
-}
-
-fn read_constant_pool(f: &mut Read, constant_pool_count: u16) -> ConstantPool {
-    let mut constant_pool: Vec<Box<CpInfo>> = Vec::new();
-    let mut constant_pool_remaining = constant_pool_count - 1;
+    for package in matches.values_of("package").into_iter().flatten() {
+        options.filter.package(package);
+    }
+    for glob in matches.values_of("glob").into_iter().flatten() {
+        options.filter.glob(glob);
+    }
+
+    options
+}
+
+fn release(matches: &clap::ArgMatches) -> Option<u32> {
+    matches.value_of("release").map(|release| match release.parse() {
+        Ok(release) => release,
+        Err(_) => {
+            eprintln!("Invalid release '{}'", release);
+            std::process::exit(1);
+        }
+    })
+}
+
+fn report_error(scanned: &ScannedEntry) {
+    if let EntryContent::Error(ref e) = scanned.entry.content {
+        eprintln!("{}: {}", scanned.location(), e);
+    }
+}
+
+fn report_summary(summary: &Summary) {
+    eprintln!(
+        "Parsed {} classes from {} {}, {} failed",
+        summary.classes,
+        summary.roots,
+        if summary.roots == 1 { "root" } else { "roots" },
+        summary.failed
+    );
+}
+
+// The roots of the input of the cross-class views: CLASS_FILE or --classpath.
+fn input_roots(matches: &clap::ArgMatches) -> Vec<PathBuf> {
+    match matches.value_of("classpath") {
+        Some(classpath) => classpath::split_classpath(classpath),
+        None => vec![PathBuf::from(matches.value_of("CLASS_FILE").unwrap())],
+    }
+}
+
+// Passes each class of the input to `each`, with where it was found,
+// reporting those that can't be read.
+fn scan_input<F: FnMut(&ScannedEntry, &ClassFile)>(matches: &clap::ArgMatches, mut each: F) {
+    let summary = classpath::scan(&input_roots(matches), &scan_options(matches), |scanned| {
+        report_error(&scanned);
+
+        if let EntryContent::Class(ref cf) = scanned.entry.content {
+            each(&scanned, cf);
+        }
+    });
+
+    report_summary(&summary);
+}
+
+// The classes of the input, and the types they extend that the class path
+// or --image have.
+fn read_hierarchy(matches: &clap::ArgMatches) -> TypeHierarchy {
+    let mut hierarchy = TypeHierarchy::new();
+    scan_input(matches, |_, cf| {
+        hierarchy.add_class(cf);
+    });
+
+    // Archives can be followed by those in their manifest's Class-Path.
+    let mut class_path = ClassPath::new(&input_roots(matches), release(matches));
+    hierarchy.complete(|name| class_path.class(name).ok());
+
+    if let Some(image_path) = matches.value_of("image") {
+        let mut image = open_image(Path::new(image_path));
+        let modules = class_modules(&image);
+
+        hierarchy.complete(|name| image.class(&format!("{}/{}", modules.get(name)?, name)).ok());
+    }
+
+    hierarchy
+}
+
+fn print_call_graph(matches: &clap::ArgMatches) {
+    let mut builder = CallGraphBuilder::new();
+    scan_input(matches, |_, cf| builder.add_class(cf));
+
+    let graph = builder.build();
+    for error in &graph.errors {
+        eprintln!("{}", error);
+    }
+
+    match matches.value_of("format").unwrap() {
+        "dot" => print!("{}", graph.to_dot()),
+        "csv" => print!("{}", graph.to_csv()),
+        "json" => print_json(&graph, matches.is_present("pretty")),
+        _ => {
+            eprintln!("The call graph can only be printed as JSON, DOT or CSV");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn print_dependencies(matches: &clap::ArgMatches) {
+    let mut analysis = DependencyAnalysis::new();
+
+    scan_input(matches, |scanned, cf| {
+        // Classes of nested archives are in those archives.
+        let container = match scanned.location().rsplit_once("!/") {
+            Some((archive, _)) => archive.to_string(),
+            None => scanned.source.clone(),
+        };
+
+        analysis.add_class(cf, &container);
+    });
+
+    let platform = match matches.value_of("image") {
+        Some(image_path) => class_modules(&open_image(Path::new(image_path))),
+        None => HashMap::new(),
+    };
+
+    print_json(&analysis.report(&platform), matches.is_present("pretty"));
+}
+
+fn print_unused(matches: &clap::ArgMatches) {
+    let mut analysis = UnusedAnalysis::new();
+    scan_input(matches, |_, cf| analysis.add_class(cf));
+
+    let report = analysis.report();
+    for error in &report.errors {
+        eprintln!("{}", error);
+    }
+
+    print_json(&report, matches.is_present("pretty"));
+}
+
+fn print_features(matches: &clap::ArgMatches) {
+    let mut analysis = FeatureAnalysis::new();
+    scan_input(matches, |scanned, cf| analysis.add_class(cf, &scanned.entry.path, scanned.entry.release));
+
+    print_json(&analysis.report(), matches.is_present("pretty"));
+}
+
+// A class, or an entry that couldn't be read as one, and its problems.
+#[derive(Serialize)]
+struct CheckedEntry<'a> {
+    source: &'a str,
+    path: &'a str,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    class: Option<String>,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    diagnostics: Vec<Diagnostic>,
+
+    // Only for classes that pass format checking.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    verify_errors: Vec<VerifyError>,
+}
+
+// Prints one JSON object per class with problems, and exits with status 1 if
+// there were any. With --verify, classes that pass format checking are also
+// verified, looking up the classes they refer to on the input and in --image.
+fn check_classes(matches: &clap::ArgMatches) {
+    let pretty = matches.is_present("pretty");
+    let verify = matches.is_present("verify");
+    let mut failed = 0;
+
+    // Platform classes come first, as the bootstrap loader finds them.
+    let mut platform = matches.value_of("image").map(|image_path| {
+        let image = open_image(Path::new(image_path));
+        let modules = class_modules(&image);
+        (image, modules)
+    });
+    let mut class_path = ClassPath::new(&input_roots(matches), release(matches));
+
+    let hierarchy = LoadingHierarchy::new(|name: &str| {
+        if let Some((ref mut image, ref modules)) = platform {
+            if let Some(module) = modules.get(name) {
+                return image.class(&format!("{}/{}", module, name)).ok();
+            }
+        }
+        class_path.class(name).ok()
+    });
+
+    let summary = classpath::scan(&input_roots(matches), &scan_options(matches), |scanned| {
+        let (class, diagnostics, verify_errors) = match scanned.entry.content {
+            EntryContent::Class(ref cf) => {
+                let diagnostics = check::check(cf);
+                let verify_errors = if verify && diagnostics.is_empty() {
+                    verifier::verify_class(cf, &hierarchy as &dyn ClassHierarchy)
+                } else {
+                    Vec::new()
+                };
+                (cf.name(), diagnostics, verify_errors)
+            }
+            EntryContent::Error(ref e) => {
+                let diagnostic = Diagnostic { location: "class file".to_string(), message: e.clone() };
+                (None, vec![diagnostic], Vec::new())
+            }
+            _ => return,
+        };
+
+        if !diagnostics.is_empty() || !verify_errors.is_empty() {
+            failed += 1;
+            let entry = CheckedEntry { source: &scanned.source, path: &scanned.entry.path, class, diagnostics, verify_errors };
+            print_json(&entry, pretty);
+        }
+    });
+
+    eprintln!(
+        "{} {} classes from {} {}, {} with problems",
+        if verify { "Verified" } else { "Checked" },
+        summary.classes + summary.failed,
+        summary.roots,
+        if summary.roots == 1 { "root" } else { "roots" },
+        failed
+    );
+
+    let missing = hierarchy.missing();
+    if verify && !missing.is_empty() {
+        eprintln!("{} classes referred to were not found, and were assumed assignable", missing.len());
+    }
+
+    if failed > 0 {
+        std::process::exit(1);
+    }
+}
+
+fn check_compatibility(matches: &clap::ArgMatches) {
+    let read_snapshot = |classpath: &str| {
+        let mut snapshot = ApiSnapshot::new();
+        let mut failed = 0;
+        let summary = classpath::scan(&classpath::split_classpath(classpath), &ScanOptions::default(), |mut scanned| {
+            if let EntryContent::Class(ref cf) = scanned.entry.content {
+                if let Err(e) = snapshot.add_class(cf) {
+                    failed += 1;
+                    scanned.entry.content = EntryContent::Error(e);
+                }
+            }
+            report_error(&scanned);
+        });
+        report_summary(&summary);
+
+        if summary.failed > 0 || failed > 0 {
+            std::process::exit(1);
+        }
+        snapshot
+    };
+
+    let old = read_snapshot(matches.value_of("OLD").unwrap());
+    let new = read_snapshot(matches.value_of("NEW").unwrap());
+    let report = compat::compare(&old, &new);
+
+    print_json(&report, matches.is_present("pretty"));
+
+    if !report.changes.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+fn print_diff(matches: &clap::ArgMatches) {
+    let read = |path: &str| {
+        let result = File::open(path)
+            .map_err(|e| e.to_string())
+            .and_then(|mut f| read_classfile(&mut f).map_err(|e| e.to_string()));
+
+        match result {
+            Ok(cf) => cf,
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
+    };
+
+    let old = read(matches.value_of("OLD").unwrap());
+    let new = read(matches.value_of("NEW").unwrap());
+    let options = DiffOptions {
+        ignore_debug: matches.is_present("ignore-debug"),
+    };
+    let report = diff::diff(&old, &new, &options);
+
+    print_json(&report, matches.is_present("pretty"));
+
+    if !report.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+// The module of every class in an image, by internal name.
+fn class_modules(image: &Image<File>) -> HashMap<String, String> {
+    image
+        .locations()
+        .into_iter()
+        .filter(|location| location.extension == "class" && !location.module.is_empty())
+        .map(|location| {
+            let name = if location.parent.is_empty() {
+                location.base
+            } else {
+                format!("{}/{}", location.parent, location.base)
+            };
+            (name, location.module)
+        })
+        .collect()
+}
+
+#[derive(Serialize)]
+struct Subtypes {
+    #[serde(rename = "type")]
+    name: String,
+    subtypes: Vec<String>,
+}
+
+fn print_hierarchy(matches: &clap::ArgMatches) {
+    let hierarchy = read_hierarchy(matches);
+    let pretty = matches.is_present("pretty");
+
+    if let Some(name) = matches.value_of("subtypes") {
+        let name = match type_name(&hierarchy, name) {
+            Some(name) => name,
+            None => {
+                eprintln!("{} is not a type of the input", name);
+                std::process::exit(1);
+            }
+        };
+
+        print_json(&Subtypes { subtypes: hierarchy.subtypes(&name), name }, pretty);
+        return;
+    }
+
+    match matches.value_of("format").unwrap() {
+        "dot" => print!("{}", hierarchy.to_dot()),
+        "json" => print_json(&hierarchy.report(), pretty),
+        _ => {
+            eprintln!("The hierarchy can only be printed as JSON or DOT");
+            std::process::exit(1);
+        }
+    }
+}
+
+// The internal name of a type given as written in Java, taking the last dots
+// for $ as needed to name a member class.
+fn type_name(hierarchy: &TypeHierarchy, name: &str) -> Option<String> {
+    let mut candidate = name.replace('.', "/");
 
     loop {
-        let tag_u8 = read_u8(f);
-        let tag = cp_tag_from(tag_u8);
-
-        let cp_info: Box<CpInfo> = match tag {
-            CpTag::Class => Box::new(ClassInfo {
-                tag: tag_u8,
-                name_index: read_u16(f),
-            }),
-
-            CpTag::Fieldref => Box::new(FieldrefInfo {
-                tag: tag_u8,
-                class_index: read_u16(f),
-                name_and_type_index: read_u16(f),
-            }),
-
-            CpTag::Methodref => Box::new(MethodrefInfo {
-                tag: tag_u8,
-                class_index: read_u16(f),
-                name_and_type_index: read_u16(f),
-            }),
-
-            CpTag::InterfaceMethodref => Box::new(InterfaceMethodrefInfo {
-                tag: tag_u8,
-                class_index: read_u16(f),
-                name_and_type_index: read_u16(f),
-            }),
-
-            CpTag::String => Box::new(StringInfo {
-                tag: tag_u8,
-                string_index: read_u16(f),
-            }),
-
-            CpTag::Integer => Box::new(IntegerInfo {
-                tag: tag_u8,
-                bytes: read_u32(f),
-            }),
-
-            CpTag::Float => Box::new(FloatInfo {
-                tag: tag_u8,
-                bytes: read_u32(f),
-            }),
-
-            CpTag::Long => Box::new(LongInfo {
-                tag: tag_u8,
-                high_bytes: read_u32(f),
-                low_bytes: read_u32(f),
-            }),
-
-            CpTag::Double => Box::new(DoubleInfo {
-                tag: tag_u8,
-                high_bytes: read_u32(f),
-                low_bytes: read_u32(f),
-            }),
-
-            CpTag::NameAndType => Box::new(NameAndTypeInfo {
-                tag: tag_u8,
-                name_index: read_u16(f),
-                descriptor_index: read_u16(f),
-            }),
-
-            CpTag::Utf8 => {
-                let length = read_u16(f);
-                Box::new(Utf8Info {
-                    tag: tag_u8,
-                    length: length,
-                    bytes: read_vec_u8(f, length as usize),
-                })
-            }
-
-            CpTag::MethodHandle => Box::new(MethodHandleInfo {
-                tag: tag_u8,
-                reference_kind: read_u8(f),
-                reference_index: read_u16(f),
-            }),
-
-            CpTag::MethodType => Box::new(MethodTypeInfo {
-                tag: tag_u8,
-                descriptor_index: read_u16(f),
-            }),
-
-            CpTag::InvokeDynamic => Box::new(InvokeDynamicInfo {
-                tag: tag_u8,
-                bootstrap_method_attr_index: read_u16(f),
-                name_and_type_index: read_u16(f),
-            }),
-
-            CpTag::Module => Box::new(ModuleInfo {
-                tag: tag_u8,
-                name_index: read_u16(f),
-            }),
-
-            CpTag::Package => Box::new(PackageInfo {
-                tag: tag_u8,
-                name_index: read_u16(f),
-            }),
+        if hierarchy.contains(&candidate) || !hierarchy.direct_subtypes(&candidate).is_empty() {
+            return Some(candidate);
+        }
+
+        let i = candidate.rfind('/')?;
+        candidate.replace_range(i..=i, "$");
+    }
+}
+
+const INDEX_FILE: &str = "index.json";
+
+// Writes each class to a file of its own under a directory, at the path of
+// the class file with the extension of the format, and an index of them all.
+struct Mirror<'a> {
+    dir: PathBuf,
+    format: &'a str,
+    index: Vec<IndexEntry>,
+    // Files written so far. When a class path has several classes of the
+    // same name, the first one is written, as it is the one that is loaded.
+    written: HashSet<PathBuf>,
+    // With --format java, the classes whose stubs are written once all have
+    // been read, as member classes are declared in their enclosing class's,
+    // with the position of their index entry.
+    stubs: Vec<(usize, ClassFile)>,
+}
+
+#[derive(Serialize)]
+struct Index<'a> {
+    classes: &'a [IndexEntry],
+}
+
+#[derive(Serialize)]
+struct IndexEntry {
+    // The class file, as the path of the entry in its root.
+    path: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+
+    // The file written for the class, relative to the output directory, or
+    // none if it couldn't be read or is shadowed by another class. A member
+    // class written as Java is in the file of the class declaring it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file: Option<String>,
+
+    #[serde(flatten)]
+    class: IndexedClass,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum IndexedClass {
+    Class {
+        name: String,
+        major_version: u16,
+        minor_version: u16,
+        java_release: Option<JavaRelease>,
+        access_flags: Vec<String>,
+        super_class: Option<String>,
+        interfaces: Vec<String>,
+    },
+    Error {
+        error: String,
+    },
+}
+
+impl<'a> Mirror<'a> {
+    fn new(dir: &Path, format: &'a str) -> Mirror<'a> {
+        Mirror {
+            dir: dir.to_path_buf(),
+            format,
+            index: Vec::new(),
+            written: HashSet::new(),
+            stubs: Vec::new(),
+        }
+    }
+
+    fn write(&mut self, scanned: ScannedEntry, show_source: bool, pretty: bool) -> Result<(), String> {
+        // The output directory doesn't repeat the name of the archive read.
+        let mirrored = mirrored_path(scanned.path_in_source(), self.format);
+        let source = if show_source { Some(scanned.source) } else { None };
+        let path = scanned.entry.path;
+
+        let cf = match scanned.entry.content {
+            EntryContent::Class(cf) => cf,
+            EntryContent::Error(e) => {
+                let class = IndexedClass::Error { error: e };
+                self.index.push(IndexEntry { path, source, file: None, class });
+                return Ok(());
+            }
+            _ => return Ok(()),
         };
 
-        match tag {
-            CpTag::Long => constant_pool_remaining -= 2,
-            CpTag::Double => constant_pool_remaining -= 2,
-            _ => constant_pool_remaining -= 1,
+        let cp = &cf.constant_pool;
+        let class = IndexedClass::Class {
+            name: cf.name().unwrap_or_default(),
+            major_version: cf.major_version,
+            minor_version: cf.minor_version,
+            java_release: cf.java_release(),
+            access_flags: format_flags(cf.access_flags, FlagContext::Class),
+            super_class: cp.class_name(cf.super_class),
+            interfaces: cf.interfaces.iter().filter_map(|&index| cp.class_name(index)).collect(),
+        };
+
+        // Classes shadowed by an earlier one are only indexed.
+        let file = mirrored?;
+        if !self.written.insert(file.clone()) {
+            self.index.push(IndexEntry { path, source, file: None, class });
+            return Ok(());
+        }
+
+        let file = file.to_string_lossy().replace('\\', "/");
+        if self.format == "java" {
+            self.stubs.push((self.index.len(), cf));
+            self.index.push(IndexEntry { path, source, file: Some(file), class });
+            return Ok(());
+        }
+
+        let contents = match self.format {
+            "asm" => cf.to_assembly(),
+            _ if pretty => cf.to_pretty_json() + "\n",
+            _ => cf.to_json() + "\n",
+        };
+
+        self.write_file(&file, &contents)?;
+        self.index.push(IndexEntry { path, source, file: Some(file), class });
+
+        Ok(())
+    }
+
+    fn write_file(&self, file: &str, contents: &str) -> Result<(), String> {
+        let target = self.dir.join(file);
+        fs::create_dir_all(target.parent().unwrap()).map_err(|e| e.to_string())?;
+        fs::write(&target, contents).map_err(|e| format!("{}: {}", target.display(), e))
+    }
+
+    // Writes the stubs of the classes that aren't members of another class
+    // read, with the members found declared inside them. Returns the paths
+    // of the classes whose stubs couldn't be written, with the errors.
+    fn write_stubs(&mut self) -> Vec<(String, String)> {
+        let stubs = std::mem::take(&mut self.stubs);
+        let mut classes = HashMap::new();
+        for (i, (_, cf)) in stubs.iter().enumerate() {
+            if let Some(name) = cf.name() {
+                classes.entry(name).or_insert(i);
+            }
+        }
+
+        // The outermost class each member is declared in, when they were
+        // read too.
+        let mut declaring = HashMap::new();
+        for (i, (_, cf)) in stubs.iter().enumerate() {
+            let mut current = cf;
+            let mut outermost = None;
+            // Bounded, in case the InnerClasses attributes form a cycle.
+            for _ in 0..stubs.len() {
+                match java_stub::declaring_class(current).ok().flatten().and_then(|name| classes.get(&name)) {
+                    Some(&outer) if outer != i => {
+                        outermost = Some(outer);
+                        current = &stubs[outer].1;
+                    }
+                    _ => break,
+                }
+            }
+            if let Some(outer) = outermost {
+                declaring.insert(i, outer);
+            }
+        }
+
+        let mut failures = Vec::new();
+        for (i, (entry, cf)) in stubs.iter().enumerate() {
+            if declaring.contains_key(&i) {
+                continue;
+            }
+
+            let file = self.index[*entry].file.clone().unwrap();
+            let written = java_stub::generate_with(cf, |name| classes.get(name).map(|&i| &stubs[i].1))
+                .and_then(|stub| self.write_file(&file, &stub));
+
+            if let Err(e) = written {
+                let entry = &mut self.index[*entry];
+                entry.file = None;
+                entry.class = IndexedClass::Error { error: e.clone() };
+                failures.push((entry.path.clone(), e));
+            }
+        }
+
+        for (i, outer) in declaring {
+            self.index[stubs[i].0].file = self.index[stubs[outer].0].file.clone();
         }
 
-        constant_pool.push(cp_info);
+        failures
+    }
+
+    fn write_index(&self, pretty: bool) -> Result<(), String> {
+        let index = Document::new(Index { classes: &self.index });
+        let json = if pretty {
+            serde_json::to_string_pretty(&index)
+        } else {
+            serde_json::to_string(&index)
+        };
 
-        if constant_pool_remaining == 0 {
-            break;
+        fs::create_dir_all(&self.dir).map_err(|e| e.to_string())?;
+        fs::write(self.dir.join(INDEX_FILE), json.unwrap() + "\n").map_err(|e| e.to_string())
+    }
+}
+
+// The file a class at `path` in an archive is written to, keeping its
+// directories, nested archives included. Paths that would lead out of the
+// output directory are refused.
+fn mirrored_path(path: &str, format: &str) -> Result<PathBuf, String> {
+    let extension = match format {
+        "java" => "java",
+        "asm" => "asm",
+        _ => "json",
+    };
+
+    let mut file = PathBuf::new();
+
+    for component in path.split("!/").flat_map(|part| part.split('/')) {
+        match component {
+            "" | "." => {}
+            ".." => return Err("refusing to write outside the output directory".to_string()),
+            _ if component.contains(['\\', ':']) => return Err("refusing to write outside the output directory".to_string()),
+            _ => file.push(component),
         }
     }
 
-    ConstantPool { array: constant_pool }
+    if file.as_os_str().is_empty() {
+        return Err("no file name to write to".to_string());
+    }
+
+    file.set_extension(extension);
+    Ok(file)
 }
 
-fn read_fields(f: &mut Read, fields_count: u16) -> Fields {
-    let mut fields = Vec::new();
+// The methods of --cfg and --frames.
+#[derive(Serialize)]
+struct Methods<T> {
+    methods: Vec<T>,
+}
 
-    for _ in 0..fields_count {
-        let access_flags = read_u16(f);
-        let name_index = read_u16(f);
-        let descriptor_index = read_u16(f);
-        let attributes_count = read_u16(f);
+#[derive(Serialize)]
+struct MethodGraph {
+    method: String,
 
-        fields.push(FieldInfo {
-            access_flags: access_flags,
-            name_index: name_index,
-            descriptor_index: descriptor_index,
-            attributes_count: attributes_count,
-            attributes: read_attributes(f, attributes_count),
-        });
+    #[serde(flatten)]
+    graph: ControlFlowGraph,
+}
+
+// Named code of the methods matching a selector, exiting when there are none.
+fn select_code<'a>(cf: &'a ClassFile, selector: &str) -> Vec<(String, &'a MethodInfo, CodeAttribute)> {
+    let cp = &cf.constant_pool;
+    let methods = cf.select_methods(selector);
+
+    if methods.is_empty() {
+        eprintln!("No method matches '{}'", selector);
+        std::process::exit(1);
     }
 
-    Fields { array: fields }
+    let mut selected = Vec::new();
+
+    for method in methods {
+        let name = format!(
+            "{}{}",
+            cp.utf8(method.name_index).unwrap_or_default(),
+            cp.utf8(method.descriptor_index).unwrap_or_default()
+        );
+
+        match method.attributes.find(cp, "Code") {
+            Some(attribute) => match read_code(&attribute.info) {
+                Ok(code) => selected.push((name, method, code)),
+                Err(e) => eprintln!("{}: {}", name, malformed("Code", e)),
+            },
+            None => eprintln!("{} has no code", name),
+        }
+    }
+
+    selected
 }
 
-fn read_methods(f: &mut Read, methods_count: u16) -> Methods {
-    let mut methods = Vec::new();
+// Prints an object as a document of the current format version.
+fn print_json<T: Serialize>(value: &T, pretty: bool) {
+    let document = Document::new(value);
 
-    for _ in 0..methods_count {
-        let access_flags = read_u16(f);
-        let name_index = read_u16(f);
-        let descriptor_index = read_u16(f);
-        let attributes_count = read_u16(f);
+    if pretty {
+        println!("{}", serde_json::to_string_pretty(&document).unwrap());
+    } else {
+        println!("{}", serde_json::to_string(&document).unwrap());
+    }
+}
 
-        methods.push(MethodInfo {
-            access_flags: access_flags,
-            name_index: name_index,
-            descriptor_index: descriptor_index,
-            attributes_count: attributes_count,
-            attributes: read_attributes(f, attributes_count),
-        });
+fn print_cfg(cf: &ClassFile, selector: &str, format: &str, pretty: bool) {
+    let cp = &cf.constant_pool;
+    let mut graphs = Vec::new();
+
+    for (name, _, code) in select_code(cf, selector) {
+        match ControlFlowGraph::build(&code, cp) {
+            Ok(graph) => graphs.push(MethodGraph { method: name, graph }),
+            Err(e) => eprintln!("{}: {}", name, e),
+        }
     }
 
-    Methods { array: methods }
+    match format {
+        "dot" => {
+            for g in &graphs {
+                print!("{}", g.graph.to_dot(&g.method, cp));
+            }
+        }
+        _ => print_json(&Methods { methods: graphs }, pretty),
+    }
 }
 
-fn read_attributes(f: &mut Read, attributes_count: u16) -> Attributes {
-    let mut attributes = Vec::new();
+#[derive(Serialize)]
+struct MethodFrames {
+    method: String,
 
-    for _ in 0..attributes_count {
-        let attribute_name_index = read_u16(f);
-        let attribute_length = read_u32(f);
+    #[serde(flatten)]
+    analysis: FrameAnalysis,
+}
 
-        attributes.push(AttributeInfo {
-            attribute_name_index: attribute_name_index,
-            attribute_length: attribute_length,
-            info: read_vec_u8(f, attribute_length as usize),
-        });
+fn print_frames(cf: &ClassFile, selector: &str, pretty: bool) {
+    let mut analyses = Vec::new();
+
+    for (name, method, code) in select_code(cf, selector) {
+        match FrameAnalysis::analyze(cf, method, &code) {
+            Ok(analysis) => analyses.push(MethodFrames { method: name, analysis }),
+            Err(e) => eprintln!("{}: {}", name, e),
+        }
     }
 
-    Attributes { array: attributes }
+    print_json(&Methods { methods: analyses }, pretty);
 }
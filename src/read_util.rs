@@ -1,45 +1,74 @@
-use std::io::prelude::*;
+use std::io;
 use std::io::Read;
 
-pub fn read_u8(f: &mut Read) -> u8 {
+// The try_ variants report truncated input as I/O errors, for reading whole
+// class files that may be corrupt.
+
+pub fn try_read_u8(f: &mut dyn Read) -> io::Result<u8> {
     let mut buf = [0u8; 1];
-    f.read_exact(&mut buf).expect("could not parse u8");
+    f.read_exact(&mut buf)?;
 
-    buf[0] as u8
+    Ok(buf[0])
 }
 
-pub fn read_u16(f: &mut Read) -> u16 {
+pub fn try_read_u16(f: &mut dyn Read) -> io::Result<u16> {
     let mut buf = [0u8; 2];
-    f.read_exact(&mut buf).expect("could not parse u16");
+    f.read_exact(&mut buf)?;
 
-    (buf[0] as u16) << 8 | (buf[1] as u16)
+    Ok((buf[0] as u16) << 8 | (buf[1] as u16))
 }
 
-pub fn read_u32(f: &mut Read) -> u32 {
+pub fn try_read_u32(f: &mut dyn Read) -> io::Result<u32> {
     let mut buf = [0u8; 4];
-    f.read_exact(&mut buf).expect("could not parse u32");
+    f.read_exact(&mut buf)?;
+
+    Ok((buf[0] as u32) << 24 | (buf[1] as u32) << 16 | (buf[2] as u32) << 8 | (buf[3] as u32))
+}
+
+pub fn try_read_vec_u8(f: &mut dyn Read, length: usize) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    f.take(length as u64).read_to_end(&mut buf)?;
 
-    (buf[0] as u32) << 24 | (buf[1] as u32) << 16 | (buf[2] as u32) << 8 | (buf[3] as u32)
+    if buf.len() < length {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "failed to fill whole buffer"));
+    }
+
+    Ok(buf)
 }
 
-pub fn read_vec_u8(f: &mut Read, length: usize) -> Vec<u8> {
-    let mut buf = vec![0u8; length];
-    f.read_exact(buf.as_mut_slice()).expect("couldnt read cp_info: Utf8");
+pub fn try_read_vec_u16(f: &mut dyn Read, length: usize) -> io::Result<Vec<u16>> {
+    let mut buf = Vec::with_capacity(length);
+
+    for _ in 0..length {
+        buf.push(try_read_u16(f)?);
+    }
 
-    buf
+    Ok(buf)
 }
 
-pub fn read_vec_u16(f: &mut Read, length: usize) -> Vec<u16> {
-    use std::slice;
+// The others read the contents of attributes, which are already in memory,
+// so running out of bytes means the attribute is truncated.
+
+fn truncated(_: io::Error) -> String {
+    String::from("truncated")
+}
 
-    let mut buf = vec![0u16; length];
-    let buf_u8 = unsafe {
-        slice::from_raw_parts_mut(
-            buf.as_mut_slice().as_mut_ptr() as *mut u8,
-            buf.len() * 2,
-        )
-    };
-    f.read_exact(buf_u8).expect("couldnt read cp_info: Utf8");
+pub fn read_u8(f: &mut dyn Read) -> Result<u8, String> {
+    try_read_u8(f).map_err(truncated)
+}
+
+pub fn read_u16(f: &mut dyn Read) -> Result<u16, String> {
+    try_read_u16(f).map_err(truncated)
+}
+
+pub fn read_u32(f: &mut dyn Read) -> Result<u32, String> {
+    try_read_u32(f).map_err(truncated)
+}
+
+pub fn read_vec_u8(f: &mut dyn Read, length: usize) -> Result<Vec<u8>, String> {
+    try_read_vec_u8(f, length).map_err(truncated)
+}
 
-    buf
+pub fn read_vec_u16(f: &mut dyn Read, length: usize) -> Result<Vec<u16>, String> {
+    try_read_vec_u16(f, length).map_err(truncated)
 }
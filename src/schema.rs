@@ -0,0 +1,71 @@
+// The JSON documents class2json writes, and the JSON Schema of those of the
+// class file model: classes, and the entries of archives and class paths.
+// Every document is an object with a format_version, which is incremented
+// whenever a field is renamed or removed or changes its representation.
+
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::schema::{InstanceType, Metadata, RootSchema, Schema, SchemaObject, SubschemaValidation};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use archive::ArchiveEntry;
+use class_file::ClassFile;
+use classpath::ScannedEntry;
+
+pub const FORMAT_VERSION: u32 = 1;
+
+/// A top-level document: the fields of its content and the format version.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Document<T> {
+    #[schemars(schema_with = "format_version_schema")]
+    pub format_version: u32,
+
+    #[serde(flatten)]
+    pub content: T,
+}
+
+impl<T> Document<T> {
+    pub fn new(content: T) -> Document<T> {
+        Document {
+            format_version: FORMAT_VERSION,
+            content,
+        }
+    }
+}
+
+fn format_version_schema(_: &mut SchemaGenerator) -> Schema {
+    SchemaObject {
+        instance_type: Some(InstanceType::Integer.into()),
+        const_value: Some(FORMAT_VERSION.into()),
+        ..Default::default()
+    }
+    .into()
+}
+
+/// The schema of a class, of an entry of an archive or directory, and of an
+/// entry of a class path, which also has the root it was found in.
+pub fn schema() -> RootSchema {
+    let mut gen = SchemaSettings::draft07().into_generator();
+
+    let documents = vec![
+        gen.subschema_for::<Document<ClassFile>>(),
+        gen.subschema_for::<Document<ArchiveEntry>>(),
+        gen.subschema_for::<Document<ScannedEntry>>(),
+    ];
+
+    RootSchema {
+        meta_schema: gen.settings().meta_schema.clone(),
+        schema: SchemaObject {
+            metadata: Some(Box::new(Metadata {
+                title: Some(format!("class2json output, format version {}", FORMAT_VERSION)),
+                ..Default::default()
+            })),
+            subschemas: Some(Box::new(SubschemaValidation {
+                any_of: Some(documents),
+                ..Default::default()
+            })),
+            ..Default::default()
+        },
+        definitions: gen.take_definitions(),
+    }
+}
@@ -1,49 +1,38 @@
-use serde::ser::{Serialize, Serializer, SerializeSeq};
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{InstanceType, Schema, SchemaObject, StringValidation};
+use serde::ser::{Serializer, SerializeSeq};
 
 use bytecode;
+use class_file::constant_pool::decode_modified_utf8;
 
-pub fn serialize_utf8info_bytes<S>(v: &Vec<u8>, s: S) -> Result<S::Ok, S::Error>
+pub fn serialize_utf8info_bytes<S>(v: &[u8], s: S) -> Result<S::Ok, S::Error>
     where S: Serializer {
-    s.serialize_str(&String::from_utf8((v).clone()).unwrap())
+    s.serialize_str(&decode_modified_utf8(v))
 }
 
-pub fn serialize_bytecode<S>(v: &Vec<u8>, s: S) -> Result<S::Ok, S::Error>
+// The contents of attributes, whatever their kind, as they are in the class
+// file. Decoded forms of them are in the assembly listing.
+pub fn serialize_hex_bytes<S>(v: &[u8], s: S) -> Result<S::Ok, S::Error>
     where S: Serializer {
-    let mut seq = s.serialize_seq(Some(v.len()))?;
-
-    let mut bytecode_iter = v.iter();
-
-    loop {
-        let byte = bytecode_iter.next();
-
-        let byte = match byte {
-            Some(byte) => *byte,
-            None => break
-        };
-
-        let (bytecode, args_len, args_dist) = bytecode::to_string(byte);
-
-        let str_buf = String::from(bytecode);
-
-        if args_len > 0 {
-            for arg_len in args_dist {
-                let arg = match arg_len {
-                    1 => bytecode_iter.next().unwrap(),
-                    2 => bytecode_iter.next().unwrap() << 8 | bytecode_iter.next(),
-                    4 => bytecode_iter.next().unwrap() << 24 | bytecode_iter.next() << 16 | bytecode_iter.next() << 8 | bytecode_iter.next().unwrap(),
-                };
-
-                
-            }
-        }
+    s.serialize_str(&v.iter().map(|b| format!("{:02x}", b)).collect::<String>())
+}
 
+pub fn serialize_instructions<S>(v: &[bytecode::Instruction], s: S) -> Result<S::Ok, S::Error>
+    where S: Serializer {
+    let mut seq = s.serialize_seq(Some(v.len()))?;
 
-        seq.serialize_element(&str_buf)?;
+    for instruction in v {
+        seq.serialize_element(&format!("{}: {}", instruction.offset, instruction))?;
     }
 
     seq.end()
 }
 
+pub fn serialize_instruction<S>(v: &bytecode::Instruction, s: S) -> Result<S::Ok, S::Error>
+    where S: Serializer {
+    s.collect_str(v)
+}
+
 pub fn serialize_u8_hex<S>(v: &u8, s: S) -> Result<S::Ok, S::Error>
     where S: Serializer {
     s.serialize_str(format!("{:02x}", v).as_str())
@@ -57,4 +46,34 @@ pub fn serialize_u16_hex<S>(v: &u16, s: S) -> Result<S::Ok, S::Error>
 pub fn serialize_u32_hex<S>(v: &u32, s: S) -> Result<S::Ok, S::Error>
     where S: Serializer {
     s.serialize_str(format!("{:08x}", v).as_str())
-}
\ No newline at end of file
+}
+
+// Schemas of the representations above, for the JSON Schema of the output.
+
+pub fn u8_hex_schema(_: &mut SchemaGenerator) -> Schema {
+    string_schema("^[0-9a-f]{2}$")
+}
+
+pub fn u16_hex_schema(_: &mut SchemaGenerator) -> Schema {
+    string_schema("^[0-9a-f]{4}$")
+}
+
+pub fn u32_hex_schema(_: &mut SchemaGenerator) -> Schema {
+    string_schema("^[0-9a-f]{8}$")
+}
+
+pub fn hex_bytes_schema(_: &mut SchemaGenerator) -> Schema {
+    string_schema("^([0-9a-f]{2})*$")
+}
+
+fn string_schema(pattern: &str) -> Schema {
+    SchemaObject {
+        instance_type: Some(InstanceType::String.into()),
+        string: Some(Box::new(StringValidation {
+            pattern: Some(pattern.to_string()),
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+    .into()
+}
@@ -0,0 +1,17 @@
+pub fn write_u8(out: &mut Vec<u8>, v: u8) {
+    out.push(v);
+}
+
+pub fn write_u16(out: &mut Vec<u8>, v: u16) {
+    out.extend_from_slice(&[(v >> 8) as u8, v as u8]);
+}
+
+pub fn write_u32(out: &mut Vec<u8>, v: u32) {
+    out.extend_from_slice(&[(v >> 24) as u8, (v >> 16) as u8, (v >> 8) as u8, v as u8]);
+}
+
+pub fn write_vec_u16(out: &mut Vec<u8>, v: &[u16]) {
+    for &x in v {
+        write_u16(out, x);
+    }
+}
@@ -0,0 +1,171 @@
+// Assembling listings: the prefixes, padding and offsets the assembler works
+// out itself, and listings of compiled classes assembling back to the same
+// bytes.
+
+extern crate class2json;
+extern crate zip;
+
+mod common;
+
+use std::fs;
+use std::io::{Cursor, Read};
+
+use class2json::assembly::assemble::assemble;
+use class2json::class_file::attributes::read_code;
+use class2json::class_file::reader::read_classfile;
+
+use common::{run, temp_file, SUM};
+
+// A class with one static method f(I)V of the code.
+fn listing(code: &str) -> String {
+    format!(
+        ".version 52 0
+.class public super p/X
+.super java/lang/Object
+
+.method public static f : (I)V
+    .code stack 2 locals 400
+{}
+    .end code
+.end method
+",
+        code
+    )
+}
+
+fn code(code: &str) -> Vec<u8> {
+    let cf = assemble(&listing(code)).unwrap();
+    let method = &cf.methods.array[0];
+    method.attributes.decode(&cf.constant_pool, "Code", read_code).unwrap().unwrap().code
+}
+
+#[test]
+fn wide_is_inserted_for_large_locals_and_increments() {
+    let bytes = code(
+        "
+        iload 3
+        iload 300
+        istore 255
+        iinc 1 100
+        iinc 1 200
+        iinc 256 1
+        return",
+    );
+
+    assert_eq!(bytes, [
+        0x15, 3,
+        0xc4, 0x15, 0x01, 0x2c,
+        0x36, 0xff,
+        0x84, 1, 100,
+        0xc4, 0x84, 0x00, 0x01, 0x00, 0xc8,
+        0xc4, 0x84, 0x01, 0x00, 0x00, 0x01,
+        0xb1,
+    ]);
+
+    // Instructions without a local can't be widened.
+    let error = assemble(&listing("wide bipush 1\nreturn")).unwrap_err();
+    assert_eq!(error.line, 7);
+    assert!(error.message.contains("cannot be widened"), "{}", error);
+}
+
+#[test]
+fn switches_are_padded_to_four_bytes() {
+    for nops in 0..4 {
+        let bytes = code(&format!(
+            "{}
+        iload_0
+        tableswitch 0
+            L
+            default : L
+L:      return",
+            "nop\n".repeat(nops)
+        ));
+
+        // The operands start at the next multiple of four after the opcode.
+        let opcode = nops + 1;
+        assert_eq!(bytes[opcode], 0xaa);
+        let operands = (opcode + 4) / 4 * 4;
+        assert!(bytes[opcode + 1..operands].iter().all(|&b| b == 0));
+
+        // default, low, high and the one target all lead to the return.
+        let target = (operands + 16 - nops) as u32;
+        let read = |at: usize| u32::from_be_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]);
+        assert_eq!([read(operands), read(operands + 4), read(operands + 8), read(operands + 12)], [target - 1, 0, 0, target - 1]);
+        assert_eq!(bytes.len(), operands + 17);
+    }
+
+    let bytes = code(
+        "
+        iload_0
+        lookupswitch
+            1 : L
+            default : L
+L:      return",
+    );
+    assert_eq!(bytes[..4], [0x1a, 0xab, 0, 0]);
+    assert_eq!(bytes[4..20], [0, 0, 0, 19, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 19]);
+}
+
+#[test]
+fn branches_past_a_short_offset_are_refused() {
+    let far = format!("L0:     goto L1\n{}L1:     return", "nop\n".repeat(32767));
+    let error = assemble(&listing(&far)).unwrap_err();
+    assert_eq!(error.line, 7);
+    assert_eq!(error.message, "branch to 'L1' is out of range");
+
+    // The farthest a goto reaches is 32767 bytes ahead, and goto_w reaches
+    // further.
+    let near = format!("L0:     goto L1\n{}L1:     return", "nop\n".repeat(32764));
+    assert_eq!(code(&near)[..3], [0xa7, 0x7f, 0xff]);
+
+    let wide = format!("L0:     goto_w L1\n{}L1:     return", "nop\n".repeat(32767));
+    assert_eq!(code(&wide)[..5], [0xc8, 0x00, 0x00, 0x80, 0x04]);
+
+    let back = format!("L0:     nop\n{}goto L0", "nop\n".repeat(32768));
+    let error = assemble(&listing(&back)).unwrap_err();
+    assert_eq!(error.message, "branch to 'L0' is out of range");
+}
+
+#[test]
+fn listings_of_compiled_classes_assemble_to_the_same_bytes() {
+    let mut classes = vec![("Sum.class".to_string(), SUM.to_vec())];
+
+    for entry in fs::read_dir("class_files/sealed").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|e| e == "class") {
+            classes.push((path.display().to_string(), fs::read(&path).unwrap()));
+        }
+    }
+
+    let jar = include_bytes!("../class_files/pack200/shapes.jar");
+    let mut zip = zip::ZipArchive::new(Cursor::new(&jar[..])).unwrap();
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i).unwrap();
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes).unwrap();
+        classes.push((file.name().to_string(), bytes));
+    }
+
+    for (name, bytes) in classes {
+        let listing = read_classfile(&mut &bytes[..]).unwrap().to_assembly();
+        let assembled = assemble(&listing).unwrap_or_else(|e| panic!("{}: {}", name, e));
+        assert!(assembled.to_bytes() == bytes, "{} assembles to other bytes", name);
+    }
+}
+
+#[test]
+fn unreadable_listings_are_reported() {
+    let path = temp_file("Latin1.j", b".version 52 0\n.class public super p/Caf\xe9\n");
+    let output = run(&["-a", path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with(&format!("{}: ", path.display())), "{}", stderr);
+    assert!(stderr.contains("UTF-8"), "{}", stderr);
+    fs::remove_file(&path).unwrap();
+
+    let output = run(&["-a", path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with(&format!("{}: ", path.display())), "{}", stderr);
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+}
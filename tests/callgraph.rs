@@ -0,0 +1,101 @@
+// Call graphs of the classes of a jar: calls to the jar's own methods and to
+// others, interface calls resolved to their implementations, and lambdas to
+// the methods implementing them.
+
+extern crate class2json;
+extern crate serde_json;
+extern crate zip;
+
+mod common;
+
+use std::fs;
+
+use serde_json::{json, Value};
+
+use class2json::assembly::assemble::assemble;
+
+use common::{jar, run_stdout, temp_file};
+
+const SHAPES: &str = "class_files/pack200/shapes.jar";
+
+// A method returning a lambda, as javac compiles one.
+const LAMBDA: &str = r#"
+.version 52 0
+.class public super p/L
+.super java/lang/Object
+
+.method public static run : ()Ljava/lang/Runnable;
+    .code stack 1 locals 0
+        invokedynamic InvokeDynamic 0 run ()Ljava/lang/Runnable;
+        areturn
+    .end code
+.end method
+
+.method private static synthetic lambda$run$0 : ()V
+    .code stack 0 locals 0
+        return
+    .end code
+.end method
+
+.bootstrapmethods
+    MethodHandle invokeStatic Method java/lang/invoke/LambdaMetafactory metafactory (Ljava/lang/invoke/MethodHandles$Lookup;Ljava/lang/String;Ljava/lang/invoke/MethodType;Ljava/lang/invoke/MethodType;Ljava/lang/invoke/MethodHandle;Ljava/lang/invoke/MethodType;)Ljava/lang/invoke/CallSite; : MethodType "()V" MethodHandle invokeStatic Method p/L lambda$run$0 ()V MethodType "()V"
+.end bootstrapmethods
+"#;
+
+fn call_graph(args: &[&str]) -> String {
+    let (ok, stdout) = run_stdout(args);
+    assert!(ok);
+    stdout
+}
+
+// The edges from a caller, without their offsets.
+fn calls_from(graph: &Value, caller: &str) -> Vec<Value> {
+    graph["edges"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|edge| edge["caller"] == caller)
+        .map(|edge| {
+            let mut edge = edge.clone();
+            edge.as_object_mut().unwrap().remove("offset");
+            edge
+        })
+        .collect()
+}
+
+#[test]
+fn interface_calls_resolve_to_implementations() {
+    let graph: Value = serde_json::from_str(&call_graph(&["--call-graph", SHAPES])).unwrap();
+
+    assert_eq!(calls_from(&graph, "p/Shapes.add(D)V"), [
+        json!({"caller": "p/Shapes.add(D)V", "callee": "p/Shapes$Square.<init>(D)V", "kind": "special"}),
+        json!({"caller": "p/Shapes.add(D)V", "callee": "java/util/List.add(Ljava/lang/Object;)Z", "kind": "interface", "external": true}),
+    ]);
+
+    // Shape.area() is abstract, so the call only goes to Square's.
+    let total: Vec<Value> = calls_from(&graph, "p/Shapes.total()D").into_iter().filter(|edge| edge["external"].is_null()).collect();
+    assert_eq!(total, [json!({"caller": "p/Shapes.total()D", "callee": "p/Shapes$Square.area()D", "kind": "interface"})]);
+
+    let methods = graph["methods"].as_array().unwrap();
+    assert!(methods.contains(&json!("p/Shapes$Shape.area()D")));
+
+    let csv = call_graph(&["--call-graph", "--format", "csv", SHAPES]);
+    assert!(csv.starts_with("caller,callee,kind,offset,external\n"), "{}", csv);
+    assert!(csv.contains("\np/Shapes.total()D,p/Shapes$Square.area()D,interface,35,false\n"), "{}", csv);
+}
+
+#[test]
+fn lambdas_call_the_methods_implementing_them() {
+    let bytes = assemble(LAMBDA).unwrap().to_bytes();
+    let path = temp_file("lambda.jar", &jar(&[("p/L.class", &bytes)]));
+
+    let graph: Value = serde_json::from_str(&call_graph(&["--call-graph", path.to_str().unwrap()])).unwrap();
+    assert_eq!(graph["edges"], json!([
+        {"caller": "p/L.run()Ljava/lang/Runnable;", "callee": "p/L.lambda$run$0()V", "kind": "lambda", "offset": 0},
+    ]));
+
+    let dot = call_graph(&["--call-graph", "--format", "dot", path.to_str().unwrap()]);
+    assert!(dot.contains("\"p/L.run()Ljava/lang/Runnable;\" -> \"p/L.lambda$run$0()V\""), "{}", dot);
+
+    fs::remove_file(path).unwrap();
+}
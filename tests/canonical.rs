@@ -0,0 +1,82 @@
+// Canonical forms of classes that differ only in the order of their constant
+// pools and members.
+
+extern crate class2json;
+
+use class2json::assembly::assemble::assemble;
+use class2json::assembly::syntax::constant_spec;
+
+const ORDERED: &str = r#".version 52 0
+.class public super p/X
+.super java/lang/Object
+
+.field public count I
+.end field
+
+.field public static final NAME Ljava/lang/String;
+    .constantvalue String "x"
+.end field
+
+.method public static f : ()Ljava/lang/String;
+    .code stack 1 locals 0
+        ldc String "f"
+        areturn
+    .end code
+.end method
+
+.method public g : ()I
+    .code stack 1 locals 1
+        aload_0
+        getfield Field p/X count I
+        ireturn
+    .end code
+.end method
+"#;
+
+// The members the other way round, and the pool starting with an entry
+// nothing uses and a duplicate of one that is.
+const SHUFFLED: &str = r#".version 52 0
+.class public super p/X
+.super java/lang/Object
+
+.method public g : ()I
+    .code stack 1 locals 1
+        aload_0
+        getfield Field p/X count I
+        ireturn
+    .end code
+.end method
+
+.method public static f : ()Ljava/lang/String;
+    .code stack 1 locals 0
+        ldc String "f"
+        areturn
+    .end code
+.end method
+
+.field public static final NAME Ljava/lang/String;
+    .constantvalue String "x"
+.end field
+
+.field public count I
+.end field
+
+.const [1] = Utf8 unused
+.const [2] = Utf8 count
+"#;
+
+#[test]
+fn differently_ordered_pools_canonicalize_to_the_same_bytes() {
+    let ordered = assemble(ORDERED).unwrap();
+    let shuffled = assemble(SHUFFLED).unwrap();
+    assert!(ordered.to_bytes() != shuffled.to_bytes());
+    assert!(shuffled.constant_pool.count() > ordered.constant_pool.count());
+
+    let canonical = ordered.to_canonical().unwrap();
+    assert_eq!(canonical.to_bytes(), shuffled.to_canonical().unwrap().to_bytes());
+
+    // Canonical forms are their own canonical form, and start with what ldc
+    // loads.
+    assert_eq!(canonical.to_canonical().unwrap().to_bytes(), canonical.to_bytes());
+    assert_eq!(constant_spec(&canonical.constant_pool, 1).as_deref(), Some("String \"f\""));
+}
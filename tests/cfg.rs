@@ -0,0 +1,109 @@
+// Control flow graphs of a method with a branch, a switch, an exception
+// handler and unreachable code.
+
+extern crate class2json;
+
+mod common;
+
+use std::fs;
+
+use class2json::assembly::assemble::assemble;
+use class2json::bytecode::cfg::{ControlFlowGraph, Edge, EdgeKind};
+use class2json::class_file::attributes::read_code;
+
+use common::{run, run_stdout, temp_file};
+
+const BRANCHES: &str = "
+.version 52 0
+.class public super p/F
+.super java/lang/Object
+
+.method static f : (I)I
+    .code stack 2 locals 1
+    .catch java/lang/ArithmeticException from Other to Done using Handler
+        iload_0
+        ifeq Zero
+        iload_0
+        tableswitch 1
+            One
+            default : Other
+Zero:   iconst_0
+        ireturn
+One:    iconst_1
+        ireturn
+Other:  iload_0
+        iconst_2
+        idiv
+Done:   ireturn
+        iconst_3
+        ireturn
+Handler:
+        pop
+        iconst_m1
+        ireturn
+    .end code
+.end method
+";
+
+fn edge(from: usize, to: usize, kind: EdgeKind) -> Edge {
+    Edge { from, to, kind }
+}
+
+#[test]
+fn blocks_and_edges_of_branches_switches_and_handlers() {
+    let cf = assemble(BRANCHES).unwrap();
+    let method = &cf.methods.array[0];
+    let code = method.attributes.decode(&cf.constant_pool, "Code", read_code).unwrap().unwrap();
+    let graph = ControlFlowGraph::build(&code, &cf.constant_pool).unwrap();
+
+    let blocks: Vec<(u32, u32, bool)> = graph.blocks.iter().map(|b| (b.start, b.end, b.reachable)).collect();
+    assert_eq!(blocks, [
+        (0, 4, true),
+        (4, 24, true),
+        (24, 26, true),
+        (26, 28, true),
+        (28, 31, true),
+        (31, 32, true),
+        (32, 34, false),
+        (34, 37, true),
+    ]);
+
+    assert_eq!(graph.edges, [
+        edge(0, 2, EdgeKind::Jump),
+        edge(0, 1, EdgeKind::FallThrough),
+        edge(1, 3, EdgeKind::Switch { key: Some(1) }),
+        edge(1, 4, EdgeKind::Switch { key: None }),
+        edge(4, 5, EdgeKind::FallThrough),
+        edge(4, 7, EdgeKind::Exception { catch_type: Some("java/lang/ArithmeticException".to_string()) }),
+    ]);
+
+    // Only the code after the last return can't be reached, as the handler is
+    // reached from the division.
+    let unreachable: Vec<u32> = graph.unreachable_blocks().iter().map(|b| b.start).collect();
+    assert_eq!(unreachable, [32]);
+    assert_eq!(graph.block_at(30).unwrap().id, 4);
+    assert_eq!(graph.predecessors(7), [4]);
+}
+
+#[test]
+fn graphs_are_printed_as_dot() {
+    let class = temp_file("F.class", &assemble(BRANCHES).unwrap().to_bytes());
+
+    let (ok, stdout) = run_stdout(&["--cfg", "F.f", "--format", "dot", class.to_str().unwrap()]);
+    assert!(ok);
+    assert!(stdout.starts_with("digraph \"f(I)I\" {\n"), "{}", stdout);
+    for line in &[
+        "    b1 [label=\"4: iload_0\\l5: tableswitch 1 [26] default 28\\l\"];",
+        "    b6 [label=\"32: iconst_3\\l33: ireturn\\l\", style=dashed, color=gray];",
+        "    b1 -> b4 [label=\"default\"];",
+        "    b4 -> b7 [style=dashed, color=red, label=\"java/lang/ArithmeticException\"];",
+    ] {
+        assert!(stdout.lines().any(|l| l == *line), "{} not in {}", line, stdout);
+    }
+
+    let output = run(&["--cfg", "F.g", class.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "No method matches 'F.g'\n");
+
+    fs::remove_file(class).unwrap();
+}
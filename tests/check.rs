@@ -0,0 +1,163 @@
+// Format checking: problems a JVM would reject a class for when loading it,
+// each reported with where it is.
+
+extern crate class2json;
+extern crate serde_json;
+extern crate zip;
+
+mod common;
+
+use std::fs;
+
+use serde_json::{json, Value};
+
+use class2json::assembly::assemble::assemble;
+use class2json::class_file::check::check;
+
+use common::{jar, run, temp_file, SUM};
+
+// A Class entry naming an Integer, a duplicate field, a field of no type and
+// a method of a reserved name.
+const MALFORMED: &str = r#"
+.version 52 0
+.class public super p/K
+.super java/lang/Object
+
+.field public a I
+.end field
+.field public a I
+.end field
+.field public b Q
+.end field
+
+.method public <bad> : ()V
+    .code stack 0 locals 1
+        return
+    .end code
+.end method
+
+.method public f : ()V
+    .code stack 1 locals 1
+        ldc [1]
+        pop
+        return
+    .end code
+.end method
+
+.const [1] = Class [2]
+.const [2] = Int 5
+"#;
+
+// A constant and an attribute of later releases than the class's.
+const TOO_OLD: &str = r#"
+.version 50 0
+.class public super p/V
+.super java/lang/Object
+.nesthost p/Outer
+
+.const [1] = MethodType "()V"
+"#;
+
+// Access flags that can't be combined, on a class and on an interface.
+const FLAGS: &str = "
+.version 52 0
+.class public final abstract super p/F
+.super java/lang/Object
+
+.field public private a I
+.end field
+.field public final volatile b I
+.end field
+
+.method public private native f : ()V
+.end method
+.method private abstract g : ()V
+.end method
+.method public static <init> : ()V
+    .code stack 0 locals 0
+        return
+    .end code
+.end method
+";
+
+const INTERFACE_FLAGS: &str = "
+.version 52 0
+.class public interface p/G
+.super java/lang/Object
+
+.field public x I
+.end field
+
+.method protected abstract m : ()V
+.end method
+";
+
+fn diagnostics(listing: &str) -> Vec<(String, String)> {
+    check(&assemble(listing).unwrap())
+        .into_iter()
+        .map(|diagnostic| (diagnostic.location, diagnostic.message))
+        .collect()
+}
+
+fn pairs(expected: &[(&str, &str)]) -> Vec<(String, String)> {
+    expected.iter().map(|&(location, message)| (location.to_string(), message.to_string())).collect()
+}
+
+#[test]
+fn problems_are_reported_with_their_location() {
+    assert_eq!(diagnostics(MALFORMED), pairs(&[
+        ("constant pool #1", "name_index 2 refers to a CONSTANT_Integer, not CONSTANT_Utf8"),
+        ("field a I", "duplicate field"),
+        ("field b Q", "invalid field descriptor \"Q\""),
+        ("method <bad>()V", "invalid method name \"<bad>\""),
+    ]));
+
+    assert_eq!(diagnostics(TOO_OLD), pairs(&[
+        ("constant pool #1", "CONSTANT_MethodType entries need class files of Java 7 or later"),
+        ("class, NestHost attribute", "NestHost attributes need class files of Java 11 or later, so are ignored"),
+    ]));
+}
+
+#[test]
+fn access_flags_that_cannot_be_combined_are_reported() {
+    assert_eq!(diagnostics(FLAGS), pairs(&[
+        ("class", "classes can't be both final and abstract"),
+        ("field a I", "fields can only be one of public, private and protected"),
+        ("field b I", "fields can't be both final and volatile"),
+        ("method f()V", "methods can only be one of public, private and protected"),
+        ("method g()V", "abstract methods can't be private"),
+        ("method <init>()V", "<init> methods can't be static"),
+    ]));
+
+    assert_eq!(diagnostics(INTERFACE_FLAGS), pairs(&[
+        ("class", "interfaces must be abstract"),
+        ("field x I", "interface fields must be public, static and final, and nothing else but synthetic"),
+        ("method m()V", "interface methods must be either public or private"),
+        ("method m()V", "interface methods can't be protected"),
+    ]));
+}
+
+#[test]
+fn check_reports_each_class_and_fails_on_problems() {
+    let malformed = assemble(MALFORMED).unwrap().to_bytes();
+    let path = temp_file("check.jar", &jar(&[("Sum.class", SUM), ("p/K.class", &malformed)]));
+    let name = path.file_name().unwrap().to_str().unwrap();
+
+    let output = run(&["--check", path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let entries: Vec<Value> = stdout.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+
+    // Classes without problems aren't listed.
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0]["path"], format!("{}!/p/K.class", name));
+    assert_eq!(entries[0]["diagnostics"][1], json!({"location": "field a I", "message": "duplicate field"}));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.ends_with("Checked 2 classes from 1 root, 1 with problems\n"), "{}", stderr);
+
+    let output = run(&["--check", "class_files/Sum.class"]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+
+    fs::remove_file(path).unwrap();
+}
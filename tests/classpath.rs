@@ -0,0 +1,44 @@
+// Reading the classes of directories, archives and the archives nested in
+// them, as roots of a class path.
+
+extern crate class2json;
+extern crate serde_json;
+extern crate zip;
+
+mod common;
+
+use std::fs;
+
+use serde_json::Value;
+
+use common::{jar, run, temp_file, SUM};
+
+#[test]
+fn nested_archive_entries_have_the_path_of_every_archive() {
+    let lib = jar(&[("p/B.class", SUM)]);
+    let fat = jar(&[
+        ("BOOT-INF/classes/p/A.class", SUM),
+        ("BOOT-INF/lib/x.jar", &lib),
+        ("BOOT-INF/lib/bad.jar", b"PK\x03\x04garbage"),
+    ]);
+    let path = temp_file("fat.jar", &fat);
+    let name = path.file_name().unwrap().to_str().unwrap();
+
+    let output = run(&[path.to_str().unwrap()]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let entries: Vec<Value> = stdout.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    let paths: Vec<&str> = entries.iter().map(|e| e["path"].as_str().unwrap()).collect();
+    assert_eq!(paths, [
+        format!("{}!/BOOT-INF/classes/p/A.class", name),
+        format!("{}!/BOOT-INF/lib/x.jar!/p/B.class", name),
+        format!("{}!/BOOT-INF/lib/bad.jar", name),
+    ]);
+    assert!(entries[2]["error"].is_string());
+
+    // Errors name the entry the same way, next to the archive.
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let bad = path.with_file_name(paths[2]);
+    assert!(stderr.starts_with(&format!("{}: ", bad.display())), "{}", stderr);
+
+    fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,62 @@
+// Helpers shared by the integration tests. Each test binary uses only some
+// of them.
+#![allow(dead_code)]
+
+use std::fs;
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use class2json::assembly::assemble::assemble;
+use class2json::class_file::ClassFile;
+
+pub const SUM: &[u8] = include_bytes!("../../class_files/Sum.class");
+
+pub fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_class2json")).args(args).output().unwrap()
+}
+
+// Whether the run succeeded, and its standard output.
+pub fn run_stdout(args: &[&str]) -> (bool, String) {
+    let output = run(args);
+    (output.status.success(), String::from_utf8(output.stdout).unwrap())
+}
+
+// A zip archive of the entries, in order.
+pub fn jar(entries: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    for &(name, bytes) in entries {
+        zip.start_file(name, zip::write::FileOptions::default()).unwrap();
+        zip.write_all(bytes).unwrap();
+    }
+
+    zip.finish().unwrap().into_inner()
+}
+
+// The listing of an empty public class of the version, extending Object.
+// Members can be added after it.
+pub fn class_listing(name: &str, major: u16, minor: u16) -> String {
+    format!(".version {} {}\n.class public super {}\n.super java/lang/Object\n", major, minor, name)
+}
+
+pub fn class(name: &str, major: u16, minor: u16) -> ClassFile {
+    assemble(&class_listing(name, major, minor)).unwrap()
+}
+
+pub fn temp_file(name: &str, bytes: &[u8]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("class2json-{}-{}", std::process::id(), name));
+    fs::write(&path, bytes).unwrap();
+    path
+}
+
+// Whether sources in a directory compile, or None without a javac to compile
+// them with.
+pub fn javac(dir: &Path, sources: &[&str]) -> Option<bool> {
+    let mut javac = Command::new("javac");
+    javac.arg("-d").arg(dir.join("javac-out"));
+    for source in sources {
+        javac.arg(dir.join(source));
+    }
+
+    javac.output().ok().map(|output| output.status.success())
+}
@@ -0,0 +1,231 @@
+// Compatibility between two versions of a library. The jars are assembled
+// here, with one class for each kind of change, or a few for members.
+
+extern crate class2json;
+extern crate serde_json;
+extern crate zip;
+
+mod common;
+
+use std::fs;
+
+use serde_json::Value;
+
+use class2json::assembly::assemble::assemble;
+
+use common::{jar, run, temp_file};
+
+// Methods other than constructors are native, so they need no code.
+const CONSTRUCTOR: &str = "
+.method public <init> : ()V
+    .code stack 1 locals 1
+        aload_0
+        invokespecial Method java/lang/Object <init> ()V
+        return
+    .end code
+.end method
+";
+
+fn class(flags: &str, name: &str, body: &str) -> (String, String) {
+    let listing = format!(".version 52 0\n.class {} {}\n{}\n", flags, name, body);
+    (name.to_string(), listing)
+}
+
+fn inner(flags: &str) -> (String, String) {
+    let body = format!(".super java/lang/Object\n.innerclasses\n    p/Outer$Inner p/Outer Inner {}\n.end innerclasses", flags);
+    class("public super", "p/Outer$Inner", &body)
+}
+
+fn old_classes() -> Vec<(String, String)> {
+    vec![
+        class("public super", "p/Same", ".super java/lang/Object"),
+        class("public super", "p/Removed", ".super java/lang/Object"),
+        class("public super", "p/Hidden", ".super java/lang/Object"),
+        class("public abstract super", "p/Kind", ".super java/lang/Object"),
+        class("public super", "p/Abstract", ".super java/lang/Object"),
+        class("public super", "p/Final", ".super java/lang/Object"),
+        class("public super", "p/Outer", ".super java/lang/Object"),
+        inner("public static"),
+        class("public super", "p/Base", ".super java/lang/Object"),
+        class("public super", "p/Sub", ".super p/Base"),
+        class("public super", "p/Impl", ".super java/lang/Object\n.implements java/lang/Runnable"),
+        class("public super", "p/Fields", "
+.super java/lang/Object
+.field public removed I
+.end field
+.field public hidden I
+.end field
+.field public type I
+.end field
+.field public moved I
+.end field
+.field public fixed I
+.end field
+.field public static final VALUE I
+    .constantvalue Int 1
+.end field
+"),
+        class("public super", "p/Methods", &format!("
+.super java/lang/Object
+{}
+.method public native removed : ()V
+.end method
+.method public native type : ()I
+.end method
+.method public native hidden : ()V
+.end method
+.method public native moved : ()V
+.end method
+.method public native fixed : ()V
+.end method
+", CONSTRUCTOR)),
+        class("public abstract super", "p/Shape", &format!("
+.super java/lang/Object
+{}
+.method public native area : ()D
+.end method
+", CONSTRUCTOR)),
+    ]
+}
+
+fn new_classes() -> Vec<(String, String)> {
+    vec![
+        class("public super", "p/Same", ".super java/lang/Object"),
+        class("super", "p/Hidden", ".super java/lang/Object"),
+        class("public interface abstract", "p/Kind", ".super java/lang/Object"),
+        class("public abstract super", "p/Abstract", ".super java/lang/Object"),
+        class("public final super", "p/Final", ".super java/lang/Object"),
+        class("public super", "p/Outer", ".super java/lang/Object"),
+        inner("public"),
+        class("public super", "p/Base", ".super java/lang/Object"),
+        class("public super", "p/Sub", ".super java/lang/Object"),
+        class("public super", "p/Impl", ".super java/lang/Object"),
+        class("public super", "p/Fields", "
+.super java/lang/Object
+.field private hidden I
+.end field
+.field public type J
+.end field
+.field public static moved I
+.end field
+.field public final fixed I
+.end field
+.field public static final VALUE I
+    .constantvalue Int 2
+.end field
+"),
+        class("public super", "p/Methods", &format!("
+.super java/lang/Object
+{}
+.method public native type : ()J
+.end method
+.method private native hidden : ()V
+.end method
+.method public static native moved : ()V
+.end method
+.method public final native fixed : ()V
+.end method
+", CONSTRUCTOR)),
+        class("public abstract super", "p/Shape", &format!("
+.super java/lang/Object
+{}
+.method public abstract area : ()D
+.end method
+.method public abstract perimeter : ()D
+.end method
+", CONSTRUCTOR)),
+    ]
+}
+
+fn assembled_jar(name: &str, classes: &[(String, String)]) -> String {
+    let classes: Vec<(String, Vec<u8>)> = classes
+        .iter()
+        .map(|(name, listing)| (format!("{}.class", name), assemble(listing).unwrap().to_bytes()))
+        .collect();
+    let entries: Vec<(&str, &[u8])> = classes.iter().map(|(name, bytes)| (&name[..], &bytes[..])).collect();
+
+    temp_file(name, &jar(&entries)).to_str().unwrap().to_string()
+}
+
+#[test]
+fn reports_each_kind_of_change() {
+    let old = assembled_jar("compat-old.jar", &old_classes());
+    let new = assembled_jar("compat-new.jar", &new_classes());
+
+    let output = run(&["compat", &old, &new]);
+    assert_eq!(output.status.code(), Some(1));
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["binary_compatible"], false);
+    assert_eq!(report["source_compatible"], false);
+
+    let mut changes: Vec<(String, String, String, String)> = report["changes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| {
+            let field = |key: &str| c[key].as_str().unwrap_or_default().to_string();
+            (field("kind"), field("breaks"), field("class"), field("member"))
+        })
+        .collect();
+    changes.sort();
+
+    let mut expected = vec![
+        ("class_removed", "binary", "p/Removed", ""),
+        ("class_less_accessible", "binary", "p/Hidden", ""),
+        ("kind_changed", "binary", "p/Kind", ""),
+        ("class_now_abstract", "binary", "p/Abstract", ""),
+        ("class_now_final", "binary", "p/Final", ""),
+        ("class_no_longer_static", "binary", "p/Outer$Inner", ""),
+        ("superclass_removed", "binary", "p/Sub", ""),
+        ("interface_removed", "binary", "p/Impl", ""),
+        ("field_removed", "binary", "p/Fields", "removed:I"),
+        ("field_less_accessible", "binary", "p/Fields", "hidden:I"),
+        ("field_type_changed", "binary", "p/Fields", "type:I"),
+        ("field_static_changed", "binary", "p/Fields", "moved:I"),
+        ("field_now_final", "binary", "p/Fields", "fixed:I"),
+        ("constant_value_changed", "binary", "p/Fields", "VALUE:I"),
+        ("method_removed", "binary", "p/Methods", "removed()V"),
+        ("method_descriptor_changed", "binary", "p/Methods", "type()I"),
+        ("method_less_accessible", "binary", "p/Methods", "hidden()V"),
+        ("method_static_changed", "binary", "p/Methods", "moved()V"),
+        ("method_now_final", "binary", "p/Methods", "fixed()V"),
+        ("method_now_abstract", "binary", "p/Shape", "area()D"),
+        ("abstract_method_added", "source", "p/Shape", "perimeter()D"),
+    ];
+    expected.sort();
+
+    let expected: Vec<(String, String, String, String)> = expected
+        .into_iter()
+        .map(|(a, b, c, d)| (a.to_string(), b.to_string(), c.to_string(), d.to_string()))
+        .collect();
+    assert_eq!(changes, expected);
+
+    for path in &[old, new] {
+        fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn source_incompatibilities_alone_keep_binary_compatibility() {
+    let old = assembled_jar("compat-source-old.jar", &[old_classes().pop().unwrap()]);
+    let mut shape = new_classes().pop().unwrap();
+    shape.1 = shape.1.replace("public abstract area", "public native area");
+    let new = assembled_jar("compat-source-new.jar", &[shape]);
+
+    let output = run(&["compat", &old, &new]);
+    assert_eq!(output.status.code(), Some(1));
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["binary_compatible"], true);
+    assert_eq!(report["source_compatible"], false);
+    assert_eq!(report["changes"][0]["message"], "p/Shape.perimeter()D was added without an implementation");
+
+    // A version against itself has no changes.
+    let output = run(&["compat", &old, &old]);
+    assert!(output.status.success());
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["changes"], serde_json::json!([]));
+
+    for path in &[old, new] {
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,111 @@
+// Dependencies between the classes, packages and modules of two jars, with a
+// package split between them, a cycle between packages and a use of JDK
+// internals.
+
+extern crate class2json;
+extern crate serde_json;
+extern crate zip;
+
+mod common;
+
+use std::collections::HashMap;
+use std::fs;
+
+use serde_json::{json, Value};
+
+use class2json::analysis::deps::DependencyAnalysis;
+use class2json::assembly::assemble::assemble;
+use class2json::class_file::ClassFile;
+
+use common::{class_listing, jar, run_stdout, temp_file};
+
+fn class_with_fields(name: &str, fields: &[&str]) -> ClassFile {
+    let mut listing = class_listing(name, 52, 0);
+    for (i, descriptor) in fields.iter().enumerate() {
+        listing.push_str(&format!(".field public f{} {}\n.end field\n", i, descriptor));
+    }
+
+    assemble(&listing).unwrap()
+}
+
+// p/A and q/Q depend on each other, and p/B in another jar is in p too.
+fn a_classes() -> Vec<ClassFile> {
+    vec![
+        class_with_fields("p/A", &["Lq/Q;", "Ljava/sql/Connection;", "Lsun/misc/Unsafe;"]),
+        class_with_fields("q/Q", &["Lp/A;"]),
+    ]
+}
+
+fn b_classes() -> Vec<ClassFile> {
+    vec![class_with_fields("p/B", &["Lmissing/X;"])]
+}
+
+#[test]
+fn reports_split_packages_cycles_and_internals() {
+    let mut analysis = DependencyAnalysis::new();
+    for cf in a_classes() {
+        analysis.add_class(&cf, "a.jar");
+    }
+    for cf in b_classes() {
+        analysis.add_class(&cf, "b.jar");
+    }
+
+    let platform: HashMap<String, String> = [
+        ("java/lang/Object", "java.base"),
+        ("java/sql/Connection", "java.sql"),
+        ("sun/misc/Unsafe", "jdk.unsupported"),
+    ]
+    .iter()
+    .map(|&(class, module)| (class.to_string(), module.to_string()))
+    .collect();
+    let report = serde_json::to_value(analysis.report(&platform)).unwrap();
+
+    assert_eq!(report["classes"][0], json!({
+        "name": "p/A",
+        "module": "a.jar",
+        "dependencies": ["java/lang/Object", "java/sql/Connection", "q/Q", "sun/misc/Unsafe"],
+    }));
+    assert_eq!(report["packages"], json!([
+        {"name": "p", "dependencies": ["java.lang", "java.sql", "missing", "q", "sun.misc"]},
+        {"name": "q", "dependencies": ["java.lang", "p"]},
+    ]));
+    assert_eq!(report["modules"], json!([
+        {"name": "a.jar", "dependencies": ["java.base", "java.sql", "jdk.unsupported"]},
+        {"name": "b.jar", "dependencies": ["java.base"]},
+    ]));
+    assert_eq!(report["split_packages"], json!([{"package": "p", "modules": ["a.jar", "b.jar"]}]));
+    assert_eq!(report["package_cycles"], json!([["p", "q"]]));
+    assert_eq!(report["internal_dependencies"], json!([
+        {"class": "p/A", "dependency": "sun/misc/Unsafe", "package": "sun.misc"},
+    ]));
+    assert_eq!(report["not_found"], json!(["missing/X"]));
+    assert_eq!(report["requires"], json!(["java.sql", "jdk.unsupported"]));
+}
+
+#[test]
+fn classes_of_each_jar_on_the_class_path_are_in_its_module() {
+    let entries = |classes: Vec<ClassFile>| -> Vec<(String, Vec<u8>)> {
+        classes.iter().map(|cf| (format!("{}.class", cf.name().unwrap()), cf.to_bytes())).collect()
+    };
+    let write = |name: &str, classes: Vec<(String, Vec<u8>)>| {
+        let entries: Vec<(&str, &[u8])> = classes.iter().map(|(name, bytes)| (&name[..], &bytes[..])).collect();
+        temp_file(name, &jar(&entries))
+    };
+    let a = write("deps-a.jar", entries(a_classes()));
+    let b = write("deps-b.jar", entries(b_classes()));
+
+    let classpath = format!("{}:{}", a.display(), b.display());
+    let (ok, stdout) = run_stdout(&["--deps", "--classpath", &classpath]);
+    assert!(ok);
+    let report: Value = serde_json::from_str(&stdout).unwrap();
+
+    let modules = json!([a.display().to_string(), b.display().to_string()]);
+    assert_eq!(report["split_packages"], json!([{"package": "p", "modules": modules}]));
+    assert_eq!(report["package_cycles"], json!([["p", "q"]]));
+    // Without --image, nothing is known of the JDK's classes.
+    assert!(report["not_found"].as_array().unwrap().contains(&json!("java/sql/Connection")));
+
+    for path in &[a, b] {
+        fs::remove_file(path).unwrap();
+    }
+}
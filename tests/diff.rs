@@ -0,0 +1,184 @@
+// Structural differences between two versions of a class, assembled here so
+// that each kind of change is made once: elements and members added, removed
+// and changed.
+
+extern crate class2json;
+extern crate serde_json;
+
+mod common;
+
+use std::fs;
+
+use class2json::assembly::assemble::assemble;
+use class2json::class_file::ClassFile;
+use class2json::diff::{diff, ChangeKind, ClassDiff, DiffOptions};
+
+use common::{run, temp_file};
+
+const OLD: &str = r#".version 52 0
+.class public super p/X
+.super java/lang/Object
+.sourcefile "X.java"
+
+.field public kept I
+.end field
+
+.field public removed I
+.end field
+
+.method public static f : (I)I
+    .code stack 2 locals 1
+        iload_0
+        iconst_1
+        iadd
+        ireturn
+    .end code
+.end method
+
+.method public static g : ()Ljava/lang/String;
+    .code stack 1 locals 0
+        ldc String "g"
+        areturn
+    .end code
+.end method
+"#;
+
+// The members come in another order, so the constant pool is numbered
+// differently too.
+const NEW: &str = r#".version 52 0
+.class public final super p/X
+.super java/lang/Object
+.implements java/lang/Runnable
+
+.method public static g : ()Ljava/lang/String;
+    .code stack 1 locals 0
+L0:     ldc String "g"
+        areturn
+        .linenumbertable
+            L0 7
+        .end linenumbertable
+    .end code
+.end method
+
+.method public static f : (I)I
+    .code stack 2 locals 1
+        iload_0
+        iconst_2
+        iadd
+        ireturn
+    .end code
+.end method
+
+.field public volatile kept I
+.end field
+
+.field public added J
+.end field
+
+.method public run : ()V
+    .code stack 0 locals 1
+        return
+    .end code
+.end method
+"#;
+
+fn class(listing: &str) -> ClassFile {
+    assemble(listing).unwrap()
+}
+
+// A difference as its change, member, element, old and new values and lines.
+type Summary<'a> = (ChangeKind, Option<&'a str>, Option<&'a str>, Option<&'a str>, Option<&'a str>, Vec<&'a str>);
+
+fn summary(report: &ClassDiff) -> Vec<Summary<'_>> {
+    report
+        .differences
+        .iter()
+        .map(|d| {
+            (
+                d.change,
+                d.member.as_deref(),
+                d.element.as_deref(),
+                d.old.as_deref(),
+                d.new.as_deref(),
+                d.lines.iter().map(|line| &line[..]).collect(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn reports_elements_and_members_added_removed_and_changed() {
+    let report = diff(&class(OLD), &class(NEW), &DiffOptions::default());
+
+    assert_eq!(summary(&report), vec![
+        (ChangeKind::Removed, None, Some("SourceFile"), Some("X.java"), None, vec![]),
+        (ChangeKind::Changed, None, Some("access_flags"), Some("public super"), Some("public final super"), vec![]),
+        (ChangeKind::Added, None, Some("interfaces"), None, Some("java/lang/Runnable"), vec![]),
+        (ChangeKind::Added, Some("field added J"), None, None, None, vec![]),
+        (ChangeKind::Changed, Some("field kept I"), Some("access_flags"), Some("public"), Some("public volatile"), vec![]),
+        (ChangeKind::Removed, Some("field removed I"), None, None, None, vec![]),
+        (ChangeKind::Changed, Some("method f (I)I"), Some("Code"), None, None, vec![
+            "@@ -1,5 +1,5 @@",
+            " stack 2 locals 1",
+            "     iload_0",
+            "-    iconst_1",
+            "+    iconst_2",
+            "     iadd",
+            "     ireturn",
+        ]),
+        (ChangeKind::Changed, Some("method g ()Ljava/lang/String;"), Some("Code"), None, None, vec![
+            "@@ -1,3 +1,4 @@",
+            " stack 1 locals 0",
+            "+    .line 7",
+            "     ldc String \"g\"",
+            "     areturn",
+        ]),
+        (ChangeKind::Added, Some("method run ()V"), None, None, None, vec![]),
+    ]);
+
+    // Without debugging information, g is the same and the source file
+    // makes no difference.
+    let options = DiffOptions { ignore_debug: true };
+    let report = diff(&class(OLD), &class(NEW), &options);
+    let members: Vec<_> = report.differences.iter().map(|d| (d.member.as_deref(), d.element.as_deref())).collect();
+    assert_eq!(members, [
+        (None, Some("access_flags")),
+        (None, Some("interfaces")),
+        (Some("field added J"), None),
+        (Some("field kept I"), Some("access_flags")),
+        (Some("field removed I"), None),
+        (Some("method f (I)I"), Some("Code")),
+        (Some("method run ()V"), None),
+    ]);
+}
+
+#[test]
+fn renumbered_constant_pools_make_no_difference() {
+    // The same members, with the methods first.
+    let (fields, methods) = OLD.split_at(OLD.find(".method").unwrap());
+    let (header, fields) = fields.split_at(fields.find(".field").unwrap());
+    let reordered = format!("{}{}\n{}", header, methods, fields);
+
+    let old = class(OLD);
+    let renumbered = class(&reordered);
+    assert!(old.to_bytes() != renumbered.to_bytes());
+    assert!(diff(&old, &renumbered, &DiffOptions::default()).is_empty());
+
+    let old_path = temp_file("diff-old.class", &old.to_bytes());
+    let new_path = temp_file("diff-new.class", &class(NEW).to_bytes());
+    let (old_path, new_path) = (old_path.to_str().unwrap(), new_path.to_str().unwrap());
+
+    let output = run(&["diff", old_path, old_path]);
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["differences"], serde_json::json!([]));
+
+    let output = run(&["diff", old_path, new_path]);
+    assert_eq!(output.status.code(), Some(1));
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["differences"][0], serde_json::json!({"change": "removed", "element": "SourceFile", "old": "X.java"}));
+
+    for path in &[old_path, new_path] {
+        fs::remove_file(path).unwrap();
+    }
+}
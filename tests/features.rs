@@ -0,0 +1,97 @@
+// The Java release of classes, the version-gated features they use and the
+// release that loads them all.
+
+extern crate class2json;
+extern crate serde_json;
+extern crate zip;
+
+mod common;
+
+use std::fs;
+
+use serde_json::{json, Value};
+
+use class2json::analysis::features::FeatureAnalysis;
+use class2json::assembly::assemble::assemble;
+use class2json::class_file::reader::read_classfile;
+use class2json::class_file::ClassFile;
+
+use common::{class, jar, run_stdout, temp_file, SUM};
+
+// A subroutine, which only class files before Java 7 may have.
+fn subroutine(major: u16) -> ClassFile {
+    let listing = format!(
+        ".version {} 0
+.class public super p/Jsr
+.super java/lang/Object
+
+.method static f : ()V
+    .code stack 1 locals 1
+        jsr S
+        return
+S:      astore_0
+        ret 0
+    .end code
+.end method
+",
+        major
+    );
+    assemble(&listing).unwrap()
+}
+
+fn sealed() -> ClassFile {
+    read_classfile(&mut &fs::read("class_files/sealed/Shape.class").unwrap()[..]).unwrap()
+}
+
+#[test]
+fn releases_features_and_problems_of_classes() {
+    let mut analysis = FeatureAnalysis::new();
+    analysis.add_class(&read_classfile(&mut &SUM[..]).unwrap(), "Sum.class", None);
+    analysis.add_class(&subroutine(50), "p/Jsr.class", None);
+    analysis.add_class(&subroutine(52), "p/Jsr.class", None);
+    analysis.add_class(&sealed(), "Shape.class", None);
+    analysis.add_class(&class("p/Preview", 61, 0xffff), "p/Preview.class", None);
+    analysis.add_class(&class("p/Later", 61, 0), "META-INF/versions/11/p/Later.class", Some(11));
+    let report = serde_json::to_value(analysis.report()).unwrap();
+
+    let summary: Vec<Value> = report["classes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| json!([c["release"], c["preview"], c["features"], c["problems"]]))
+        .collect();
+    assert_eq!(summary, [
+        json!(["8", null, [], null]),
+        json!(["6", null, ["subroutines"], null]),
+        json!(["8", null, ["subroutines"], ["uses jsr and ret, which class files of Java 8 can't"]]),
+        json!(["17", null, ["sealed_type"], null]),
+        json!(["17", true, [], null]),
+        json!(["17", null, [], ["needs Java 17 but is versioned for Java 11"]]),
+    ]);
+
+    // Versioned classes don't count towards the minimum, but a preview class
+    // makes it exact.
+    assert_eq!(report["minimum_release"], "17");
+    assert_eq!(report["preview"], true);
+    assert_eq!(report["minimum_release_classes"], json!(["Shape", "p/Preview"]));
+}
+
+#[test]
+fn minimum_release_of_a_jar() {
+    let bytes = |cf: ClassFile| cf.to_bytes();
+    let path = temp_file("features.jar", &jar(&[
+        ("Sum.class", SUM),
+        ("p/Old.class", &bytes(class("p/Old", 45, 3))),
+        ("p/Eleven.class", &bytes(class("p/Eleven", 55, 0))),
+    ]));
+
+    let (ok, stdout) = run_stdout(&["--features", path.to_str().unwrap()]);
+    assert!(ok);
+    let report: Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(report["classes"][1]["release"], "1.1");
+    assert_eq!(report["minimum_release"], "11");
+    assert_eq!(report["minimum_release_classes"], json!(["p/Eleven"]));
+    assert!(report.get("preview").is_none());
+
+    fs::remove_file(path).unwrap();
+}
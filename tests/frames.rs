@@ -0,0 +1,261 @@
+// Inferring the types of a method's stack and locals, and recomputing its
+// StackMapTable when writing.
+
+extern crate class2json;
+extern crate serde_json;
+extern crate zip;
+
+mod common;
+
+use std::fs;
+use std::io::{Cursor, Write};
+
+use class2json::assembly::assemble::assemble;
+use class2json::bytecode::frames::{Frame, FrameAnalysis, Type};
+use class2json::bytecode::hierarchy::ClassHierarchyMap;
+use class2json::class_file::attributes::read_code;
+
+use common::{run, run_stdout, temp_file};
+
+// Returns one of two arrays whose components only have Number in common, with
+// no StackMapTable.
+const PICK: &str = "
+.version 52 0
+.class public super p/Pick
+.super java/lang/Object
+
+.method static pick : (Z)[Ljava/lang/Number;
+    .code stack 1 locals 1
+        iload_0
+        ifeq L11
+        iconst_1
+        anewarray java/lang/Integer
+        goto L15
+L11:    iconst_1
+        anewarray java/lang/Long
+L15:    areturn
+    .end code
+.end method
+";
+
+// A jar of the classes the frames of PICK merge.
+fn numbers_jar() -> Vec<u8> {
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let classes = [
+        ("java/lang/Number", "java/lang/Object"),
+        ("java/lang/Integer", "java/lang/Number"),
+        ("java/lang/Long", "java/lang/Number"),
+    ];
+
+    for (name, superclass) in &classes {
+        let source = format!(".version 52 0\n.class public super {}\n.super {}\n", name, superclass);
+        zip.start_file(format!("{}.class", name), zip::write::FileOptions::default()).unwrap();
+        zip.write_all(&assemble(&source).unwrap().to_bytes()).unwrap();
+    }
+    zip.finish().unwrap().into_inner()
+}
+
+#[test]
+fn computed_frames_merge_classes_from_the_class_path() {
+    let listing = temp_file("Pick.j", PICK.as_bytes());
+    let jar = temp_file("numbers.jar", &numbers_jar());
+    let output = std::env::temp_dir().join(format!("class2json-{}-Pick.class", std::process::id()));
+    let (listing, jar, output) = (listing.to_str().unwrap(), jar.to_str().unwrap(), output.to_str().unwrap());
+
+    let written = run(&["-a", listing, "-o", output, "--compute-frames", "--classpath", jar]);
+    assert!(written.status.success());
+    assert!(written.stderr.is_empty());
+    let listed = run(&["-f", "asm", output]);
+    assert!(String::from_utf8(listed.stdout).unwrap().contains(".stack same_locals_1_stack_item Object [Ljava/lang/Number;"));
+
+    // Without the class path the merge falls back to Object, with a warning.
+    let written = run(&["-a", listing, "-o", output, "--compute-frames"]);
+    assert!(written.status.success());
+    assert!(String::from_utf8(written.stderr).unwrap().contains("java/lang/Integer, java/lang/Long"));
+    let listed = run(&["-f", "asm", output]);
+    assert!(String::from_utf8(listed.stdout).unwrap().contains(".stack same_locals_1_stack_item Object [Ljava/lang/Object;"));
+
+    for path in &[listing, jar, output] {
+        fs::remove_file(path).unwrap();
+    }
+}
+
+// Joins branches whose locals hold different arrays, a float and an int, and
+// null and a String. Version 49, so there is no StackMapTable to check.
+const MERGE: &str = "
+.version 49 0
+.class public super p/Merge
+.super java/lang/Object
+
+.method static merge : (Z)V
+    .code stack 1 locals 4
+        iload_0
+        ifeq L16
+        iconst_1
+        anewarray java/lang/Integer
+        astore_1
+        fconst_0
+        fstore_2
+        aconst_null
+        astore_3
+        goto L26
+L16:    iconst_1
+        anewarray java/lang/Long
+        astore_1
+        iconst_0
+        istore_2
+        ldc String \"s\"
+        astore_3
+L26:    return
+    .end code
+.end method
+";
+
+// A constructor that calls its superclass's and creates another instance.
+const POINT: &str = "
+.version 49 0
+.class public super p/Point
+.super java/lang/Object
+
+.method <init> : ()V
+    .code stack 2 locals 2
+        aload_0
+        invokespecial Method java/lang/Object <init> ()V
+        new p/Point
+        dup
+        invokespecial Method p/Point <init> ()V
+        astore_1
+        return
+    .end code
+.end method
+
+.method static wide : (JD)V
+    .code stack 4 locals 5
+        lload_0
+        dload_2
+        pop2
+        pop2
+        iconst_0
+        istore_1
+        return
+    .end code
+.end method
+";
+
+// Frames that disagree with the code: local 1 is an int, not a float, the
+// stack is empty, and the third branch target has no frame.
+const DECLARED: &str = "
+.version 52 0
+.class public super p/Declared
+.super java/lang/Object
+
+.method static f : (I)V
+    .code stack 1 locals 2
+        iconst_0
+        istore_1
+        iload_0
+        ifeq L7
+        nop
+        .stack full locals Integer Float stack
+L7:     iload_0
+        ifne L12
+        nop
+        .stack same_locals_1_stack_item Integer
+L12:    iload_0
+        ifeq L17
+        nop
+L17:    return
+    .end code
+.end method
+";
+
+fn analyze(listing: &str, selector: &str, hierarchy: &ClassHierarchyMap) -> FrameAnalysis {
+    let cf = assemble(listing).unwrap();
+    let method = cf.select_methods(selector)[0];
+    let code = method.attributes.decode(&cf.constant_pool, "Code", read_code).unwrap().unwrap();
+    FrameAnalysis::analyze_with(&cf, method, &code, hierarchy).unwrap()
+}
+
+fn frame(locals: Vec<Type>, stack: Vec<Type>) -> Frame {
+    Frame { locals, stack }
+}
+
+fn reference(name: &str) -> Type {
+    Type::Reference(name.to_string())
+}
+
+#[test]
+fn merges_join_the_types_of_both_branches() {
+    let mut hierarchy = ClassHierarchyMap::new();
+    hierarchy.insert("java/lang/Object", None, false);
+    hierarchy.insert("java/lang/Number", Some("java/lang/Object"), false);
+    hierarchy.insert("java/lang/Integer", Some("java/lang/Number"), false);
+    hierarchy.insert("java/lang/Long", Some("java/lang/Number"), false);
+
+    let analysis = analyze(MERGE, "merge", &hierarchy);
+    let locals = vec![Type::Integer, reference("[Ljava/lang/Number;"), Type::Top, reference("java/lang/String")];
+    assert_eq!(analysis.frame_at(26), Some(&frame(locals, vec![])));
+    assert!(analysis.mismatches.is_empty());
+
+    // Classes the hierarchy doesn't know merge to Object.
+    let analysis = analyze(MERGE, "merge", &ClassHierarchyMap::new());
+    assert_eq!(analysis.frame_at(26).unwrap().locals[1], reference("[Ljava/lang/Object;"));
+}
+
+#[test]
+fn objects_are_uninitialized_until_their_constructor_is_called() {
+    let analysis = analyze(POINT, "<init>", &ClassHierarchyMap::new());
+
+    assert_eq!(analysis.frame_at(0), Some(&frame(vec![Type::UninitializedThis, Type::Top], vec![])));
+    assert_eq!(analysis.frame_at(4), Some(&frame(vec![reference("p/Point"), Type::Top], vec![])));
+    assert_eq!(analysis.frame_at(8).unwrap().stack, [Type::Uninitialized(4), Type::Uninitialized(4)]);
+    assert_eq!(analysis.frame_at(11).unwrap().stack, [reference("p/Point")]);
+}
+
+#[test]
+fn longs_and_doubles_take_two_slots() {
+    let analysis = analyze(POINT, "wide", &ClassHierarchyMap::new());
+
+    let locals = vec![Type::Long, Type::Top, Type::Double, Type::Top, Type::Top];
+    assert_eq!(analysis.frame_at(0), Some(&frame(locals.clone(), vec![])));
+    assert_eq!(analysis.frame_at(2).unwrap().stack, [Type::Long, Type::Top, Type::Double, Type::Top]);
+
+    // Storing into the second half of the long leaves the first unusable.
+    assert_eq!(analysis.frame_at(6).unwrap().locals, [Type::Top, Type::Integer, Type::Double, Type::Top, Type::Top]);
+
+    // The JSON lists the slots the way a StackMapTable does.
+    let json = serde_json::to_value(analysis.frame_at(2).unwrap()).unwrap();
+    assert_eq!(json, serde_json::json!({"locals": ["long", "double"], "stack": ["long", "double"]}));
+}
+
+#[test]
+fn frames_that_disagree_with_the_code_are_mismatches() {
+    let analysis = analyze(DECLARED, "f", &ClassHierarchyMap::new());
+    let mismatches: Vec<(u32, &str)> = analysis.mismatches.iter().map(|m| (m.offset, &m.message[..])).collect();
+
+    // The frame at 12 keeps the float local of the one before it.
+    assert_eq!(mismatches, [
+        (17, "no StackMapTable frame at a branch target"),
+        (7, "local 1 is int but the frame declares float"),
+        (12, "local 1 is int but the frame declares float"),
+        (12, "stack has 0 slots but the frame declares 1"),
+    ]);
+}
+
+#[test]
+fn frames_are_printed_as_json() {
+    let listing = temp_file("Declared.j", DECLARED.as_bytes());
+    let class = std::env::temp_dir().join(format!("class2json-{}-Declared.class", std::process::id()));
+    let (listing, class) = (listing.to_str().unwrap(), class.to_str().unwrap());
+    assert!(run(&["-a", listing, "-o", class]).status.success());
+
+    let (_, stdout) = run_stdout(&["--frames", "f", class]);
+    let json: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    let method = &json["methods"][0];
+    assert_eq!(method["frames"][2]["locals"], serde_json::json!(["int", "int"]));
+    assert_eq!(method["mismatches"][1], serde_json::json!({"offset": 7, "message": "local 1 is int but the frame declares float"}));
+
+    for path in &[listing, class] {
+        fs::remove_file(path).unwrap();
+    }
+}
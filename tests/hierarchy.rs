@@ -0,0 +1,103 @@
+// The type hierarchy of the classes of a jar, with the supertypes missing
+// from it.
+
+extern crate class2json;
+extern crate serde_json;
+extern crate zip;
+
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+
+use serde_json::{json, Value};
+
+use class2json::assembly::assemble::assemble;
+
+use common::{jar, run_stdout, temp_file};
+
+// Two interfaces, a class implementing the second and its subclass, and a
+// class whose superclass isn't in the jar.
+fn types_jar(name: &str) -> PathBuf {
+    let types = [
+        ("public interface abstract", "p/I", "java/lang/Object", ""),
+        ("public interface abstract", "p/J", "java/lang/Object", ".implements p/I"),
+        ("public super", "p/A", "java/lang/Object", ".implements p/J"),
+        ("public super", "p/B", "p/A", ""),
+        ("public super", "p/C", "missing/M", ""),
+    ];
+    let classes: Vec<(String, Vec<u8>)> = types
+        .iter()
+        .map(|(flags, name, superclass, rest)| {
+            let listing = format!(".version 52 0\n.class {} {}\n.super {}\n{}\n", flags, name, superclass, rest);
+            (format!("{}.class", name), assemble(&listing).unwrap().to_bytes())
+        })
+        .collect();
+    let entries: Vec<(&str, &[u8])> = classes.iter().map(|(name, bytes)| (&name[..], &bytes[..])).collect();
+
+    temp_file(name, &jar(&entries))
+}
+
+#[test]
+fn hierarchy_has_every_supertype_and_the_missing_ones() {
+    let path = types_jar("hierarchy.jar");
+
+    let (ok, stdout) = run_stdout(&["--hierarchy", path.to_str().unwrap()]);
+    assert!(ok);
+    let hierarchy: Value = serde_json::from_str(&stdout).unwrap();
+    let types = hierarchy["types"].as_array().unwrap();
+    let of = |name: &str| types.iter().find(|t| t["name"] == name).unwrap().clone();
+
+    assert_eq!(types.len(), 5);
+    assert_eq!(of("p/B"), json!({
+        "name": "p/B",
+        "kind": "class",
+        "superclass": "p/A",
+        "interfaces": [],
+        "superclasses": ["p/A", "java/lang/Object"],
+        "all_interfaces": ["p/J", "p/I"],
+        "direct_subtypes": [],
+    }));
+    assert_eq!(of("p/J")["kind"], "interface");
+    assert_eq!(of("p/J")["direct_subtypes"], json!(["p/A"]));
+    assert_eq!(of("p/C")["superclasses"], json!(["missing/M"]));
+
+    assert_eq!(hierarchy["missing"], json!([
+        {"name": "java/lang/Object", "referenced_by": ["p/A", "p/I", "p/J"]},
+        {"name": "missing/M", "referenced_by": ["p/C"]},
+    ]));
+
+    let (ok, stdout) = run_stdout(&["--hierarchy", "--format", "dot", path.to_str().unwrap()]);
+    assert!(ok);
+    for line in &[
+        "    \"p/I\" [style=rounded];",
+        "    \"missing/M\" [style=dashed, color=red];",
+        "    \"p/B\" -> \"p/A\";",
+        "    \"p/A\" -> \"p/J\" [style=dashed];",
+    ] {
+        assert!(stdout.lines().any(|l| l == *line), "{} not in {}", line, stdout);
+    }
+
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn subtypes_are_direct_and_indirect() {
+    let path = types_jar("subtypes.jar");
+
+    let subtypes = |name: &str| -> Value {
+        let (ok, stdout) = run_stdout(&["--subtypes", name, path.to_str().unwrap()]);
+        assert!(ok);
+        serde_json::from_str::<Value>(&stdout).unwrap()["subtypes"].clone()
+    };
+    assert_eq!(subtypes("p.I"), json!(["p/A", "p/B", "p/J"]));
+    assert_eq!(subtypes("p/A"), json!(["p/B"]));
+    assert_eq!(subtypes("p.B"), json!([]));
+
+    // The types can be those of a class path as well.
+    let (ok, stdout) = run_stdout(&["--subtypes", "p.I", "--classpath", path.to_str().unwrap()]);
+    assert!(ok);
+    assert_eq!(serde_json::from_str::<Value>(&stdout).unwrap()["subtypes"], json!(["p/A", "p/B", "p/J"]));
+
+    fs::remove_file(path).unwrap();
+}
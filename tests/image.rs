@@ -0,0 +1,493 @@
+// Reading JMOD files and jimage runtime images. The images are built here
+// from a few small resources, in the layout the JDK's jlink writes.
+
+extern crate class2json;
+extern crate flate2;
+extern crate serde_json;
+extern crate zip;
+
+mod common;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Cursor, Write};
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use class2json::archive::jimage::{Image, Location, COMPRESSED_MAGIC, IMAGE_MAGIC};
+use class2json::assembly::assemble::assemble;
+
+use common::{run_stdout, temp_file, SUM};
+
+const FIXTURE: &str = "
+.version 52 0
+.class public super p/Fixture
+.super java/lang/Object
+
+.method public static f : (Ljava/lang/String;[Ljava/util/List;LTop;J)V
+    .code stack 2 locals 5
+        ldc2_w Long 12345678901
+        pop2
+        return
+    .end code
+.end method
+";
+
+const HASH_MULTIPLIER: i32 = 0x0100_0193;
+
+fn hash_code(name: &str, seed: i32) -> i32 {
+    let mut hash = seed;
+    for &b in name.as_bytes() {
+        hash = hash.wrapping_mul(HASH_MULTIPLIER) ^ b as i32;
+    }
+    hash & 0x7fff_ffff
+}
+
+fn fixture_class() -> Vec<u8> {
+    assemble(FIXTURE).unwrap().to_bytes()
+}
+
+enum Stored {
+    Plain,
+    Zip,
+    CompactCp,
+    // compact-cp, then zip over that.
+    Both,
+}
+
+struct ImageBuilder {
+    big_endian: bool,
+    strings: Vec<u8>,
+    string_offsets: HashMap<Vec<u8>, u32>,
+    resources: Vec<(String, Vec<u8>, Stored)>,
+}
+
+impl ImageBuilder {
+    fn new(big_endian: bool) -> ImageBuilder {
+        let mut builder = ImageBuilder {
+            big_endian,
+            strings: Vec::new(),
+            string_offsets: HashMap::new(),
+            resources: Vec::new(),
+        };
+        // Offset 0 is the empty string, standing for absent name parts.
+        builder.string(b"");
+        builder
+    }
+
+    fn add(mut self, name: &str, content: &[u8], stored: Stored) -> ImageBuilder {
+        self.resources.push((name.to_string(), content.to_vec(), stored));
+        self
+    }
+
+    fn string(&mut self, s: &[u8]) -> u32 {
+        if let Some(&offset) = self.string_offsets.get(s) {
+            return offset;
+        }
+        let offset = self.strings.len() as u32;
+        self.strings.extend_from_slice(s);
+        self.strings.push(0);
+        self.string_offsets.insert(s.to_vec(), offset);
+        offset
+    }
+
+    fn u32(&self, out: &mut Vec<u8>, value: u32) {
+        if self.big_endian {
+            out.extend_from_slice(&value.to_be_bytes());
+        } else {
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    fn u64(&self, out: &mut Vec<u8>, value: u64) {
+        if self.big_endian {
+            out.extend_from_slice(&value.to_be_bytes());
+        } else {
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    fn compress(&mut self, decompressor: &str, compressed: Vec<u8>, uncompressed_size: usize) -> Vec<u8> {
+        let name = self.string(decompressor.as_bytes());
+        let mut out = Vec::new();
+        self.u32(&mut out, COMPRESSED_MAGIC);
+        self.u64(&mut out, compressed.len() as u64);
+        self.u64(&mut out, uncompressed_size as u64);
+        self.u32(&mut out, name);
+        self.u32(&mut out, 0);
+        out.push(1);
+        out.extend_from_slice(&compressed);
+        out
+    }
+
+    fn zip(&mut self, content: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(content).unwrap();
+        let compressed = encoder.finish().unwrap();
+        self.compress("zip", compressed, content.len())
+    }
+
+    fn compact_cp(&mut self, class: &[u8]) -> Vec<u8> {
+        let mut out = class[..10].to_vec();
+        let count = u16::from_be_bytes([class[8], class[9]]);
+        let mut i = 10;
+        let mut index = 1;
+
+        while index < count {
+            let tag = class[i];
+            let size = match tag {
+                1 => {
+                    let length = u16::from_be_bytes([class[i + 1], class[i + 2]]) as usize;
+                    let utf8 = class[i + 3..i + 3 + length].to_vec();
+                    self.shared_utf8(&mut out, &utf8);
+                    i += 3 + length;
+                    index += 1;
+                    continue;
+                }
+                3 | 4 | 9 | 10 | 11 | 12 | 17 | 18 => 4,
+                5 | 6 => 8,
+                7 | 8 | 16 | 19 | 20 => 2,
+                15 => 3,
+                _ => panic!("unexpected tag {}", tag),
+            };
+            out.extend_from_slice(&class[i..i + 1 + size]);
+            i += 1 + size;
+            index += if tag == 5 || tag == 6 { 2 } else { 1 };
+        }
+
+        out.extend_from_slice(&class[i..]);
+        self.compress("compact-cp", out, class.len())
+    }
+
+    // Method descriptors become a skeleton plus package and class names,
+    // other strings a plain reference to the string table.
+    fn shared_utf8(&mut self, out: &mut Vec<u8>, utf8: &[u8]) {
+        if utf8[0] != b'(' {
+            out.push(23);
+            let offset = self.string(utf8);
+            compressed_int(out, offset);
+            return;
+        }
+
+        let text = String::from_utf8(utf8.to_vec()).unwrap();
+        let mut skeleton = String::new();
+        let mut indexes = Vec::new();
+        let mut rest = text.as_str();
+
+        while let Some(start) = rest.find('L') {
+            let end = rest[start..].find(';').unwrap() + start;
+            let name = &rest[start + 1..end];
+            let (package, class) = match name.rfind('/') {
+                Some(slash) => (&name[..slash], &name[slash + 1..]),
+                None => ("", name),
+            };
+            skeleton.push_str(&rest[..start + 1]);
+            compressed_int(&mut indexes, self.string(package.as_bytes()));
+            compressed_int(&mut indexes, self.string(class.as_bytes()));
+            rest = &rest[end..];
+        }
+        skeleton.push_str(rest);
+
+        out.push(25);
+        let offset = self.string(skeleton.as_bytes());
+        compressed_int(out, offset);
+        compressed_int(out, indexes.len() as u32);
+        out.extend_from_slice(&indexes);
+    }
+
+    fn build(mut self) -> Vec<u8> {
+        let resources = std::mem::take(&mut self.resources);
+        let count = resources.len();
+        // Offset 0 is left unused, marking empty slots of the table.
+        let mut locations = vec![0];
+        let mut location_offsets = Vec::new();
+        let mut content = Vec::new();
+
+        for (name, bytes, stored) in &resources {
+            let stored_bytes = match *stored {
+                Stored::Plain => bytes.clone(),
+                Stored::Zip => self.zip(bytes),
+                Stored::CompactCp => self.compact_cp(bytes),
+                Stored::Both => {
+                    let shared = self.compact_cp(bytes);
+                    self.zip(&shared)
+                }
+            };
+
+            // /module/parent/base.extension
+            let rest = &name[1..];
+            let (module, rest) = rest.split_at(rest.find('/').unwrap());
+            let rest = &rest[1..];
+            let (parent, file) = match rest.rfind('/') {
+                Some(slash) => (&rest[..slash], &rest[slash + 1..]),
+                None => ("", rest),
+            };
+            let (base, extension) = match file.rfind('.') {
+                Some(dot) => (&file[..dot], &file[dot + 1..]),
+                None => (file, ""),
+            };
+
+            let compressed_size = match *stored {
+                Stored::Plain => 0,
+                _ => stored_bytes.len() as u64,
+            };
+            let attributes = [
+                (1, self.string(module.as_bytes()) as u64),
+                (2, self.string(parent.as_bytes()) as u64),
+                (3, self.string(base.as_bytes()) as u64),
+                (4, self.string(extension.as_bytes()) as u64),
+                (5, content.len() as u64),
+                (6, compressed_size),
+                (7, bytes.len() as u64),
+            ];
+
+            location_offsets.push(locations.len() as u32);
+            for &(kind, value) in &attributes {
+                if value == 0 {
+                    continue;
+                }
+                let length = (8 - value.leading_zeros() as usize / 8).max(1);
+                locations.push((kind << 3) as u8 | (length - 1) as u8);
+                locations.extend_from_slice(&value.to_be_bytes()[8 - length..]);
+            }
+            locations.push(0);
+
+            content.extend_from_slice(&stored_bytes);
+        }
+
+        let names: Vec<&str> = resources.iter().map(|r| r.0.as_str()).collect();
+        let (redirect, slots) = (count..).find_map(|length| perfect_hash(&names, length)).unwrap();
+
+        let mut out = Vec::new();
+        for &value in &[IMAGE_MAGIC, 1 << 16, 0, count as u32, slots.len() as u32] {
+            self.u32(&mut out, value);
+        }
+        self.u32(&mut out, locations.len() as u32);
+        self.u32(&mut out, self.strings.len() as u32);
+        for &value in &redirect {
+            self.u32(&mut out, value as u32);
+        }
+        for slot in &slots {
+            self.u32(&mut out, slot.map_or(0, |i| location_offsets[i]));
+        }
+        out.extend_from_slice(&locations);
+        out.extend_from_slice(&self.strings);
+        out.extend_from_slice(&content);
+        out
+    }
+}
+
+// The redirect table and the resource in each slot. Names alone in their
+// bucket are stored directly, colliding ones get a seed that spreads them
+// over free slots. As in jlink, a bigger table is tried when no seed works.
+fn perfect_hash(names: &[&str], length: usize) -> Option<(Vec<i32>, Vec<Option<usize>>)> {
+    let mut buckets: Vec<Vec<usize>> = vec![Vec::new(); length];
+    for (i, name) in names.iter().enumerate() {
+        buckets[hash_code(name, HASH_MULTIPLIER) as usize % length].push(i);
+    }
+    let mut order: Vec<usize> = (0..length).collect();
+    order.sort_by_key(|&b| std::cmp::Reverse(buckets[b].len()));
+
+    let mut redirect = vec![0i32; length];
+    let mut slots: Vec<Option<usize>> = vec![None; length];
+
+    for &bucket in order.iter().filter(|&&b| buckets[b].len() > 1) {
+        let seed = (1..100).find(|&seed| {
+            let mut taken: Vec<usize> = Vec::new();
+            buckets[bucket].iter().all(|&i| {
+                let slot = hash_code(names[i], seed) as usize % length;
+                let free = slots[slot].is_none() && !taken.contains(&slot);
+                taken.push(slot);
+                free
+            })
+        })?;
+        for &i in &buckets[bucket] {
+            slots[hash_code(names[i], seed) as usize % length] = Some(i);
+        }
+        redirect[bucket] = seed;
+    }
+    for &bucket in order.iter().filter(|&&b| buckets[b].len() == 1) {
+        let slot = slots.iter().position(Option::is_none).unwrap();
+        slots[slot] = Some(buckets[bucket][0]);
+        redirect[bucket] = -1 - slot as i32;
+    }
+
+    Some((redirect, slots))
+}
+
+fn compressed_int(out: &mut Vec<u8>, value: u32) {
+    if value < 0x20 {
+        out.push(0x80 | 1 << 5 | value as u8);
+    } else if value < 0x2000 {
+        out.push(0x80 | 2 << 5 | (value >> 8) as u8);
+        out.push(value as u8);
+    } else {
+        out.extend_from_slice(&value.to_be_bytes());
+    }
+}
+
+fn open(bytes: Vec<u8>) -> Image<Cursor<Vec<u8>>> {
+    Image::new(Cursor::new(bytes)).unwrap()
+}
+
+#[test]
+fn reads_uncompressed_resources() {
+    let mut image = open(
+        ImageBuilder::new(false)
+            .add("/m/Sum.class", SUM, Stored::Plain)
+            .add("/java.base/p/Fixture.class", &fixture_class(), Stored::Plain)
+            .add("/java.base/META-INF/services/p.Service", b"p.Impl\n", Stored::Plain)
+            .add("/m/LICENSE", b"none", Stored::Plain)
+            .build(),
+    );
+
+    assert_eq!(image.len(), 4);
+    assert_eq!(image.major_version, 1);
+    assert_eq!(image.resource("/m/Sum.class").unwrap(), SUM);
+    assert_eq!(image.resource("/m/LICENSE").unwrap(), b"none");
+    assert_eq!(image.resource("/java.base/META-INF/services/p.Service").unwrap(), b"p.Impl\n");
+    assert_eq!(image.class("java.base/p/Fixture").unwrap().name().unwrap(), "p/Fixture");
+
+    let location = image.find("/java.base/p/Fixture.class").unwrap();
+    assert_eq!(
+        location,
+        Location {
+            module: String::from("java.base"),
+            parent: String::from("p"),
+            base: String::from("Fixture"),
+            extension: String::from("class"),
+            offset: SUM.len() as u64,
+            compressed_size: 0,
+            uncompressed_size: fixture_class().len() as u64,
+        }
+    );
+
+    let mut names: Vec<String> = image.locations().iter().map(Location::full_name).collect();
+    names.sort();
+    assert_eq!(
+        names,
+        [
+            "/java.base/META-INF/services/p.Service",
+            "/java.base/p/Fixture.class",
+            "/m/LICENSE",
+            "/m/Sum.class",
+        ]
+    );
+
+    assert!(image.find("/m/Missing.class").is_none());
+    assert!(image.find("/m/Sum").is_none());
+    assert!(image.resource("/n/Sum.class").is_err());
+}
+
+#[test]
+fn reads_big_endian_images() {
+    let mut image = open(
+        ImageBuilder::new(true)
+            .add("/m/Sum.class", SUM, Stored::Plain)
+            .add("/m/p/Fixture.class", &fixture_class(), Stored::Zip)
+            .build(),
+    );
+
+    assert_eq!(image.resource("/m/Sum.class").unwrap(), SUM);
+    assert_eq!(image.resource("/m/p/Fixture.class").unwrap(), fixture_class());
+}
+
+#[test]
+fn decompresses_resources() {
+    let fixture = fixture_class();
+    let mut image = open(
+        ImageBuilder::new(false)
+            .add("/m/zip/Sum.class", SUM, Stored::Zip)
+            .add("/m/cp/Sum.class", SUM, Stored::CompactCp)
+            .add("/m/cp/Fixture.class", &fixture, Stored::CompactCp)
+            .add("/m/both/Fixture.class", &fixture, Stored::Both)
+            .build(),
+    );
+
+    let location = image.find("/m/zip/Sum.class").unwrap();
+    assert!(location.compressed_size != 0);
+    assert_eq!(location.uncompressed_size, SUM.len() as u64);
+
+    assert_eq!(image.resource("/m/zip/Sum.class").unwrap(), SUM);
+    assert_eq!(image.resource("/m/cp/Sum.class").unwrap(), SUM);
+    assert_eq!(image.resource("/m/cp/Fixture.class").unwrap(), fixture);
+    assert_eq!(image.resource("/m/both/Fixture.class").unwrap(), fixture);
+}
+
+#[test]
+fn finds_every_resource_of_a_larger_image() {
+    let names: Vec<String> = (0..200).map(|i| format!("/m{}/p/q/C{}.class", i % 7, i)).collect();
+
+    let mut builder = ImageBuilder::new(false);
+    for name in &names {
+        builder = builder.add(name, name.as_bytes(), Stored::Plain);
+    }
+    let mut image = open(builder.build());
+
+    for name in &names {
+        assert_eq!(image.resource(name).unwrap(), name.as_bytes());
+    }
+    assert!(image.find("/m0/p/q/C1.class").is_none());
+}
+
+#[test]
+fn rejects_other_files() {
+    assert!(Image::new(Cursor::new(SUM.to_vec())).is_err());
+
+    let mut truncated = ImageBuilder::new(false).add("/m/Sum.class", SUM, Stored::Plain).build();
+    truncated.truncate(40);
+    assert!(Image::new(Cursor::new(truncated)).is_err());
+}
+
+#[test]
+fn dumps_a_class_from_an_image() {
+    let path = temp_file(
+        "modules",
+        &ImageBuilder::new(false)
+            .add("/java.base/java/lang/Sum.class", SUM, Stored::Zip)
+            .build(),
+    );
+    let image = path.to_str().unwrap();
+
+    let (ok, stdout) = run_stdout(&["--image", image, "java.base/java/lang/Sum"]);
+    assert!(ok);
+    let json: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(json["major_version"], "0034");
+
+    let (ok, stdout) = run_stdout(&["--image", image, "-f", "asm", "/java.base/java/lang/Sum.class"]);
+    assert!(ok);
+    assert!(stdout.starts_with(".version 52 0\n.class public super Sum\n"));
+
+    let (ok, _) = run_stdout(&["--image", image, "java.base/java/lang/Missing"]);
+    assert!(!ok);
+
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn dumps_the_classes_of_a_jmod() {
+    let mut jmod = b"JM\x01\x00".to_vec();
+    {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::FileOptions::default();
+        zip.start_file("classes/Sum.class", options).unwrap();
+        zip.write_all(SUM).unwrap();
+        zip.start_file("legal/LICENSE", options).unwrap();
+        zip.write_all(b"none").unwrap();
+        jmod.extend_from_slice(&zip.finish().unwrap().into_inner());
+    }
+    let path = temp_file("m.jmod", &jmod);
+
+    let (ok, stdout) = run_stdout(&["--list-resources", path.to_str().unwrap()]);
+    assert!(ok);
+    let entries: Vec<serde_json::Value> = stdout.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!(entries.len(), 2);
+    let name = path.file_name().unwrap().to_str().unwrap();
+    assert_eq!(entries[0]["path"], format!("{}!/classes/Sum.class", name));
+    assert_eq!(entries[0]["class"]["major_version"], "0034");
+    assert_eq!(entries[1]["path"], format!("{}!/legal/LICENSE", name));
+    assert_eq!(entries[1]["resource"]["size"], 4);
+
+    fs::remove_file(path).unwrap();
+}
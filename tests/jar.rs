@@ -0,0 +1,55 @@
+// Reading every entry of a jar, including those that aren't classes and those
+// that can't be parsed.
+
+extern crate class2json;
+extern crate serde_json;
+extern crate zip;
+
+mod common;
+
+use std::fs;
+
+use serde_json::Value;
+
+use common::{jar, run, temp_file, SUM};
+
+#[test]
+fn every_entry_is_reported_and_corrupt_ones_do_not_stop_the_run() {
+    let path = temp_file("app.jar", &jar(&[
+        ("META-INF/MANIFEST.MF", b"Manifest-Version: 1.0\r\n\r\n"),
+        ("p/Sum.class", SUM),
+        ("p/Bad.class", b"\xca\xfe\xba\xbe\x00"),
+        ("res/a.txt", b"hello"),
+    ]));
+    let name = path.file_name().unwrap().to_str().unwrap();
+
+    let entries = |args: &[&str]| -> (Option<i32>, Vec<Value>, String) {
+        let mut args = args.to_vec();
+        args.push(path.to_str().unwrap());
+        let output = run(&args);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let entries = stdout.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        (output.status.code(), entries, String::from_utf8(output.stderr).unwrap())
+    };
+
+    let (code, documents, stderr) = entries(&[]);
+    assert_eq!(code, Some(1));
+    let paths: Vec<&str> = documents.iter().map(|d| d["path"].as_str().unwrap()).collect();
+    assert_eq!(paths, [
+        format!("{}!/META-INF/MANIFEST.MF", name),
+        format!("{}!/p/Sum.class", name),
+        format!("{}!/p/Bad.class", name),
+    ]);
+    assert_eq!(documents[0]["manifest"]["main"]["Manifest-Version"], "1.0");
+    assert_eq!(documents[1]["class"]["major_version"], "0034");
+    assert_eq!(documents[2]["error"], "truncated class file");
+    assert!(stderr.ends_with("Parsed 1 classes from 1 root, 1 failed\n"), "{}", stderr);
+
+    // Resources are only listed when asked for.
+    let (_, documents, _) = entries(&["--list-resources"]);
+    assert_eq!(documents.len(), 4);
+    assert_eq!(documents[3]["path"], format!("{}!/res/a.txt", name));
+    assert_eq!(documents[3]["resource"]["size"], 5);
+
+    fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,62 @@
+// Java source stubs, which should compile as they are.
+
+extern crate class2json;
+extern crate zip;
+
+mod common;
+
+use std::fs;
+
+use class2json::assembly::assemble::assemble;
+
+use common::{class_listing, javac, run, temp_file};
+
+#[test]
+fn subclasses_of_sealed_types_are_non_sealed_unless_final() {
+    let dir = std::env::temp_dir().join(format!("class2json-{}-sealed", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let mut sources = Vec::new();
+    for class in &["S", "Shape", "Circle", "Square"] {
+        let output = run(&["-f", "java", &format!("class_files/sealed/{}.class", class)]);
+        assert!(output.status.success());
+
+        let source = format!("{}.java", class);
+        fs::write(dir.join(&source), &output.stdout).unwrap();
+        sources.push(source);
+    }
+
+    let stub = |source: &str| fs::read_to_string(dir.join(source)).unwrap();
+    assert!(stub("S.java").contains("public static non-sealed class Two implements S"));
+    assert!(stub("S.java").contains("public static final class One implements S"));
+    assert!(stub("Square.java").starts_with("public non-sealed class Square extends Shape"));
+    assert!(stub("Circle.java").starts_with("public final class Circle extends Shape"));
+
+    let sources: Vec<&str> = sources.iter().map(String::as_str).collect();
+    assert_ne!(javac(&dir, &sources), Some(false));
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn unpaired_surrogates_in_constants_are_escaped() {
+    // Java strings may hold a surrogate without its pair, which can't be
+    // written as it is but can as an escape.
+    let listing = format!("{}{}", class_listing("p/Strings", 52, 0), "
+.field public static final LONE Ljava/lang/String;
+    .constantvalue String b\"a\\xed\\xa0\\x80b\"
+.end field
+.field public static final PAIR Ljava/lang/String;
+    .constantvalue String b\"\\xed\\xa0\\xbd\\xed\\xb8\\x80\"
+.end field
+");
+    let path = temp_file("Strings.class", &assemble(&listing).unwrap().to_bytes());
+
+    let output = run(&["-f", "java", path.to_str().unwrap()]);
+    assert!(output.status.success());
+    let stub = String::from_utf8(output.stdout).unwrap();
+    assert!(stub.contains("LONE = \"a\\ud800b\";"), "{}", stub);
+    assert!(stub.contains("PAIR = \"\\ud83d\\ude00\";"), "{}", stub);
+
+    fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,81 @@
+// Looking classes up by name on a class path, in the order a JVM would search
+// it, including the jars manifests list in Class-Path.
+
+extern crate class2json;
+extern crate serde_json;
+extern crate zip;
+
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+
+use serde_json::Value;
+
+use class2json::classpath::ClassPath;
+
+use common::{class, jar, run, temp_file};
+
+// app.jar lists lib.jar in its Class-Path, which is searched before other.jar
+// comes next on the class path. p/Dup is in all three.
+fn jars(test: &str) -> (PathBuf, PathBuf, PathBuf) {
+    let lib = temp_file(&format!("{}-lib.jar", test), &jar(&[
+        ("p/Map.class", &class("p/Map", 52, 0).to_bytes()),
+        ("p/Map$Entry.class", &class("p/Map$Entry", 52, 0).to_bytes()),
+        ("p/Dup.class", &class("p/Dup", 53, 0).to_bytes()),
+    ]));
+    let manifest = format!("Manifest-Version: 1.0\r\nClass-Path: {}\r\n\r\n", lib.file_name().unwrap().to_str().unwrap());
+    let app = temp_file(&format!("{}-app.jar", test), &jar(&[
+        ("META-INF/MANIFEST.MF", manifest.as_bytes()),
+        ("app/Main.class", &class("app/Main", 52, 0).to_bytes()),
+    ]));
+    let other = temp_file(&format!("{}-other.jar", test), &jar(&[("p/Dup.class", &class("p/Dup", 54, 0).to_bytes()), ("q/Q.class", &class("q/Q", 52, 0).to_bytes())]));
+
+    (app, lib, other)
+}
+
+#[test]
+fn classes_are_found_in_class_path_order() {
+    let (app, lib, other) = jars("lookup");
+    let mut class_path = ClassPath::new(&[app.clone(), other.clone()], None);
+
+    let found = |class_path: &mut ClassPath, name: &str| {
+        class_path.lookup(name).map(|entry| (entry.source, entry.entry.path))
+    };
+    let lib_name = lib.file_name().unwrap().to_str().unwrap();
+    assert_eq!(found(&mut class_path, "app.Main").unwrap().1, format!("{}!/app/Main.class", app.file_name().unwrap().to_str().unwrap()));
+
+    // Class-Path entries come before the next root.
+    assert_eq!(found(&mut class_path, "p.Dup"), Some((lib.display().to_string(), format!("{}!/p/Dup.class", lib_name))));
+    assert_eq!(class_path.class("p/Dup").unwrap().major_version, 53);
+    assert_eq!(found(&mut class_path, "q/Q").unwrap().0, other.display().to_string());
+
+    // Dotted names stand for member classes too.
+    assert_eq!(found(&mut class_path, "p.Map.Entry").unwrap().1, format!("{}!/p/Map$Entry.class", lib_name));
+    assert_eq!(found(&mut class_path, "p.Map$Entry").unwrap().1, format!("{}!/p/Map$Entry.class", lib_name));
+    assert_eq!(found(&mut class_path, "p.Missing"), None);
+    assert_eq!(class_path.class("p/Missing").unwrap_err(), "p/Missing not found on the class path");
+
+    for path in &[app, lib, other] {
+        fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn classpath_option_dumps_the_first_class_of_a_name() {
+    let (app, lib, other) = jars("lookup-option");
+    let classpath = format!("{}:{}", app.display(), other.display());
+
+    let output = run(&["--classpath", &classpath, "p.Dup"]);
+    assert!(output.status.success());
+    let class: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(class["major_version"], "0035");
+
+    let output = run(&["--classpath", &classpath, "p.Missing"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "p.Missing not found on the class path\n");
+
+    for path in &[app, lib, other] {
+        fs::remove_file(path).unwrap();
+    }
+}
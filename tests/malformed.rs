@@ -0,0 +1,78 @@
+// Classes with attributes that can't be decoded are reported as errors of
+// their own, rather than aborting the whole run.
+
+extern crate class2json;
+extern crate zip;
+
+mod common;
+
+use std::fs;
+use std::io::{Cursor, Write};
+
+use class2json::assembly::assemble::assemble;
+
+use common::{run, temp_file, SUM};
+
+// A Code attribute whose code runs past its end, and annotations and
+// bootstrap methods cut short.
+const MALFORMED: &str = "
+.version 52 0
+.class public super p/Malformed
+.super java/lang/Object
+
+.method public static f : ()V
+    .attribute Code b\"\\x00\\x01\\x00\\x00\\x00\\x00\\x00\\x10\\xb1\"
+.end method
+
+.attribute RuntimeVisibleAnnotations b\"\\x00\\x01\\x00\"
+.attribute BootstrapMethods b\"\\x00\\x02\\x00\"
+";
+
+#[test]
+fn malformed_attributes_fail_the_class() {
+    let path = temp_file("Malformed.class", &assemble(MALFORMED).unwrap().to_bytes());
+
+    for args in &[&["-f", "java"][..], &["--canonical"]] {
+        let mut args = args.to_vec();
+        args.push(path.to_str().unwrap());
+
+        let output = run(&args);
+        assert_eq!(output.status.code(), Some(1), "for {:?}", args);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("malformed"), "for {:?}", args);
+    }
+
+    // The listing keeps attributes it can't decode as bytes.
+    let output = run(&["-f", "asm", path.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains(".attribute Code"));
+
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn malformed_attributes_do_not_abort_archives() {
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let options = zip::write::FileOptions::default();
+    zip.start_file("p/Malformed.class", options).unwrap();
+    zip.write_all(&assemble(MALFORMED).unwrap().to_bytes()).unwrap();
+    zip.start_file("Sum.class", options).unwrap();
+    zip.write_all(SUM).unwrap();
+    let path = temp_file("malformed.jar", &zip.finish().unwrap().into_inner());
+    let path = path.to_str().unwrap();
+
+    for view in &["--canonical", "--unused", "--features", "--call-graph"] {
+        let output = run(&[view, path]);
+        assert_ne!(output.status.code(), Some(101), "{} panicked", view);
+        assert!(String::from_utf8(output.stdout).unwrap().contains("malformed"), "{} doesn't report the class", view);
+    }
+
+    for view in &["--canonical", "--features", "--call-graph", "--deps", "--hierarchy"] {
+        let output = run(&[view, path]);
+        assert!(String::from_utf8(output.stdout).unwrap().contains("Sum"), "{} lost the other classes", view);
+    }
+
+    let output = run(&["compat", path, path]);
+    assert_ne!(output.status.code(), Some(101));
+
+    fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,40 @@
+// Mirroring archives, directories and class paths into a directory of one
+// file per class.
+
+extern crate class2json;
+extern crate serde_json;
+extern crate zip;
+
+mod common;
+
+use std::fs;
+
+use serde_json::Value;
+
+use common::{javac, run};
+
+#[test]
+fn member_classes_are_declared_in_the_stub_of_their_class() {
+    let dir = std::env::temp_dir().join(format!("class2json-{}-mirror", std::process::id()));
+    let output = run(&["--output-dir", dir.to_str().unwrap(), "-f", "java", "class_files/sealed"]);
+    assert!(output.status.success());
+
+    let mut files: Vec<String> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    files.sort();
+    assert_eq!(files, ["Circle.java", "S.java", "Shape.java", "Square.java", "index.json"]);
+
+    let stub = fs::read_to_string(dir.join("S.java")).unwrap();
+    assert!(stub.contains("final class One implements S"));
+    assert!(stub.contains("non-sealed class Two implements S"));
+
+    let index: Value = serde_json::from_str(&fs::read_to_string(dir.join("index.json")).unwrap()).unwrap();
+    let member = index["classes"].as_array().unwrap().iter().find(|c| c["name"] == "S$One").unwrap();
+    assert_eq!(member["file"], "S.java");
+
+    assert_ne!(javac(&dir, &["S.java", "Shape.java", "Circle.java", "Square.java"]), Some(false));
+
+    fs::remove_dir_all(dir).unwrap();
+}
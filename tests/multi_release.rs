@@ -0,0 +1,91 @@
+// Multi-release jars: the classes of META-INF/versions/N/ replace the base
+// classes for releases from N on.
+
+extern crate class2json;
+extern crate serde_json;
+extern crate zip;
+
+mod common;
+
+use std::fs;
+use std::io::Cursor;
+
+use serde_json::Value;
+
+use class2json::archive::Archive;
+
+use common::{class, jar, run, temp_file};
+
+const MANIFEST: &[u8] = b"Manifest-Version: 1.0\r
+Multi-Release: true\r
+\r
+Name: p/A.class\r
+Sealed: true\r
+\r
+";
+
+fn multi_release_jar() -> Vec<u8> {
+    jar(&[
+        ("META-INF/MANIFEST.MF", MANIFEST),
+        ("p/A.class", &class("p/A", 52, 0).to_bytes()),
+        ("META-INF/versions/9/p/A.class", &class("p/A", 53, 0).to_bytes()),
+        ("META-INF/versions/11/p/A.class", &class("p/A", 55, 0).to_bytes()),
+        ("META-INF/versions/11/p/B.class", &class("p/B", 55, 0).to_bytes()),
+    ])
+}
+
+#[test]
+fn releases_load_the_highest_version_up_to_theirs() {
+    let archive = Archive::new(Cursor::new(multi_release_jar())).unwrap();
+    assert!(archive.is_multi_release());
+    assert_eq!(archive.manifest().unwrap().entry("p/A.class").unwrap().get("sealed"), Some("true"));
+
+    let names = |release: u32| -> Vec<&str> {
+        archive.release_entries(release).into_iter().map(|i| archive.name(i).unwrap()).collect()
+    };
+    assert_eq!(names(8), ["META-INF/MANIFEST.MF", "p/A.class"]);
+    assert_eq!(names(10), ["META-INF/MANIFEST.MF", "META-INF/versions/9/p/A.class"]);
+    assert_eq!(names(17), [
+        "META-INF/MANIFEST.MF",
+        "META-INF/versions/11/p/A.class",
+        "META-INF/versions/11/p/B.class",
+    ]);
+}
+
+#[test]
+fn release_selects_the_classes_that_are_dumped() {
+    let path = temp_file("mr.jar", &multi_release_jar());
+    let name = path.file_name().unwrap().to_str().unwrap().to_string();
+
+    let classes = |args: &[&str]| -> Vec<(String, String)> {
+        let mut args = args.to_vec();
+        args.push(path.to_str().unwrap());
+        let output = run(&args);
+        assert!(output.status.success());
+
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap())
+            .filter(|entry| entry["class"].is_object())
+            .map(|entry| {
+                let path = entry["path"].as_str().unwrap().trim_start_matches(&name[..]).to_string();
+                (path, entry["class"]["major_version"].as_str().unwrap().to_string())
+            })
+            .collect()
+    };
+    let pairs = |expected: &[(&str, &str)]| -> Vec<(String, String)> {
+        expected.iter().map(|&(path, major)| (path.to_string(), major.to_string())).collect()
+    };
+
+    // Every version without a release.
+    assert_eq!(classes(&[]).len(), 4);
+    assert_eq!(classes(&["--release", "8"]), pairs(&[("!/p/A.class", "0034")]));
+    assert_eq!(classes(&["--release", "10"]), pairs(&[("!/META-INF/versions/9/p/A.class", "0035")]));
+    assert_eq!(classes(&["--release", "11"]), pairs(&[
+        ("!/META-INF/versions/11/p/A.class", "0037"),
+        ("!/META-INF/versions/11/p/B.class", "0037"),
+    ]));
+
+    fs::remove_file(path).unwrap();
+}
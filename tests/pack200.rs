@@ -0,0 +1,67 @@
+// Unpacking Pack200 archives. shapes.jar holds Sum.class and the classes of
+// Shapes.java as Pack200 normalizes them, and shapes.pack the same jar packed
+// by Apache Commons Compress, so unpacking gives back its files exactly.
+
+extern crate class2json;
+extern crate serde_json;
+extern crate zip;
+
+mod common;
+
+use std::io::{Cursor, Read};
+
+use class2json::archive::pack200::unpack;
+
+use common::run_stdout;
+
+const JAR: &[u8] = include_bytes!("../class_files/pack200/shapes.jar");
+const PACKED: &[u8] = include_bytes!("../class_files/pack200/shapes.pack");
+const GZIPPED: &[u8] = include_bytes!("../class_files/pack200/shapes.pack.gz");
+
+fn jar_files() -> Vec<(String, Vec<u8>)> {
+    let mut zip = zip::ZipArchive::new(Cursor::new(JAR)).unwrap();
+    let mut files = Vec::new();
+
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i).unwrap();
+        if file.is_dir() {
+            continue;
+        }
+
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents).unwrap();
+        files.push((file.name().to_string(), contents));
+    }
+
+    files
+}
+
+#[test]
+fn unpacks_the_files_of_the_jar() {
+    let expected = jar_files();
+    assert_eq!(expected.len(), 5);
+
+    for packed in [PACKED, GZIPPED] {
+        let files = unpack(packed).unwrap();
+        let files: Vec<(String, Vec<u8>)> = files.into_iter().map(|file| (file.name, file.contents)).collect();
+        assert_eq!(files.len(), expected.len());
+
+        for ((name, contents), (expected_name, expected_contents)) in files.iter().zip(&expected) {
+            assert_eq!(name, expected_name);
+            assert!(contents == expected_contents, "{} differs from the jar's", name);
+        }
+    }
+}
+
+#[test]
+fn reads_packed_archives_as_roots() {
+    let (ok, stdout) = run_stdout(&["class_files/pack200/shapes.pack.gz"]);
+    assert!(ok);
+
+    let names: Vec<String> = stdout
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .map(|entry| entry["path"].as_str().unwrap().to_string())
+        .collect();
+    assert!(names.contains(&"shapes.pack.gz!/p/Shapes$Square.class".to_string()), "{:?}", names);
+}
@@ -0,0 +1,102 @@
+// Scanning the directories and archives of a class path, in order and
+// filtered by package or glob.
+
+extern crate class2json;
+extern crate serde_json;
+extern crate zip;
+
+mod common;
+
+use std::fs;
+
+use serde_json::Value;
+
+use class2json::classpath::Filter;
+
+use common::{class, jar, run, temp_file};
+
+#[test]
+fn class_paths_are_scanned_in_order() {
+    let dir = std::env::temp_dir().join(format!("class2json-{}-scan", std::process::id()));
+    for name in &["p/b/Z", "p/a/Y", "p/a/X", "q/W"] {
+        let path = dir.join(format!("{}.class", name));
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, class(name, 52, 0).to_bytes()).unwrap();
+    }
+    fs::write(dir.join("p/a/Broken.class"), b"junk").unwrap();
+    let lib = temp_file("scan.jar", &jar(&[("p/a/V.class", &class("p/a/V", 52, 0).to_bytes()), ("r/U.class", &class("r/U", 52, 0).to_bytes())]));
+    let lib_name = lib.file_name().unwrap().to_str().unwrap().to_string();
+    let classpath = format!("{}:{}", dir.display(), lib.display());
+
+    let paths = |args: &[&str]| -> (Vec<String>, String) {
+        let mut args = args.to_vec();
+        args.extend(&["--classpath", &classpath]);
+        let output = run(&args);
+        let paths = String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap()["path"].as_str().unwrap().to_string())
+            .collect();
+        (paths, String::from_utf8(output.stderr).unwrap())
+    };
+
+    // Directories are walked in path order, whichever classes are parsed
+    // first, and then the next root.
+    let (all, stderr) = paths(&[]);
+    assert_eq!(all, [
+        "p/a/Broken.class".to_string(),
+        "p/a/X.class".to_string(),
+        "p/a/Y.class".to_string(),
+        "p/b/Z.class".to_string(),
+        "q/W.class".to_string(),
+        format!("{}!/p/a/V.class", lib_name),
+        format!("{}!/r/U.class", lib_name),
+    ]);
+    assert!(stderr.ends_with("Parsed 6 classes from 2 roots, 1 failed\n"), "{}", stderr);
+    for _ in 0..3 {
+        assert_eq!(paths(&[]).0, all);
+    }
+
+    let (in_package, _) = paths(&["--package", "p.a", "--package", "r"]);
+    assert_eq!(in_package, [
+        "p/a/Broken.class".to_string(),
+        "p/a/X.class".to_string(),
+        "p/a/Y.class".to_string(),
+        format!("{}!/p/a/V.class", lib_name),
+        format!("{}!/r/U.class", lib_name),
+    ]);
+
+    let (matching, stderr) = paths(&["--glob", "p/**/?.class", "--package", "p"]);
+    assert_eq!(matching, [
+        "p/a/X.class".to_string(),
+        "p/a/Y.class".to_string(),
+        "p/b/Z.class".to_string(),
+        format!("{}!/p/a/V.class", lib_name),
+    ]);
+    assert!(stderr.ends_with("Parsed 4 classes from 2 roots, 0 failed\n"), "{}", stderr);
+
+    fs::remove_dir_all(dir).unwrap();
+    fs::remove_file(lib).unwrap();
+}
+
+#[test]
+fn filters_select_packages_and_globs() {
+    let mut filter = Filter::new();
+    assert!(filter.is_empty() && filter.matches("Any.class"));
+
+    filter.package("java.util");
+    assert!(filter.matches("java/util/List.class"));
+    assert!(filter.matches("java/util/concurrent/Future.class"));
+    assert!(!filter.matches("java/utility/Tool.class"));
+    assert!(!filter.matches("java/Object.class"));
+
+    // Both have to match.
+    filter.glob("**/*Map.class");
+    assert!(filter.matches("java/util/concurrent/ConcurrentMap.class"));
+    assert!(!filter.matches("java/util/List.class"));
+
+    let mut filter = Filter::new();
+    filter.glob("a/*/C.class");
+    assert!(filter.matches("a/b/C.class"));
+    assert!(!filter.matches("a/b/c/C.class"));
+}
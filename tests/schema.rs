@@ -0,0 +1,229 @@
+// The JSON Schema of the output, and that documents carry a format_version
+// and validate against it.
+
+extern crate class2json;
+extern crate jsonschema;
+extern crate serde_json;
+extern crate zip;
+
+mod common;
+
+use std::fs;
+use std::io::{Cursor, Write};
+
+use jsonschema::JSONSchema;
+use serde_json::Value;
+
+use class2json::assembly::assemble::assemble;
+use class2json::schema::FORMAT_VERSION;
+
+use common::{run_stdout, temp_file, SUM};
+
+const PUBLISHED_SCHEMA: &str = include_str!("../schema/class2json.schema.json");
+
+// A class with a constant of every kind.
+const CONSTANTS: &str = "
+.version 61 0
+.class public super p/Constants
+.super java/lang/Object
+
+.method public static f : ()V
+    .code stack 4 locals 0
+        ldc Float 1.5
+        ldc Int 7
+        ldc2_w Long 12345678901
+        ldc2_w Double 2.5
+        pop2
+        pop2
+        pop
+        pop
+        ldc String \"s\"
+        pop
+        getstatic Field java/lang/System out Ljava/io/PrintStream;
+        pop
+        ldc MethodType \"()V\"
+        pop
+        ldc MethodHandle invokeStatic Method p/Constants f ()V
+        pop
+        ldc Dynamic 0 value I
+        pop
+        invokedynamic InvokeDynamic 0 run ()Ljava/lang/Runnable;
+        invokeinterface InterfaceMethod java/lang/Runnable run ()V 1
+        return
+    .end code
+.end method
+
+.bootstrapmethods
+    MethodHandle invokeStatic Method p/Constants bootstrap ()V :
+.end bootstrapmethods
+
+.const [1] = Module m
+.const [2] = Package p
+";
+
+fn schema() -> JSONSchema {
+    let (ok, stdout) = run_stdout(&["--schema"]);
+    assert!(ok);
+    JSONSchema::compile(&serde_json::from_str(&stdout).unwrap()).unwrap()
+}
+
+fn documents(stdout: &str) -> Vec<Value> {
+    stdout.lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+}
+
+fn assert_valid(schema: &JSONSchema, document: &Value) {
+    if let Err(errors) = schema.validate(document) {
+        let errors: Vec<String> = errors.map(|e| format!("{} at {}", e, e.instance_path)).collect();
+        panic!("{} does not validate: {:?}", document, errors);
+    }
+}
+
+#[test]
+fn published_schema_is_up_to_date() {
+    let (ok, stdout) = run_stdout(&["--schema"]);
+    assert!(ok);
+    assert_eq!(stdout, PUBLISHED_SCHEMA);
+}
+
+#[test]
+fn class_documents_validate() {
+    let schema = schema();
+    let sum = temp_file("Sum.class", SUM);
+    let constants = temp_file("Constants.class", &assemble(CONSTANTS).unwrap().to_bytes());
+
+    for path in &[&sum, &constants] {
+        for args in &[vec![], vec!["--pretty"], vec!["--canonical"]] {
+            let mut args = args.clone();
+            args.push(path.to_str().unwrap());
+
+            let (ok, stdout) = run_stdout(&args);
+            assert!(ok);
+            let document: Value = serde_json::from_str(&stdout).unwrap();
+            assert_eq!(document["format_version"], FORMAT_VERSION);
+            assert_valid(&schema, &document);
+        }
+    }
+
+    fs::remove_file(sum).unwrap();
+    fs::remove_file(constants).unwrap();
+}
+
+#[test]
+fn entry_documents_validate() {
+    let schema = schema();
+
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let options = zip::write::FileOptions::default();
+    zip.start_file("META-INF/MANIFEST.MF", options).unwrap();
+    zip.write_all(b"Manifest-Version: 1.0\r\nMulti-Release: true\r\n\r\nName: p/\r\nSealed: true\r\n").unwrap();
+    zip.start_file("Sum.class", options).unwrap();
+    zip.write_all(SUM).unwrap();
+    zip.start_file("META-INF/versions/11/Sum.class", options).unwrap();
+    zip.write_all(SUM).unwrap();
+    zip.start_file("Broken.class", options).unwrap();
+    zip.write_all(b"\xca\xfe\xba\xbe\x00").unwrap();
+    zip.start_file("README", options).unwrap();
+    zip.write_all(b"none").unwrap();
+    let path = temp_file("entries.jar", &zip.finish().unwrap().into_inner());
+
+    let (ok, stdout) = run_stdout(&["--list-resources", "--release", "11", path.to_str().unwrap()]);
+    assert!(!ok);
+    let entries = documents(&stdout);
+    for kind in &["manifest", "class", "error", "resource"] {
+        assert!(entries.iter().any(|entry| entry.get(kind).is_some()), "no {} entry", kind);
+    }
+    assert!(entries.iter().any(|entry| entry.get("release").is_some()));
+    for entry in &entries {
+        assert_valid(&schema, entry);
+    }
+
+    let (_, stdout) = run_stdout(&["--classpath", path.to_str().unwrap()]);
+    let entries = documents(&stdout);
+    assert!(!entries.is_empty());
+    for entry in &entries {
+        assert!(entry.get("source").is_some());
+        assert_valid(&schema, entry);
+    }
+
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn schema_rejects_other_representations() {
+    let schema = schema();
+    let (_, stdout) = run_stdout(&["class_files/Sum.class"]);
+    let document: Value = serde_json::from_str(&stdout).unwrap();
+    assert_valid(&schema, &document);
+
+    let mut decimal = document.clone();
+    decimal["major_version"] = Value::from(52);
+    assert!(!schema.is_valid(&decimal));
+
+    let mut newer = document.clone();
+    newer["format_version"] = Value::from(FORMAT_VERSION + 1);
+    assert!(!schema.is_valid(&newer));
+
+    let mut unversioned = document;
+    unversioned.as_object_mut().unwrap().remove("format_version");
+    assert!(!schema.is_valid(&unversioned));
+}
+
+#[test]
+fn attribute_contents_are_hex() {
+    let schema = schema();
+
+    // Bytes that also decode as instructions: nop, aconst_null, nop, dup.
+    let source = "
+.version 52 0
+.class public super p/Throws
+.super java/lang/Object
+
+.method public static f : ()V
+    .attribute Exceptions b\"\\x00\\x01\\x00\\x59\"
+.end method
+";
+    let path = temp_file("Throws.class", &assemble(source).unwrap().to_bytes());
+
+    let (ok, stdout) = run_stdout(&[path.to_str().unwrap()]);
+    assert!(ok);
+    let document: Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(document["methods"][0]["attributes"][0]["info"], "00010059");
+    assert_valid(&schema, &document);
+
+    let mut listed = document;
+    listed["methods"][0]["attributes"][0]["info"] = Value::from(vec!["nop", "aconst_null", "nop", "dup"]);
+    assert!(!schema.is_valid(&listed));
+
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn every_document_has_a_format_version() {
+    let path = temp_file("Versioned.class", SUM);
+    let path = path.to_str().unwrap();
+
+    let views: &[&[&str]] = &[
+        &["--frames", "add"],
+        &["--cfg", "add"],
+        &["--hierarchy"],
+        &["--subtypes", "java.lang.Object"],
+        &["--call-graph"],
+        &["--deps"],
+        &["--unused"],
+        &["--features"],
+    ];
+
+    for args in views {
+        let mut args = args.to_vec();
+        args.push(path);
+
+        let (_, stdout) = run_stdout(&args);
+        let documents = documents(&stdout);
+        assert!(!documents.is_empty(), "no output for {:?}", args);
+        for document in documents {
+            assert_eq!(document["format_version"], FORMAT_VERSION, "for {:?}", args);
+        }
+    }
+
+    fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,108 @@
+// Unused constant pool entries and private members, with private members
+// used from a nestmate counting as used.
+
+extern crate class2json;
+extern crate serde_json;
+
+mod common;
+
+use std::fs;
+
+use serde_json::{json, Value};
+
+use class2json::analysis::unused::UnusedAnalysis;
+use class2json::assembly::assemble::assemble;
+
+use common::{run_stdout, temp_file};
+
+// Of the private members, only `unused` and `dead` aren't referred to: `used`
+// and `helper` are by get(), `shared` by the nestmate and serialVersionUID by
+// serialization.
+const OUTER: &str = "
+.version 55 0
+.class public super p/Outer
+.super java/lang/Object
+.nestmembers p/Outer$In
+
+.field private used I
+.end field
+.field private unused I
+.end field
+.field private static shared I
+.end field
+.field private static final serialVersionUID J
+    .constantvalue Long 1
+.end field
+
+.method public get : ()I
+    .code stack 1 locals 1
+        aload_0
+        getfield Field p/Outer used I
+        aload_0
+        invokespecial Method p/Outer helper ()V
+        ireturn
+    .end code
+.end method
+
+.method private helper : ()V
+    .code stack 0 locals 1
+        return
+    .end code
+.end method
+
+.method private dead : ()V
+    .code stack 0 locals 1
+        return
+    .end code
+.end method
+
+.const [1] = Utf8 leftover
+.const [2] = Utf8 used
+";
+
+const INNER: &str = "
+.version 55 0
+.class super p/Outer$In
+.super java/lang/Object
+.nesthost p/Outer
+
+.method static read : ()I
+    .code stack 1 locals 0
+        getstatic Field p/Outer shared I
+        ireturn
+    .end code
+.end method
+";
+
+#[test]
+fn private_members_used_by_nestmates_are_used() {
+    let mut analysis = UnusedAnalysis::new();
+    analysis.add_class(&assemble(OUTER).unwrap());
+    analysis.add_class(&assemble(INNER).unwrap());
+    let report = serde_json::to_value(analysis.report()).unwrap();
+
+    assert_eq!(report, json!({
+        "classes": [{
+            "class": "p/Outer",
+            "unused_entries": [{"index": 1, "entry": "Utf8 \"leftover\""}],
+            "unused_private_fields": ["unused I"],
+            "unused_private_methods": ["dead ()V"],
+        }],
+    }));
+}
+
+#[test]
+fn private_members_are_not_reported_without_the_nestmates() {
+    let path = temp_file("Outer.class", &assemble(OUTER).unwrap().to_bytes());
+
+    let (ok, stdout) = run_stdout(&["--unused", path.to_str().unwrap()]);
+    assert!(ok);
+    let report: Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(report["classes"], json!([{
+        "class": "p/Outer",
+        "unused_entries": [{"index": 1, "entry": "Utf8 \"leftover\""}],
+        "missing_nestmates": ["p/Outer$In"],
+    }]));
+
+    fs::remove_file(path).unwrap();
+}
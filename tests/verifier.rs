@@ -0,0 +1,196 @@
+// The type-checking verifier, and the class hierarchy it checks assignments
+// against.
+
+extern crate class2json;
+extern crate serde_json;
+
+mod common;
+
+use std::fs;
+
+use class2json::assembly::assemble::assemble;
+use class2json::bytecode::frames::{Frame, Type};
+use class2json::bytecode::hierarchy::{ClassHierarchy, ClassHierarchyMap, LoadingHierarchy};
+use class2json::bytecode::verifier::{verify_class, VerifyError};
+
+use common::{run, temp_file};
+
+// Passes a p/Impl where the missing p/Missing is expected.
+const MISSING_INTERFACE: &str = "
+.version 52 0
+.class public super p/Caller
+.super java/lang/Object
+
+.method public static f : ()V
+    .code stack 2 locals 0
+        new p/Impl
+        dup
+        invokespecial Method p/Impl <init> ()V
+        invokestatic Method p/Api use (Lp/Missing;)V
+        return
+    .end code
+.end method
+";
+
+// One failing method for each kind of check.
+const BAD: &str = "
+.version 52 0
+.class public super p/Bad
+.super java/lang/Object
+
+.method static branch : (I)V
+    .code stack 1 locals 2
+        iconst_0
+        istore_1
+        iload_0
+        ifeq L7
+        nop
+        .stack full locals Integer Float stack
+L7:     return
+    .end code
+.end method
+
+.method static add : ()I
+    .code stack 2 locals 0
+        iconst_0
+        fconst_0
+        iadd
+        ireturn
+    .end code
+.end method
+
+.method static name : (I)Ljava/lang/String;
+    .code stack 1 locals 1
+        iload_0
+        areturn
+    .end code
+.end method
+
+.method static pass : (Ljava/lang/Integer;)V
+    .code stack 1 locals 1
+        aload_0
+        invokestatic Method p/Bad use (Ljava/lang/Long;)V
+        return
+    .end code
+.end method
+
+.method static uninitialized : ()V
+    .code stack 2 locals 0
+        new p/Bad
+        invokevirtual Method java/lang/Object hashCode ()I
+        pop
+        return
+    .end code
+.end method
+";
+
+fn jdk_hierarchy() -> ClassHierarchyMap {
+    let mut hierarchy = ClassHierarchyMap::new();
+    hierarchy.insert("java/lang/Object", None, false);
+    hierarchy.insert("java/lang/Number", Some("java/lang/Object"), false);
+    hierarchy.insert("java/lang/Integer", Some("java/lang/Number"), false);
+    hierarchy.insert("java/lang/Long", Some("java/lang/Number"), false);
+    hierarchy.insert("java/lang/Runnable", Some("java/lang/Object"), true);
+    hierarchy.insert("p/Impl", Some("java/lang/Object"), false);
+    hierarchy
+}
+
+#[test]
+fn anything_is_assignable_to_missing_classes() {
+    let hierarchy = LoadingHierarchy::new(|name: &str| {
+        let source = format!(".version 52 0\n.class public super {}\n.super java/lang/Object\n", name);
+        if name == "p/Impl" { assemble(&source).ok() } else { None }
+    });
+
+    assert!(hierarchy.is_assignable("p/Impl", "p/Missing"));
+    assert!(hierarchy.is_assignable("p/Impl", "java/lang/Object"));
+    assert_eq!(hierarchy.missing(), vec!["p/Missing"]);
+
+    let known = jdk_hierarchy();
+    assert!(known.is_assignable("java/lang/Integer", "java/lang/Number"));
+    assert!(known.is_assignable("java/lang/Integer", "java/lang/Runnable"));
+    assert!(!known.is_assignable("java/lang/Integer", "java/lang/Long"));
+    assert!(known.is_assignable("java/lang/Integer", "p/Missing"));
+}
+
+#[test]
+fn missing_interface_targets_verify() {
+    let cf = assemble(MISSING_INTERFACE).unwrap();
+
+    assert!(verify_class(&cf, &jdk_hierarchy()).is_empty());
+}
+
+fn frame(locals: Vec<Type>, stack: Vec<Type>) -> Option<Box<Frame>> {
+    Some(Box::new(Frame { locals, stack }))
+}
+
+fn error(method: &str, offset: u32, message: &str, expected: Option<Box<Frame>>, actual: Option<Box<Frame>>) -> VerifyError {
+    VerifyError { method: method.to_string(), offset, message: message.to_string(), expected, actual }
+}
+
+#[test]
+fn failures_report_the_instruction_and_frames() {
+    let cf = assemble(BAD).unwrap();
+    let errors = verify_class(&cf, &jdk_hierarchy());
+
+    let integer = Type::Reference("java/lang/Integer".to_string());
+    assert_eq!(errors, [
+        error(
+            "branch(I)V",
+            3,
+            "local 1 is int but the frame at 7 declares float",
+            frame(vec![Type::Integer, Type::Float], vec![]),
+            frame(vec![Type::Integer, Type::Integer], vec![]),
+        ),
+        error("add()I", 2, "expected int on the stack, found float", None, frame(vec![], vec![Type::Integer, Type::Float])),
+        error(
+            "name(I)Ljava/lang/String;",
+            1,
+            "returns int but the method returns java/lang/String",
+            None,
+            frame(vec![Type::Integer], vec![Type::Integer]),
+        ),
+        error(
+            "pass(Ljava/lang/Integer;)V",
+            1,
+            "argument 0 of use(Ljava/lang/Long;)V is java/lang/Integer but must be java/lang/Long",
+            None,
+            frame(vec![integer.clone()], vec![integer]),
+        ),
+        error(
+            "uninitialized()V",
+            3,
+            "invokevirtual of java/lang/Object.hashCode()I on uninitialized(0), which is not a java/lang/Object",
+            None,
+            frame(vec![], vec![Type::Uninitialized(0)]),
+        ),
+    ]);
+
+    // Without the classes, an Integer may be a Long.
+    let errors = verify_class(&cf, &ClassHierarchyMap::new());
+    assert!(!errors.iter().any(|e| e.method.starts_with("pass")));
+}
+
+#[test]
+fn failures_are_printed_as_json() {
+    let listing = temp_file("Bad.j", BAD.as_bytes());
+    let class = std::env::temp_dir().join(format!("class2json-{}-Bad.class", std::process::id()));
+    let (listing, class) = (listing.to_str().unwrap(), class.to_str().unwrap());
+    assert!(run(&["-a", listing, "-o", class]).status.success());
+
+    let output = run(&["--verify", class]);
+    assert_eq!(output.status.code(), Some(1));
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["class"], "p/Bad");
+    assert_eq!(json["verify_errors"][0], serde_json::json!({
+        "method": "branch(I)V",
+        "offset": 3,
+        "message": "local 1 is int but the frame at 7 declares float",
+        "expected": {"locals": ["int", "float"], "stack": []},
+        "actual": {"locals": ["int", "int"], "stack": []},
+    }));
+
+    for path in &[listing, class] {
+        fs::remove_file(path).unwrap();
+    }
+}